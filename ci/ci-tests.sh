@@ -176,3 +176,4 @@ fi
 echo -e "\n\nTest cfg-flag builds"
 RUSTFLAGS="--cfg=taproot" cargo test --verbose --color always -p lightning
 RUSTFLAGS="--cfg=async_signing" cargo test --verbose --color always -p lightning
+RUSTFLAGS="--cfg=dual_funding" cargo test --verbose --color always -p lightning
@@ -72,6 +72,11 @@ GEN_TEST msg_tx_ack_rbf msg_targets::
 GEN_TEST msg_tx_abort msg_targets::
 
 GEN_TEST msg_stfu msg_targets::
+GEN_TEST msg_dyn_propose msg_targets::
+GEN_TEST msg_dyn_ack msg_targets::
+GEN_TEST msg_dyn_reject msg_targets::
+GEN_TEST msg_peer_storage msg_targets::
+GEN_TEST msg_your_peer_storage msg_targets::
 
 GEN_TEST msg_splice msg_targets::
 GEN_TEST msg_splice_ack msg_targets::
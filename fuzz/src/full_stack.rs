@@ -345,6 +345,10 @@ impl NodeSigner for KeyProvider {
 		self.inbound_payment_key.clone()
 	}
 
+	fn get_peer_storage_key(&self) -> KeyMaterial {
+		KeyMaterial([0; 32])
+	}
+
 	fn sign_invoice(&self, _hrp_bytes: &[u8], _invoice_data: &[u5], _recipient: Recipient) -> Result<RecoverableSignature, ()> {
 		unreachable!()
 	}
@@ -60,6 +60,11 @@ GEN_TEST lightning::ln::msgs::TxAckRbf test_msg_simple ""
 GEN_TEST lightning::ln::msgs::TxAbort test_msg_simple ""
 
 GEN_TEST lightning::ln::msgs::Stfu test_msg_simple ""
+GEN_TEST lightning::ln::msgs::DynPropose test_msg_simple ""
+GEN_TEST lightning::ln::msgs::DynAck test_msg_simple ""
+GEN_TEST lightning::ln::msgs::DynReject test_msg_simple ""
+GEN_TEST lightning::ln::msgs::PeerStorage test_msg_simple ""
+GEN_TEST lightning::ln::msgs::YourPeerStorage test_msg_simple ""
 
 GEN_TEST lightning::ln::msgs::Splice test_msg_simple ""
 GEN_TEST lightning::ln::msgs::SpliceAck test_msg_simple ""
@@ -42,6 +42,11 @@ pub mod msg_tx_init_rbf;
 pub mod msg_tx_ack_rbf;
 pub mod msg_tx_abort;
 pub mod msg_stfu;
+pub mod msg_dyn_propose;
+pub mod msg_dyn_ack;
+pub mod msg_dyn_reject;
+pub mod msg_peer_storage;
+pub mod msg_your_peer_storage;
 pub mod msg_splice;
 pub mod msg_splice_ack;
 pub mod msg_splice_locked;
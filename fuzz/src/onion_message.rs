@@ -16,7 +16,7 @@ use lightning::sign::{Recipient, KeyMaterial, EntropySource, NodeSigner, SignerP
 use lightning::util::test_channel_signer::TestChannelSigner;
 use lightning::util::logger::Logger;
 use lightning::util::ser::{Readable, Writeable, Writer};
-use lightning::onion_message::{CustomOnionMessageHandler, Destination, MessageRouter, OffersMessage, OffersMessageHandler, OnionMessageContents, OnionMessagePath, OnionMessenger, PendingOnionMessage};
+use lightning::onion_message::{AsyncPaymentsMessageHandler, CustomOnionMessageHandler, Destination, DNSResolverMessage, DNSResolverMessageHandler, DNSSECProof, DNSSECQuery, HeldHtlcAvailable, MessageRouter, OffersMessage, OffersMessageHandler, OnionMessageContents, OnionMessagePath, OnionMessenger, PendingOnionMessage, ReleaseHeldHtlc};
 
 use crate::utils::test_logger;
 
@@ -36,10 +36,12 @@ pub fn do_test<L: Logger>(data: &[u8], logger: &L) {
 		};
 		let message_router = TestMessageRouter {};
 		let offers_msg_handler = TestOffersMessageHandler {};
+		let async_payments_msg_handler = TestAsyncPaymentsMessageHandler {};
+		let dns_resolver_msg_handler = TestDNSResolverMessageHandler {};
 		let custom_msg_handler = TestCustomMessageHandler {};
 		let onion_messenger = OnionMessenger::new(
 			&keys_manager, &keys_manager, logger, &message_router, &offers_msg_handler,
-			&custom_msg_handler
+			&async_payments_msg_handler, &dns_resolver_msg_handler, &custom_msg_handler
 		);
 
 		let peer_node_id = {
@@ -102,6 +104,24 @@ impl OffersMessageHandler for TestOffersMessageHandler {
 	}
 }
 
+struct TestAsyncPaymentsMessageHandler {}
+
+impl AsyncPaymentsMessageHandler for TestAsyncPaymentsMessageHandler {
+	fn held_htlc_available(
+		&self, _message: HeldHtlcAvailable, _path_id: Option<[u8; 32]>, _reply_path: Option<BlindedPath>
+	) {}
+	fn release_held_htlc(&self, _message: ReleaseHeldHtlc) {}
+}
+
+struct TestDNSResolverMessageHandler {}
+
+impl DNSResolverMessageHandler for TestDNSResolverMessageHandler {
+	fn handle_dnssec_query(&self, _message: DNSSECQuery) -> Option<DNSResolverMessage> {
+		None
+	}
+	fn handle_dnssec_proof(&self, _message: DNSSECProof, _path_id: Option<[u8; 32]>) {}
+}
+
 #[derive(Debug)]
 struct TestCustomMessage {}
 
@@ -179,6 +199,8 @@ impl NodeSigner for KeyProvider {
 
 	fn get_inbound_payment_key_material(&self) -> KeyMaterial { unreachable!() }
 
+	fn get_peer_storage_key(&self) -> KeyMaterial { unreachable!() }
+
 	fn sign_invoice(&self, _hrp_bytes: &[u8], _invoice_data: &[u5], _recipient: Recipient) -> Result<RecoverableSignature, ()> {
 		unreachable!()
 	}
@@ -58,6 +58,11 @@ void msg_tx_init_rbf_run(const unsigned char* data, size_t data_len);
 void msg_tx_ack_rbf_run(const unsigned char* data, size_t data_len);
 void msg_tx_abort_run(const unsigned char* data, size_t data_len);
 void msg_stfu_run(const unsigned char* data, size_t data_len);
+void msg_dyn_propose_run(const unsigned char* data, size_t data_len);
+void msg_dyn_ack_run(const unsigned char* data, size_t data_len);
+void msg_dyn_reject_run(const unsigned char* data, size_t data_len);
+void msg_peer_storage_run(const unsigned char* data, size_t data_len);
+void msg_your_peer_storage_run(const unsigned char* data, size_t data_len);
 void msg_splice_run(const unsigned char* data, size_t data_len);
 void msg_splice_ack_run(const unsigned char* data, size_t data_len);
 void msg_splice_locked_run(const unsigned char* data, size_t data_len);
@@ -31,7 +31,7 @@ use lightning::events::EventHandler;
 #[cfg(any(feature = "std", feature = "futures"))]
 use lightning::events::EventsProvider;
 
-use lightning::ln::channelmanager::ChannelManager;
+use lightning::ln::channelmanager::{ChannelDetails, ChannelManager};
 use lightning::ln::msgs::OnionMessageHandler;
 use lightning::ln::peer_handler::APeerManager;
 use lightning::routing::gossip::{NetworkGraph, P2PGossipSync};
@@ -39,7 +39,9 @@ use lightning::routing::utxo::UtxoLookup;
 use lightning::routing::router::Router;
 use lightning::routing::scoring::{ScoreUpdate, WriteableScore};
 use lightning::util::logger::Logger;
-use lightning::util::persist::Persister;
+use lightning::util::forwarding_history::ForwardingHistory;
+use lightning::util::payment_history::PaymentHistory;
+use lightning::util::persist::{KVStore, Persister};
 #[cfg(feature = "std")]
 use lightning::util::wakers::Sleeper;
 use lightning_rapid_gossip_sync::RapidGossipSync;
@@ -277,6 +279,30 @@ fn update_scorer<'a, S: 'static + Deref<Target = SC> + Send + Sync, SC: 'a + Wri
 	true
 }
 
+/// Records the forward described by the given event, if any, in the [`ForwardingHistory`].
+fn record_forward<K: Deref, F: FnOnce() -> Vec<ChannelDetails>, L: Deref>(
+	forwarding_history: &ForwardingHistory<K>, event: &Event, duration_since_epoch: Duration,
+	list_channels: F, logger: &L,
+) where K::Target: KVStore, L::Target: Logger {
+	if let Err(e) = forwarding_history.handle_event(event, duration_since_epoch, list_channels) {
+		log_error!(logger, "Error: Failed to persist forwarding history, check your disk and permissions {}", e)
+	}
+}
+
+/// Records the payment described by the given event, if any, in the [`PaymentHistory`].
+fn record_payment<K: Deref, L: Deref>(
+	payment_history: &PaymentHistory<K>, event: &Event, duration_since_epoch: Duration, logger: &L,
+) where K::Target: KVStore, L::Target: Logger {
+	if let Err(e) = payment_history.handle_event(event, duration_since_epoch) {
+		log_error!(logger, "Error: Failed to persist payment history, check your disk and permissions {}", e)
+	}
+}
+
+/// The type used in place of a [`ForwardingHistory`] if none is given.
+type NoForwardingHistory = &'static ForwardingHistory<&'static (dyn KVStore + Send + Sync)>;
+/// The type used in place of a [`PaymentHistory`] if none is given.
+type NoPaymentHistory = &'static PaymentHistory<&'static (dyn KVStore + Send + Sync)>;
+
 macro_rules! define_run_body {
 	(
 		$persister: ident, $chain_monitor: ident, $process_chain_monitor_events: expr,
@@ -664,12 +690,85 @@ where
 	P::Target: 'static + Persist<<SP::Target as SignerProvider>::EcdsaSigner>,
 	PS::Target: 'static + Persister<'a, CW, T, ES, NS, SP, F, R, L, SC>,
 	PM::Target: APeerManager + Send + Sync,
+{
+	process_events_async_with_history(
+		persister, event_handler, chain_monitor, channel_manager, gossip_sync, peer_manager, logger,
+		scorer, None::<NoForwardingHistory>, None::<NoPaymentHistory>, sleeper,
+		mobile_interruptable_platform, fetch_time
+	).await
+}
+
+/// Processes background events in a future, as [`process_events_async`] does, additionally
+/// recording the HTLCs we forward in the given [`ForwardingHistory`] and the payments we send and
+/// receive in the given [`PaymentHistory`].
+///
+/// The [`ForwardingHistory`] and [`PaymentHistory`] are only updated while `fetch_time` returns a
+/// time.
+///
+/// Requires the `futures` feature.
+#[cfg(feature = "futures")]
+pub async fn process_events_async_with_history<
+	'a,
+	UL: 'static + Deref + Send + Sync,
+	CF: 'static + Deref + Send + Sync,
+	CW: 'static + Deref + Send + Sync,
+	T: 'static + Deref + Send + Sync,
+	ES: 'static + Deref + Send + Sync,
+	NS: 'static + Deref + Send + Sync,
+	SP: 'static + Deref + Send + Sync,
+	F: 'static + Deref + Send + Sync,
+	R: 'static + Deref + Send + Sync,
+	G: 'static + Deref<Target = NetworkGraph<L>> + Send + Sync,
+	L: 'static + Deref + Send + Sync,
+	P: 'static + Deref + Send + Sync,
+	EventHandlerFuture: core::future::Future<Output = ()>,
+	EventHandler: Fn(Event) -> EventHandlerFuture,
+	PS: 'static + Deref + Send,
+	M: 'static + Deref<Target = ChainMonitor<<SP::Target as SignerProvider>::EcdsaSigner, CF, T, F, L, P>> + Send + Sync,
+	CM: 'static + Deref<Target = ChannelManager<CW, T, ES, NS, SP, F, R, L>> + Send + Sync,
+	PGS: 'static + Deref<Target = P2PGossipSync<G, UL, L>> + Send + Sync,
+	RGS: 'static + Deref<Target = RapidGossipSync<G, L>> + Send,
+	PM: 'static + Deref + Send + Sync,
+	S: 'static + Deref<Target = SC> + Send + Sync,
+	SC: for<'b> WriteableScore<'b>,
+	FH: 'static + Deref<Target = ForwardingHistory<FK>> + Send + Sync,
+	FK: 'static + Deref + Send + Sync,
+	PH: 'static + Deref<Target = PaymentHistory<PK>> + Send + Sync,
+	PK: 'static + Deref + Send + Sync,
+	SleepFuture: core::future::Future<Output = bool> + core::marker::Unpin,
+	Sleeper: Fn(Duration) -> SleepFuture,
+	FetchTime: Fn() -> Option<Duration>,
+>(
+	persister: PS, event_handler: EventHandler, chain_monitor: M, channel_manager: CM,
+	gossip_sync: GossipSync<PGS, RGS, G, UL, L>, peer_manager: PM, logger: L, scorer: Option<S>,
+	forwarding_history: Option<FH>, payment_history: Option<PH>, sleeper: Sleeper,
+	mobile_interruptable_platform: bool, fetch_time: FetchTime,
+) -> Result<(), lightning::io::Error>
+where
+	UL::Target: 'static + UtxoLookup,
+	CF::Target: 'static + chain::Filter,
+	CW::Target: 'static + chain::Watch<<SP::Target as SignerProvider>::EcdsaSigner>,
+	T::Target: 'static + BroadcasterInterface,
+	ES::Target: 'static + EntropySource,
+	NS::Target: 'static + NodeSigner,
+	SP::Target: 'static + SignerProvider,
+	F::Target: 'static + FeeEstimator,
+	R::Target: 'static + Router,
+	L::Target: 'static + Logger,
+	P::Target: 'static + Persist<<SP::Target as SignerProvider>::EcdsaSigner>,
+	PS::Target: 'static + Persister<'a, CW, T, ES, NS, SP, F, R, L, SC>,
+	PM::Target: APeerManager + Send + Sync,
+	FK::Target: 'static + KVStore,
+	PK::Target: 'static + KVStore,
 {
 	let mut should_break = false;
 	let async_event_handler = |event| {
 		let network_graph = gossip_sync.network_graph();
 		let event_handler = &event_handler;
 		let scorer = &scorer;
+		let forwarding_history = &forwarding_history;
+		let payment_history = &payment_history;
+		let channel_manager = &channel_manager;
 		let logger = &logger;
 		let persister = &persister;
 		let fetch_time = &fetch_time;
@@ -687,6 +786,17 @@ where
 					}
 				}
 			}
+			if let Some(ref forwarding_history) = forwarding_history {
+				if let Some(duration_since_epoch) = fetch_time() {
+					record_forward(forwarding_history, &event, duration_since_epoch,
+						|| channel_manager.list_channels(), logger);
+				}
+			}
+			if let Some(ref payment_history) = payment_history {
+				if let Some(duration_since_epoch) = fetch_time() {
+					record_payment(payment_history, &event, duration_since_epoch, logger);
+				}
+			}
 			event_handler(event).await;
 		}
 	};
@@ -769,6 +879,9 @@ impl BackgroundProcessor {
 	/// functionality implemented by other handlers.
 	/// * [`P2PGossipSync`] if given will update the [`NetworkGraph`] based on payment failures.
 	///
+	/// A [`ForwardingHistory`] and [`PaymentHistory`] may additionally be populated via
+	/// [`start_with_history`].
+	///
 	/// # Rapid Gossip Sync
 	///
 	/// If rapid gossip sync is meant to run at startup, pass [`RapidGossipSync`] via `gossip_sync`
@@ -778,6 +891,7 @@ impl BackgroundProcessor {
 	/// [top-level documentation]: BackgroundProcessor
 	/// [`join`]: Self::join
 	/// [`stop`]: Self::stop
+	/// [`start_with_history`]: Self::start_with_history
 	/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
 	/// [`ChannelManager::write`]: lightning::ln::channelmanager::ChannelManager#impl-Writeable
 	/// [`Persister::persist_manager`]: lightning::util::persist::Persister::persist_manager
@@ -825,6 +939,66 @@ impl BackgroundProcessor {
 		P::Target: 'static + Persist<<SP::Target as SignerProvider>::EcdsaSigner>,
 		PS::Target: 'static + Persister<'a, CW, T, ES, NS, SP, F, R, L, SC>,
 		PM::Target: APeerManager + Send + Sync,
+	{
+		Self::start_with_history(
+			persister, event_handler, chain_monitor, channel_manager, gossip_sync, peer_manager, logger,
+			scorer, None::<NoForwardingHistory>, None::<NoPaymentHistory>
+		)
+	}
+
+	/// Start a background thread as [`start`] does, additionally recording the HTLCs we forward in
+	/// the given [`ForwardingHistory`] and the payments we send and receive in the given
+	/// [`PaymentHistory`].
+	///
+	/// [`start`]: Self::start
+	pub fn start_with_history<
+		'a,
+		UL: 'static + Deref + Send + Sync,
+		CF: 'static + Deref + Send + Sync,
+		CW: 'static + Deref + Send + Sync,
+		T: 'static + Deref + Send + Sync,
+		ES: 'static + Deref + Send + Sync,
+		NS: 'static + Deref + Send + Sync,
+		SP: 'static + Deref + Send + Sync,
+		F: 'static + Deref + Send + Sync,
+		R: 'static + Deref + Send + Sync,
+		G: 'static + Deref<Target = NetworkGraph<L>> + Send + Sync,
+		L: 'static + Deref + Send + Sync,
+		P: 'static + Deref + Send + Sync,
+		EH: 'static + EventHandler + Send,
+		PS: 'static + Deref + Send,
+		M: 'static + Deref<Target = ChainMonitor<<SP::Target as SignerProvider>::EcdsaSigner, CF, T, F, L, P>> + Send + Sync,
+		CM: 'static + Deref<Target = ChannelManager<CW, T, ES, NS, SP, F, R, L>> + Send + Sync,
+		PGS: 'static + Deref<Target = P2PGossipSync<G, UL, L>> + Send + Sync,
+		RGS: 'static + Deref<Target = RapidGossipSync<G, L>> + Send,
+		PM: 'static + Deref + Send + Sync,
+		S: 'static + Deref<Target = SC> + Send + Sync,
+		SC: for <'b> WriteableScore<'b>,
+		FH: 'static + Deref<Target = ForwardingHistory<FK>> + Send + Sync,
+		FK: 'static + Deref + Send + Sync,
+		PH: 'static + Deref<Target = PaymentHistory<PK>> + Send + Sync,
+		PK: 'static + Deref + Send + Sync,
+	>(
+		persister: PS, event_handler: EH, chain_monitor: M, channel_manager: CM,
+		gossip_sync: GossipSync<PGS, RGS, G, UL, L>, peer_manager: PM, logger: L, scorer: Option<S>,
+		forwarding_history: Option<FH>, payment_history: Option<PH>,
+	) -> Self
+	where
+		UL::Target: 'static + UtxoLookup,
+		CF::Target: 'static + chain::Filter,
+		CW::Target: 'static + chain::Watch<<SP::Target as SignerProvider>::EcdsaSigner>,
+		T::Target: 'static + BroadcasterInterface,
+		ES::Target: 'static + EntropySource,
+		NS::Target: 'static + NodeSigner,
+		SP::Target: 'static + SignerProvider,
+		F::Target: 'static + FeeEstimator,
+		R::Target: 'static + Router,
+		L::Target: 'static + Logger,
+		P::Target: 'static + Persist<<SP::Target as SignerProvider>::EcdsaSigner>,
+		PS::Target: 'static + Persister<'a, CW, T, ES, NS, SP, F, R, L, SC>,
+		PM::Target: APeerManager + Send + Sync,
+		FK::Target: 'static + KVStore,
+		PK::Target: 'static + KVStore,
 	{
 		let stop_thread = Arc::new(AtomicBool::new(false));
 		let stop_thread_clone = stop_thread.clone();
@@ -845,6 +1019,19 @@ impl BackgroundProcessor {
 						}
 					}
 				}
+				if let Some(ref forwarding_history) = forwarding_history {
+					use std::time::SystemTime;
+					let duration_since_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+						.expect("Time should be sometime after 1970");
+					record_forward(forwarding_history, &event, duration_since_epoch,
+						|| channel_manager.list_channels(), &logger);
+				}
+				if let Some(ref payment_history) = payment_history {
+					use std::time::SystemTime;
+					let duration_since_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+						.expect("Time should be sometime after 1970");
+					record_payment(payment_history, &event, duration_since_epoch, &logger);
+				}
 				event_handler.handle_event(event);
 			};
 			define_run_body!(
@@ -929,7 +1116,7 @@ mod tests {
 	use lightning::chain::transaction::OutPoint;
 	use lightning::events::{Event, PathFailure, MessageSendEventsProvider, MessageSendEvent};
 	use lightning::{get_event_msg, get_event};
-	use lightning::ln::PaymentHash;
+	use lightning::ln::{ChannelId, PaymentHash};
 	use lightning::ln::channelmanager;
 	use lightning::ln::channelmanager::{BREAKDOWN_TIMEOUT, ChainParameters, MIN_CLTV_EXPIRY_DELTA, PaymentId};
 	use lightning::ln::features::{ChannelFeatures, NodeFeatures};
@@ -940,6 +1127,8 @@ mod tests {
 	use lightning::routing::scoring::{ChannelUsage, ScoreUpdate, ScoreLookUp, LockableScore};
 	use lightning::routing::router::{DefaultRouter, Path, RouteHop, CandidateRouteHop};
 	use lightning::util::config::UserConfig;
+	use lightning::util::forwarding_history::ForwardingHistory;
+	use lightning::util::payment_history::{PaymentHistory, PaymentStatus};
 	use lightning::util::ser::Writeable;
 	use lightning::util::test_utils;
 	use lightning::util::persist::{KVStore,
@@ -995,6 +1184,9 @@ mod tests {
 	type PGS = Arc<P2PGossipSync<Arc<NetworkGraph<Arc<test_utils::TestLogger>>>, Arc<test_utils::TestChainSource>, Arc<test_utils::TestLogger>>>;
 	type RGS = Arc<RapidGossipSync<Arc<NetworkGraph<Arc<test_utils::TestLogger>>>, Arc<test_utils::TestLogger>>>;
 
+	type TestForwardingHistory = ForwardingHistory<Arc<test_utils::TestStore>>;
+	type TestPaymentHistory = PaymentHistory<Arc<test_utils::TestStore>>;
+
 	struct Node {
 		node: Arc<ChannelManager>,
 		p2p_gossip_sync: PGS,
@@ -1624,6 +1816,79 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn test_forwarding_history_recording() {
+		let (_, nodes) = create_nodes(2, "test_forwarding_history_recording");
+		let data_dir = nodes[0].kv_store.get_data_dir();
+		let persister = Arc::new(Persister::new(data_dir));
+		let forwarding_history: Arc<TestForwardingHistory> =
+			Arc::new(ForwardingHistory::new(Arc::new(test_utils::TestStore::new(false))));
+
+		let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+		let event_handler = move |event: Event| match event {
+			Event::PaymentForwarded { .. } => sender.send(()).unwrap(),
+			_ => panic!("Unexpected event: {:?}", event),
+		};
+		let bg_processor = BackgroundProcessor::start_with_history(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].no_gossip_sync(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(nodes[0].scorer.clone()), Some(Arc::clone(&forwarding_history)), None::<Arc<TestPaymentHistory>>);
+
+		nodes[0].node.push_pending_event(Event::PaymentForwarded {
+			prev_channel_id: Some(ChannelId::from_bytes([1; 32])),
+			next_channel_id: Some(ChannelId::from_bytes([2; 32])),
+			fee_earned_msat: Some(1000),
+			claim_from_onchain_tx: false,
+			outbound_amount_forwarded_msat: Some(100_000),
+			trampoline_route_fee_msat: None,
+		});
+		receiver
+			.recv_timeout(Duration::from_secs(EVENT_DEADLINE))
+			.expect("PaymentForwarded not handled within deadline");
+
+		// The forward is recorded before the user's event handler is called.
+		let summary = forwarding_history.summary(0, u64::max_value()).unwrap();
+		assert_eq!(summary.succeeded_forwards, 1);
+		assert_eq!(summary.failed_forwards, 0);
+		assert_eq!(summary.fee_earned_msat, 1000);
+		assert_eq!(summary.amount_forwarded_msat, 100_000);
+
+		if !std::thread::panicking() {
+			bg_processor.stop().unwrap();
+		}
+	}
+
+	#[test]
+	fn test_payment_history_recording() {
+		let (_, nodes) = create_nodes(2, "test_payment_history_recording");
+		let data_dir = nodes[0].kv_store.get_data_dir();
+		let persister = Arc::new(Persister::new(data_dir));
+		let payment_history: Arc<TestPaymentHistory> =
+			Arc::new(PaymentHistory::new(Arc::new(test_utils::TestStore::new(false))));
+
+		let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+		let event_handler = move |event: Event| match event {
+			Event::PaymentFailed { .. } => sender.send(()).unwrap(),
+			_ => panic!("Unexpected event: {:?}", event),
+		};
+		let bg_processor = BackgroundProcessor::start_with_history(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].no_gossip_sync(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(nodes[0].scorer.clone()), None::<Arc<TestForwardingHistory>>, Some(Arc::clone(&payment_history)));
+
+		let payment_id = PaymentId([42; 32]);
+		nodes[0].node.push_pending_event(Event::PaymentFailed {
+			payment_id,
+			payment_hash: PaymentHash([42; 32]),
+			reason: None,
+		});
+		receiver
+			.recv_timeout(Duration::from_secs(EVENT_DEADLINE))
+			.expect("PaymentFailed not handled within deadline");
+
+		// The payment is recorded before the user's event handler is called.
+		let record = payment_history.outbound_payment(&payment_id).unwrap().unwrap();
+		assert_eq!(record.status, PaymentStatus::Failed { reason: None });
+
+		if !std::thread::panicking() {
+			bg_processor.stop().unwrap();
+		}
+	}
+
 	#[test]
 	fn test_scorer_persistence() {
 		let (_, nodes) = create_nodes(2, "test_scorer_persistence");
@@ -5,8 +5,9 @@ use crate::{BlockData, BlockHeaderData, BlockSource, AsyncBlockSourceResult};
 use crate::http::{HttpClient, HttpEndpoint, HttpError, JsonResponse};
 use crate::gossip::UtxoSource;
 
-use bitcoin::hash_types::BlockHash;
-use bitcoin::OutPoint;
+use bitcoin::hash_types::{BlockHash, Txid};
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::{OutPoint, Transaction};
 
 use std::sync::Mutex;
 
@@ -114,6 +115,55 @@ impl RpcClient {
 
 		JsonResponse(result).try_into()
 	}
+
+	/// Submits a single transaction to the RPC server's mempool and relays it, returning its txid.
+	pub async fn send_raw_transaction(&self, tx: &Transaction) -> std::io::Result<Txid> {
+		let tx_hex = serde_json::json!(serialize_hex(tx));
+		self.call_method("sendrawtransaction", &[tx_hex]).await
+	}
+
+	/// Submits a package consisting of a child transaction and its unconfirmed parents to the RPC
+	/// server's mempool using `submitpackage`, such that they're evaluated as a unit and relayed.
+	///
+	/// The package must be topologically sorted with the child last, as is passed to
+	/// [`BroadcasterInterface::broadcast_package`]. Single-transaction packages are submitted via
+	/// `sendrawtransaction` instead, as not all servers accept them via `submitpackage`.
+	///
+	/// This serves as a reference for implementing [`BroadcasterInterface::broadcast_package`],
+	/// which must not block and thus should spawn the returned future on an async runtime. Note
+	/// that `submitpackage` requires Bitcoin Core 28.0 or later for packages to be relayed.
+	///
+	/// When an `Err` is returned for a package rejected by the RPC server, its
+	/// [`std::io::Error::into_inner`] contains an [`RpcError`] with the reason(s) the package or
+	/// any of its transactions were rejected.
+	///
+	/// [`BroadcasterInterface::broadcast_package`]: lightning::chain::chaininterface::BroadcasterInterface::broadcast_package
+	pub async fn submit_package(&self, package: &[&Transaction]) -> std::io::Result<()> {
+		if package.len() == 1 {
+			return self.send_raw_transaction(package[0]).await.map(|_| ());
+		}
+
+		let package_hex = serde_json::json!(package.iter().map(|tx| serialize_hex(*tx)).collect::<Vec<_>>());
+		let response: serde_json::Value = self.call_method("submitpackage", &[package_hex]).await?;
+		let package_msg = match response["package_msg"].as_str() {
+			Some(package_msg) => package_msg,
+			None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected package_msg")),
+		};
+		if package_msg == "success" {
+			return Ok(());
+		}
+
+		let mut message = package_msg.to_string();
+		if let Some(tx_results) = response["tx-results"].as_object() {
+			for (wtxid, tx_result) in tx_results {
+				if let Some(error) = tx_result["error"].as_str() {
+					message.push_str(&format!("; {}: {}", wtxid, error));
+				}
+			}
+		}
+		// Mirror the RPC_VERIFY_REJECTED code returned by `sendrawtransaction` for rejections.
+		Err(std::io::Error::new(std::io::ErrorKind::Other, RpcError { code: -26, message }))
+	}
 }
 
 impl BlockSource for RpcClient {
@@ -269,6 +319,73 @@ mod tests {
 		}
 	}
 
+	fn package() -> (Transaction, Transaction) {
+		let parent = Transaction {
+			version: 3,
+			lock_time: bitcoin::blockdata::locktime::absolute::LockTime::ZERO,
+			input: vec![bitcoin::TxIn::default()],
+			output: vec![bitcoin::TxOut::default()],
+		};
+		let child = Transaction {
+			version: 3,
+			lock_time: bitcoin::blockdata::locktime::absolute::LockTime::ZERO,
+			input: vec![bitcoin::TxIn {
+				previous_output: OutPoint::new(parent.txid(), 0),
+				..Default::default()
+			}],
+			output: vec![bitcoin::TxOut::default()],
+		};
+		(parent, child)
+	}
+
+	#[tokio::test]
+	async fn submits_package() {
+		let response = serde_json::json!({ "result": {
+			"package_msg": "success", "tx-results": {}, "replaced-transactions": []
+		}});
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+
+		let (parent, child) = package();
+		match client.submit_package(&[&parent, &child]).await {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(()) => {},
+		}
+	}
+
+	#[tokio::test]
+	async fn fails_to_submit_package() {
+		let response = serde_json::json!({ "result": {
+			"package_msg": "transaction failed",
+			"tx-results": { "abcd": { "txid": "abcd", "error": "min relay fee not met" } },
+		}});
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+
+		let (parent, child) = package();
+		match client.submit_package(&[&parent, &child]).await {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::Other);
+				let rpc_error: Box<RpcError> = e.into_inner().unwrap().downcast().unwrap();
+				assert_eq!(rpc_error.message, "transaction failed; abcd: min relay fee not met");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[tokio::test]
+	async fn submits_single_transaction_package() {
+		let (parent, _) = package();
+		let response = serde_json::json!({ "result": parent.txid().to_string() });
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+
+		match client.submit_package(&[&parent]).await {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(()) => {},
+		}
+	}
+
 	#[tokio::test]
 	async fn fails_to_fetch_spent_utxo() {
 		let response = serde_json::json!({ "result": null });
@@ -617,6 +617,9 @@ mod tests {
 		fn handle_open_channel_v2(&self, _their_node_id: &PublicKey, _msg: &OpenChannelV2) {}
 		fn handle_accept_channel_v2(&self, _their_node_id: &PublicKey, _msg: &AcceptChannelV2) {}
 		fn handle_stfu(&self, _their_node_id: &PublicKey, _msg: &Stfu) {}
+		fn handle_dyn_propose(&self, _their_node_id: &PublicKey, _msg: &DynPropose) {}
+		fn handle_dyn_ack(&self, _their_node_id: &PublicKey, _msg: &DynAck) {}
+		fn handle_dyn_reject(&self, _their_node_id: &PublicKey, _msg: &DynReject) {}
 		fn handle_splice(&self, _their_node_id: &PublicKey, _msg: &Splice) {}
 		fn handle_splice_ack(&self, _their_node_id: &PublicKey, _msg: &SpliceAck) {}
 		fn handle_splice_locked(&self, _their_node_id: &PublicKey, _msg: &SpliceLocked) {}
@@ -629,6 +632,8 @@ mod tests {
 		fn handle_tx_init_rbf(&self, _their_node_id: &PublicKey, _msg: &TxInitRbf) {}
 		fn handle_tx_ack_rbf(&self, _their_node_id: &PublicKey, _msg: &TxAckRbf) {}
 		fn handle_tx_abort(&self, _their_node_id: &PublicKey, _msg: &TxAbort) {}
+		fn handle_peer_storage(&self, _their_node_id: &PublicKey, _msg: &PeerStorage) {}
+		fn handle_your_peer_storage(&self, _their_node_id: &PublicKey, _msg: &YourPeerStorage) {}
 		fn peer_disconnected(&self, their_node_id: &PublicKey) {
 			if *their_node_id == self.expected_pubkey {
 				self.disconnected_flag.store(true, Ordering::SeqCst);
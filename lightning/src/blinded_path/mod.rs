@@ -70,6 +70,23 @@ impl BlindedPath {
 	//  TODO: make all payloads the same size with padding + add dummy hops
 	pub fn new_for_message<ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification>(
 		node_pks: &[PublicKey], entropy_source: &ES, secp_ctx: &Secp256k1<T>
+	) -> Result<Self, ()> {
+		Self::new_for_message_internal(node_pks, None, entropy_source, secp_ctx)
+	}
+
+	/// Create a blinded path for an onion message, to be forwarded along `node_pks`, which the
+	/// destination node (the last node pubkey in `node_pks`) can identify using `path_id` when
+	/// receiving a message over it.
+	///
+	/// Errors if no hops are provided or if `node_pk`(s) are invalid.
+	pub fn new_for_message_with_path_id<ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification>(
+		node_pks: &[PublicKey], path_id: [u8; 32], entropy_source: &ES, secp_ctx: &Secp256k1<T>
+	) -> Result<Self, ()> {
+		Self::new_for_message_internal(node_pks, Some(path_id), entropy_source, secp_ctx)
+	}
+
+	fn new_for_message_internal<ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification>(
+		node_pks: &[PublicKey], path_id: Option<[u8; 32]>, entropy_source: &ES, secp_ctx: &Secp256k1<T>
 	) -> Result<Self, ()> {
 		if node_pks.is_empty() { return Err(()) }
 		let blinding_secret_bytes = entropy_source.get_secure_random_bytes();
@@ -79,7 +96,7 @@ impl BlindedPath {
 		Ok(BlindedPath {
 			introduction_node_id,
 			blinding_point: PublicKey::from_secret_key(secp_ctx, &blinding_secret),
-			blinded_hops: message::blinded_hops(secp_ctx, node_pks, &blinding_secret).map_err(|_| ())?,
+			blinded_hops: message::blinded_hops(secp_ctx, node_pks, path_id, &blinding_secret).map_err(|_| ())?,
 		})
 	}
 
@@ -42,6 +42,24 @@ pub trait BroadcasterInterface {
 	/// Bitcoin transaction packages are defined in BIP 331 and here:
 	/// <https://github.com/bitcoin/bitcoin/blob/master/doc/policy/packages.md>
 	fn broadcast_transactions(&self, txs: &[&Transaction]);
+
+	/// Sends a package consisting of a single child transaction along with its unconfirmed parents
+	/// out to (hopefully) be mined, such that they're evaluated as a unit (e.g. via Bitcoin Core's
+	/// `submitpackage` RPC) rather than each transaction on its own.
+	///
+	/// The package is topologically sorted, with the child always being the last transaction and
+	/// spending at least one output of each of the other transactions. LDK uses this to broadcast
+	/// a commitment transaction along with the child transaction spending its anchor output, which
+	/// is required for the commitment transaction to be relayed if its own feerate is below the
+	/// mempool minimum, e.g. for zero-fee commitments.
+	///
+	/// As with [`Self::broadcast_transactions`], LDK will automatically rebroadcast packages that
+	/// haven't made it into a block.
+	///
+	/// The default implementation simply calls [`Self::broadcast_transactions`] with the package.
+	fn broadcast_package(&self, package: &[&Transaction]) {
+		self.broadcast_transactions(package);
+	}
 }
 
 /// An enum that represents the priority at which we want a transaction to confirm used for feerate
@@ -39,6 +39,7 @@ use crate::util::logger::{Logger, WithContext};
 use crate::util::errors::APIError;
 use crate::util::wakers::{Future, Notifier};
 use crate::ln::channelmanager::ChannelDetails;
+use crate::io;
 
 use crate::prelude::*;
 use crate::sync::{RwLock, RwLockReadGuard, Mutex, MutexGuard};
@@ -194,6 +195,18 @@ pub trait Persist<ChannelSigner: WriteableEcdsaChannelSigner> {
 	///
 	/// [`Writeable::write`]: crate::util::ser::Writeable::write
 	fn update_persisted_channel(&self, channel_id: OutPoint, update: Option<&ChannelMonitorUpdate>, data: &ChannelMonitor<ChannelSigner>, update_id: MonitorUpdateId) -> ChannelMonitorUpdateStatus;
+
+	/// Prevents the channel monitor from being loaded on startup, as it has been fully resolved
+	/// and removed from the [`ChainMonitor`] by
+	/// [`ChainMonitor::archive_fully_resolved_channel_monitors`].
+	///
+	/// Rather than deleting the data outright, it should be moved to an archive location to hedge
+	/// against data loss in case of unexpected failure.
+	///
+	/// If an error is returned, the failure is logged and the [`ChannelMonitor`] is kept, so that
+	/// archiving it is retried on the next call to
+	/// [`ChainMonitor::archive_fully_resolved_channel_monitors`].
+	fn archive_persisted_channel(&self, channel_id: OutPoint) -> Result<(), io::Error>;
 }
 
 struct MonitorHolder<ChannelSigner: WriteableEcdsaChannelSigner> {
@@ -501,6 +514,45 @@ where C::Target: chain::Filter,
 		self.monitors.write().unwrap().remove(funding_txo).unwrap().monitor
 	}
 
+	/// Removes the [`ChannelMonitor`]s which have been fully resolved for at least
+	/// `resolved_blocks` blocks from the set of monitors, archiving them via
+	/// [`Persist::archive_persisted_channel`]. Their outputs are no longer watched and they will
+	/// no longer be returned by [`Self::list_monitors`].
+	///
+	/// See [`ChannelMonitor::is_fully_resolved`] for the exact criteria, including the minimum
+	/// `resolved_blocks` enforced. Because the time at which a monitor became fully resolved is
+	/// only noted when this is called, this should be called regularly, e.g. once per block or once
+	/// a day. A `resolved_blocks` of 4032, i.e. roughly four weeks, leaves ample time to deal with
+	/// any unexpected on-chain activity.
+	pub fn archive_fully_resolved_channel_monitors(&self, resolved_blocks: u32) {
+		let mut have_monitors_to_archive = false;
+		for (_, monitor_holder) in self.monitors.read().unwrap().iter() {
+			if monitor_holder.monitor.is_fully_resolved(&self.logger, resolved_blocks) {
+				have_monitors_to_archive = true;
+			}
+		}
+		if have_monitors_to_archive {
+			let mut monitors = self.monitors.write().unwrap();
+			monitors.retain(|funding_txo, monitor_holder| {
+				// Don't archive a monitor while a persistence of it is still in flight.
+				let has_pending_updates = !monitor_holder.pending_monitor_updates.lock().unwrap().is_empty();
+				if !has_pending_updates && monitor_holder.monitor.is_fully_resolved(&self.logger, resolved_blocks) {
+					let logger = WithChannelMonitor::from(&self.logger, &monitor_holder.monitor);
+					log_info!(logger, "Archiving fully resolved ChannelMonitor for funding txo {}", funding_txo);
+					match self.persister.archive_persisted_channel(*funding_txo) {
+						Ok(()) => false,
+						Err(e) => {
+							log_error!(logger, "Failed to archive ChannelMonitor for funding txo {}, will retry: {}", funding_txo, e);
+							true
+						},
+					}
+				} else {
+					true
+				}
+			});
+		}
+	}
+
 	/// Indicates the persistence of a [`ChannelMonitor`] has completed after
 	/// [`ChannelMonitorUpdateStatus::InProgress`] was returned from an update operation.
 	///
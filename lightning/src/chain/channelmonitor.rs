@@ -36,6 +36,7 @@ use bitcoin::sighash::EcdsaSighashType;
 use crate::ln::channel::INITIAL_COMMITMENT_NUMBER;
 use crate::ln::{PaymentHash, PaymentPreimage, ChannelId};
 use crate::ln::msgs::DecodeError;
+use crate::ln::features::ChannelTypeFeatures;
 use crate::ln::channel_keys::{DelayedPaymentKey, DelayedPaymentBasepoint, HtlcBasepoint, HtlcKey, RevocationKey, RevocationBasepoint};
 use crate::ln::chan_utils::{self,CommitmentTransaction, CounterpartyCommitmentSecrets, HTLCOutputInCommitment, HTLCClaim, ChannelTransactionParameters, HolderCommitmentTransaction, TxCreationKeys};
 use crate::ln::channelmanager::{HTLCSource, SentHTLCId};
@@ -533,6 +534,15 @@ pub(crate) enum ChannelMonitorUpdateStep {
 	ShutdownScript {
 		scriptpubkey: ScriptBuf,
 	},
+	/// Used to indicate that the channel's type has been changed by a dynamic commitment upgrade.
+	/// Commitment transactions numbered `holder_commitment_number` and
+	/// `counterparty_commitment_number` (and all later ones) are built using
+	/// `channel_type_features`, while earlier ones still use the previous type.
+	ChannelTypeUpdated {
+		channel_type_features: ChannelTypeFeatures,
+		holder_commitment_number: u64,
+		counterparty_commitment_number: u64,
+	},
 }
 
 impl ChannelMonitorUpdateStep {
@@ -544,6 +554,7 @@ impl ChannelMonitorUpdateStep {
 			ChannelMonitorUpdateStep::CommitmentSecret { .. } => "CommitmentSecret",
 			ChannelMonitorUpdateStep::ChannelForceClosed { .. } => "ChannelForceClosed",
 			ChannelMonitorUpdateStep::ShutdownScript { .. } => "ShutdownScript",
+			ChannelMonitorUpdateStep::ChannelTypeUpdated { .. } => "ChannelTypeUpdated",
 		}
 	}
 }
@@ -577,8 +588,30 @@ impl_writeable_tlv_based_enum_upgradable!(ChannelMonitorUpdateStep,
 	(5, ShutdownScript) => {
 		(0, scriptpubkey, required),
 	},
+	(6, ChannelTypeUpdated) => {
+		(0, channel_type_features, required),
+		(2, holder_commitment_number, required),
+		(4, counterparty_commitment_number, required),
+	},
 );
 
+/// A change of the channel's type which happened while the channel was open. Commitment
+/// transactions built before the change still use `previous_channel_type_features`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ChannelTypeUpgrade {
+	holder_commitment_number: u64,
+	counterparty_commitment_number: u64,
+	previous_channel_type_features: ChannelTypeFeatures,
+	channel_type_features: ChannelTypeFeatures,
+}
+
+impl_writeable_tlv_based!(ChannelTypeUpgrade, {
+	(0, holder_commitment_number, required),
+	(2, counterparty_commitment_number, required),
+	(4, previous_channel_type_features, required),
+	(6, channel_type_features, required),
+});
+
 /// Details about the balance(s) available for spending once the channel appears on chain.
 ///
 /// See [`ChannelMonitor::get_claimable_balances`] for more details on when these will or will not
@@ -906,6 +939,14 @@ pub(crate) struct ChannelMonitorImpl<Signer: WriteableEcdsaChannelSigner> {
 	/// Ordering of tuple data: (their_per_commitment_point, feerate_per_kw, to_broadcaster_sats,
 	/// to_countersignatory_sats)
 	initial_counterparty_commitment_info: Option<(PublicKey, u32, u64, u64)>,
+
+	/// Any changes of the channel type which happened after the channel was opened, in the order
+	/// they were applied.
+	channel_type_upgrades: Vec<ChannelTypeUpgrade>,
+
+	/// The height at which [`ChannelMonitor::is_fully_resolved`] first found us to have no
+	/// claimable balances left, or `None` if we had some when it was last called.
+	balances_empty_height: Option<u32>,
 }
 
 /// Transaction outputs to watch for on-chain spends.
@@ -1097,6 +1138,8 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for ChannelMonitorImpl<Signe
 			(13, self.spendable_txids_confirmed, required_vec),
 			(15, self.counterparty_fulfilled_htlcs, required),
 			(17, self.initial_counterparty_commitment_info, option),
+			(19, self.channel_type_upgrades, optional_vec),
+			(21, self.balances_empty_height, option),
 		});
 
 		Ok(())
@@ -1278,6 +1321,8 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 			best_block,
 			counterparty_node_id: Some(counterparty_node_id),
 			initial_counterparty_commitment_info: None,
+			channel_type_upgrades: Vec::new(),
+			balances_empty_height: None,
 		})
 	}
 
@@ -1780,6 +1825,53 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 		spendable_outputs
 	}
 
+	/// Checks whether this monitor is fully resolved, i.e. the channel's funding output has been
+	/// spent, [`Self::get_claimable_balances`] has returned no balances for at least
+	/// `resolved_blocks` blocks, and all events it generated have been handled. Fully resolved
+	/// monitors are no longer needed and may be archived via
+	/// [`ChainMonitor::archive_fully_resolved_channel_monitors`].
+	///
+	/// A `resolved_blocks` below [`ANTI_REORG_DELAY`] is treated as [`ANTI_REORG_DELAY`], giving a
+	/// reorg at least that many blocks to bring back a balance before we forget about it.
+	///
+	/// The height at which we first had no claimable balances left is only noted when this is
+	/// called, so it should be called regularly, e.g. once per block or on startup.
+	///
+	/// [`ChainMonitor::archive_fully_resolved_channel_monitors`]: crate::chain::chainmonitor::ChainMonitor::archive_fully_resolved_channel_monitors
+	pub fn is_fully_resolved<L: Deref>(&self, logger: &L, resolved_blocks: u32) -> bool
+	where L::Target: Logger {
+		let resolved_blocks = cmp::max(resolved_blocks, ANTI_REORG_DELAY);
+		let no_claimable_balances = self.get_claimable_balances().is_empty();
+		let mut inner = self.inner.lock().unwrap();
+		let logger = WithChannelMonitor::from_impl(logger, &*inner);
+		let current_height = inner.best_block.height;
+		// A monitor without balances whose funding output hasn't been spent tracks a channel which
+		// never confirmed, which we don't want to forget about.
+		let is_all_funds_claimed = no_claimable_balances && inner.funding_spend_seen;
+		// Events not yet handled, e.g. HTLC resolutions the `ChannelManager` still needs to fail or
+		// claim backwards, would be lost along with the monitor.
+		let has_pending_events =
+			!inner.pending_monitor_events.is_empty() || !inner.pending_events.is_empty();
+		match (inner.balances_empty_height, is_all_funds_claimed) {
+			(Some(balances_empty_height), true) => {
+				!has_pending_events &&
+					current_height >= balances_empty_height.saturating_add(resolved_blocks)
+			},
+			(Some(_), false) => {
+				// This can happen if the transactions claiming our balances were reorged out.
+				log_debug!(logger, "ChannelMonitor has claimable balances again, resetting its resolution height");
+				inner.balances_empty_height = None;
+				false
+			},
+			(None, true) => {
+				log_debug!(logger, "ChannelMonitor has no claimable balances left as of height {}", current_height);
+				inner.balances_empty_height = Some(current_height);
+				false
+			},
+			(None, false) => false,
+		}
+	}
+
 	#[cfg(test)]
 	pub fn get_counterparty_payment_script(&self) -> ScriptBuf {
 		self.inner.lock().unwrap().counterparty_payment_script.clone()
@@ -2579,6 +2671,15 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 			feerate_per_kw: trusted_tx.feerate_per_kw(),
 		};
 		self.onchain_tx_handler.provide_latest_holder_tx(holder_commitment_tx);
+		if let Some(upgrade) = self.channel_type_upgrades.last() {
+			// Once our first commitment transaction using the new channel type is in place, any
+			// claims on it (and our pre-signed HTLC transactions) must use the new type.
+			if self.current_holder_commitment_number <= upgrade.holder_commitment_number &&
+				self.onchain_tx_handler.channel_type_features() != &upgrade.channel_type_features
+			{
+				self.onchain_tx_handler.update_channel_type_features(upgrade.channel_type_features.clone());
+			}
+		}
 		mem::swap(&mut new_holder_commitment_tx, &mut self.current_holder_commitment_tx);
 		self.prev_holder_signed_commitment_tx = Some(new_holder_commitment_tx);
 		for (claimed_htlc_id, claimed_preimage) in claimed_htlcs {
@@ -2849,6 +2950,11 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 						panic!("Attempted to replace shutdown script {} with {}", shutdown_script, scriptpubkey);
 					}
 				},
+				ChannelMonitorUpdateStep::ChannelTypeUpdated { channel_type_features, holder_commitment_number, counterparty_commitment_number } => {
+					log_trace!(logger, "Updating ChannelMonitor with new channel type {}", channel_type_features);
+					if self.lockdown_from_offchain { panic!(); }
+					self.provide_channel_type_upgrade(channel_type_features.clone(), *holder_commitment_number, *counterparty_commitment_number);
+				},
 			}
 		}
 
@@ -2936,11 +3042,14 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 				} => {
 					let mut htlc_descriptors = Vec::with_capacity(htlcs.len());
 					for htlc in htlcs {
+						let mut transaction_parameters = self.onchain_tx_handler.channel_transaction_parameters.clone();
+						transaction_parameters.channel_type_features =
+							self.holder_commitment_channel_type_features(htlc.per_commitment_number).clone();
 						htlc_descriptors.push(HTLCDescriptor {
 							channel_derivation_parameters: ChannelDerivationParameters {
 								keys_id: self.channel_keys_id,
 								value_satoshis: self.channel_value_satoshis,
-								transaction_parameters: self.onchain_tx_handler.channel_transaction_parameters.clone(),
+								transaction_parameters,
 							},
 							commitment_txid: htlc.commitment_txid,
 							per_commitment_number: htlc.per_commitment_number,
@@ -2990,13 +3099,17 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 		let countersignatory_funding_key = countersignatory_keys.funding_pubkey;
 		let keys = TxCreationKeys::from_channel_static_keys(&their_per_commitment_point,
 			&broadcaster_keys, &countersignatory_keys, &self.onchain_tx_handler.secp_ctx);
-		let channel_parameters =
-			&self.onchain_tx_handler.channel_transaction_parameters.as_counterparty_broadcastable();
+		let mut channel_parameters = self.onchain_tx_handler.channel_transaction_parameters.clone();
+		channel_parameters.channel_type_features =
+			self.counterparty_commitment_channel_type_features(commitment_number).clone();
 
+		let ephemeral_anchor_value_sat = chan_utils::ephemeral_anchor_value_sat(
+			self.channel_value_satoshis, to_broadcaster_value, to_countersignatory_value,
+			nondust_htlcs.iter().map(|(htlc, _)| htlc));
 		CommitmentTransaction::new_with_auxiliary_htlc_data(commitment_number,
-			to_broadcaster_value, to_countersignatory_value, broadcaster_funding_key,
+			to_broadcaster_value, to_countersignatory_value, ephemeral_anchor_value_sat, broadcaster_funding_key,
 			countersignatory_funding_key, keys, feerate_per_kw, &mut nondust_htlcs,
-			channel_parameters)
+			&channel_parameters.as_counterparty_broadcastable())
 	}
 
 	fn counterparty_commitment_txs_from_update(&self, update: &ChannelMonitorUpdate) -> Vec<CommitmentTransaction> {
@@ -3065,6 +3178,45 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 		self.current_holder_commitment_number
 	}
 
+	fn provide_channel_type_upgrade(&mut self, channel_type_features: ChannelTypeFeatures, holder_commitment_number: u64, counterparty_commitment_number: u64) {
+		let previous_channel_type_features = self.latest_channel_type_features().clone();
+		// Our output on the counterparty's commitment transactions changes with the channel type,
+		// so start watching for the new one. Ones using a previous type are still matched against
+		// the upgrades we track when looking for spendable outputs.
+		self.counterparty_payment_script = chan_utils::get_counterparty_payment_script(
+			&channel_type_features, &self.onchain_tx_handler.channel_transaction_parameters.holder_pubkeys.payment_point);
+		self.channel_type_upgrades.push(ChannelTypeUpgrade {
+			holder_commitment_number, counterparty_commitment_number,
+			previous_channel_type_features, channel_type_features,
+		});
+	}
+
+	/// The channel type used for any commitment transaction built from now on.
+	fn latest_channel_type_features(&self) -> &ChannelTypeFeatures {
+		self.channel_type_upgrades.last().map(|upgrade| &upgrade.channel_type_features)
+			.unwrap_or(self.onchain_tx_handler.channel_type_features())
+	}
+
+	/// The channel type the counterparty commitment transaction with the given commitment number
+	/// was built with.
+	fn counterparty_commitment_channel_type_features(&self, commitment_number: u64) -> &ChannelTypeFeatures {
+		// Commitment numbers count down, so any commitment with a number above the one an upgrade
+		// applied from was built before that upgrade.
+		self.channel_type_upgrades.iter()
+			.find(|upgrade| commitment_number > upgrade.counterparty_commitment_number)
+			.map(|upgrade| &upgrade.previous_channel_type_features)
+			.unwrap_or(self.latest_channel_type_features())
+	}
+
+	/// The channel type our holder commitment transaction with the given commitment number was
+	/// built with.
+	fn holder_commitment_channel_type_features(&self, commitment_number: u64) -> &ChannelTypeFeatures {
+		self.channel_type_upgrades.iter()
+			.find(|upgrade| commitment_number > upgrade.holder_commitment_number)
+			.map(|upgrade| &upgrade.previous_channel_type_features)
+			.unwrap_or(self.latest_channel_type_features())
+	}
+
 	/// Attempts to claim a counterparty commitment transaction's outputs using the revocation key and
 	/// data in counterparty_claimable_outpoints. Will directly claim any HTLC outputs which expire at a
 	/// height > height + CLTV_SHARED_CLAIM_BUFFER. In any case, will install monitoring for
@@ -3104,11 +3256,12 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 
 			let revokeable_redeemscript = chan_utils::get_revokeable_redeemscript(&revocation_pubkey, self.counterparty_commitment_params.on_counterparty_tx_csv, &delayed_key);
 			let revokeable_p2wsh = revokeable_redeemscript.to_v0_p2wsh();
+			let channel_type_features = self.counterparty_commitment_channel_type_features(commitment_number).clone();
 
 			// First, process non-htlc outputs (to_holder & to_counterparty)
 			for (idx, outp) in tx.output.iter().enumerate() {
 				if outp.script_pubkey == revokeable_p2wsh {
-					let revk_outp = RevokedOutput::build(per_commitment_point, self.counterparty_commitment_params.counterparty_delayed_payment_base_key, self.counterparty_commitment_params.counterparty_htlc_base_key, per_commitment_key, outp.value, self.counterparty_commitment_params.on_counterparty_tx_csv, self.onchain_tx_handler.channel_type_features().supports_anchors_zero_fee_htlc_tx());
+					let revk_outp = RevokedOutput::build(per_commitment_point, self.counterparty_commitment_params.counterparty_delayed_payment_base_key, self.counterparty_commitment_params.counterparty_htlc_base_key, per_commitment_key, outp.value, self.counterparty_commitment_params.on_counterparty_tx_csv, channel_type_features.supports_anchors_zero_fee_htlc_tx());
 					let justice_package = PackageTemplate::build_package(commitment_txid, idx as u32, PackageSolvingData::RevokedOutput(revk_outp), height + self.counterparty_commitment_params.on_counterparty_tx_csv as u32, height);
 					claimable_outpoints.push(justice_package);
 					to_counterparty_output_info =
@@ -3126,7 +3279,7 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 							return (claimable_outpoints, (commitment_txid, watch_outputs),
 								to_counterparty_output_info);
 						}
-						let revk_htlc_outp = RevokedHTLCOutput::build(per_commitment_point, self.counterparty_commitment_params.counterparty_delayed_payment_base_key, self.counterparty_commitment_params.counterparty_htlc_base_key, per_commitment_key, htlc.amount_msat / 1000, htlc.clone(), &self.onchain_tx_handler.channel_transaction_parameters.channel_type_features);
+						let revk_htlc_outp = RevokedHTLCOutput::build(per_commitment_point, self.counterparty_commitment_params.counterparty_delayed_payment_base_key, self.counterparty_commitment_params.counterparty_htlc_base_key, per_commitment_key, htlc.amount_msat / 1000, htlc.clone(), &channel_type_features);
 						let justice_package = PackageTemplate::build_package(commitment_txid, transaction_output_index, PackageSolvingData::RevokedHTLCOutput(revk_htlc_outp), htlc.cltv_expiry, height);
 						claimable_outpoints.push(justice_package);
 					}
@@ -3244,13 +3397,13 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 							CounterpartyOfferedHTLCOutput::build(*per_commitment_point,
 								self.counterparty_commitment_params.counterparty_delayed_payment_base_key,
 								self.counterparty_commitment_params.counterparty_htlc_base_key,
-								preimage.unwrap(), htlc.clone(), self.onchain_tx_handler.channel_type_features().clone()))
+								preimage.unwrap(), htlc.clone(), self.counterparty_commitment_channel_type_features(commitment_number).clone()))
 					} else {
 						PackageSolvingData::CounterpartyReceivedHTLCOutput(
 							CounterpartyReceivedHTLCOutput::build(*per_commitment_point,
 								self.counterparty_commitment_params.counterparty_delayed_payment_base_key,
 								self.counterparty_commitment_params.counterparty_htlc_base_key,
-								htlc.clone(), self.onchain_tx_handler.channel_type_features().clone()))
+								htlc.clone(), self.counterparty_commitment_channel_type_features(commitment_number).clone()))
 					};
 					let counterparty_package = PackageTemplate::build_package(commitment_txid, transaction_output_index, counterparty_htlc_outp, htlc.cltv_expiry, 0);
 					claimable_outpoints.push(counterparty_package);
@@ -3317,11 +3470,20 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 		let redeemscript = chan_utils::get_revokeable_redeemscript(&holder_tx.revocation_key, self.on_holder_tx_csv, &holder_tx.delayed_payment_key);
 		let broadcasted_holder_revokable_script = Some((redeemscript.to_v0_p2wsh(), holder_tx.per_commitment_point.clone(), holder_tx.revocation_key.clone()));
 
+		// Our previous holder commitment may predate a channel type upgrade, so its HTLCs must be
+		// claimed with the type it was signed with rather than the latest one.
+		let commitment_number = if holder_tx.txid == self.current_holder_commitment_tx.txid {
+			self.current_holder_commitment_number
+		} else {
+			self.current_holder_commitment_number + 1
+		};
+		let channel_type_features = self.holder_commitment_channel_type_features(commitment_number);
+
 		for &(ref htlc, _, _) in holder_tx.htlc_outputs.iter() {
 			if let Some(transaction_output_index) = htlc.transaction_output_index {
 				let htlc_output = if htlc.offered {
 					let htlc_output = HolderHTLCOutput::build_offered(
-						htlc.amount_msat, htlc.cltv_expiry, self.onchain_tx_handler.channel_type_features().clone()
+						htlc.amount_msat, htlc.cltv_expiry, channel_type_features.clone()
 					);
 					htlc_output
 				} else {
@@ -3332,7 +3494,7 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 						continue;
 					};
 					let htlc_output = HolderHTLCOutput::build_accepted(
-						payment_preimage, htlc.amount_msat, self.onchain_tx_handler.channel_type_features().clone()
+						payment_preimage, htlc.amount_msat, channel_type_features.clone()
 					);
 					htlc_output
 				};
@@ -4258,13 +4420,23 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 					}));
 				}
 			}
-			if self.counterparty_payment_script == outp.script_pubkey {
+			let payment_point = &self.onchain_tx_handler.channel_transaction_parameters.holder_pubkeys.payment_point;
+			let payment_output_channel_type_features = if self.counterparty_payment_script == outp.script_pubkey {
+				Some(self.latest_channel_type_features())
+			} else {
+				self.channel_type_upgrades.iter()
+					.map(|upgrade| &upgrade.previous_channel_type_features)
+					.find(|features| chan_utils::get_counterparty_payment_script(features, payment_point) == outp.script_pubkey)
+			};
+			if let Some(channel_type_features) = payment_output_channel_type_features {
+				let mut channel_transaction_parameters = self.onchain_tx_handler.channel_transaction_parameters.clone();
+				channel_transaction_parameters.channel_type_features = channel_type_features.clone();
 				spendable_outputs.push(SpendableOutputDescriptor::StaticPaymentOutput(StaticPaymentOutputDescriptor {
 					outpoint: OutPoint { txid: tx.txid(), index: i as u16 },
 					output: outp.clone(),
 					channel_keys_id: self.channel_keys_id,
 					channel_value_satoshis: self.channel_value_satoshis,
-					channel_transaction_parameters: Some(self.onchain_tx_handler.channel_transaction_parameters.clone()),
+					channel_transaction_parameters: Some(channel_transaction_parameters),
 				}));
 			}
 			if self.shutdown_script.as_ref() == Some(&outp.script_pubkey) {
@@ -4557,6 +4729,8 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 		let mut spendable_txids_confirmed = Some(Vec::new());
 		let mut counterparty_fulfilled_htlcs = Some(HashMap::new());
 		let mut initial_counterparty_commitment_info = None;
+		let mut channel_type_upgrades = Some(Vec::new());
+		let mut balances_empty_height = None;
 		read_tlv_fields!(reader, {
 			(1, funding_spend_confirmed, option),
 			(3, htlcs_resolved_on_chain, optional_vec),
@@ -4567,6 +4741,8 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			(13, spendable_txids_confirmed, optional_vec),
 			(15, counterparty_fulfilled_htlcs, option),
 			(17, initial_counterparty_commitment_info, option),
+			(19, channel_type_upgrades, optional_vec),
+			(21, balances_empty_height, option),
 		});
 
 		// Monitors for anchor outputs channels opened in v0.0.116 suffered from a bug in which the
@@ -4634,6 +4810,8 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			best_block,
 			counterparty_node_id,
 			initial_counterparty_commitment_info,
+			channel_type_upgrades: channel_type_upgrades.unwrap(),
+			balances_empty_height,
 		})))
 	}
 }
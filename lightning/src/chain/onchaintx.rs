@@ -631,7 +631,10 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 					let conf_target = ConfirmationTarget::OnChainSweep;
 					let package_target_feerate_sat_per_1000_weight = cached_request
 						.compute_package_feerate(fee_estimator, conf_target, force_feerate_bump);
-					if let Some(input_amount_sat) = output.funding_amount {
+					// Zero-fee commitments can't be relayed without a child spending their ephemeral
+					// anchor, no matter their feerate.
+					let zero_fee_commitment = self.channel_type_features().supports_zero_fee_commitments();
+					if let Some(input_amount_sat) = output.funding_amount.filter(|_| !zero_fee_commitment) {
 						let fee_sat = input_amount_sat - tx.output.iter().map(|output| output.value).sum::<u64>();
 						let commitment_tx_feerate_sat_per_1000_weight =
 							compute_feerate_sat_per_1000_weight(fee_sat, tx.weight().to_wu());
@@ -645,7 +648,12 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 
 					// We'll locate an anchor output we can spend within the commitment transaction.
 					let funding_pubkey = &self.channel_transaction_parameters.holder_pubkeys.funding_pubkey;
-					match chan_utils::get_anchor_output(&tx, funding_pubkey) {
+					let anchor_output = if zero_fee_commitment {
+						chan_utils::get_ephemeral_anchor_output(&tx)
+					} else {
+						chan_utils::get_anchor_output(&tx, funding_pubkey)
+					};
+					match anchor_output {
 						// An anchor output was found, so we should yield a funding event externally.
 						Some((idx, _)) => {
 							// TODO: Use a lower confirmation target when both our and the
@@ -1135,11 +1143,14 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 				&self.channel_transaction_parameters.as_holder_broadcastable(), htlc_idx, preimage,
 			);
 
+			// The previous holder commitment may still use the channel type from before an upgrade.
+			let mut transaction_parameters = self.channel_transaction_parameters.clone();
+			transaction_parameters.channel_type_features = trusted_tx.channel_type_features().clone();
 			let htlc_descriptor = HTLCDescriptor {
 				channel_derivation_parameters: ChannelDerivationParameters {
 					value_satoshis: self.channel_value_satoshis,
 					keys_id: self.channel_keys_id,
-					transaction_parameters: self.channel_transaction_parameters.clone(),
+					transaction_parameters,
 				},
 				commitment_txid: trusted_tx.txid(),
 				per_commitment_number: trusted_tx.commitment_number(),
@@ -1194,4 +1205,11 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 	pub(crate) fn channel_type_features(&self) -> &ChannelTypeFeatures {
 		&self.channel_transaction_parameters.channel_type_features
 	}
+
+	/// Switches the channel type used to sign and claim our holder commitment transactions after
+	/// the channel's type was upgraded.
+	pub(crate) fn update_channel_type_features(&mut self, channel_type_features: ChannelTypeFeatures) {
+		self.signer.provide_channel_type_features(&channel_type_features);
+		self.channel_transaction_parameters.channel_type_features = channel_type_features;
+	}
 }
@@ -20,8 +20,9 @@ use crate::io_extras::sink;
 use crate::ln::channel::ANCHOR_OUTPUT_VALUE_SATOSHI;
 use crate::ln::chan_utils;
 use crate::ln::chan_utils::{
-	ANCHOR_INPUT_WITNESS_WEIGHT, HTLC_SUCCESS_INPUT_ANCHOR_WITNESS_WEIGHT,
-	HTLC_TIMEOUT_INPUT_ANCHOR_WITNESS_WEIGHT, HTLCOutputInCommitment
+	ANCHOR_INPUT_WITNESS_WEIGHT, EPHEMERAL_ANCHOR_INPUT_WITNESS_WEIGHT,
+	HTLC_SUCCESS_INPUT_ANCHOR_WITNESS_WEIGHT, HTLC_TIMEOUT_INPUT_ANCHOR_WITNESS_WEIGHT,
+	HTLCOutputInCommitment
 };
 use crate::prelude::*;
 use crate::sign::{
@@ -46,6 +47,10 @@ const BASE_INPUT_SIZE: u64 = 32 /* txid */ + 4 /* vout */ + 4 /* sequence */;
 
 const BASE_INPUT_WEIGHT: u64 = BASE_INPUT_SIZE * WITNESS_SCALE_FACTOR as u64;
 
+/// The maximum weight of a child of an unconfirmed TRUC (BIP 431) transaction, such as a zero-fee
+/// commitment transaction.
+const TRUC_CHILD_MAX_WEIGHT: u64 = 1000 * WITNESS_SCALE_FACTOR as u64;
+
 /// A descriptor used to sign for a commitment transaction's anchor output.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct AnchorDescriptor {
@@ -59,13 +64,30 @@ pub struct AnchorDescriptor {
 impl AnchorDescriptor {
 	/// Returns the UTXO to be spent by the anchor input, which can be obtained via
 	/// [`Self::unsigned_tx_input`].
+	///
+	/// For channels with zero-fee commitments, the ephemeral anchor's value depends on the
+	/// commitment transaction and isn't known here, so it must be read from the commitment
+	/// transaction's output instead. A value of zero is returned in that case.
 	pub fn previous_utxo(&self) -> TxOut {
+		if self.is_ephemeral() {
+			return TxOut {
+				script_pubkey: chan_utils::get_ephemeral_anchor_script_pubkey(),
+				value: 0,
+			};
+		}
 		TxOut {
 			script_pubkey: self.witness_script().to_v0_p2wsh(),
 			value: ANCHOR_OUTPUT_VALUE_SATOSHI,
 		}
 	}
 
+	/// Returns whether the anchor is the ephemeral anchor of a zero-fee commitment transaction,
+	/// which anyone may spend with an empty witness and therefore doesn't need to be signed for.
+	pub fn is_ephemeral(&self) -> bool {
+		self.channel_derivation_parameters.transaction_parameters.channel_type_features
+			.supports_zero_fee_commitments()
+	}
+
 	/// Returns the unsigned transaction input spending the anchor output in the commitment
 	/// transaction.
 	pub fn unsigned_tx_input(&self) -> TxIn {
@@ -117,7 +139,9 @@ pub enum BumpTransactionEvent {
 	/// and child anchor transactions), possibly resulting in a loss of funds. Once the transaction
 	/// is constructed, it must be fully signed for and broadcast by the consumer of the event
 	/// along with the `commitment_tx` enclosed. Note that the `commitment_tx` must always be
-	/// broadcast first, as the child anchor transaction depends on it.
+	/// broadcast first, as the child anchor transaction depends on it. Ideally, both are submitted
+	/// as a single package, as [`BumpTransactionEventHandler`] does via
+	/// [`BroadcasterInterface::broadcast_package`].
 	///
 	/// The consumer should be able to sign for any of the additional inputs included within the
 	/// child anchor transaction. To sign its anchor input, an [`EcdsaChannelSigner`] should be
@@ -126,6 +150,12 @@ pub enum BumpTransactionEvent {
 	/// provided to [`build_anchor_input_witness`] along with the `funding_pubkey` to obtain the
 	/// full witness required to spend.
 	///
+	/// For channels with zero-fee commitments, the anchor is an ephemeral anchor (see
+	/// [`AnchorDescriptor::is_ephemeral`]) which is spent with an empty witness rather than
+	/// signed for. As the commitment transaction is a zero-fee version 3 (TRUC) transaction, the
+	/// child anchor transaction must also be version 3 with a weight of at most 4000 WU, and the
+	/// two can only be relayed together as a package.
+	///
 	/// It is possible to receive more than one instance of this event if a valid child anchor
 	/// transaction is never broadcast or is but not with a sufficient fee to be mined. Care should
 	/// be taken by the consumer of the event to ensure any future iterations of the child anchor
@@ -587,15 +617,24 @@ where
 		&self, claim_id: ClaimId, package_target_feerate_sat_per_1000_weight: u32,
 		commitment_tx: &Transaction, commitment_tx_fee_sat: u64, anchor_descriptor: &AnchorDescriptor,
 	) -> Result<(), ()> {
+		let is_ephemeral_anchor = anchor_descriptor.is_ephemeral();
+		let (previous_anchor_utxo, anchor_input_witness_weight) = if is_ephemeral_anchor {
+			let anchor_output = commitment_tx.output.get(anchor_descriptor.outpoint.vout as usize)
+				.ok_or(())?;
+			(anchor_output.clone(), EPHEMERAL_ANCHOR_INPUT_WITNESS_WEIGHT)
+		} else {
+			(anchor_descriptor.previous_utxo(), ANCHOR_INPUT_WITNESS_WEIGHT)
+		};
+
 		// Our commitment transaction already has fees allocated to it, so we should take them into
 		// account. We do so by pretending the commitment tranasction's fee and weight are part of
 		// the anchor input.
-		let mut anchor_utxo = anchor_descriptor.previous_utxo();
+		let mut anchor_utxo = previous_anchor_utxo.clone();
 		anchor_utxo.value += commitment_tx_fee_sat;
 		let must_spend = vec![Input {
 			outpoint: anchor_descriptor.outpoint,
 			previous_utxo: anchor_utxo,
-			satisfaction_weight: commitment_tx.weight().to_wu() + ANCHOR_INPUT_WITNESS_WEIGHT + EMPTY_SCRIPT_SIG_WEIGHT,
+			satisfaction_weight: commitment_tx.weight().to_wu() + anchor_input_witness_weight + EMPTY_SCRIPT_SIG_WEIGHT,
 		}];
 		#[cfg(debug_assertions)]
 		let must_spend_amount =	must_spend.iter().map(|input| input.previous_utxo.value).sum::<u64>();
@@ -607,14 +646,15 @@ where
 		)?;
 
 		let mut anchor_tx = Transaction {
-			version: 2,
+			// Children of TRUC transactions must be TRUC transactions themselves.
+			version: if is_ephemeral_anchor { 3 } else { 2 },
 			lock_time: LockTime::ZERO, // TODO: Use next best height.
 			input: vec![anchor_descriptor.unsigned_tx_input()],
 			output: vec![],
 		};
 
 		#[cfg(debug_assertions)]
-		let total_satisfaction_weight = ANCHOR_INPUT_WITNESS_WEIGHT + EMPTY_SCRIPT_SIG_WEIGHT +
+		let total_satisfaction_weight = anchor_input_witness_weight + EMPTY_SCRIPT_SIG_WEIGHT +
 			coin_selection.confirmed_utxos.iter().map(|utxo| utxo.satisfaction_weight).sum::<u64>();
 		#[cfg(debug_assertions)]
 		let total_input_amount = must_spend_amount +
@@ -626,7 +666,7 @@ where
 		// construct psbt
 		let mut anchor_psbt = PartiallySignedTransaction::from_unsigned_tx(anchor_tx).unwrap();
 		// add witness_utxo to anchor input
-		anchor_psbt.inputs[0].witness_utxo = Some(anchor_descriptor.previous_utxo());
+		anchor_psbt.inputs[0].witness_utxo = Some(previous_anchor_utxo);
 		// add witness_utxo to remaining inputs
 		for (idx, utxo) in coin_selection.confirmed_utxos.into_iter().enumerate() {
 			// add 1 to skip the anchor input
@@ -644,9 +684,18 @@ where
 		log_debug!(self.logger, "Signing anchor transaction {}", anchor_txid);
 		anchor_tx = self.utxo_source.sign_psbt(anchor_psbt)?;
 
-		let signer = anchor_descriptor.derive_channel_signer(&self.signer_provider);
-		let anchor_sig = signer.sign_holder_anchor_input(&anchor_tx, 0, &self.secp)?;
-		anchor_tx.input[0].witness = anchor_descriptor.tx_input_witness(&anchor_sig);
+		if is_ephemeral_anchor {
+			anchor_tx.input[0].witness = Witness::new();
+			if anchor_tx.weight().to_wu() > TRUC_CHILD_MAX_WEIGHT {
+				log_error!(self.logger, "Anchor transaction {} spending ephemeral anchor exceeds the maximum weight of {} WU",
+					anchor_txid, TRUC_CHILD_MAX_WEIGHT);
+				return Err(());
+			}
+		} else {
+			let signer = anchor_descriptor.derive_channel_signer(&self.signer_provider);
+			let anchor_sig = signer.sign_holder_anchor_input(&anchor_tx, 0, &self.secp)?;
+			anchor_tx.input[0].witness = anchor_descriptor.tx_input_witness(&anchor_sig);
+		}
 
 		#[cfg(debug_assertions)] {
 			let signed_tx_weight = anchor_tx.weight().to_wu();
@@ -669,7 +718,7 @@ where
 
 		log_info!(self.logger, "Broadcasting anchor transaction {} to bump channel close with txid {}",
 			anchor_txid, commitment_tx.txid());
-		self.broadcaster.broadcast_transactions(&[&commitment_tx, &anchor_tx]);
+		self.broadcaster.broadcast_package(&[&commitment_tx, &anchor_tx]);
 		Ok(())
 	}
 
@@ -23,7 +23,9 @@ use crate::ln::channelmanager::{InterceptId, PaymentId, RecipientOnionFields};
 use crate::ln::channel::FUNDING_CONF_DEADLINE_BLOCKS;
 use crate::ln::features::ChannelTypeFeatures;
 use crate::ln::msgs;
+use crate::ln::our_peer_storage::DecryptedOurPeerStorage;
 use crate::ln::{ChannelId, PaymentPreimage, PaymentHash, PaymentSecret};
+use crate::offers::invoice::Bolt12Invoice;
 use crate::routing::gossip::NetworkUpdate;
 use crate::util::errors::APIError;
 use crate::util::ser::{BigSize, FixedLengthReader, Writeable, Writer, MaybeReadable, Readable, RequiredWrapper, UpgradableRequired, WithoutLength};
@@ -295,6 +297,12 @@ pub enum HTLCDestination {
 		/// The payment hash of the payment we attempted to process.
 		payment_hash: PaymentHash
 	},
+	/// We were acting as a trampoline node but failed to find a route to, or to pay, the next
+	/// trampoline node.
+	TrampolineForward {
+		/// The `node_id` of the next trampoline node.
+		node_id: PublicKey,
+	},
 }
 
 impl_writeable_tlv_based_enum_upgradable!(HTLCDestination,
@@ -311,6 +319,9 @@ impl_writeable_tlv_based_enum_upgradable!(HTLCDestination,
 	(4, FailedPayment) => {
 		(0, payment_hash, required),
 	},
+	(6, TrampolineForward) => {
+		(0, node_id, required),
+	},
 );
 
 /// Will be used in [`Event::HTLCIntercepted`] to identify the next hop in the HTLC's path.
@@ -366,6 +377,33 @@ impl_writeable_tlv_based_enum!(PaymentFailureReason,
 	(10, UnexpectedError) => {}, ;
 );
 
+/// The per-peer forwarding limit which caused us to reject an HTLC. Used in
+/// [`Event::PeerForwardingLimitExceeded`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerForwardingLimit {
+	/// The peer already had [`PeerForwardingLimits::max_inflight_htlcs`] HTLCs pending forward
+	/// across all of its channels.
+	///
+	/// [`PeerForwardingLimits::max_inflight_htlcs`]: crate::util::config::PeerForwardingLimits::max_inflight_htlcs
+	InFlightHTLCs,
+	/// Forwarding the HTLC would have put more than
+	/// [`PeerForwardingLimits::max_inflight_value_msat`] in flight on behalf of the peer.
+	///
+	/// [`PeerForwardingLimits::max_inflight_value_msat`]: crate::util::config::PeerForwardingLimits::max_inflight_value_msat
+	InFlightValue,
+	/// The peer already had [`PeerForwardingLimits::max_htlcs_per_second`] HTLCs forwarded in the
+	/// current second.
+	///
+	/// [`PeerForwardingLimits::max_htlcs_per_second`]: crate::util::config::PeerForwardingLimits::max_htlcs_per_second
+	HTLCRate,
+}
+
+impl_writeable_tlv_based_enum!(PeerForwardingLimit,
+	(0, InFlightHTLCs) => {},
+	(2, InFlightValue) => {},
+	(4, HTLCRate) => {}, ;
+);
+
 /// An Event which you should probably take some action in response to.
 ///
 /// Note that while Writeable and Readable are implemented for Event, you probably shouldn't use
@@ -589,6 +627,19 @@ pub enum Event {
 		///
 		/// [`Route::get_total_fees`]: crate::routing::router::Route::get_total_fees
 		fee_paid_msat: Option<u64>,
+		/// The BOLT 12 invoice that was paid, if the payment was for an offer paid using
+		/// [`ChannelManager::pay_for_offer`] or for a refund created using
+		/// [`ChannelManager::create_refund_builder`].
+		///
+		/// Along with [`payment_preimage`], this may be used to create a [`PayerProof`] by calling
+		/// [`ChannelManager::create_payer_proof`].
+		///
+		/// [`ChannelManager::pay_for_offer`]: crate::ln::channelmanager::ChannelManager::pay_for_offer
+		/// [`ChannelManager::create_refund_builder`]: crate::ln::channelmanager::ChannelManager::create_refund_builder
+		/// [`payment_preimage`]: Self::PaymentSent::payment_preimage
+		/// [`PayerProof`]: crate::offers::payer_proof::PayerProof
+		/// [`ChannelManager::create_payer_proof`]: crate::ln::channelmanager::ChannelManager::create_payer_proof
+		bolt12_invoice: Option<Bolt12Invoice>,
 	},
 	/// Indicates an outbound payment failed. Individual [`Event::PaymentPathFailed`] events
 	/// provide failure information for each path attempt in the payment, including retries.
@@ -803,6 +854,13 @@ pub enum Event {
 		///
 		/// The caveat described above the `fee_earned_msat` field applies here as well.
 		outbound_amount_forwarded_msat: Option<u64>,
+		/// If we forwarded this payment as a trampoline node, the fees, in milli-satoshis, we paid
+		/// along the route we found to the next trampoline node. These have already been deducted
+		/// from `fee_earned_msat`, which is what remains of the sender's trampoline fee.
+		///
+		/// This is `None` for regular forwards and for events serialized by versions which did not
+		/// support trampoline forwarding.
+		trampoline_route_fee_msat: Option<u64>,
 	},
 	/// Used to indicate that a channel with the given `channel_id` is being opened and pending
 	/// confirmation on-chain.
@@ -950,6 +1008,69 @@ pub enum Event {
 		/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
 		channel_type: ChannelTypeFeatures,
 	},
+	/// Indicates a request to open a new dual-funded channel by a peer.
+	///
+	/// Unlike [`Event::OpenChannelRequest`], this event is generated for every `open_channel2`
+	/// message we receive (as long as we accept inbound channels at all), as we need to know which
+	/// of our UTXOs, if any, we wish to contribute to the funding transaction.
+	///
+	/// To accept the request, call [`ChannelManager::accept_inbound_dual_funded_channel`] with the
+	/// inputs to contribute, which may be empty. To reject the request, call
+	/// [`ChannelManager::force_close_without_broadcasting_txn`]. Note that a [`Event::ChannelClosed`]
+	/// event will _not_ be triggered if the channel is rejected.
+	///
+	/// [`ChannelManager::accept_inbound_dual_funded_channel`]: crate::ln::channelmanager::ChannelManager::accept_inbound_dual_funded_channel
+	/// [`ChannelManager::force_close_without_broadcasting_txn`]: crate::ln::channelmanager::ChannelManager::force_close_without_broadcasting_txn
+	#[cfg(dual_funding)]
+	OpenDualFundedChannelRequest {
+		/// The temporary channel ID of the channel requested to be opened.
+		///
+		/// When responding to the request, the `temporary_channel_id` should be passed back to the
+		/// `ChannelManager` through [`ChannelManager::accept_inbound_dual_funded_channel`] to
+		/// accept, or through [`ChannelManager::force_close_without_broadcasting_txn`] to reject.
+		///
+		/// [`ChannelManager::accept_inbound_dual_funded_channel`]: crate::ln::channelmanager::ChannelManager::accept_inbound_dual_funded_channel
+		/// [`ChannelManager::force_close_without_broadcasting_txn`]: crate::ln::channelmanager::ChannelManager::force_close_without_broadcasting_txn
+		temporary_channel_id: ChannelId,
+		/// The node_id of the counterparty requesting to open the channel.
+		counterparty_node_id: PublicKey,
+		/// The amount the counterparty is contributing to the channel value, in satoshis.
+		counterparty_funding_satoshis: u64,
+		/// The feerate, set by the counterparty, at which we pay for the inputs and outputs we
+		/// contribute to the funding transaction.
+		funding_feerate_sat_per_1000_weight: u32,
+		/// Whether the counterparty requires that all of the inputs we contribute are confirmed.
+		require_confirmed_inputs: bool,
+		/// The features that this channel will operate with.
+		channel_type: ChannelTypeFeatures,
+	},
+	/// Indicates that the funding transaction of a dual-funded channel has been negotiated with our
+	/// counterparty and our initial `ChannelMonitor` has been created, so it is now safe to sign the
+	/// inputs we contributed to it.
+	///
+	/// The transaction should be signed by the wallet which provided the inputs and passed back
+	/// through [`ChannelManager::funding_transaction_signed`]. Only the witnesses for our own
+	/// inputs are used; all other fields must be left untouched.
+	///
+	/// This event is not generated if we did not contribute any inputs to the funding transaction.
+	///
+	/// [`ChannelManager::funding_transaction_signed`]: crate::ln::channelmanager::ChannelManager::funding_transaction_signed
+	#[cfg(dual_funding)]
+	FundingTransactionReadyForSigning {
+		/// The channel_id of the channel which the funding transaction funds.
+		channel_id: ChannelId,
+		/// The node_id of the counterparty the channel is being opened with.
+		counterparty_node_id: PublicKey,
+		/// The `user_channel_id` value passed in to [`ChannelManager::create_dual_funded_channel`]
+		/// for outbound channels, or to [`ChannelManager::accept_inbound_dual_funded_channel`] for
+		/// inbound channels.
+		///
+		/// [`ChannelManager::create_dual_funded_channel`]: crate::ln::channelmanager::ChannelManager::create_dual_funded_channel
+		/// [`ChannelManager::accept_inbound_dual_funded_channel`]: crate::ln::channelmanager::ChannelManager::accept_inbound_dual_funded_channel
+		user_channel_id: u128,
+		/// The negotiated funding transaction, with no witnesses for any of its inputs.
+		unsigned_transaction: Transaction,
+	},
 	/// Indicates that the HTLC was accepted, but could not be processed when or after attempting to
 	/// forward it.
 	///
@@ -977,6 +1098,92 @@ pub enum Event {
 	///
 	/// [`ChannelHandshakeConfig::negotiate_anchors_zero_fee_htlc_tx`]: crate::util::config::ChannelHandshakeConfig::negotiate_anchors_zero_fee_htlc_tx
 	BumpTransaction(BumpTransactionEvent),
+	/// Indicates that a peer returned a backup of our channels which we previously asked it to
+	/// store via `peer_storage`, and that the backup includes channels the [`ChannelManager`]
+	/// doesn't know about.
+	///
+	/// This generally means we lost our state and were restarted from our seed only, in which case
+	/// the included [`PeerStorageChannel`]s provide the information required to ask our
+	/// counterparties to force-close the channels and to re-derive the keys needed to claim our
+	/// funds. It may, however, also be generated for channels which were closed recently if the
+	/// peer hadn't received our latest backup yet.
+	///
+	/// Backups are only exchanged if [`UserConfig::enable_peer_storage`] is set.
+	///
+	/// This event is not persisted, as peers return the backup every time we connect to them.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`PeerStorageChannel`]: crate::ln::our_peer_storage::PeerStorageChannel
+	/// [`UserConfig::enable_peer_storage`]: crate::util::config::UserConfig::enable_peer_storage
+	PeerStorageRetrieved {
+		/// The node id of the peer which returned the backup.
+		counterparty_node_id: PublicKey,
+		/// The decrypted contents of the backup.
+		peer_storage: DecryptedOurPeerStorage,
+	},
+	/// Indicates that we refused to forward an HTLC because the peer it was received from exceeded
+	/// one of the [`UserConfig::peer_forwarding_limits`].
+	///
+	/// The HTLC is failed back with a `temporary_channel_failure`, for which an
+	/// [`Event::HTLCHandlingFailed`] is generated as well. This event exists so that peers
+	/// repeatedly running into the limits, e.g. because they are attempting to jam our channels,
+	/// can be alerted on.
+	///
+	/// [`UserConfig::peer_forwarding_limits`]: crate::util::config::UserConfig::peer_forwarding_limits
+	PeerForwardingLimitExceeded {
+		/// The node id of the peer the HTLC was received from.
+		counterparty_node_id: PublicKey,
+		/// The channel over which the HTLC was received.
+		prev_channel_id: ChannelId,
+		/// The amount of the incoming HTLC.
+		inbound_amount_msat: u64,
+		/// The limit which the peer exceeded.
+		limit: PeerForwardingLimit,
+	},
+	/// Indicates that a rebalance started via [`ChannelManager::rebalance`] succeeded, i.e. the
+	/// circular payment reached us over the inbound channel and was claimed.
+	///
+	/// [`Event::PaymentSent`], [`Event::PaymentClaimable`] and [`Event::PaymentClaimed`] are not
+	/// generated for rebalances, though [`Event::PaymentPathSuccessful`] and
+	/// [`Event::PaymentPathFailed`] are, so that they can be used for scoring.
+	///
+	/// [`ChannelManager::rebalance`]: crate::ln::channelmanager::ChannelManager::rebalance
+	RebalanceSucceeded {
+		/// The `payment_id` returned by [`ChannelManager::rebalance`].
+		///
+		/// [`ChannelManager::rebalance`]: crate::ln::channelmanager::ChannelManager::rebalance
+		payment_id: PaymentId,
+		/// The channel over which the rebalance left our node.
+		outbound_channel_id: ChannelId,
+		/// The channel over which the rebalance came back to our node.
+		inbound_channel_id: ChannelId,
+		/// The amount which was moved from the outbound to the inbound channel, not including fees.
+		amount_msat: u64,
+		/// The total fee which was paid to the nodes along the route, including the counterparty
+		/// of the inbound channel.
+		fee_paid_msat: Option<u64>,
+	},
+	/// Indicates that a rebalance started via [`ChannelManager::rebalance`] failed, either because
+	/// no route with a low enough fee could be found or because all attempts failed and no retries
+	/// remain.
+	///
+	/// Individual [`Event::PaymentPathFailed`] events provide failure information for each attempt.
+	///
+	/// [`ChannelManager::rebalance`]: crate::ln::channelmanager::ChannelManager::rebalance
+	RebalanceFailed {
+		/// The `payment_id` returned by [`ChannelManager::rebalance`].
+		///
+		/// [`ChannelManager::rebalance`]: crate::ln::channelmanager::ChannelManager::rebalance
+		payment_id: PaymentId,
+		/// The channel over which the rebalance was to leave our node.
+		outbound_channel_id: ChannelId,
+		/// The channel over which the rebalance was to come back to our node.
+		inbound_channel_id: ChannelId,
+		/// The amount which was to be moved from the outbound to the inbound channel.
+		amount_msat: u64,
+		/// The reason the rebalance failed.
+		reason: Option<PaymentFailureReason>,
+	},
 }
 
 impl Writeable for Event {
@@ -1019,13 +1226,17 @@ impl Writeable for Event {
 					(10, skimmed_fee_opt, option),
 				});
 			},
-			&Event::PaymentSent { ref payment_id, ref payment_preimage, ref payment_hash, ref fee_paid_msat } => {
+			&Event::PaymentSent {
+				ref payment_id, ref payment_preimage, ref payment_hash, ref fee_paid_msat,
+				ref bolt12_invoice,
+			} => {
 				2u8.write(writer)?;
 				write_tlv_fields!(writer, {
 					(0, payment_preimage, required),
 					(1, payment_hash, required),
 					(3, payment_id, option),
 					(5, fee_paid_msat, option),
+					(7, bolt12_invoice, option),
 				});
 			},
 			&Event::PaymentPathFailed {
@@ -1079,7 +1290,7 @@ impl Writeable for Event {
 			}
 			&Event::PaymentForwarded {
 				fee_earned_msat, prev_channel_id, claim_from_onchain_tx,
-				next_channel_id, outbound_amount_forwarded_msat
+				next_channel_id, outbound_amount_forwarded_msat, trampoline_route_fee_msat,
 			} => {
 				7u8.write(writer)?;
 				write_tlv_fields!(writer, {
@@ -1088,6 +1299,7 @@ impl Writeable for Event {
 					(2, claim_from_onchain_tx, required),
 					(3, next_channel_id, option),
 					(5, outbound_amount_forwarded_msat, option),
+					(7, trampoline_route_fee_msat, option),
 				});
 			},
 			&Event::ChannelClosed { ref channel_id, ref user_channel_id, ref reason,
@@ -1137,6 +1349,22 @@ impl Writeable for Event {
 				// We never write the OpenChannelRequest events as, upon disconnection, peers
 				// drop any channels which have not yet exchanged funding_signed.
 			},
+			#[cfg(dual_funding)]
+			&Event::OpenDualFundedChannelRequest { .. } => {
+				37u8.write(writer)?;
+				// Like `OpenChannelRequest`s, we never write these as peers drop any channels which
+				// have not yet completed funding upon disconnection.
+			},
+			#[cfg(dual_funding)]
+			&Event::FundingTransactionReadyForSigning { ref channel_id, ref counterparty_node_id, ref user_channel_id, ref unsigned_transaction } => {
+				39u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, user_channel_id, required),
+					(6, unsigned_transaction, required),
+				});
+			},
 			&Event::PaymentClaimed { ref payment_hash, ref amount_msat, ref purpose, ref receiver_node_id, ref htlcs, ref sender_intended_total_msat } => {
 				19u8.write(writer)?;
 				write_tlv_fields!(writer, {
@@ -1213,6 +1441,48 @@ impl Writeable for Event {
 				35u8.write(writer)?;
 				// Never write ConnectionNeeded events as buffered onion messages aren't serialized.
 			},
+			&Event::PeerStorageRetrieved { .. } => {
+				41u8.write(writer)?;
+				// Never write PeerStorageRetrieved events as peers return our backup whenever we
+				// reconnect.
+			},
+			&Event::PeerForwardingLimitExceeded {
+				ref counterparty_node_id, ref prev_channel_id, ref inbound_amount_msat, ref limit
+			} => {
+				43u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, counterparty_node_id, required),
+					(2, prev_channel_id, required),
+					(4, inbound_amount_msat, required),
+					(6, limit, required),
+				});
+			},
+			&Event::RebalanceSucceeded {
+				ref payment_id, ref outbound_channel_id, ref inbound_channel_id, ref amount_msat,
+				ref fee_paid_msat
+			} => {
+				45u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, payment_id, required),
+					(2, outbound_channel_id, required),
+					(4, inbound_channel_id, required),
+					(6, amount_msat, required),
+					(7, fee_paid_msat, option),
+				});
+			},
+			&Event::RebalanceFailed {
+				ref payment_id, ref outbound_channel_id, ref inbound_channel_id, ref amount_msat,
+				ref reason
+			} => {
+				47u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, payment_id, required),
+					(2, outbound_channel_id, required),
+					(4, inbound_channel_id, required),
+					(6, amount_msat, required),
+					(7, reason, upgradable_option),
+				});
+			},
 			// Note that, going forward, all new events must only write data inside of
 			// `write_tlv_fields`. Versions 0.0.101+ will ignore odd-numbered events that write
 			// data via `write_tlv_fields`.
@@ -1279,11 +1549,13 @@ impl MaybeReadable for Event {
 					let mut payment_hash = None;
 					let mut payment_id = None;
 					let mut fee_paid_msat = None;
+					let mut bolt12_invoice = None;
 					read_tlv_fields!(reader, {
 						(0, payment_preimage, required),
 						(1, payment_hash, option),
 						(3, payment_id, option),
 						(5, fee_paid_msat, option),
+						(7, bolt12_invoice, option),
 					});
 					if payment_hash.is_none() {
 						payment_hash = Some(PaymentHash(Sha256::hash(&payment_preimage.0[..]).to_byte_array()));
@@ -1293,6 +1565,7 @@ impl MaybeReadable for Event {
 						payment_preimage,
 						payment_hash: payment_hash.unwrap(),
 						fee_paid_msat,
+						bolt12_invoice,
 					}))
 				};
 				f()
@@ -1383,16 +1656,18 @@ impl MaybeReadable for Event {
 					let mut claim_from_onchain_tx = false;
 					let mut next_channel_id = None;
 					let mut outbound_amount_forwarded_msat = None;
+					let mut trampoline_route_fee_msat = None;
 					read_tlv_fields!(reader, {
 						(0, fee_earned_msat, option),
 						(1, prev_channel_id, option),
 						(2, claim_from_onchain_tx, required),
 						(3, next_channel_id, option),
 						(5, outbound_amount_forwarded_msat, option),
+						(7, trampoline_route_fee_msat, option),
 					});
 					Ok(Some(Event::PaymentForwarded {
 						fee_earned_msat, prev_channel_id, claim_from_onchain_tx, next_channel_id,
-						outbound_amount_forwarded_msat
+						outbound_amount_forwarded_msat, trampoline_route_fee_msat,
 					}))
 				};
 				f()
@@ -1612,6 +1887,83 @@ impl MaybeReadable for Event {
 			},
 			// Note that we do not write a length-prefixed TLV for ConnectionNeeded events.
 			35u8 => Ok(None),
+			// Note that we do not write a length-prefixed TLV for OpenDualFundedChannelRequest events.
+			37u8 => Ok(None),
+			#[cfg(dual_funding)]
+			39u8 => {
+				let f = || {
+					_init_and_read_len_prefixed_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, user_channel_id, required),
+						(6, unsigned_transaction, required),
+					});
+					Ok(Some(Event::FundingTransactionReadyForSigning {
+						channel_id: channel_id.0.unwrap(),
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						user_channel_id: user_channel_id.0.unwrap(),
+						unsigned_transaction: unsigned_transaction.0.unwrap(),
+					}))
+				};
+				f()
+			},
+			// Note that we do not write a length-prefixed TLV for PeerStorageRetrieved events.
+			41u8 => Ok(None),
+			43u8 => {
+				let f = || {
+					_init_and_read_len_prefixed_tlv_fields!(reader, {
+						(0, counterparty_node_id, required),
+						(2, prev_channel_id, required),
+						(4, inbound_amount_msat, required),
+						(6, limit, required),
+					});
+					Ok(Some(Event::PeerForwardingLimitExceeded {
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						prev_channel_id: prev_channel_id.0.unwrap(),
+						inbound_amount_msat: inbound_amount_msat.0.unwrap(),
+						limit: limit.0.unwrap(),
+					}))
+				};
+				f()
+			},
+			45u8 => {
+				let f = || {
+					_init_and_read_len_prefixed_tlv_fields!(reader, {
+						(0, payment_id, required),
+						(2, outbound_channel_id, required),
+						(4, inbound_channel_id, required),
+						(6, amount_msat, required),
+						(7, fee_paid_msat, option),
+					});
+					Ok(Some(Event::RebalanceSucceeded {
+						payment_id: payment_id.0.unwrap(),
+						outbound_channel_id: outbound_channel_id.0.unwrap(),
+						inbound_channel_id: inbound_channel_id.0.unwrap(),
+						amount_msat: amount_msat.0.unwrap(),
+						fee_paid_msat,
+					}))
+				};
+				f()
+			},
+			47u8 => {
+				let f = || {
+					_init_and_read_len_prefixed_tlv_fields!(reader, {
+						(0, payment_id, required),
+						(2, outbound_channel_id, required),
+						(4, inbound_channel_id, required),
+						(6, amount_msat, required),
+						(7, reason, upgradable_option),
+					});
+					Ok(Some(Event::RebalanceFailed {
+						payment_id: payment_id.0.unwrap(),
+						outbound_channel_id: outbound_channel_id.0.unwrap(),
+						inbound_channel_id: inbound_channel_id.0.unwrap(),
+						amount_msat: amount_msat.0.unwrap(),
+						reason,
+					}))
+				};
+				f()
+			},
 			// Versions prior to 0.0.100 did not ignore odd types, instead returning InvalidValue.
 			// Version 0.0.100 failed to properly ignore odd types, possibly resulting in corrupt
 			// reads.
@@ -1690,6 +2042,27 @@ pub enum MessageSendEvent {
 		/// The message which should be sent.
 		msg: msgs::Stfu,
 	},
+	/// Used to indicate that a dyn_propose message should be sent to the peer with the given node id.
+	SendDynPropose {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::DynPropose,
+	},
+	/// Used to indicate that a dyn_ack message should be sent to the peer with the given node id.
+	SendDynAck {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::DynAck,
+	},
+	/// Used to indicate that a dyn_reject message should be sent to the peer with the given node id.
+	SendDynReject {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::DynReject,
+	},
 	/// Used to indicate that a splice message should be sent to the peer with the given node id.
 	SendSplice {
 		/// The node_id of the node which should receive this message
@@ -1824,6 +2197,21 @@ pub enum MessageSendEvent {
 		/// The message which should be sent.
 		msg: msgs::ChannelReestablish,
 	},
+	/// Used to indicate that a peer_storage message should be sent to the peer with the given node_id.
+	SendPeerStorage {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::PeerStorage,
+	},
+	/// Used to indicate that a your_peer_storage message should be sent to the peer with the given
+	/// node_id.
+	SendYourPeerStorage {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::YourPeerStorage,
+	},
 	/// Used to send a channel_announcement and channel_update to a specific peer, likely on
 	/// initial connection to ensure our peers know about our channels.
 	SendChannelAnnouncement {
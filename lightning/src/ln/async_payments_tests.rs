@@ -0,0 +1,483 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests of async payments, where the sender's LSP holds HTLCs until the often-offline recipient
+//! comes online and its LSP releases them via a `release_held_htlc` onion message.
+
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::secp256k1::{KeyPair, Secp256k1, SecretKey};
+use core::convert::{Infallible, TryFrom};
+
+use crate::blinded_path::BlindedPath;
+use crate::chain::channelmonitor::HTLC_FAIL_BACK_BUFFER;
+use crate::events::{Event, HTLCDestination, MessageSendEventsProvider, PaymentPurpose};
+use crate::ln::PaymentHash;
+use crate::ln::channelmanager::{PaymentId, RecipientOnionFields};
+use crate::ln::functional_test_utils::*;
+use crate::ln::msgs::ChannelMessageHandler;
+use crate::ln::outbound_payment::Retry;
+use crate::ln::peer_handler::IgnoringMessageHandler;
+use crate::offers::parse::Bolt12SemanticError;
+use crate::offers::static_invoice::StaticInvoice;
+use crate::onion_message::{AsyncPaymentsMessage, AsyncPaymentsMessageHandler, OffersMessage, OffersMessageHandler, OnionMessagePath, ParsedOnionMessageContents, PeeledOnion, PendingOnionMessage, create_onion_message, peel_onion_message};
+use crate::util::config::UserConfig;
+use crate::util::ser::Writeable;
+use crate::util::test_utils;
+
+use crate::prelude::*;
+
+fn hold_htlcs_config() -> UserConfig {
+	let mut config = test_default_channel_config();
+	config.hold_htlcs_for_async_payments = true;
+	config
+}
+
+/// Encodes `message` in an onion message sent by `from` and decodes it at `to`, returning what
+/// `to`'s `OnionMessenger` would pass to its [`AsyncPaymentsMessageHandler`].
+fn pass_async_payments_message<'a, 'b, 'c>(
+	from: &Node<'a, 'b, 'c>, to: &Node<'a, 'b, 'c>, message: PendingOnionMessage<AsyncPaymentsMessage>
+) -> (AsyncPaymentsMessage, Option<[u8; 32]>, Option<BlindedPath>) {
+	#[cfg(not(c_bindings))]
+	let PendingOnionMessage { contents, destination, reply_path } = message;
+	#[cfg(c_bindings)]
+	let (contents, destination, reply_path) = message;
+	let secp_ctx = Secp256k1::new();
+	let path = OnionMessagePath { intermediate_nodes: Vec::new(), destination, first_node_addresses: None };
+	let (first_node_id, onion_message, _) = create_onion_message(
+		&from.keys_manager, &from.keys_manager, &secp_ctx, path, contents, reply_path
+	).unwrap();
+	assert_eq!(first_node_id, to.node.get_our_node_id());
+	match peel_onion_message(&onion_message, &secp_ctx, to.keys_manager, to.logger, &IgnoringMessageHandler {}) {
+		Ok(PeeledOnion::Receive(ParsedOnionMessageContents::AsyncPayments(message), path_id, reply_path)) =>
+			(message, path_id, reply_path),
+		_ => panic!("Unexpected onion message"),
+	}
+}
+
+/// Passes the single [`AsyncPaymentsMessage::HeldHtlcAvailable`] queued by `sender` to
+/// `recipient_lsp`.
+fn pass_held_htlc_available<'a, 'b, 'c>(sender: &Node<'a, 'b, 'c>, recipient_lsp: &Node<'a, 'b, 'c>) {
+	let mut messages = AsyncPaymentsMessageHandler::release_pending_messages(sender.node);
+	assert_eq!(messages.len(), 1);
+	match pass_async_payments_message(sender, recipient_lsp, messages.pop().unwrap()) {
+		(AsyncPaymentsMessage::HeldHtlcAvailable(message), path_id, reply_path) => {
+			assert!(path_id.is_some());
+			assert!(reply_path.is_some());
+			recipient_lsp.node.held_htlc_available(message, path_id, reply_path);
+		},
+		_ => panic!("Unexpected message"),
+	}
+}
+
+/// Passes the single [`AsyncPaymentsMessage::ReleaseHeldHtlc`] queued by `recipient_lsp` to
+/// `sender_lsp`.
+fn pass_release_held_htlc<'a, 'b, 'c>(recipient_lsp: &Node<'a, 'b, 'c>, sender_lsp: &Node<'a, 'b, 'c>) {
+	let mut messages = AsyncPaymentsMessageHandler::release_pending_messages(recipient_lsp.node);
+	assert_eq!(messages.len(), 1);
+	match pass_async_payments_message(recipient_lsp, sender_lsp, messages.pop().unwrap()) {
+		(AsyncPaymentsMessage::ReleaseHeldHtlc(message), None, None) => {
+			sender_lsp.node.release_held_htlc(message);
+		},
+		_ => panic!("Unexpected message"),
+	}
+}
+
+#[test]
+fn test_async_payment_held_until_recipient_online() {
+	// nodes[0] pays the often-offline nodes[3] via nodes[0]'s LSP nodes[1] and nodes[3]'s LSP
+	// nodes[2]. The HTLC is held at nodes[1] until nodes[3] connects to nodes[2].
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, Some(hold_htlcs_config()), None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+	let node_id_2 = nodes[2].node.get_our_node_id();
+	let node_id_3 = nodes[3].node.get_our_node_id();
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+	create_announced_chan_between_nodes(&nodes, 2, 3);
+
+	let amt_msat = 100_000;
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[3], amt_msat);
+	let release_paths = nodes[2].node.create_async_recipient_release_paths(node_id_3).unwrap();
+
+	nodes[3].node.peer_disconnected(&node_id_2);
+	nodes[2].node.peer_disconnected(&node_id_3);
+
+	nodes[0].node.send_async_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0), release_paths).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let payment_event = SendEvent::from_event(events.remove(0));
+	assert!(payment_event.msgs[0].hold_htlc.is_some());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &payment_event.commitment_msg, false, true);
+
+	// The HTLC is held rather than forwarded.
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	nodes[1].node.process_pending_htlc_forwards();
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	// nodes[3] is offline, so nodes[2] waits for it to connect before releasing the HTLC.
+	pass_held_htlc_available(&nodes[0], &nodes[2]);
+	assert!(AsyncPaymentsMessageHandler::release_pending_messages(nodes[2].node).is_empty());
+
+	let mut reconnect_args = ReconnectArgs::new(&nodes[2], &nodes[3]);
+	reconnect_args.send_channel_ready = (true, true);
+	reconnect_nodes(reconnect_args);
+	pass_release_held_htlc(&nodes[2], &nodes[1]);
+
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	check_added_monitors!(nodes[1], 1);
+	let mut events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let ev = events.remove(0);
+	assert!(SendEvent::from_event(ev.clone()).msgs[0].hold_htlc.is_none());
+	pass_along_path(&nodes[1], &[&nodes[2], &nodes[3]], amt_msat, payment_hash, Some(payment_secret), ev, true, None);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2], &nodes[3]], payment_preimage);
+}
+
+#[test]
+fn test_async_payment_released_before_held() {
+	// If the release arrives before the HTLC was irrevocably committed to, the HTLC is forwarded
+	// as soon as it is.
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, Some(hold_htlcs_config()), None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+	let node_id_3 = nodes[3].node.get_our_node_id();
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+	create_announced_chan_between_nodes(&nodes, 2, 3);
+
+	let amt_msat = 100_000;
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[3], amt_msat);
+	let release_paths = nodes[2].node.create_async_recipient_release_paths(node_id_3).unwrap();
+
+	nodes[0].node.send_async_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0), release_paths).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let payment_event = SendEvent::from_event(events.remove(0));
+
+	// nodes[3] is online, so nodes[2] releases the HTLC immediately.
+	pass_held_htlc_available(&nodes[0], &nodes[2]);
+	pass_release_held_htlc(&nodes[2], &nodes[1]);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	check_added_monitors!(nodes[1], 1);
+	let mut events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	pass_along_path(&nodes[1], &[&nodes[2], &nodes[3]], amt_msat, payment_hash, Some(payment_secret), events.remove(0), true, None);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2], &nodes[3]], payment_preimage);
+}
+
+#[test]
+fn test_async_payment_held_htlc_timeout() {
+	// A held HTLC which is never released (here, across a restart) is failed back before it
+	// expires.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(hold_htlcs_config()), None]);
+	let nodes_1_deserialized;
+	let mut nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let node_id_2 = nodes[2].node.get_our_node_id();
+
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let (_, _, chan_id_2, _) = create_announced_chan_between_nodes(&nodes, 1, 2);
+	let scid_2 = nodes[1].node.list_channels().iter()
+		.find(|chan| chan.channel_id == chan_id_2).unwrap().short_channel_id.unwrap();
+
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 100_000);
+	let release_paths = nodes[1].node.create_async_recipient_release_paths(node_id_2).unwrap();
+	nodes[0].node.send_async_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0), release_paths).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	assert_eq!(AsyncPaymentsMessageHandler::release_pending_messages(nodes[0].node).len(), 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let payment_event = SendEvent::from_event(events.remove(0));
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &payment_event.commitment_msg, false, true);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	// The held HTLC is persisted across restarts.
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	nodes[2].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	let chan_1_monitor_serialized = get_monitor!(nodes[1], chan_id_1).encode();
+	let chan_2_monitor_serialized = get_monitor!(nodes[1], chan_id_2).encode();
+	reload_node!(nodes[1], hold_htlcs_config(), nodes[1].node.encode(),
+		&[&chan_1_monitor_serialized, &chan_2_monitor_serialized], persister, new_chain_monitor, nodes_1_deserialized);
+	reconnect_nodes(ReconnectArgs::new(&nodes[0], &nodes[1]));
+	let mut reconnect_args = ReconnectArgs::new(&nodes[1], &nodes[2]);
+	reconnect_args.send_channel_ready = (true, true);
+	reconnect_nodes(reconnect_args);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	// Fail the HTLC back once its outgoing CLTV expiry comes within `HTLC_FAIL_BACK_BUFFER` blocks.
+	let outgoing_cltv_value = payment_event.msgs[0].cltv_expiry - route.paths[0].hops[0].cltv_expiry_delta;
+	let cur_height = nodes[1].best_block_info().1;
+	connect_blocks(&nodes[0], outgoing_cltv_value - HTLC_FAIL_BACK_BUFFER - cur_height - 1);
+	connect_blocks(&nodes[1], outgoing_cltv_value - HTLC_FAIL_BACK_BUFFER - cur_height - 1);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	connect_blocks(&nodes[0], 1);
+	connect_blocks(&nodes[1], 1);
+
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[1], vec![HTLCDestination::InvalidForward { requested_forward_scid: scid_2 }]);
+	check_added_monitors!(nodes[1], 1);
+	let htlc_timeout_updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(htlc_timeout_updates.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &htlc_timeout_updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], htlc_timeout_updates.commitment_signed, false);
+	expect_payment_failed!(nodes[0], payment_hash, false, 0x2000 | 2, []);
+}
+
+#[test]
+fn test_static_invoice_served_by_lsp() {
+	// The often-offline nodes[2] creates an offer and a static invoice for it, which its LSP
+	// nodes[1] then serves in response to invoice requests.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let node_id_2 = nodes[2].node.get_our_node_id();
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let message_paths = nodes[1].node.create_async_recipient_release_paths(node_id_2).unwrap();
+	let offer = nodes[2].node
+		.create_async_receive_offer_builder("coffee".to_string(), message_paths.clone()).unwrap()
+		.amount_msats(10_000_000)
+		.build().unwrap();
+	assert_eq!(offer.paths(), &message_paths[..]);
+	assert_ne!(offer.signing_pubkey(), node_id_2);
+
+	let secp_ctx = Secp256k1::new();
+	let invoice = nodes[2].node.create_static_invoice_builder(&offer, message_paths.clone()).unwrap()
+		.build_and_sign(&secp_ctx).unwrap();
+	assert!(invoice.is_for_offer(&offer));
+	assert_eq!(invoice.signing_pubkey(), offer.signing_pubkey());
+	assert_eq!(invoice.message_paths(), &message_paths[..]);
+	assert_eq!(invoice.payment_paths().len(), 1);
+	assert_eq!(invoice.payment_paths()[0].1.introduction_node_id, node_id_2);
+	assert!(nodes[2].node.refresh_static_invoice(&offer, &invoice).unwrap().is_none());
+
+	// The recipient hands the encoded invoice to its LSP.
+	let invoice = StaticInvoice::try_from(invoice.encode()).unwrap();
+	nodes[1].node.serve_static_invoice(invoice.clone());
+
+	let payer_keys = KeyPair::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+	let invoice_request = offer.request_invoice(vec![1; 32], payer_keys.public_key()).unwrap()
+		.chain_hash(offer.chains()[0]).unwrap()
+		.build().unwrap()
+		.sign::<_, Infallible>(
+			|message| Ok(secp_ctx.sign_schnorr_no_aux_rand(message.as_ref().as_digest(), &payer_keys))
+		)
+		.unwrap();
+	match nodes[1].node.handle_message(OffersMessage::InvoiceRequest(invoice_request.clone())) {
+		Some(OffersMessage::StaticInvoice(served_invoice)) => {
+			assert_eq!(served_invoice.encode(), invoice.encode());
+		},
+		_ => panic!("Expected static invoice"),
+	}
+
+	// Once no longer served, the LSP can't respond to the invoice request itself.
+	assert!(nodes[1].node.stop_serving_static_invoice(&offer.signing_pubkey()).is_some());
+	match nodes[1].node.handle_message(OffersMessage::InvoiceRequest(invoice_request)) {
+		Some(OffersMessage::InvoiceError(_)) => {},
+		_ => panic!("Expected invoice error"),
+	}
+	assert!(OffersMessageHandler::release_pending_messages(nodes[1].node).is_empty());
+}
+
+#[test]
+fn test_async_payment_for_static_invoice() {
+	// nodes[0] pays an offer of the often-offline nodes[3], receiving a static invoice from
+	// nodes[3]'s LSP nodes[2] in response. The resulting keysend HTLC is held at nodes[0]'s LSP
+	// nodes[1] until nodes[3] comes online.
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, Some(hold_htlcs_config()), None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+	let node_id_2 = nodes[2].node.get_our_node_id();
+	let node_id_3 = nodes[3].node.get_our_node_id();
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+	create_announced_chan_between_nodes(&nodes, 2, 3);
+
+	let amt_msat = 10_000_000;
+	let message_paths = nodes[2].node.create_async_recipient_release_paths(node_id_3).unwrap();
+	let offer = nodes[3].node
+		.create_async_receive_offer_builder("coffee".to_string(), message_paths.clone()).unwrap()
+		.amount_msats(amt_msat)
+		.build().unwrap();
+	let secp_ctx = Secp256k1::new();
+	let invoice = nodes[3].node.create_static_invoice_builder(&offer, message_paths).unwrap()
+		.build_and_sign(&secp_ctx).unwrap();
+	nodes[2].node.serve_static_invoice(invoice);
+
+	nodes[3].node.peer_disconnected(&node_id_2);
+	nodes[2].node.peer_disconnected(&node_id_3);
+
+	let payment_id = PaymentId([1; 32]);
+	nodes[0].node.pay_for_offer(&offer, None, None, None, payment_id, Retry::Attempts(0), None).unwrap();
+	let mut messages = OffersMessageHandler::release_pending_messages(nodes[0].node);
+	assert_eq!(messages.len(), 1);
+	#[cfg(not(c_bindings))]
+	let PendingOnionMessage { contents, .. } = messages.pop().unwrap();
+	#[cfg(c_bindings)]
+	let (contents, _, _) = messages.pop().unwrap();
+	let invoice = match nodes[2].node.handle_message(contents) {
+		Some(OffersMessage::StaticInvoice(invoice)) => invoice,
+		_ => panic!("Expected static invoice"),
+	};
+
+	assert!(nodes[0].node.handle_message(OffersMessage::StaticInvoice(invoice.clone())).is_none());
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let payment_event = SendEvent::from_event(events.remove(0));
+	assert!(payment_event.msgs[0].hold_htlc.is_some());
+	let payment_hash = payment_event.msgs[0].payment_hash;
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &payment_event.commitment_msg, false, true);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	// Only one invoice is paid per payment.
+	assert!(matches!(
+		nodes[0].node.handle_message(OffersMessage::StaticInvoice(invoice)),
+		Some(OffersMessage::InvoiceError(_))
+	));
+
+	pass_held_htlc_available(&nodes[0], &nodes[2]);
+	assert!(AsyncPaymentsMessageHandler::release_pending_messages(nodes[2].node).is_empty());
+
+	let mut reconnect_args = ReconnectArgs::new(&nodes[2], &nodes[3]);
+	reconnect_args.send_channel_ready = (true, true);
+	reconnect_nodes(reconnect_args);
+	pass_release_held_htlc(&nodes[2], &nodes[1]);
+
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	check_added_monitors!(nodes[1], 1);
+	let mut events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	do_pass_along_path(&nodes[1], &[&nodes[2], &nodes[3]], amt_msat, payment_hash, None, events.remove(0), true, false, None, false);
+
+	let events = nodes[3].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	let payment_preimage = match &events[0] {
+		Event::PaymentClaimable {
+			payment_hash: claimable_hash, amount_msat,
+			purpose: PaymentPurpose::SpontaneousPayment(payment_preimage), ..
+		} => {
+			assert_eq!(*claimable_hash, payment_hash);
+			assert_eq!(*amount_msat, amt_msat);
+			*payment_preimage
+		},
+		_ => panic!("Unexpected event"),
+	};
+	assert_eq!(PaymentHash(Sha256::hash(&payment_preimage.0).to_byte_array()), payment_hash);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2], &nodes[3]], payment_preimage);
+}
+
+#[test]
+fn test_served_static_invoice_persisted() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes_0_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	let chan_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+	let message_paths = nodes[0].node.create_async_recipient_release_paths(node_id_1).unwrap();
+	let offer = nodes[1].node
+		.create_async_receive_offer_builder("coffee".to_string(), message_paths.clone()).unwrap()
+		.amount_msats(10_000_000)
+		.build().unwrap();
+	let secp_ctx = Secp256k1::new();
+	let invoice = nodes[1].node.create_static_invoice_builder(&offer, message_paths).unwrap()
+		.build_and_sign(&secp_ctx).unwrap();
+	nodes[0].node.serve_static_invoice(invoice.clone());
+
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	let chan_monitor_serialized = get_monitor!(nodes[0], chan_id).encode();
+	reload_node!(nodes[0], nodes[0].node.encode(), &[&chan_monitor_serialized], persister,
+		new_chain_monitor, nodes_0_deserialized);
+
+	let payer_keys = KeyPair::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+	let invoice_request = offer.request_invoice(vec![1; 32], payer_keys.public_key()).unwrap()
+		.chain_hash(offer.chains()[0]).unwrap()
+		.build().unwrap()
+		.sign::<_, Infallible>(
+			|message| Ok(secp_ctx.sign_schnorr_no_aux_rand(message.as_ref().as_digest(), &payer_keys))
+		)
+		.unwrap();
+	match nodes[0].node.handle_message(OffersMessage::InvoiceRequest(invoice_request)) {
+		Some(OffersMessage::StaticInvoice(served_invoice)) => {
+			assert_eq!(served_invoice.encode(), invoice.encode());
+		},
+		_ => panic!("Expected static invoice"),
+	}
+}
+
+#[test]
+fn test_refresh_static_invoice() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let message_paths = nodes[0].node.create_async_recipient_release_paths(node_id_1).unwrap();
+	let offer = nodes[1].node
+		.create_async_receive_offer_builder("coffee".to_string(), message_paths.clone()).unwrap()
+		.build().unwrap();
+	let other_offer = nodes[1].node
+		.create_async_receive_offer_builder("tea".to_string(), message_paths.clone()).unwrap()
+		.build().unwrap();
+
+	// An invoice past half of its lifetime is replaced by one with the default expiry.
+	let secp_ctx = Secp256k1::new();
+	let invoice = nodes[1].node.create_static_invoice_builder(&offer, message_paths.clone()).unwrap()
+		.relative_expiry(0)
+		.build_and_sign(&secp_ctx).unwrap();
+	let refreshed_invoice = nodes[1].node.refresh_static_invoice(&offer, &invoice).unwrap().unwrap();
+	assert!(refreshed_invoice.is_for_offer(&offer));
+	assert_eq!(refreshed_invoice.message_paths(), &message_paths[..]);
+	assert_eq!(refreshed_invoice.relative_expiry().as_secs(), 60 * 60 * 24 * 7);
+	assert!(nodes[1].node.refresh_static_invoice(&offer, &refreshed_invoice).unwrap().is_none());
+
+	assert_eq!(
+		nodes[1].node.refresh_static_invoice(&other_offer, &invoice),
+		Err(Bolt12SemanticError::InvalidSigningPubkey)
+	);
+
+	// Only offers created by the node itself are supported.
+	match nodes[0].node.create_static_invoice_builder(&offer, message_paths) {
+		Err(e) => assert_eq!(e, Bolt12SemanticError::InvalidMetadata),
+		Ok(_) => panic!("Expected error"),
+	}
+}
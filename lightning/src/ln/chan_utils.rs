@@ -35,6 +35,10 @@ use bitcoin::secp256k1::{SecretKey, PublicKey, Scalar};
 use bitcoin::secp256k1::{Secp256k1, ecdsa::Signature, Message};
 use bitcoin::{secp256k1, Sequence, Witness};
 use bitcoin::PublicKey as BitcoinPublicKey;
+#[cfg(taproot)]
+use bitcoin::secp256k1::XOnlyPublicKey;
+#[cfg(taproot)]
+use bitcoin::taproot::{LeafVersion, Signature as TaprootSignature, TaprootBuilder, TaprootSpendInfo};
 
 use crate::io;
 use crate::prelude::*;
@@ -50,6 +54,11 @@ use super::channel_keys::{DelayedPaymentBasepoint, DelayedPaymentKey, HtlcKey, H
 
 /// Maximum number of one-way in-flight HTLC (protocol-level value).
 pub const MAX_HTLCS: u16 = 483;
+/// Maximum number of one-way in-flight HTLC on a channel using zero-fee commitments.
+///
+/// Zero-fee commitment transactions are version 3 (TRUC) transactions, which are limited to
+/// 10,000 vbytes, which only leaves room for this many HTLC outputs in each direction.
+pub const MAX_ZERO_FEE_COMMITMENT_HTLCS: u16 = 114;
 /// The weight of a BIP141 witnessScript for a BOLT3's "offered HTLC output" on a commitment transaction, non-anchor variant.
 pub const OFFERED_HTLC_SCRIPT_WEIGHT: usize = 133;
 /// The weight of a BIP141 witnessScript for a BOLT3's "offered HTLC output" on a commitment transaction, anchor variant.
@@ -65,6 +74,11 @@ pub const MAX_ACCEPTED_HTLC_SCRIPT_WEIGHT: usize = 143;
 
 /// The upper bound weight of an anchor input.
 pub const ANCHOR_INPUT_WITNESS_WEIGHT: u64 = 116;
+/// The weight of an ephemeral anchor input's witness, which is always empty.
+pub const EPHEMERAL_ANCHOR_INPUT_WITNESS_WEIGHT: u64 = 1;
+/// The maximum value of the ephemeral anchor output on a zero-fee commitment transaction. Any
+/// trimmed value above it goes to fees instead.
+pub const EPHEMERAL_ANCHOR_MAX_VALUE_SATOSHI: u64 = 240;
 /// The upper bound weight of an HTLC timeout input from a commitment transaction with anchor
 /// outputs.
 pub const HTLC_TIMEOUT_INPUT_ANCHOR_WITNESS_WEIGHT: u64 = 288;
@@ -700,6 +714,17 @@ pub(crate) fn build_htlc_output(
 		htlc.amount_msat / 1000 - total_fee
 	};
 
+	#[cfg(taproot)]
+	if channel_type_features.supports_taproot() {
+		let spend_info = get_taproot_second_stage_htlc_spend_info(
+			revocation_key, contest_delay, broadcaster_delayed_payment_key, &Secp256k1::verification_only(),
+		);
+		return TxOut {
+			script_pubkey: ScriptBuf::new_v1_p2tr_tweaked(spend_info.output_key()),
+			value: output_value,
+		};
+	}
+
 	TxOut {
 		script_pubkey: get_revokeable_redeemscript(revocation_key, contest_delay, broadcaster_delayed_payment_key).to_v0_p2wsh(),
 		value: output_value,
@@ -800,6 +825,44 @@ pub(crate) fn get_anchor_output<'a>(commitment_tx: &'a Transaction, funding_pubk
 		.map(|(idx, txout)| (idx as u32, txout))
 }
 
+/// Gets the scriptPubkey of the ephemeral anchor output of a zero-fee commitment transaction.
+///
+/// This is a pay-to-anchor output (`OP_1 <0x4e73>`), which anyone may spend with an empty witness.
+/// Its value is the sum of trimmed outputs, capped at [`EPHEMERAL_ANCHOR_MAX_VALUE_SATOSHI`], and it
+/// must be spent by a child transaction in the same package as the commitment transaction.
+#[inline]
+pub fn get_ephemeral_anchor_script_pubkey() -> ScriptBuf {
+	Builder::new().push_opcode(opcodes::all::OP_PUSHNUM_1)
+		.push_slice([0x4e, 0x73])
+		.into_script()
+}
+
+/// Locates the ephemeral anchor output within a zero-fee `commitment_tx`.
+pub(crate) fn get_ephemeral_anchor_output<'a>(commitment_tx: &'a Transaction) -> Option<(u32, &'a TxOut)> {
+	let anchor_script = get_ephemeral_anchor_script_pubkey();
+	commitment_tx.output.iter().enumerate()
+		.find(|(_, txout)| txout.script_pubkey == anchor_script)
+		.map(|(idx, txout)| (idx as u32, txout))
+}
+
+/// Computes the value of the ephemeral anchor output of a zero-fee commitment transaction given
+/// the values of all of its other outputs.
+///
+/// The ephemeral anchor collects whatever value of the channel isn't assigned to an output, i.e.
+/// trimmed HTLCs and balances and any rounded-off millisatoshis, up to
+/// [`EPHEMERAL_ANCHOR_MAX_VALUE_SATOSHI`].
+pub fn ephemeral_anchor_value_sat<'a, I: Iterator<Item = &'a HTLCOutputInCommitment>>(
+	channel_value_satoshis: u64, to_broadcaster_value_sat: u64, to_countersignatory_value_sat: u64,
+	nondust_htlcs: I,
+) -> u64 {
+	let nondust_htlcs_value_sat = nondust_htlcs.map(|htlc| htlc.amount_msat / 1000).sum::<u64>();
+	let trimmed_value_sat = channel_value_satoshis
+		.saturating_sub(to_broadcaster_value_sat)
+		.saturating_sub(to_countersignatory_value_sat)
+		.saturating_sub(nondust_htlcs_value_sat);
+	cmp::min(trimmed_value_sat, EPHEMERAL_ANCHOR_MAX_VALUE_SATOSHI)
+}
+
 /// Returns the witness required to satisfy and spend an anchor input.
 pub fn build_anchor_input_witness(funding_key: &PublicKey, funding_sig: &Signature) -> Witness {
 	let anchor_redeem_script = chan_utils::get_anchor_redeemscript(funding_key);
@@ -809,6 +872,204 @@ pub fn build_anchor_input_witness(funding_key: &PublicKey, funding_sig: &Signatu
 	ret
 }
 
+/// The BIP 341 "nothing up my sleeve" point, used as the internal key of taproot outputs which
+/// must only be spendable via their script paths.
+#[cfg(taproot)]
+pub(crate) const TAPROOT_NUMS_POINT: [u8; 33] = [
+	0x02, 0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a,
+	0x5e, 0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a,
+	0xc0,
+];
+
+#[cfg(taproot)]
+fn taproot_nums_point() -> XOnlyPublicKey {
+	PublicKey::from_slice(&TAPROOT_NUMS_POINT).unwrap().x_only_public_key().0
+}
+
+/// Gets the MuSig2 aggregate of the two funding public keys of a taproot channel, which are
+/// sorted before being aggregated. Note that the order of funding public keys does not matter.
+#[cfg(taproot)]
+pub fn get_taproot_funding_aggregate_key(broadcaster: &PublicKey, countersignatory: &PublicKey) -> XOnlyPublicKey {
+	let mut keys = vec![*broadcaster, *countersignatory];
+	keys.sort_unstable_by_key(|key| key.serialize());
+	musig2::get_xonly_key(keys)
+}
+
+/// Gets the P2TR script_pubkey for the funding output of a taproot channel. The output key is the
+/// BIP 86 tweak of the MuSig2 aggregate funding key, i.e. it can only be spent via the key path.
+/// Note that the order of funding public keys does not matter.
+#[cfg(taproot)]
+pub fn get_taproot_funding_script<C: secp256k1::Verification>(broadcaster: &PublicKey, countersignatory: &PublicKey, secp_ctx: &Secp256k1<C>) -> ScriptBuf {
+	let aggregate_key = get_taproot_funding_aggregate_key(broadcaster, countersignatory);
+	ScriptBuf::new_v1_p2tr(secp_ctx, aggregate_key, None)
+}
+
+#[cfg(taproot)]
+fn taproot_spend_info<C: secp256k1::Verification>(internal_key: XOnlyPublicKey, leaves: &[ScriptBuf], secp_ctx: &Secp256k1<C>) -> TaprootSpendInfo {
+	let depth = if leaves.len() > 1 { 1 } else { 0 };
+	let mut builder = TaprootBuilder::new();
+	for leaf in leaves {
+		builder = builder.add_leaf(depth, leaf.clone()).expect("Trees of at most two leaves are always valid");
+	}
+	builder.finalize(secp_ctx, internal_key).expect("Trees of at most two leaves are always complete")
+}
+
+/// Gets the tapscript leaf through which the broadcaster can spend their balance (or a
+/// second-stage HTLC output) after `contest_delay` blocks.
+#[cfg(taproot)]
+pub fn get_taproot_to_delayed_leaf(contest_delay: u16, broadcaster_delayed_payment_key: &DelayedPaymentKey) -> ScriptBuf {
+	Builder::new().push_x_only_key(&broadcaster_delayed_payment_key.to_public_key().x_only_public_key().0)
+		.push_opcode(opcodes::all::OP_CHECKSIG)
+		.push_int(contest_delay as i64)
+		.push_opcode(opcodes::all::OP_CSV)
+		.push_opcode(opcodes::all::OP_DROP)
+		.into_script()
+}
+
+/// Gets the tapscript leaf through which the countersignatory can claim the broadcaster's
+/// balance on a revoked commitment transaction.
+#[cfg(taproot)]
+pub fn get_taproot_to_local_revoke_leaf(revocation_key: &RevocationKey, broadcaster_delayed_payment_key: &DelayedPaymentKey) -> ScriptBuf {
+	Builder::new().push_x_only_key(&broadcaster_delayed_payment_key.to_public_key().x_only_public_key().0)
+		.push_opcode(opcodes::all::OP_DROP)
+		.push_x_only_key(&revocation_key.to_public_key().x_only_public_key().0)
+		.push_opcode(opcodes::all::OP_CHECKSIG)
+		.into_script()
+}
+
+/// Gets the taproot spend info for the `to_local` output of a taproot commitment transaction.
+/// The internal key is unspendable, so the output may only be spent via the delayed or
+/// revocation leaves.
+#[cfg(taproot)]
+pub fn get_taproot_to_local_spend_info<C: secp256k1::Verification>(revocation_key: &RevocationKey, contest_delay: u16, broadcaster_delayed_payment_key: &DelayedPaymentKey, secp_ctx: &Secp256k1<C>) -> TaprootSpendInfo {
+	taproot_spend_info(taproot_nums_point(), &[
+		get_taproot_to_delayed_leaf(contest_delay, broadcaster_delayed_payment_key),
+		get_taproot_to_local_revoke_leaf(revocation_key, broadcaster_delayed_payment_key),
+	], secp_ctx)
+}
+
+/// Gets the tapscript leaf for the `to_remote` output of a taproot commitment transaction.
+#[cfg(taproot)]
+pub fn get_taproot_to_remote_leaf(payment_point: &PublicKey) -> ScriptBuf {
+	Builder::new().push_x_only_key(&payment_point.x_only_public_key().0)
+		.push_opcode(opcodes::all::OP_CHECKSIG)
+		.push_int(1)
+		.push_opcode(opcodes::all::OP_CSV)
+		.push_opcode(opcodes::all::OP_DROP)
+		.into_script()
+}
+
+/// Gets the taproot spend info for the `to_remote` output of a taproot commitment transaction.
+#[cfg(taproot)]
+pub fn get_taproot_to_remote_spend_info<C: secp256k1::Verification>(payment_point: &PublicKey, secp_ctx: &Secp256k1<C>) -> TaprootSpendInfo {
+	taproot_spend_info(taproot_nums_point(), &[get_taproot_to_remote_leaf(payment_point)], secp_ctx)
+}
+
+/// Gets the tapscript leaf through which anyone can sweep an anchor output after 16 blocks.
+#[cfg(taproot)]
+pub fn get_taproot_anchor_leaf() -> ScriptBuf {
+	Builder::new().push_int(16)
+		.push_opcode(opcodes::all::OP_CSV)
+		.into_script()
+}
+
+/// Gets the taproot spend info for an anchor output of a taproot commitment transaction. The
+/// internal key is the broadcaster's delayed payment key for their anchor, or the
+/// countersignatory's payment point for theirs, spendable at any time via the key path.
+#[cfg(taproot)]
+pub fn get_taproot_anchor_spend_info<C: secp256k1::Verification>(anchor_key: &PublicKey, secp_ctx: &Secp256k1<C>) -> TaprootSpendInfo {
+	taproot_spend_info(anchor_key.x_only_public_key().0, &[get_taproot_anchor_leaf()], secp_ctx)
+}
+
+/// Gets the two tapscript leaves of an HTLC output in a taproot commitment transaction. The first
+/// is spent by the second-stage HTLC transaction (via the HTLC-Timeout path for offered HTLCs and
+/// the HTLC-Success path for received HTLCs), the second directly by the countersignatory.
+#[cfg(taproot)]
+pub(crate) fn get_taproot_htlc_leaves(htlc: &HTLCOutputInCommitment, broadcaster_htlc_key: &HtlcKey, countersignatory_htlc_key: &HtlcKey) -> (ScriptBuf, ScriptBuf) {
+	let payment_hash160 = Ripemd160::hash(&htlc.payment_hash.0[..]).to_byte_array();
+	let broadcaster_htlc_key = broadcaster_htlc_key.to_public_key().x_only_public_key().0;
+	let countersignatory_htlc_key = countersignatory_htlc_key.to_public_key().x_only_public_key().0;
+	let hash_check = Builder::new().push_opcode(opcodes::all::OP_SIZE)
+		.push_int(32)
+		.push_opcode(opcodes::all::OP_EQUALVERIFY)
+		.push_opcode(opcodes::all::OP_HASH160)
+		.push_slice(&payment_hash160)
+		.push_opcode(opcodes::all::OP_EQUALVERIFY);
+	let second_stage_leaf = if htlc.offered { Builder::new() } else { hash_check.clone() }
+		.push_x_only_key(&broadcaster_htlc_key)
+		.push_opcode(opcodes::all::OP_CHECKSIGVERIFY)
+		.push_x_only_key(&countersignatory_htlc_key)
+		.push_opcode(opcodes::all::OP_CHECKSIG)
+		.into_script();
+	let countersignatory_leaf = if htlc.offered { hash_check } else { Builder::new() }
+		.push_x_only_key(&countersignatory_htlc_key)
+		.push_opcode(opcodes::all::OP_CHECKSIG)
+		.push_int(1)
+		.push_opcode(opcodes::all::OP_CSV)
+		.push_opcode(opcodes::all::OP_DROP);
+	let countersignatory_leaf = if htlc.offered {
+		countersignatory_leaf
+	} else {
+		countersignatory_leaf.push_int(htlc.cltv_expiry as i64)
+			.push_opcode(opcodes::all::OP_CLTV)
+			.push_opcode(opcodes::all::OP_DROP)
+	}.into_script();
+	(second_stage_leaf, countersignatory_leaf)
+}
+
+/// Gets the taproot spend info for an HTLC output in a taproot commitment transaction. The
+/// internal key is the revocation key, allowing the countersignatory to claim the output via the
+/// key path if the commitment transaction is revoked.
+#[cfg(taproot)]
+pub fn get_taproot_htlc_spend_info<C: secp256k1::Verification>(htlc: &HTLCOutputInCommitment, keys: &TxCreationKeys, secp_ctx: &Secp256k1<C>) -> TaprootSpendInfo {
+	let (second_stage_leaf, countersignatory_leaf) = get_taproot_htlc_leaves(htlc, &keys.broadcaster_htlc_key, &keys.countersignatory_htlc_key);
+	taproot_spend_info(keys.revocation_key.to_public_key().x_only_public_key().0,
+		&[second_stage_leaf, countersignatory_leaf], secp_ctx)
+}
+
+/// Gets the taproot spend info for the output of a second-stage HTLC transaction on a taproot
+/// channel. The internal key is the revocation key, and the only leaf is the delayed path.
+#[cfg(taproot)]
+pub fn get_taproot_second_stage_htlc_spend_info<C: secp256k1::Verification>(revocation_key: &RevocationKey, contest_delay: u16, broadcaster_delayed_payment_key: &DelayedPaymentKey, secp_ctx: &Secp256k1<C>) -> TaprootSpendInfo {
+	taproot_spend_info(revocation_key.to_public_key().x_only_public_key().0,
+		&[get_taproot_to_delayed_leaf(contest_delay, broadcaster_delayed_payment_key)], secp_ctx)
+}
+
+/// Returns the witness required to spend a taproot output via the given tapscript leaf, given the
+/// stack elements the leaf expects (in the order they are pushed).
+///
+/// Panics if `leaf` is not part of `spend_info`.
+#[cfg(taproot)]
+pub fn build_taproot_script_path_witness(stack: &[&[u8]], leaf: &Script, spend_info: &TaprootSpendInfo) -> Witness {
+	let control_block = spend_info.control_block(&(leaf.to_owned(), LeafVersion::TapScript))
+		.expect("The leaf must be part of the taproot output");
+	let mut witness = Witness::new();
+	for element in stack {
+		witness.push(element);
+	}
+	witness.push(leaf.as_bytes());
+	witness.push(control_block.serialize());
+	witness
+}
+
+/// Returns the witness required to satisfy and spend an HTLC input of a taproot commitment
+/// transaction via a second-stage HTLC transaction.
+#[cfg(taproot)]
+pub fn build_taproot_htlc_input_witness(
+	local_sig: &TaprootSignature, remote_sig: &TaprootSignature, preimage: &Option<PaymentPreimage>,
+	htlc: &HTLCOutputInCommitment, keys: &TxCreationKeys, secp_ctx: &Secp256k1<secp256k1::All>,
+) -> Witness {
+	let spend_info = get_taproot_htlc_spend_info(htlc, keys, secp_ctx);
+	let (second_stage_leaf, _) = get_taproot_htlc_leaves(htlc, &keys.broadcaster_htlc_key, &keys.countersignatory_htlc_key);
+	let remote_sig = remote_sig.to_vec();
+	let local_sig = local_sig.to_vec();
+	let mut stack: Vec<&[u8]> = vec![&remote_sig, &local_sig];
+	if let Some(preimage) = preimage {
+		stack.push(&preimage.0);
+	}
+	build_taproot_script_path_witness(&stack, &second_stage_leaf, &spend_info)
+}
+
 /// Per-channel data used to build transactions in conjunction with the per-commitment data (CommitmentTransaction).
 /// The fields are organized by holder/counterparty.
 ///
@@ -1055,7 +1316,7 @@ impl HolderCommitmentTransaction {
 		for _ in 0..htlcs.len() {
 			counterparty_htlc_sigs.push(dummy_sig);
 		}
-		let inner = CommitmentTransaction::new_with_auxiliary_htlc_data(0, 0, 0, dummy_key.clone(), dummy_key.clone(), keys, 0, htlcs, &channel_parameters.as_counterparty_broadcastable());
+		let inner = CommitmentTransaction::new_with_auxiliary_htlc_data(0, 0, 0, 0, dummy_key.clone(), dummy_key.clone(), keys, 0, htlcs, &channel_parameters.as_counterparty_broadcastable());
 		htlcs.sort_by_key(|htlc| htlc.0.transaction_output_index);
 		HolderCommitmentTransaction {
 			inner,
@@ -1272,6 +1533,8 @@ pub struct CommitmentTransaction {
 	to_broadcaster_value_sat: u64,
 	to_countersignatory_value_sat: u64,
 	to_broadcaster_delay: Option<u16>, // Added in 0.0.117
+	// Only non-zero for zero-fee commitments
+	ephemeral_anchor_value_sat: u64,
 	feerate_per_kw: u32,
 	htlcs: Vec<HTLCOutputInCommitment>,
 	// Note that on upgrades, some features of existing outputs may be missed.
@@ -1288,6 +1551,7 @@ impl PartialEq for CommitmentTransaction {
 		let eq = self.commitment_number == o.commitment_number &&
 			self.to_broadcaster_value_sat == o.to_broadcaster_value_sat &&
 			self.to_countersignatory_value_sat == o.to_countersignatory_value_sat &&
+			self.ephemeral_anchor_value_sat == o.ephemeral_anchor_value_sat &&
 			self.feerate_per_kw == o.feerate_per_kw &&
 			self.htlcs == o.htlcs &&
 			self.channel_type_features == o.channel_type_features &&
@@ -1303,6 +1567,9 @@ impl PartialEq for CommitmentTransaction {
 impl Writeable for CommitmentTransaction {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
 		let legacy_deserialization_prevention_marker = legacy_deserialization_prevention_marker_for_channel_type_features(&self.channel_type_features);
+		let ephemeral_anchor_value_sat = if self.channel_type_features.supports_zero_fee_commitments() {
+			Some(self.ephemeral_anchor_value_sat)
+		} else { None };
 		write_tlv_fields!(writer, {
 			(0, self.commitment_number, required),
 			(1, self.to_broadcaster_delay, option),
@@ -1314,6 +1581,7 @@ impl Writeable for CommitmentTransaction {
 			(12, self.htlcs, required_vec),
 			(14, legacy_deserialization_prevention_marker, option),
 			(15, self.channel_type_features, required),
+			(17, ephemeral_anchor_value_sat, option),
 		});
 		Ok(())
 	}
@@ -1332,6 +1600,7 @@ impl Readable for CommitmentTransaction {
 			(12, htlcs, required_vec),
 			(14, _legacy_deserialization_prevention_marker, option),
 			(15, channel_type_features, option),
+			(17, ephemeral_anchor_value_sat, option),
 		});
 
 		let mut additional_features = ChannelTypeFeatures::empty();
@@ -1343,6 +1612,7 @@ impl Readable for CommitmentTransaction {
 			to_broadcaster_value_sat: to_broadcaster_value_sat.0.unwrap(),
 			to_countersignatory_value_sat: to_countersignatory_value_sat.0.unwrap(),
 			to_broadcaster_delay,
+			ephemeral_anchor_value_sat: ephemeral_anchor_value_sat.unwrap_or(0),
 			feerate_per_kw: feerate_per_kw.0.unwrap(),
 			keys: keys.0.unwrap(),
 			built: built.0.unwrap(),
@@ -1362,19 +1632,26 @@ impl CommitmentTransaction {
 	///
 	/// Only include HTLCs that are above the dust limit for the channel.
 	///
+	/// `ephemeral_anchor_value_sat` is ignored unless the channel uses zero-fee commitments, see
+	/// [`ephemeral_anchor_value_sat`].
+	///
 	/// This is not exported to bindings users due to the generic though we likely should expose a version without
-	pub fn new_with_auxiliary_htlc_data<T>(commitment_number: u64, to_broadcaster_value_sat: u64, to_countersignatory_value_sat: u64, broadcaster_funding_key: PublicKey, countersignatory_funding_key: PublicKey, keys: TxCreationKeys, feerate_per_kw: u32, htlcs_with_aux: &mut Vec<(HTLCOutputInCommitment, T)>, channel_parameters: &DirectedChannelTransactionParameters) -> CommitmentTransaction {
+	pub fn new_with_auxiliary_htlc_data<T>(commitment_number: u64, to_broadcaster_value_sat: u64, to_countersignatory_value_sat: u64, ephemeral_anchor_value_sat: u64, broadcaster_funding_key: PublicKey, countersignatory_funding_key: PublicKey, keys: TxCreationKeys, feerate_per_kw: u32, htlcs_with_aux: &mut Vec<(HTLCOutputInCommitment, T)>, channel_parameters: &DirectedChannelTransactionParameters) -> CommitmentTransaction {
+		let ephemeral_anchor_value_sat = if channel_parameters.channel_type_features().supports_zero_fee_commitments() {
+			ephemeral_anchor_value_sat
+		} else { 0 };
 		// Sort outputs and populate output indices while keeping track of the auxiliary data
-		let (outputs, htlcs) = Self::internal_build_outputs(&keys, to_broadcaster_value_sat, to_countersignatory_value_sat, htlcs_with_aux, channel_parameters, &broadcaster_funding_key, &countersignatory_funding_key).unwrap();
+		let (outputs, htlcs) = Self::internal_build_outputs(&keys, to_broadcaster_value_sat, to_countersignatory_value_sat, ephemeral_anchor_value_sat, htlcs_with_aux, channel_parameters, &broadcaster_funding_key, &countersignatory_funding_key).unwrap();
 
 		let (obscured_commitment_transaction_number, txins) = Self::internal_build_inputs(commitment_number, channel_parameters);
-		let transaction = Self::make_transaction(obscured_commitment_transaction_number, txins, outputs);
+		let transaction = Self::make_transaction(obscured_commitment_transaction_number, txins, outputs, channel_parameters.channel_type_features());
 		let txid = transaction.txid();
 		CommitmentTransaction {
 			commitment_number,
 			to_broadcaster_value_sat,
 			to_countersignatory_value_sat,
 			to_broadcaster_delay: Some(channel_parameters.contest_delay()),
+			ephemeral_anchor_value_sat,
 			feerate_per_kw,
 			htlcs,
 			channel_type_features: channel_parameters.channel_type_features().clone(),
@@ -1398,9 +1675,9 @@ impl CommitmentTransaction {
 		let (obscured_commitment_transaction_number, txins) = Self::internal_build_inputs(self.commitment_number, channel_parameters);
 
 		let mut htlcs_with_aux = self.htlcs.iter().map(|h| (h.clone(), ())).collect();
-		let (outputs, _) = Self::internal_build_outputs(keys, self.to_broadcaster_value_sat, self.to_countersignatory_value_sat, &mut htlcs_with_aux, channel_parameters, broadcaster_funding_key, countersignatory_funding_key)?;
+		let (outputs, _) = Self::internal_build_outputs(keys, self.to_broadcaster_value_sat, self.to_countersignatory_value_sat, self.ephemeral_anchor_value_sat, &mut htlcs_with_aux, channel_parameters, broadcaster_funding_key, countersignatory_funding_key)?;
 
-		let transaction = Self::make_transaction(obscured_commitment_transaction_number, txins, outputs);
+		let transaction = Self::make_transaction(obscured_commitment_transaction_number, txins, outputs, channel_parameters.channel_type_features());
 		let txid = transaction.txid();
 		let built_transaction = BuiltCommitmentTransaction {
 			transaction,
@@ -1409,9 +1686,11 @@ impl CommitmentTransaction {
 		Ok(built_transaction)
 	}
 
-	fn make_transaction(obscured_commitment_transaction_number: u64, txins: Vec<TxIn>, outputs: Vec<TxOut>) -> Transaction {
+	fn make_transaction(obscured_commitment_transaction_number: u64, txins: Vec<TxIn>, outputs: Vec<TxOut>, channel_type_features: &ChannelTypeFeatures) -> Transaction {
 		Transaction {
-			version: 2,
+			// Zero-fee commitments rely on TRUC (BIP 431) policy to be relayed as a package along
+			// with the child spending their ephemeral anchor.
+			version: if channel_type_features.supports_zero_fee_commitments() { 3 } else { 2 },
 			lock_time: LockTime::from_consensus(((0x20 as u32) << 8 * 3) | ((obscured_commitment_transaction_number & 0xffffffu64) as u32)),
 			input: txins,
 			output: outputs,
@@ -1422,12 +1701,17 @@ impl CommitmentTransaction {
 	// - initial sorting of outputs / HTLCs in the constructor, in which case T is auxiliary data the
 	//   caller needs to have sorted together with the HTLCs so it can keep track of the output index
 	// - building of a bitcoin transaction during a verify() call, in which case T is just ()
-	fn internal_build_outputs<T>(keys: &TxCreationKeys, to_broadcaster_value_sat: u64, to_countersignatory_value_sat: u64, htlcs_with_aux: &mut Vec<(HTLCOutputInCommitment, T)>, channel_parameters: &DirectedChannelTransactionParameters, broadcaster_funding_key: &PublicKey, countersignatory_funding_key: &PublicKey) -> Result<(Vec<TxOut>, Vec<HTLCOutputInCommitment>), ()> {
+	fn internal_build_outputs<T>(keys: &TxCreationKeys, to_broadcaster_value_sat: u64, to_countersignatory_value_sat: u64, ephemeral_anchor_value_sat: u64, htlcs_with_aux: &mut Vec<(HTLCOutputInCommitment, T)>, channel_parameters: &DirectedChannelTransactionParameters, broadcaster_funding_key: &PublicKey, countersignatory_funding_key: &PublicKey) -> Result<(Vec<TxOut>, Vec<HTLCOutputInCommitment>), ()> {
 		let countersignatory_pubkeys = channel_parameters.countersignatory_pubkeys();
 		let contest_delay = channel_parameters.contest_delay();
 
 		let mut txouts: Vec<(TxOut, Option<&mut HTLCOutputInCommitment>)> = Vec::new();
 
+		#[cfg(taproot)]
+		if channel_parameters.channel_type_features().supports_taproot() {
+			return Self::internal_build_taproot_outputs(keys, to_broadcaster_value_sat, to_countersignatory_value_sat, htlcs_with_aux, channel_parameters);
+		}
+
 		if to_countersignatory_value_sat > 0 {
 			let script = if channel_parameters.channel_type_features().supports_anchors_zero_fee_htlc_tx() {
 			    get_to_countersignatory_with_anchors_redeemscript(&countersignatory_pubkeys.payment_point).to_v0_p2wsh()
@@ -1458,7 +1742,17 @@ impl CommitmentTransaction {
 			));
 		}
 
-		if channel_parameters.channel_type_features().supports_anchors_zero_fee_htlc_tx() {
+		if channel_parameters.channel_type_features().supports_zero_fee_commitments() {
+			// As the commitment transaction pays no fee, it can only ever confirm along with a
+			// child spending its ephemeral anchor, which therefore must always be present.
+			txouts.push((
+				TxOut {
+					script_pubkey: get_ephemeral_anchor_script_pubkey(),
+					value: ephemeral_anchor_value_sat,
+				},
+				None,
+			));
+		} else if channel_parameters.channel_type_features().supports_anchors_zero_fee_htlc_tx() {
 			if to_broadcaster_value_sat > 0 || !htlcs_with_aux.is_empty() {
 				let anchor_script = get_anchor_redeemscript(broadcaster_funding_key);
 				txouts.push((
@@ -1482,7 +1776,6 @@ impl CommitmentTransaction {
 			}
 		}
 
-		let mut htlcs = Vec::with_capacity(htlcs_with_aux.len());
 		for (htlc, _) in htlcs_with_aux {
 			let script = chan_utils::get_htlc_redeemscript(&htlc, &channel_parameters.channel_type_features(), &keys);
 			let txout = TxOut {
@@ -1492,6 +1785,52 @@ impl CommitmentTransaction {
 			txouts.push((txout, Some(htlc)));
 		}
 
+		Ok(Self::sort_and_index_outputs(txouts))
+	}
+
+	#[cfg(taproot)]
+	fn internal_build_taproot_outputs<T>(keys: &TxCreationKeys, to_broadcaster_value_sat: u64, to_countersignatory_value_sat: u64, htlcs_with_aux: &mut Vec<(HTLCOutputInCommitment, T)>, channel_parameters: &DirectedChannelTransactionParameters) -> Result<(Vec<TxOut>, Vec<HTLCOutputInCommitment>), ()> {
+		let secp_ctx = Secp256k1::verification_only();
+		let countersignatory_pubkeys = channel_parameters.countersignatory_pubkeys();
+		let contest_delay = channel_parameters.contest_delay();
+		let p2tr_output = |spend_info: TaprootSpendInfo, value: u64| TxOut {
+			script_pubkey: ScriptBuf::new_v1_p2tr_tweaked(spend_info.output_key()),
+			value,
+		};
+
+		let mut txouts: Vec<(TxOut, Option<&mut HTLCOutputInCommitment>)> = Vec::new();
+
+		if to_countersignatory_value_sat > 0 {
+			let spend_info = get_taproot_to_remote_spend_info(&countersignatory_pubkeys.payment_point, &secp_ctx);
+			txouts.push((p2tr_output(spend_info, to_countersignatory_value_sat), None));
+		}
+
+		if to_broadcaster_value_sat > 0 {
+			let spend_info = get_taproot_to_local_spend_info(
+				&keys.revocation_key, contest_delay, &keys.broadcaster_delayed_payment_key, &secp_ctx,
+			);
+			txouts.push((p2tr_output(spend_info, to_broadcaster_value_sat), None));
+		}
+
+		if to_broadcaster_value_sat > 0 || !htlcs_with_aux.is_empty() {
+			let spend_info = get_taproot_anchor_spend_info(&keys.broadcaster_delayed_payment_key.to_public_key(), &secp_ctx);
+			txouts.push((p2tr_output(spend_info, ANCHOR_OUTPUT_VALUE_SATOSHI), None));
+		}
+
+		if to_countersignatory_value_sat > 0 || !htlcs_with_aux.is_empty() {
+			let spend_info = get_taproot_anchor_spend_info(&countersignatory_pubkeys.payment_point, &secp_ctx);
+			txouts.push((p2tr_output(spend_info, ANCHOR_OUTPUT_VALUE_SATOSHI), None));
+		}
+
+		for (htlc, _) in htlcs_with_aux {
+			let spend_info = get_taproot_htlc_spend_info(&htlc, keys, &secp_ctx);
+			txouts.push((p2tr_output(spend_info, htlc.amount_msat / 1000), Some(htlc)));
+		}
+
+		Ok(Self::sort_and_index_outputs(txouts))
+	}
+
+	fn sort_and_index_outputs(mut txouts: Vec<(TxOut, Option<&mut HTLCOutputInCommitment>)>) -> (Vec<TxOut>, Vec<HTLCOutputInCommitment>) {
 		// Sort output in BIP-69 order (amount, scriptPubkey).  Tie-breaks based on HTLC
 		// CLTV expiration height.
 		sort_outputs(&mut txouts, |a, b| {
@@ -1509,6 +1848,7 @@ impl CommitmentTransaction {
 		});
 
 		let mut outputs = Vec::with_capacity(txouts.len());
+		let mut htlcs = Vec::with_capacity(txouts.len());
 		for (idx, out) in txouts.drain(..).enumerate() {
 			if let Some(htlc) = out.1 {
 				htlc.transaction_output_index = Some(idx as u32);
@@ -1516,7 +1856,7 @@ impl CommitmentTransaction {
 			}
 			outputs.push(out.0);
 		}
-		Ok((outputs, htlcs))
+		(outputs, htlcs)
 	}
 
 	fn internal_build_inputs(commitment_number: u64, channel_parameters: &DirectedChannelTransactionParameters) -> (u64, Vec<TxIn>) {
@@ -1811,7 +2151,7 @@ mod tests {
 	use super::{CounterpartyCommitmentSecrets, ChannelPublicKeys};
 	use crate::chain;
 	use crate::prelude::*;
-	use crate::ln::chan_utils::{get_htlc_redeemscript, get_to_countersignatory_with_anchors_redeemscript, CommitmentTransaction, TxCreationKeys, ChannelTransactionParameters, CounterpartyChannelTransactionParameters, HTLCOutputInCommitment};
+	use crate::ln::chan_utils::{get_htlc_redeemscript, get_to_countersignatory_with_anchors_redeemscript, get_ephemeral_anchor_script_pubkey, get_ephemeral_anchor_output, ephemeral_anchor_value_sat, CommitmentTransaction, TxCreationKeys, ChannelTransactionParameters, CounterpartyChannelTransactionParameters, HTLCOutputInCommitment, EPHEMERAL_ANCHOR_MAX_VALUE_SATOSHI};
 	use bitcoin::secp256k1::{PublicKey, SecretKey, Secp256k1};
 	use crate::util::test_utils;
 	use crate::sign::{ChannelSigner, SignerProvider};
@@ -1822,6 +2162,8 @@ mod tests {
 	use bitcoin::address::Payload;
 	use bitcoin::PublicKey as BitcoinPublicKey;
 	use crate::ln::features::ChannelTypeFeatures;
+	use crate::util::ser::{Readable, Writeable};
+	use crate::io;
 
 	struct TestCommitmentTxBuilder {
 		commitment_number: u64,
@@ -1829,6 +2171,7 @@ mod tests {
 		counterparty_funding_pubkey: PublicKey,
 		keys: TxCreationKeys,
 		feerate_per_kw: u32,
+		ephemeral_anchor_value_sat: u64,
 		htlcs_with_aux: Vec<(HTLCOutputInCommitment, ())>,
 		channel_parameters: ChannelTransactionParameters,
 		counterparty_pubkeys: ChannelPublicKeys,
@@ -1865,6 +2208,7 @@ mod tests {
 				counterparty_funding_pubkey: counterparty_pubkeys.funding_pubkey,
 				keys,
 				feerate_per_kw: 1,
+				ephemeral_anchor_value_sat: 0,
 				htlcs_with_aux,
 				channel_parameters,
 				counterparty_pubkeys,
@@ -1874,6 +2218,7 @@ mod tests {
 		fn build(&mut self, to_broadcaster_sats: u64, to_countersignatory_sats: u64) -> CommitmentTransaction {
 			CommitmentTransaction::new_with_auxiliary_htlc_data(
 				self.commitment_number, to_broadcaster_sats, to_countersignatory_sats,
+				self.ephemeral_anchor_value_sat,
 				self.holder_funding_pubkey.clone(),
 				self.counterparty_funding_pubkey.clone(),
 				self.keys.clone(), self.feerate_per_kw,
@@ -1947,6 +2292,56 @@ mod tests {
 				   "002087a3faeb1950a469c0e2db4a79b093a41b9526e5a6fc6ef5cb949bde3be379c7");
 	}
 
+	#[test]
+	fn test_zero_fee_commitments() {
+		let mut builder = TestCommitmentTxBuilder::new();
+		builder.channel_parameters.channel_type_features = ChannelTypeFeatures::zero_fee_commitments_and_dependencies();
+		assert_eq!(get_ephemeral_anchor_script_pubkey().to_hex_string(), "51024e73");
+
+		// Generate broadcaster and counterparty outputs as well as a single ephemeral anchor on a
+		// version 3 transaction
+		let tx = builder.build(1000, 2000);
+		assert_eq!(tx.built.transaction.version, 3);
+		assert_eq!(tx.built.transaction.output.len(), 3);
+		let (anchor_idx, anchor_output) = get_ephemeral_anchor_output(&tx.built.transaction).unwrap();
+		assert_eq!(anchor_idx, 0);
+		assert_eq!(anchor_output.value, 0);
+		assert_eq!(tx.built.transaction.output[2].script_pubkey, get_to_countersignatory_with_anchors_redeemscript(&builder.counterparty_pubkeys.payment_point).to_v0_p2wsh());
+
+		// The ephemeral anchor is always present, even without any other outputs
+		let tx = builder.build(0, 0);
+		assert_eq!(tx.built.transaction.output.len(), 1);
+
+		// Trimmed value goes to the ephemeral anchor, up to its maximum value
+		let offered_htlc = HTLCOutputInCommitment {
+			offered: true,
+			amount_msat: 600000,
+			cltv_expiry: 100,
+			payment_hash: PaymentHash([43; 32]),
+			transaction_output_index: None,
+		};
+		assert_eq!(ephemeral_anchor_value_sat(3_000, 1_000, 2_000, [].iter()), 0);
+		assert_eq!(ephemeral_anchor_value_sat(3_700, 1_000, 2_000, [offered_htlc.clone()].iter()), 100);
+		assert_eq!(ephemeral_anchor_value_sat(10_000, 1_000, 2_000, [offered_htlc.clone()].iter()), EPHEMERAL_ANCHOR_MAX_VALUE_SATOSHI);
+
+		builder.ephemeral_anchor_value_sat = 100;
+		builder.htlcs_with_aux = vec![(offered_htlc.clone(), ())];
+		let tx = builder.build(3000, 0);
+		assert_eq!(tx.built.transaction.output.len(), 3);
+		assert_eq!(get_ephemeral_anchor_output(&tx.built.transaction).unwrap().1.value, 100);
+		assert_eq!(tx.htlcs()[0].transaction_output_index, Some(1));
+
+		// The ephemeral anchor's value survives a serialization roundtrip
+		let tx_read = CommitmentTransaction::read(&mut io::Cursor::new(tx.encode())).unwrap();
+		assert_eq!(tx_read, tx);
+
+		// Non-zero-fee channels never have an ephemeral anchor
+		builder.channel_parameters.channel_type_features = ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies();
+		let tx = builder.build(3000, 0);
+		assert_eq!(tx.built.transaction.version, 2);
+		assert!(get_ephemeral_anchor_output(&tx.built.transaction).is_none());
+	}
+
 	#[test]
 	fn test_finding_revokeable_output_index() {
 		let mut builder = TestCommitmentTxBuilder::new();
@@ -2346,4 +2741,94 @@ mod tests {
 			assert!(monitor.provide_secret(281474976710648, secrets.last().unwrap().clone()).is_err());
 		}
 	}
+
+	#[cfg(taproot)]
+	#[test]
+	fn test_taproot_nums_point() {
+		// BIP 341 derives the NUMS point by hashing the uncompressed encoding of the generator.
+		let generator = <Vec<u8>>::from_hex("0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8").unwrap();
+		assert_eq!(bitcoin::hashes::sha256::Hash::hash(&generator).to_byte_array()[..], super::TAPROOT_NUMS_POINT[1..]);
+		assert!(PublicKey::from_slice(&super::TAPROOT_NUMS_POINT).is_ok());
+	}
+
+	#[cfg(taproot)]
+	#[test]
+	fn test_taproot_funding_script() {
+		let keys: Vec<PublicKey> = [
+			"02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+			"03dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659",
+		].iter().map(|hex| PublicKey::from_slice(&<Vec<u8>>::from_hex(hex).unwrap()).unwrap()).collect();
+
+		// The funding key is independent of which side is the broadcaster.
+		let secp_ctx = Secp256k1::verification_only();
+		assert_eq!(super::get_taproot_funding_script(&keys[0], &keys[1], &secp_ctx),
+			super::get_taproot_funding_script(&keys[1], &keys[0], &secp_ctx));
+		assert!(super::get_taproot_funding_script(&keys[0], &keys[1], &secp_ctx).is_v1_p2tr());
+	}
+
+	#[cfg(taproot)]
+	#[test]
+	fn test_taproot_commitment_outputs() {
+		use bitcoin::taproot::{LeafVersion, TaprootSpendInfo};
+
+		let secp_ctx = Secp256k1::verification_only();
+		let mut builder = TestCommitmentTxBuilder::new();
+		let mut channel_type_features = ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies();
+		channel_type_features.set_taproot_required();
+		builder.channel_parameters.channel_type_features = channel_type_features.clone();
+
+		let received_htlc = HTLCOutputInCommitment {
+			offered: false,
+			amount_msat: 400000,
+			cltv_expiry: 100,
+			payment_hash: PaymentHash([42; 32]),
+			transaction_output_index: None,
+		};
+		let offered_htlc = HTLCOutputInCommitment {
+			offered: true,
+			amount_msat: 600000,
+			cltv_expiry: 100,
+			payment_hash: PaymentHash([43; 32]),
+			transaction_output_index: None,
+		};
+		builder.htlcs_with_aux = vec![(received_htlc.clone(), ()), (offered_htlc.clone(), ())];
+		let tx = builder.build(3000, 2000);
+		let keys = builder.keys.clone();
+		let p2tr = |spend_info: &TaprootSpendInfo| ScriptBuf::new_v1_p2tr_tweaked(spend_info.output_key());
+		let find_output = |script: &ScriptBuf| tx.built.transaction.output.iter().position(|txout| txout.script_pubkey == *script);
+
+		// Both balances, both anchors and both HTLCs are present, all as P2TR outputs.
+		assert_eq!(tx.built.transaction.output.len(), 6);
+		assert!(tx.built.transaction.output.iter().all(|txout| txout.script_pubkey.is_v1_p2tr()));
+
+		let to_local = super::get_taproot_to_local_spend_info(&keys.revocation_key, 0, &keys.broadcaster_delayed_payment_key, &secp_ctx);
+		assert!(find_output(&p2tr(&to_local)).is_some());
+		let to_remote = super::get_taproot_to_remote_spend_info(&builder.counterparty_pubkeys.payment_point, &secp_ctx);
+		assert!(find_output(&p2tr(&to_remote)).is_some());
+		let local_anchor = super::get_taproot_anchor_spend_info(&keys.broadcaster_delayed_payment_key.to_public_key(), &secp_ctx);
+		assert!(find_output(&p2tr(&local_anchor)).is_some());
+
+		for htlc in tx.htlcs() {
+			let spend_info = super::get_taproot_htlc_spend_info(htlc, &keys, &secp_ctx);
+			assert_eq!(find_output(&p2tr(&spend_info)), Some(htlc.transaction_output_index.unwrap() as usize));
+
+			// Both leaves must be provably committed to by the output key.
+			let (second_stage_leaf, countersignatory_leaf) = super::get_taproot_htlc_leaves(htlc, &keys.broadcaster_htlc_key, &keys.countersignatory_htlc_key);
+			for leaf in [second_stage_leaf, countersignatory_leaf] {
+				let control_block = spend_info.control_block(&(leaf.clone(), LeafVersion::TapScript)).unwrap();
+				assert!(control_block.verify_taproot_commitment(&secp_ctx, spend_info.output_key().to_inner(), &leaf));
+			}
+
+			// Second-stage HTLC transactions pay to a taproot output as well.
+			let htlc_tx = super::build_htlc_transaction(&tx.trust().txid(), tx.feerate_per_kw(), 0, htlc, &channel_type_features, &keys.broadcaster_delayed_payment_key, &keys.revocation_key);
+			let second_stage = super::get_taproot_second_stage_htlc_spend_info(&keys.revocation_key, 0, &keys.broadcaster_delayed_payment_key, &secp_ctx);
+			assert_eq!(htlc_tx.output[0].script_pubkey, p2tr(&second_stage));
+		}
+
+		// The revocation leaf of the to_local output spends via a valid control block.
+		let revoke_leaf = super::get_taproot_to_local_revoke_leaf(&keys.revocation_key, &keys.broadcaster_delayed_payment_key);
+		let witness = super::build_taproot_script_path_witness(&[&[1; 64]], &revoke_leaf, &to_local);
+		assert_eq!(witness.len(), 3);
+		assert_eq!(witness.nth(1).unwrap(), revoke_leaf.as_bytes());
+	}
 }
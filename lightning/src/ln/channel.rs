@@ -10,6 +10,16 @@
 use bitcoin::blockdata::constants::ChainHash;
 use bitcoin::blockdata::script::{Script, ScriptBuf, Builder};
 use bitcoin::blockdata::transaction::Transaction;
+#[cfg(dual_funding)]
+use bitcoin::blockdata::transaction::TxIn;
+#[cfg(dual_funding)]
+use bitcoin::blockdata::transaction::TxOut;
+#[cfg(dual_funding)]
+use bitcoin::blockdata::locktime::absolute::LockTime;
+#[cfg(dual_funding)]
+use bitcoin::Witness;
+#[cfg(dual_funding)]
+use bitcoin::blockdata::constants::WITNESS_SCALE_FACTOR;
 use bitcoin::sighash;
 use bitcoin::sighash::EcdsaSighashType;
 use bitcoin::consensus::encode;
@@ -30,9 +40,11 @@ use crate::ln::msgs;
 use crate::ln::msgs::DecodeError;
 use crate::ln::script::{self, ShutdownScript};
 use crate::ln::channelmanager::{self, CounterpartyForwardingInfo, PendingHTLCStatus, HTLCSource, SentHTLCId, HTLCFailureMsg, PendingHTLCInfo, RAACommitmentOrder, BREAKDOWN_TIMEOUT, MIN_CLTV_EXPIRY_DELTA, MAX_LOCAL_BREAKDOWN_TIMEOUT, ChannelShutdownState};
-use crate::ln::chan_utils::{CounterpartyCommitmentSecrets, TxCreationKeys, HTLCOutputInCommitment, htlc_success_tx_weight, htlc_timeout_tx_weight, make_funding_redeemscript, ChannelPublicKeys, CommitmentTransaction, HolderCommitmentTransaction, ChannelTransactionParameters, CounterpartyChannelTransactionParameters, MAX_HTLCS, get_commitment_transaction_number_obscure_factor, ClosingTransaction};
+use crate::ln::chan_utils::{CounterpartyCommitmentSecrets, TxCreationKeys, HTLCOutputInCommitment, htlc_success_tx_weight, htlc_timeout_tx_weight, make_funding_redeemscript, ChannelPublicKeys, CommitmentTransaction, HolderCommitmentTransaction, ChannelTransactionParameters, CounterpartyChannelTransactionParameters, MAX_HTLCS, MAX_ZERO_FEE_COMMITMENT_HTLCS, get_commitment_transaction_number_obscure_factor, ClosingTransaction};
 use crate::ln::chan_utils;
 use crate::ln::onion_utils::HTLCFailReason;
+#[cfg(dual_funding)]
+use crate::ln::interactivetxs::{AbortReason, ConstructedTransaction, InteractiveTxConstructor, InteractiveTxMessageSend, calculate_change_output_value};
 use crate::chain::BestBlock;
 use crate::chain::chaininterface::{FeeEstimator, ConfirmationTarget, LowerBoundedFeeEstimator};
 use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, ChannelMonitorUpdateStep, LATENCY_GRACE_PERIOD_BLOCKS, CLOSED_CHANNEL_UPDATE_ID};
@@ -42,6 +54,8 @@ use crate::sign::{EntropySource, ChannelSigner, SignerProvider, NodeSigner, Reci
 use crate::events::ClosureReason;
 use crate::routing::gossip::NodeId;
 use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer};
+#[cfg(dual_funding)]
+use crate::util::ser::TransactionU16LenLimited;
 use crate::util::logger::{Logger, Record, WithContext};
 use crate::util::errors::APIError;
 use crate::util::config::{UserConfig, ChannelConfig, LegacyChannelConfig, ChannelHandshakeConfig, ChannelHandshakeLimits, MaxDustHTLCExposure};
@@ -235,6 +249,11 @@ struct OutboundHTLCOutput {
 	source: HTLCSource,
 	blinding_point: Option<PublicKey>,
 	skimmed_fee_msat: Option<u64>,
+	/// If set, the secret our counterparty should hold this HTLC until it receives in a
+	/// `release_held_htlc` onion message.
+	hold_htlc: Option<[u8; 32]>,
+	/// The endorsement signal we set on this HTLC, if any.
+	endorsed: Option<bool>,
 }
 
 /// See AwaitingRemoteRevoke ChannelState for more info
@@ -250,6 +269,8 @@ enum HTLCUpdateAwaitingACK {
 		// The extra fee we're skimming off the top of this HTLC.
 		skimmed_fee_msat: Option<u64>,
 		blinding_point: Option<PublicKey>,
+		hold_htlc: Option<[u8; 32]>,
+		endorsed: Option<bool>,
 	},
 	ClaimHTLC {
 		payment_preimage: PaymentPreimage,
@@ -365,6 +386,11 @@ mod state_flags {
 	pub const LOCAL_SHUTDOWN_SENT: u32 = 1 << 11;
 	pub const SHUTDOWN_COMPLETE: u32 = 1 << 12;
 	pub const WAITING_FOR_BATCH: u32 = 1 << 13;
+	pub const WAITING_FOR_TX_SIGNATURES: u32 = 1 << 14;
+	pub const AWAITING_QUIESCENCE: u32 = 1 << 15;
+	pub const LOCAL_STFU_SENT: u32 = 1 << 16;
+	pub const REMOTE_STFU_SENT: u32 = 1 << 17;
+	pub const QUIESCENT: u32 = 1 << 18;
 }
 
 define_state_flags!(
@@ -404,7 +430,10 @@ define_state_flags!(
 			OUR_CHANNEL_READY, state_flags::OUR_CHANNEL_READY),
 		("Indicates the channel was funded in a batch and the broadcast of the funding transaction \
 			is being held until all channels in the batch have received `funding_signed` and have \
-			their monitors persisted.", WAITING_FOR_BATCH, state_flags::WAITING_FOR_BATCH)
+			their monitors persisted.", WAITING_FOR_BATCH, state_flags::WAITING_FOR_BATCH),
+		("Indicates the channel was dual-funded and we're still exchanging `tx_signatures` for the \
+			funding transaction with our counterparty, so it cannot be broadcast yet.",
+			WAITING_FOR_TX_SIGNATURES, state_flags::WAITING_FOR_TX_SIGNATURES)
 	]
 );
 
@@ -415,10 +444,27 @@ define_state_flags!(
 			`revoke_and_ack` message. During this period, we can't generate new `commitment_signed` \
 			messages as we'd be unable to determine which HTLCs they included in their `revoke_and_ack` \
 			implicit ACK, so instead we have to hold them away temporarily to be sent later.",
-			AWAITING_REMOTE_REVOKE, state_flags::AWAITING_REMOTE_REVOKE)
+			AWAITING_REMOTE_REVOKE, state_flags::AWAITING_REMOTE_REVOKE),
+		("Indicates we've been asked to make the channel quiescent but have yet to send our `stfu` \
+			as some updates are still pending. Any new updates from us are held in the holding cell \
+			in the meantime.", AWAITING_QUIESCENCE, state_flags::AWAITING_QUIESCENCE),
+		("Indicates we've sent an `stfu` and are waiting on our counterparty's. We may not send any \
+			further updates until quiescence is terminated.", LOCAL_STFU_SENT, state_flags::LOCAL_STFU_SENT),
+		("Indicates we've received an `stfu` from our counterparty and will respond with our own \
+			once all pending updates have been irrevocably committed. Our counterparty may not send \
+			any further updates until quiescence is terminated.", REMOTE_STFU_SENT, state_flags::REMOTE_STFU_SENT),
+		("Indicates both sides have exchanged `stfu` and the channel is quiescent. No updates may \
+			be sent by either side until quiescence is terminated, either explicitly or by \
+			disconnecting.", QUIESCENT, state_flags::QUIESCENT)
 	]
 );
 
+impl ChannelReadyFlags {
+	/// All flags which track a pending or completed quiescence handshake.
+	const QUIESCENCE_FLAGS: ChannelReadyFlags = ChannelReadyFlags(state_flags::AWAITING_QUIESCENCE |
+		state_flags::LOCAL_STFU_SENT | state_flags::REMOTE_STFU_SENT | state_flags::QUIESCENT);
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq)]
 enum ChannelState {
 	/// We are negotiating the parameters required for the channel prior to funding it.
@@ -528,7 +574,8 @@ impl ChannelState {
 			ChannelState::ChannelReady(flags) =>
 				flags.is_set(ChannelReadyFlags::AWAITING_REMOTE_REVOKE) ||
 					flags.is_set(FundedStateFlags::MONITOR_UPDATE_IN_PROGRESS.into()) ||
-					flags.is_set(FundedStateFlags::PEER_DISCONNECTED.into()),
+					flags.is_set(FundedStateFlags::PEER_DISCONNECTED.into()) ||
+					!(*flags & ChannelReadyFlags::QUIESCENCE_FLAGS).is_empty(),
 			_ => {
 				debug_assert!(false, "The holding cell is only valid within ChannelReady");
 				false
@@ -550,20 +597,56 @@ impl ChannelState {
 		AwaitingChannelReadyFlags::THEIR_CHANNEL_READY, AwaitingChannelReady);
 	impl_state_flag!(is_waiting_for_batch, set_waiting_for_batch, clear_waiting_for_batch,
 		AwaitingChannelReadyFlags::WAITING_FOR_BATCH, AwaitingChannelReady);
+	impl_state_flag!(is_waiting_for_tx_signatures, set_waiting_for_tx_signatures, clear_waiting_for_tx_signatures,
+		AwaitingChannelReadyFlags::WAITING_FOR_TX_SIGNATURES, AwaitingChannelReady);
 	impl_state_flag!(is_awaiting_remote_revoke, set_awaiting_remote_revoke, clear_awaiting_remote_revoke,
 		ChannelReadyFlags::AWAITING_REMOTE_REVOKE, ChannelReady);
+	impl_state_flag!(is_awaiting_quiescence, set_awaiting_quiescence, clear_awaiting_quiescence,
+		ChannelReadyFlags::AWAITING_QUIESCENCE, ChannelReady);
+	impl_state_flag!(is_local_stfu_sent, set_local_stfu_sent, clear_local_stfu_sent,
+		ChannelReadyFlags::LOCAL_STFU_SENT, ChannelReady);
+	impl_state_flag!(is_remote_stfu_sent, set_remote_stfu_sent, clear_remote_stfu_sent,
+		ChannelReadyFlags::REMOTE_STFU_SENT, ChannelReady);
+	impl_state_flag!(is_quiescent, set_quiescent, clear_quiescent,
+		ChannelReadyFlags::QUIESCENT, ChannelReady);
+
+	/// Clears all quiescence-related flags. Quiescence is implicitly terminated upon disconnection
+	/// and is never persisted.
+	fn clear_quiescence_flags(&mut self) {
+		if let ChannelState::ChannelReady(flags) = self {
+			*flags &= !ChannelReadyFlags::QUIESCENCE_FLAGS;
+		}
+	}
 }
 
 pub const INITIAL_COMMITMENT_NUMBER: u64 = (1 << 48) - 1;
 
 pub const DEFAULT_MAX_HTLCS: u16 = 50;
 
+/// The maximum number of HTLCs either side may have in flight towards the other on a channel of
+/// the given type.
+pub(crate) fn max_htlcs(channel_type_features: &ChannelTypeFeatures) -> u16 {
+	if channel_type_features.supports_zero_fee_commitments() { MAX_ZERO_FEE_COMMITMENT_HTLCS } else { MAX_HTLCS }
+}
+
 pub(crate) fn commitment_tx_base_weight(channel_type_features: &ChannelTypeFeatures) -> u64 {
 	const COMMITMENT_TX_BASE_WEIGHT: u64 = 724;
 	const COMMITMENT_TX_BASE_ANCHOR_WEIGHT: u64 = 1124;
 	if channel_type_features.supports_anchors_zero_fee_htlc_tx() { COMMITMENT_TX_BASE_ANCHOR_WEIGHT } else { COMMITMENT_TX_BASE_WEIGHT }
 }
 
+/// The value of the anchor outputs the funder pays for on each commitment transaction.
+///
+/// Zero-fee commitments replace both anchors with a single ephemeral anchor, which only ever
+/// carries value which would otherwise have gone to fees, so the funder doesn't pay for it.
+pub(crate) fn anchor_outputs_value_sat(channel_type_features: &ChannelTypeFeatures) -> u64 {
+	if channel_type_features.supports_anchors_zero_fee_htlc_tx() && !channel_type_features.supports_zero_fee_commitments() {
+		ANCHOR_OUTPUT_VALUE_SATOSHI * 2
+	} else {
+		0
+	}
+}
+
 #[cfg(not(test))]
 const COMMITMENT_TX_WEIGHT_PER_HTLC: u64 = 172;
 #[cfg(test)]
@@ -791,6 +874,8 @@ pub(super) struct MonitorRestoreUpdates {
 	pub funding_broadcastable: Option<Transaction>,
 	pub channel_ready: Option<msgs::ChannelReady>,
 	pub announcement_sigs: Option<msgs::AnnouncementSignatures>,
+	#[cfg(dual_funding)]
+	pub tx_signatures: Option<msgs::TxSignatures>,
 }
 
 /// The return value of `signer_maybe_unblocked`
@@ -900,6 +985,10 @@ impl_writeable_tlv_based!(PendingChannelMonitorUpdate, {
 pub(super) enum ChannelPhase<SP: Deref> where SP::Target: SignerProvider {
 	UnfundedOutboundV1(OutboundV1Channel<SP>),
 	UnfundedInboundV1(InboundV1Channel<SP>),
+	#[cfg(dual_funding)]
+	UnfundedOutboundV2(OutboundV2Channel<SP>),
+	#[cfg(dual_funding)]
+	UnfundedInboundV2(InboundV2Channel<SP>),
 	Funded(Channel<SP>),
 }
 
@@ -912,6 +1001,10 @@ impl<'a, SP: Deref> ChannelPhase<SP> where
 			ChannelPhase::Funded(chan) => &chan.context,
 			ChannelPhase::UnfundedOutboundV1(chan) => &chan.context,
 			ChannelPhase::UnfundedInboundV1(chan) => &chan.context,
+			#[cfg(dual_funding)]
+			ChannelPhase::UnfundedOutboundV2(chan) => &chan.context,
+			#[cfg(dual_funding)]
+			ChannelPhase::UnfundedInboundV2(chan) => &chan.context,
 		}
 	}
 
@@ -920,6 +1013,10 @@ impl<'a, SP: Deref> ChannelPhase<SP> where
 			ChannelPhase::Funded(ref mut chan) => &mut chan.context,
 			ChannelPhase::UnfundedOutboundV1(ref mut chan) => &mut chan.context,
 			ChannelPhase::UnfundedInboundV1(ref mut chan) => &mut chan.context,
+			#[cfg(dual_funding)]
+			ChannelPhase::UnfundedOutboundV2(ref mut chan) => &mut chan.context,
+			#[cfg(dual_funding)]
+			ChannelPhase::UnfundedInboundV2(ref mut chan) => &mut chan.context,
 		}
 	}
 }
@@ -946,6 +1043,70 @@ impl UnfundedChannelContext {
 	}
 }
 
+/// The weight of the fields of a dual-funded transaction which are paid for by the initiator: the
+/// version, locktime, input/output counts and segwit marker and flag.
+#[cfg(dual_funding)]
+pub(crate) const TX_COMMON_FIELDS_WEIGHT: u64 = (4 /* version */ + 4 /* locktime */ +
+	1 /* input count */ + 1 /* output count */) * WITNESS_SCALE_FACTOR as u64 + 2 /* segwit marker + flag */;
+
+/// The progress of an upgrade of a [`Channel`]'s type negotiated via dynamic commitments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChannelTypeUpgradeState {
+	/// We requested the upgrade and are waiting for the channel to become quiescent before sending
+	/// `dyn_propose`.
+	AwaitingQuiescence,
+	/// We sent `dyn_propose` and are waiting on our counterparty's `dyn_ack` or `dyn_reject`.
+	Proposed,
+	/// Both sides agreed on the new channel type and are exchanging the first commitment
+	/// transactions built with it.
+	Committing {
+		commitment_signed_sent: bool,
+		commitment_signed_received: bool,
+	},
+}
+
+impl_writeable_tlv_based_enum!(ChannelTypeUpgradeState,
+	(0, AwaitingQuiescence) => {},
+	(2, Proposed) => {},
+	(4, Committing) => {
+		(0, commitment_signed_sent, required),
+		(2, commitment_signed_received, required),
+	};
+);
+
+/// An upgrade of the [`Channel`]'s type which is being negotiated or committed to.
+///
+/// Once both sides agree on the new type, it applies to all commitment transactions built from
+/// then on. The upgrade completes once both sides have irrevocably committed to a commitment
+/// transaction using it, after which quiescence is terminated.
+// TODO(dyn_commitments): Only upgrades to `option_anchors_zero_fee_htlc_tx` are supported for now,
+// revisit once the dynamic commitments spec settles on which transitions are allowed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PendingChannelTypeUpgrade {
+	/// The channel type we're upgrading to.
+	channel_type: ChannelTypeFeatures,
+	/// The channel type used before the upgrade, which we revert to if our counterparty never
+	/// received our first commitment transaction using the new type.
+	previous_channel_type: ChannelTypeFeatures,
+	state: ChannelTypeUpgradeState,
+	/// Whether the [`ChannelMonitorUpdateStep::ChannelTypeUpdated`] has yet to be included in a
+	/// [`ChannelMonitorUpdate`].
+	monitor_update_pending: bool,
+}
+
+impl_writeable_tlv_based!(PendingChannelTypeUpgrade, {
+	(0, channel_type, required),
+	(2, previous_channel_type, required),
+	(4, state, required),
+	(6, monitor_update_pending, required),
+});
+
+/// Our response to a counterparty's `dyn_propose`.
+pub(super) enum DynProposeResponse {
+	Ack(msgs::DynAck),
+	Reject(msgs::DynReject),
+}
+
 /// Contains everything about the channel including state, and various flags.
 pub(super) struct ChannelContext<SP: Deref> where SP::Target: SignerProvider {
 	config: LegacyChannelConfig,
@@ -1186,10 +1347,19 @@ pub(super) struct ChannelContext<SP: Deref> where SP::Target: SignerProvider {
 	/// to in a timely manner, which may lead to channels becoming unusable and/or force-closed. An
 	/// example of such can be found at <https://github.com/lightningnetwork/lnd/issues/7682>.
 	///
-	/// This is currently only used when waiting for a [`msgs::ChannelReestablish`] or
-	/// [`msgs::RevokeAndACK`] message from the counterparty.
+	/// This is currently only used when waiting for a [`msgs::ChannelReestablish`],
+	/// [`msgs::RevokeAndACK`] or [`msgs::Stfu`] message from the counterparty.
 	sent_message_awaiting_response: Option<usize>,
 
+	/// Whether we are the initiator of the current quiescence session, set once the `stfu`
+	/// exchange determines it and cleared once quiescence is terminated.
+	///
+	/// This is never persisted as quiescence is always terminated upon disconnection.
+	#[cfg(test)]
+	pub(super) is_holder_quiescence_initiator: Option<bool>,
+	#[cfg(not(test))]
+	is_holder_quiescence_initiator: Option<bool>,
+
 	#[cfg(any(test, fuzzing))]
 	// When we receive an HTLC fulfill on an outbound path, we may immediately fulfill the
 	// corresponding HTLC on the inbound path. If, then, the outbound path channel is
@@ -1451,6 +1621,16 @@ impl<SP: Deref> ChannelContext<SP> where SP::Target: SignerProvider  {
 		self.counterparty_htlc_minimum_msat
 	}
 
+	/// Allowed in any state (including after shutdown)
+	pub fn get_counterparty_max_accepted_htlcs(&self) -> u16 {
+		self.counterparty_max_accepted_htlcs
+	}
+
+	/// Allowed in any state (including after shutdown)
+	pub fn get_counterparty_max_htlc_value_in_flight_msat(&self) -> u64 {
+		self.counterparty_max_htlc_value_in_flight_msat
+	}
+
 	/// Allowed in any state (including after shutdown), but will return none before TheirInitSent
 	pub fn get_counterparty_htlc_maximum_msat(&self) -> Option<u64> {
 		self.get_htlc_maximum_msat(self.counterparty_max_htlc_value_in_flight_msat)
@@ -1470,6 +1650,11 @@ impl<SP: Deref> ChannelContext<SP> where SP::Target: SignerProvider  {
 		self.channel_value_satoshis
 	}
 
+	/// Gets the identifier the channel's signer was derived from.
+	pub fn get_channel_keys_id(&self) -> [u8; 32] {
+		self.channel_keys_id
+	}
+
 	pub fn get_fee_proportional_millionths(&self) -> u32 {
 		self.config.options.forwarding_fee_proportional_millionths
 	}
@@ -1558,11 +1743,272 @@ impl<SP: Deref> ChannelContext<SP> where SP::Target: SignerProvider  {
 		did_channel_update
 	}
 
+	/// Checks an `accept_channel` (or, for V2 channels, an equivalent built from their
+	/// `accept_channel2`) and updates our state with the counterparty's parameters.
+	fn do_accept_channel_checks(&mut self, msg: &msgs::AcceptChannel, default_limits: &ChannelHandshakeLimits, their_features: &InitFeatures) -> Result<(), ChannelError> {
+		let peer_limits = if let Some(ref limits) = self.inbound_handshake_limits_override { limits } else { default_limits };
+
+		// Check sanity of message fields:
+		if !self.is_outbound() {
+			return Err(ChannelError::Close("Got an accept_channel message from an inbound peer".to_owned()));
+		}
+		if !matches!(self.channel_state, ChannelState::NegotiatingFunding(flags) if flags == NegotiatingFundingFlags::OUR_INIT_SENT) {
+			return Err(ChannelError::Close("Got an accept_channel message at a strange time".to_owned()));
+		}
+		if msg.dust_limit_satoshis > 21000000 * 100000000 {
+			return Err(ChannelError::Close(format!("Peer never wants payout outputs? dust_limit_satoshis was {}", msg.dust_limit_satoshis)));
+		}
+		if msg.channel_reserve_satoshis > self.channel_value_satoshis {
+			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must not be greater than ({})", msg.channel_reserve_satoshis, self.channel_value_satoshis)));
+		}
+		if msg.dust_limit_satoshis > self.holder_selected_channel_reserve_satoshis {
+			return Err(ChannelError::Close(format!("Dust limit ({}) is bigger than our channel reserve ({})", msg.dust_limit_satoshis, self.holder_selected_channel_reserve_satoshis)));
+		}
+		if msg.channel_reserve_satoshis > self.channel_value_satoshis - self.holder_selected_channel_reserve_satoshis {
+			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must not be greater than channel value minus our reserve ({})",
+				msg.channel_reserve_satoshis, self.channel_value_satoshis - self.holder_selected_channel_reserve_satoshis)));
+		}
+		let full_channel_value_msat = (self.channel_value_satoshis - msg.channel_reserve_satoshis) * 1000;
+		if msg.htlc_minimum_msat >= full_channel_value_msat {
+			return Err(ChannelError::Close(format!("Minimum htlc value ({}) is full channel value ({})", msg.htlc_minimum_msat, full_channel_value_msat)));
+		}
+		let max_delay_acceptable = u16::min(peer_limits.their_to_self_delay, MAX_LOCAL_BREAKDOWN_TIMEOUT);
+		if msg.to_self_delay > max_delay_acceptable {
+			return Err(ChannelError::Close(format!("They wanted our payments to be delayed by a needlessly long period. Upper limit: {}. Actual: {}", max_delay_acceptable, msg.to_self_delay)));
+		}
+		if msg.max_accepted_htlcs < 1 {
+			return Err(ChannelError::Close("0 max_accepted_htlcs makes for a useless channel".to_owned()));
+		}
+		let max_accepted_htlcs_limit = max_htlcs(&self.channel_type);
+		if msg.max_accepted_htlcs > max_accepted_htlcs_limit {
+			return Err(ChannelError::Close(format!("max_accepted_htlcs was {}. It must not be larger than {}", msg.max_accepted_htlcs, max_accepted_htlcs_limit)));
+		}
+
+		// Now check against optional parameters as set by config...
+		if msg.htlc_minimum_msat > peer_limits.max_htlc_minimum_msat {
+			return Err(ChannelError::Close(format!("htlc_minimum_msat ({}) is higher than the user specified limit ({})", msg.htlc_minimum_msat, peer_limits.max_htlc_minimum_msat)));
+		}
+		if msg.max_htlc_value_in_flight_msat < peer_limits.min_max_htlc_value_in_flight_msat {
+			return Err(ChannelError::Close(format!("max_htlc_value_in_flight_msat ({}) is less than the user specified limit ({})", msg.max_htlc_value_in_flight_msat, peer_limits.min_max_htlc_value_in_flight_msat)));
+		}
+		if msg.channel_reserve_satoshis > peer_limits.max_channel_reserve_satoshis {
+			return Err(ChannelError::Close(format!("channel_reserve_satoshis ({}) is higher than the user specified limit ({})", msg.channel_reserve_satoshis, peer_limits.max_channel_reserve_satoshis)));
+		}
+		if msg.max_accepted_htlcs < peer_limits.min_max_accepted_htlcs {
+			return Err(ChannelError::Close(format!("max_accepted_htlcs ({}) is less than the user specified limit ({})", msg.max_accepted_htlcs, peer_limits.min_max_accepted_htlcs)));
+		}
+		if msg.dust_limit_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
+			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is less than the implementation limit ({})", msg.dust_limit_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS)));
+		}
+		if msg.dust_limit_satoshis > MAX_CHAN_DUST_LIMIT_SATOSHIS {
+			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is greater than the implementation limit ({})", msg.dust_limit_satoshis, MAX_CHAN_DUST_LIMIT_SATOSHIS)));
+		}
+		if msg.minimum_depth > peer_limits.max_minimum_depth {
+			return Err(ChannelError::Close(format!("We consider the minimum depth to be unreasonably large. Expected minimum: ({}). Actual: ({})", peer_limits.max_minimum_depth, msg.minimum_depth)));
+		}
+
+		if let Some(ty) = &msg.channel_type {
+			if *ty != self.channel_type {
+				return Err(ChannelError::Close("Channel Type in accept_channel didn't match the one sent in open_channel.".to_owned()));
+			}
+		} else if their_features.supports_channel_type() {
+			// Assume they've accepted the channel type as they said they understand it.
+		} else {
+			let channel_type = ChannelTypeFeatures::from_init(&their_features);
+			if channel_type != ChannelTypeFeatures::only_static_remote_key() {
+				return Err(ChannelError::Close("Only static_remote_key is supported for non-negotiated channel types".to_owned()));
+			}
+			self.channel_type = channel_type.clone();
+			self.channel_transaction_parameters.channel_type_features = channel_type;
+		}
+
+		let counterparty_shutdown_scriptpubkey = if their_features.supports_upfront_shutdown_script() {
+			match &msg.shutdown_scriptpubkey {
+				&Some(ref script) => {
+					// Peer is signaling upfront_shutdown and has opt-out with a 0-length script. We don't enforce anything
+					if script.len() == 0 {
+						None
+					} else {
+						if !script::is_bolt2_compliant(&script, their_features) {
+							return Err(ChannelError::Close(format!("Peer is signaling upfront_shutdown but has provided an unacceptable scriptpubkey format: {}", script)));
+						}
+						Some(script.clone())
+					}
+				},
+				// Peer is signaling upfront shutdown but don't opt-out with correct mechanism (a.k.a 0-length script). Peer looks buggy, we fail the channel
+				&None => {
+					return Err(ChannelError::Close("Peer is signaling upfront_shutdown but we don't get any script. Use 0-length script to opt-out".to_owned()));
+				}
+			}
+		} else { None };
+
+		self.counterparty_dust_limit_satoshis = msg.dust_limit_satoshis;
+		self.counterparty_max_htlc_value_in_flight_msat = cmp::min(msg.max_htlc_value_in_flight_msat, self.channel_value_satoshis * 1000);
+		self.counterparty_selected_channel_reserve_satoshis = Some(msg.channel_reserve_satoshis);
+		self.counterparty_htlc_minimum_msat = msg.htlc_minimum_msat;
+		self.counterparty_max_accepted_htlcs = msg.max_accepted_htlcs;
+
+		if peer_limits.trust_own_funding_0conf {
+			self.minimum_depth = Some(msg.minimum_depth);
+		} else {
+			self.minimum_depth = Some(cmp::max(1, msg.minimum_depth));
+		}
+
+		let counterparty_pubkeys = ChannelPublicKeys {
+			funding_pubkey: msg.funding_pubkey,
+			revocation_basepoint: RevocationBasepoint::from(msg.revocation_basepoint),
+			payment_point: msg.payment_point,
+			delayed_payment_basepoint: DelayedPaymentBasepoint::from(msg.delayed_payment_basepoint),
+			htlc_basepoint: HtlcBasepoint::from(msg.htlc_basepoint)
+		};
+
+		self.channel_transaction_parameters.counterparty_parameters = Some(CounterpartyChannelTransactionParameters {
+			selected_contest_delay: msg.to_self_delay,
+			pubkeys: counterparty_pubkeys,
+		});
+
+		self.counterparty_cur_commitment_point = Some(msg.first_per_commitment_point);
+		self.counterparty_shutdown_scriptpubkey = counterparty_shutdown_scriptpubkey;
+
+		self.channel_state = ChannelState::NegotiatingFunding(
+			NegotiatingFundingFlags::OUR_INIT_SENT | NegotiatingFundingFlags::THEIR_INIT_SENT
+		);
+		self.inbound_handshake_limits_override = None; // We're done enforcing limits on our peer's handshake now.
+
+		Ok(())
+	}
+
+	/// Sets both peers' channel reserves to the value required for dual-funded channels, given
+	/// the current channel value and both peers' dust limits.
+	#[cfg(dual_funding)]
+	fn set_v2_channel_reserves(&mut self) {
+		let dust_limit_satoshis = cmp::max(self.holder_dust_limit_satoshis, self.counterparty_dust_limit_satoshis);
+		let channel_reserve_satoshis = get_v2_channel_reserve_satoshis(self.channel_value_satoshis, dust_limit_satoshis);
+		self.holder_selected_channel_reserve_satoshis = channel_reserve_satoshis;
+		self.counterparty_selected_channel_reserve_satoshis = Some(channel_reserve_satoshis);
+	}
+
+	/// Signs our counterparty's initial commitment transaction for a dual-funded channel, whose
+	/// funding outpoint must have been set.
+	#[cfg(dual_funding)]
+	fn get_initial_counterparty_commitment_signature<L: Deref>(&self, logger: &L) -> Result<Signature, ChannelError> where L::Target: Logger {
+		let counterparty_keys = self.build_remote_transaction_keys();
+		let counterparty_initial_commitment_tx = self.build_commitment_transaction(self.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, false, logger).tx;
+		match &self.holder_signer {
+			// TODO (taproot|arik): move match into calling method for Taproot
+			ChannelSignerType::Ecdsa(ecdsa) => {
+				ecdsa.sign_counterparty_commitment(&counterparty_initial_commitment_tx, Vec::new(), Vec::new(), &self.secp_ctx)
+					.map(|(signature, _)| signature)
+					.map_err(|_| ChannelError::Close("Failed to get signature for the counterparty's initial commitment transaction".to_owned()))
+			},
+			// TODO (taproot|arik)
+			#[cfg(taproot)]
+			_ => todo!()
+		}
+	}
+
+	/// Handles the initial `commitment_signed` for a dual-funded channel, the V2 equivalent of
+	/// `funding_signed`, returning the `ChannelMonitor` for the channel.
+	///
+	/// The funding transaction may not be broadcast until `tx_signatures` have been exchanged.
+	#[cfg(dual_funding)]
+	fn initial_commitment_signed_v2<L: Deref>(
+		&mut self, msg: &msgs::CommitmentSigned, best_block: BestBlock, signer_provider: &SP, logger: &L
+	) -> Result<ChannelMonitor<<SP::Target as SignerProvider>::EcdsaSigner>, ChannelError>
+	where
+		L::Target: Logger
+	{
+		if !matches!(self.channel_state, ChannelState::FundingNegotiated) {
+			return Err(ChannelError::Close("Received initial commitment_signed before funding transaction constructed!".to_owned()));
+		}
+		if self.commitment_secrets.get_min_seen_secret() != (1 << 48) ||
+				self.cur_counterparty_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER ||
+				self.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
+			panic!("Should not have advanced channel commitment tx numbers prior to initial commitment_signed");
+		}
+		if !msg.htlc_signatures.is_empty() {
+			return Err(ChannelError::Close("Initial commitment_signed must not contain HTLC signatures".to_owned()));
+		}
+
+		let funding_script = self.get_funding_redeemscript();
+
+		let counterparty_keys = self.build_remote_transaction_keys();
+		let counterparty_initial_commitment_tx = self.build_commitment_transaction(self.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, false, logger).tx;
+		let counterparty_trusted_tx = counterparty_initial_commitment_tx.trust();
+		let counterparty_initial_bitcoin_tx = counterparty_trusted_tx.built_transaction();
+
+		log_trace!(logger, "Initial counterparty tx for channel {} is: txid {} tx {}",
+			&self.channel_id(), counterparty_initial_bitcoin_tx.txid, encode::serialize_hex(&counterparty_initial_bitcoin_tx.transaction));
+
+		let holder_keys = self.build_holder_transaction_keys(self.cur_holder_commitment_transaction_number);
+		let initial_commitment_tx = self.build_commitment_transaction(self.cur_holder_commitment_transaction_number, &holder_keys, true, false, logger).tx;
+		{
+			let trusted_tx = initial_commitment_tx.trust();
+			let initial_commitment_bitcoin_tx = trusted_tx.built_transaction();
+			let sighash = initial_commitment_bitcoin_tx.get_sighash_all(&funding_script, self.channel_value_satoshis);
+			// They sign our commitment transaction, allowing us to broadcast the tx if we wish.
+			if let Err(_) = self.secp_ctx.verify_ecdsa(&sighash, &msg.signature, &self.get_counterparty_pubkeys().funding_pubkey) {
+				return Err(ChannelError::Close("Invalid initial commitment_signed signature from peer".to_owned()));
+			}
+		}
+
+		let holder_commitment_tx = HolderCommitmentTransaction::new(
+			initial_commitment_tx,
+			msg.signature,
+			Vec::new(),
+			&self.get_holder_pubkeys().funding_pubkey,
+			self.counterparty_funding_pubkey()
+		);
+
+		if let Err(_) = self.holder_signer.as_ref().validate_holder_commitment(&holder_commitment_tx, Vec::new()) {
+			return Err(ChannelError::Close("Failed to validate our commitment".to_owned()));
+		}
+
+		let funding_redeemscript = self.get_funding_redeemscript();
+		let funding_txo = self.get_funding_txo().unwrap();
+		let funding_txo_script = funding_redeemscript.to_v0_p2wsh();
+		let obscure_factor = get_commitment_transaction_number_obscure_factor(&self.get_holder_pubkeys().payment_point, &self.get_counterparty_pubkeys().payment_point, self.is_outbound());
+		let shutdown_script = self.shutdown_scriptpubkey.clone().map(|script| script.into_inner());
+		let mut monitor_signer = signer_provider.derive_channel_signer(self.channel_value_satoshis, self.channel_keys_id);
+		monitor_signer.provide_channel_parameters(&self.channel_transaction_parameters);
+		let channel_monitor = ChannelMonitor::new(self.secp_ctx.clone(), monitor_signer,
+		                                          shutdown_script, self.get_holder_selected_contest_delay(),
+		                                          &self.destination_script, (funding_txo, funding_txo_script),
+		                                          &self.channel_transaction_parameters,
+		                                          funding_redeemscript.clone(), self.channel_value_satoshis,
+		                                          obscure_factor,
+		                                          holder_commitment_tx, best_block, self.counterparty_node_id);
+		channel_monitor.provide_initial_counterparty_commitment_tx(
+			counterparty_initial_bitcoin_tx.txid, Vec::new(),
+			self.cur_counterparty_commitment_transaction_number,
+			self.counterparty_cur_commitment_point.unwrap(),
+			counterparty_initial_commitment_tx.feerate_per_kw(),
+			counterparty_initial_commitment_tx.to_broadcaster_value_sat(),
+			counterparty_initial_commitment_tx.to_countersignatory_value_sat(), logger);
+
+		assert!(!self.channel_state.is_monitor_update_in_progress()); // We have no had any monitor(s) yet to fail update!
+		self.channel_state = ChannelState::AwaitingChannelReady(AwaitingChannelReadyFlags::WAITING_FOR_TX_SIGNATURES);
+		self.cur_holder_commitment_transaction_number -= 1;
+		self.cur_counterparty_commitment_transaction_number -= 1;
+
+		log_info!(logger, "Received initial commitment_signed from peer for channel {}", &self.channel_id());
+
+		Ok(channel_monitor)
+	}
+
 	/// Returns true if funding_signed was sent/received and the
 	/// funding transaction has been broadcast if necessary.
 	pub fn is_funding_broadcast(&self) -> bool {
 		!self.channel_state.is_pre_funded_state() &&
-			!matches!(self.channel_state, ChannelState::AwaitingChannelReady(flags) if flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_BATCH))
+			!matches!(self.channel_state, ChannelState::AwaitingChannelReady(flags) if flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_BATCH)) &&
+			!matches!(self.channel_state, ChannelState::AwaitingChannelReady(flags) if flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_TX_SIGNATURES))
+	}
+
+	/// Returns true if the `ChannelManager` should persist this channel, i.e. once its funding
+	/// transaction has been broadcast or, for a dual-funded channel, while we're still exchanging
+	/// `tx_signatures` for it, as that exchange has to be resumed after a restart.
+	pub fn is_persistable(&self) -> bool {
+		self.is_funding_broadcast() || self.channel_state.is_waiting_for_tx_signatures()
 	}
 
 	/// Transaction nomenclature is somewhat confusing here as there are many different cases - a
@@ -1753,7 +2199,7 @@ impl<SP: Deref> ChannelContext<SP> where SP::Target: SignerProvider  {
 		}
 
 		let total_fee_sat = commit_tx_fee_sat(feerate_per_kw, included_non_dust_htlcs.len(), &self.channel_transaction_parameters.channel_type_features);
-		let anchors_val = if self.channel_transaction_parameters.channel_type_features.supports_anchors_zero_fee_htlc_tx() { ANCHOR_OUTPUT_VALUE_SATOSHI * 2 } else { 0 } as i64;
+		let anchors_val = anchor_outputs_value_sat(&self.channel_transaction_parameters.channel_type_features) as i64;
 		let (value_to_self, value_to_remote) = if self.is_outbound() {
 			(value_to_self_msat / 1000 - anchors_val - total_fee_sat as i64, value_to_remote_msat / 1000)
 		} else {
@@ -1785,9 +2231,13 @@ impl<SP: Deref> ChannelContext<SP> where SP::Target: SignerProvider  {
 		let channel_parameters =
 			if local { self.channel_transaction_parameters.as_holder_broadcastable() }
 			else { self.channel_transaction_parameters.as_counterparty_broadcastable() };
+		let ephemeral_anchor_value_sat = chan_utils::ephemeral_anchor_value_sat(
+			self.channel_value_satoshis, value_to_a as u64, value_to_b as u64,
+			included_non_dust_htlcs.iter().map(|(htlc, _)| htlc));
 		let tx = CommitmentTransaction::new_with_auxiliary_htlc_data(commitment_number,
 		                                                             value_to_a as u64,
 		                                                             value_to_b as u64,
+		                                                             ephemeral_anchor_value_sat,
 		                                                             funding_pubkey_a,
 		                                                             funding_pubkey_b,
 		                                                             keys.clone(),
@@ -1966,6 +2416,18 @@ impl<SP: Deref> ChannelContext<SP> where SP::Target: SignerProvider  {
 		stats
 	}
 
+	/// Gets the amount that would go to us if we closed the channel now, ignoring any on-chain
+	/// fees. See [`AvailableBalances::balance_msat`].
+	pub fn get_balance_msat(&self) -> u64 {
+		let mut balance_msat = self.value_to_self_msat;
+		for ref htlc in self.pending_inbound_htlcs.iter() {
+			if let InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::Fulfill(_)) = htlc.state {
+				balance_msat += htlc.amount_msat;
+			}
+		}
+		balance_msat - self.get_outbound_pending_htlc_stats(None).pending_htlcs_value_msat
+	}
+
 	/// Get the available balances, see [`AvailableBalances`]'s fields for more info.
 	/// Doesn't bother handling the
 	/// if-we-removed-it-already-but-haven't-fully-resolved-they-can-still-send-an-inbound-HTLC
@@ -1979,13 +2441,7 @@ impl<SP: Deref> ChannelContext<SP> where SP::Target: SignerProvider  {
 		let inbound_stats = context.get_inbound_pending_htlc_stats(None);
 		let outbound_stats = context.get_outbound_pending_htlc_stats(None);
 
-		let mut balance_msat = context.value_to_self_msat;
-		for ref htlc in context.pending_inbound_htlcs.iter() {
-			if let InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::Fulfill(_)) = htlc.state {
-				balance_msat += htlc.amount_msat;
-			}
-		}
-		balance_msat -= outbound_stats.pending_htlcs_value_msat;
+		let balance_msat = context.get_balance_msat();
 
 		let outbound_capacity_msat = context.value_to_self_msat
 				.saturating_sub(outbound_stats.pending_htlcs_value_msat)
@@ -1994,11 +2450,7 @@ impl<SP: Deref> ChannelContext<SP> where SP::Target: SignerProvider  {
 
 		let mut available_capacity_msat = outbound_capacity_msat;
 
-		let anchor_outputs_value_msat = if context.get_channel_type().supports_anchors_zero_fee_htlc_tx() {
-			ANCHOR_OUTPUT_VALUE_SATOSHI * 2 * 1000
-		} else {
-			0
-		};
+		let anchor_outputs_value_msat = anchor_outputs_value_sat(context.get_channel_type()) * 1000;
 		if context.is_outbound() {
 			// We should mind channel commit tx fee when computing how much of the available capacity
 			// can be used in the next htlc. Mirrors the logic in send_htlc.
@@ -2320,7 +2772,9 @@ impl<SP: Deref> ChannelContext<SP> where SP::Target: SignerProvider  {
 		where F: Fn() -> Option<O> {
 		match self.channel_state {
 			ChannelState::FundingNegotiated => f(),
-			ChannelState::AwaitingChannelReady(flags) => if flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_BATCH) {
+			ChannelState::AwaitingChannelReady(flags) => if flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_BATCH) ||
+				flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_TX_SIGNATURES)
+			{
 				f()
 			} else {
 				None
@@ -2499,6 +2953,13 @@ pub(crate) fn get_legacy_default_holder_selected_channel_reserve_satoshis(channe
 	cmp::min(channel_value_satoshis, cmp::max(q, 1000))
 }
 
+/// Returns the channel reserve each peer must keep in a dual-funded channel: 1% of the total
+/// channel value, but no less than the given dust limit.
+#[cfg(dual_funding)]
+pub(crate) fn get_v2_channel_reserve_satoshis(channel_value_satoshis: u64, dust_limit_satoshis: u64) -> u64 {
+	cmp::min(channel_value_satoshis, cmp::max(channel_value_satoshis / 100, dust_limit_satoshis))
+}
+
 // Get the fee cost in SATS of a commitment tx with a given number of HTLC outputs.
 // Note that num_htlcs should not include dust HTLCs.
 #[inline]
@@ -2518,6 +2979,13 @@ pub(crate) fn commit_tx_fee_msat(feerate_per_kw: u32, num_htlcs: usize, channel_
 // Counterparty designates channel data owned by the another channel participant entity.
 pub(super) struct Channel<SP: Deref> where SP::Target: SignerProvider {
 	pub context: ChannelContext<SP>,
+	/// Info about an in-progress upgrade of the channel's type, if any.
+	pending_channel_type_upgrade: Option<PendingChannelTypeUpgrade>,
+	/// The state of the `tx_signatures` exchange for a dual-funded channel's funding transaction.
+	/// Kept until our counterparty's `channel_ready` so that our `tx_signatures` can be
+	/// retransmitted on reestablish.
+	#[cfg(dual_funding)]
+	pub interactive_tx_signing_session: Option<InteractiveTxSigningSession>,
 }
 
 #[cfg(any(test, fuzzing))]
@@ -2596,6 +3064,12 @@ impl<SP: Deref> Channel<SP> where
 		feerate_per_kw: u32, cur_feerate_per_kw: Option<u32>, logger: &L
 	) -> Result<(), ChannelError> where F::Target: FeeEstimator, L::Target: Logger,
 	{
+		if channel_type.supports_zero_fee_commitments() {
+			if feerate_per_kw != 0 {
+				return Err(ChannelError::Close(format!("Peer's feerate must be 0 for zero-fee commitments. Actual: {}", feerate_per_kw)));
+			}
+			return Ok(());
+		}
 		let lower_limit_conf_target = if channel_type.supports_anchors_zero_fee_htlc_tx() {
 			ConfirmationTarget::MinAllowedAnchorChannelRemoteFee
 		} else {
@@ -3045,23 +3519,32 @@ impl<SP: Deref> Channel<SP> where
 			ChannelState::AwaitingChannelReady(flags) => {
 				let flags = *flags & !FundedStateFlags::ALL;
 				debug_assert!(!flags.is_set(AwaitingChannelReadyFlags::OUR_CHANNEL_READY) || !flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_BATCH));
-				if flags & !AwaitingChannelReadyFlags::WAITING_FOR_BATCH == AwaitingChannelReadyFlags::THEIR_CHANNEL_READY {
+				let held_flags = AwaitingChannelReadyFlags::WAITING_FOR_BATCH | AwaitingChannelReadyFlags::WAITING_FOR_TX_SIGNATURES;
+				if flags & !held_flags == AwaitingChannelReadyFlags::THEIR_CHANNEL_READY {
 					// If we reconnected before sending our `channel_ready` they may still resend theirs.
 					check_reconnection = true;
-				} else if (flags & !AwaitingChannelReadyFlags::WAITING_FOR_BATCH).is_empty() {
+				} else if (flags & !held_flags).is_empty() {
 					self.context.channel_state.set_their_channel_ready();
 				} else if flags == AwaitingChannelReadyFlags::OUR_CHANNEL_READY {
 					self.context.channel_state = ChannelState::ChannelReady(self.context.channel_state.with_funded_state_flags_mask().into());
 					self.context.update_time_counter += 1;
 				} else {
-					// We're in `WAITING_FOR_BATCH`, so we should wait until we're ready.
-					debug_assert!(flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_BATCH));
+					// We're in `WAITING_FOR_BATCH` or `WAITING_FOR_TX_SIGNATURES`, so we should wait
+					// until we're ready.
+					debug_assert!(flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_BATCH) ||
+						flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_TX_SIGNATURES));
 				}
 			}
 			// If we reconnected before sending our `channel_ready` they may still resend theirs.
 			ChannelState::ChannelReady(_) => check_reconnection = true,
 			_ => return Err(ChannelError::Close("Peer sent a channel_ready at a strange time".to_owned())),
 		}
+		// Our counterparty only sends `channel_ready` once they have our `tx_signatures`, so we no
+		// longer need to be able to retransmit them.
+		#[cfg(dual_funding)]
+		if !self.context.channel_state.is_waiting_for_tx_signatures() {
+			self.interactive_tx_signing_session = None;
+		}
 		if check_reconnection {
 			// They probably disconnected/reconnected and re-sent the channel_ready, which is
 			// required, or they're sending a fresh SCID alias.
@@ -3117,6 +3600,9 @@ impl<SP: Deref> Channel<SP> where
 		if self.context.channel_state.is_peer_disconnected() {
 			return Err(ChannelError::Close("Peer sent update_add_htlc when we needed a channel_reestablish".to_owned()));
 		}
+		if self.context.channel_state.is_remote_stfu_sent() || self.context.channel_state.is_quiescent() {
+			return Err(ChannelError::Close("Peer sent update_add_htlc after sending stfu".to_owned()));
+		}
 		if msg.amount_msat > self.context.channel_value_satoshis * 1000 {
 			return Err(ChannelError::Close("Remote side tried to send more than the total value of the channel".to_owned()));
 		}
@@ -3200,8 +3686,8 @@ impl<SP: Deref> Channel<SP> where
 				let htlc_candidate = HTLCCandidate::new(msg.amount_msat, HTLCInitiator::RemoteOffered);
 				self.context.next_remote_commit_tx_fee_msat(htlc_candidate, None) // Don't include the extra fee spike buffer HTLC in calculations
 			};
-			let anchor_outputs_value_msat = if !self.context.is_outbound() && self.context.get_channel_type().supports_anchors_zero_fee_htlc_tx() {
-				ANCHOR_OUTPUT_VALUE_SATOSHI * 2 * 1000
+			let anchor_outputs_value_msat = if !self.context.is_outbound() {
+				anchor_outputs_value_sat(self.context.get_channel_type()) * 1000
 			} else {
 				0
 			};
@@ -3213,11 +3699,7 @@ impl<SP: Deref> Channel<SP> where
 			}
 		}
 
-		let anchor_outputs_value_msat = if self.context.get_channel_type().supports_anchors_zero_fee_htlc_tx() {
-			ANCHOR_OUTPUT_VALUE_SATOSHI * 2 * 1000
-		} else {
-			0
-		};
+		let anchor_outputs_value_msat = anchor_outputs_value_sat(self.context.get_channel_type()) * 1000;
 		if !self.context.is_outbound() {
 			// `Some(())` is for the fee spike buffer we keep for the remote. This deviates from
 			// the spec because the fee spike buffer requirement doesn't exist on the receiver's
@@ -3305,6 +3787,9 @@ impl<SP: Deref> Channel<SP> where
 		if self.context.channel_state.is_peer_disconnected() {
 			return Err(ChannelError::Close("Peer sent update_fulfill_htlc when we needed a channel_reestablish".to_owned()));
 		}
+		if self.context.channel_state.is_remote_stfu_sent() || self.context.channel_state.is_quiescent() {
+			return Err(ChannelError::Close("Peer sent update_fulfill_htlc after sending stfu".to_owned()));
+		}
 
 		self.mark_outbound_htlc_removed(msg.htlc_id, Some(msg.payment_preimage), None).map(|htlc| (htlc.source.clone(), htlc.amount_msat))
 	}
@@ -3316,6 +3801,9 @@ impl<SP: Deref> Channel<SP> where
 		if self.context.channel_state.is_peer_disconnected() {
 			return Err(ChannelError::Close("Peer sent update_fail_htlc when we needed a channel_reestablish".to_owned()));
 		}
+		if self.context.channel_state.is_remote_stfu_sent() || self.context.channel_state.is_quiescent() {
+			return Err(ChannelError::Close("Peer sent update_fail_htlc after sending stfu".to_owned()));
+		}
 
 		self.mark_outbound_htlc_removed(msg.htlc_id, None, Some(fail_reason))?;
 		Ok(())
@@ -3328,6 +3816,9 @@ impl<SP: Deref> Channel<SP> where
 		if self.context.channel_state.is_peer_disconnected() {
 			return Err(ChannelError::Close("Peer sent update_fail_malformed_htlc when we needed a channel_reestablish".to_owned()));
 		}
+		if self.context.channel_state.is_remote_stfu_sent() || self.context.channel_state.is_quiescent() {
+			return Err(ChannelError::Close("Peer sent update_fail_malformed_htlc after sending stfu".to_owned()));
+		}
 
 		self.mark_outbound_htlc_removed(msg.htlc_id, None, Some(fail_reason))?;
 		Ok(())
@@ -3500,16 +3991,30 @@ impl<SP: Deref> Channel<SP> where
 			}
 		}
 
+		if let Some(upgrade) = self.pending_channel_type_upgrade.as_mut() {
+			if let ChannelTypeUpgradeState::Committing { commitment_signed_sent, commitment_signed_received } = &mut upgrade.state {
+				*commitment_signed_received = true;
+				// Both sides have to sign a commitment transaction using the new channel type, even
+				// if nothing else changed.
+				if !*commitment_signed_sent {
+					need_commitment = true;
+				}
+			}
+		}
+
+		let mut updates = Vec::new();
+		updates.extend(self.take_channel_type_upgrade_monitor_step());
+		updates.push(ChannelMonitorUpdateStep::LatestHolderCommitmentTXInfo {
+			commitment_tx: holder_commitment_tx,
+			htlc_outputs: htlcs_and_sigs,
+			claimed_htlcs,
+			nondust_htlc_sources,
+		});
 		self.context.latest_monitor_update_id += 1;
 		let mut monitor_update = ChannelMonitorUpdate {
 			update_id: self.context.latest_monitor_update_id,
 			counterparty_node_id: Some(self.context.counterparty_node_id),
-			updates: vec![ChannelMonitorUpdateStep::LatestHolderCommitmentTXInfo {
-				commitment_tx: holder_commitment_tx,
-				htlc_outputs: htlcs_and_sigs,
-				claimed_htlcs,
-				nondust_htlc_sources,
-			}]
+			updates,
 		};
 
 		self.context.cur_holder_commitment_transaction_number -= 1;
@@ -3602,11 +4107,11 @@ impl<SP: Deref> Channel<SP> where
 				match &htlc_update {
 					&HTLCUpdateAwaitingACK::AddHTLC {
 						amount_msat, cltv_expiry, ref payment_hash, ref source, ref onion_routing_packet,
-						skimmed_fee_msat, blinding_point, ..
+						skimmed_fee_msat, blinding_point, hold_htlc, endorsed,
 					} => {
 						match self.send_htlc(
 							amount_msat, *payment_hash, cltv_expiry, source.clone(), onion_routing_packet.clone(),
-							false, skimmed_fee_msat, blinding_point, fee_estimator, logger
+							false, skimmed_fee_msat, blinding_point, hold_htlc, endorsed, fee_estimator, logger
 						) {
 							Ok(_) => update_add_count += 1,
 							Err(e) => {
@@ -3776,7 +4281,12 @@ impl<SP: Deref> Channel<SP> where
 		// OK, we step the channel here and *then* if the new generation fails we can fail the
 		// channel based on that, but stepping stuff here should be safe either way.
 		self.context.channel_state.clear_awaiting_remote_revoke();
-		self.context.sent_message_awaiting_response = None;
+		if self.context.channel_state.is_local_stfu_sent() {
+			// We're still waiting on their `stfu` in response to ours.
+			self.mark_awaiting_response();
+		} else {
+			self.context.sent_message_awaiting_response = None;
+		}
 		self.context.counterparty_prev_commitment_point = self.context.counterparty_cur_commitment_point;
 		self.context.counterparty_cur_commitment_point = Some(msg.next_per_commitment_point);
 		self.context.cur_counterparty_commitment_transaction_number -= 1;
@@ -3939,7 +4449,7 @@ impl<SP: Deref> Channel<SP> where
 			return_with_htlcs_to_fail!(Vec::new());
 		}
 
-		match self.free_holding_cell_htlcs(fee_estimator, logger) {
+		match self.maybe_free_holding_cell_htlcs(fee_estimator, logger) {
 			(Some(mut additional_update), htlcs_to_fail) => {
 				// free_holding_cell_htlcs may bump latest_monitor_id multiple times but we want them to be
 				// strictly increasing by one, so decrement it here.
@@ -4009,6 +4519,8 @@ impl<SP: Deref> Channel<SP> where
 		if !self.context.is_usable() {
 			panic!("Cannot update fee until channel is fully established and we haven't started shutting down");
 		}
+		debug_assert!(!self.context.channel_type.supports_zero_fee_commitments(),
+			"Zero-fee commitment channels never update their fee");
 		if !self.context.is_live() {
 			panic!("Cannot update fee while peer is disconnected/we're awaiting a monitor update (ChannelManager should have caught this)");
 		}
@@ -4039,7 +4551,7 @@ impl<SP: Deref> Channel<SP> where
 			return None;
 		}
 
-		if self.context.channel_state.is_awaiting_remote_revoke() || self.context.channel_state.is_monitor_update_in_progress() {
+		if self.context.channel_state.should_force_holding_cell() {
 			force_holding_cell = true;
 		}
 
@@ -4131,6 +4643,19 @@ impl<SP: Deref> Channel<SP> where
 
 		self.context.sent_message_awaiting_response = None;
 
+		// Quiescence is implicitly terminated upon disconnection, with any updates held while it was
+		// pending being freed once the channel is reestablished.
+		self.context.channel_state.clear_quiescence_flags();
+		self.context.is_holder_quiescence_initiator = None;
+
+		// A channel type upgrade has to start over after reconnecting unless both sides already
+		// agreed on it, in which case any mismatch is resolved in `channel_reestablish`.
+		if let Some(upgrade) = &self.pending_channel_type_upgrade {
+			if !matches!(upgrade.state, ChannelTypeUpgradeState::Committing { .. }) {
+				self.pending_channel_type_upgrade = None;
+			}
+		}
+
 		self.context.channel_state.set_peer_disconnected();
 		log_trace!(logger, "Peer disconnection resulted in {} remote-announced HTLC drops on channel {}", inbound_drop_count, &self.context.channel_id());
 		Ok(())
@@ -4180,7 +4705,8 @@ impl<SP: Deref> Channel<SP> where
 		// first received the funding_signed.
 		let mut funding_broadcastable =
 			if self.context.is_outbound() &&
-				matches!(self.context.channel_state, ChannelState::AwaitingChannelReady(flags) if !flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_BATCH)) ||
+				matches!(self.context.channel_state, ChannelState::AwaitingChannelReady(flags) if !flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_BATCH) &&
+					!flags.is_set(AwaitingChannelReadyFlags::WAITING_FOR_TX_SIGNATURES)) ||
 				matches!(self.context.channel_state, ChannelState::ChannelReady(_))
 			{
 				self.context.funding_transaction.take()
@@ -4223,10 +4749,21 @@ impl<SP: Deref> Channel<SP> where
 			self.context.monitor_pending_commitment_signed = false;
 			return MonitorRestoreUpdates {
 				raa: None, commitment_update: None, order: RAACommitmentOrder::RevokeAndACKFirst,
-				accepted_htlcs, failed_htlcs, finalized_claimed_htlcs, funding_broadcastable, channel_ready, announcement_sigs
+				accepted_htlcs, failed_htlcs, finalized_claimed_htlcs, funding_broadcastable, channel_ready, announcement_sigs,
+				#[cfg(dual_funding)]
+				tx_signatures: None,
 			};
 		}
 
+		// Now that the initial monitor for a dual-funded channel is persisted we may send our
+		// `tx_signatures`, possibly completing the exchange.
+		#[cfg(dual_funding)]
+		let tx_signatures = self.maybe_get_tx_signatures();
+		#[cfg(dual_funding)]
+		if let Some(funding_tx) = self.maybe_finalize_funding_tx(logger) {
+			funding_broadcastable = Some(funding_tx);
+		}
+
 		let raa = if self.context.monitor_pending_revoke_and_ack {
 			Some(self.get_last_revoke_and_ack())
 		} else { None };
@@ -4245,7 +4782,9 @@ impl<SP: Deref> Channel<SP> where
 			if commitment_update.is_some() { "a" } else { "no" }, if raa.is_some() { "an" } else { "no" },
 			match order { RAACommitmentOrder::CommitmentFirst => "commitment", RAACommitmentOrder::RevokeAndACKFirst => "RAA"});
 		MonitorRestoreUpdates {
-			raa, commitment_update, order, accepted_htlcs, failed_htlcs, finalized_claimed_htlcs, funding_broadcastable, channel_ready, announcement_sigs
+			raa, commitment_update, order, accepted_htlcs, failed_htlcs, finalized_claimed_htlcs, funding_broadcastable, channel_ready, announcement_sigs,
+			#[cfg(dual_funding)]
+			tx_signatures,
 		}
 	}
 
@@ -4258,6 +4797,12 @@ impl<SP: Deref> Channel<SP> where
 		if self.context.channel_state.is_peer_disconnected() {
 			return Err(ChannelError::Close("Peer sent update_fee when we needed a channel_reestablish".to_owned()));
 		}
+		if self.context.channel_state.is_remote_stfu_sent() || self.context.channel_state.is_quiescent() {
+			return Err(ChannelError::Close("Peer sent update_fee after sending stfu".to_owned()));
+		}
+		if self.context.channel_type.supports_zero_fee_commitments() {
+			return Err(ChannelError::Close("Peer sent update_fee on a zero-fee commitment channel".to_owned()));
+		}
 		Channel::<SP>::check_remote_fee(&self.context.channel_type, fee_estimator, msg.feerate_per_kw, Some(self.context.feerate_per_kw), logger)?;
 
 		self.context.pending_update_fee = Some((msg.feerate_per_kw, FeeUpdateState::RemoteAnnounced));
@@ -4337,6 +4882,8 @@ impl<SP: Deref> Channel<SP> where
 					onion_routing_packet: (**onion_packet).clone(),
 					skimmed_fee_msat: htlc.skimmed_fee_msat,
 					blinding_point: htlc.blinding_point,
+					hold_htlc: htlc.hold_htlc,
+					endorsed: htlc.endorsed,
 				});
 			}
 		}
@@ -4442,6 +4989,10 @@ impl<SP: Deref> Channel<SP> where
 			return Err(ChannelError::Close("Peer sent an invalid channel_reestablish to force close in a non-standard way".to_owned()));
 		}
 
+		if let Some(their_channel_type) = &msg.channel_type {
+			self.reestablish_channel_type(their_channel_type, logger)?;
+		}
+
 		let our_commitment_transaction = INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number - 1;
 		if msg.next_remote_commitment_number > 0 {
 			let expected_point = self.context.holder_signer.as_ref().get_per_commitment_point(INITIAL_COMMITMENT_NUMBER - msg.next_remote_commitment_number + 1, &self.context.secp_ctx);
@@ -4760,14 +5311,433 @@ impl<SP: Deref> Channel<SP> where
 		*ticks_elapsed >= DISCONNECT_PEER_AWAITING_RESPONSE_TICKS
 	}
 
-	pub fn shutdown(
-		&mut self, signer_provider: &SP, their_features: &InitFeatures, msg: &msgs::Shutdown
-	) -> Result<(Option<msgs::Shutdown>, Option<ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>), ChannelError>
-	{
-		if self.context.channel_state.is_peer_disconnected() {
-			return Err(ChannelError::Close("Peer sent shutdown when we needed a channel_reestablish".to_owned()));
-		}
-		if self.context.channel_state.is_pre_funded_state() {
+	/// Returns true if any HTLC or fee update has been sent by either side and has yet to be
+	/// irrevocably committed by both.
+	///
+	/// Note that this does not consider updates in the holding cell, as they've yet to be sent.
+	fn has_uncommitted_updates(&self) -> bool {
+		self.context.channel_state.is_awaiting_remote_revoke() ||
+			self.context.expecting_peer_commitment_signed ||
+			self.context.pending_update_fee.is_some() ||
+			self.context.pending_inbound_htlcs.iter().any(|htlc| !matches!(htlc.state, InboundHTLCState::Committed)) ||
+			self.context.pending_outbound_htlcs.iter().any(|htlc| !matches!(htlc.state, OutboundHTLCState::Committed))
+	}
+
+	/// Returns true if the channel is quiescent, i.e. both sides have exchanged `stfu` and no
+	/// updates may be made to it until quiescence is terminated.
+	pub fn is_quiescent(&self) -> bool {
+		self.context.channel_state.is_quiescent()
+	}
+
+	/// Starts the process of making the channel quiescent. Any new updates from us will be held in
+	/// the holding cell until quiescence is terminated.
+	///
+	/// Returns an `stfu` message to send to our counterparty if all pending updates have already
+	/// been irrevocably committed, otherwise it will be generated via [`Self::try_send_stfu`] once
+	/// they have.
+	pub fn propose_quiescence<L: Deref>(&mut self, logger: &L) -> Result<Option<msgs::Stfu>, APIError>
+	where L::Target: Logger {
+		if !self.context.is_live() {
+			return Err(APIError::ChannelUnavailable { err: format!(
+				"Channel {} cannot be made quiescent as it is not usable or its peer is disconnected",
+				self.context.channel_id()) });
+		}
+		if self.context.channel_state.is_quiescent() {
+			return Err(APIError::APIMisuseError { err: format!(
+				"Channel {} is already quiescent", self.context.channel_id()) });
+		}
+		if self.context.channel_state.is_awaiting_quiescence() || self.context.channel_state.is_local_stfu_sent() {
+			// We've already started the handshake, there's nothing left for us to do.
+			return Ok(None);
+		}
+
+		log_debug!(logger, "Attempting to make channel {} quiescent", &self.context.channel_id());
+		self.context.channel_state.set_awaiting_quiescence();
+		Ok(self.try_send_stfu(logger))
+	}
+
+	/// Generates our `stfu` if we're either attempting to make the channel quiescent or responding
+	/// to our counterparty's attempt and all pending updates have since been irrevocably
+	/// committed.
+	///
+	/// This should be called any time the channel may have settled, which the `ChannelManager`
+	/// checks each time its pending message events are fetched.
+	pub fn try_send_stfu<L: Deref>(&mut self, logger: &L) -> Option<msgs::Stfu> where L::Target: Logger {
+		// We never have both `stfu` flags set, we move on to quiescent instead.
+		debug_assert!(!(self.context.channel_state.is_local_stfu_sent() && self.context.channel_state.is_remote_stfu_sent()));
+
+		if !self.context.channel_state.is_awaiting_quiescence() && !self.context.channel_state.is_remote_stfu_sent() {
+			return None;
+		}
+		// We also can't send our `stfu` while we're waiting on a monitor update or our signer, as it
+		// must follow any messages we're still due to send.
+		if !self.context.is_live() || self.has_uncommitted_updates() ||
+			self.context.channel_state.is_monitor_update_in_progress() ||
+			self.context.signer_pending_commitment_update
+		{
+			return None;
+		}
+
+		let initiator = if self.context.channel_state.is_remote_stfu_sent() {
+			// Our counterparty already sent their `stfu`, so responding with ours makes the channel
+			// quiescent. We may have also been trying to initiate quiescence ourselves, in which case
+			// the initiator was already resolved when we received theirs.
+			let is_holder_initiator = self.context.is_holder_quiescence_initiator.unwrap_or(false);
+			self.context.channel_state.clear_awaiting_quiescence();
+			self.context.channel_state.clear_remote_stfu_sent();
+			self.context.channel_state.set_quiescent();
+			log_debug!(logger, "Responding to counterparty stfu, channel {} is now quiescent and we are{} the initiator",
+				&self.context.channel_id(), if is_holder_initiator { "" } else { " not" });
+			is_holder_initiator
+		} else {
+			self.context.channel_state.clear_awaiting_quiescence();
+			self.context.channel_state.set_local_stfu_sent();
+			self.mark_awaiting_response();
+			log_debug!(logger, "Sending stfu to initiate quiescence on channel {}", &self.context.channel_id());
+			true
+		};
+
+		Some(msgs::Stfu {
+			channel_id: self.context.channel_id,
+			initiator: initiator as u8,
+		})
+	}
+
+	/// Handles an `stfu` message from our counterparty, returning our own `stfu` in response if
+	/// it can be sent immediately.
+	///
+	/// If both sides sent `stfu` as the initiator, the channel funder is considered the initiator.
+	pub fn stfu<L: Deref>(&mut self, msg: &msgs::Stfu, logger: &L) -> Result<Option<msgs::Stfu>, ChannelError>
+	where L::Target: Logger {
+		if !matches!(self.context.channel_state, ChannelState::ChannelReady(_)) {
+			return Err(ChannelError::Close("Peer sent stfu when the channel was not in an operational state".to_owned()));
+		}
+		if self.context.channel_state.is_peer_disconnected() {
+			return Err(ChannelError::Close("Peer sent stfu when we needed a channel_reestablish".to_owned()));
+		}
+		if !self.context.is_usable() {
+			return Err(ChannelError::Warn("Peer sent stfu while the channel is shutting down".to_owned()));
+		}
+		if self.context.channel_state.is_quiescent() || self.context.channel_state.is_remote_stfu_sent() {
+			return Err(ChannelError::Close("Peer sent stfu after already sending one".to_owned()));
+		}
+
+		let remote_initiator = msg.initiator != 0;
+		if !self.context.channel_state.is_local_stfu_sent() {
+			if !remote_initiator {
+				return Err(ChannelError::Close("Peer sent stfu in response to one we never sent".to_owned()));
+			}
+			// If we were also waiting to send our `stfu` as the initiator, break the tie in favor of
+			// the channel funder.
+			let is_holder_initiator = self.context.channel_state.is_awaiting_quiescence() && self.context.is_outbound();
+			self.context.is_holder_quiescence_initiator = Some(is_holder_initiator);
+			self.context.channel_state.set_remote_stfu_sent();
+			log_debug!(logger, "Received stfu proposing quiescence on channel {}", &self.context.channel_id());
+			return Ok(self.try_send_stfu(logger));
+		}
+
+		// We already sent our `stfu`, so this is either a response to it, or we both sent `stfu` at
+		// the same time as the initiator and have to break the tie in favor of the channel funder.
+		if self.has_uncommitted_updates() {
+			// We only sent `stfu` once all our updates were committed, and they may not send us any
+			// new ones once they've sent theirs, so they must have sent it too early.
+			return Err(ChannelError::Close("Peer sent stfu while updates were still pending".to_owned()));
+		}
+		let is_holder_initiator = !remote_initiator || self.context.is_outbound();
+		self.context.is_holder_quiescence_initiator = Some(is_holder_initiator);
+		self.context.channel_state.clear_local_stfu_sent();
+		self.context.channel_state.set_quiescent();
+		self.context.sent_message_awaiting_response = None;
+		log_debug!(logger, "Received stfu, channel {} is now quiescent and we are{} the initiator",
+			&self.context.channel_id(), if is_holder_initiator { "" } else { " not" });
+		Ok(None)
+	}
+
+	/// Terminates quiescence, allowing updates to flow once again. Any updates held in the holding
+	/// cell in the meantime should be freed via [`Self::maybe_free_holding_cell_htlcs`].
+	///
+	/// This must only be called once our counterparty also considers quiescence terminated, i.e.
+	/// at the end of the protocol which required it. Otherwise, quiescence can only be terminated
+	/// by disconnecting.
+	fn exit_quiescence(&mut self) {
+		if self.context.channel_state.is_quiescent() {
+			self.context.channel_state.clear_quiescent();
+		}
+		self.context.is_holder_quiescence_initiator = None;
+	}
+
+	/// Checks whether this channel may be upgraded to the given `channel_type`.
+	///
+	/// Only upgrading a channel which doesn't support anchor outputs to
+	/// `option_anchors_zero_fee_htlc_tx` is currently supported. As the funder pays for the anchor
+	/// outputs, it must be able to afford them along with the commitment transaction fee while
+	/// staying above its reserve.
+	fn check_channel_type_upgrade(&self, channel_type: &ChannelTypeFeatures) -> Result<(), String> {
+		let current_channel_type = self.context.get_channel_type();
+		if channel_type == current_channel_type {
+			return Err(format!("Channel {} already has channel type {}", self.context.channel_id(), channel_type));
+		}
+		let mut anchors_channel_type = current_channel_type.clone();
+		anchors_channel_type.set_anchors_zero_fee_htlc_tx_required();
+		if current_channel_type.supports_anchors_zero_fee_htlc_tx() || *channel_type != anchors_channel_type {
+			return Err(format!("Channel {} can only be upgraded from {} to {}",
+				self.context.channel_id(), current_channel_type, anchors_channel_type));
+		}
+
+		let holder_balance_msat = self.context.value_to_self_msat -
+			self.context.pending_outbound_htlcs.iter().map(|htlc| htlc.amount_msat).sum::<u64>();
+		let counterparty_balance_msat = self.context.channel_value_satoshis * 1000 - self.context.value_to_self_msat -
+			self.context.pending_inbound_htlcs.iter().map(|htlc| htlc.amount_msat).sum::<u64>();
+		let (funder_balance_msat, funder_reserve_msat) = if self.context.is_outbound() {
+			(holder_balance_msat, self.context.counterparty_selected_channel_reserve_satoshis.unwrap_or(0) * 1000)
+		} else {
+			(counterparty_balance_msat, self.context.holder_selected_channel_reserve_satoshis * 1000)
+		};
+		// Upgrading to anchors may only turn dust HTLCs into non-dust ones, so assume they all get
+		// an output.
+		let num_htlcs = self.context.pending_inbound_htlcs.len() + self.context.pending_outbound_htlcs.len();
+		let commit_tx_fee_msat = commit_tx_fee_msat(self.context.feerate_per_kw, num_htlcs, channel_type);
+		let anchor_outputs_value_msat = ANCHOR_OUTPUT_VALUE_SATOSHI * 2 * 1000;
+		if funder_balance_msat < commit_tx_fee_msat + anchor_outputs_value_msat + funder_reserve_msat {
+			return Err(format!("Channel {} funder cannot afford the commitment transaction fee and anchor outputs with channel type {}",
+				self.context.channel_id(), channel_type));
+		}
+		Ok(())
+	}
+
+	/// Starts upgrading the channel to the given `channel_type` by making the channel quiescent,
+	/// after which `dyn_propose` will be sent via [`Self::maybe_propose_channel_type_upgrade`].
+	///
+	/// Returns an `stfu` message to send to our counterparty if one can be sent immediately.
+	pub fn propose_channel_type_upgrade<L: Deref>(
+		&mut self, channel_type: ChannelTypeFeatures, logger: &L
+	) -> Result<Option<msgs::Stfu>, APIError> where L::Target: Logger {
+		if self.pending_channel_type_upgrade.is_some() {
+			return Err(APIError::APIMisuseError { err: format!(
+				"Channel {} already has a channel type upgrade in progress", self.context.channel_id()) });
+		}
+		self.check_channel_type_upgrade(&channel_type).map_err(|err| APIError::APIMisuseError { err })?;
+
+		let stfu = self.propose_quiescence(logger)?;
+		log_info!(logger, "Upgrading channel {} to channel type {} once quiescent", &self.context.channel_id(), channel_type);
+		self.pending_channel_type_upgrade = Some(PendingChannelTypeUpgrade {
+			previous_channel_type: self.context.channel_type.clone(),
+			channel_type,
+			state: ChannelTypeUpgradeState::AwaitingQuiescence,
+			monitor_update_pending: false,
+		});
+		Ok(stfu)
+	}
+
+	/// Generates our `dyn_propose` once a channel type upgrade we requested has made the channel
+	/// quiescent.
+	///
+	/// This should be called any time the channel may have become quiescent, which the
+	/// `ChannelManager` checks each time its pending message events are fetched.
+	///
+	/// If the upgrade can no longer be completed, it is abandoned and an error is returned, upon
+	/// which the peer should be disconnected to terminate quiescence on both sides.
+	pub fn maybe_propose_channel_type_upgrade<L: Deref>(&mut self, logger: &L) -> Result<Option<msgs::DynPropose>, ChannelError>
+	where L::Target: Logger {
+		match &self.pending_channel_type_upgrade {
+			Some(upgrade) if upgrade.state == ChannelTypeUpgradeState::AwaitingQuiescence => {},
+			_ => return Ok(None),
+		}
+		if !self.context.channel_state.is_quiescent() {
+			return Ok(None);
+		}
+		if self.context.is_holder_quiescence_initiator != Some(true) {
+			// Our counterparty won the tie-break to become the quiescence initiator, so it's up to
+			// them what happens while quiescent and our upgrade has to be requested again.
+			log_info!(logger, "Abandoning channel type upgrade of channel {} as our counterparty initiated quiescence",
+				&self.context.channel_id());
+			self.pending_channel_type_upgrade = None;
+			return Ok(None);
+		}
+		// The balances may have changed while we were waiting on the channel to become quiescent.
+		// Our counterparty is quiescent too and there's no message to end quiescence without a
+		// proposal, so we have to disconnect.
+		let channel_type = self.pending_channel_type_upgrade.as_ref().expect("Checked above").channel_type.clone();
+		if let Err(e) = self.check_channel_type_upgrade(&channel_type) {
+			log_info!(logger, "Abandoning channel type upgrade: {}", e);
+			self.pending_channel_type_upgrade = None;
+			return Err(ChannelError::Warn(format!("Abandoning channel type upgrade: {}", e)));
+		}
+		self.pending_channel_type_upgrade.as_mut().expect("Checked above").state = ChannelTypeUpgradeState::Proposed;
+		self.mark_awaiting_response();
+		log_debug!(logger, "Proposing channel type {} for channel {}", channel_type, &self.context.channel_id());
+		Ok(Some(msgs::DynPropose {
+			channel_id: self.context.channel_id,
+			channel_type,
+		}))
+	}
+
+	/// Switches the channel over to `channel_type`, which applies to all commitment transactions
+	/// built from now on. The [`ChannelMonitor`] is made aware of the switch with the next
+	/// [`ChannelMonitorUpdate`] we generate.
+	fn apply_channel_type_upgrade(&mut self, channel_type: ChannelTypeFeatures) {
+		let previous_channel_type = mem::replace(&mut self.context.channel_type, channel_type.clone());
+		self.context.channel_transaction_parameters.channel_type_features = channel_type.clone();
+		self.context.holder_signer.as_mut().provide_channel_type_features(&channel_type);
+		self.pending_channel_type_upgrade = Some(PendingChannelTypeUpgrade {
+			channel_type,
+			previous_channel_type,
+			state: ChannelTypeUpgradeState::Committing {
+				commitment_signed_sent: false,
+				commitment_signed_received: false,
+			},
+			monitor_update_pending: true,
+		});
+	}
+
+	/// Returns the [`ChannelMonitorUpdateStep`] informing the [`ChannelMonitor`] of a channel type
+	/// upgrade, if it has yet to be included in a [`ChannelMonitorUpdate`]. Must be called before
+	/// the commitment numbers are advanced for the commitment transaction being built.
+	fn take_channel_type_upgrade_monitor_step(&mut self) -> Option<ChannelMonitorUpdateStep> {
+		let upgrade = self.pending_channel_type_upgrade.as_mut()?;
+		if !upgrade.monitor_update_pending {
+			return None;
+		}
+		upgrade.monitor_update_pending = false;
+		Some(ChannelMonitorUpdateStep::ChannelTypeUpdated {
+			channel_type_features: upgrade.channel_type.clone(),
+			holder_commitment_number: self.context.cur_holder_commitment_transaction_number,
+			counterparty_commitment_number: self.context.cur_counterparty_commitment_transaction_number,
+		})
+	}
+
+	/// Handles a `dyn_propose` message from our counterparty, the quiescence initiator.
+	///
+	/// If we accept the proposed channel type, it applies immediately and we respond with
+	/// `dyn_ack`, otherwise we respond with `dyn_reject` and terminate quiescence.
+	pub fn dyn_propose<L: Deref>(
+		&mut self, msg: &msgs::DynPropose, our_supported_features: &ChannelTypeFeatures, logger: &L
+	) -> Result<DynProposeResponse, ChannelError> where L::Target: Logger {
+		if !self.context.channel_state.is_quiescent() {
+			return Err(ChannelError::Warn("Peer sent dyn_propose while the channel was not quiescent".to_owned()));
+		}
+		if self.context.is_holder_quiescence_initiator != Some(false) {
+			return Err(ChannelError::Warn("Peer sent dyn_propose while we were the quiescence initiator".to_owned()));
+		}
+		if let Some(upgrade) = &self.pending_channel_type_upgrade {
+			if upgrade.state != ChannelTypeUpgradeState::AwaitingQuiescence {
+				return Err(ChannelError::Close("Peer sent dyn_propose while a channel type upgrade was already in progress".to_owned()));
+			}
+			// Our counterparty became the quiescence initiator, so our own upgrade can't proceed.
+			log_info!(logger, "Abandoning our channel type upgrade of channel {} in favor of our counterparty's",
+				&self.context.channel_id());
+			self.pending_channel_type_upgrade = None;
+		}
+
+		let check_res = if msg.channel_type.requires_unknown_bits_from(our_supported_features) {
+			Err(format!("Channel type {} is not supported", msg.channel_type))
+		} else {
+			self.check_channel_type_upgrade(&msg.channel_type)
+		};
+		if let Err(e) = check_res {
+			log_info!(logger, "Rejecting channel type upgrade proposed by our counterparty: {}", e);
+			self.exit_quiescence();
+			return Ok(DynProposeResponse::Reject(msgs::DynReject { channel_id: self.context.channel_id }));
+		}
+
+		log_info!(logger, "Accepting upgrade of channel {} to channel type {}", &self.context.channel_id(), msg.channel_type);
+		self.apply_channel_type_upgrade(msg.channel_type.clone());
+		Ok(DynProposeResponse::Ack(msgs::DynAck { channel_id: self.context.channel_id }))
+	}
+
+	/// Handles a `dyn_ack` message from our counterparty accepting the channel type we proposed.
+	///
+	/// The new channel type applies immediately, and we send our first commitment transaction
+	/// using it, returning the resulting [`ChannelMonitorUpdate`].
+	pub fn dyn_ack<L: Deref>(&mut self, _msg: &msgs::DynAck, logger: &L) -> Result<Option<ChannelMonitorUpdate>, ChannelError>
+	where L::Target: Logger {
+		let channel_type = match &self.pending_channel_type_upgrade {
+			Some(upgrade) if upgrade.state == ChannelTypeUpgradeState::Proposed => upgrade.channel_type.clone(),
+			_ => return Err(ChannelError::Close("Peer sent dyn_ack for a channel type we never proposed".to_owned())),
+		};
+		self.context.sent_message_awaiting_response = None;
+		log_info!(logger, "Counterparty accepted upgrade of channel {} to channel type {}", &self.context.channel_id(), channel_type);
+		self.apply_channel_type_upgrade(channel_type);
+
+		let monitor_update = self.build_commitment_no_status_check(logger);
+		self.monitor_updating_paused(false, true, false, Vec::new(), Vec::new(), Vec::new());
+		Ok(self.push_ret_blockable_mon_update(monitor_update))
+	}
+
+	/// Handles a `dyn_reject` message from our counterparty, abandoning the channel type upgrade
+	/// we proposed and terminating quiescence.
+	pub fn dyn_reject<L: Deref>(&mut self, _msg: &msgs::DynReject, logger: &L) -> Result<(), ChannelError>
+	where L::Target: Logger {
+		match &self.pending_channel_type_upgrade {
+			Some(upgrade) if upgrade.state == ChannelTypeUpgradeState::Proposed => {},
+			_ => return Err(ChannelError::Warn("Peer sent dyn_reject for a channel type we never proposed".to_owned())),
+		}
+		self.context.sent_message_awaiting_response = None;
+		log_info!(logger, "Counterparty rejected channel type upgrade of channel {}", &self.context.channel_id());
+		self.pending_channel_type_upgrade = None;
+		self.exit_quiescence();
+		Ok(())
+	}
+
+	/// Completes a channel type upgrade once both sides have irrevocably committed to a commitment
+	/// transaction using the new type, terminating quiescence.
+	///
+	/// Returns true if an upgrade was completed.
+	pub fn maybe_complete_channel_type_upgrade<L: Deref>(&mut self, logger: &L) -> bool where L::Target: Logger {
+		match &self.pending_channel_type_upgrade {
+			Some(PendingChannelTypeUpgrade {
+				state: ChannelTypeUpgradeState::Committing { commitment_signed_sent: true, commitment_signed_received: true },
+				..
+			}) => {},
+			_ => return false,
+		}
+		if self.has_uncommitted_updates() || self.context.channel_state.is_monitor_update_in_progress() {
+			return false;
+		}
+		log_info!(logger, "Completed upgrade of channel {} to channel type {}", &self.context.channel_id(), self.context.channel_type);
+		self.pending_channel_type_upgrade = None;
+		// We may have been disconnected while committing, in which case we're no longer quiescent.
+		self.exit_quiescence();
+		true
+	}
+
+	/// Reconciles our channel type with the one our counterparty sent in `channel_reestablish`.
+	///
+	/// If they never received our first commitment transaction using an upgraded type, we revert
+	/// to the previous type. If we never received their `dyn_ack`, they will revert instead.
+	fn reestablish_channel_type<L: Deref>(&mut self, their_channel_type: &ChannelTypeFeatures, logger: &L) -> Result<(), ChannelError>
+	where L::Target: Logger {
+		if their_channel_type == &self.context.channel_type {
+			return Ok(());
+		}
+		if let Some(upgrade) = &self.pending_channel_type_upgrade {
+			if upgrade.monitor_update_pending && their_channel_type == &upgrade.previous_channel_type {
+				log_info!(logger, "Reverting channel {} to channel type {} as our counterparty never committed to the upgrade",
+					&self.context.channel_id(), their_channel_type);
+				self.context.channel_type = their_channel_type.clone();
+				self.context.channel_transaction_parameters.channel_type_features = their_channel_type.clone();
+				self.context.holder_signer.as_mut().provide_channel_type_features(their_channel_type);
+				self.pending_channel_type_upgrade = None;
+				return Ok(());
+			}
+		}
+		if self.pending_channel_type_upgrade.is_none() && self.check_channel_type_upgrade(their_channel_type).is_ok() {
+			log_info!(logger, "Counterparty has to revert the upgrade of channel {} to channel type {} which we never committed to",
+				&self.context.channel_id(), their_channel_type);
+			return Ok(());
+		}
+		Err(ChannelError::Close(format!("Peer sent channel_reestablish with channel type {} but our channel type is {}",
+			their_channel_type, self.context.channel_type)))
+	}
+
+	pub fn shutdown(
+		&mut self, signer_provider: &SP, their_features: &InitFeatures, msg: &msgs::Shutdown
+	) -> Result<(Option<msgs::Shutdown>, Option<ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>), ChannelError>
+	{
+		if self.context.channel_state.is_peer_disconnected() {
+			return Err(ChannelError::Close("Peer sent shutdown when we needed a channel_reestablish".to_owned()));
+		}
+		if self.context.channel_state.is_pre_funded_state() {
 			// Spec says we should fail the connection, not the channel, but that's nonsense, there
 			// are plenty of reasons you may want to fail a channel pre-funding, and spec says you
 			// can do that via error message without getting a connection fail anyway...
@@ -5181,7 +6151,7 @@ impl<SP: Deref> Channel<SP> where
 		if !self.is_awaiting_monitor_update() { return false; }
 		if matches!(
 			self.context.channel_state, ChannelState::AwaitingChannelReady(flags)
-			if (flags & !(AwaitingChannelReadyFlags::THEIR_CHANNEL_READY | FundedStateFlags::PEER_DISCONNECTED | FundedStateFlags::MONITOR_UPDATE_IN_PROGRESS | AwaitingChannelReadyFlags::WAITING_FOR_BATCH)).is_empty()
+			if (flags & !(AwaitingChannelReadyFlags::THEIR_CHANNEL_READY | FundedStateFlags::PEER_DISCONNECTED | FundedStateFlags::MONITOR_UPDATE_IN_PROGRESS | AwaitingChannelReadyFlags::WAITING_FOR_BATCH | AwaitingChannelReadyFlags::WAITING_FOR_TX_SIGNATURES)).is_empty()
 		) {
 			// If we're not a 0conf channel, we'll be waiting on a monitor update with only
 			// AwaitingChannelReady set, though our peer could have sent their channel_ready.
@@ -5277,6 +6247,11 @@ impl<SP: Deref> Channel<SP> where
 		} else if matches!(self.context.channel_state, ChannelState::AwaitingChannelReady(f) if f & !FundedStateFlags::ALL == AwaitingChannelReadyFlags::OUR_CHANNEL_READY) {
 			// We got a reorg but not enough to trigger a force close, just ignore.
 			false
+		} else if matches!(self.context.channel_state, ChannelState::AwaitingChannelReady(f) if f.is_set(AwaitingChannelReadyFlags::WAITING_FOR_TX_SIGNATURES)) {
+			// Our counterparty may broadcast a dual-funded funding transaction as soon as they have
+			// our `tx_signatures`, so it may confirm before we've received theirs. We'll send our
+			// `channel_ready` once they arrive.
+			false
 		} else {
 			if self.context.funding_tx_confirmation_height != 0 &&
 				self.context.channel_state < ChannelState::ChannelReady(ChannelReadyFlags::new())
@@ -5733,6 +6708,14 @@ impl<SP: Deref> Channel<SP> where
 			log_info!(logger, "Sending a data_loss_protect with no previous remote per_commitment_secret for channel {}", &self.context.channel_id());
 			[0;32]
 		};
+		// If we've sent `commitment_signed` for an interactively constructed funding transaction but
+		// have not received our counterparty's `tx_signatures` for it, we have to ask for them again.
+		#[cfg(dual_funding)]
+		let next_funding_txid = self.interactive_tx_signing_session.as_ref()
+			.filter(|session| session.counterparty_witnesses.is_none())
+			.map(|session| session.constructed_transaction.tx.txid());
+		#[cfg(not(dual_funding))]
+		let next_funding_txid = None;
 		self.mark_awaiting_response();
 		msgs::ChannelReestablish {
 			channel_id: self.context.channel_id(),
@@ -5756,10 +6739,8 @@ impl<SP: Deref> Channel<SP> where
 			next_remote_commitment_number: INITIAL_COMMITMENT_NUMBER - self.context.cur_counterparty_commitment_transaction_number - 1,
 			your_last_per_commitment_secret: remote_last_secret,
 			my_current_per_commitment_point: dummy_pubkey,
-			// TODO(dual_funding): If we've sent `commtiment_signed` for an interactive transaction
-			// construction but have not received `tx_signatures` we MUST set `next_funding_txid` to the
-			// txid of that interactive transaction, else we MUST NOT set it.
-			next_funding_txid: None,
+			next_funding_txid,
+			channel_type: Some(self.context.channel_type.clone()),
 		}
 	}
 
@@ -5774,13 +6755,14 @@ impl<SP: Deref> Channel<SP> where
 	pub fn queue_add_htlc<F: Deref, L: Deref>(
 		&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource,
 		onion_routing_packet: msgs::OnionPacket, skimmed_fee_msat: Option<u64>,
-		blinding_point: Option<PublicKey>, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
+		blinding_point: Option<PublicKey>, endorsed: Option<bool>,
+		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
 	) -> Result<(), ChannelError>
 	where F::Target: FeeEstimator, L::Target: Logger
 	{
 		self
 			.send_htlc(amount_msat, payment_hash, cltv_expiry, source, onion_routing_packet, true,
-				skimmed_fee_msat, blinding_point, fee_estimator, logger)
+				skimmed_fee_msat, blinding_point, None, endorsed, fee_estimator, logger)
 			.map(|msg_opt| assert!(msg_opt.is_none(), "We forced holding cell?"))
 			.map_err(|err| {
 				if let ChannelError::Ignore(_) = err { /* fine */ }
@@ -5808,8 +6790,8 @@ impl<SP: Deref> Channel<SP> where
 	fn send_htlc<F: Deref, L: Deref>(
 		&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource,
 		onion_routing_packet: msgs::OnionPacket, mut force_holding_cell: bool,
-		skimmed_fee_msat: Option<u64>, blinding_point: Option<PublicKey>,
-		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
+		skimmed_fee_msat: Option<u64>, blinding_point: Option<PublicKey>, hold_htlc: Option<[u8; 32]>,
+		endorsed: Option<bool>, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
 	) -> Result<Option<msgs::UpdateAddHTLC>, ChannelError>
 	where F::Target: FeeEstimator, L::Target: Logger
 	{
@@ -5870,6 +6852,8 @@ impl<SP: Deref> Channel<SP> where
 				onion_routing_packet,
 				skimmed_fee_msat,
 				blinding_point,
+				hold_htlc,
+				endorsed,
 			});
 			return Ok(None);
 		}
@@ -5883,6 +6867,8 @@ impl<SP: Deref> Channel<SP> where
 			source,
 			blinding_point,
 			skimmed_fee_msat,
+			hold_htlc,
+			endorsed,
 		});
 
 		let res = msgs::UpdateAddHTLC {
@@ -5894,6 +6880,8 @@ impl<SP: Deref> Channel<SP> where
 			onion_routing_packet,
 			skimmed_fee_msat,
 			blinding_point,
+			hold_htlc,
+			endorsed,
 		};
 		self.context.next_holder_htlc_id += 1;
 
@@ -5933,6 +6921,14 @@ impl<SP: Deref> Channel<SP> where
 		}
 		self.context.resend_order = RAACommitmentOrder::RevokeAndACKFirst;
 
+		let mut updates = Vec::new();
+		updates.extend(self.take_channel_type_upgrade_monitor_step());
+		if let Some(upgrade) = self.pending_channel_type_upgrade.as_mut() {
+			if let ChannelTypeUpgradeState::Committing { commitment_signed_sent, .. } = &mut upgrade.state {
+				*commitment_signed_sent = true;
+			}
+		}
+
 		let (mut htlcs_ref, counterparty_commitment_tx) =
 			self.build_commitment_no_state_update(logger);
 		let counterparty_commitment_txid = counterparty_commitment_tx.trust().txid();
@@ -5944,19 +6940,20 @@ impl<SP: Deref> Channel<SP> where
 		}
 
 		self.context.latest_monitor_update_id += 1;
-		let monitor_update = ChannelMonitorUpdate {
+		let mut monitor_update = ChannelMonitorUpdate {
 			update_id: self.context.latest_monitor_update_id,
 			counterparty_node_id: Some(self.context.counterparty_node_id),
-			updates: vec![ChannelMonitorUpdateStep::LatestCounterpartyCommitmentTXInfo {
-				commitment_txid: counterparty_commitment_txid,
-				htlc_outputs: htlcs.clone(),
-				commitment_number: self.context.cur_counterparty_commitment_transaction_number,
-				their_per_commitment_point: self.context.counterparty_cur_commitment_point.unwrap(),
-				feerate_per_kw: Some(counterparty_commitment_tx.feerate_per_kw()),
-				to_broadcaster_value_sat: Some(counterparty_commitment_tx.to_broadcaster_value_sat()),
-				to_countersignatory_value_sat: Some(counterparty_commitment_tx.to_countersignatory_value_sat()),
-			}]
+			updates,
 		};
+		monitor_update.updates.push(ChannelMonitorUpdateStep::LatestCounterpartyCommitmentTXInfo {
+			commitment_txid: counterparty_commitment_txid,
+			htlc_outputs: htlcs.clone(),
+			commitment_number: self.context.cur_counterparty_commitment_transaction_number,
+			their_per_commitment_point: self.context.counterparty_cur_commitment_point.unwrap(),
+			feerate_per_kw: Some(counterparty_commitment_tx.feerate_per_kw()),
+			to_broadcaster_value_sat: Some(counterparty_commitment_tx.to_broadcaster_value_sat()),
+			to_countersignatory_value_sat: Some(counterparty_commitment_tx.to_countersignatory_value_sat()),
+		});
 		self.context.channel_state.set_awaiting_remote_revoke();
 		monitor_update
 	}
@@ -6056,12 +7053,12 @@ impl<SP: Deref> Channel<SP> where
 	pub fn send_htlc_and_commit<F: Deref, L: Deref>(
 		&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32,
 		source: HTLCSource, onion_routing_packet: msgs::OnionPacket, skimmed_fee_msat: Option<u64>,
-		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
+		hold_htlc: Option<[u8; 32]>, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
 	) -> Result<Option<ChannelMonitorUpdate>, ChannelError>
 	where F::Target: FeeEstimator, L::Target: Logger
 	{
 		let send_res = self.send_htlc(amount_msat, payment_hash, cltv_expiry, source,
-			onion_routing_packet, false, skimmed_fee_msat, None, fee_estimator, logger);
+			onion_routing_packet, false, skimmed_fee_msat, None, hold_htlc, None, fee_estimator, logger);
 		if let Err(e) = &send_res { if let ChannelError::Ignore(_) = e {} else { debug_assert!(false, "Sending cannot trigger channel failure"); } }
 		match send_res? {
 			Some(_) => {
@@ -6189,6 +7186,166 @@ impl<SP: Deref> Channel<SP> where
 			})
 			.chain(self.context.pending_outbound_htlcs.iter().map(|htlc| (&htlc.source, &htlc.payment_hash)))
 	}
+
+	/// Returns the unsigned funding transaction of a dual-funded channel if we have yet to provide
+	/// our signatures for the inputs we contributed to it.
+	#[cfg(dual_funding)]
+	pub fn unsigned_funding_tx_awaiting_holder_signatures(&self) -> Option<Transaction> {
+		self.interactive_tx_signing_session.as_ref()
+			.filter(|session| session.holder_tx_signatures.is_none())
+			.map(|session| session.constructed_transaction.tx.clone())
+	}
+
+	/// Handles our signatures for the inputs we contributed to a dual-funded channel's funding
+	/// transaction, provided by the user via
+	/// [`ChannelManager::funding_transaction_signed`].
+	///
+	/// Returns the `tx_signatures` to send our counterparty, if we may send them now, and the fully
+	/// signed funding transaction, if both peers have now provided their signatures.
+	///
+	/// [`ChannelManager::funding_transaction_signed`]: super::channelmanager::ChannelManager::funding_transaction_signed
+	#[cfg(dual_funding)]
+	pub fn funding_transaction_signed<L: Deref>(&mut self, transaction: Transaction, logger: &L)
+	-> Result<(Option<msgs::TxSignatures>, Option<Transaction>), APIError> where L::Target: Logger {
+		let session = match self.interactive_tx_signing_session.as_mut() {
+			Some(session) => session,
+			None => return Err(APIError::APIMisuseError {
+				err: format!("Channel {} is not awaiting signatures for its funding transaction", self.context.channel_id()),
+			}),
+		};
+		if session.holder_tx_signatures.is_some() {
+			return Err(APIError::APIMisuseError {
+				err: format!("Funding transaction for channel {} was already signed", self.context.channel_id()),
+			});
+		}
+		let unsigned_tx = &session.constructed_transaction.tx;
+		if transaction.txid() != unsigned_tx.txid() || transaction.input.len() != unsigned_tx.input.len() {
+			return Err(APIError::APIMisuseError {
+				err: format!("Transaction {} does not match the negotiated funding transaction {}", transaction.txid(), unsigned_tx.txid()),
+			});
+		}
+		let mut witnesses = Vec::with_capacity(session.constructed_transaction.holder_input_indices.len());
+		for idx in session.constructed_transaction.holder_input_indices.iter() {
+			let witness = &transaction.input[*idx].witness;
+			if witness.is_empty() {
+				return Err(APIError::APIMisuseError {
+					err: format!("Funding transaction input {} which we contributed was not signed", idx),
+				});
+			}
+			witnesses.push(witness.clone());
+		}
+		session.holder_tx_signatures = Some(msgs::TxSignatures {
+			channel_id: self.context.channel_id(),
+			tx_hash: unsigned_tx.txid(),
+			witnesses,
+		});
+		Ok((self.maybe_get_tx_signatures(), self.maybe_finalize_funding_tx(logger)))
+	}
+
+	/// Handles a `tx_signatures` message from our counterparty for a dual-funded channel's funding
+	/// transaction.
+	///
+	/// Returns our own `tx_signatures` to send in response, if we may send them now, and the fully
+	/// signed funding transaction, if both peers have now provided their signatures.
+	#[cfg(dual_funding)]
+	pub fn tx_signatures<L: Deref>(&mut self, msg: &msgs::TxSignatures, logger: &L)
+	-> Result<(Option<msgs::TxSignatures>, Option<Transaction>), ChannelError> where L::Target: Logger {
+		let session = match self.interactive_tx_signing_session.as_mut() {
+			Some(session) => session,
+			None => return Err(ChannelError::Close("Received tx_signatures at a strange time".to_owned())),
+		};
+		if session.counterparty_witnesses.is_some() {
+			return Err(ChannelError::Close("Received duplicate tx_signatures".to_owned()));
+		}
+		if session.holder_sends_tx_signatures_first && !session.sent_tx_signatures {
+			return Err(ChannelError::Close("Received tx_signatures before we sent ours, despite contributing more to the funding transaction".to_owned()));
+		}
+		let constructed_transaction = &session.constructed_transaction;
+		if msg.tx_hash != constructed_transaction.tx.txid() {
+			return Err(ChannelError::Close(format!("Received tx_signatures for transaction {} instead of the negotiated funding transaction {}",
+				msg.tx_hash, constructed_transaction.tx.txid())));
+		}
+		if msg.witnesses.len() != constructed_transaction.counterparty_input_count() {
+			return Err(ChannelError::Close(format!("Received {} witnesses in tx_signatures but our counterparty contributed {} inputs",
+				msg.witnesses.len(), constructed_transaction.counterparty_input_count())));
+		}
+		if msg.witnesses.iter().any(|witness| witness.is_empty()) {
+			return Err(ChannelError::Close("Received an empty witness in tx_signatures".to_owned()));
+		}
+		session.counterparty_witnesses = Some(msg.witnesses.clone());
+		Ok((self.maybe_get_tx_signatures(), self.maybe_finalize_funding_tx(logger)))
+	}
+
+	/// Resumes the `tx_signatures` exchange for a dual-funded channel's funding transaction after
+	/// a successful [`Self::channel_reestablish`], retransmitting our `tx_signatures` if our
+	/// counterparty indicated via `next_funding_txid` that they never received them.
+	///
+	/// Returns the same as [`Self::tx_signatures`].
+	#[cfg(dual_funding)]
+	pub fn resume_tx_signatures_exchange<L: Deref>(&mut self, msg: &msgs::ChannelReestablish, logger: &L)
+	-> (Option<msgs::TxSignatures>, Option<Transaction>) where L::Target: Logger {
+		if let Some(session) = self.interactive_tx_signing_session.as_mut() {
+			if msg.next_funding_txid == Some(session.constructed_transaction.tx.txid()) {
+				session.sent_tx_signatures = false;
+			}
+		}
+		(self.maybe_get_tx_signatures(), self.maybe_finalize_funding_tx(logger))
+	}
+
+	/// Returns our `tx_signatures` if we're allowed to send them. This requires the initial
+	/// monitor to have been persisted and, unless we're meant to send ours first, our
+	/// counterparty's `tx_signatures` to have been received.
+	#[cfg(dual_funding)]
+	fn maybe_get_tx_signatures(&mut self) -> Option<msgs::TxSignatures> {
+		if self.context.channel_state.is_monitor_update_in_progress() ||
+			self.context.channel_state.is_peer_disconnected()
+		{
+			return None;
+		}
+		let session = self.interactive_tx_signing_session.as_mut()?;
+		if session.sent_tx_signatures ||
+			(!session.holder_sends_tx_signatures_first && session.counterparty_witnesses.is_none())
+		{
+			return None;
+		}
+		let tx_signatures = session.holder_tx_signatures.clone()?;
+		session.sent_tx_signatures = true;
+		Some(tx_signatures)
+	}
+
+	/// Once both peers' `tx_signatures` have been exchanged, assembles the fully signed funding
+	/// transaction, returning it to broadcast the first time the exchange completes.
+	///
+	/// The signing session is kept until our counterparty's `channel_ready` so that we can still
+	/// retransmit our `tx_signatures` if they ask for them on reestablish.
+	#[cfg(dual_funding)]
+	fn maybe_finalize_funding_tx<L: Deref>(&mut self, logger: &L) -> Option<Transaction> where L::Target: Logger {
+		if !self.context.channel_state.is_waiting_for_tx_signatures() {
+			return None;
+		}
+		let session = match &self.interactive_tx_signing_session {
+			Some(session) if session.sent_tx_signatures && session.counterparty_witnesses.is_some() => session,
+			_ => return None,
+		};
+		let mut funding_tx = session.constructed_transaction.tx.clone();
+		let mut holder_witnesses = session.holder_tx_signatures.as_ref()
+			.expect("We only send tx_signatures once we have them").witnesses.iter();
+		let mut counterparty_witnesses = session.counterparty_witnesses.as_ref().expect("Checked above").iter();
+		let holder_input_indices = &session.constructed_transaction.holder_input_indices;
+		for (idx, input) in funding_tx.input.iter_mut().enumerate() {
+			input.witness = if holder_input_indices.contains(&idx) {
+				holder_witnesses.next()
+			} else {
+				counterparty_witnesses.next()
+			}.expect("Witness counts were checked against the number of inputs contributed").clone();
+		}
+
+		self.context.funding_transaction = None;
+		self.context.channel_state.clear_waiting_for_tx_signatures();
+		log_info!(logger, "Exchanged tx_signatures for funding transaction {} of channel {}",
+			funding_tx.txid(), &self.context.channel_id());
+		Some(funding_tx)
+	}
 }
 
 /// A not-yet-funded outbound (from holder) channel using V1 channel establishment.
@@ -6234,12 +7391,19 @@ impl<SP: Deref> OutboundV1Channel<SP> where SP::Target: SignerProvider {
 		let channel_type = Self::get_initial_channel_type(&config, their_features);
 		debug_assert!(channel_type.is_subset(&channelmanager::provided_channel_type_features(&config)));
 
-		let (commitment_conf_target, anchor_outputs_value_msat)  = if channel_type.supports_anchors_zero_fee_htlc_tx() {
-			(ConfirmationTarget::AnchorChannelFee, ANCHOR_OUTPUT_VALUE_SATOSHI * 2 * 1000)
+		let commitment_conf_target = if channel_type.supports_anchors_zero_fee_htlc_tx() {
+			ConfirmationTarget::AnchorChannelFee
+		} else {
+			ConfirmationTarget::NonAnchorChannelFee
+		};
+		let anchor_outputs_value_msat = anchor_outputs_value_sat(&channel_type) * 1000;
+		// Zero-fee commitment transactions pay their fee entirely through a child transaction
+		// spending their ephemeral anchor, so there's no feerate to agree on.
+		let commitment_feerate = if channel_type.supports_zero_fee_commitments() {
+			0
 		} else {
-			(ConfirmationTarget::NonAnchorChannelFee, 0)
+			fee_estimator.bounded_sat_per_1000_weight(commitment_conf_target)
 		};
-		let commitment_feerate = fee_estimator.bounded_sat_per_1000_weight(commitment_conf_target);
 
 		let value_to_self_msat = channel_value_satoshis * 1000 - push_msat;
 		let commitment_tx_fee = commit_tx_fee_msat(commitment_feerate, MIN_AFFORDABLE_HTLC_COUNT, &channel_type);
@@ -6348,7 +7512,7 @@ impl<SP: Deref> OutboundV1Channel<SP> where SP::Target: SignerProvider {
 				counterparty_htlc_minimum_msat: 0,
 				holder_htlc_minimum_msat: if config.channel_handshake_config.our_htlc_minimum_msat == 0 { 1 } else { config.channel_handshake_config.our_htlc_minimum_msat },
 				counterparty_max_accepted_htlcs: 0,
-				holder_max_accepted_htlcs: cmp::min(config.channel_handshake_config.our_max_accepted_htlcs, MAX_HTLCS),
+				holder_max_accepted_htlcs: cmp::min(config.channel_handshake_config.our_max_accepted_htlcs, max_htlcs(&channel_type)),
 				minimum_depth: None, // Filled in in accept_channel
 
 				counterparty_forwarding_info: None,
@@ -6384,6 +7548,7 @@ impl<SP: Deref> OutboundV1Channel<SP> where SP::Target: SignerProvider {
 
 				workaround_lnd_bug_4006: None,
 				sent_message_awaiting_response: None,
+				is_holder_quiescence_initiator: None,
 
 				latest_inbound_scid_alias: None,
 				outbound_scid_alias,
@@ -6512,6 +7677,12 @@ impl<SP: Deref> OutboundV1Channel<SP> where SP::Target: SignerProvider {
 		if config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx &&
 			their_features.supports_anchors_zero_fee_htlc_tx() {
 			ret.set_anchors_zero_fee_htlc_tx_required();
+
+			// Zero-fee commitments build on `anchors_zero_fee_htlc_tx`, so we only try them on top.
+			if config.channel_handshake_config.negotiate_zero_fee_commitments &&
+				their_features.supports_zero_fee_commitments() {
+				ret.set_zero_fee_commitments_required();
+			}
 		}
 
 		ret
@@ -6547,7 +7718,10 @@ impl<SP: Deref> OutboundV1Channel<SP> where SP::Target: SignerProvider {
 		// checks whether the counterparty supports every feature, this would only happen if the
 		// counterparty is advertising the feature, but rejecting channels proposing the feature for
 		// whatever reason.
-		if self.context.channel_type.supports_anchors_zero_fee_htlc_tx() {
+		if self.context.channel_type.supports_zero_fee_commitments() {
+			self.context.channel_type.clear_zero_fee_commitments();
+			self.context.feerate_per_kw = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::AnchorChannelFee);
+		} else if self.context.channel_type.supports_anchors_zero_fee_htlc_tx() {
 			self.context.channel_type.clear_anchors_zero_fee_htlc_tx();
 			self.context.feerate_per_kw = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::NonAnchorChannelFee);
 			assert!(!self.context.channel_transaction_parameters.channel_type_features.supports_anchors_nonzero_fee_htlc_tx());
@@ -6604,158 +7778,30 @@ impl<SP: Deref> OutboundV1Channel<SP> where SP::Target: SignerProvider {
 
 	// Message handlers
 	pub fn accept_channel(&mut self, msg: &msgs::AcceptChannel, default_limits: &ChannelHandshakeLimits, their_features: &InitFeatures) -> Result<(), ChannelError> {
-		let peer_limits = if let Some(ref limits) = self.context.inbound_handshake_limits_override { limits } else { default_limits };
+		self.context.do_accept_channel_checks(msg, default_limits, their_features)
+	}
 
-		// Check sanity of message fields:
+	/// Handles a funding_signed message from the remote end.
+	/// If this call is successful, broadcast the funding transaction (and not before!)
+	pub fn funding_signed<L: Deref>(
+		mut self, msg: &msgs::FundingSigned, best_block: BestBlock, signer_provider: &SP, logger: &L
+	) -> Result<(Channel<SP>, ChannelMonitor<<SP::Target as SignerProvider>::EcdsaSigner>), (OutboundV1Channel<SP>, ChannelError)>
+	where
+		L::Target: Logger
+	{
 		if !self.context.is_outbound() {
-			return Err(ChannelError::Close("Got an accept_channel message from an inbound peer".to_owned()));
-		}
-		if !matches!(self.context.channel_state, ChannelState::NegotiatingFunding(flags) if flags == NegotiatingFundingFlags::OUR_INIT_SENT) {
-			return Err(ChannelError::Close("Got an accept_channel message at a strange time".to_owned()));
-		}
-		if msg.dust_limit_satoshis > 21000000 * 100000000 {
-			return Err(ChannelError::Close(format!("Peer never wants payout outputs? dust_limit_satoshis was {}", msg.dust_limit_satoshis)));
-		}
-		if msg.channel_reserve_satoshis > self.context.channel_value_satoshis {
-			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must not be greater than ({})", msg.channel_reserve_satoshis, self.context.channel_value_satoshis)));
-		}
-		if msg.dust_limit_satoshis > self.context.holder_selected_channel_reserve_satoshis {
-			return Err(ChannelError::Close(format!("Dust limit ({}) is bigger than our channel reserve ({})", msg.dust_limit_satoshis, self.context.holder_selected_channel_reserve_satoshis)));
-		}
-		if msg.channel_reserve_satoshis > self.context.channel_value_satoshis - self.context.holder_selected_channel_reserve_satoshis {
-			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must not be greater than channel value minus our reserve ({})",
-				msg.channel_reserve_satoshis, self.context.channel_value_satoshis - self.context.holder_selected_channel_reserve_satoshis)));
-		}
-		let full_channel_value_msat = (self.context.channel_value_satoshis - msg.channel_reserve_satoshis) * 1000;
-		if msg.htlc_minimum_msat >= full_channel_value_msat {
-			return Err(ChannelError::Close(format!("Minimum htlc value ({}) is full channel value ({})", msg.htlc_minimum_msat, full_channel_value_msat)));
-		}
-		let max_delay_acceptable = u16::min(peer_limits.their_to_self_delay, MAX_LOCAL_BREAKDOWN_TIMEOUT);
-		if msg.to_self_delay > max_delay_acceptable {
-			return Err(ChannelError::Close(format!("They wanted our payments to be delayed by a needlessly long period. Upper limit: {}. Actual: {}", max_delay_acceptable, msg.to_self_delay)));
+			return Err((self, ChannelError::Close("Received funding_signed for an inbound channel?".to_owned())));
 		}
-		if msg.max_accepted_htlcs < 1 {
-			return Err(ChannelError::Close("0 max_accepted_htlcs makes for a useless channel".to_owned()));
+		if !matches!(self.context.channel_state, ChannelState::FundingNegotiated) {
+			return Err((self, ChannelError::Close("Received funding_signed in strange state!".to_owned())));
 		}
-		if msg.max_accepted_htlcs > MAX_HTLCS {
-			return Err(ChannelError::Close(format!("max_accepted_htlcs was {}. It must not be larger than {}", msg.max_accepted_htlcs, MAX_HTLCS)));
+		if self.context.commitment_secrets.get_min_seen_secret() != (1 << 48) ||
+				self.context.cur_counterparty_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER ||
+				self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
+			panic!("Should not have advanced channel commitment tx numbers prior to funding_created");
 		}
 
-		// Now check against optional parameters as set by config...
-		if msg.htlc_minimum_msat > peer_limits.max_htlc_minimum_msat {
-			return Err(ChannelError::Close(format!("htlc_minimum_msat ({}) is higher than the user specified limit ({})", msg.htlc_minimum_msat, peer_limits.max_htlc_minimum_msat)));
-		}
-		if msg.max_htlc_value_in_flight_msat < peer_limits.min_max_htlc_value_in_flight_msat {
-			return Err(ChannelError::Close(format!("max_htlc_value_in_flight_msat ({}) is less than the user specified limit ({})", msg.max_htlc_value_in_flight_msat, peer_limits.min_max_htlc_value_in_flight_msat)));
-		}
-		if msg.channel_reserve_satoshis > peer_limits.max_channel_reserve_satoshis {
-			return Err(ChannelError::Close(format!("channel_reserve_satoshis ({}) is higher than the user specified limit ({})", msg.channel_reserve_satoshis, peer_limits.max_channel_reserve_satoshis)));
-		}
-		if msg.max_accepted_htlcs < peer_limits.min_max_accepted_htlcs {
-			return Err(ChannelError::Close(format!("max_accepted_htlcs ({}) is less than the user specified limit ({})", msg.max_accepted_htlcs, peer_limits.min_max_accepted_htlcs)));
-		}
-		if msg.dust_limit_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
-			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is less than the implementation limit ({})", msg.dust_limit_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS)));
-		}
-		if msg.dust_limit_satoshis > MAX_CHAN_DUST_LIMIT_SATOSHIS {
-			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is greater than the implementation limit ({})", msg.dust_limit_satoshis, MAX_CHAN_DUST_LIMIT_SATOSHIS)));
-		}
-		if msg.minimum_depth > peer_limits.max_minimum_depth {
-			return Err(ChannelError::Close(format!("We consider the minimum depth to be unreasonably large. Expected minimum: ({}). Actual: ({})", peer_limits.max_minimum_depth, msg.minimum_depth)));
-		}
-
-		if let Some(ty) = &msg.channel_type {
-			if *ty != self.context.channel_type {
-				return Err(ChannelError::Close("Channel Type in accept_channel didn't match the one sent in open_channel.".to_owned()));
-			}
-		} else if their_features.supports_channel_type() {
-			// Assume they've accepted the channel type as they said they understand it.
-		} else {
-			let channel_type = ChannelTypeFeatures::from_init(&their_features);
-			if channel_type != ChannelTypeFeatures::only_static_remote_key() {
-				return Err(ChannelError::Close("Only static_remote_key is supported for non-negotiated channel types".to_owned()));
-			}
-			self.context.channel_type = channel_type.clone();
-			self.context.channel_transaction_parameters.channel_type_features = channel_type;
-		}
-
-		let counterparty_shutdown_scriptpubkey = if their_features.supports_upfront_shutdown_script() {
-			match &msg.shutdown_scriptpubkey {
-				&Some(ref script) => {
-					// Peer is signaling upfront_shutdown and has opt-out with a 0-length script. We don't enforce anything
-					if script.len() == 0 {
-						None
-					} else {
-						if !script::is_bolt2_compliant(&script, their_features) {
-							return Err(ChannelError::Close(format!("Peer is signaling upfront_shutdown but has provided an unacceptable scriptpubkey format: {}", script)));
-						}
-						Some(script.clone())
-					}
-				},
-				// Peer is signaling upfront shutdown but don't opt-out with correct mechanism (a.k.a 0-length script). Peer looks buggy, we fail the channel
-				&None => {
-					return Err(ChannelError::Close("Peer is signaling upfront_shutdown but we don't get any script. Use 0-length script to opt-out".to_owned()));
-				}
-			}
-		} else { None };
-
-		self.context.counterparty_dust_limit_satoshis = msg.dust_limit_satoshis;
-		self.context.counterparty_max_htlc_value_in_flight_msat = cmp::min(msg.max_htlc_value_in_flight_msat, self.context.channel_value_satoshis * 1000);
-		self.context.counterparty_selected_channel_reserve_satoshis = Some(msg.channel_reserve_satoshis);
-		self.context.counterparty_htlc_minimum_msat = msg.htlc_minimum_msat;
-		self.context.counterparty_max_accepted_htlcs = msg.max_accepted_htlcs;
-
-		if peer_limits.trust_own_funding_0conf {
-			self.context.minimum_depth = Some(msg.minimum_depth);
-		} else {
-			self.context.minimum_depth = Some(cmp::max(1, msg.minimum_depth));
-		}
-
-		let counterparty_pubkeys = ChannelPublicKeys {
-			funding_pubkey: msg.funding_pubkey,
-			revocation_basepoint: RevocationBasepoint::from(msg.revocation_basepoint),
-			payment_point: msg.payment_point,
-			delayed_payment_basepoint: DelayedPaymentBasepoint::from(msg.delayed_payment_basepoint),
-			htlc_basepoint: HtlcBasepoint::from(msg.htlc_basepoint)
-		};
-
-		self.context.channel_transaction_parameters.counterparty_parameters = Some(CounterpartyChannelTransactionParameters {
-			selected_contest_delay: msg.to_self_delay,
-			pubkeys: counterparty_pubkeys,
-		});
-
-		self.context.counterparty_cur_commitment_point = Some(msg.first_per_commitment_point);
-		self.context.counterparty_shutdown_scriptpubkey = counterparty_shutdown_scriptpubkey;
-
-		self.context.channel_state = ChannelState::NegotiatingFunding(
-			NegotiatingFundingFlags::OUR_INIT_SENT | NegotiatingFundingFlags::THEIR_INIT_SENT
-		);
-		self.context.inbound_handshake_limits_override = None; // We're done enforcing limits on our peer's handshake now.
-
-		Ok(())
-	}
-
-	/// Handles a funding_signed message from the remote end.
-	/// If this call is successful, broadcast the funding transaction (and not before!)
-	pub fn funding_signed<L: Deref>(
-		mut self, msg: &msgs::FundingSigned, best_block: BestBlock, signer_provider: &SP, logger: &L
-	) -> Result<(Channel<SP>, ChannelMonitor<<SP::Target as SignerProvider>::EcdsaSigner>), (OutboundV1Channel<SP>, ChannelError)>
-	where
-		L::Target: Logger
-	{
-		if !self.context.is_outbound() {
-			return Err((self, ChannelError::Close("Received funding_signed for an inbound channel?".to_owned())));
-		}
-		if !matches!(self.context.channel_state, ChannelState::FundingNegotiated) {
-			return Err((self, ChannelError::Close("Received funding_signed in strange state!".to_owned())));
-		}
-		if self.context.commitment_secrets.get_min_seen_secret() != (1 << 48) ||
-				self.context.cur_counterparty_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER ||
-				self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
-			panic!("Should not have advanced channel commitment tx numbers prior to funding_created");
-		}
-
-		let funding_script = self.context.get_funding_redeemscript();
+		let funding_script = self.context.get_funding_redeemscript();
 
 		let counterparty_keys = self.context.build_remote_transaction_keys();
 		let counterparty_initial_commitment_tx = self.context.build_commitment_transaction(self.context.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, false, logger).tx;
@@ -6824,7 +7870,12 @@ impl<SP: Deref> OutboundV1Channel<SP> where SP::Target: SignerProvider {
 
 		log_info!(logger, "Received funding_signed from peer for channel {}", &self.context.channel_id());
 
-		let mut channel = Channel { context: self.context };
+		let mut channel = Channel {
+			context: self.context,
+			pending_channel_type_upgrade: None,
+			#[cfg(dual_funding)]
+			interactive_tx_signing_session: None,
+		};
 
 		let need_channel_ready = channel.check_get_channel_ready(0).is_some();
 		channel.monitor_updating_paused(false, false, need_channel_ready, Vec::new(), Vec::new(), Vec::new());
@@ -6884,6 +7935,9 @@ impl<SP: Deref> InboundV1Channel<SP> where SP::Target: SignerProvider {
 			if channel_type.requires_scid_privacy() && announced_channel {
 				return Err(ChannelError::Close("SCID Alias/Privacy Channel Type cannot be set on a public channel".to_owned()));
 			}
+			if channel_type.requires_zero_fee_commitments() && !channel_type.requires_anchors_zero_fee_htlc_tx() {
+				return Err(ChannelError::Close("Zero-fee commitments Channel Type must also require anchors_zero_fee_htlc_tx".to_owned()));
+			}
 			channel_type.clone()
 		} else {
 			let channel_type = ChannelTypeFeatures::from_init(&their_features);
@@ -6937,8 +7991,9 @@ impl<SP: Deref> InboundV1Channel<SP> where SP::Target: SignerProvider {
 		if msg.max_accepted_htlcs < 1 {
 			return Err(ChannelError::Close("0 max_accepted_htlcs makes for a useless channel".to_owned()));
 		}
-		if msg.max_accepted_htlcs > MAX_HTLCS {
-			return Err(ChannelError::Close(format!("max_accepted_htlcs was {}. It must not be larger than {}", msg.max_accepted_htlcs, MAX_HTLCS)));
+		let max_accepted_htlcs_limit = max_htlcs(&channel_type);
+		if msg.max_accepted_htlcs > max_accepted_htlcs_limit {
+			return Err(ChannelError::Close(format!("max_accepted_htlcs was {}. It must not be larger than {}", msg.max_accepted_htlcs, max_accepted_htlcs_limit)));
 		}
 
 		// Now check against optional parameters as set by config...
@@ -6991,11 +8046,7 @@ impl<SP: Deref> InboundV1Channel<SP> where SP::Target: SignerProvider {
 
 		// check if the funder's amount for the initial commitment tx is sufficient
 		// for full fee payment plus a few HTLCs to ensure the channel will be useful.
-		let anchor_outputs_value = if channel_type.supports_anchors_zero_fee_htlc_tx() {
-			ANCHOR_OUTPUT_VALUE_SATOSHI * 2
-		} else {
-			0
-		};
+		let anchor_outputs_value = anchor_outputs_value_sat(&channel_type);
 		let funders_amount_msat = msg.funding_satoshis * 1000 - msg.push_msat;
 		let commitment_tx_fee = commit_tx_fee_msat(msg.feerate_per_kw, MIN_AFFORDABLE_HTLC_COUNT, &channel_type) / 1000;
 		if (funders_amount_msat / 1000).saturating_sub(anchor_outputs_value) < commitment_tx_fee {
@@ -7136,7 +8187,7 @@ impl<SP: Deref> InboundV1Channel<SP> where SP::Target: SignerProvider {
 				counterparty_htlc_minimum_msat: msg.htlc_minimum_msat,
 				holder_htlc_minimum_msat: if config.channel_handshake_config.our_htlc_minimum_msat == 0 { 1 } else { config.channel_handshake_config.our_htlc_minimum_msat },
 				counterparty_max_accepted_htlcs: msg.max_accepted_htlcs,
-				holder_max_accepted_htlcs: cmp::min(config.channel_handshake_config.our_max_accepted_htlcs, MAX_HTLCS),
+				holder_max_accepted_htlcs: cmp::min(config.channel_handshake_config.our_max_accepted_htlcs, max_htlcs(&channel_type)),
 				minimum_depth,
 
 				counterparty_forwarding_info: None,
@@ -7175,6 +8226,7 @@ impl<SP: Deref> InboundV1Channel<SP> where SP::Target: SignerProvider {
 
 				workaround_lnd_bug_4006: None,
 				sent_message_awaiting_response: None,
+				is_holder_quiescence_initiator: None,
 
 				latest_inbound_scid_alias: None,
 				outbound_scid_alias: 0,
@@ -7369,6 +8421,9 @@ impl<SP: Deref> InboundV1Channel<SP> where SP::Target: SignerProvider {
 		// `ChannelMonitor`.
 		let mut channel = Channel {
 			context: self.context,
+			pending_channel_type_upgrade: None,
+			#[cfg(dual_funding)]
+			interactive_tx_signing_session: None,
 		};
 		let need_channel_ready = channel.check_get_channel_ready(0).is_some();
 		channel.monitor_updating_paused(false, false, need_channel_ready, Vec::new(), Vec::new(), Vec::new());
@@ -7377,6 +8432,703 @@ impl<SP: Deref> InboundV1Channel<SP> where SP::Target: SignerProvider {
 	}
 }
 
+/// The number of times we'll negotiate the funding transaction of a dual-funded channel before
+/// giving up and closing the channel.
+#[cfg(dual_funding)]
+const MAX_FUNDING_NEGOTIATION_ATTEMPTS: u8 = 3;
+
+/// The parameters of a dual-funded channel's funding transaction and our contribution to it.
+#[cfg(dual_funding)]
+pub(super) struct DualFundingChannelContext {
+	/// The amount in satoshis we will be contributing to the channel.
+	pub our_funding_satoshis: u64,
+	/// The amount in satoshis our counterparty will be contributing to the channel.
+	pub their_funding_satoshis: u64,
+	/// The funding transaction locktime suggested by the initiator.
+	pub funding_tx_locktime: LockTime,
+	/// The feerate set by the initiator to be used for the funding transaction.
+	pub funding_feerate_sat_per_1000_weight: u32,
+	/// The inputs we contribute to the funding transaction, along with their previous transactions.
+	our_funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>,
+	/// The script our change output pays to, if our inputs leave enough change for one.
+	change_script: ScriptBuf,
+	/// Whether we've sent `tx_abort` for the current negotiation and are waiting for our
+	/// counterparty to echo it back.
+	sent_tx_abort: bool,
+	/// The number of negotiations of the funding transaction which have been aborted so far.
+	aborted_negotiations: u8,
+}
+
+/// Tracks the exchange of `tx_signatures` for an interactively constructed funding transaction.
+#[cfg(dual_funding)]
+pub(super) struct InteractiveTxSigningSession {
+	constructed_transaction: ConstructedTransaction,
+	/// The peer contributing the lower total input value sends its `tx_signatures` first, with
+	/// ties broken by the lower node id.
+	holder_sends_tx_signatures_first: bool,
+	/// Our `tx_signatures`, once the user has signed the inputs we contributed.
+	holder_tx_signatures: Option<msgs::TxSignatures>,
+	/// Our counterparty's witnesses for the inputs they contributed, once received.
+	counterparty_witnesses: Option<Vec<Witness>>,
+	sent_tx_signatures: bool,
+}
+
+#[cfg(dual_funding)]
+impl InteractiveTxSigningSession {
+	fn new(
+		channel_id: ChannelId, constructed_transaction: ConstructedTransaction,
+		holder_node_id: &PublicKey, counterparty_node_id: &PublicKey,
+	) -> Self {
+		let holder_sends_tx_signatures_first =
+			if constructed_transaction.holder_inputs_value == constructed_transaction.counterparty_inputs_value {
+				holder_node_id.serialize() < counterparty_node_id.serialize()
+			} else {
+				constructed_transaction.holder_inputs_value < constructed_transaction.counterparty_inputs_value
+			};
+		// If we didn't contribute any inputs there's nothing for the user to sign.
+		let holder_tx_signatures = if constructed_transaction.holder_input_indices.is_empty() {
+			Some(msgs::TxSignatures {
+				channel_id,
+				tx_hash: constructed_transaction.tx.txid(),
+				witnesses: Vec::new(),
+			})
+		} else { None };
+		Self {
+			constructed_transaction,
+			holder_sends_tx_signatures_first,
+			holder_tx_signatures,
+			counterparty_witnesses: None,
+			sent_tx_signatures: false,
+		}
+	}
+}
+
+#[cfg(dual_funding)]
+impl_writeable_tlv_based!(InteractiveTxSigningSession, {
+	(0, constructed_transaction, required),
+	(2, holder_sends_tx_signatures_first, required),
+	(4, holder_tx_signatures, option),
+	(6, counterparty_witnesses, option),
+	(8, sent_tx_signatures, required),
+});
+
+/// Common behavior of not-yet-funded channels using V2 channel establishment, which build their
+/// funding transaction interactively with their counterparty.
+#[cfg(dual_funding)]
+pub(super) trait InteractivelyFunded<SP: Deref> where SP::Target: SignerProvider {
+	fn context(&self) -> &ChannelContext<SP>;
+
+	fn context_mut(&mut self) -> &mut ChannelContext<SP>;
+
+	fn dual_funding_context(&self) -> &DualFundingChannelContext;
+
+	fn dual_funding_context_mut(&mut self) -> &mut DualFundingChannelContext;
+
+	fn interactive_tx_constructor_mut(&mut self) -> &mut Option<InteractiveTxConstructor>;
+
+	fn signing_session_mut(&mut self) -> &mut Option<InteractiveTxSigningSession>;
+
+	/// Starts constructing the funding transaction with our counterparty, contributing our inputs
+	/// and any change output (and, as the initiator, the funding output).
+	///
+	/// Returns the first message to send if we're the initiator.
+	fn begin_interactive_funding_tx_construction<ES: Deref>(&mut self, entropy_source: &ES)
+	-> Result<Option<InteractiveTxMessageSend>, ChannelError> where ES::Target: EntropySource {
+		let context = self.context();
+		let dual_funding_context = self.dual_funding_context();
+		let is_initiator = context.is_outbound();
+		let funding_output = TxOut {
+			value: context.channel_value_satoshis,
+			script_pubkey: context.get_funding_redeemscript().to_v0_p2wsh(),
+		};
+
+		let mut outputs_to_contribute = Vec::new();
+		if is_initiator {
+			outputs_to_contribute.push(funding_output.clone());
+		}
+		match calculate_change_output_value(
+			is_initiator, dual_funding_context.our_funding_satoshis, &dual_funding_context.our_funding_inputs,
+			&funding_output.script_pubkey, &dual_funding_context.change_script,
+			dual_funding_context.funding_feerate_sat_per_1000_weight,
+		) {
+			Ok(Some(change_value)) => outputs_to_contribute.push(TxOut {
+				value: change_value,
+				script_pubkey: dual_funding_context.change_script.clone(),
+			}),
+			Ok(None) => {},
+			Err(()) => return Err(ChannelError::Close(format!(
+				"Our funding inputs are insufficient to cover our contribution of {} sats and fees",
+				dual_funding_context.our_funding_satoshis))),
+		}
+
+		let (constructor, first_message) = InteractiveTxConstructor::new(
+			entropy_source, context.channel_id(), dual_funding_context.funding_feerate_sat_per_1000_weight,
+			is_initiator, dual_funding_context.funding_tx_locktime,
+			dual_funding_context.our_funding_inputs.clone(), outputs_to_contribute, funding_output,
+			dual_funding_context.their_funding_satoshis,
+		);
+		*self.interactive_tx_constructor_mut() = Some(constructor);
+		Ok(first_message)
+	}
+
+	/// Feeds a message from our counterparty into the ongoing negotiation.
+	///
+	/// If the negotiation fails, it is aborted and the `tx_abort` to send is returned instead,
+	/// keeping the channel around so that the negotiation can be retried once our counterparty
+	/// acknowledges it. Only after [`MAX_FUNDING_NEGOTIATION_ATTEMPTS`] failed negotiations is the
+	/// channel closed.
+	fn with_interactive_tx_constructor<R, F>(&mut self, msg_name: &str, f: F)
+	-> Result<Result<R, msgs::TxAbort>, ChannelError>
+	where F: FnOnce(&mut InteractiveTxConstructor) -> Result<R, AbortReason> {
+		let reason = match self.interactive_tx_constructor_mut() {
+			Some(constructor) => match f(constructor) {
+				Ok(res) => return Ok(Ok(res)),
+				Err(reason) => reason,
+			},
+			// Our counterparty may still be sending messages for the negotiation we aborted until
+			// it sees our `tx_abort`.
+			None if self.dual_funding_context().sent_tx_abort => return Err(ChannelError::Ignore(
+				format!("Ignoring {} for an aborted funding negotiation", msg_name))),
+			None => return Err(ChannelError::Close(format!("Received {} at a strange time", msg_name))),
+		};
+
+		*self.interactive_tx_constructor_mut() = None;
+		let dual_funding_context = self.dual_funding_context_mut();
+		dual_funding_context.aborted_negotiations += 1;
+		if dual_funding_context.aborted_negotiations >= MAX_FUNDING_NEGOTIATION_ATTEMPTS {
+			return Err(ChannelError::Close(format!(
+				"Interactive transaction construction failed on {}: {}", msg_name, reason)));
+		}
+		dual_funding_context.sent_tx_abort = true;
+		Ok(Err(msgs::TxAbort {
+			channel_id: self.context().channel_id(),
+			data: reason.to_string().into_bytes(),
+		}))
+	}
+
+	fn tx_add_input(&mut self, msg: &msgs::TxAddInput) -> Result<InteractiveTxMessageSend, ChannelError> {
+		self.with_interactive_tx_constructor("tx_add_input", |constructor| constructor.handle_tx_add_input(msg))
+			.map(|res| res.unwrap_or_else(InteractiveTxMessageSend::TxAbort))
+	}
+
+	fn tx_add_output(&mut self, msg: &msgs::TxAddOutput) -> Result<InteractiveTxMessageSend, ChannelError> {
+		self.with_interactive_tx_constructor("tx_add_output", |constructor| constructor.handle_tx_add_output(msg))
+			.map(|res| res.unwrap_or_else(InteractiveTxMessageSend::TxAbort))
+	}
+
+	fn tx_remove_input(&mut self, msg: &msgs::TxRemoveInput) -> Result<InteractiveTxMessageSend, ChannelError> {
+		self.with_interactive_tx_constructor("tx_remove_input", |constructor| constructor.handle_tx_remove_input(msg))
+			.map(|res| res.unwrap_or_else(InteractiveTxMessageSend::TxAbort))
+	}
+
+	fn tx_remove_output(&mut self, msg: &msgs::TxRemoveOutput) -> Result<InteractiveTxMessageSend, ChannelError> {
+		self.with_interactive_tx_constructor("tx_remove_output", |constructor| constructor.handle_tx_remove_output(msg))
+			.map(|res| res.unwrap_or_else(InteractiveTxMessageSend::TxAbort))
+	}
+
+	/// Handles a `tx_complete`, returning our next message, if any, and, if this completed the
+	/// negotiation, the initial `commitment_signed` to send.
+	fn tx_complete<L: Deref>(&mut self, msg: &msgs::TxComplete, holder_node_id: &PublicKey, logger: &L)
+	-> Result<(Option<InteractiveTxMessageSend>, Option<msgs::CommitmentSigned>), ChannelError> where L::Target: Logger {
+		let (message_send, constructed_transaction) =
+			match self.with_interactive_tx_constructor("tx_complete", |constructor| constructor.handle_tx_complete(msg))? {
+				Ok(res) => res,
+				Err(tx_abort) => return Ok((Some(InteractiveTxMessageSend::TxAbort(tx_abort)), None)),
+			};
+		let commitment_signed = match constructed_transaction {
+			Some(constructed_transaction) =>
+				Some(self.funding_tx_constructed(constructed_transaction, holder_node_id, logger)?),
+			None => None,
+		};
+		Ok((message_send, commitment_signed))
+	}
+
+	/// Handles a `tx_abort` from our counterparty, returning the `tx_abort` to echo back, if we
+	/// didn't send one ourselves, followed by our first message of the renewed negotiation, if
+	/// we're the initiator.
+	///
+	/// The negotiation is restarted with the same contributions as the aborted one. Replacing the
+	/// funding transaction with different contributions via `tx_init_rbf`/`tx_ack_rbf` is not
+	/// supported, so the negotiation can only be aborted until the funding transaction has been
+	/// constructed.
+	fn tx_abort<ES: Deref>(&mut self, msg: &msgs::TxAbort, entropy_source: &ES)
+	-> Result<(Option<msgs::TxAbort>, Option<InteractiveTxMessageSend>), ChannelError> where ES::Target: EntropySource {
+		if self.signing_session_mut().is_some() {
+			return Err(ChannelError::Close(format!(
+				"Received tx_abort after the funding transaction was constructed: {}",
+				String::from_utf8_lossy(&msg.data))));
+		}
+
+		let echo = if self.dual_funding_context().sent_tx_abort {
+			None
+		} else {
+			*self.interactive_tx_constructor_mut() = None;
+			let dual_funding_context = self.dual_funding_context_mut();
+			dual_funding_context.aborted_negotiations += 1;
+			if dual_funding_context.aborted_negotiations >= MAX_FUNDING_NEGOTIATION_ATTEMPTS {
+				return Err(ChannelError::Close(format!(
+					"Counterparty aborted the funding negotiation too many times: {}",
+					String::from_utf8_lossy(&msg.data))));
+			}
+			Some(msgs::TxAbort { channel_id: self.context().channel_id(), data: Vec::new() })
+		};
+		self.dual_funding_context_mut().sent_tx_abort = false;
+
+		let first_message = self.begin_interactive_funding_tx_construction(entropy_source)?;
+		Ok((echo, first_message))
+	}
+
+	/// Updates our state with the negotiated funding transaction and signs our counterparty's
+	/// initial commitment transaction.
+	fn funding_tx_constructed<L: Deref>(
+		&mut self, constructed_transaction: ConstructedTransaction, holder_node_id: &PublicKey, logger: &L
+	) -> Result<msgs::CommitmentSigned, ChannelError> where L::Target: Logger {
+		*self.interactive_tx_constructor_mut() = None;
+
+		let context = self.context_mut();
+		let funding_txo = OutPoint {
+			txid: constructed_transaction.tx.txid(),
+			index: constructed_transaction.funding_output_index,
+		};
+		context.channel_transaction_parameters.funding_outpoint = Some(funding_txo);
+		context.holder_signer.as_mut().provide_channel_parameters(&context.channel_transaction_parameters);
+
+		let signature = match context.get_initial_counterparty_commitment_signature(logger) {
+			Ok(signature) => signature,
+			Err(e) => {
+				context.channel_transaction_parameters.funding_outpoint = None;
+				return Err(e);
+			},
+		};
+
+		context.channel_state = ChannelState::FundingNegotiated;
+		context.funding_transaction = Some(constructed_transaction.tx.clone());
+		let channel_id = context.channel_id();
+		let counterparty_node_id = context.counterparty_node_id;
+		log_info!(logger, "Constructed funding transaction {} for channel {}", funding_txo.txid, &channel_id);
+
+		*self.signing_session_mut() = Some(InteractiveTxSigningSession::new(
+			channel_id, constructed_transaction, holder_node_id, &counterparty_node_id));
+
+		Ok(msgs::CommitmentSigned {
+			channel_id,
+			signature,
+			htlc_signatures: Vec::new(),
+			#[cfg(taproot)]
+			partial_signature_with_nonce: None,
+		})
+	}
+}
+
+/// A not-yet-funded outbound (from holder) channel using V2 channel establishment.
+#[cfg(dual_funding)]
+pub(super) struct OutboundV2Channel<SP: Deref> where SP::Target: SignerProvider {
+	pub context: ChannelContext<SP>,
+	pub unfunded_context: UnfundedChannelContext,
+	pub dual_funding_context: DualFundingChannelContext,
+	/// The constructor of the funding transaction, once negotiation of it has begun.
+	pub interactive_tx_constructor: Option<InteractiveTxConstructor>,
+	signing_session: Option<InteractiveTxSigningSession>,
+}
+
+#[cfg(dual_funding)]
+impl<SP: Deref> OutboundV2Channel<SP> where SP::Target: SignerProvider {
+	pub fn new<ES: Deref, F: Deref>(
+		fee_estimator: &LowerBoundedFeeEstimator<F>, entropy_source: &ES, signer_provider: &SP,
+		counterparty_node_id: PublicKey, their_features: &InitFeatures, funding_satoshis: u64,
+		funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>, change_script: Option<ScriptBuf>,
+		funding_feerate_sat_per_1000_weight: u32, user_id: u128, config: &UserConfig,
+		current_chain_height: u32, outbound_scid_alias: u64,
+	) -> Result<OutboundV2Channel<SP>, APIError>
+	where ES::Target: EntropySource,
+	      F::Target: FeeEstimator,
+	{
+		// Until our counterparty tells us their contribution, the channel looks just like a V1
+		// channel we fully fund ourselves.
+		let OutboundV1Channel { mut context, unfunded_context } = OutboundV1Channel::new(
+			fee_estimator, entropy_source, signer_provider, counterparty_node_id, their_features,
+			funding_satoshis, 0, user_id, config, current_chain_height, outbound_scid_alias, None,
+		)?;
+
+		let change_script = match change_script {
+			Some(script) => script,
+			None => signer_provider.get_destination_script(context.channel_keys_id)
+				.map_err(|_| APIError::ChannelUnavailable { err: "Failed to get change script".to_owned() })?,
+		};
+
+		let temporary_channel_id = ChannelId::temporary_v2_from_revocation_basepoint(
+			&context.get_holder_pubkeys().revocation_basepoint);
+		context.channel_id = temporary_channel_id;
+		context.temporary_channel_id = Some(temporary_channel_id);
+
+		Ok(Self {
+			context,
+			unfunded_context,
+			dual_funding_context: DualFundingChannelContext {
+				our_funding_satoshis: funding_satoshis,
+				their_funding_satoshis: 0,
+				funding_tx_locktime: LockTime::from_consensus(current_chain_height),
+				funding_feerate_sat_per_1000_weight,
+				our_funding_inputs: funding_inputs,
+				change_script,
+				sent_tx_abort: false,
+				aborted_negotiations: 0,
+			},
+			interactive_tx_constructor: None,
+			signing_session: None,
+		})
+	}
+
+	pub fn get_open_channel_v2(&self, chain_hash: ChainHash) -> msgs::OpenChannelV2 {
+		if !self.context.is_outbound() {
+			panic!("Tried to open a channel for an inbound channel?");
+		}
+		if self.context.have_received_message() {
+			panic!("Cannot generate an open_channel2 after we've moved forward");
+		}
+
+		if self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
+			panic!("Tried to send an open_channel2 for a channel that has already advanced");
+		}
+
+		let first_per_commitment_point = self.context.holder_signer.as_ref().get_per_commitment_point(
+			self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
+		let second_per_commitment_point = self.context.holder_signer.as_ref().get_per_commitment_point(
+			self.context.cur_holder_commitment_transaction_number - 1, &self.context.secp_ctx);
+		let keys = self.context.get_holder_pubkeys();
+
+		msgs::OpenChannelV2 {
+			chain_hash,
+			temporary_channel_id: self.context.channel_id,
+			funding_feerate_sat_per_1000_weight: self.dual_funding_context.funding_feerate_sat_per_1000_weight,
+			commitment_feerate_sat_per_1000_weight: self.context.feerate_per_kw,
+			funding_satoshis: self.dual_funding_context.our_funding_satoshis,
+			dust_limit_satoshis: self.context.holder_dust_limit_satoshis,
+			max_htlc_value_in_flight_msat: self.context.holder_max_htlc_value_in_flight_msat,
+			htlc_minimum_msat: self.context.holder_htlc_minimum_msat,
+			to_self_delay: self.context.get_holder_selected_contest_delay(),
+			max_accepted_htlcs: self.context.holder_max_accepted_htlcs,
+			locktime: self.dual_funding_context.funding_tx_locktime.to_consensus_u32(),
+			funding_pubkey: keys.funding_pubkey,
+			revocation_basepoint: keys.revocation_basepoint.to_public_key(),
+			payment_basepoint: keys.payment_point,
+			delayed_payment_basepoint: keys.delayed_payment_basepoint.to_public_key(),
+			htlc_basepoint: keys.htlc_basepoint.to_public_key(),
+			first_per_commitment_point,
+			second_per_commitment_point,
+			channel_flags: if self.context.config.announced_channel {1} else {0},
+			shutdown_scriptpubkey: Some(match &self.context.shutdown_scriptpubkey {
+				Some(script) => script.clone().into_inner(),
+				None => Builder::new().into_script(),
+			}),
+			channel_type: Some(self.context.channel_type.clone()),
+			require_confirmed_inputs: None,
+		}
+	}
+
+	/// Handles an `accept_channel2` from our counterparty, updating the channel value with their
+	/// contribution. Note that the channel ID changes to the V2 channel ID during this call.
+	pub fn accept_channel_v2(
+		&mut self, msg: &msgs::AcceptChannelV2, default_limits: &ChannelHandshakeLimits,
+		their_features: &InitFeatures, signer_provider: &SP,
+	) -> Result<(), ChannelError> {
+		let channel_value_satoshis = match self.dual_funding_context.our_funding_satoshis.checked_add(msg.funding_satoshis) {
+			Some(value) if value < TOTAL_BITCOIN_SUPPLY_SATOSHIS => value,
+			_ => return Err(ChannelError::Close(format!("Funding must be smaller than the total bitcoin supply. Our counterparty contributed {}", msg.funding_satoshis))),
+		};
+		if !their_features.supports_wumbo() && channel_value_satoshis > MAX_FUNDING_SATOSHIS_NO_WUMBO {
+			return Err(ChannelError::Close(format!("Total funding must not exceed {} as our counterparty doesn't support wumbo, it was {}", MAX_FUNDING_SATOSHIS_NO_WUMBO, channel_value_satoshis)));
+		}
+
+		// Both peers' reserves depend on the total channel value, so we can only set them now.
+		let dust_limit_satoshis = cmp::max(self.context.holder_dust_limit_satoshis, msg.dust_limit_satoshis);
+		let channel_reserve_satoshis = get_v2_channel_reserve_satoshis(channel_value_satoshis, dust_limit_satoshis);
+		self.context.channel_value_satoshis = channel_value_satoshis;
+		self.context.holder_selected_channel_reserve_satoshis = channel_reserve_satoshis;
+
+		let accept_channel = msgs::AcceptChannel {
+			temporary_channel_id: msg.temporary_channel_id,
+			dust_limit_satoshis: msg.dust_limit_satoshis,
+			max_htlc_value_in_flight_msat: msg.max_htlc_value_in_flight_msat,
+			channel_reserve_satoshis,
+			htlc_minimum_msat: msg.htlc_minimum_msat,
+			minimum_depth: msg.minimum_depth,
+			to_self_delay: msg.to_self_delay,
+			max_accepted_htlcs: msg.max_accepted_htlcs,
+			funding_pubkey: msg.funding_pubkey,
+			revocation_basepoint: msg.revocation_basepoint,
+			payment_point: msg.payment_basepoint,
+			delayed_payment_basepoint: msg.delayed_payment_basepoint,
+			htlc_basepoint: msg.htlc_basepoint,
+			first_per_commitment_point: msg.first_per_commitment_point,
+			shutdown_scriptpubkey: msg.shutdown_scriptpubkey.clone(),
+			channel_type: msg.channel_type.clone(),
+			#[cfg(taproot)]
+			next_local_nonce: None,
+		};
+		self.context.do_accept_channel_checks(&accept_channel, default_limits, their_features)?;
+		self.context.set_v2_channel_reserves();
+
+		// Our signer was derived before the channel value was known, so re-derive it now that it is.
+		let holder_signer = signer_provider.derive_channel_signer(channel_value_satoshis, self.context.channel_keys_id);
+		self.context.holder_signer = ChannelSignerType::Ecdsa(holder_signer);
+		#[cfg(debug_assertions)]
+		{
+			let value_to_remote_msat = channel_value_satoshis * 1000 - self.context.value_to_self_msat;
+			*self.context.holder_max_commitment_tx_output.lock().unwrap() = (self.context.value_to_self_msat, value_to_remote_msat);
+			*self.context.counterparty_max_commitment_tx_output.lock().unwrap() = (self.context.value_to_self_msat, value_to_remote_msat);
+		}
+
+		self.dual_funding_context.their_funding_satoshis = msg.funding_satoshis;
+		self.context.channel_id = ChannelId::v2_from_revocation_basepoints(
+			&self.context.get_holder_pubkeys().revocation_basepoint,
+			&self.context.get_counterparty_pubkeys().revocation_basepoint);
+		Ok(())
+	}
+
+	/// Handles the initial `commitment_signed` from our counterparty, returning the funded
+	/// [`Channel`] and its `ChannelMonitor`.
+	pub fn initial_commitment_signed<L: Deref>(
+		mut self, msg: &msgs::CommitmentSigned, best_block: BestBlock, signer_provider: &SP, logger: &L
+	) -> Result<(Channel<SP>, ChannelMonitor<<SP::Target as SignerProvider>::EcdsaSigner>), (Self, ChannelError)>
+	where
+		L::Target: Logger
+	{
+		if self.signing_session.is_none() {
+			return Err((self, ChannelError::Close("Received initial commitment_signed before funding transaction constructed!".to_owned())));
+		}
+		let channel_monitor = match self.context.initial_commitment_signed_v2(msg, best_block, signer_provider, logger) {
+			Ok(channel_monitor) => channel_monitor,
+			Err(e) => return Err((self, e)),
+		};
+
+		let mut channel = Channel {
+			context: self.context,
+			pending_channel_type_upgrade: None,
+			interactive_tx_signing_session: self.signing_session,
+		};
+		channel.monitor_updating_paused(false, false, false, Vec::new(), Vec::new(), Vec::new());
+		Ok((channel, channel_monitor))
+	}
+}
+
+#[cfg(dual_funding)]
+impl<SP: Deref> InteractivelyFunded<SP> for OutboundV2Channel<SP> where SP::Target: SignerProvider {
+	fn context(&self) -> &ChannelContext<SP> {
+		&self.context
+	}
+
+	fn context_mut(&mut self) -> &mut ChannelContext<SP> {
+		&mut self.context
+	}
+
+	fn dual_funding_context(&self) -> &DualFundingChannelContext {
+		&self.dual_funding_context
+	}
+
+	fn dual_funding_context_mut(&mut self) -> &mut DualFundingChannelContext {
+		&mut self.dual_funding_context
+	}
+
+	fn interactive_tx_constructor_mut(&mut self) -> &mut Option<InteractiveTxConstructor> {
+		&mut self.interactive_tx_constructor
+	}
+
+	fn signing_session_mut(&mut self) -> &mut Option<InteractiveTxSigningSession> {
+		&mut self.signing_session
+	}
+}
+
+/// A not-yet-funded inbound (from counterparty) channel using V2 channel establishment.
+#[cfg(dual_funding)]
+pub(super) struct InboundV2Channel<SP: Deref> where SP::Target: SignerProvider {
+	pub context: ChannelContext<SP>,
+	pub unfunded_context: UnfundedChannelContext,
+	pub dual_funding_context: DualFundingChannelContext,
+	/// The constructor of the funding transaction, once negotiation of it has begun.
+	pub interactive_tx_constructor: Option<InteractiveTxConstructor>,
+	signing_session: Option<InteractiveTxSigningSession>,
+}
+
+#[cfg(dual_funding)]
+impl<SP: Deref> InboundV2Channel<SP> where SP::Target: SignerProvider {
+	/// Creates a new dual-funded channel from a remote side's `open_channel2`, contributing
+	/// `funding_satoshis` of our own from `funding_inputs`.
+	pub fn new<ES: Deref, F: Deref, L: Deref>(
+		fee_estimator: &LowerBoundedFeeEstimator<F>, entropy_source: &ES, signer_provider: &SP,
+		counterparty_node_id: PublicKey, our_supported_features: &ChannelTypeFeatures,
+		their_features: &InitFeatures, msg: &msgs::OpenChannelV2, funding_satoshis: u64,
+		funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>, change_script: Option<ScriptBuf>,
+		user_id: u128, config: &UserConfig, current_chain_height: u32, logger: &L,
+	) -> Result<InboundV2Channel<SP>, ChannelError>
+		where ES::Target: EntropySource,
+			  F::Target: FeeEstimator,
+			  L::Target: Logger,
+	{
+		let channel_value_satoshis = msg.funding_satoshis.checked_add(funding_satoshis)
+			.ok_or(ChannelError::Close(format!("Funding must be smaller than the total bitcoin supply. It was {} + {}", msg.funding_satoshis, funding_satoshis)))?;
+		let dust_limit_satoshis = cmp::max(msg.dust_limit_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS);
+
+		// The checks we run on an `open_channel` all apply here too, so run them against one
+		// equivalent to our counterparty's `open_channel2`, with our contribution pushed to us.
+		let open_channel = msgs::OpenChannel {
+			chain_hash: msg.chain_hash,
+			temporary_channel_id: msg.temporary_channel_id,
+			funding_satoshis: channel_value_satoshis,
+			push_msat: funding_satoshis * 1000,
+			dust_limit_satoshis: msg.dust_limit_satoshis,
+			max_htlc_value_in_flight_msat: msg.max_htlc_value_in_flight_msat,
+			channel_reserve_satoshis: get_v2_channel_reserve_satoshis(channel_value_satoshis, dust_limit_satoshis),
+			htlc_minimum_msat: msg.htlc_minimum_msat,
+			feerate_per_kw: msg.commitment_feerate_sat_per_1000_weight,
+			to_self_delay: msg.to_self_delay,
+			max_accepted_htlcs: msg.max_accepted_htlcs,
+			funding_pubkey: msg.funding_pubkey,
+			revocation_basepoint: msg.revocation_basepoint,
+			payment_point: msg.payment_basepoint,
+			delayed_payment_basepoint: msg.delayed_payment_basepoint,
+			htlc_basepoint: msg.htlc_basepoint,
+			first_per_commitment_point: msg.first_per_commitment_point,
+			channel_flags: msg.channel_flags,
+			shutdown_scriptpubkey: msg.shutdown_scriptpubkey.clone(),
+			channel_type: msg.channel_type.clone(),
+		};
+		let InboundV1Channel { mut context, unfunded_context } = InboundV1Channel::new(
+			fee_estimator, entropy_source, signer_provider, counterparty_node_id, our_supported_features,
+			their_features, &open_channel, user_id, config, current_chain_height, logger, false,
+		)?;
+		context.set_v2_channel_reserves();
+
+		let change_script = match change_script {
+			Some(script) => script,
+			None => signer_provider.get_destination_script(context.channel_keys_id)
+				.map_err(|_| ChannelError::Close("Failed to get change script".to_owned()))?,
+		};
+
+		context.channel_id = ChannelId::v2_from_revocation_basepoints(
+			&context.get_holder_pubkeys().revocation_basepoint,
+			&context.get_counterparty_pubkeys().revocation_basepoint);
+
+		Ok(Self {
+			context,
+			unfunded_context,
+			dual_funding_context: DualFundingChannelContext {
+				our_funding_satoshis: funding_satoshis,
+				their_funding_satoshis: msg.funding_satoshis,
+				funding_tx_locktime: LockTime::from_consensus(msg.locktime),
+				funding_feerate_sat_per_1000_weight: msg.funding_feerate_sat_per_1000_weight,
+				our_funding_inputs: funding_inputs,
+				change_script,
+				sent_tx_abort: false,
+				aborted_negotiations: 0,
+			},
+			interactive_tx_constructor: None,
+			signing_session: None,
+		})
+	}
+
+	/// Marks an inbound channel as accepted and generates a [`msgs::AcceptChannelV2`] message which
+	/// should be sent back to the counterparty node.
+	///
+	/// [`msgs::AcceptChannelV2`]: crate::ln::msgs::AcceptChannelV2
+	pub fn accept_inbound_dual_funded_channel(&mut self) -> msgs::AcceptChannelV2 {
+		if self.context.is_outbound() {
+			panic!("Tried to send accept_channel2 for an outbound channel?");
+		}
+		if !matches!(
+			self.context.channel_state, ChannelState::NegotiatingFunding(flags)
+			if flags == (NegotiatingFundingFlags::OUR_INIT_SENT | NegotiatingFundingFlags::THEIR_INIT_SENT)
+		) {
+			panic!("Tried to send accept_channel2 after channel had moved forward");
+		}
+		if self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
+			panic!("Tried to send an accept_channel2 for a channel that has already advanced");
+		}
+
+		let first_per_commitment_point = self.context.holder_signer.as_ref().get_per_commitment_point(
+			self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
+		let second_per_commitment_point = self.context.holder_signer.as_ref().get_per_commitment_point(
+			self.context.cur_holder_commitment_transaction_number - 1, &self.context.secp_ctx);
+		let keys = self.context.get_holder_pubkeys();
+
+		msgs::AcceptChannelV2 {
+			temporary_channel_id: self.context.temporary_channel_id.unwrap(),
+			funding_satoshis: self.dual_funding_context.our_funding_satoshis,
+			dust_limit_satoshis: self.context.holder_dust_limit_satoshis,
+			max_htlc_value_in_flight_msat: self.context.holder_max_htlc_value_in_flight_msat,
+			htlc_minimum_msat: self.context.holder_htlc_minimum_msat,
+			minimum_depth: self.context.minimum_depth.unwrap(),
+			to_self_delay: self.context.get_holder_selected_contest_delay(),
+			max_accepted_htlcs: self.context.holder_max_accepted_htlcs,
+			funding_pubkey: keys.funding_pubkey,
+			revocation_basepoint: keys.revocation_basepoint.to_public_key(),
+			payment_basepoint: keys.payment_point,
+			delayed_payment_basepoint: keys.delayed_payment_basepoint.to_public_key(),
+			htlc_basepoint: keys.htlc_basepoint.to_public_key(),
+			first_per_commitment_point,
+			second_per_commitment_point,
+			shutdown_scriptpubkey: Some(match &self.context.shutdown_scriptpubkey {
+				Some(script) => script.clone().into_inner(),
+				None => Builder::new().into_script(),
+			}),
+			channel_type: Some(self.context.channel_type.clone()),
+			require_confirmed_inputs: None,
+		}
+	}
+
+	/// Handles the initial `commitment_signed` from our counterparty, returning the funded
+	/// [`Channel`] and its `ChannelMonitor`.
+	pub fn initial_commitment_signed<L: Deref>(
+		mut self, msg: &msgs::CommitmentSigned, best_block: BestBlock, signer_provider: &SP, logger: &L
+	) -> Result<(Channel<SP>, ChannelMonitor<<SP::Target as SignerProvider>::EcdsaSigner>), (Self, ChannelError)>
+	where
+		L::Target: Logger
+	{
+		if self.signing_session.is_none() {
+			return Err((self, ChannelError::Close("Received initial commitment_signed before funding transaction constructed!".to_owned())));
+		}
+		let channel_monitor = match self.context.initial_commitment_signed_v2(msg, best_block, signer_provider, logger) {
+			Ok(channel_monitor) => channel_monitor,
+			Err(e) => return Err((self, e)),
+		};
+
+		let mut channel = Channel {
+			context: self.context,
+			pending_channel_type_upgrade: None,
+			interactive_tx_signing_session: self.signing_session,
+		};
+		channel.monitor_updating_paused(false, false, false, Vec::new(), Vec::new(), Vec::new());
+		Ok((channel, channel_monitor))
+	}
+}
+
+#[cfg(dual_funding)]
+impl<SP: Deref> InteractivelyFunded<SP> for InboundV2Channel<SP> where SP::Target: SignerProvider {
+	fn context(&self) -> &ChannelContext<SP> {
+		&self.context
+	}
+
+	fn context_mut(&mut self) -> &mut ChannelContext<SP> {
+		&mut self.context
+	}
+
+	fn dual_funding_context(&self) -> &DualFundingChannelContext {
+		&self.dual_funding_context
+	}
+
+	fn dual_funding_context_mut(&mut self) -> &mut DualFundingChannelContext {
+		&mut self.dual_funding_context
+	}
+
+	fn interactive_tx_constructor_mut(&mut self) -> &mut Option<InteractiveTxConstructor> {
+		&mut self.interactive_tx_constructor
+	}
+
+	fn signing_session_mut(&mut self) -> &mut Option<InteractiveTxSigningSession> {
+		&mut self.signing_session
+	}
+}
+
 const SERIALIZATION_VERSION: u8 = 3;
 const MIN_SERIALIZATION_VERSION: u8 = 3;
 
@@ -7458,6 +9210,8 @@ impl<SP: Deref> Writeable for Channel<SP> where SP::Target: SignerProvider {
 			if matches!(channel_state, ChannelState::AwaitingChannelReady(_)|ChannelState::ChannelReady(_)) {
 				channel_state.set_peer_disconnected();
 			}
+			// Quiescence never survives a disconnection so there's no need to persist it.
+			channel_state.clear_quiescence_flags();
 			channel_state.to_u32().write(writer)?;
 		}
 		self.context.channel_value_satoshis.write(writer)?;
@@ -7514,6 +9268,8 @@ impl<SP: Deref> Writeable for Channel<SP> where SP::Target: SignerProvider {
 		let mut preimages: Vec<&Option<PaymentPreimage>> = vec![];
 		let mut pending_outbound_skimmed_fees: Vec<Option<u64>> = Vec::new();
 		let mut pending_outbound_blinding_points: Vec<Option<PublicKey>> = Vec::new();
+		let mut pending_outbound_hold_htlcs: Vec<Option<[u8; 32]>> = Vec::new();
+		let mut pending_outbound_endorsements: Vec<Option<bool>> = Vec::new();
 
 		(self.context.pending_outbound_htlcs.len() as u64).write(writer)?;
 		for htlc in self.context.pending_outbound_htlcs.iter() {
@@ -7554,10 +9310,14 @@ impl<SP: Deref> Writeable for Channel<SP> where SP::Target: SignerProvider {
 			}
 			pending_outbound_skimmed_fees.push(htlc.skimmed_fee_msat);
 			pending_outbound_blinding_points.push(htlc.blinding_point);
+			pending_outbound_hold_htlcs.push(htlc.hold_htlc);
+			pending_outbound_endorsements.push(htlc.endorsed);
 		}
 
 		let mut holding_cell_skimmed_fees: Vec<Option<u64>> = Vec::new();
 		let mut holding_cell_blinding_points: Vec<Option<PublicKey>> = Vec::new();
+		let mut holding_cell_hold_htlcs: Vec<Option<[u8; 32]>> = Vec::new();
+		let mut holding_cell_endorsements: Vec<Option<bool>> = Vec::new();
 		// Vec of (htlc_id, failure_code, sha256_of_onion)
 		let mut malformed_htlcs: Vec<(u64, u16, [u8; 32])> = Vec::new();
 		(self.context.holding_cell_htlc_updates.len() as u64).write(writer)?;
@@ -7565,7 +9325,7 @@ impl<SP: Deref> Writeable for Channel<SP> where SP::Target: SignerProvider {
 			match update {
 				&HTLCUpdateAwaitingACK::AddHTLC {
 					ref amount_msat, ref cltv_expiry, ref payment_hash, ref source, ref onion_routing_packet,
-					blinding_point, skimmed_fee_msat,
+					blinding_point, skimmed_fee_msat, hold_htlc, endorsed,
 				} => {
 					0u8.write(writer)?;
 					amount_msat.write(writer)?;
@@ -7576,6 +9336,8 @@ impl<SP: Deref> Writeable for Channel<SP> where SP::Target: SignerProvider {
 
 					holding_cell_skimmed_fees.push(skimmed_fee_msat);
 					holding_cell_blinding_points.push(blinding_point);
+					holding_cell_hold_htlcs.push(hold_htlc);
+					holding_cell_endorsements.push(endorsed);
 				},
 				&HTLCUpdateAwaitingACK::ClaimHTLC { ref payment_preimage, ref htlc_id } => {
 					1u8.write(writer)?;
@@ -7725,6 +9487,18 @@ impl<SP: Deref> Writeable for Channel<SP> where SP::Target: SignerProvider {
 
 		let holder_max_accepted_htlcs = if self.context.holder_max_accepted_htlcs == DEFAULT_MAX_HTLCS { None } else { Some(self.context.holder_max_accepted_htlcs) };
 
+		// A channel type upgrade only has to survive a restart once both sides agreed on it, as it
+		// otherwise has to start over after reconnecting anyway.
+		let pending_channel_type_upgrade = self.pending_channel_type_upgrade.as_ref()
+			.filter(|upgrade| matches!(upgrade.state, ChannelTypeUpgradeState::Committing { .. }));
+
+		// The signing session is written as an even type as a channel which is still exchanging
+		// `tx_signatures` can't make progress without it.
+		#[cfg(dual_funding)]
+		let interactive_tx_signing_session = self.interactive_tx_signing_session.as_ref();
+		#[cfg(not(dual_funding))]
+		let interactive_tx_signing_session: Option<u8> = None;
+
 		write_tlv_fields!(writer, {
 			(0, self.context.announcement_sigs, option),
 			// minimum_depth and counterparty_selected_channel_reserve_satoshis used to have a
@@ -7760,6 +9534,12 @@ impl<SP: Deref> Writeable for Channel<SP> where SP::Target: SignerProvider {
 			(39, pending_outbound_blinding_points, optional_vec),
 			(41, holding_cell_blinding_points, optional_vec),
 			(43, malformed_htlcs, optional_vec), // Added in 0.0.119
+			(45, pending_channel_type_upgrade, option),
+			(47, pending_outbound_hold_htlcs, optional_vec),
+			(49, holding_cell_hold_htlcs, optional_vec),
+			(51, pending_outbound_endorsements, optional_vec),
+			(53, holding_cell_endorsements, optional_vec),
+			(54, interactive_tx_signing_session, option),
 		});
 
 		Ok(())
@@ -7872,6 +9652,8 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 				},
 				skimmed_fee_msat: None,
 				blinding_point: None,
+				hold_htlc: None,
+				endorsed: None,
 			});
 		}
 
@@ -7887,6 +9669,8 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 					onion_routing_packet: Readable::read(reader)?,
 					skimmed_fee_msat: None,
 					blinding_point: None,
+					hold_htlc: None,
+					endorsed: None,
 				},
 				1 => HTLCUpdateAwaitingACK::ClaimHTLC {
 					payment_preimage: Readable::read(reader)?,
@@ -8052,6 +9836,18 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 
 		let mut malformed_htlcs: Option<Vec<(u64, u16, [u8; 32])>> = None;
 
+		let mut pending_channel_type_upgrade: Option<PendingChannelTypeUpgrade> = None;
+
+		let mut pending_outbound_hold_htlcs_opt: Option<Vec<Option<[u8; 32]>>> = None;
+		let mut holding_cell_hold_htlcs_opt: Option<Vec<Option<[u8; 32]>>> = None;
+		let mut pending_outbound_endorsements_opt: Option<Vec<Option<bool>>> = None;
+		let mut holding_cell_endorsements_opt: Option<Vec<Option<bool>>> = None;
+
+		#[cfg(dual_funding)]
+		let mut interactive_tx_signing_session: Option<InteractiveTxSigningSession> = None;
+		#[cfg(not(dual_funding))]
+		let mut interactive_tx_signing_session: Option<u8> = None;
+
 		read_tlv_fields!(reader, {
 			(0, announcement_sigs, option),
 			(1, minimum_depth, option),
@@ -8081,8 +9877,21 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 			(39, pending_outbound_blinding_points_opt, optional_vec),
 			(41, holding_cell_blinding_points_opt, optional_vec),
 			(43, malformed_htlcs, optional_vec), // Added in 0.0.119
+			(45, pending_channel_type_upgrade, option),
+			(47, pending_outbound_hold_htlcs_opt, optional_vec),
+			(49, holding_cell_hold_htlcs_opt, optional_vec),
+			(51, pending_outbound_endorsements_opt, optional_vec),
+			(53, holding_cell_endorsements_opt, optional_vec),
+			(54, interactive_tx_signing_session, option),
 		});
 
+		// We can't resume the `tx_signatures` exchange of a dual-funded channel without support for
+		// them.
+		#[cfg(not(dual_funding))]
+		if interactive_tx_signing_session.is_some() {
+			return Err(DecodeError::UnknownRequiredFeature);
+		}
+
 		let (channel_keys_id, holder_signer) = if let Some(channel_keys_id) = channel_keys_id {
 			let mut holder_signer = signer_provider.derive_channel_signer(channel_value_satoshis, channel_keys_id);
 			// If we've gotten to the funding stage of the channel, populate the signer with its
@@ -8174,6 +9983,42 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 			// We expect all blinding points to be consumed above
 			if iter.next().is_some() { return Err(DecodeError::InvalidValue) }
 		}
+		if let Some(hold_htlcs) = pending_outbound_hold_htlcs_opt {
+			let mut iter = hold_htlcs.into_iter();
+			for htlc in pending_outbound_htlcs.iter_mut() {
+				htlc.hold_htlc = iter.next().ok_or(DecodeError::InvalidValue)?;
+			}
+			// We expect all hold secrets to be consumed above
+			if iter.next().is_some() { return Err(DecodeError::InvalidValue) }
+		}
+		if let Some(hold_htlcs) = holding_cell_hold_htlcs_opt {
+			let mut iter = hold_htlcs.into_iter();
+			for htlc in holding_cell_htlc_updates.iter_mut() {
+				if let HTLCUpdateAwaitingACK::AddHTLC { ref mut hold_htlc, .. } = htlc {
+					*hold_htlc = iter.next().ok_or(DecodeError::InvalidValue)?;
+				}
+			}
+			// We expect all hold secrets to be consumed above
+			if iter.next().is_some() { return Err(DecodeError::InvalidValue) }
+		}
+		if let Some(endorsements) = pending_outbound_endorsements_opt {
+			let mut iter = endorsements.into_iter();
+			for htlc in pending_outbound_htlcs.iter_mut() {
+				htlc.endorsed = iter.next().ok_or(DecodeError::InvalidValue)?;
+			}
+			// We expect all endorsements to be consumed above
+			if iter.next().is_some() { return Err(DecodeError::InvalidValue) }
+		}
+		if let Some(endorsements) = holding_cell_endorsements_opt {
+			let mut iter = endorsements.into_iter();
+			for htlc in holding_cell_htlc_updates.iter_mut() {
+				if let HTLCUpdateAwaitingACK::AddHTLC { ref mut endorsed, .. } = htlc {
+					*endorsed = iter.next().ok_or(DecodeError::InvalidValue)?;
+				}
+			}
+			// We expect all endorsements to be consumed above
+			if iter.next().is_some() { return Err(DecodeError::InvalidValue) }
+		}
 
 		if let Some(malformed_htlcs) = malformed_htlcs {
 			for (malformed_htlc_id, failure_code, sha256_of_onion) in malformed_htlcs {
@@ -8297,6 +10142,7 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 
 				workaround_lnd_bug_4006: None,
 				sent_message_awaiting_response: None,
+				is_holder_quiescence_initiator: None,
 
 				latest_inbound_scid_alias,
 				// Later in the ChannelManager deserialization phase we scan for channels and assign scid aliases if its missing
@@ -8312,7 +10158,10 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 				channel_keys_id,
 
 				blocked_monitor_updates: blocked_monitor_updates.unwrap(),
-			}
+			},
+			pending_channel_type_upgrade,
+			#[cfg(dual_funding)]
+			interactive_tx_signing_session,
 		})
 	}
 }
@@ -8534,6 +10383,8 @@ mod tests {
 			},
 			skimmed_fee_msat: None,
 			blinding_point: None,
+			hold_htlc: None,
+			endorsed: None,
 		});
 
 		// Make sure when Node A calculates their local commitment transaction, none of the HTLCs pass
@@ -8875,7 +10726,12 @@ mod tests {
 		let config = UserConfig::default();
 		let features = channelmanager::provided_init_features(&config);
 		let outbound_chan = OutboundV1Channel::<&TestKeysInterface>::new(&feeest, &&keys_provider, &&keys_provider, node_b_node_id, &features, 10000000, 100000, 42, &config, 0, 42, None).unwrap();
-		let mut chan = Channel { context: outbound_chan.context };
+		let mut chan = Channel {
+			context: outbound_chan.context,
+			pending_channel_type_upgrade: None,
+			#[cfg(dual_funding)]
+			interactive_tx_signing_session: None,
+		};
 
 		let dummy_htlc_source = HTLCSource::OutboundRoute {
 			path: Path {
@@ -8899,6 +10755,8 @@ mod tests {
 			source: dummy_htlc_source.clone(),
 			skimmed_fee_msat: None,
 			blinding_point: None,
+			hold_htlc: None,
+			endorsed: None,
 		};
 		let mut pending_outbound_htlcs = vec![dummy_outbound_output.clone(); 10];
 		for (idx, htlc) in pending_outbound_htlcs.iter_mut().enumerate() {
@@ -8924,6 +10782,8 @@ mod tests {
 			},
 			skimmed_fee_msat: None,
 			blinding_point: None,
+			hold_htlc: None,
+			endorsed: None,
 		};
 		let dummy_holding_cell_claim_htlc = HTLCUpdateAwaitingACK::ClaimHTLC {
 			payment_preimage: PaymentPreimage([42; 32]),
@@ -9218,6 +11078,8 @@ mod tests {
 				source: HTLCSource::dummy(),
 				skimmed_fee_msat: None,
 				blinding_point: None,
+				hold_htlc: None,
+				endorsed: None,
 			};
 			out.payment_hash.0 = Sha256::hash(&<Vec<u8>>::from_hex("0202020202020202020202020202020202020202020202020202020202020202").unwrap()).to_byte_array();
 			out
@@ -9232,6 +11094,8 @@ mod tests {
 				source: HTLCSource::dummy(),
 				skimmed_fee_msat: None,
 				blinding_point: None,
+				hold_htlc: None,
+				endorsed: None,
 			};
 			out.payment_hash.0 = Sha256::hash(&<Vec<u8>>::from_hex("0303030303030303030303030303030303030303030303030303030303030303").unwrap()).to_byte_array();
 			out
@@ -9644,6 +11508,8 @@ mod tests {
 				source: HTLCSource::dummy(),
 				skimmed_fee_msat: None,
 				blinding_point: None,
+				hold_htlc: None,
+				endorsed: None,
 			};
 			out.payment_hash.0 = Sha256::hash(&<Vec<u8>>::from_hex("0505050505050505050505050505050505050505050505050505050505050505").unwrap()).to_byte_array();
 			out
@@ -9658,6 +11524,8 @@ mod tests {
 				source: HTLCSource::dummy(),
 				skimmed_fee_msat: None,
 				blinding_point: None,
+				hold_htlc: None,
+				endorsed: None,
 			};
 			out.payment_hash.0 = Sha256::hash(&<Vec<u8>>::from_hex("0505050505050505050505050505050505050505050505050505050505050505").unwrap()).to_byte_array();
 			out
@@ -9,10 +9,13 @@
 
 //! ChannelId definition.
 
+use crate::ln::channel_keys::RevocationBasepoint;
 use crate::ln::msgs::DecodeError;
 use crate::sign::EntropySource;
 use crate::util::ser::{Readable, Writeable, Writer};
 
+use bitcoin::hashes::{Hash as _, HashEngine as _, sha256::Hash as Sha256};
+
 use crate::io;
 use core::fmt;
 use core::ops::Deref;
@@ -20,10 +23,11 @@ use core::ops::Deref;
 /// A unique 32-byte identifier for a channel.
 /// Depending on how the ID is generated, several varieties are distinguished
 /// (but all are stored as 32 bytes):
-///   _v1_ and _temporary_.
+///   _v1_, _v2_ and _temporary_.
 /// A _v1_ channel ID is generated based on funding tx outpoint (txid & index).
-/// A _temporary_ ID is generated randomly.
-/// (Later revocation-point-based _v2_ is a possibility.)
+/// A _v2_ channel ID is generated based on both parties' revocation basepoints.
+/// A _temporary_ ID is generated randomly (or, for dual-funded channels, from the initiator's
+/// revocation basepoint).
 /// The variety (context) is not stored, it is relevant only at creation.
 ///
 /// This is not exported to bindings users as we just use [u8; 32] directly.
@@ -46,6 +50,33 @@ impl ChannelId {
 		Self(entropy_source.get_secure_random_bytes())
 	}
 
+	/// Create a _v2_ channel ID by hashing the holder and counterparty revocation basepoints,
+	/// concatenated in increasing sorted order.
+	pub fn v2_from_revocation_basepoints(
+		ours: &RevocationBasepoint, theirs: &RevocationBasepoint,
+	) -> Self {
+		let ours = ours.0.serialize();
+		let theirs = theirs.0.serialize();
+		let (lesser_point, greater_point) = if ours < theirs {
+			(ours, theirs)
+		} else {
+			(theirs, ours)
+		};
+		let mut engine = Sha256::engine();
+		engine.input(&lesser_point[..]);
+		engine.input(&greater_point[..]);
+		Self(Sha256::from_engine(engine).to_byte_array())
+	}
+
+	/// Create a _temporary_ _v2_ channel ID by hashing a zeroed out basepoint concatenated with
+	/// the holder's revocation basepoint.
+	pub fn temporary_v2_from_revocation_basepoint(our_revocation_basepoint: &RevocationBasepoint) -> Self {
+		let mut engine = Sha256::engine();
+		engine.input(&[0u8; 33]);
+		engine.input(&our_revocation_basepoint.0.serialize()[..]);
+		Self(Sha256::from_engine(engine).to_byte_array())
+	}
+
 	/// Generic constructor; create a new channel ID from the provided data.
 	/// Use a more specific `*_from_*` constructor when possible.
 	pub fn from_bytes(data: [u8; 32]) -> Self {
@@ -84,9 +115,11 @@ impl fmt::Display for ChannelId {
 
 #[cfg(test)]
 mod tests {
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
 	use hex::DisplayHex;
 
 	use crate::ln::ChannelId;
+	use crate::ln::channel_keys::RevocationBasepoint;
 	use crate::util::ser::{Readable, Writeable};
 	use crate::util::test_utils;
 	use crate::prelude::*;
@@ -98,6 +131,20 @@ mod tests {
 		assert_eq!(channel_id.0.as_hex().to_string(), "0202020202020202020202020202020202020202020202020202020202020203");
 	}
 
+	#[test]
+	fn test_channel_id_v2_from_basepoints() {
+		let secp_ctx = Secp256k1::new();
+		let ours = RevocationBasepoint(PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[1; 32]).unwrap()));
+		let theirs = RevocationBasepoint(PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[2; 32]).unwrap()));
+
+		// The ID is independent of which side derives it.
+		let channel_id = ChannelId::v2_from_revocation_basepoints(&ours, &theirs);
+		assert_eq!(channel_id, ChannelId::v2_from_revocation_basepoints(&theirs, &ours));
+		assert_ne!(channel_id, ChannelId::temporary_v2_from_revocation_basepoint(&ours));
+		assert_ne!(ChannelId::temporary_v2_from_revocation_basepoint(&ours),
+			ChannelId::temporary_v2_from_revocation_basepoint(&theirs));
+	}
+
 	#[test]
 	fn test_channel_id_new_from_data() {
 		let data: [u8; 32] = [2; 32];
@@ -19,15 +19,20 @@
 
 use bitcoin::blockdata::block::Header;
 use bitcoin::blockdata::transaction::Transaction;
+#[cfg(dual_funding)]
+use bitcoin::blockdata::transaction::TxIn;
+#[cfg(dual_funding)]
+use bitcoin::blockdata::script::ScriptBuf;
 use bitcoin::blockdata::constants::ChainHash;
 use bitcoin::key::constants::SECRET_KEY_SIZE;
 use bitcoin::network::constants::Network;
 
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hash_types::{BlockHash, Txid};
 
-use bitcoin::secp256k1::{SecretKey,PublicKey};
+use bitcoin::secp256k1::{KeyPair,SecretKey,PublicKey};
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::{secp256k1, Sequence};
 
@@ -43,34 +48,44 @@ use crate::events::{Event, EventHandler, EventsProvider, MessageSendEvent, Messa
 // Since this struct is returned in `list_channels` methods, expose it here in case users want to
 // construct one themselves.
 use crate::ln::{inbound_payment, ChannelId, PaymentHash, PaymentPreimage, PaymentSecret};
-use crate::ln::channel::{Channel, ChannelPhase, ChannelContext, ChannelError, ChannelUpdateStatus, ShutdownResult, UnfundedChannelContext, UpdateFulfillCommitFetch, OutboundV1Channel, InboundV1Channel, WithChannelContext};
+use crate::ln::channel::{Channel, ChannelPhase, ChannelContext, ChannelError, ChannelUpdateStatus, DynProposeResponse, ShutdownResult, UnfundedChannelContext, UpdateFulfillCommitFetch, OutboundV1Channel, InboundV1Channel, WithChannelContext};
+#[cfg(dual_funding)]
+use crate::ln::channel::{InboundV2Channel, InteractivelyFunded, OutboundV2Channel};
+#[cfg(dual_funding)]
+use crate::ln::interactivetxs::{check_funding_inputs, InteractiveTxMessageSend};
 use crate::ln::features::{Bolt12InvoiceFeatures, ChannelFeatures, ChannelTypeFeatures, InitFeatures, NodeFeatures};
 #[cfg(any(feature = "_test_utils", test))]
 use crate::ln::features::Bolt11InvoiceFeatures;
-use crate::routing::router::{BlindedTail, InFlightHtlcs, Path, Payee, PaymentParameters, Route, RouteParameters, Router};
-use crate::ln::onion_payment::{check_incoming_htlc_cltv, create_recv_pending_htlc_info, create_fwd_pending_htlc_info, decode_incoming_update_add_htlc_onion, InboundOnionErr, NextPacketDetails};
+use crate::routing::router::{BlindedTail, InFlightHtlcs, Path, Payee, PaymentParameters, Route, RouteHop, RouteParameters, Router, TrampolineHop};
+use crate::ln::onion_payment::{check_incoming_htlc_cltv, create_recv_pending_htlc_info, create_fwd_pending_htlc_info, create_trampoline_fwd_pending_htlc_info, decode_incoming_update_add_htlc_onion, next_trampoline_pubkey, InboundOnionErr, NextPacketDetails};
 use crate::ln::msgs;
 use crate::ln::onion_utils;
-use crate::ln::onion_utils::{HTLCFailReason, INVALID_ONION_BLINDING};
+use crate::ln::onion_utils::{HTLCFailReason, INVALID_ONION_BLINDING, TEMPORARY_TRAMPOLINE_FAILURE, TRAMPOLINE_EXPIRY_TOO_SOON, TRAMPOLINE_FEE_INSUFFICIENT};
 use crate::ln::msgs::{ChannelMessageHandler, DecodeError, LightningError};
+use crate::ln::our_peer_storage::{DecryptedOurPeerStorage, PeerStorageChannel};
 #[cfg(test)]
 use crate::ln::outbound_payment;
 use crate::ln::outbound_payment::{Bolt12PaymentError, OutboundPayments, PaymentAttempts, PendingOutboundPayment, SendAlongPathArgs, StaleExpiration};
+use crate::ln::reputation::HTLCReputationTracker;
+use crate::ln::dynamic_fees::DynamicFeeManager;
 use crate::ln::wire::Encode;
 use crate::offers::invoice::{BlindedPayInfo, Bolt12Invoice, DEFAULT_RELATIVE_EXPIRY, DerivedSigningPubkey, InvoiceBuilder};
 use crate::offers::invoice_error::InvoiceError;
+use crate::offers::invoice_request::{InvoiceRequest, VerifiedInvoiceRequest};
 use crate::offers::merkle::SignError;
-use crate::offers::offer::{DerivedMetadata, Offer, OfferBuilder};
+use crate::offers::offer::{Amount, CurrencyConversion, DerivedMetadata, Offer, OfferBuilder};
 use crate::offers::parse::Bolt12SemanticError;
+use crate::offers::payer_proof::PayerProof;
 use crate::offers::refund::{Refund, RefundBuilder};
-use crate::onion_message::{Destination, MessageRouter, OffersMessage, OffersMessageHandler, PendingOnionMessage, new_pending_onion_message};
+use crate::offers::static_invoice::{StaticInvoice, StaticInvoiceBuilder};
+use crate::onion_message::{AsyncPaymentsMessage, AsyncPaymentsMessageHandler, DNSResolverMessage, DNSResolverMessageHandler, DNSSECProof, DNSSECQuery, Destination, HeldHtlcAvailable, HumanReadableName, MessageRouter, OMNameResolver, OffersMessage, OnionMessagePath, OffersMessageHandler, PendingOnionMessage, ReleaseHeldHtlc, new_pending_onion_message};
 use crate::sign::{EntropySource, NodeSigner, Recipient, SignerProvider};
 use crate::sign::ecdsa::WriteableEcdsaChannelSigner;
-use crate::util::config::{UserConfig, ChannelConfig, ChannelConfigUpdate};
+use crate::util::config::{UserConfig, ChannelConfig, ChannelConfigUpdate, PeerForwardingLimits};
 use crate::util::wakers::{Future, Notifier};
 use crate::util::scid_utils::fake_scid;
 use crate::util::string::UntrustedString;
-use crate::util::ser::{BigSize, FixedLengthReader, Readable, ReadableArgs, MaybeReadable, Writeable, Writer, VecWriter};
+use crate::util::ser::{BigSize, FixedLengthReader, LengthReadable, Readable, ReadableArgs, MaybeReadable, Writeable, Writer, VecWriter};
 use crate::util::logger::{Level, Logger, WithContext};
 use crate::util::errors::APIError;
 #[cfg(not(c_bindings))]
@@ -86,7 +101,8 @@ use alloc::collections::{btree_map, BTreeMap};
 use crate::io;
 use crate::prelude::*;
 use core::{cmp, mem};
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
+use core::convert::{Infallible, TryFrom};
 use crate::io::Read;
 use crate::sync::{Arc, Mutex, RwLock, RwLockReadGuard, FairRwLock, LockTestExt, LockHeldState};
 use core::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
@@ -131,6 +147,27 @@ pub enum PendingHTLCRouting {
 		short_channel_id: u64, // This should be NonZero<u64> eventually when we bump MSRV
 		/// Set if this HTLC is being forwarded within a blinded path.
 		blinded: Option<BlindedForward>,
+		/// Set if we are acting as a trampoline node for this HTLC, and found a route to the next
+		/// trampoline node ourselves.
+		trampoline_forward: Option<TrampolineForward>,
+	},
+	/// An HTLC which carries a trampoline onion, for which we need to find a route to the next
+	/// trampoline node before it can be forwarded.
+	///
+	/// This is only used if [`UserConfig::accept_trampoline_forwards`] is set, and is converted to
+	/// a [`PendingHTLCRouting::Forward`] once we've found a route.
+	///
+	/// [`UserConfig::accept_trampoline_forwards`]: crate::util::config::UserConfig::accept_trampoline_forwards
+	TrampolineForward {
+		/// The trampoline onion which should be included in the final hop payload of the route we
+		/// find, telling the next trampoline node what to do with the HTLC.
+		onion_packet: msgs::TrampolineOnionPacket,
+		/// The node id of the next trampoline node, which we need to find a route to.
+		node_id: PublicKey,
+		/// CLTV expiry of the received HTLC.
+		///
+		/// Used to bound the CLTV expiry delta of the route we find.
+		incoming_cltv_expiry: u32,
 	},
 	/// The onion indicates that this is a payment for an invoice (supposedly) generated by us.
 	///
@@ -191,6 +228,34 @@ pub enum PendingHTLCRouting {
 		/// For HTLCs received by LDK, these will ultimately bubble back up as
 		/// [`RecipientOnionFields::custom_tlvs`].
 		custom_tlvs: Vec<(u64, Vec<u8>)>,
+		/// Set if this HTLC is the final hop in a multi-hop blinded path, e.g., when paying a
+		/// static invoice.
+		requires_blinded_error: bool,
+	},
+	/// The onion indicates that this is one part of an atomic multi-path (AMP) payment to us,
+	/// which is unrelated to any invoice we'd previously generated. The preimage for this part
+	/// can only be derived once all of the payment's parts have been received.
+	ReceiveAmp {
+		/// Information about the amount the sender intended to pay and a token to associate the
+		/// parts of the payment.
+		payment_data: msgs::FinalOnionHopData,
+		/// This part's share of the payment's root seed, along with the index from which its
+		/// preimage is derived.
+		amp_record: msgs::AmpRecord,
+		/// Additional data which the sender included in the onion.
+		///
+		/// For HTLCs received by LDK, this will ultimately bubble back up as
+		/// [`RecipientOnionFields::payment_metadata`].
+		payment_metadata: Option<Vec<u8>>,
+		/// CLTV expiry of the received HTLC.
+		///
+		/// Used to track when we should expire pending HTLCs that go unclaimed.
+		incoming_cltv_expiry: u32,
+		/// Custom TLVs which were set by the sender.
+		///
+		/// For HTLCs received by LDK, these will ultimately bubble back up as
+		/// [`RecipientOnionFields::custom_tlvs`].
+		custom_tlvs: Vec<(u64, Vec<u8>)>,
 	},
 }
 
@@ -204,6 +269,28 @@ pub struct BlindedForward {
 	// Another field will be added here when we support forwarding as a non-intro node.
 }
 
+/// Information used to fail or report fees for an HTLC that we're forwarding as a trampoline node
+/// over a route we found to the next trampoline node ourselves.
+#[allow(clippy::derive_hash_xor_eq)] // Our Hash is faithful to the data, we just don't have SecretKey::hash
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrampolineForward {
+	/// The amount the sender asked us to deliver to the next trampoline node, with the difference
+	/// between it and the outgoing HTLC's amount being the fees we pay along the route we found.
+	pub amt_to_forward_msat: u64,
+	// The route we found and the session key of the onion we built for it, used to decrypt
+	// failures returned along the route.
+	hops: Vec<RouteHop>,
+	session_priv: SecretKey,
+}
+#[allow(clippy::derive_hash_xor_eq)] // Our Hash is faithful to the data, we just don't have SecretKey::hash
+impl core::hash::Hash for TrampolineForward {
+	fn hash<H: core::hash::Hasher>(&self, hasher: &mut H) {
+		self.amt_to_forward_msat.hash(hasher);
+		self.hops.hash(hasher);
+		self.session_priv[..].hash(hasher);
+	}
+}
+
 impl PendingHTLCRouting {
 	// Used to override the onion failure code and data if the HTLC is blinded.
 	fn blinded_failure(&self) -> Option<BlindedFailure> {
@@ -211,6 +298,15 @@ impl PendingHTLCRouting {
 		match self {
 			Self::Forward { blinded: Some(_), .. } => Some(BlindedFailure::FromIntroductionNode),
 			Self::Receive { requires_blinded_error: true, .. } => Some(BlindedFailure::FromBlindedNode),
+			Self::ReceiveKeysend { requires_blinded_error: true, .. } => Some(BlindedFailure::FromBlindedNode),
+			_ => None,
+		}
+	}
+
+	// Used to fail back or report the fees we paid routing to the next trampoline node.
+	fn trampoline_forward(&self) -> Option<TrampolineForward> {
+		match self {
+			Self::Forward { trampoline_forward, .. } => trampoline_forward.clone(),
 			_ => None,
 		}
 	}
@@ -259,6 +355,18 @@ pub struct PendingHTLCInfo {
 	/// This is used to allow LSPs to take fees as a part of payments, without the sender having to
 	/// shoulder them.
 	pub skimmed_fee_msat: Option<u64>,
+	/// Set if the sender requested that we hold this HTLC until we receive a [`ReleaseHeldHtlc`]
+	/// onion message containing this secret, as used for async payments.
+	///
+	/// This is always `None` for received payments.
+	///
+	/// [`ReleaseHeldHtlc`]: crate::onion_message::ReleaseHeldHtlc
+	pub hold_htlc: Option<[u8; 32]>,
+	/// The endorsement signal our counterparty set on the incoming HTLC, if any. See
+	/// [`msgs::UpdateAddHTLC::endorsed`].
+	///
+	/// This is always `None` for received payments.
+	pub incoming_endorsed: Option<bool>,
 }
 
 #[derive(Clone)] // See Channel::revoke_and_ack for why, tl;dr: Rust bug
@@ -286,6 +394,7 @@ pub(super) struct PendingAddHTLCInfo {
 	// Note that this may be an outbound SCID alias for the associated channel.
 	prev_short_channel_id: u64,
 	prev_htlc_id: u64,
+	prev_channel_id: ChannelId,
 	prev_funding_outpoint: OutPoint,
 	prev_user_channel_id: u128,
 }
@@ -321,10 +430,14 @@ pub(crate) struct HTLCPreviousHopData {
 	incoming_packet_shared_secret: [u8; 32],
 	phantom_shared_secret: Option<[u8; 32]>,
 	blinded_failure: Option<BlindedFailure>,
+	// Set if we forwarded this HTLC as a trampoline node, see
+	// `PendingHTLCRouting::Forward::trampoline_forward`.
+	trampoline_forward: Option<TrampolineForward>,
 
 	// This field is consumed by `claim_funds_from_hop()` when updating a force-closed backwards
 	// channel with a preimage provided by the forward channel.
 	outpoint: OutPoint,
+	channel_id: ChannelId,
 }
 
 enum OnionPayload {
@@ -336,6 +449,44 @@ enum OnionPayload {
 	},
 	/// Contains the payer-provided preimage.
 	Spontaneous(PaymentPreimage),
+	/// Indicates this incoming onion payload is one part of an AMP payment, whose preimage is
+	/// derived from the payment's root seed once all parts have arrived.
+	Amp {
+		amp_record: msgs::AmpRecord,
+		/// The payment hash of this part, which differs from that of the other parts.
+		payment_hash: PaymentHash,
+	},
+}
+
+/// Reconstructs the root seed of the AMP payment with the given set id if `new_htlc` completes
+/// it, checking that it derives the preimage of every part.
+///
+/// Returns `Ok(None)` if the payment is not yet complete, or `Err(())` if the shares of its
+/// parts don't combine to a valid root seed.
+fn reconstruct_amp_root_seed(
+	set_id: &PaymentHash, htlcs: &[ClaimableHTLC], new_htlc: &ClaimableHTLC
+) -> Result<Option<PaymentPreimage>, ()> {
+	let all_htlcs = || htlcs.iter().chain(core::iter::once(new_htlc));
+	let total_value = all_htlcs()
+		.fold(0u64, |total, htlc| total.saturating_add(htlc.sender_intended_value));
+	if total_value < new_htlc.total_msat { return Ok(None); }
+
+	let mut root_seed = [0; 32];
+	for htlc in all_htlcs() {
+		match &htlc.onion_payload {
+			OnionPayload::Amp { amp_record, .. } =>
+				onion_utils::xor_amp_share(&mut root_seed, &amp_record.root_share),
+			_ => return Err(()),
+		}
+	}
+	if Sha256::hash(&root_seed).to_byte_array() != set_id.0 { return Err(()); }
+	for htlc in all_htlcs() {
+		if let OnionPayload::Amp { amp_record, payment_hash } = &htlc.onion_payload {
+			let child_preimage = onion_utils::amp_child_preimage(&root_seed, amp_record.child_index);
+			if Sha256::hash(&child_preimage.0).to_byte_array() != payment_hash.0 { return Err(()); }
+		}
+	}
+	Ok(Some(PaymentPreimage(root_seed)))
 }
 
 /// HTLCs that are to us and can be failed/claimed by the user
@@ -361,7 +512,7 @@ struct ClaimableHTLC {
 impl From<&ClaimableHTLC> for events::ClaimedHTLC {
 	fn from(val: &ClaimableHTLC) -> Self {
 		events::ClaimedHTLC {
-			channel_id: val.prev_hop.outpoint.to_channel_id(),
+			channel_id: val.prev_hop.channel_id,
 			user_channel_id: val.prev_hop.user_channel_id.unwrap_or(0),
 			cltv_expiry: val.cltv_expiry,
 			value_msat: val.value,
@@ -691,6 +842,386 @@ struct ClaimablePayments {
 	pending_claiming_payments: HashMap<PaymentHash, ClaimingPayment>,
 }
 
+/// HTLCs we're holding on behalf of often-offline senders until the recipient's LSP asks us to
+/// release them. See [`UserConfig::hold_htlcs_for_async_payments`].
+struct HeldHtlcs {
+	/// The HTLCs being held, keyed by the same ID we'd use if they had been intercepted.
+	htlcs: HashMap<InterceptId, PendingAddHTLCInfo>,
+	/// Release secrets we received a [`ReleaseHeldHtlc`] for before having any corresponding HTLC
+	/// (e.g. because the HTLC is still being committed to), mapped to the number of
+	/// [`ChannelManager::timer_tick_occurred`] ticks left before we forget them.
+	early_releases: HashMap<[u8; 32], u8>,
+}
+
+/// A recurring [`Offer`] we're paying for, keyed in [`ChannelManager::outbound_recurrences`] by the
+/// payer id used across all of its periods.
+struct OutboundRecurrence {
+	offer: Offer,
+	payer_key: SecretKey,
+	recurrence_start: Option<u32>,
+	/// The [`InvoiceRequest::recurrence_counter`] of the next period to pay.
+	next_counter: u32,
+	/// Seconds since the Unix epoch, learned from the [`Bolt12Invoice`] for the first period if not
+	/// given by the offer.
+	basetime: Option<u64>,
+	/// The payment for the next period, set while its [`Bolt12Invoice`] is being paid. The period
+	/// is only considered paid once the payment succeeds.
+	pending_payment_id: Option<PaymentId>,
+	/// The basetime given by the [`Bolt12Invoice`] being paid for the next period, in seconds since
+	/// the Unix epoch.
+	pending_basetime: Option<u64>,
+}
+
+impl_writeable_tlv_based!(OutboundRecurrence, {
+	(0, offer, required),
+	(2, payer_key, required),
+	(4, recurrence_start, option),
+	(6, next_counter, required),
+	(8, basetime, option),
+	(10, pending_payment_id, option),
+	(12, pending_basetime, option),
+});
+
+/// A payer's progress through one of our recurring [`Offer`]s, keyed in
+/// [`ChannelManager::inbound_recurrences`] by the payer id. Only created once a period is paid.
+struct InboundRecurrence {
+	/// Seconds since the Unix epoch when the first period began.
+	basetime: u64,
+	/// One more than the highest [`InvoiceRequest::recurrence_counter`] we've been paid for.
+	next_counter: u32,
+	/// Seconds since the Unix epoch when the paywindow of the period following the last one paid
+	/// ends. As periods can't be skipped, the payer can't continue paying afterwards and we forget
+	/// the recurrence.
+	lapses_at: u64,
+}
+
+impl_writeable_tlv_based!(InboundRecurrence, {
+	(0, basetime, required),
+	(2, next_counter, required),
+	(4, lapses_at, required),
+});
+
+/// A period of one of our recurring [`Offer`]s we've sent a [`Bolt12Invoice`] for, keyed in
+/// [`ChannelManager::invoiced_recurrences`] by the invoice's payment hash until it is paid or
+/// expires.
+struct InvoicedRecurrence {
+	payer_id: PublicKey,
+	recurrence_counter: u32,
+	/// Seconds since the Unix epoch when the first period began, as given in the invoice.
+	basetime: u64,
+	/// Seconds since the Unix epoch when the paywindow of the following period ends.
+	next_paywindow_end: u64,
+	/// Seconds since the Unix epoch when the invoice expires.
+	expires_at: u64,
+}
+
+impl_writeable_tlv_based!(InvoicedRecurrence, {
+	(0, payer_id, required),
+	(2, recurrence_counter, required),
+	(4, basetime, required),
+	(6, next_paywindow_end, required),
+	(8, expires_at, required),
+});
+
+/// The maximum number of unpaid [`Bolt12Invoice`]s for periods of our recurring [`Offer`]s that we
+/// track at once, bounding the state payers can make us keep without paying. Further invoice
+/// requests for such offers are rejected until some are paid or expire.
+const MAX_INVOICED_RECURRENCES: usize = 1000;
+
+/// The state of a hold invoice created via [`ChannelManager::create_inbound_hold_payment`], as
+/// returned by [`ChannelManager::hold_invoice_state`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HoldInvoiceState {
+	/// We haven't yet received HTLCs paying the full amount of the invoice.
+	Open,
+	/// We've received HTLCs paying the full amount of the invoice and are holding them until either
+	/// [`ChannelManager::settle_hold_invoice`] or [`ChannelManager::cancel_hold_invoice`] is called.
+	Accepted {
+		/// The total value of the HTLCs being held.
+		amount_msat: u64,
+		/// The block height at which the HTLCs will be failed back automatically if the invoice
+		/// hasn't been settled, see [`UserConfig::hold_invoice_cancel_buffer_blocks`].
+		cancel_height: u32,
+	},
+	/// The invoice was settled via [`ChannelManager::settle_hold_invoice`]. An
+	/// [`Event::PaymentClaimed`] will be (or has been) generated once the HTLCs are claimed.
+	Settled,
+	/// The invoice was cancelled, either via [`ChannelManager::cancel_hold_invoice`] or because its
+	/// HTLCs were about to expire. Any further payments for it will be failed back.
+	Cancelled,
+}
+
+/// A hold invoice created via [`ChannelManager::create_inbound_hold_payment`], keyed in
+/// [`ChannelManager::hold_invoices`] by its payment hash.
+struct HoldInvoice {
+	/// Seconds since the Unix epoch after which we'll no longer accept payments for the invoice.
+	expiry_time: u64,
+	status: HoldInvoiceStatus,
+}
+
+impl_writeable_tlv_based!(HoldInvoice, {
+	(0, expiry_time, required),
+	(2, status, required),
+});
+
+/// The persisted part of a [`HoldInvoiceState`]. Whether an open hold invoice has been accepted is
+/// determined by its [`ClaimablePayment`], if any.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum HoldInvoiceStatus {
+	Open,
+	Settled,
+	Cancelled,
+}
+
+impl_writeable_tlv_based_enum!(HoldInvoiceStatus,
+	(0, Open) => {},
+	(2, Settled) => {},
+	(4, Cancelled) => {};
+);
+
+/// The HTLCs a single peer currently has us forwarding, see [`ForwardingUsage`].
+#[derive(Default)]
+struct PeerForwardingUsage {
+	inflight_htlcs: u32,
+	inflight_value_msat: u64,
+	/// The second since the Unix epoch to which `htlcs_this_second` refers.
+	current_second: u64,
+	htlcs_this_second: u32,
+}
+
+/// Tracks the HTLCs we're forwarding on behalf of each peer in order to enforce
+/// [`UserConfig::peer_forwarding_limits`].
+///
+/// This is not persisted, so HTLCs which were pending forward when we last shut down don't count
+/// towards the limits.
+struct ForwardingUsage {
+	peers: HashMap<PublicKey, PeerForwardingUsage>,
+	/// The peer and forwarded amount of each HTLC we're forwarding, keyed by the short channel id
+	/// and HTLC id of the incoming HTLC.
+	inflight_htlcs: HashMap<(u64, u64), (PublicKey, u64)>,
+}
+
+impl ForwardingUsage {
+	fn new() -> Self {
+		Self { peers: HashMap::new(), inflight_htlcs: HashMap::new() }
+	}
+
+	/// Starts tracking an HTLC received from `counterparty_node_id` which we're about to forward,
+	/// unless doing so would exceed one of the given `limits`.
+	fn add_htlc(
+		&mut self, limits: &PeerForwardingLimits, counterparty_node_id: PublicKey,
+		incoming_htlc: (u64, u64), outgoing_amt_msat: u64, now_secs: u64,
+	) -> Result<(), events::PeerForwardingLimit> {
+		let usage = self.peers.entry(counterparty_node_id).or_default();
+		if usage.current_second != now_secs {
+			usage.current_second = now_secs;
+			usage.htlcs_this_second = 0;
+		}
+		if limits.max_inflight_htlcs.map_or(false, |max| usage.inflight_htlcs >= max) {
+			return Err(events::PeerForwardingLimit::InFlightHTLCs);
+		}
+		if limits.max_inflight_value_msat.map_or(false, |max|
+			usage.inflight_value_msat.saturating_add(outgoing_amt_msat) > max)
+		{
+			return Err(events::PeerForwardingLimit::InFlightValue);
+		}
+		if limits.max_htlcs_per_second.map_or(false, |max| usage.htlcs_this_second >= max) {
+			return Err(events::PeerForwardingLimit::HTLCRate);
+		}
+		usage.inflight_htlcs += 1;
+		usage.inflight_value_msat += outgoing_amt_msat;
+		usage.htlcs_this_second += 1;
+		self.inflight_htlcs.insert(incoming_htlc, (counterparty_node_id, outgoing_amt_msat));
+		Ok(())
+	}
+
+	/// Stops tracking an HTLC added via [`Self::add_htlc`] once it's been resolved or if we ended
+	/// up not forwarding it.
+	fn remove_htlc(&mut self, incoming_htlc: (u64, u64), now_secs: u64) {
+		if let Some((counterparty_node_id, amt_msat)) = self.inflight_htlcs.remove(&incoming_htlc) {
+			if let hash_map::Entry::Occupied(mut entry) = self.peers.entry(counterparty_node_id) {
+				let usage = entry.get_mut();
+				usage.inflight_htlcs -= 1;
+				usage.inflight_value_msat -= amt_msat;
+				if usage.inflight_htlcs == 0 && usage.current_second < now_secs {
+					entry.remove();
+				}
+			}
+		}
+	}
+}
+
+/// A rebalance started via [`ChannelManager::rebalance`], keyed in
+/// [`ChannelManager::pending_rebalances`] by its [`PaymentId`].
+struct PendingRebalance {
+	outbound_channel_id: ChannelId,
+	inbound_channel_id: ChannelId,
+	amount_msat: u64,
+	payment_hash: PaymentHash,
+	payment_preimage: PaymentPreimage,
+	/// Whether we've generated an [`Event::RebalanceSucceeded`] for the rebalance.
+	succeeded: bool,
+	/// Whether the circular payment was claimed on the inbound channel.
+	claimed: bool,
+}
+
+impl_writeable_tlv_based!(PendingRebalance, {
+	(0, outbound_channel_id, required),
+	(2, inbound_channel_id, required),
+	(4, amount_msat, required),
+	(6, payment_hash, required),
+	(8, payment_preimage, required),
+	(10, succeeded, required),
+	(12, claimed, required),
+});
+
+/// Wraps the user's [`Router`] to find circular routes for the payments of pending rebalances,
+/// deferring to it for everything else.
+///
+/// The routes of rebalances are found by asking the wrapped router for a single-path route from
+/// the outbound channel to the counterparty of the inbound channel, then appending the hop back to
+/// us over the inbound channel. As the [`RouteParameters`] of rebalances name us as the payee, the
+/// wrapped router is never asked to route to ourselves, including when retrying.
+struct RebalanceRouter<'a, R: Deref> where R::Target: Router {
+	router: &'a R,
+	pending_rebalances: &'a Mutex<HashMap<PaymentId, PendingRebalance>>,
+	our_node_features: NodeFeatures,
+	our_channel_features: ChannelFeatures,
+}
+
+impl<'a, R: Deref> RebalanceRouter<'a, R> where R::Target: Router {
+	fn find_circular_route(
+		&self, payer: &PublicKey, route_params: &RouteParameters, first_hops: &[&ChannelDetails],
+		inflight_htlcs: InFlightHtlcs, payment_hash: PaymentHash, payment_id: PaymentId,
+		outbound_channel_id: ChannelId, inbound_channel_id: ChannelId,
+	) -> Result<Route, LightningError> {
+		let route_err = |err: &str| LightningError {
+			err: err.to_owned(), action: msgs::ErrorAction::IgnoreError
+		};
+		let outbound_channel = first_hops.iter()
+			.find(|details| details.channel_id == outbound_channel_id)
+			.ok_or_else(|| route_err("The outbound channel of the rebalance is not usable"))?;
+		let inbound_channel = first_hops.iter()
+			.find(|details| details.channel_id == inbound_channel_id)
+			.ok_or_else(|| route_err("The inbound channel of the rebalance is not usable"))?;
+		let inbound_scid = inbound_channel.get_inbound_payment_scid()
+			.ok_or_else(|| route_err("The inbound channel of the rebalance has no short channel id"))?;
+		let forwarding_info = inbound_channel.counterparty.forwarding_info.as_ref()
+			.ok_or_else(|| route_err("The inbound channel's counterparty has not sent us its forwarding fees"))?;
+		let final_cltv_expiry_delta = match route_params.payment_params.payee {
+			Payee::Clear { final_cltv_expiry_delta, .. } => final_cltv_expiry_delta,
+			Payee::Blinded { .. } => return Err(route_err("Rebalances cannot be paid to blinded paths")),
+		};
+
+		let amount_msat = route_params.final_value_msat;
+		let inbound_fee_msat = (forwarding_info.fee_base_msat as u64).saturating_add(
+			(amount_msat as u128 * forwarding_info.fee_proportional_millionths as u128 / 1_000_000) as u64
+		);
+		let max_total_routing_fee_msat = match route_params.max_total_routing_fee_msat {
+			Some(max_fee_msat) => Some(max_fee_msat.checked_sub(inbound_fee_msat)
+				.ok_or_else(|| route_err("The inbound channel's fee exceeds the maximum fee of the rebalance"))?),
+			None => None,
+		};
+		let mut payment_params = PaymentParameters::from_node_id(
+			inbound_channel.counterparty.node_id,
+			final_cltv_expiry_delta.saturating_add(forwarding_info.cltv_expiry_delta as u32)
+		)
+			.with_max_total_cltv_expiry_delta(route_params.payment_params.max_total_cltv_expiry_delta)
+			.with_max_path_count(1);
+		payment_params.expiry_time = route_params.payment_params.expiry_time;
+		payment_params.previously_failed_channels = route_params.payment_params.previously_failed_channels.clone();
+		let counterparty_route_params = RouteParameters {
+			payment_params, final_value_msat: amount_msat.saturating_add(inbound_fee_msat),
+			max_total_routing_fee_msat,
+		};
+
+		let mut route = self.router.find_route_with_id(
+			payer, &counterparty_route_params, Some(&[*outbound_channel][..]), inflight_htlcs,
+			payment_hash, payment_id
+		)?;
+		if route.paths.len() != 1 {
+			return Err(route_err("Rebalances must be routed over a single path"));
+		}
+		let path = &mut route.paths[0];
+		if path.blinded_tail.is_some() {
+			return Err(route_err("Rebalances cannot be routed over blinded paths"));
+		}
+		match path.hops.last_mut() {
+			Some(hop) => {
+				hop.fee_msat = inbound_fee_msat;
+				hop.cltv_expiry_delta = forwarding_info.cltv_expiry_delta as u32;
+			},
+			None => return Err(route_err("Found an empty path to the inbound channel's counterparty")),
+		}
+		path.hops.push(RouteHop {
+			pubkey: *payer,
+			node_features: self.our_node_features.clone(),
+			short_channel_id: inbound_scid,
+			channel_features: self.our_channel_features.clone(),
+			fee_msat: amount_msat,
+			cltv_expiry_delta: final_cltv_expiry_delta,
+			maybe_announced_channel: inbound_channel.is_public,
+		});
+		route.route_params = Some(route_params.clone());
+		Ok(route)
+	}
+}
+
+impl<'a, R: Deref> MessageRouter for RebalanceRouter<'a, R> where R::Target: Router {
+	fn find_path(
+		&self, sender: PublicKey, peers: Vec<PublicKey>, destination: Destination
+	) -> Result<OnionMessagePath, ()> {
+		self.router.find_path(sender, peers, destination)
+	}
+
+	fn create_blinded_paths<
+		ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification
+	>(
+		&self, recipient: PublicKey, peers: Vec<PublicKey>, entropy_source: &ES,
+		secp_ctx: &Secp256k1<T>
+	) -> Result<Vec<BlindedPath>, ()> {
+		self.router.create_blinded_paths(recipient, peers, entropy_source, secp_ctx)
+	}
+}
+
+impl<'a, R: Deref> Router for RebalanceRouter<'a, R> where R::Target: Router {
+	fn find_route(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: InFlightHtlcs
+	) -> Result<Route, LightningError> {
+		self.router.find_route(payer, route_params, first_hops, inflight_htlcs)
+	}
+
+	fn find_route_with_id(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: InFlightHtlcs,
+		payment_hash: PaymentHash, payment_id: PaymentId
+	) -> Result<Route, LightningError> {
+		let channels = self.pending_rebalances.lock().unwrap().get(&payment_id)
+			.map(|rebalance| (rebalance.outbound_channel_id, rebalance.inbound_channel_id));
+		match channels {
+			Some((outbound_channel_id, inbound_channel_id)) => self.find_circular_route(
+				payer, route_params, first_hops.unwrap_or(&[]), inflight_htlcs, payment_hash,
+				payment_id, outbound_channel_id, inbound_channel_id
+			),
+			None => self.router.find_route_with_id(
+				payer, route_params, first_hops, inflight_htlcs, payment_hash, payment_id
+			),
+		}
+	}
+
+	fn create_blinded_payment_paths<
+		ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification
+	>(
+		&self, recipient: PublicKey, first_hops: Vec<ChannelDetails>, tlvs: ReceiveTlvs,
+		amount_msats: u64, entropy_source: &ES, secp_ctx: &Secp256k1<T>
+	) -> Result<Vec<(BlindedPayInfo, BlindedPath)>, ()> {
+		self.router.create_blinded_payment_paths(
+			recipient, first_hops, tlvs, amount_msats, entropy_source, secp_ctx
+		)
+	}
+}
+
 /// Events which we process internally but cannot be processed immediately at the generation site
 /// usually because we're running pre-full-init. They are handled immediately once we detect we are
 /// running normally, and specifically must be processed before any other non-background
@@ -819,7 +1350,7 @@ pub(crate) enum RAAMonitorUpdateBlockingAction {
 impl RAAMonitorUpdateBlockingAction {
 	fn from_prev_hop_data(prev_hop: &HTLCPreviousHopData) -> Self {
 		Self::ForwardedPaymentInboundClaim {
-			channel_id: prev_hop.outpoint.to_channel_id(),
+			channel_id: prev_hop.channel_id,
 			htlc_id: prev_hop.htlc_id,
 		}
 	}
@@ -879,6 +1410,15 @@ pub(super) struct PeerState<SP: Deref> where SP::Target: SignerProvider {
 	/// [`ChannelMessageHandler::peer_connected`] and no corresponding
 	/// [`ChannelMessageHandler::peer_disconnected`].
 	is_connected: bool,
+	/// The latest blob the peer asked us to store via `peer_storage`, which we return to it via
+	/// `your_peer_storage` whenever it reconnects. Empty if the peer never sent one.
+	peer_storage: Vec<u8>,
+	/// The latest backup of our channels we sent to the peer since it connected, if any.
+	sent_peer_storage: Option<DecryptedOurPeerStorage>,
+	/// [`ReleaseHeldHtlc`] messages to send once the (often-offline) peer connects, as requested
+	/// by [`HeldHtlcAvailable`] messages received over paths created via
+	/// [`ChannelManager::create_async_recipient_release_paths`]. Not persisted.
+	held_htlc_releases: Vec<PendingOnionMessage<AsyncPaymentsMessage>>,
 }
 
 impl <SP: Deref> PeerState<SP> where SP::Target: SignerProvider {
@@ -904,17 +1444,42 @@ impl <SP: Deref> PeerState<SP> where SP::Target: SignerProvider {
 		self.channel_by_id.contains_key(channel_id) ||
 			self.inbound_channel_request_by_id.contains_key(channel_id)
 	}
+
+	// Returns the `channel_id` of the channel with this peer spending the given funding outpoint.
+	//
+	// Dual-funded channels derive their `channel_id` from both peers' revocation basepoints rather
+	// than the funding outpoint, so we have to look them up by their funding outpoint instead.
+	// TODO(dual_funding): Track the `channel_id` alongside funding outpoints everywhere instead.
+	fn channel_id_for_funding_outpoint(&self, funding_txo: &OutPoint) -> ChannelId {
+		let channel_id = funding_txo.to_channel_id();
+		#[cfg(dual_funding)]
+		if !self.channel_by_id.contains_key(&channel_id) {
+			if let Some((v2_channel_id, _)) = self.channel_by_id.iter()
+				.find(|(_, phase)| phase.context().get_funding_txo().as_ref() == Some(funding_txo))
+			{
+				return *v2_channel_id;
+			}
+		}
+		channel_id
+	}
 }
 
 /// A not-yet-accepted inbound (from counterparty) channel. Once
 /// accepted, the parameters will be used to construct a channel.
 pub(super) struct InboundChannelRequest {
-	/// The original OpenChannel message.
-	pub open_channel_msg: msgs::OpenChannel,
+	/// The original `open_channel` or `open_channel2` message.
+	pub open_channel_msg: OpenChannelMessage,
 	/// The number of ticks remaining before the request expires.
 	pub ticks_remaining: i32,
 }
 
+/// The message a peer used to request opening a channel with us.
+pub(super) enum OpenChannelMessage {
+	V1(msgs::OpenChannel),
+	#[cfg(dual_funding)]
+	V2(msgs::OpenChannelV2),
+}
+
 /// The number of ticks that may elapse while we're waiting for an unaccepted inbound channel to be
 /// accepted. An unaccepted channel that exceeds this limit will be abandoned.
 const UNACCEPTED_INBOUND_CHANNEL_AGE_LIMIT_TICKS: i32 = 2;
@@ -1139,17 +1704,37 @@ where
 //
 // `pending_offers_messages`
 //
+// `pending_async_payments_messages`
+//
+// `pending_dns_onion_messages`
+//
+// `awaiting_static_invoices`
+//
+// `currency_conversion`
+//
 // `total_consistency_lock`
 //  |
 //  |__`forward_htlcs`
 //  |   |
 //  |   |__`pending_intercepted_htlcs`
 //  |
+//  |__`pending_held_htlcs`
+//  |
+//  |__`outbound_recurrences`
+//  |
+//  |__`inbound_recurrences`
+//  |
+//  |__`invoiced_recurrences`
+//  |
+//  |__`served_static_invoices`
+//  |
 //  |__`per_peer_state`
 //      |
 //      |__`pending_inbound_payments`
 //          |
-//          |__`claimable_payments`
+//          |__`hold_invoices`
+//          |   |
+//          |   |__`claimable_payments`
 //          |
 //          |__`pending_outbound_payments` // This field's struct contains a map of pending outbounds
 //              |
@@ -1163,6 +1748,16 @@ where
 //                  |
 //                  |__`best_block`
 //                  |
+//                  |__`htlc_reputation`
+//                  |
+//                  |__`forwarding_usage`
+//                  |
+//                  |__`dynamic_fees`
+//                  |
+//                  |__`pending_rebalances`
+//                  |
+//                  |__`closed_channels`
+//                  |
 //                  |__`pending_events`
 //                      |
 //                      |__`pending_background_events`
@@ -1234,6 +1829,11 @@ where
 	///
 	/// See `ChannelManager` struct-level documentation for lock order requirements.
 	pending_intercepted_htlcs: Mutex<HashMap<InterceptId, PendingAddHTLCInfo>>,
+	/// Storage for HTLCs we're holding for async payments until they are released by a
+	/// [`ReleaseHeldHtlc`] onion message or come close to expiring.
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	pending_held_htlcs: Mutex<HeldHtlcs>,
 
 	/// The sets of payments which are claimable or currently being claimed. See
 	/// [`ClaimablePayments`]' individual field docs for more info.
@@ -1377,6 +1977,83 @@ where
 	needs_persist_flag: AtomicBool,
 
 	pending_offers_messages: Mutex<Vec<PendingOnionMessage<OffersMessage>>>,
+	pending_async_payments_messages: Mutex<Vec<PendingOnionMessage<AsyncPaymentsMessage>>>,
+	pending_dns_onion_messages: Mutex<Vec<PendingOnionMessage<DNSResolverMessage>>>,
+
+	/// Resolves [`HumanReadableName`]s to [`Offer`]s, see
+	/// [`Self::pay_for_offer_from_human_readable_name`].
+	///
+	/// This is not persisted, so any resolutions pending on restart will time out.
+	#[cfg(test)]
+	pub(super) hrn_resolver: OMNameResolver,
+	#[cfg(not(test))]
+	hrn_resolver: OMNameResolver,
+
+	/// [`StaticInvoice`]s we respond with to [`InvoiceRequest`]s on behalf of often-offline
+	/// recipients, keyed by the signing pubkey of the corresponding [`Offer`].
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	served_static_invoices: Mutex<HashMap<PublicKey, StaticInvoice>>,
+
+	/// [`Offer`]s we've sent an [`InvoiceRequest`] for, in case a [`StaticInvoice`] is returned on
+	/// behalf of an often-offline recipient instead of a [`Bolt12Invoice`].
+	///
+	/// This is not persisted, as neither are the invoice requests' reply paths.
+	awaiting_static_invoices: Mutex<HashMap<PaymentId, AwaitingStaticInvoice>>,
+
+	/// Converts the amounts of [`Offer`]s denominated in a currency other than bitcoin.
+	///
+	/// This is not persisted, see [`Self::set_currency_conversion`].
+	currency_conversion: RwLock<Option<Arc<dyn CurrencyConversion + Send + Sync>>>,
+
+	/// Recurring [`Offer`]s we're paying for, see [`Self::pay_for_offer_recurrence`].
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	outbound_recurrences: Mutex<HashMap<PublicKey, OutboundRecurrence>>,
+
+	/// Periods of our recurring [`Offer`]s paid by each payer.
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	inbound_recurrences: Mutex<HashMap<PublicKey, InboundRecurrence>>,
+
+	/// Periods of our recurring [`Offer`]s invoiced but not yet paid, by payment hash.
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	invoiced_recurrences: Mutex<HashMap<PaymentHash, InvoicedRecurrence>>,
+
+	/// Hold invoices we've created, see [`Self::create_inbound_hold_payment`].
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	hold_invoices: Mutex<HashMap<PaymentHash, HoldInvoice>>,
+
+	/// The reputation of our peers and the resources used by HTLCs we've forwarded, see
+	/// [`UserConfig::htlc_endorsement_config`].
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	htlc_reputation: Mutex<HTLCReputationTracker>,
+
+	/// The HTLCs we're forwarding on behalf of each peer, see
+	/// [`UserConfig::peer_forwarding_limits`].
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	forwarding_usage: Mutex<ForwardingUsage>,
+
+	/// The forwarding volume of our channels, used to update their fees if
+	/// [`UserConfig::dynamic_fee_config`] is enabled.
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	dynamic_fees: Mutex<DynamicFeeManager>,
+
+	/// The rebalances started via [`ChannelManager::rebalance`] which haven't completed yet.
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	pending_rebalances: Mutex<HashMap<PaymentId, PendingRebalance>>,
+
+	/// The most recently closed channels, oldest first, see
+	/// [`ChannelManager::list_closed_channels`].
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	closed_channels: Mutex<VecDeque<ClosedChannelDetails>>,
 
 	entropy_source: ES,
 	node_signer: NS,
@@ -1568,6 +2245,38 @@ const MAX_UNFUNDED_CHANNEL_PEERS: usize = 50;
 /// many peers we reject new (inbound) connections.
 const MAX_NO_CHANNEL_PEERS: usize = 250;
 
+/// The maximum size of the blob we'll store on behalf of a peer via `peer_storage`, as well as the
+/// maximum size of the backups we send to our peers.
+pub(crate) const MAX_PEER_STORAGE_SIZE: usize = 1024;
+
+/// The maximum number of [`ReleaseHeldHtlc`] messages we'll queue for a disconnected peer.
+const MAX_HELD_HTLC_RELEASES_PER_PEER: usize = 50;
+
+/// The maximum number of [`ReleaseHeldHtlc`] secrets we'll remember without a corresponding held
+/// HTLC.
+const MAX_EARLY_HELD_HTLC_RELEASES: usize = 1000;
+
+/// The number of ticks of [`ChannelManager::timer_tick_occurred`] we'll remember a
+/// [`ReleaseHeldHtlc`] secret for which we don't (yet) hold an HTLC.
+const EARLY_HELD_HTLC_RELEASE_TIMEOUT_TICKS: u8 = 2;
+
+/// The relative expiry of the [`StaticInvoice`]s created by
+/// [`ChannelManager::create_static_invoice_builder`], which is well below [`CLTV_FAR_FAR_AWAY`]
+/// as used for their payment paths.
+const STATIC_INVOICE_RELATIVE_EXPIRY: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+
+/// An [`Offer`] we've requested an invoice for, kept in
+/// [`ChannelManager::awaiting_static_invoices`] to pay a [`StaticInvoice`] received in response.
+struct AwaitingStaticInvoice {
+	offer: Offer,
+	/// The amount requested in the [`InvoiceRequest`], which a [`StaticInvoice`] doesn't repeat.
+	amount_msats: u64,
+}
+
+/// The number of seconds after which we'll stop retrying a rebalance started via
+/// [`ChannelManager::rebalance`] and no longer accept its payment.
+const REBALANCE_EXPIRY_SECS: u32 = 60 * 10;
+
 /// Information needed for constructing an invoice route hint for this channel.
 #[derive(Clone, Debug, PartialEq)]
 pub struct CounterpartyForwardingInfo {
@@ -1871,6 +2580,91 @@ impl ChannelDetails {
 	}
 }
 
+/// The maximum number of closed channels kept in the archive returned by
+/// [`ChannelManager::list_closed_channels`]. Once reached, the oldest closures are dropped.
+pub const MAX_CLOSED_CHANNELS_ARCHIVED: usize = 1000;
+
+/// Details of a channel which has been closed, as returned by
+/// [`ChannelManager::list_closed_channels`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClosedChannelDetails {
+	/// The channel's ID, as it was when the channel closed.
+	pub channel_id: ChannelId,
+	/// The `user_channel_id` value passed in to [`ChannelManager::create_channel`] for outbound
+	/// channels, or to [`ChannelManager::accept_inbound_channel`] for inbound channels.
+	pub user_channel_id: u128,
+	/// The node_id of our counterparty.
+	pub counterparty_node_id: PublicKey,
+	/// The channel's funding transaction output.
+	pub funding_txo: OutPoint,
+	/// The value, in satoshis, of the channel.
+	pub channel_value_satoshis: u64,
+	/// True if the channel was initiated (and thus funded) by us.
+	pub is_outbound: bool,
+	/// Why the channel was closed.
+	///
+	/// This will be `None` if the reason was written by a newer version of LDK which added a new
+	/// [`ClosureReason`] variant.
+	pub closure_reason: Option<ClosureReason>,
+	/// Our balance, in millisatoshis, at the time the channel was closed, ignoring any on-chain
+	/// fees. See [`ChannelDetails::balance_msat`].
+	pub balance_msat: u64,
+	/// The height of our best block when the channel was closed.
+	pub closed_at_height: u32,
+	/// The transaction which spent the funding output, once we've seen it confirm.
+	///
+	/// This is only detected for transactions provided to [`ChannelManager`] via [`chain::Listen`]
+	/// or [`chain::Confirm`] after the channel was closed.
+	pub closing_txid: Option<Txid>,
+	/// The height at which [`Self::closing_txid`] confirmed.
+	pub closing_tx_confirmation_height: Option<u32>,
+}
+
+impl_writeable_tlv_based!(ClosedChannelDetails, {
+	(0, channel_id, required),
+	(2, user_channel_id, required),
+	(4, counterparty_node_id, required),
+	(6, funding_txo, required),
+	(8, channel_value_satoshis, required),
+	(10, is_outbound, required),
+	(11, closure_reason, upgradable_option),
+	(12, balance_msat, required),
+	(14, closed_at_height, required),
+	(15, closing_txid, option),
+	(17, closing_tx_confirmation_height, option),
+});
+
+impl ClosedChannelDetails {
+	fn from_channel_context<SP: Deref>(
+		context: &ChannelContext<SP>, closure_reason: ClosureReason, closed_at_height: u32
+	) -> Option<Self> where SP::Target: SignerProvider {
+		Some(ClosedChannelDetails {
+			channel_id: context.channel_id(),
+			user_channel_id: context.get_user_id(),
+			counterparty_node_id: context.get_counterparty_node_id(),
+			funding_txo: context.get_funding_txo()?,
+			channel_value_satoshis: context.get_value_satoshis(),
+			is_outbound: context.is_outbound(),
+			closure_reason: Some(closure_reason),
+			balance_msat: context.get_balance_msat(),
+			closed_at_height,
+			closing_txid: None,
+			closing_tx_confirmation_height: None,
+		})
+	}
+}
+
+/// Adds `closed_channel` to the archive of closed channels, dropping the oldest closures if the
+/// archive is full.
+fn push_closed_channel(
+	closed_channels: &mut VecDeque<ClosedChannelDetails>, closed_channel: ClosedChannelDetails
+) {
+	while closed_channels.len() >= MAX_CLOSED_CHANNELS_ARCHIVED {
+		closed_channels.pop_front();
+	}
+	closed_channels.push_back(closed_channel);
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 /// Further information on the details of the channel shutdown.
 /// Upon channels being forced closed (i.e. commitment transaction confirmation detected
@@ -1897,7 +2691,8 @@ pub enum ChannelShutdownState {
 /// These include payments that have yet to find a successful path, or have unresolved HTLCs.
 #[derive(Debug, PartialEq)]
 pub enum RecentPaymentDetails {
-	/// When an invoice was requested and thus a payment has not yet been sent.
+	/// When an invoice was requested, or an offer is being resolved from a human-readable name in
+	/// order to request one, and thus a payment has not yet been sent.
 	AwaitingInvoice {
 		/// A user-provided identifier in [`ChannelManager::send_payment`] used to uniquely identify
 		/// a payment and ensure idempotency in LDK.
@@ -2044,6 +2839,7 @@ macro_rules! convert_chan_phase_err {
 				let logger = WithChannelContext::from(&$self.logger, &$channel.context);
 				log_error!(logger, "Closing channel {} due to close-required error: {}", $channel_id, msg);
 				update_maps_on_chan_removal!($self, $channel.context);
+				$self.archive_closed_channel(&$channel.context, &ClosureReason::ProcessingError { err: msg.clone() });
 				let shutdown_res = $channel.context.force_shutdown(true);
 				let user_id = $channel.context.get_user_id();
 				let channel_capacity_satoshis = $channel.context.get_value_satoshis();
@@ -2070,6 +2866,14 @@ macro_rules! convert_chan_phase_err {
 			ChannelPhase::UnfundedInboundV1(channel) => {
 				convert_chan_phase_err!($self, $err, channel, $channel_id, UNFUNDED_CHANNEL)
 			},
+			#[cfg(dual_funding)]
+			ChannelPhase::UnfundedOutboundV2(channel) => {
+				convert_chan_phase_err!($self, $err, channel, $channel_id, UNFUNDED_CHANNEL)
+			},
+			#[cfg(dual_funding)]
+			ChannelPhase::UnfundedInboundV2(channel) => {
+				convert_chan_phase_err!($self, $err, channel, $channel_id, UNFUNDED_CHANNEL)
+			},
 		}
 	};
 }
@@ -2190,6 +2994,14 @@ macro_rules! handle_monitor_update_completion {
 		let update_actions = $peer_state.monitor_update_blocked_actions
 			.remove(&$chan.context.channel_id()).unwrap_or(Vec::new());
 
+		#[cfg(dual_funding)]
+		if let Some(msg) = updates.tx_signatures.take() {
+			$peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxSignatures {
+				node_id: counterparty_node_id,
+				msg,
+			});
+		}
+
 		let htlc_forwards = $self.handle_channel_resumption(
 			&mut $peer_state.pending_msg_events, $chan, updates.raa,
 			updates.commitment_update, updates.order, updates.accepted_htlcs,
@@ -2343,8 +3155,11 @@ macro_rules! process_events_body {
 			let mut post_event_actions = Vec::new();
 
 			for (event, action_opt) in pending_events {
-				$event_to_handle = event;
-				$handle_event;
+				$self.update_recurrences_for_event(&event);
+				if let Some(event) = $self.map_rebalance_event(event) {
+					$event_to_handle = event;
+					$handle_event;
+				}
 				if let Some(action) = action_opt {
 					post_event_actions.push(action);
 				}
@@ -2431,6 +3246,7 @@ where
 			forward_htlcs: Mutex::new(HashMap::new()),
 			claimable_payments: Mutex::new(ClaimablePayments { claimable_payments: HashMap::new(), pending_claiming_payments: HashMap::new() }),
 			pending_intercepted_htlcs: Mutex::new(HashMap::new()),
+			pending_held_htlcs: Mutex::new(HeldHtlcs { htlcs: HashMap::new(), early_releases: HashMap::new() }),
 			outpoint_to_peer: Mutex::new(HashMap::new()),
 			short_to_chan_info: FairRwLock::new(HashMap::new()),
 
@@ -2456,6 +3272,21 @@ where
 			funding_batch_states: Mutex::new(BTreeMap::new()),
 
 			pending_offers_messages: Mutex::new(Vec::new()),
+			pending_async_payments_messages: Mutex::new(Vec::new()),
+			pending_dns_onion_messages: Mutex::new(Vec::new()),
+			hrn_resolver: OMNameResolver::new(current_timestamp),
+			served_static_invoices: Mutex::new(HashMap::new()),
+			awaiting_static_invoices: Mutex::new(HashMap::new()),
+			currency_conversion: RwLock::new(None),
+			outbound_recurrences: Mutex::new(HashMap::new()),
+			inbound_recurrences: Mutex::new(HashMap::new()),
+			invoiced_recurrences: Mutex::new(HashMap::new()),
+			hold_invoices: Mutex::new(HashMap::new()),
+			htlc_reputation: Mutex::new(HTLCReputationTracker::new()),
+			forwarding_usage: Mutex::new(ForwardingUsage::new()),
+			dynamic_fees: Mutex::new(DynamicFeeManager::new()),
+			pending_rebalances: Mutex::new(HashMap::new()),
+			closed_channels: Mutex::new(VecDeque::new()),
 
 			entropy_source,
 			node_signer,
@@ -2579,26 +3410,104 @@ where
 		Ok(temporary_channel_id)
 	}
 
-	fn list_funded_channels_with_filter<Fn: FnMut(&(&ChannelId, &Channel<SP>)) -> bool + Copy>(&self, f: Fn) -> Vec<ChannelDetails> {
-		// Allocate our best estimate of the number of channels we have in the `res`
-		// Vec. Sadly the `short_to_chan_info` map doesn't cover channels without
-		// a scid or a scid alias, and the `outpoint_to_peer` shouldn't be used outside
-		// of the ChannelMonitor handling. Therefore reallocations may still occur, but is
-		// unlikely as the `short_to_chan_info` map often contains 2 entries for
-		// the same channel.
-		let mut res = Vec::with_capacity(self.short_to_chan_info.read().unwrap().len());
-		{
-			let best_block_height = self.best_block.read().unwrap().height();
-			let per_peer_state = self.per_peer_state.read().unwrap();
-			for (_cp_id, peer_state_mutex) in per_peer_state.iter() {
-				let mut peer_state_lock = peer_state_mutex.lock().unwrap();
-				let peer_state = &mut *peer_state_lock;
-				res.extend(peer_state.channel_by_id.iter()
-					.filter_map(|(chan_id, phase)| match phase {
-						// Only `Channels` in the `ChannelPhase::Funded` phase can be considered funded.
-						ChannelPhase::Funded(chan) => Some((chan_id, chan)),
-						_ => None,
-					})
+	/// Creates a new outbound dual-funded channel to the given remote node, to which both we and
+	/// our counterparty may contribute funds. Returns the channel's temporary channel id.
+	///
+	/// `funding_satoshis` is our contribution to the channel, which must be covered, along with our
+	/// share of the funding transaction fee at `funding_feerate_sat_per_1000_weight`, by the
+	/// outputs spent by `funding_inputs`. Each input must be provided alongside the transaction it
+	/// spends and is currently assumed to be a P2WPKH input for fee estimation purposes. Any change
+	/// is paid to `change_script` or, if `None`, to a script from
+	/// [`SignerProvider::get_destination_script`].
+	///
+	/// The remote node must support dual funding. Once the funding transaction has been negotiated
+	/// with them, an [`Event::FundingTransactionReadyForSigning`] will be generated, after which
+	/// the transaction must be signed and handed back via
+	/// [`ChannelManager::funding_transaction_signed`].
+	///
+	/// See [`ChannelManager::create_channel`] for a description of `user_channel_id` and
+	/// `override_config`.
+	///
+	/// [`Event::FundingTransactionReadyForSigning`]: events::Event::FundingTransactionReadyForSigning
+	#[cfg(dual_funding)]
+	pub fn create_dual_funded_channel(
+		&self, their_network_key: PublicKey, funding_satoshis: u64, funding_inputs: Vec<(TxIn, Transaction)>,
+		change_script: Option<ScriptBuf>, funding_feerate_sat_per_1000_weight: u32, user_channel_id: u128,
+		override_config: Option<UserConfig>,
+	) -> Result<ChannelId, APIError> {
+		if funding_satoshis < 1000 {
+			return Err(APIError::APIMisuseError { err: format!("Channel value must be at least 1000 satoshis. It was {}", funding_satoshis) });
+		}
+		let funding_inputs = check_funding_inputs(funding_inputs)
+			.map_err(|err| APIError::APIMisuseError { err })?;
+
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		// We want to make sure the lock is actually acquired by PersistenceNotifierGuard.
+		debug_assert!(&self.total_consistency_lock.try_write().is_err());
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+
+		let peer_state_mutex = per_peer_state.get(&their_network_key)
+			.ok_or_else(|| APIError::APIMisuseError{ err: format!("Not connected to node: {}", their_network_key) })?;
+
+		let mut peer_state = peer_state_mutex.lock().unwrap();
+		if !peer_state.latest_features.supports_dual_fund() {
+			return Err(APIError::ChannelUnavailable { err: format!("Peer {} does not support dual-funded channels", their_network_key) });
+		}
+
+		let channel = {
+			let outbound_scid_alias = self.create_and_insert_outbound_scid_alias();
+			let their_features = &peer_state.latest_features;
+			let config = if override_config.is_some() { override_config.as_ref().unwrap() } else { &self.default_configuration };
+			match OutboundV2Channel::new(&self.fee_estimator, &self.entropy_source, &self.signer_provider,
+				their_network_key, their_features, funding_satoshis, funding_inputs, change_script,
+				funding_feerate_sat_per_1000_weight, user_channel_id, config,
+				self.best_block.read().unwrap().height(), outbound_scid_alias)
+			{
+				Ok(res) => res,
+				Err(e) => {
+					self.outbound_scid_aliases.lock().unwrap().remove(&outbound_scid_alias);
+					return Err(e);
+				},
+			}
+		};
+		let res = channel.get_open_channel_v2(self.chain_hash);
+
+		let temporary_channel_id = channel.context.channel_id();
+		match peer_state.channel_by_id.entry(temporary_channel_id) {
+			hash_map::Entry::Occupied(_) => {
+				return Err(APIError::APIMisuseError { err: format!("Channel with temporary channel ID {} already exists!", temporary_channel_id) });
+			},
+			hash_map::Entry::Vacant(entry) => { entry.insert(ChannelPhase::UnfundedOutboundV2(channel)); }
+		}
+
+		peer_state.pending_msg_events.push(events::MessageSendEvent::SendOpenChannelV2 {
+			node_id: their_network_key,
+			msg: res,
+		});
+		Ok(temporary_channel_id)
+	}
+
+	fn list_funded_channels_with_filter<Fn: FnMut(&(&ChannelId, &Channel<SP>)) -> bool + Copy>(&self, f: Fn) -> Vec<ChannelDetails> {
+		// Allocate our best estimate of the number of channels we have in the `res`
+		// Vec. Sadly the `short_to_chan_info` map doesn't cover channels without
+		// a scid or a scid alias, and the `outpoint_to_peer` shouldn't be used outside
+		// of the ChannelMonitor handling. Therefore reallocations may still occur, but is
+		// unlikely as the `short_to_chan_info` map often contains 2 entries for
+		// the same channel.
+		let mut res = Vec::with_capacity(self.short_to_chan_info.read().unwrap().len());
+		{
+			let best_block_height = self.best_block.read().unwrap().height();
+			let per_peer_state = self.per_peer_state.read().unwrap();
+			for (_cp_id, peer_state_mutex) in per_peer_state.iter() {
+				let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+				let peer_state = &mut *peer_state_lock;
+				res.extend(peer_state.channel_by_id.iter()
+					.filter_map(|(chan_id, phase)| match phase {
+						// Only `Channels` in the `ChannelPhase::Funded` phase can be considered funded.
+						ChannelPhase::Funded(chan) => Some((chan_id, chan)),
+						_ => None,
+					})
 					.filter(f)
 					.map(|(_channel_id, channel)| {
 						ChannelDetails::from_channel_context(&channel.context, best_block_height,
@@ -2670,6 +3579,15 @@ where
 		vec![]
 	}
 
+	/// Gets the list of funded channels which have been closed, oldest closure first. See
+	/// [`ClosedChannelDetails`] field documentation for more information.
+	///
+	/// Only the most recent [`MAX_CLOSED_CHANNELS_ARCHIVED`] closures are kept. Channels which were
+	/// closed before their funding transaction was generated are not included.
+	pub fn list_closed_channels(&self) -> Vec<ClosedChannelDetails> {
+		self.closed_channels.lock().unwrap().iter().cloned().collect()
+	}
+
 	/// Returns in an undefined order recent payments that -- if not fulfilled -- have yet to find a
 	/// successful path, or have unresolved HTLCs.
 	///
@@ -2681,7 +3599,9 @@ where
 	pub fn list_recent_payments(&self) -> Vec<RecentPaymentDetails> {
 		self.pending_outbound_payments.pending_outbound_payments.lock().unwrap().iter()
 			.filter_map(|(payment_id, pending_outbound_payment)| match pending_outbound_payment {
-				PendingOutboundPayment::AwaitingInvoice { .. } => {
+				PendingOutboundPayment::AwaitingOffer { .. } |
+					PendingOutboundPayment::AwaitingInvoice { .. } =>
+				{
 					Some(RecentPaymentDetails::AwaitingInvoice { payment_id: *payment_id })
 				},
 				// InvoiceReceived is an intermediate state and doesn't need to be exposed
@@ -2706,8 +3626,18 @@ where
 			.collect()
 	}
 
+	/// Adds a channel which is being closed to the archive returned by
+	/// [`Self::list_closed_channels`].
+	fn archive_closed_channel(&self, context: &ChannelContext<SP>, closure_reason: &ClosureReason) {
+		let closed_at_height = self.best_block.read().unwrap().height();
+		if let Some(closed_channel) = ClosedChannelDetails::from_channel_context(context, closure_reason.clone(), closed_at_height) {
+			push_closed_channel(&mut self.closed_channels.lock().unwrap(), closed_channel);
+		}
+	}
+
 	/// Helper function that issues the channel close events
 	fn issue_channel_close_events(&self, context: &ChannelContext<SP>, closure_reason: ClosureReason) {
+		self.archive_closed_channel(context, &closure_reason);
 		let mut pending_events_lock = self.pending_events.lock().unwrap();
 		match context.unbroadcasted_funding() {
 			Some(transaction) => {
@@ -2856,6 +3786,195 @@ where
 		self.close_channel_internal(channel_id, counterparty_node_id, target_feerate_sats_per_1000_weight, shutdown_script)
 	}
 
+	/// Begins making a channel quiescent, e.g. ahead of a protocol upgrade which requires that no
+	/// updates be in flight on the channel.
+	///
+	/// Once called, any new updates from us are held until quiescence is terminated, either by the
+	/// protocol which required it completing or by disconnecting from the peer. Our `stfu` is sent
+	/// once all pending updates in both directions have been irrevocably committed, and the
+	/// channel becomes quiescent once the counterparty responds with theirs, at which point
+	/// [`ChannelManager::is_channel_quiescent`] will return true. If the counterparty fails to
+	/// respond in a timely manner, it will be disconnected in
+	/// [`ChannelManager::timer_tick_occurred`].
+	///
+	/// The channel must be usable, its peer connected and must support `option_quiesce`. May
+	/// generate a [`SendStfu`] message event, which should be relayed.
+	///
+	/// Not public as quiescence can only be terminated by the protocol requiring it, such as
+	/// [`ChannelManager::upgrade_channel_type`].
+	///
+	/// [`SendStfu`]: events::MessageSendEvent::SendStfu
+	pub(crate) fn propose_quiescence(&self, channel_id: &ChannelId, counterparty_node_id: &PublicKey) -> Result<(), APIError> {
+		// Quiescence is never persisted, so we only need to have our message events handled.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || NotifyOption::SkipPersistHandleEvents);
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+
+		if !peer_state.latest_features.supports_quiescence() {
+			return Err(APIError::ChannelUnavailable {
+				err: format!("Peer {} does not support quiescence", counterparty_node_id)
+			});
+		}
+
+		match peer_state.channel_by_id.get_mut(channel_id) {
+			Some(ChannelPhase::Funded(chan)) => {
+				let logger = WithChannelContext::from(&self.logger, &chan.context);
+				if let Some(msg) = chan.propose_quiescence(&&logger)? {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendStfu {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				Ok(())
+			},
+			Some(_) => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} is not funded, cannot make it quiescent", channel_id)
+			}),
+			None => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} not found for the passed counterparty node_id {}",
+					channel_id, counterparty_node_id)
+			}),
+		}
+	}
+
+	/// Returns whether the channel has completed the `stfu` handshake and is currently quiescent.
+	///
+	/// Returns false if the channel can't be found.
+	pub fn is_channel_quiescent(&self, channel_id: &ChannelId, counterparty_node_id: &PublicKey) -> bool {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		per_peer_state.get(counterparty_node_id).map_or(false, |peer_state_mutex| {
+			let peer_state = peer_state_mutex.lock().unwrap();
+			match peer_state.channel_by_id.get(channel_id) {
+				Some(ChannelPhase::Funded(chan)) => chan.is_quiescent(),
+				_ => false,
+			}
+		})
+	}
+
+	/// Terminates quiescence on a channel previously made quiescent, allowing updates to flow once
+	/// again.
+	///
+	/// The quiescence protocol has no message to end quiescence on its own, so we disconnect from
+	/// the peer, which implicitly terminates it on both sides. Any updates held while the channel
+	/// was quiescent will be sent to the counterparty once the channel is reestablished.
+	///
+	/// Returns an error if the channel is not currently quiescent.
+	#[cfg(test)]
+	pub(crate) fn exit_quiescence(&self, channel_id: &ChannelId, counterparty_node_id: &PublicKey) -> Result<(), APIError> {
+		// Quiescence is never persisted, so we only need to have our message events handled.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || NotifyOption::SkipPersistHandleEvents);
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+
+		match peer_state.channel_by_id.get(channel_id) {
+			Some(ChannelPhase::Funded(chan)) => {
+				if !chan.is_quiescent() {
+					return Err(APIError::APIMisuseError { err: format!("Channel {} is not quiescent", channel_id) });
+				}
+				let logger = WithChannelContext::from(&self.logger, &chan.context);
+				log_debug!(logger, "Disconnecting peer {} to terminate quiescence on channel {}",
+					counterparty_node_id, channel_id);
+				peer_state.pending_msg_events.push(events::MessageSendEvent::HandleError {
+					node_id: *counterparty_node_id,
+					action: msgs::ErrorAction::DisconnectPeerWithWarning {
+						msg: msgs::WarningMessage {
+							channel_id: *channel_id,
+							data: "Disconnecting to terminate quiescence".to_owned(),
+						},
+					},
+				});
+				Ok(())
+			},
+			Some(_) => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} is not funded and cannot be quiescent", channel_id)
+			}),
+			None => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} not found for the passed counterparty node_id {}",
+					channel_id, counterparty_node_id)
+			}),
+		}
+	}
+
+	/// Upgrades an open channel to a new [`ChannelTypeFeatures`] without closing it, using the
+	/// (draft) dynamic commitments protocol.
+	///
+	/// Currently, only channels which don't support anchor outputs may be upgraded, to the same
+	/// channel type with `option_anchors_zero_fee_htlc_tx` added. Both we and the counterparty must
+	/// support anchor outputs (see
+	/// [`ChannelHandshakeConfig::negotiate_anchors_zero_fee_htlc_tx`]) and the counterparty must
+	/// support `option_quiesce`. The channel funder must be able to afford the anchor outputs.
+	///
+	/// The upgrade first makes the channel quiescent, holding any new updates from us until the
+	/// counterparty has acknowledged with its own `stfu`, after which the new channel type is
+	/// proposed to the counterparty. If they accept, both sides exchange commitment transactions
+	/// using the new type and quiescence is terminated. The new type is reflected in
+	/// [`ChannelDetails::channel_type`] as soon as it has been agreed on. If the counterparty
+	/// rejects the upgrade, or we disconnect before it was agreed on, the channel keeps its current
+	/// type.
+	///
+	/// May generate a [`SendStfu`] message event, which should be relayed.
+	///
+	/// [`ChannelHandshakeConfig::negotiate_anchors_zero_fee_htlc_tx`]: crate::util::config::ChannelHandshakeConfig::negotiate_anchors_zero_fee_htlc_tx
+	/// [`SendStfu`]: events::MessageSendEvent::SendStfu
+	pub fn upgrade_channel_type(
+		&self, channel_id: &ChannelId, counterparty_node_id: &PublicKey, channel_type: ChannelTypeFeatures
+	) -> Result<(), APIError> {
+		// The upgrade is only persisted once agreed upon, we only need to have our message events
+		// handled until then.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || NotifyOption::SkipPersistHandleEvents);
+
+		if channel_type.requires_unknown_bits_from(&self.channel_type_features()) {
+			return Err(APIError::APIMisuseError {
+				err: format!("Channel type {} is not supported by our configuration", channel_type)
+			});
+		}
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+
+		if !peer_state.latest_features.supports_quiescence() {
+			return Err(APIError::ChannelUnavailable {
+				err: format!("Peer {} does not support quiescence", counterparty_node_id)
+			});
+		}
+		if channel_type.requires_unknown_bits_from(&ChannelTypeFeatures::from_init(&peer_state.latest_features)) {
+			return Err(APIError::ChannelUnavailable {
+				err: format!("Peer {} does not support channel type {}", counterparty_node_id, channel_type)
+			});
+		}
+
+		match peer_state.channel_by_id.get_mut(channel_id) {
+			Some(ChannelPhase::Funded(chan)) => {
+				let logger = WithChannelContext::from(&self.logger, &chan.context);
+				if let Some(msg) = chan.propose_channel_type_upgrade(channel_type, &&logger)? {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendStfu {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				Ok(())
+			},
+			Some(_) => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} is not funded, cannot upgrade its channel type", channel_id)
+			}),
+			None => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} not found for the passed counterparty node_id {}",
+					channel_id, counterparty_node_id)
+			}),
+		}
+	}
+
 	fn finish_close_channel(&self, mut shutdown_res: ShutdownResult) {
 		debug_assert_ne!(self.per_peer_state.held_by_thread(), LockHeldState::HeldByThread);
 		#[cfg(debug_assertions)]
@@ -2867,6 +3986,7 @@ where
 			&self.logger, Some(shutdown_res.counterparty_node_id), Some(shutdown_res.channel_id),
 		);
 		log_debug!(logger, "Finishing closure of channel with {} HTLCs to fail", shutdown_res.dropped_outbound_htlcs.len());
+		self.dynamic_fees.lock().unwrap().channel_closed(&shutdown_res.channel_id);
 		for htlc_source in shutdown_res.dropped_outbound_htlcs.drain(..) {
 			let (source, payment_hash, counterparty_node_id, channel_id) = htlc_source;
 			let reason = HTLCFailReason::from_failure_code(0x4000 | 8);
@@ -2938,6 +4058,12 @@ where
 						// Unfunded channel has no update
 						(None, chan_phase.context().get_counterparty_node_id())
 					},
+					#[cfg(dual_funding)]
+					ChannelPhase::UnfundedOutboundV2(_) | ChannelPhase::UnfundedInboundV2(_) => {
+						self.finish_close_channel(chan_phase.context_mut().force_shutdown(false));
+						// Unfunded channel has no update
+						(None, chan_phase.context().get_counterparty_node_id())
+					},
 				}
 			} else if peer_state.inbound_channel_request_by_id.remove(channel_id).is_some() {
 				log_error!(logger, "Force-closing channel {}", &channel_id);
@@ -3254,7 +4380,19 @@ where
 					Ok(info) => PendingHTLCStatus::Forward(info),
 					Err(InboundOnionErr { err_code, err_data, msg }) => return_err!(msg, err_code, &err_data)
 				}
-			}
+			},
+			trampoline_hop @ onion_utils::Hop::TrampolineForward { .. } => {
+				if !self.default_configuration.accept_trampoline_forwards {
+					return_err!("We don't accept trampoline forwards", 0x4000 | 22, &[0; 0]);
+				}
+				let current_height: u32 = self.best_block.read().unwrap().height();
+				let next_trampoline_pubkey = next_trampoline_pubkey(&self.secp_ctx, &trampoline_hop);
+				match create_trampoline_fwd_pending_htlc_info(msg, trampoline_hop, shared_secret,
+					next_trampoline_pubkey, current_height) {
+					Ok(info) => PendingHTLCStatus::Forward(info),
+					Err(InboundOnionErr { err_code, err_data, msg }) => return_err!(msg, err_code, &err_data)
+				}
+			},
 		}
 	}
 
@@ -3346,14 +4484,14 @@ where
 		let _lck = self.total_consistency_lock.read().unwrap();
 		self.send_payment_along_path(SendAlongPathArgs {
 			path, payment_hash, recipient_onion, total_value, cur_height, payment_id, keysend_preimage,
-			session_priv_bytes
+			session_priv_bytes, hold_htlc: None,
 		})
 	}
 
 	fn send_payment_along_path(&self, args: SendAlongPathArgs) -> Result<(), APIError> {
 		let SendAlongPathArgs {
 			path, payment_hash, recipient_onion, total_value, cur_height, payment_id, keysend_preimage,
-			session_priv_bytes
+			session_priv_bytes, hold_htlc,
 		} = args;
 		// The top-level caller should hold the total_consistency_lock read lock.
 		debug_assert!(self.total_consistency_lock.try_write().is_err());
@@ -3403,7 +4541,7 @@ where
 								session_priv: session_priv.clone(),
 								first_hop_htlc_msat: htlc_msat,
 								payment_id,
-							}, onion_packet, None, &self.fee_estimator, &&logger);
+							}, onion_packet, None, hold_htlc, &self.fee_estimator, &&logger);
 						match break_chan_phase_entry!(self, send_res, chan_phase_entry) {
 							Some(monitor_update) => {
 								match handle_new_monitor_update!(self, funding_txo, monitor_update, peer_state_lock, peer_state, per_peer_state, chan) {
@@ -3501,6 +4639,121 @@ where
 				|args| self.send_payment_along_path(args))
 	}
 
+	/// Sends an async payment along the given [`Route`], asking our first hop to hold the resulting
+	/// HTLC(s) until the often-offline recipient comes online.
+	///
+	/// Alongside the HTLC(s), a [`HeldHtlcAvailable`] onion message is queued for each of the
+	/// `release_paths` provided by the recipient's LSP (see
+	/// [`Self::create_async_recipient_release_paths`]), with a reply path to our first hop. Once the
+	/// recipient connects to its LSP, the LSP replies with a [`ReleaseHeldHtlc`] message, upon
+	/// which our first hop forwards the HTLC(s). If they are never released, our first hop fails
+	/// them back once they come close to expiring.
+	///
+	/// All paths in `route` must share the same first hop, which should have
+	/// [`UserConfig::hold_htlcs_for_async_payments`] set. Otherwise, the HTLC(s) will be forwarded
+	/// immediately.
+	///
+	/// See [`Self::send_payment_with_route`] for more details on the remaining parameters and the
+	/// possible errors.
+	pub fn send_async_payment_with_route(
+		&self, route: &Route, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields,
+		payment_id: PaymentId, release_paths: Vec<BlindedPath>
+	) -> Result<(), PaymentSendFailure> {
+		let first_hop_node_id = match route.paths.first().and_then(|path| path.hops.first()) {
+			Some(hop) => hop.pubkey,
+			None => return Err(PaymentSendFailure::ParameterError(APIError::InvalidRoute {
+				err: "No paths were provided".to_owned()
+			})),
+		};
+		if route.paths.iter().any(|path| path.hops.first().map(|hop| hop.pubkey) != Some(first_hop_node_id)) {
+			return Err(PaymentSendFailure::ParameterError(APIError::InvalidRoute {
+				err: "All paths of an async payment must share the same first hop".to_owned()
+			}));
+		}
+		if release_paths.is_empty() {
+			return Err(PaymentSendFailure::ParameterError(APIError::APIMisuseError {
+				err: "At least one release path must be provided".to_owned()
+			}));
+		}
+		let reply_path = BlindedPath::one_hop_for_message(
+			first_hop_node_id, &*self.entropy_source, &self.secp_ctx
+		).map_err(|()| PaymentSendFailure::ParameterError(APIError::APIMisuseError {
+			err: "Failed to create a reply path to the first hop".to_owned()
+		}))?;
+		let payment_release_secret = self.entropy_source.get_secure_random_bytes();
+
+		let best_block_height = self.best_block.read().unwrap().height();
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let res = self.pending_outbound_payments
+			.send_payment_with_route(route, payment_hash, recipient_onion, payment_id,
+				&self.entropy_source, &self.node_signer, best_block_height,
+				|args| self.send_payment_along_path(SendAlongPathArgs {
+					hold_htlc: Some(payment_release_secret), ..args
+				}));
+
+		// Even if only some paths were sent, the recipient needs to release them.
+		if let Ok(()) | Err(PaymentSendFailure::PartialFailure { .. }) = res {
+			self.enqueue_held_htlc_available(payment_release_secret, release_paths, reply_path);
+		}
+		res
+	}
+
+	/// Enqueues a [`HeldHtlcAvailable`] message to be sent to the recipient's LSP over each of the
+	/// `release_paths` (with an upper bound), replied to over `reply_path` to release the HTLC(s).
+	fn enqueue_held_htlc_available(
+		&self, payment_release_secret: [u8; 32], release_paths: Vec<BlindedPath>,
+		reply_path: BlindedPath
+	) {
+		const MESSAGE_LIMIT: usize = 10;
+		let mut pending_async_payments_messages = self.pending_async_payments_messages.lock().unwrap();
+		for path in release_paths.into_iter().take(MESSAGE_LIMIT) {
+			let message = new_pending_onion_message(
+				AsyncPaymentsMessage::HeldHtlcAvailable(HeldHtlcAvailable { payment_release_secret }),
+				Destination::BlindedPath(path),
+				Some(reply_path.clone()),
+			);
+			pending_async_payments_messages.push(message);
+		}
+	}
+
+	/// Pays `invoice`, received in response to the [`InvoiceRequest`] for `payment_id`, as an async
+	/// payment: the HTLC is held by our first hop until the recipient's LSP releases it once the
+	/// recipient comes online, as in [`Self::send_async_payment_with_route`].
+	fn send_payment_for_static_invoice(
+		&self, invoice: &StaticInvoice, amount_msats: u64, payment_id: PaymentId
+	) -> Result<(), Bolt12PaymentError> {
+		let best_block_height = self.best_block.read().unwrap().height();
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let payment_release_secret = self.entropy_source.get_secure_random_bytes();
+		let first_hop_node_id = Cell::new(None);
+		self.pending_outbound_payments
+			.send_payment_for_static_invoice(
+				invoice, amount_msats, payment_id, &self.router, self.list_usable_channels(),
+				|| self.compute_inflight_htlcs(), &self.entropy_source, &self.node_signer,
+				best_block_height, &self.logger, &self.pending_events,
+				|args| {
+					let first_hop = args.path.hops.first().map(|hop| hop.pubkey);
+					let res = self.send_payment_along_path(SendAlongPathArgs {
+						hold_htlc: Some(payment_release_secret), ..args
+					});
+					if res.is_ok() { first_hop_node_id.set(first_hop); }
+					res
+				}
+			)?;
+
+		// The payment is never retried, so if no HTLC was sent there is nothing to release.
+		if let Some(first_hop_node_id) = first_hop_node_id.get() {
+			match BlindedPath::one_hop_for_message(first_hop_node_id, &*self.entropy_source, &self.secp_ctx) {
+				Ok(reply_path) => self.enqueue_held_htlc_available(
+					payment_release_secret, invoice.message_paths().to_vec(), reply_path
+				),
+				Err(()) => log_error!(self.logger,
+					"Failed to create a reply path to the first hop of async payment {}", payment_id),
+			}
+		}
+		Ok(())
+	}
+
 	/// Similar to [`ChannelManager::send_payment_with_route`], but will automatically find a route based on
 	/// `route_params` and retry failed payment paths based on `retry_strategy`.
 	pub fn send_payment(&self, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields, payment_id: PaymentId, route_params: RouteParameters, retry_strategy: Retry) -> Result<(), RetryableSendFailure> {
@@ -3513,6 +4766,153 @@ where
 				&self.pending_events, |args| self.send_payment_along_path(args))
 	}
 
+	/// Sends a payment to the recipient described by `route_params` via the trampoline node
+	/// `trampoline_hop`, which finds a route to the recipient on our behalf.
+	///
+	/// This allows nodes with only a partial view of the network graph, such as mobile clients, to
+	/// pay recipients they cannot find a route to themselves. Instead, they pick a channel
+	/// counterparty which supports trampoline routing (see
+	/// [`InitFeatures::supports_trampoline_routing`]) and pay it a fee to route the rest of the way.
+	///
+	/// `route_params` must describe a recipient which did not provide blinded paths. The fee and
+	/// CLTV expiry delta of `trampoline_hop` are added on top of the amount and final CLTV expiry
+	/// delta in `route_params`, with the fee counting towards
+	/// [`RouteParameters::max_total_routing_fee_msat`]. Multi-path payments are not yet supported,
+	/// so we'll only ever route to the trampoline node over a single path.
+	///
+	/// Otherwise, this behaves like [`Self::send_payment`], though note that `retry_strategy` only
+	/// applies to failures along the route to the trampoline node. If the trampoline node fails to
+	/// find a route to the recipient, the payment will fail and may be re-attempted with a higher
+	/// fee or via a different trampoline node.
+	pub fn send_payment_via_trampoline(
+		&self, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields, payment_id: PaymentId,
+		route_params: RouteParameters, trampoline_hop: TrampolineHop, retry_strategy: Retry
+	) -> Result<(), RetryableSendFailure> {
+		let (recipient, final_cltv_expiry_delta) = match route_params.payment_params.payee {
+			Payee::Clear { node_id, final_cltv_expiry_delta, .. } => (node_id, final_cltv_expiry_delta),
+			Payee::Blinded { .. } => return Err(RetryableSendFailure::RouteNotFound),
+		};
+		let supports_trampoline = self.per_peer_state.read().unwrap().get(&trampoline_hop.pubkey)
+			.map_or(false, |peer_state_mutex| {
+				peer_state_mutex.lock().unwrap().latest_features.supports_trampoline_routing()
+			});
+		if !supports_trampoline {
+			return Err(RetryableSendFailure::RouteNotFound);
+		}
+
+		let best_block_height = self.best_block.read().unwrap().height();
+		let session_priv = SecretKey::from_slice(&self.entropy_source.get_secure_random_bytes())
+			.expect("RNG is busted");
+		let prng_seed = self.entropy_source.get_secure_random_bytes();
+		let (trampoline_packet, trampoline_msat, _) = onion_utils::create_trampoline_onion(
+			&self.secp_ctx, &trampoline_hop, recipient, route_params.final_value_msat,
+			final_cltv_expiry_delta, &recipient_onion, best_block_height + 1, &payment_hash,
+			&session_priv, prng_seed
+		).map_err(|e| {
+			log_error!(self.logger, "Failed to build a trampoline onion for payment hash {}: {:?}", payment_hash, e);
+			RetryableSendFailure::RouteNotFound
+		})?;
+
+		let max_total_routing_fee_msat = match route_params.max_total_routing_fee_msat {
+			Some(max_fee_msat) => match max_fee_msat.checked_sub(trampoline_hop.fee_msat) {
+				Some(remaining_fee_msat) => Some(remaining_fee_msat),
+				None => return Err(RetryableSendFailure::RouteNotFound),
+			},
+			None => None,
+		};
+		let mut payment_params = PaymentParameters::from_node_id(
+			trampoline_hop.pubkey, final_cltv_expiry_delta.saturating_add(trampoline_hop.cltv_expiry_delta)
+		)
+			.with_max_total_cltv_expiry_delta(route_params.payment_params.max_total_cltv_expiry_delta)
+			.with_max_path_count(1);
+		payment_params.expiry_time = route_params.payment_params.expiry_time;
+		let trampoline_route_params = RouteParameters {
+			payment_params, final_value_msat: trampoline_msat, max_total_routing_fee_msat,
+		};
+		let trampoline_onion = RecipientOnionFields {
+			payment_secret: Some(PaymentSecret(self.entropy_source.get_secure_random_bytes())),
+			payment_metadata: None,
+			custom_tlvs: Vec::new(),
+			trampoline_packet: Some(trampoline_packet),
+			amp_record: None,
+		};
+		self.send_payment(payment_hash, trampoline_onion, payment_id, trampoline_route_params, retry_strategy)
+	}
+
+	/// Moves `amount_msat` of liquidity from our side of the channel with `outbound_channel_id` to
+	/// our side of the channel with `inbound_channel_id` by paying ourselves in a circle.
+	///
+	/// A route from the outbound channel to the counterparty of the inbound channel is found using
+	/// the [`Router`], paying at most `max_total_routing_fee_msat` in fees, including the fee the
+	/// counterparty of the inbound channel charges for forwarding back to us. The route is limited
+	/// to a single path. The payment preimage and secret are generated internally and the payment
+	/// is claimed automatically once it reaches us, so no [`Event::PaymentClaimable`] or
+	/// [`Event::PaymentClaimed`] is generated for it. Failed attempts are retried over alternative
+	/// routes according to `retry_strategy`.
+	///
+	/// Once the rebalance completes, an [`Event::RebalanceSucceeded`] or [`Event::RebalanceFailed`]
+	/// is generated in place of [`Event::PaymentSent`] or [`Event::PaymentFailed`]. Until then,
+	/// the rebalance may be abandoned via [`Self::abandon_payment`] using the returned
+	/// [`PaymentId`].
+	///
+	/// Both channels must be usable (see [`Self::list_usable_channels`]) and the counterparty of
+	/// the inbound channel must have sent us its forwarding fees, otherwise
+	/// [`RetryableSendFailure::RouteNotFound`] is returned.
+	pub fn rebalance(
+		&self, outbound_channel_id: ChannelId, inbound_channel_id: ChannelId, amount_msat: u64,
+		max_total_routing_fee_msat: u64, retry_strategy: Retry
+	) -> Result<PaymentId, RetryableSendFailure> {
+		if outbound_channel_id == inbound_channel_id {
+			return Err(RetryableSendFailure::RouteNotFound);
+		}
+		let payment_preimage = PaymentPreimage(self.entropy_source.get_secure_random_bytes());
+		let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).to_byte_array());
+		let payment_secret = self.create_inbound_payment_for_hash(
+			payment_hash, Some(amount_msat), REBALANCE_EXPIRY_SECS, Some(MIN_FINAL_CLTV_EXPIRY_DELTA)
+		).map_err(|()| RetryableSendFailure::RouteNotFound)?;
+		let payment_id = PaymentId(self.entropy_source.get_secure_random_bytes());
+
+		let mut payment_params = PaymentParameters::from_node_id(
+			self.get_our_node_id(), MIN_FINAL_CLTV_EXPIRY_DELTA as u32
+		)
+			.with_max_path_count(1);
+		payment_params.expiry_time =
+			Some(self.duration_since_epoch().as_secs() + REBALANCE_EXPIRY_SECS as u64);
+		let route_params = RouteParameters {
+			payment_params, final_value_msat: amount_msat,
+			max_total_routing_fee_msat: Some(max_total_routing_fee_msat),
+		};
+
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		self.pending_rebalances.lock().unwrap().insert(payment_id, PendingRebalance {
+			outbound_channel_id, inbound_channel_id, amount_msat, payment_hash, payment_preimage,
+			succeeded: false, claimed: false,
+		});
+		let best_block_height = self.best_block.read().unwrap().height();
+		let res = self.pending_outbound_payments
+			.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret), payment_id,
+				retry_strategy, route_params, &&self.rebalance_router(), self.list_usable_channels(),
+				|| self.compute_inflight_htlcs(), &self.entropy_source, &self.node_signer,
+				best_block_height, &self.logger, &self.pending_events,
+				|args| self.send_payment_along_path(args));
+		if let Err(e) = res {
+			self.pending_rebalances.lock().unwrap().remove(&payment_id);
+			return Err(e);
+		}
+		Ok(payment_id)
+	}
+
+	/// Returns the [`Router`] used to find routes for outbound payments, which handles rebalances
+	/// started via [`Self::rebalance`] itself.
+	fn rebalance_router(&self) -> RebalanceRouter<R> {
+		RebalanceRouter {
+			router: &self.router,
+			pending_rebalances: &self.pending_rebalances,
+			our_node_features: provided_node_features(&self.default_configuration),
+			our_channel_features: provided_channel_features(&self.default_configuration),
+		}
+	}
+
 	#[cfg(test)]
 	pub(super) fn test_send_payment_internal(&self, route: &Route, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields, keysend_preimage: Option<PaymentPreimage>, payment_id: PaymentId, recv_value_msat: Option<u64>, onion_session_privs: Vec<[u8; 32]>) -> Result<(), PaymentSendFailure> {
 		let best_block_height = self.best_block.read().unwrap().height();
@@ -3614,6 +5014,34 @@ where
 			&self.logger, &self.pending_events, |args| self.send_payment_along_path(args))
 	}
 
+	/// Sends an atomic multi-path (AMP) spontaneous payment, automatically finding a route based on
+	/// `route_params` and retrying failed payment paths based on `retry_strategy`.
+	///
+	/// Unlike [`ChannelManager::send_spontaneous_payment_with_retry`], each part of the payment is
+	/// locked to a different preimage, derived from a random root seed which is split across the
+	/// parts. The recipient can only reconstruct the root seed, and thus claim any part, once all
+	/// parts have arrived. As the recipient doesn't need to provide a preimage or a payment secret,
+	/// AMP payments can be made to the same node any number of times without an invoice.
+	///
+	/// The returned [`PaymentHash`] is the SHA256 hash of the root seed, which identifies the
+	/// payment in [`Event::PaymentSent`] (with the root seed as its preimage) and
+	/// [`Event::PaymentFailed`]. Note that [`Event::PaymentPathFailed`] carries the payment hash of
+	/// the individual part that failed.
+	///
+	/// If `recipient_onion` has no payment secret, a random one is used to tie the parts together.
+	/// See [`PaymentParameters::for_keysend`] for help in constructing `route_params`, which should
+	/// allow MPP for the payment to be split.
+	///
+	/// [`PaymentParameters::for_keysend`]: crate::routing::router::PaymentParameters::for_keysend
+	pub fn send_amp_payment(&self, recipient_onion: RecipientOnionFields, payment_id: PaymentId, route_params: RouteParameters, retry_strategy: Retry) -> Result<PaymentHash, RetryableSendFailure> {
+		let best_block_height = self.best_block.read().unwrap().height();
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		self.pending_outbound_payments.send_amp_payment(recipient_onion, payment_id, retry_strategy,
+			route_params, &self.router, self.list_usable_channels(), || self.compute_inflight_htlcs(),
+			&self.entropy_source, &self.node_signer, best_block_height, &self.logger,
+			&self.pending_events, |args| self.send_payment_along_path(args))
+	}
+
 	/// Send a payment that is probing the given route for liquidity. We calculate the
 	/// [`PaymentHash`] of probes based on a static secret and a random [`PaymentId`], which allows
 	/// us to easily discern them from real payments.
@@ -4151,9 +5579,9 @@ where
 			})?;
 
 		let routing = match payment.forward_info.routing {
-			PendingHTLCRouting::Forward { onion_packet, blinded, .. } => {
+			PendingHTLCRouting::Forward { onion_packet, blinded, trampoline_forward, .. } => {
 				PendingHTLCRouting::Forward {
-					onion_packet, blinded, short_channel_id: next_hop_scid
+					onion_packet, blinded, short_channel_id: next_hop_scid, trampoline_forward,
 				}
 			},
 			_ => unreachable!() // Only `PendingHTLCRouting::Forward`s are intercepted
@@ -4168,6 +5596,7 @@ where
 		let mut per_source_pending_forward = [(
 			payment.prev_short_channel_id,
 			payment.prev_funding_outpoint,
+			payment.prev_channel_id,
 			payment.prev_user_channel_id,
 			vec![(pending_htlc_info, payment.prev_htlc_id)]
 		)];
@@ -4195,10 +5624,12 @@ where
 				short_channel_id: payment.prev_short_channel_id,
 				user_channel_id: Some(payment.prev_user_channel_id),
 				outpoint: payment.prev_funding_outpoint,
+				channel_id: payment.prev_channel_id,
 				htlc_id: payment.prev_htlc_id,
 				incoming_packet_shared_secret: payment.forward_info.incoming_shared_secret,
 				phantom_shared_secret: None,
 				blinded_failure: payment.forward_info.routing.blinded_failure(),
+				trampoline_forward: payment.forward_info.routing.trampoline_forward(),
 			});
 
 			let failure_reason = HTLCFailReason::from_failure_code(0x4000 | 10);
@@ -4209,21 +5640,157 @@ where
 		Ok(())
 	}
 
-	/// Processes HTLCs which are pending waiting on random forward delay.
+	/// Finds a route to the next trampoline node for each pending [`PendingHTLCRouting::TrampolineForward`]
+	/// in `forward_htlcs`, turning it into a regular forward over the first hop of that route.
 	///
-	/// Should only really ever be called in response to a PendingHTLCsForwardable event.
-	/// Will likely generate further events.
-	pub fn process_pending_htlc_forwards(&self) {
-		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
-
-		let mut new_events = VecDeque::new();
-		let mut failed_forwards = Vec::new();
-		let mut phantom_receives: Vec<(u64, OutPoint, u128, Vec<(PendingHTLCInfo, u64)>)> = Vec::new();
-		{
-			let mut forward_htlcs = HashMap::new();
-			mem::swap(&mut forward_htlcs, &mut self.forward_htlcs.lock().unwrap());
+	/// If the HTLC later fails along the route, it is failed back rather than retried over another
+	/// one, with the failure decrypted using the [`TrampolineForward`] we keep for it.
+	fn resolve_trampoline_forwards(
+		&self, forward_htlcs: &mut HashMap<u64, Vec<HTLCForwardInfo>>,
+		failed_forwards: &mut Vec<(HTLCSource, PaymentHash, HTLCFailReason, HTLCDestination)>,
+	) {
+		let trampoline_forwards = match forward_htlcs.get_mut(&0) {
+			Some(pending_forwards) => {
+				let (trampoline_forwards, other_forwards): (Vec<_>, Vec<_>) = pending_forwards.drain(..)
+					.partition(|forward_info| matches!(forward_info, HTLCForwardInfo::AddHTLC(PendingAddHTLCInfo {
+						forward_info: PendingHTLCInfo { routing: PendingHTLCRouting::TrampolineForward { .. }, .. }, ..
+					})));
+				*pending_forwards = other_forwards;
+				trampoline_forwards
+			},
+			None => return,
+		};
+		if forward_htlcs.get(&0).map_or(false, |pending_forwards| pending_forwards.is_empty()) {
+			forward_htlcs.remove(&0);
+		}
+		if trampoline_forwards.is_empty() { return }
 
-			for (short_chan_id, mut pending_forwards) in forward_htlcs {
+		let our_node_id = self.get_our_node_id();
+		let usable_channels = self.list_usable_channels();
+		let first_hops = usable_channels.iter().collect::<Vec<_>>();
+		for forward_info in trampoline_forwards {
+			let mut pending_add = match forward_info {
+				HTLCForwardInfo::AddHTLC(pending_add) => pending_add,
+				_ => unreachable!(),
+			};
+			let (onion_packet, node_id, incoming_cltv_expiry) = match pending_add.forward_info.routing {
+				PendingHTLCRouting::TrampolineForward { ref onion_packet, node_id, incoming_cltv_expiry } =>
+					(onion_packet.clone(), node_id, incoming_cltv_expiry),
+				_ => unreachable!(),
+			};
+			let PendingHTLCInfo {
+				payment_hash, incoming_amt_msat, outgoing_amt_msat: amt_to_forward, outgoing_cltv_value, ..
+			} = pending_add.forward_info;
+			let incoming_amt_msat = incoming_amt_msat.unwrap_or(0);
+
+			macro_rules! fail_trampoline_forward {
+				($msg: expr, $err_code: expr) => {
+					{
+						let logger = WithContext::from(&self.logger, Some(node_id), Some(pending_add.prev_channel_id));
+						log_info!(logger, "Failed to forward trampoline HTLC with payment hash {}: {}", &payment_hash, $msg);
+						let htlc_source = HTLCSource::PreviousHopData(HTLCPreviousHopData {
+							short_channel_id: pending_add.prev_short_channel_id,
+							user_channel_id: Some(pending_add.prev_user_channel_id),
+							outpoint: pending_add.prev_funding_outpoint,
+							channel_id: pending_add.prev_channel_id,
+							htlc_id: pending_add.prev_htlc_id,
+							incoming_packet_shared_secret: pending_add.forward_info.incoming_shared_secret,
+							phantom_shared_secret: None,
+							blinded_failure: None,
+							trampoline_forward: None,
+						});
+						failed_forwards.push((htlc_source, payment_hash,
+							HTLCFailReason::reason($err_code, Vec::new()),
+							HTLCDestination::TrampolineForward { node_id }
+						));
+						continue;
+					}
+				}
+			}
+
+			let max_total_cltv_expiry_delta = incoming_cltv_expiry
+				.saturating_sub(outgoing_cltv_value.saturating_add(MIN_CLTV_EXPIRY_DELTA as u32));
+			let payment_params = PaymentParameters::from_node_id(node_id, 0)
+				.with_max_total_cltv_expiry_delta(max_total_cltv_expiry_delta)
+				// We forward the received HTLC as a single outgoing one.
+				.with_max_path_count(1);
+			let mut route_params = RouteParameters::from_payment_params_and_value(payment_params, amt_to_forward);
+			route_params.max_total_routing_fee_msat = Some(incoming_amt_msat.saturating_sub(amt_to_forward));
+
+			let route = match self.router.find_route(
+				&our_node_id, &route_params, Some(&first_hops), self.compute_inflight_htlcs()
+			) {
+				Ok(route) => route,
+				Err(e) => fail_trampoline_forward!(
+					format!("Failed to find a route to the next trampoline node: {}", e.err),
+					TEMPORARY_TRAMPOLINE_FAILURE
+				),
+			};
+			let path = match route.paths.first() {
+				Some(path) if !path.hops.is_empty() => path,
+				_ => fail_trampoline_forward!("Router returned an empty route", TEMPORARY_TRAMPOLINE_FAILURE),
+			};
+
+			let session_priv = SecretKey::from_slice(&self.entropy_source.get_secure_random_bytes())
+				.expect("RNG is busted");
+			let prng_seed = self.entropy_source.get_secure_random_bytes();
+			let recipient_onion = RecipientOnionFields {
+				payment_secret: None,
+				payment_metadata: None,
+				custom_tlvs: Vec::new(),
+				trampoline_packet: Some(onion_packet),
+				amp_record: None,
+			};
+			let (outgoing_packet, htlc_msat, htlc_cltv) = match onion_utils::create_payment_onion(
+				&self.secp_ctx, path, &session_priv, amt_to_forward, recipient_onion, outgoing_cltv_value,
+				&payment_hash, &None, prng_seed
+			) {
+				Ok(res) => res,
+				Err(e) => fail_trampoline_forward!(
+					format!("Failed to build an onion to the next trampoline node: {:?}", e),
+					TEMPORARY_TRAMPOLINE_FAILURE
+				),
+			};
+			// Don't rely on the `Router` having respected our limits.
+			if htlc_msat > incoming_amt_msat {
+				fail_trampoline_forward!("Route to the next trampoline node exceeds the trampoline fee", TRAMPOLINE_FEE_INSUFFICIENT);
+			}
+			if htlc_cltv as u64 + MIN_CLTV_EXPIRY_DELTA as u64 > incoming_cltv_expiry as u64 {
+				fail_trampoline_forward!("Route to the next trampoline node exceeds the trampoline CLTV delta", TRAMPOLINE_EXPIRY_TOO_SOON);
+			}
+
+			let short_channel_id = path.hops[0].short_channel_id;
+			pending_add.forward_info.routing = PendingHTLCRouting::Forward {
+				onion_packet: outgoing_packet,
+				short_channel_id,
+				blinded: None,
+				trampoline_forward: Some(TrampolineForward {
+					amt_to_forward_msat: amt_to_forward, hops: path.hops.clone(), session_priv,
+				}),
+			};
+			pending_add.forward_info.outgoing_amt_msat = htlc_msat;
+			pending_add.forward_info.outgoing_cltv_value = htlc_cltv;
+			forward_htlcs.entry(short_channel_id).or_insert_with(Vec::new)
+				.push(HTLCForwardInfo::AddHTLC(pending_add));
+		}
+	}
+
+	/// Processes HTLCs which are pending waiting on random forward delay.
+	///
+	/// Should only really ever be called in response to a PendingHTLCsForwardable event.
+	/// Will likely generate further events.
+	pub fn process_pending_htlc_forwards(&self) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let mut new_events = VecDeque::new();
+		let mut failed_forwards = Vec::new();
+		let mut phantom_receives: Vec<(u64, OutPoint, ChannelId, u128, Vec<(PendingHTLCInfo, u64)>)> = Vec::new();
+		{
+			let mut forward_htlcs = HashMap::new();
+			mem::swap(&mut forward_htlcs, &mut self.forward_htlcs.lock().unwrap());
+			self.resolve_trampoline_forwards(&mut forward_htlcs, &mut failed_forwards);
+
+			for (short_chan_id, mut pending_forwards) in forward_htlcs {
 				if short_chan_id != 0 {
 					let mut forwarding_counterparty = None;
 					macro_rules! forwarding_channel_not_found {
@@ -4231,7 +5798,7 @@ where
 							for forward_info in pending_forwards.drain(..) {
 								match forward_info {
 									HTLCForwardInfo::AddHTLC(PendingAddHTLCInfo {
-										prev_short_channel_id, prev_htlc_id, prev_funding_outpoint, prev_user_channel_id,
+										prev_short_channel_id, prev_htlc_id, prev_channel_id, prev_funding_outpoint, prev_user_channel_id,
 										forward_info: PendingHTLCInfo {
 											routing, incoming_shared_secret, payment_hash, outgoing_amt_msat,
 											outgoing_cltv_value, ..
@@ -4239,17 +5806,19 @@ where
 									}) => {
 										macro_rules! failure_handler {
 											($msg: expr, $err_code: expr, $err_data: expr, $phantom_ss: expr, $next_hop_unknown: expr) => {
-												let logger = WithContext::from(&self.logger, forwarding_counterparty, Some(prev_funding_outpoint.to_channel_id()));
+												let logger = WithContext::from(&self.logger, forwarding_counterparty, Some(prev_channel_id));
 												log_info!(logger, "Failed to accept/forward incoming HTLC: {}", $msg);
 
 												let htlc_source = HTLCSource::PreviousHopData(HTLCPreviousHopData {
 													short_channel_id: prev_short_channel_id,
 													user_channel_id: Some(prev_user_channel_id),
 													outpoint: prev_funding_outpoint,
+													channel_id: prev_channel_id,
 													htlc_id: prev_htlc_id,
 													incoming_packet_shared_secret: incoming_shared_secret,
 													phantom_shared_secret: $phantom_ss,
 													blinded_failure: routing.blinded_failure(),
+													trampoline_forward: routing.trampoline_forward(),
 												});
 
 												let reason = if $next_hop_unknown {
@@ -4308,10 +5877,13 @@ where
 															outgoing_cltv_value, Some(phantom_shared_secret), false, None,
 															current_height, self.default_configuration.accept_mpp_keysend)
 														{
-															Ok(info) => phantom_receives.push((prev_short_channel_id, prev_funding_outpoint, prev_user_channel_id, vec![(info, prev_htlc_id)])),
+															Ok(info) => phantom_receives.push((prev_short_channel_id, prev_funding_outpoint, prev_channel_id, prev_user_channel_id, vec![(info, prev_htlc_id)])),
 															Err(InboundOnionErr { err_code, err_data, msg }) => failed_payment!(msg, err_code, err_data, Some(phantom_shared_secret))
 														}
 													},
+													onion_utils::Hop::TrampolineForward { .. } => {
+														failed_payment!("Phantom nodes cannot act as trampoline nodes", 0x4000 | 22, Vec::new(), Some(phantom_shared_secret));
+													},
 													_ => panic!(),
 												}
 											} else {
@@ -4353,12 +5925,12 @@ where
 						for forward_info in pending_forwards.drain(..) {
 							match forward_info {
 								HTLCForwardInfo::AddHTLC(PendingAddHTLCInfo {
-									prev_short_channel_id, prev_htlc_id, prev_funding_outpoint, prev_user_channel_id,
+									prev_short_channel_id, prev_htlc_id, prev_channel_id, prev_funding_outpoint, prev_user_channel_id,
 									forward_info: PendingHTLCInfo {
-										incoming_shared_secret, payment_hash, outgoing_amt_msat, outgoing_cltv_value,
-										routing: PendingHTLCRouting::Forward {
-											onion_packet, blinded, ..
-										}, skimmed_fee_msat, ..
+										incoming_shared_secret, payment_hash, incoming_amt_msat, outgoing_amt_msat,
+										outgoing_cltv_value, routing: PendingHTLCRouting::Forward {
+											onion_packet, blinded, trampoline_forward, ..
+										}, skimmed_fee_msat, incoming_endorsed, ..
 									},
 								}) => {
 									log_trace!(logger, "Adding HTLC from short id {} with payment_hash {} to channel with short id {} after delay", prev_short_channel_id, &payment_hash, short_chan_id);
@@ -4366,11 +5938,13 @@ where
 										short_channel_id: prev_short_channel_id,
 										user_channel_id: Some(prev_user_channel_id),
 										outpoint: prev_funding_outpoint,
+										channel_id: prev_channel_id,
 										htlc_id: prev_htlc_id,
 										incoming_packet_shared_secret: incoming_shared_secret,
 										// Phantom payments are only PendingHTLCRouting::Receive.
 										phantom_shared_secret: None,
 										blinded_failure: blinded.map(|_| BlindedFailure::FromIntroductionNode),
+										trampoline_forward,
 									});
 									let next_blinding_point = blinded.and_then(|b| {
 										let encrypted_tlvs_ss = self.node_signer.ecdh(
@@ -4380,11 +5954,71 @@ where
 											&self.secp_ctx, b.inbound_blinding_point, &encrypted_tlvs_ss
 										).ok()
 									});
+									let endorsement_config = &self.default_configuration.htlc_endorsement_config;
+									let forwarding_limits = &self.default_configuration.peer_forwarding_limits;
+									let limit_forwards = forwarding_limits.max_inflight_htlcs.is_some() ||
+										forwarding_limits.max_inflight_value_msat.is_some() ||
+										forwarding_limits.max_htlcs_per_second.is_some();
+									let incoming_node_id = if endorsement_config.enabled || limit_forwards {
+										self.short_to_chan_info.read().unwrap().get(&prev_short_channel_id)
+											.map(|(node_id, _)| *node_id)
+									} else { None };
+									let incoming_htlc = (prev_short_channel_id, prev_htlc_id);
+									let now_secs = self.duration_since_epoch().as_secs();
+									if let (true, Some(incoming_node_id)) = (limit_forwards, incoming_node_id) {
+										let add_res = self.forwarding_usage.lock().unwrap().add_htlc(
+											forwarding_limits, incoming_node_id, incoming_htlc, outgoing_amt_msat, now_secs
+										);
+										if let Err(limit) = add_res {
+											log_debug!(logger, "Failing HTLC with payment_hash {} from peer {} as it exceeded its forwarding limit {:?}", &payment_hash, incoming_node_id, limit);
+											self.pending_events.lock().unwrap().push_back((events::Event::PeerForwardingLimitExceeded {
+												counterparty_node_id: incoming_node_id,
+												prev_channel_id,
+												inbound_amount_msat: incoming_amt_msat.unwrap_or(outgoing_amt_msat),
+												limit,
+											}, None));
+											let (failure_code, data) = self.get_htlc_temp_fail_err_and_data(0x1000|7, short_chan_id, chan);
+											failed_forwards.push((htlc_source, payment_hash,
+												HTLCFailReason::reason(failure_code, data),
+												HTLCDestination::NextHopChannel { node_id: Some(chan.context.get_counterparty_node_id()), channel_id: forward_chan_id }
+											));
+											continue;
+										}
+									}
+									let endorsed = if let (true, Some(incoming_node_id)) = (endorsement_config.enabled, incoming_node_id) {
+										let fee_msat = incoming_amt_msat.unwrap_or(outgoing_amt_msat)
+											.saturating_sub(outgoing_amt_msat);
+										let cur_height = self.best_block.read().unwrap().height();
+										let max_hold_secs = (outgoing_cltv_value.saturating_sub(cur_height) as u64) * 600;
+										let add_res = self.htlc_reputation.lock().unwrap().add_htlc(
+											endorsement_config, incoming_htlc, incoming_node_id,
+											incoming_endorsed == Some(true), short_chan_id, outgoing_amt_msat, fee_msat,
+											max_hold_secs, chan.context.get_counterparty_max_accepted_htlcs(),
+											chan.context.get_counterparty_max_htlc_value_in_flight_msat(), now_secs
+										);
+										match add_res {
+											Ok(endorsed) => Some(endorsed),
+											Err(()) => {
+												self.forwarding_usage.lock().unwrap().remove_htlc(incoming_htlc, now_secs);
+												log_trace!(logger, "Failing HTLC with payment_hash {} as it would use resources on short id {} reserved for endorsed HTLCs", &payment_hash, short_chan_id);
+												let (failure_code, data) = self.get_htlc_temp_fail_err_and_data(0x1000|7, short_chan_id, chan);
+												failed_forwards.push((htlc_source, payment_hash,
+													HTLCFailReason::reason(failure_code, data),
+													HTLCDestination::NextHopChannel { node_id: Some(chan.context.get_counterparty_node_id()), channel_id: forward_chan_id }
+												));
+												continue;
+											},
+										}
+									} else if endorsement_config.enabled { Some(false) } else { None };
 									if let Err(e) = chan.queue_add_htlc(outgoing_amt_msat,
 										payment_hash, outgoing_cltv_value, htlc_source.clone(),
-										onion_packet, skimmed_fee_msat, next_blinding_point, &self.fee_estimator,
-										&&logger)
+										onion_packet, skimmed_fee_msat, next_blinding_point, endorsed,
+										&self.fee_estimator, &&logger)
 									{
+										if incoming_node_id.is_some() {
+											self.htlc_reputation.lock().unwrap().remove_htlc(incoming_htlc);
+											self.forwarding_usage.lock().unwrap().remove_htlc(incoming_htlc, now_secs);
+										}
 										if let ChannelError::Ignore(msg) = e {
 											log_trace!(logger, "Failed to forward HTLC with payment_hash {}: {}", &payment_hash, msg);
 										} else {
@@ -4441,13 +6075,20 @@ where
 					'next_forwardable_htlc: for forward_info in pending_forwards.drain(..) {
 						match forward_info {
 							HTLCForwardInfo::AddHTLC(PendingAddHTLCInfo {
-								prev_short_channel_id, prev_htlc_id, prev_funding_outpoint, prev_user_channel_id,
+								prev_short_channel_id, prev_htlc_id, prev_channel_id, prev_funding_outpoint, prev_user_channel_id,
 								forward_info: PendingHTLCInfo {
 									routing, incoming_shared_secret, payment_hash, incoming_amt_msat, outgoing_amt_msat,
 									skimmed_fee_msat, ..
 								}
 							}) => {
 								let blinded_failure = routing.blinded_failure();
+								// The parts of an AMP payment each have their own payment hash, but are
+								// tracked together under the payment's set id.
+								let htlc_payment_hash = payment_hash;
+								let payment_hash = match &routing {
+									PendingHTLCRouting::ReceiveAmp { amp_record, .. } => PaymentHash(amp_record.set_id),
+									_ => payment_hash,
+								};
 								let (cltv_expiry, onion_payload, payment_data, phantom_shared_secret, mut onion_fields) = match routing {
 									PendingHTLCRouting::Receive {
 										payment_data, payment_metadata, incoming_cltv_expiry, phantom_shared_secret,
@@ -4455,19 +6096,32 @@ where
 									} => {
 										let _legacy_hop_data = Some(payment_data.clone());
 										let onion_fields = RecipientOnionFields { payment_secret: Some(payment_data.payment_secret),
-												payment_metadata, custom_tlvs };
+												payment_metadata, custom_tlvs, trampoline_packet: None, amp_record: None };
 										(incoming_cltv_expiry, OnionPayload::Invoice { _legacy_hop_data },
 											Some(payment_data), phantom_shared_secret, onion_fields)
 									},
-									PendingHTLCRouting::ReceiveKeysend { payment_data, payment_preimage, payment_metadata, incoming_cltv_expiry, custom_tlvs } => {
+									PendingHTLCRouting::ReceiveKeysend { payment_data, payment_preimage, payment_metadata, incoming_cltv_expiry, custom_tlvs, requires_blinded_error: _ } => {
 										let onion_fields = RecipientOnionFields {
 											payment_secret: payment_data.as_ref().map(|data| data.payment_secret),
 											payment_metadata,
 											custom_tlvs,
+											trampoline_packet: None,
+											amp_record: None,
 										};
 										(incoming_cltv_expiry, OnionPayload::Spontaneous(payment_preimage),
 											payment_data, None, onion_fields)
 									},
+									PendingHTLCRouting::ReceiveAmp { payment_data, amp_record, payment_metadata, incoming_cltv_expiry, custom_tlvs } => {
+										let onion_fields = RecipientOnionFields {
+											payment_secret: Some(payment_data.payment_secret),
+											payment_metadata,
+											custom_tlvs,
+											trampoline_packet: None,
+											amp_record: None,
+										};
+										(incoming_cltv_expiry, OnionPayload::Amp { amp_record, payment_hash: htlc_payment_hash },
+											Some(payment_data), None, onion_fields)
+									},
 									_ => {
 										panic!("short_channel_id == 0 should imply any pending_forward entries are of type Receive");
 									}
@@ -4477,10 +6131,12 @@ where
 										short_channel_id: prev_short_channel_id,
 										user_channel_id: Some(prev_user_channel_id),
 										outpoint: prev_funding_outpoint,
+										channel_id: prev_channel_id,
 										htlc_id: prev_htlc_id,
 										incoming_packet_shared_secret: incoming_shared_secret,
 										phantom_shared_secret,
 										blinded_failure,
+										trampoline_forward: None,
 									},
 									// We differentiate the received value from the sender intended value
 									// if possible so that we don't prematurely mark MPP payments complete
@@ -4508,10 +6164,12 @@ where
 												short_channel_id: $htlc.prev_hop.short_channel_id,
 												user_channel_id: $htlc.prev_hop.user_channel_id,
 												outpoint: prev_funding_outpoint,
+												channel_id: prev_channel_id,
 												htlc_id: $htlc.prev_hop.htlc_id,
 												incoming_packet_shared_secret: $htlc.prev_hop.incoming_packet_shared_secret,
 												phantom_shared_secret,
 												blinded_failure,
+												trampoline_forward: None,
 											}), payment_hash,
 											HTLCFailReason::reason(0x4000 | 15, htlc_msat_height_data),
 											HTLCDestination::FailedPayment { payment_hash: $payment_hash },
@@ -4533,10 +6191,34 @@ where
 											events::PaymentPurpose::SpontaneousPayment(_) => true,
 											events::PaymentPurpose::InvoicePayment { .. } => false,
 										};
+										// Hold `hold_invoices` until we've locked `claimable_payments` so that a
+										// concurrent settle or cancel either sees this HTLC or is seen here.
+										let hold_invoices = self.hold_invoices.lock().unwrap();
 										let mut claimable_payments = self.claimable_payments.lock().unwrap();
+										let hold_invoice_closed = hold_invoices.get(&payment_hash)
+											.map_or(false, |hold| hold.status != HoldInvoiceStatus::Open);
+										mem::drop(hold_invoices);
+										if hold_invoice_closed {
+											log_trace!(self.logger, "Failing new HTLC with payment_hash {} as its hold invoice was already settled or cancelled", &payment_hash);
+											fail_htlc!(claimable_htlc, payment_hash);
+										}
 										if claimable_payments.pending_claiming_payments.contains_key(&payment_hash) {
 											fail_htlc!(claimable_htlc, payment_hash);
 										}
+										// Until all parts of an AMP payment have arrived they share a placeholder
+										// purpose, which is replaced once the preimage is known.
+										let amp_purpose = if let OnionPayload::Amp { .. } = claimable_htlc.onion_payload {
+											let earlier_htlcs = claimable_payments.claimable_payments.get(&payment_hash)
+												.map_or(&[][..], |payment| &payment.htlcs[..]);
+											match reconstruct_amp_root_seed(&payment_hash, earlier_htlcs, &claimable_htlc) {
+												Ok(root_seed) => root_seed.map(events::PaymentPurpose::SpontaneousPayment),
+												Err(()) => {
+													// Any earlier parts will time out as the payment is incomplete.
+													log_trace!(self.logger, "Failing new AMP HTLC with payment_hash {} as its parts didn't reconstruct the root seed", &payment_hash);
+													fail_htlc!(claimable_htlc, payment_hash);
+												}
+											}
+										} else { None };
 										let ref mut claimable_payment = claimable_payments.claimable_payments
 											.entry(payment_hash)
 											// Note that if we insert here we MUST NOT fail_htlc!()
@@ -4551,7 +6233,8 @@ where
 											log_trace!(self.logger, "Failing new {} HTLC with payment_hash {} as we already had an existing {} HTLC with the same payment hash", log_keysend(is_keysend), &payment_hash, log_keysend(!is_keysend));
 											fail_htlc!(claimable_htlc, payment_hash);
 										}
-										if !self.default_configuration.accept_mpp_keysend && is_keysend && !claimable_payment.htlcs.is_empty() {
+										let is_amp = matches!(claimable_htlc.onion_payload, OnionPayload::Amp { .. });
+										if !self.default_configuration.accept_mpp_keysend && is_keysend && !is_amp && !claimable_payment.htlcs.is_empty() {
 											log_trace!(self.logger, "Failing new keysend HTLC with payment_hash {} as we already had an existing keysend HTLC with the same payment hash and our config states we don't accept MPP keysend", &payment_hash);
 											fail_htlc!(claimable_htlc, payment_hash);
 										}
@@ -4587,7 +6270,6 @@ where
 											#[allow(unused_assignments)] {
 												committed_to_claimable = true;
 											}
-											let prev_channel_id = prev_funding_outpoint.to_channel_id();
 											htlcs.push(claimable_htlc);
 											let amount_msat = htlcs.iter().map(|htlc| htlc.value).sum();
 											htlcs.iter_mut().for_each(|htlc| htlc.total_value_received = Some(amount_msat));
@@ -4595,10 +6277,17 @@ where
 												.map(|htlc| htlc.counterparty_skimmed_fee_msat.unwrap_or(0)).sum();
 											debug_assert!(total_value.saturating_sub(amount_msat) <=
 												counterparty_skimmed_fee_msat);
+											let purpose = match amp_purpose {
+												Some(purpose) => {
+													claimable_payment.purpose = purpose.clone();
+													purpose
+												},
+												None => $purpose,
+											};
 											new_events.push_back((events::Event::PaymentClaimable {
 												receiver_node_id: Some(receiver_node_id),
 												payment_hash,
-												purpose: $purpose,
+												purpose,
 												amount_msat,
 												counterparty_skimmed_fee_msat,
 												via_channel_id: Some(prev_channel_id),
@@ -4656,11 +6345,15 @@ where
 											OnionPayload::Spontaneous(preimage) => {
 												let purpose = events::PaymentPurpose::SpontaneousPayment(preimage);
 												check_total_value!(purpose);
-											}
+											},
+											OnionPayload::Amp { .. } => {
+												let purpose = events::PaymentPurpose::SpontaneousPayment(PaymentPreimage(payment_hash.0));
+												check_total_value!(purpose);
+											},
 										}
 									},
 									hash_map::Entry::Occupied(inbound_payment) => {
-										if let OnionPayload::Spontaneous(_) = claimable_htlc.onion_payload {
+										if let OnionPayload::Spontaneous(_) | OnionPayload::Amp { .. } = claimable_htlc.onion_payload {
 											log_trace!(self.logger, "Failing new keysend HTLC with payment_hash {} because we already have an inbound payment with the same payment hash", &payment_hash);
 											fail_htlc!(claimable_htlc, payment_hash);
 										}
@@ -4695,7 +6388,7 @@ where
 		}
 
 		let best_block_height = self.best_block.read().unwrap().height();
-		self.pending_outbound_payments.check_retry_payments(&self.router, || self.list_usable_channels(),
+		self.pending_outbound_payments.check_retry_payments(&&self.rebalance_router(), || self.list_usable_channels(),
 			|| self.compute_inflight_htlcs(), &self.entropy_source, &self.node_signer, best_block_height,
 			&self.pending_events, &self.logger, |args| self.send_payment_along_path(args));
 
@@ -4743,7 +6436,7 @@ where
 						if let Some(peer_state_mutex) = per_peer_state.get(&counterparty_node_id) {
 							let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 							let peer_state = &mut *peer_state_lock;
-							match peer_state.channel_by_id.entry(funding_txo.to_channel_id()) {
+							match peer_state.channel_by_id.entry(peer_state.channel_id_for_funding_outpoint(&funding_txo)) {
 								hash_map::Entry::Occupied(mut chan_phase) => {
 									if let ChannelPhase::Funded(chan) = chan_phase.get_mut() {
 										updated_chan = true;
@@ -4792,6 +6485,8 @@ where
 
 	fn update_channel_fee(&self, chan_id: &ChannelId, chan: &mut Channel<SP>, new_feerate: u32) -> NotifyOption {
 		if !chan.context.is_outbound() { return NotifyOption::SkipPersistNoEvents; }
+		// Zero-fee commitment transactions are bumped entirely via their ephemeral anchor.
+		if chan.context.get_channel_type().supports_zero_fee_commitments() { return NotifyOption::SkipPersistNoEvents; }
 
 		let logger = WithChannelContext::from(&self.logger, &chan.context);
 
@@ -4863,6 +6558,8 @@ where
 	///    or those awaiting an invoice that hasn't been delivered in the necessary amount of time.
 	///    The latter is determined using the system clock in `std` and the highest seen block time
 	///    minus two hours in `no-std`.
+	///  * Sending an updated backup of our channels to our peers if our set of channels changed and
+	///    [`UserConfig::enable_peer_storage`] is set.
 	///
 	/// Note that this may cause reentrancy through [`chain::Watch::update_channel`] calls or feerate
 	/// estimate fetches.
@@ -4973,6 +6670,34 @@ where
 									_ => {},
 								}
 
+								let dynamic_fee_config = &self.default_configuration.dynamic_fee_config;
+								if dynamic_fee_config.enabled && chan.context.is_usable() {
+									let mut config = chan.context.config();
+									let balance_msat = chan.context.get_available_balances(&self.fee_estimator).balance_msat;
+									let new_fees = self.dynamic_fees.lock().unwrap().timer_tick(
+										dynamic_fee_config, *chan_id, chan.context.get_value_satoshis() * 1000, balance_msat,
+										config.forwarding_fee_base_msat, config.forwarding_fee_proportional_millionths
+									);
+									if let Some((fee_base_msat, fee_proportional_millionths)) = new_fees {
+										let logger = WithChannelContext::from(&self.logger, &chan.context);
+										log_debug!(logger, "Updating fees of channel {} to {} msat + {} ppm",
+											chan_id, fee_base_msat, fee_proportional_millionths);
+										config.forwarding_fee_base_msat = fee_base_msat;
+										config.forwarding_fee_proportional_millionths = fee_proportional_millionths;
+										if chan.context.update_config(&config) {
+											if let Ok(msg) = self.get_channel_update_for_broadcast(&chan) {
+												pending_msg_events.push(events::MessageSendEvent::BroadcastChannelUpdate { msg });
+											} else if let Ok(msg) = self.get_channel_update_for_unicast(&chan) {
+												pending_msg_events.push(events::MessageSendEvent::SendChannelUpdate {
+													node_id: counterparty_node_id,
+													msg,
+												});
+											}
+											should_persist = NotifyOption::DoPersist;
+										}
+									}
+								}
+
 								chan.context.maybe_expire_prev_config();
 
 								if chan.should_disconnect_peer_awaiting_response() {
@@ -5000,6 +6725,16 @@ where
 								process_unfunded_channel_tick(chan_id, &mut chan.context, &mut chan.unfunded_context,
 									pending_msg_events, counterparty_node_id)
 							},
+							#[cfg(dual_funding)]
+							ChannelPhase::UnfundedInboundV2(chan) => {
+								process_unfunded_channel_tick(chan_id, &mut chan.context, &mut chan.unfunded_context,
+									pending_msg_events, counterparty_node_id)
+							},
+							#[cfg(dual_funding)]
+							ChannelPhase::UnfundedOutboundV2(chan) => {
+								process_unfunded_channel_tick(chan_id, &mut chan.context, &mut chan.unfunded_context,
+									pending_msg_events, counterparty_node_id)
+							},
 						}
 					});
 
@@ -5058,7 +6793,7 @@ where
 					debug_assert!(false);
 					return false;
 				}
-				if let OnionPayload::Invoice { .. } = payment.htlcs[0].onion_payload {
+				if let OnionPayload::Invoice { .. } | OnionPayload::Amp { .. } = payment.htlcs[0].onion_payload {
 					// Check if we've received all the parts we need for an MPP (the value of the parts adds to total_msat).
 					// In this case we're not going to handle any timeouts of the parts here.
 					// This condition determining whether the MPP is complete here must match
@@ -5079,6 +6814,20 @@ where
 				true
 			});
 
+			// Once an invoice has expired no further payments for it will be accepted, so we only need
+			// to remember hold invoices which still have HTLCs pending.
+			let highest_seen_timestamp = self.highest_seen_timestamp.load(Ordering::Acquire) as u64;
+			let mut hold_invoices = self.hold_invoices.lock().unwrap();
+			let claimable_payments = self.claimable_payments.lock().unwrap();
+			hold_invoices.retain(|payment_hash, hold| {
+				hold.expiry_time >= highest_seen_timestamp
+					|| claimable_payments.claimable_payments.contains_key(payment_hash)
+					|| claimable_payments.pending_claiming_payments.contains_key(payment_hash)
+			});
+			mem::drop(claimable_payments);
+			mem::drop(hold_invoices);
+			self.prune_inbound_recurrences();
+
 			for htlc_source in timed_out_mpp_htlcs.drain(..) {
 				let source = HTLCSource::PreviousHopData(htlc_source.0.clone());
 				let reason = HTLCFailReason::from_failure_code(23);
@@ -5106,6 +6855,17 @@ where
 			self.pending_outbound_payments.remove_stale_payments(
 				duration_since_epoch, &self.pending_events
 			);
+			self.hrn_resolver.retain_pending_resolutions(
+				|payment_id| self.pending_outbound_payments.is_awaiting_offer(payment_id)
+			);
+			self.awaiting_static_invoices.lock().unwrap().retain(
+				|payment_id, _| self.pending_outbound_payments.is_awaiting_invoice(*payment_id)
+			);
+
+			self.pending_held_htlcs.lock().unwrap().early_releases.retain(|_, ticks_remaining| {
+				*ticks_remaining = ticks_remaining.saturating_sub(1);
+				*ticks_remaining > 0
+			});
 
 			// Technically we don't need to do this here, but if we have holding cell entries in a
 			// channel that need freeing, it's better to do that here and block a background task
@@ -5114,6 +6874,10 @@ where
 				should_persist = NotifyOption::DoPersist;
 			}
 
+			if self.maybe_send_our_peer_storage() && should_persist == NotifyOption::SkipPersistNoEvents {
+				should_persist = NotifyOption::SkipPersistHandleEvents;
+			}
+
 			should_persist
 		});
 	}
@@ -5283,13 +7047,20 @@ where
 			},
 			HTLCSource::PreviousHopData(HTLCPreviousHopData {
 				ref short_channel_id, ref htlc_id, ref incoming_packet_shared_secret,
-				ref phantom_shared_secret, ref outpoint, ref blinded_failure, ..
+				ref phantom_shared_secret, ref channel_id, ref blinded_failure,
+				ref trampoline_forward, ..
 			}) => {
 				log_trace!(
-					WithContext::from(&self.logger, None, Some(outpoint.to_channel_id())),
+					WithContext::from(&self.logger, None, Some(*channel_id)),
 					"Failing {}HTLC with payment_hash {} backwards from us: {:?}",
 					if blinded_failure.is_some() { "blinded " } else { "" }, &payment_hash, onion_error
 				);
+				let now_secs = self.duration_since_epoch().as_secs();
+				self.htlc_reputation.lock().unwrap().resolve_htlc(
+					&self.default_configuration.htlc_endorsement_config, (*short_channel_id, *htlc_id),
+					false, now_secs
+				);
+				self.forwarding_usage.lock().unwrap().remove_htlc((*short_channel_id, *htlc_id), now_secs);
 				let failure = match blinded_failure {
 					Some(BlindedFailure::FromIntroductionNode) => {
 						let blinded_onion_error = HTLCFailReason::reason(INVALID_ONION_BLINDING, vec![0; 32]);
@@ -5305,6 +7076,19 @@ where
 							sha256_of_onion: [0; 32]
 						}
 					},
+					None if trampoline_forward.is_some() => {
+						// Failures from the route we found to the next trampoline node are encrypted
+						// to us rather than the sender, so decrypt them and fail back with our own.
+						let trampoline_forward = trampoline_forward.as_ref().unwrap();
+						let path = Path { hops: trampoline_forward.hops.clone(), blinded_tail: None };
+						let trampoline_error = onion_error.for_trampoline_forward(
+							&self.secp_ctx, &path, &trampoline_forward.session_priv
+						);
+						let err_packet = trampoline_error.get_encrypted_failure_packet(
+							incoming_packet_shared_secret, phantom_shared_secret
+						);
+						HTLCForwardInfo::FailHTLC { htlc_id: *htlc_id, err_packet }
+					},
 					None => {
 						let err_packet = onion_error.get_encrypted_failure_packet(
 							incoming_packet_shared_secret, phantom_shared_secret
@@ -5330,7 +7114,7 @@ where
 				if push_forward_ev { self.push_pending_forwards_ev(); }
 				let mut pending_events = self.pending_events.lock().unwrap();
 				pending_events.push_back((events::Event::HTLCHandlingFailed {
-					prev_channel_id: outpoint.to_channel_id(),
+					prev_channel_id: *channel_id,
 					failed_next_destination: destination,
 				}, None));
 			},
@@ -5471,9 +7255,16 @@ where
 		}
 		if valid_mpp {
 			for htlc in sources.drain(..) {
-				let prev_hop_chan_id = htlc.prev_hop.outpoint.to_channel_id();
+				let prev_hop_chan_id = htlc.prev_hop.channel_id;
+				// Each part of an AMP payment is claimed with its own preimage, derived from the
+				// payment's root seed.
+				let htlc_preimage = match &htlc.onion_payload {
+					OnionPayload::Amp { amp_record, .. } =>
+						onion_utils::amp_child_preimage(&payment_preimage.0, amp_record.child_index),
+					_ => payment_preimage,
+				};
 				if let Err((pk, err)) = self.claim_funds_from_hop(
-					htlc.prev_hop, payment_preimage,
+					htlc.prev_hop, htlc_preimage,
 					|_, definitely_duplicate| {
 						debug_assert!(!definitely_duplicate, "We shouldn't claim duplicatively from a payment");
 						Some(MonitorUpdateCompletionAction::PaymentClaimed { payment_hash })
@@ -5524,7 +7315,7 @@ where
 
 		{
 			let per_peer_state = self.per_peer_state.read().unwrap();
-			let chan_id = prev_hop.outpoint.to_channel_id();
+			let chan_id = prev_hop.channel_id;
 			let counterparty_node_id_opt = match self.short_to_chan_info.read().unwrap().get(&prev_hop.short_channel_id) {
 				Some((cp_id, _dup_chan_id)) => Some(cp_id.clone()),
 				None => None
@@ -5590,9 +7381,10 @@ where
 								};
 								if let Some(peer_state_mtx) = per_peer_state.get(&node_id) {
 									let mut peer_state = peer_state_mtx.lock().unwrap();
+									let channel_id = peer_state.channel_id_for_funding_outpoint(&funding_outpoint);
 									if let Some(blockers) = peer_state
 										.actions_blocking_raa_monitor_updates
-										.get_mut(&funding_outpoint.to_channel_id())
+										.get_mut(&channel_id)
 									{
 										let mut found_blocker = false;
 										blockers.retain(|iter| {
@@ -5632,7 +7424,7 @@ where
 				// with a preimage we *must* somehow manage to propagate it to the upstream
 				// channel, or we must have an ability to receive the same event and try
 				// again on restart.
-				log_error!(WithContext::from(&self.logger, None, Some(prev_hop.outpoint.to_channel_id())), "Critical error: failed to update channel monitor with preimage {:?}: {:?}",
+				log_error!(WithContext::from(&self.logger, None, Some(prev_hop.channel_id)), "Critical error: failed to update channel monitor with preimage {:?}: {:?}",
 					payment_preimage, update_res);
 			}
 		} else {
@@ -5666,7 +7458,8 @@ where
 
 	fn claim_funds_internal(&self, source: HTLCSource, payment_preimage: PaymentPreimage,
 		forwarded_htlc_value_msat: Option<u64>, from_onchain: bool, startup_replay: bool,
-		next_channel_counterparty_node_id: Option<PublicKey>, next_channel_outpoint: OutPoint
+		next_channel_counterparty_node_id: Option<PublicKey>, next_channel_outpoint: OutPoint,
+		next_channel_id: ChannelId,
 	) {
 		match source {
 			HTLCSource::OutboundRoute { session_priv, payment_id, path, .. } => {
@@ -5684,7 +7477,16 @@ where
 					&self.logger);
 			},
 			HTLCSource::PreviousHopData(hop_data) => {
-				let prev_outpoint = hop_data.outpoint;
+				let now_secs = self.duration_since_epoch().as_secs();
+				self.htlc_reputation.lock().unwrap().resolve_htlc(
+					&self.default_configuration.htlc_endorsement_config,
+					(hop_data.short_channel_id, hop_data.htlc_id), true, now_secs
+				);
+				self.forwarding_usage.lock().unwrap()
+					.remove_htlc((hop_data.short_channel_id, hop_data.htlc_id), now_secs);
+				let prev_channel_id = hop_data.channel_id;
+				let trampoline_forward_amt_msat = hop_data.trampoline_forward.as_ref()
+					.map(|trampoline_forward| trampoline_forward.amt_to_forward_msat);
 				let completed_blocker = RAAMonitorUpdateBlockingAction::from_prev_hop_data(&hop_data);
 				#[cfg(debug_assertions)]
 				let claiming_chan_funding_outpoint = hop_data.outpoint;
@@ -5745,7 +7547,7 @@ where
 										BackgroundEvent::MonitorUpdatesComplete {
 											channel_id, ..
 										} =>
-											*channel_id == claiming_chan_funding_outpoint.to_channel_id(),
+											*channel_id == prev_channel_id,
 									}
 								}), "{:?}", *background_events);
 							}
@@ -5764,13 +7566,20 @@ where
 									Some(claimed_htlc_value - forwarded_htlc_value)
 								} else { None }
 							} else { None };
+							let trampoline_route_fee_msat = trampoline_forward_amt_msat.and_then(|amt|
+								forwarded_htlc_value_msat.map(|forwarded_htlc_value| forwarded_htlc_value.saturating_sub(amt))
+							);
+							if let Some(forwarded_htlc_value) = forwarded_htlc_value_msat {
+								self.dynamic_fees.lock().unwrap().htlc_forwarded(next_channel_id, forwarded_htlc_value);
+							}
 							Some(MonitorUpdateCompletionAction::EmitEventAndFreeOtherChannel {
 								event: events::Event::PaymentForwarded {
 									fee_earned_msat,
 									claim_from_onchain_tx: from_onchain,
-									prev_channel_id: Some(prev_outpoint.to_channel_id()),
-									next_channel_id: Some(next_channel_outpoint.to_channel_id()),
+									prev_channel_id: Some(prev_channel_id),
+									next_channel_id: Some(next_channel_id),
 									outbound_amount_forwarded_msat: forwarded_htlc_value_msat,
+									trampoline_route_fee_msat,
 								},
 								downstream_counterparty_and_funding_outpoint: chan_to_release,
 							})
@@ -5843,7 +7652,7 @@ where
 		commitment_update: Option<msgs::CommitmentUpdate>, order: RAACommitmentOrder,
 		pending_forwards: Vec<(PendingHTLCInfo, u64)>, funding_broadcastable: Option<Transaction>,
 		channel_ready: Option<msgs::ChannelReady>, announcement_sigs: Option<msgs::AnnouncementSignatures>)
-	-> Option<(u64, OutPoint, u128, Vec<(PendingHTLCInfo, u64)>)> {
+	-> Option<(u64, OutPoint, ChannelId, u128, Vec<(PendingHTLCInfo, u64)>)> {
 		let logger = WithChannelContext::from(&self.logger, &channel.context);
 		log_trace!(logger, "Handling channel resumption for channel {} with {} RAA, {} commitment update, {} pending forwards, {}broadcasting funding, {} channel ready, {} announcement",
 			&channel.context.channel_id(),
@@ -5858,7 +7667,7 @@ where
 		let counterparty_node_id = channel.context.get_counterparty_node_id();
 		if !pending_forwards.is_empty() {
 			htlc_forwards = Some((channel.context.get_short_channel_id().unwrap_or(channel.context.outbound_scid_alias()),
-				channel.context.get_funding_txo().unwrap(), channel.context.get_user_id(), pending_forwards));
+				channel.context.get_funding_txo().unwrap(), channel.context.channel_id(), channel.context.get_user_id(), pending_forwards));
 		}
 
 		if let Some(msg) = channel_ready {
@@ -5933,12 +7742,13 @@ where
 		if peer_state_mutex_opt.is_none() { return }
 		peer_state_lock = peer_state_mutex_opt.unwrap().lock().unwrap();
 		let peer_state = &mut *peer_state_lock;
+		let channel_id = peer_state.channel_id_for_funding_outpoint(funding_txo);
 		let channel =
-			if let Some(ChannelPhase::Funded(chan)) = peer_state.channel_by_id.get_mut(&funding_txo.to_channel_id()) {
+			if let Some(ChannelPhase::Funded(chan)) = peer_state.channel_by_id.get_mut(&channel_id) {
 				chan
 			} else {
 				let update_actions = peer_state.monitor_update_blocked_actions
-					.remove(&funding_txo.to_channel_id()).unwrap_or(Vec::new());
+					.remove(&channel_id).unwrap_or(Vec::new());
 				mem::drop(peer_state_lock);
 				mem::drop(per_peer_state);
 				self.handle_monitor_update_completion_actions(update_actions);
@@ -6017,15 +7827,20 @@ where
 		// happening and return an error. N.B. that we create channel with an outbound SCID of zero so
 		// that we can delay allocating the SCID until after we're sure that the checks below will
 		// succeed.
-		let mut channel = match peer_state.inbound_channel_request_by_id.remove(temporary_channel_id) {
-			Some(unaccepted_channel) => {
+		let mut channel = match peer_state.inbound_channel_request_by_id.get(temporary_channel_id) {
+			Some(InboundChannelRequest { open_channel_msg: OpenChannelMessage::V1(open_channel_msg), .. }) => {
 				let best_block_height = self.best_block.read().unwrap().height();
-				InboundV1Channel::new(&self.fee_estimator, &self.entropy_source, &self.signer_provider,
+				let res = InboundV1Channel::new(&self.fee_estimator, &self.entropy_source, &self.signer_provider,
 					counterparty_node_id.clone(), &self.channel_type_features(), &peer_state.latest_features,
-					&unaccepted_channel.open_channel_msg, user_channel_id, &self.default_configuration, best_block_height,
-					&self.logger, accept_0conf).map_err(|e| APIError::ChannelUnavailable { err: e.to_string() })
+					open_channel_msg, user_channel_id, &self.default_configuration, best_block_height,
+					&self.logger, accept_0conf).map_err(|e| APIError::ChannelUnavailable { err: e.to_string() });
+				peer_state.inbound_channel_request_by_id.remove(temporary_channel_id);
+				res
 			}
-			_ => Err(APIError::APIMisuseError { err: "No such channel awaiting to be accepted.".to_owned() })
+			#[cfg(dual_funding)]
+			Some(InboundChannelRequest { open_channel_msg: OpenChannelMessage::V2(_), .. }) =>
+				Err(APIError::APIMisuseError { err: "Please use accept_inbound_dual_funded_channel to accept dual-funded channels.".to_owned() }),
+			None => Err(APIError::APIMisuseError { err: "No such channel awaiting to be accepted.".to_owned() })
 		}?;
 
 		if accept_0conf {
@@ -6070,6 +7885,157 @@ where
 		Ok(())
 	}
 
+	/// Accepts a request to open a dual-funded channel after an
+	/// [`Event::OpenDualFundedChannelRequest`], contributing `funding_satoshis` to it.
+	///
+	/// `funding_satoshis` must be covered, along with our share of the funding transaction fee at
+	/// the feerate set by our counterparty, by the outputs spent by `funding_inputs`. Each input
+	/// must be provided alongside the transaction it spends and is currently assumed to be a P2WPKH
+	/// input for fee estimation purposes. Any change is paid to `change_script` or, if `None`, to a
+	/// script from [`SignerProvider::get_destination_script`]. To accept the channel without
+	/// contributing to it, pass a `funding_satoshis` of 0 and no inputs.
+	///
+	/// The `user_channel_id` parameter will be provided back in
+	/// [`Event::ChannelClosed::user_channel_id`] to allow tracking of which events correspond
+	/// with which `accept_inbound_dual_funded_channel` call.
+	///
+	/// [`Event::OpenDualFundedChannelRequest`]: events::Event::OpenDualFundedChannelRequest
+	/// [`Event::ChannelClosed::user_channel_id`]: events::Event::ChannelClosed::user_channel_id
+	#[cfg(dual_funding)]
+	pub fn accept_inbound_dual_funded_channel(
+		&self, temporary_channel_id: &ChannelId, counterparty_node_id: &PublicKey, user_channel_id: u128,
+		funding_satoshis: u64, funding_inputs: Vec<(TxIn, Transaction)>, change_script: Option<ScriptBuf>,
+	) -> Result<(), APIError> {
+		let funding_inputs = check_funding_inputs(funding_inputs)
+			.map_err(|err| APIError::APIMisuseError { err })?;
+
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let peers_without_funded_channels =
+			self.peers_without_funded_channels(|peer| { peer.total_channel_count() > 0 });
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		let is_only_peer_channel = peer_state.total_channel_count() == 1;
+
+		let mut channel = match peer_state.inbound_channel_request_by_id.get(temporary_channel_id) {
+			Some(InboundChannelRequest { open_channel_msg: OpenChannelMessage::V2(open_channel_msg), .. }) => {
+				let best_block_height = self.best_block.read().unwrap().height();
+				let res = InboundV2Channel::new(&self.fee_estimator, &self.entropy_source, &self.signer_provider,
+					counterparty_node_id.clone(), &self.channel_type_features(), &peer_state.latest_features,
+					open_channel_msg, funding_satoshis, funding_inputs, change_script, user_channel_id,
+					&self.default_configuration, best_block_height, &self.logger
+				).map_err(|e| APIError::ChannelUnavailable { err: e.to_string() });
+				peer_state.inbound_channel_request_by_id.remove(temporary_channel_id);
+				res
+			},
+			Some(InboundChannelRequest { open_channel_msg: OpenChannelMessage::V1(_), .. }) =>
+				Err(APIError::APIMisuseError { err: "Please use accept_inbound_channel to accept channels which are not dual-funded.".to_owned() }),
+			None => Err(APIError::APIMisuseError { err: "No such channel awaiting to be accepted.".to_owned() })
+		}?;
+
+		let refusal = if channel.context.get_channel_type().requires_zero_conf() {
+			Some(("No zero confirmation channels accepted", "Zero confirmation dual-funded channels are not supported."))
+		} else if is_only_peer_channel && peers_without_funded_channels >= MAX_UNFUNDED_CHANNEL_PEERS {
+			// If this peer already has some channels, a new channel won't increase our number of
+			// peers with unfunded channels, so as long as we aren't over the maximum number of
+			// unfunded channels per-peer we can accept channels from a peer with existing ones.
+			Some(("Have too many peers with unfunded channels, not accepting new ones", "Too many peers with unfunded channels, refusing to accept new ones"))
+		} else { None };
+		if let Some((data, err)) = refusal {
+			peer_state.pending_msg_events.push(events::MessageSendEvent::HandleError {
+				node_id: channel.context.get_counterparty_node_id(),
+				action: msgs::ErrorAction::SendErrorMessage{
+					msg: msgs::ErrorMessage { channel_id: temporary_channel_id.clone(), data: data.to_owned() }
+				}
+			});
+			return Err(APIError::APIMisuseError { err: err.to_owned() });
+		}
+
+		// Now that we know we have a channel, assign an outbound SCID alias.
+		let outbound_scid_alias = self.create_and_insert_outbound_scid_alias();
+		channel.context.set_outbound_scid_alias(outbound_scid_alias);
+
+		peer_state.pending_msg_events.push(events::MessageSendEvent::SendAcceptChannelV2 {
+			node_id: channel.context.get_counterparty_node_id(),
+			msg: channel.accept_inbound_dual_funded_channel(),
+		});
+
+		// As the non-initiator we wait for our counterparty's first message before contributing ours.
+		let channel_id = channel.context.channel_id();
+		if let Err(e) = channel.begin_interactive_funding_tx_construction(&self.entropy_source) {
+			peer_state.pending_msg_events.pop();
+			let api_err = APIError::APIMisuseError { err: e.to_string() };
+			let mut phase = ChannelPhase::UnfundedInboundV2(channel);
+			let (_, err) = convert_chan_phase_err!(self, e, &mut phase, &channel_id);
+			mem::drop(peer_state_lock);
+			mem::drop(per_peer_state);
+			let _: Result<(), _> = handle_error!(self, Err(err), *counterparty_node_id);
+			return Err(api_err);
+		}
+		peer_state.channel_by_id.insert(channel_id, ChannelPhase::UnfundedInboundV2(channel));
+
+		Ok(())
+	}
+
+	/// Provides our signatures for the inputs we contributed to a dual-funded channel's funding
+	/// transaction, after an [`Event::FundingTransactionReadyForSigning`].
+	///
+	/// `transaction` must be the transaction provided in the event with witnesses set for all of
+	/// our inputs. Once both we and our counterparty have exchanged signatures, the funding
+	/// transaction is broadcast and an [`Event::ChannelPending`] is generated.
+	///
+	/// [`Event::FundingTransactionReadyForSigning`]: events::Event::FundingTransactionReadyForSigning
+	/// [`Event::ChannelPending`]: events::Event::ChannelPending
+	#[cfg(dual_funding)]
+	pub fn funding_transaction_signed(
+		&self, channel_id: &ChannelId, counterparty_node_id: &PublicKey, transaction: Transaction,
+	) -> Result<(), APIError> {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+
+		match peer_state.channel_by_id.get_mut(channel_id) {
+			Some(ChannelPhase::Funded(chan)) => {
+				let logger = WithChannelContext::from(&self.logger, &chan.context);
+				let (tx_signatures, funding_tx) = chan.funding_transaction_signed(transaction, &&logger)?;
+				if let Some(msg) = tx_signatures {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxSignatures {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				if let Some(funding_tx) = funding_tx {
+					self.broadcast_interactive_funding(chan, &funding_tx);
+				}
+				Ok(())
+			},
+			Some(_) => Err(APIError::APIMisuseError {
+				err: format!("Channel with id {} has not yet negotiated its funding transaction", channel_id)
+			}),
+			None => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} not found for the passed counterparty node_id {}",
+					channel_id, counterparty_node_id)
+			}),
+		}
+	}
+
+	/// Broadcasts the now fully signed funding transaction of a dual-funded channel.
+	#[cfg(dual_funding)]
+	fn broadcast_interactive_funding(&self, channel: &mut Channel<SP>, funding_tx: &Transaction) {
+		let logger = WithChannelContext::from(&self.logger, &channel.context);
+		log_info!(logger, "Broadcasting funding transaction with txid {}", funding_tx.txid());
+		self.tx_broadcaster.broadcast_transactions(&[funding_tx]);
+		let mut pending_events = self.pending_events.lock().unwrap();
+		emit_channel_pending_event!(pending_events, channel);
+	}
+
 	/// Gets the number of peers which match the given filter and do not have any funded, outbound,
 	/// or 0-conf channels.
 	///
@@ -6113,9 +8079,20 @@ where
 						num_unfunded_channels += 1;
 					}
 				},
+				#[cfg(dual_funding)]
+				ChannelPhase::UnfundedInboundV2(chan) => {
+					if chan.context.minimum_depth().unwrap_or(1) != 0 {
+						num_unfunded_channels += 1;
+					}
+				},
 				ChannelPhase::UnfundedOutboundV1(_) => {
 					// Outbound channels don't contribute to the unfunded count in the DoS context.
 					continue;
+				},
+				#[cfg(dual_funding)]
+				ChannelPhase::UnfundedOutboundV2(_) => {
+					// Outbound channels don't contribute to the unfunded count in the DoS context.
+					continue;
 				}
 			}
 		}
@@ -6184,7 +8161,7 @@ where
 				channel_type: msg.channel_type.clone().unwrap(),
 			}, None));
 			peer_state.inbound_channel_request_by_id.insert(channel_id, InboundChannelRequest {
-				open_channel_msg: msg.clone(),
+				open_channel_msg: OpenChannelMessage::V1(msg.clone()),
 				ticks_remaining: UNACCEPTED_INBOUND_CHANNEL_AGE_LIMIT_TICKS,
 			});
 			return Ok(());
@@ -6261,77 +8238,393 @@ where
 		Ok(())
 	}
 
-	fn internal_funding_created(&self, counterparty_node_id: &PublicKey, msg: &msgs::FundingCreated) -> Result<(), MsgHandleErrInternal> {
-		let best_block = *self.best_block.read().unwrap();
+	#[cfg(dual_funding)]
+	fn internal_open_channel_v2(&self, counterparty_node_id: &PublicKey, msg: &msgs::OpenChannelV2) -> Result<(), MsgHandleErrInternal> {
+		// Note that the ChannelManager is NOT re-persisted on disk after this, so any changes are
+		// likely to be lost on restart!
+		if msg.chain_hash != self.chain_hash {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close("Unknown genesis block hash".to_owned(), msg.temporary_channel_id.clone()));
+		}
+
+		if !self.default_configuration.accept_inbound_channels {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close("No inbound channels accepted".to_owned(), msg.temporary_channel_id.clone()));
+		}
 
 		let per_peer_state = self.per_peer_state.read().unwrap();
 		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
 			.ok_or_else(|| {
 				debug_assert!(false);
-				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.temporary_channel_id)
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.temporary_channel_id.clone())
 			})?;
-
 		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 		let peer_state = &mut *peer_state_lock;
-		let (mut chan, funding_msg_opt, monitor) =
-			match peer_state.channel_by_id.remove(&msg.temporary_channel_id) {
-				Some(ChannelPhase::UnfundedInboundV1(inbound_chan)) => {
-					let logger = WithChannelContext::from(&self.logger, &inbound_chan.context);
-					match inbound_chan.funding_created(msg, best_block, &self.signer_provider, &&logger) {
-						Ok(res) => res,
-						Err((inbound_chan, err)) => {
-							// We've already removed this inbound channel from the map in `PeerState`
-							// above so at this point we just need to clean up any lingering entries
-							// concerning this channel as it is safe to do so.
-							debug_assert!(matches!(err, ChannelError::Close(_)));
-							// Really we should be returning the channel_id the peer expects based
-							// on their funding info here, but they're horribly confused anyway, so
-							// there's not a lot we can do to save them.
-							return Err(convert_chan_phase_err!(self, err, &mut ChannelPhase::UnfundedInboundV1(inbound_chan), &msg.temporary_channel_id).1);
-						},
-					}
-				},
-				Some(mut phase) => {
-					let err_msg = format!("Got an unexpected funding_created message from peer with counterparty_node_id {}", counterparty_node_id);
-					let err = ChannelError::Close(err_msg);
-					return Err(convert_chan_phase_err!(self, err, &mut phase, &msg.temporary_channel_id).1);
-				},
-				None => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.temporary_channel_id))
-			};
 
-		let funded_channel_id = chan.context.channel_id();
-
-		macro_rules! fail_chan { ($err: expr) => { {
-			// Note that at this point we've filled in the funding outpoint on our
-			// channel, but its actually in conflict with another channel. Thus, if
-			// we call `convert_chan_phase_err` immediately (thus calling
-			// `update_maps_on_chan_removal`), we'll remove the existing channel
-			// from `outpoint_to_peer`. Thus, we must first unset the funding outpoint
-			// on the channel.
-			let err = ChannelError::Close($err.to_owned());
-			chan.unset_funding_info(msg.temporary_channel_id);
-			return Err(convert_chan_phase_err!(self, err, chan, &funded_channel_id, UNFUNDED_CHANNEL).1);
-		} } }
+		let best_block_height = self.best_block.read().unwrap().height();
+		if Self::unfunded_channel_count(peer_state, best_block_height) >= MAX_UNFUNDED_CHANS_PER_PEER {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close(
+				format!("Refusing more than {} unfunded channels.", MAX_UNFUNDED_CHANS_PER_PEER),
+				msg.temporary_channel_id.clone()));
+		}
 
-		match peer_state.channel_by_id.entry(funded_channel_id) {
-			hash_map::Entry::Occupied(_) => {
-				fail_chan!("Already had channel with the new channel_id");
-			},
-			hash_map::Entry::Vacant(e) => {
-				let mut outpoint_to_peer_lock = self.outpoint_to_peer.lock().unwrap();
-				match outpoint_to_peer_lock.entry(monitor.get_funding_txo().0) {
-					hash_map::Entry::Occupied(_) => {
-						fail_chan!("The funding_created message had the same funding_txid as an existing channel - funding is not possible");
-					},
-					hash_map::Entry::Vacant(i_e) => {
-						let monitor_res = self.chain_monitor.watch_channel(monitor.get_funding_txo().0, monitor);
-						if let Ok(persist_state) = monitor_res {
-							i_e.insert(chan.context.get_counterparty_node_id());
-							mem::drop(outpoint_to_peer_lock);
+		let channel_id = msg.temporary_channel_id;
+		if peer_state.has_channel(&channel_id) {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close("temporary_channel_id collision for the same peer!".to_owned(), msg.temporary_channel_id.clone()));
+		}
 
-							// There's no problem signing a counterparty's funding transaction if our monitor
-							// hasn't persisted to disk yet - we can't lose money on a transaction that we haven't
-							// accepted payment from yet. We do, however, need to wait to send our channel_ready
+		// As we have to decide which inputs, if any, to contribute to the funding transaction, we
+		// always defer creating a dual-funded channel until the user has accepted it.
+		let channel_type = msg.channel_type.clone().unwrap_or_else(||
+			ChannelTypeFeatures::from_init(&peer_state.latest_features));
+		let mut pending_events = self.pending_events.lock().unwrap();
+		pending_events.push_back((events::Event::OpenDualFundedChannelRequest {
+			temporary_channel_id: msg.temporary_channel_id.clone(),
+			counterparty_node_id: counterparty_node_id.clone(),
+			counterparty_funding_satoshis: msg.funding_satoshis,
+			funding_feerate_sat_per_1000_weight: msg.funding_feerate_sat_per_1000_weight,
+			require_confirmed_inputs: msg.require_confirmed_inputs.is_some(),
+			channel_type,
+		}, None));
+		peer_state.inbound_channel_request_by_id.insert(channel_id, InboundChannelRequest {
+			open_channel_msg: OpenChannelMessage::V2(msg.clone()),
+			ticks_remaining: UNACCEPTED_INBOUND_CHANNEL_AGE_LIMIT_TICKS,
+		});
+		Ok(())
+	}
+
+	#[cfg(dual_funding)]
+	fn internal_accept_channel_v2(&self, counterparty_node_id: &PublicKey, msg: &msgs::AcceptChannelV2) -> Result<(), MsgHandleErrInternal> {
+		// Note that the ChannelManager is NOT re-persisted on disk after this, so any changes are
+		// likely to be lost on restart!
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.temporary_channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		let mut chan = match peer_state.channel_by_id.entry(msg.temporary_channel_id) {
+			hash_map::Entry::Occupied(phase) => {
+				if !matches!(phase.get(), ChannelPhase::UnfundedOutboundV2(_)) {
+					return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got an unexpected accept_channel2 message from peer with counterparty_node_id {}", counterparty_node_id), msg.temporary_channel_id));
+				}
+				if let ChannelPhase::UnfundedOutboundV2(chan) = phase.remove() { chan } else { unreachable!() }
+			},
+			hash_map::Entry::Vacant(_) => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.temporary_channel_id))
+		};
+
+		// Now that we know both revocation basepoints, the channel moves to its final channel_id.
+		let res = chan.accept_channel_v2(&msg, &self.default_configuration.channel_handshake_limits,
+			&peer_state.latest_features, &self.signer_provider)
+			.and_then(|_| chan.begin_interactive_funding_tx_construction(&self.entropy_source));
+		let tx_msg_opt = match res {
+			Ok(tx_msg_opt) => tx_msg_opt,
+			Err(e) => {
+				let channel_id = chan.context.channel_id();
+				return Err(convert_chan_phase_err!(self, e, &mut ChannelPhase::UnfundedOutboundV2(chan), &channel_id).1);
+			},
+		};
+		let channel_id = chan.context.channel_id();
+		if peer_state.has_channel(&channel_id) {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close("channel_id collision for the same peer!".to_owned(), msg.temporary_channel_id));
+		}
+		if let Some(tx_msg) = tx_msg_opt {
+			peer_state.pending_msg_events.push(tx_msg.into_msg_send_event(*counterparty_node_id));
+		}
+		peer_state.channel_by_id.insert(channel_id, ChannelPhase::UnfundedOutboundV2(chan));
+		Ok(())
+	}
+
+	/// Handles one of the interactive transaction construction messages for a dual-funded channel
+	/// which has yet to complete its funding negotiation, queueing our response.
+	#[cfg(dual_funding)]
+	fn internal_tx_msg<HandleTxMsgFn: FnOnce(&mut ChannelPhase<SP>) -> Result<InteractiveTxMessageSend, ChannelError>>(
+		&self, counterparty_node_id: &PublicKey, channel_id: ChannelId, tx_msg_handler: HandleTxMsgFn
+	) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(channel_id) {
+			hash_map::Entry::Occupied(mut chan_phase_entry) => {
+				let tx_msg = try_chan_phase_entry!(self, tx_msg_handler(chan_phase_entry.get_mut()), chan_phase_entry);
+				peer_state.pending_msg_events.push(tx_msg.into_msg_send_event(*counterparty_node_id));
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), channel_id))
+		}
+	}
+
+	#[cfg(dual_funding)]
+	fn internal_tx_complete(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxComplete) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan_phase_entry) => {
+				let our_node_id = self.get_our_node_id();
+				let res = match chan_phase_entry.get_mut() {
+					ChannelPhase::UnfundedOutboundV2(chan) => {
+						let logger = WithChannelContext::from(&self.logger, &chan.context);
+						chan.tx_complete(msg, &our_node_id, &&logger)
+					},
+					ChannelPhase::UnfundedInboundV2(chan) => {
+						let logger = WithChannelContext::from(&self.logger, &chan.context);
+						chan.tx_complete(msg, &our_node_id, &&logger)
+					},
+					_ => Err(ChannelError::Close(
+						"Got a tx_complete message for a channel not being dual-funded".into())),
+				};
+				let (tx_msg_opt, commitment_signed_opt) = try_chan_phase_entry!(self, res, chan_phase_entry);
+				if let Some(tx_msg) = tx_msg_opt {
+					peer_state.pending_msg_events.push(tx_msg.into_msg_send_event(*counterparty_node_id));
+				}
+				if let Some(commitment_signed) = commitment_signed_opt {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::UpdateHTLCs {
+						node_id: *counterparty_node_id,
+						updates: msgs::CommitmentUpdate {
+							update_add_htlcs: vec![],
+							update_fulfill_htlcs: vec![],
+							update_fail_htlcs: vec![],
+							update_fail_malformed_htlcs: vec![],
+							update_fee: None,
+							commitment_signed,
+						},
+					});
+				}
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	#[cfg(dual_funding)]
+	fn internal_tx_abort(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAbort) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan_phase_entry) => {
+				let res = match chan_phase_entry.get_mut() {
+					ChannelPhase::UnfundedOutboundV2(chan) => chan.tx_abort(msg, &self.entropy_source),
+					ChannelPhase::UnfundedInboundV2(chan) => chan.tx_abort(msg, &self.entropy_source),
+					_ => Err(ChannelError::Close(
+						"Got a tx_abort message for a channel not being dual-funded".into())),
+				};
+				let (tx_abort_opt, tx_msg_opt) = try_chan_phase_entry!(self, res, chan_phase_entry);
+				if let Some(tx_abort) = tx_abort_opt {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxAbort {
+						node_id: *counterparty_node_id,
+						msg: tx_abort,
+					});
+				}
+				if let Some(tx_msg) = tx_msg_opt {
+					peer_state.pending_msg_events.push(tx_msg.into_msg_send_event(*counterparty_node_id));
+				}
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	/// Handles the counterparty's initial `commitment_signed` for a dual-funded channel, creating
+	/// the channel's [`ChannelMonitor`] and moving it to [`ChannelPhase::Funded`].
+	#[cfg(dual_funding)]
+	fn internal_initial_commitment_signed_v2(
+		&self, counterparty_node_id: &PublicKey, msg: &msgs::CommitmentSigned,
+	) -> Result<(), MsgHandleErrInternal> {
+		let best_block = *self.best_block.read().unwrap();
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		let res = match peer_state.channel_by_id.remove(&msg.channel_id) {
+			Some(ChannelPhase::UnfundedOutboundV2(chan)) => {
+				let logger = WithChannelContext::from(&self.logger, &chan.context);
+				chan.initial_commitment_signed(msg, best_block, &self.signer_provider, &&logger)
+					.map_err(|(chan, e)| (ChannelPhase::UnfundedOutboundV2(chan), e))
+			},
+			Some(ChannelPhase::UnfundedInboundV2(chan)) => {
+				let logger = WithChannelContext::from(&self.logger, &chan.context);
+				chan.initial_commitment_signed(msg, best_block, &self.signer_provider, &&logger)
+					.map_err(|(chan, e)| (ChannelPhase::UnfundedInboundV2(chan), e))
+			},
+			Some(mut phase) => {
+				let err = ChannelError::Close("Got an unexpected initial commitment_signed message".to_owned());
+				return Err(convert_chan_phase_err!(self, err, &mut phase, &msg.channel_id).1);
+			},
+			None => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		};
+		let (mut chan, monitor) = match res {
+			Ok(res) => res,
+			Err((mut phase, e)) => {
+				// We've already removed this channel from the map in `PeerState` above so at this
+				// point we just need to clean up any lingering entries concerning this channel.
+				return Err(convert_chan_phase_err!(self, e, &mut phase, &msg.channel_id).1);
+			},
+		};
+
+		let funding_txo = monitor.get_funding_txo().0;
+		let mut outpoint_to_peer_lock = self.outpoint_to_peer.lock().unwrap();
+		if outpoint_to_peer_lock.contains_key(&funding_txo) {
+			mem::drop(outpoint_to_peer_lock);
+			let err = ChannelError::Close("The negotiated funding transaction had the same txid as an existing channel".to_owned());
+			chan.unset_funding_info(msg.channel_id);
+			return Err(convert_chan_phase_err!(self, err, chan, &msg.channel_id, UNFUNDED_CHANNEL).1);
+		}
+		match self.chain_monitor.watch_channel(funding_txo, monitor) {
+			Ok(persist_state) => {
+				outpoint_to_peer_lock.insert(funding_txo, *counterparty_node_id);
+				mem::drop(outpoint_to_peer_lock);
+				if let Some(unsigned_transaction) = chan.unsigned_funding_tx_awaiting_holder_signatures() {
+					self.pending_events.lock().unwrap().push_back((events::Event::FundingTransactionReadyForSigning {
+						channel_id: chan.context.channel_id(),
+						counterparty_node_id: *counterparty_node_id,
+						user_channel_id: chan.context.get_user_id(),
+						unsigned_transaction,
+					}, None));
+				}
+				if let ChannelPhase::Funded(chan) = peer_state.channel_by_id.entry(msg.channel_id).or_insert(ChannelPhase::Funded(chan)) {
+					handle_new_monitor_update!(self, persist_state, peer_state_lock, peer_state, per_peer_state, chan, INITIAL_MONITOR);
+				} else { unreachable!(); }
+				Ok(())
+			},
+			Err(()) => {
+				mem::drop(outpoint_to_peer_lock);
+				let logger = WithChannelContext::from(&self.logger, &chan.context);
+				log_error!(logger, "Persisting initial ChannelMonitor failed, implying the funding outpoint was duplicated");
+				chan.unset_funding_info(msg.channel_id);
+				let err = ChannelError::Close("Channel funding outpoint was a duplicate".to_owned());
+				return Err(convert_chan_phase_err!(self, err, chan, &msg.channel_id, UNFUNDED_CHANNEL).1);
+			},
+		}
+	}
+
+	#[cfg(dual_funding)]
+	fn internal_tx_signatures(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxSignatures) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan_phase_entry) => {
+				if let ChannelPhase::Funded(chan) = chan_phase_entry.get_mut() {
+					let logger = WithChannelContext::from(&self.logger, &chan.context);
+					let (tx_signatures_opt, funding_tx_opt) = try_chan_phase_entry!(self, chan.tx_signatures(msg, &&logger), chan_phase_entry);
+					if let Some(tx_signatures) = tx_signatures_opt {
+						peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxSignatures {
+							node_id: *counterparty_node_id,
+							msg: tx_signatures,
+						});
+					}
+					if let Some(ref funding_tx) = funding_tx_opt {
+						if let ChannelPhase::Funded(chan) = chan_phase_entry.get_mut() {
+							self.broadcast_interactive_funding(chan, funding_tx);
+						}
+					}
+					Ok(())
+				} else {
+					try_chan_phase_entry!(self, Err(ChannelError::Close(
+						"Got a tx_signatures message for an unfunded channel!".into())), chan_phase_entry)
+				}
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	fn internal_funding_created(&self, counterparty_node_id: &PublicKey, msg: &msgs::FundingCreated) -> Result<(), MsgHandleErrInternal> {
+		let best_block = *self.best_block.read().unwrap();
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.temporary_channel_id)
+			})?;
+
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		let (mut chan, funding_msg_opt, monitor) =
+			match peer_state.channel_by_id.remove(&msg.temporary_channel_id) {
+				Some(ChannelPhase::UnfundedInboundV1(inbound_chan)) => {
+					let logger = WithChannelContext::from(&self.logger, &inbound_chan.context);
+					match inbound_chan.funding_created(msg, best_block, &self.signer_provider, &&logger) {
+						Ok(res) => res,
+						Err((inbound_chan, err)) => {
+							// We've already removed this inbound channel from the map in `PeerState`
+							// above so at this point we just need to clean up any lingering entries
+							// concerning this channel as it is safe to do so.
+							debug_assert!(matches!(err, ChannelError::Close(_)));
+							// Really we should be returning the channel_id the peer expects based
+							// on their funding info here, but they're horribly confused anyway, so
+							// there's not a lot we can do to save them.
+							return Err(convert_chan_phase_err!(self, err, &mut ChannelPhase::UnfundedInboundV1(inbound_chan), &msg.temporary_channel_id).1);
+						},
+					}
+				},
+				Some(mut phase) => {
+					let err_msg = format!("Got an unexpected funding_created message from peer with counterparty_node_id {}", counterparty_node_id);
+					let err = ChannelError::Close(err_msg);
+					return Err(convert_chan_phase_err!(self, err, &mut phase, &msg.temporary_channel_id).1);
+				},
+				None => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.temporary_channel_id))
+			};
+
+		let funded_channel_id = chan.context.channel_id();
+
+		macro_rules! fail_chan { ($err: expr) => { {
+			// Note that at this point we've filled in the funding outpoint on our
+			// channel, but its actually in conflict with another channel. Thus, if
+			// we call `convert_chan_phase_err` immediately (thus calling
+			// `update_maps_on_chan_removal`), we'll remove the existing channel
+			// from `outpoint_to_peer`. Thus, we must first unset the funding outpoint
+			// on the channel.
+			let err = ChannelError::Close($err.to_owned());
+			chan.unset_funding_info(msg.temporary_channel_id);
+			return Err(convert_chan_phase_err!(self, err, chan, &funded_channel_id, UNFUNDED_CHANNEL).1);
+		} } }
+
+		match peer_state.channel_by_id.entry(funded_channel_id) {
+			hash_map::Entry::Occupied(_) => {
+				fail_chan!("Already had channel with the new channel_id");
+			},
+			hash_map::Entry::Vacant(e) => {
+				let mut outpoint_to_peer_lock = self.outpoint_to_peer.lock().unwrap();
+				match outpoint_to_peer_lock.entry(monitor.get_funding_txo().0) {
+					hash_map::Entry::Occupied(_) => {
+						fail_chan!("The funding_created message had the same funding_txid as an existing channel - funding is not possible");
+					},
+					hash_map::Entry::Vacant(i_e) => {
+						let monitor_res = self.chain_monitor.watch_channel(monitor.get_funding_txo().0, monitor);
+						if let Ok(persist_state) = monitor_res {
+							i_e.insert(chan.context.get_counterparty_node_id());
+							mem::drop(outpoint_to_peer_lock);
+
+							// There's no problem signing a counterparty's funding transaction if our monitor
+							// hasn't persisted to disk yet - we can't lose money on a transaction that we haven't
+							// accepted payment from yet. We do, however, need to wait to send our channel_ready
 							// until we have persisted our monitor.
 							if let Some(msg) = funding_msg_opt {
 								peer_state.pending_msg_events.push(events::MessageSendEvent::SendFundingSigned {
@@ -6519,6 +8812,15 @@ where
 						let mut chan = remove_channel_phase!(self, chan_phase_entry);
 						finish_shutdown = Some(chan.context_mut().force_shutdown(false));
 					},
+					#[cfg(dual_funding)]
+					ChannelPhase::UnfundedInboundV2(_) | ChannelPhase::UnfundedOutboundV2(_) => {
+						let context = phase.context_mut();
+						let logger = WithChannelContext::from(&self.logger, context);
+						log_error!(logger, "Immediately closing unfunded channel {} as peer asked to cooperatively shut it down (which is unnecessary)", &msg.channel_id);
+						self.issue_channel_close_events(&context, ClosureReason::CounterpartyCoopClosedUnfundedChannel);
+						let mut chan = remove_channel_phase!(self, chan_phase_entry);
+						finish_shutdown = Some(chan.context_mut().force_shutdown(false));
+					},
 				}
 			} else {
 				return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
@@ -6716,7 +9018,7 @@ where
 				hash_map::Entry::Vacant(_) => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
 			}
 		};
-		self.claim_funds_internal(htlc_source, msg.payment_preimage.clone(), Some(forwarded_htlc_value), false, false, Some(*counterparty_node_id), funding_txo);
+		self.claim_funds_internal(htlc_source, msg.payment_preimage.clone(), Some(forwarded_htlc_value), false, false, Some(*counterparty_node_id), funding_txo, msg.channel_id);
 		Ok(())
 	}
 
@@ -6782,6 +9084,14 @@ where
 				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
 			})?;
 		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		#[cfg(dual_funding)]
+		if matches!(peer_state_lock.channel_by_id.get(&msg.channel_id),
+			Some(ChannelPhase::UnfundedOutboundV2(_)) | Some(ChannelPhase::UnfundedInboundV2(_)))
+		{
+			mem::drop(peer_state_lock);
+			mem::drop(per_peer_state);
+			return self.internal_initial_commitment_signed_v2(counterparty_node_id, msg);
+		}
 		let peer_state = &mut *peer_state_lock;
 		match peer_state.channel_by_id.entry(msg.channel_id) {
 			hash_map::Entry::Occupied(mut chan_phase_entry) => {
@@ -6804,17 +9114,32 @@ where
 	}
 
 	#[inline]
-	fn forward_htlcs(&self, per_source_pending_forwards: &mut [(u64, OutPoint, u128, Vec<(PendingHTLCInfo, u64)>)]) {
-		for &mut (prev_short_channel_id, prev_funding_outpoint, prev_user_channel_id, ref mut pending_forwards) in per_source_pending_forwards {
+	fn forward_htlcs(&self, per_source_pending_forwards: &mut [(u64, OutPoint, ChannelId, u128, Vec<(PendingHTLCInfo, u64)>)]) {
+		for &mut (prev_short_channel_id, prev_funding_outpoint, prev_channel_id, prev_user_channel_id, ref mut pending_forwards) in per_source_pending_forwards {
 			let mut push_forward_event = false;
 			let mut new_intercept_events = VecDeque::new();
 			let mut failed_intercept_forwards = Vec::new();
 			if !pending_forwards.is_empty() {
 				for (forward_info, prev_htlc_id) in pending_forwards.drain(..) {
+					if let Some(payment_release_secret) = forward_info.hold_htlc {
+						if self.default_configuration.hold_htlcs_for_async_payments {
+							let mut held_htlcs = self.pending_held_htlcs.lock().unwrap();
+							if !held_htlcs.early_releases.contains_key(&payment_release_secret) {
+								let logger = WithContext::from(&self.logger, None, Some(prev_channel_id));
+								log_trace!(logger, "Holding HTLC with payment hash {} until it is released", &forward_info.payment_hash);
+								let intercept_id = InterceptId(Sha256::hash(&forward_info.incoming_shared_secret).to_byte_array());
+								held_htlcs.htlcs.insert(intercept_id, PendingAddHTLCInfo {
+									prev_short_channel_id, prev_funding_outpoint, prev_channel_id, prev_htlc_id, prev_user_channel_id, forward_info });
+								continue;
+							}
+						}
+					}
 					let scid = match forward_info.routing {
 						PendingHTLCRouting::Forward { short_channel_id, .. } => short_channel_id,
 						PendingHTLCRouting::Receive { .. } => 0,
 						PendingHTLCRouting::ReceiveKeysend { .. } => 0,
+						PendingHTLCRouting::ReceiveAmp { .. } => 0,
+						PendingHTLCRouting::TrampolineForward { .. } => 0,
 					};
 					// Pull this now to avoid introducing a lock order with `forward_htlcs`.
 					let is_our_scid = self.short_to_chan_info.read().unwrap().contains_key(&scid);
@@ -6824,7 +9149,7 @@ where
 					match forward_htlcs.entry(scid) {
 						hash_map::Entry::Occupied(mut entry) => {
 							entry.get_mut().push(HTLCForwardInfo::AddHTLC(PendingAddHTLCInfo {
-								prev_short_channel_id, prev_funding_outpoint, prev_htlc_id, prev_user_channel_id, forward_info }));
+								prev_short_channel_id, prev_funding_outpoint, prev_channel_id, prev_htlc_id, prev_user_channel_id, forward_info }));
 						},
 						hash_map::Entry::Vacant(entry) => {
 							if !is_our_scid && forward_info.incoming_amt_msat.is_some() &&
@@ -6842,19 +9167,21 @@ where
 											intercept_id
 										}, None));
 										entry.insert(PendingAddHTLCInfo {
-											prev_short_channel_id, prev_funding_outpoint, prev_htlc_id, prev_user_channel_id, forward_info });
+											prev_short_channel_id, prev_funding_outpoint, prev_channel_id, prev_htlc_id, prev_user_channel_id, forward_info });
 									},
 									hash_map::Entry::Occupied(_) => {
-										let logger = WithContext::from(&self.logger, None, Some(prev_funding_outpoint.to_channel_id()));
+										let logger = WithContext::from(&self.logger, None, Some(prev_channel_id));
 										log_info!(logger, "Failed to forward incoming HTLC: detected duplicate intercepted payment over short channel id {}", scid);
 										let htlc_source = HTLCSource::PreviousHopData(HTLCPreviousHopData {
 											short_channel_id: prev_short_channel_id,
 											user_channel_id: Some(prev_user_channel_id),
 											outpoint: prev_funding_outpoint,
+											channel_id: prev_channel_id,
 											htlc_id: prev_htlc_id,
 											incoming_packet_shared_secret: forward_info.incoming_shared_secret,
 											phantom_shared_secret: None,
 											blinded_failure: forward_info.routing.blinded_failure(),
+											trampoline_forward: forward_info.routing.trampoline_forward(),
 										});
 
 										failed_intercept_forwards.push((htlc_source, forward_info.payment_hash,
@@ -6870,7 +9197,7 @@ where
 									push_forward_event = true;
 								}
 								entry.insert(vec!(HTLCForwardInfo::AddHTLC(PendingAddHTLCInfo {
-									prev_short_channel_id, prev_funding_outpoint, prev_htlc_id, prev_user_channel_id, forward_info })));
+									prev_short_channel_id, prev_funding_outpoint, prev_channel_id, prev_htlc_id, prev_user_channel_id, forward_info })));
 							}
 						}
 					}
@@ -6914,10 +9241,10 @@ where
 	/// the [`ChannelMonitorUpdate`] in question.
 	fn raa_monitor_updates_held(&self,
 		actions_blocking_raa_monitor_updates: &BTreeMap<ChannelId, Vec<RAAMonitorUpdateBlockingAction>>,
-		channel_funding_outpoint: OutPoint, counterparty_node_id: PublicKey
+		channel_funding_outpoint: OutPoint, channel_id: ChannelId, counterparty_node_id: PublicKey
 	) -> bool {
 		actions_blocking_raa_monitor_updates
-			.get(&channel_funding_outpoint.to_channel_id()).map(|v| !v.is_empty()).unwrap_or(false)
+			.get(&channel_id).map(|v| !v.is_empty()).unwrap_or(false)
 		|| self.pending_events.lock().unwrap().iter().any(|(_, action)| {
 			action == &Some(EventCompletionAction::ReleaseRAAChannelMonitorUpdate {
 				channel_funding_outpoint,
@@ -6937,7 +9264,7 @@ where
 
 			if let Some(chan) = peer_state.channel_by_id.get(&channel_id) {
 				return self.raa_monitor_updates_held(&peer_state.actions_blocking_raa_monitor_updates,
-					chan.context().get_funding_txo().unwrap(), counterparty_node_id);
+					chan.context().get_funding_txo().unwrap(), channel_id, counterparty_node_id);
 			}
 		}
 		false
@@ -6959,7 +9286,7 @@ where
 						let funding_txo_opt = chan.context.get_funding_txo();
 						let mon_update_blocked = if let Some(funding_txo) = funding_txo_opt {
 							self.raa_monitor_updates_held(
-								&peer_state.actions_blocking_raa_monitor_updates, funding_txo,
+								&peer_state.actions_blocking_raa_monitor_updates, funding_txo, msg.channel_id,
 								*counterparty_node_id)
 						} else { false };
 						let (htlcs_to_fail, monitor_update_opt) = try_chan_phase_entry!(self,
@@ -6983,7 +9310,7 @@ where
 		Ok(())
 	}
 
-	fn internal_update_fee(&self, counterparty_node_id: &PublicKey, msg: &msgs::UpdateFee) -> Result<(), MsgHandleErrInternal> {
+	fn internal_stfu(&self, counterparty_node_id: &PublicKey, msg: &msgs::Stfu) -> Result<(), MsgHandleErrInternal> {
 		let per_peer_state = self.per_peer_state.read().unwrap();
 		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
 			.ok_or_else(|| {
@@ -6996,10 +9323,15 @@ where
 			hash_map::Entry::Occupied(mut chan_phase_entry) => {
 				if let ChannelPhase::Funded(chan) = chan_phase_entry.get_mut() {
 					let logger = WithChannelContext::from(&self.logger, &chan.context);
-					try_chan_phase_entry!(self, chan.update_fee(&self.fee_estimator, &msg, &&logger), chan_phase_entry);
+					if let Some(stfu) = try_chan_phase_entry!(self, chan.stfu(msg, &&logger), chan_phase_entry) {
+						peer_state.pending_msg_events.push(events::MessageSendEvent::SendStfu {
+							node_id: *counterparty_node_id,
+							msg: stfu,
+						});
+					}
 				} else {
 					return try_chan_phase_entry!(self, Err(ChannelError::Close(
-						"Got an update_fee message for an unfunded channel!".into())), chan_phase_entry);
+						"Got an stfu message for an unfunded channel!".into())), chan_phase_entry);
 				}
 			},
 			hash_map::Entry::Vacant(_) => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
@@ -7007,7 +9339,7 @@ where
 		Ok(())
 	}
 
-	fn internal_announcement_signatures(&self, counterparty_node_id: &PublicKey, msg: &msgs::AnnouncementSignatures) -> Result<(), MsgHandleErrInternal> {
+	fn internal_dyn_propose(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynPropose) -> Result<(), MsgHandleErrInternal> {
 		let per_peer_state = self.per_peer_state.read().unwrap();
 		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
 			.ok_or_else(|| {
@@ -7019,19 +9351,202 @@ where
 		match peer_state.channel_by_id.entry(msg.channel_id) {
 			hash_map::Entry::Occupied(mut chan_phase_entry) => {
 				if let ChannelPhase::Funded(chan) = chan_phase_entry.get_mut() {
-					if !chan.context.is_usable() {
-						return Err(MsgHandleErrInternal::from_no_close(LightningError{err: "Got an announcement_signatures before we were ready for it".to_owned(), action: msgs::ErrorAction::IgnoreError}));
+					let logger = WithChannelContext::from(&self.logger, &chan.context);
+					let our_supported_features = self.channel_type_features();
+					match try_chan_phase_entry!(self, chan.dyn_propose(msg, &our_supported_features, &&logger), chan_phase_entry) {
+						DynProposeResponse::Ack(msg) => {
+							peer_state.pending_msg_events.push(events::MessageSendEvent::SendDynAck {
+								node_id: *counterparty_node_id,
+								msg,
+							});
+						},
+						DynProposeResponse::Reject(msg) => {
+							peer_state.pending_msg_events.push(events::MessageSendEvent::SendDynReject {
+								node_id: *counterparty_node_id,
+								msg,
+							});
+						},
 					}
-
-					peer_state.pending_msg_events.push(events::MessageSendEvent::BroadcastChannelAnnouncement {
-						msg: try_chan_phase_entry!(self, chan.announcement_signatures(
-							&self.node_signer, self.chain_hash, self.best_block.read().unwrap().height(),
-							msg, &self.default_configuration
-						), chan_phase_entry),
-						// Note that announcement_signatures fails if the channel cannot be announced,
-						// so get_channel_update_for_broadcast will never fail by the time we get here.
-						update_msg: Some(self.get_channel_update_for_broadcast(chan).unwrap()),
-					});
+				} else {
+					return try_chan_phase_entry!(self, Err(ChannelError::Close(
+						"Got a dyn_propose message for an unfunded channel!".into())), chan_phase_entry);
+				}
+			},
+			hash_map::Entry::Vacant(_) => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+		Ok(())
+	}
+
+	fn internal_dyn_ack(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynAck) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan_phase_entry) => {
+				if let ChannelPhase::Funded(chan) = chan_phase_entry.get_mut() {
+					let logger = WithChannelContext::from(&self.logger, &chan.context);
+					let funding_txo = chan.context.get_funding_txo();
+					let monitor_update_opt = try_chan_phase_entry!(self, chan.dyn_ack(msg, &&logger), chan_phase_entry);
+					if let Some(monitor_update) = monitor_update_opt {
+						handle_new_monitor_update!(self, funding_txo.unwrap(), monitor_update, peer_state_lock,
+							peer_state, per_peer_state, chan);
+					}
+					Ok(())
+				} else {
+					return try_chan_phase_entry!(self, Err(ChannelError::Close(
+						"Got a dyn_ack message for an unfunded channel!".into())), chan_phase_entry);
+				}
+			},
+			hash_map::Entry::Vacant(_) => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	fn internal_dyn_reject(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynReject) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan_phase_entry) => {
+				if let ChannelPhase::Funded(chan) = chan_phase_entry.get_mut() {
+					let logger = WithChannelContext::from(&self.logger, &chan.context);
+					try_chan_phase_entry!(self, chan.dyn_reject(msg, &&logger), chan_phase_entry);
+				} else {
+					return try_chan_phase_entry!(self, Err(ChannelError::Close(
+						"Got a dyn_reject message for an unfunded channel!".into())), chan_phase_entry);
+				}
+			},
+			hash_map::Entry::Vacant(_) => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+		Ok(())
+	}
+
+	fn internal_peer_storage(&self, counterparty_node_id: &PublicKey, msg: &msgs::PeerStorage) -> NotifyOption {
+		let logger = WithContext::from(&self.logger, Some(*counterparty_node_id), None);
+		if !self.default_configuration.enable_peer_storage {
+			log_debug!(logger, "Ignoring peer_storage from {} as we don't provide storage", log_pubkey!(counterparty_node_id));
+			return NotifyOption::SkipPersistNoEvents;
+		}
+		if msg.data.len() > MAX_PEER_STORAGE_SIZE {
+			log_debug!(logger, "Ignoring peer_storage from {} as its {} bytes exceed our limit of {} bytes",
+				log_pubkey!(counterparty_node_id), msg.data.len(), MAX_PEER_STORAGE_SIZE);
+			return NotifyOption::SkipPersistNoEvents;
+		}
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = match per_peer_state.get(counterparty_node_id) {
+			Some(peer_state_mutex) => peer_state_mutex,
+			None => {
+				debug_assert!(false);
+				return NotifyOption::SkipPersistNoEvents;
+			},
+		};
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		// Only store data for peers we have a funded channel with, which also ensures we'll
+		// persist it, and limits the total amount of data peers can make us store.
+		if !peer_state.channel_by_id.values().any(|phase| matches!(phase, ChannelPhase::Funded(_))) {
+			log_debug!(logger, "Ignoring peer_storage from {} as we have no funded channels with it", log_pubkey!(counterparty_node_id));
+			return NotifyOption::SkipPersistNoEvents;
+		}
+		if peer_state.peer_storage == msg.data {
+			return NotifyOption::SkipPersistNoEvents;
+		}
+		log_trace!(logger, "Storing {} bytes of peer_storage for {}", msg.data.len(), log_pubkey!(counterparty_node_id));
+		peer_state.peer_storage = msg.data.clone();
+		NotifyOption::DoPersist
+	}
+
+	fn internal_your_peer_storage(&self, counterparty_node_id: &PublicKey, msg: &msgs::YourPeerStorage) -> NotifyOption {
+		let logger = WithContext::from(&self.logger, Some(*counterparty_node_id), None);
+		let peer_storage = match DecryptedOurPeerStorage::decrypt(&self.node_signer.get_peer_storage_key(), &msg.data) {
+			Ok(peer_storage) => peer_storage,
+			Err(_) => {
+				log_debug!(logger, "Failed to decrypt the your_peer_storage returned by {}", log_pubkey!(counterparty_node_id));
+				return NotifyOption::SkipPersistNoEvents;
+			},
+		};
+
+		let unknown_channel_count = {
+			let per_peer_state = self.per_peer_state.read().unwrap();
+			peer_storage.channels.iter().filter(|chan| {
+				per_peer_state.get(&chan.counterparty_node_id)
+					.map(|peer_state_mutex| !peer_state_mutex.lock().unwrap().channel_by_id.contains_key(&chan.channel_id))
+					.unwrap_or(true)
+			}).count()
+		};
+		if unknown_channel_count == 0 {
+			log_trace!(logger, "Backup returned by {} only contains channels we know about", log_pubkey!(counterparty_node_id));
+			return NotifyOption::SkipPersistNoEvents;
+		}
+
+		log_info!(logger, "Backup returned by {} contains {} channels we don't know about",
+			log_pubkey!(counterparty_node_id), unknown_channel_count);
+		self.pending_events.lock().unwrap().push_back((events::Event::PeerStorageRetrieved {
+			counterparty_node_id: *counterparty_node_id,
+			peer_storage,
+		}, None));
+		NotifyOption::SkipPersistHandleEvents
+	}
+
+	fn internal_update_fee(&self, counterparty_node_id: &PublicKey, msg: &msgs::UpdateFee) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan_phase_entry) => {
+				if let ChannelPhase::Funded(chan) = chan_phase_entry.get_mut() {
+					let logger = WithChannelContext::from(&self.logger, &chan.context);
+					try_chan_phase_entry!(self, chan.update_fee(&self.fee_estimator, &msg, &&logger), chan_phase_entry);
+				} else {
+					return try_chan_phase_entry!(self, Err(ChannelError::Close(
+						"Got an update_fee message for an unfunded channel!".into())), chan_phase_entry);
+				}
+			},
+			hash_map::Entry::Vacant(_) => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+		Ok(())
+	}
+
+	fn internal_announcement_signatures(&self, counterparty_node_id: &PublicKey, msg: &msgs::AnnouncementSignatures) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan_phase_entry) => {
+				if let ChannelPhase::Funded(chan) = chan_phase_entry.get_mut() {
+					if !chan.context.is_usable() {
+						return Err(MsgHandleErrInternal::from_no_close(LightningError{err: "Got an announcement_signatures before we were ready for it".to_owned(), action: msgs::ErrorAction::IgnoreError}));
+					}
+
+					peer_state.pending_msg_events.push(events::MessageSendEvent::BroadcastChannelAnnouncement {
+						msg: try_chan_phase_entry!(self, chan.announcement_signatures(
+							&self.node_signer, self.chain_hash, self.best_block.read().unwrap().height(),
+							msg, &self.default_configuration
+						), chan_phase_entry),
+						// Note that announcement_signatures fails if the channel cannot be announced,
+						// so get_channel_update_for_broadcast will never fail by the time we get here.
+						update_msg: Some(self.get_channel_update_for_broadcast(chan).unwrap()),
+					});
 				} else {
 					return try_chan_phase_entry!(self, Err(ChannelError::Close(
 						"Got an announcement_signatures message for an unfunded channel!".into())), chan_phase_entry);
@@ -7138,9 +9653,22 @@ where
 							}
 						}
 						let need_lnd_workaround = chan.context.workaround_lnd_bug_4006.take();
+						#[cfg(dual_funding)]
+						let funding_broadcastable = {
+							let (tx_signatures_opt, funding_tx_opt) = chan.resume_tx_signatures_exchange(msg, &&logger);
+							if let Some(tx_signatures) = tx_signatures_opt {
+								peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxSignatures {
+									node_id: *counterparty_node_id,
+									msg: tx_signatures,
+								});
+							}
+							funding_tx_opt
+						};
+						#[cfg(not(dual_funding))]
+						let funding_broadcastable = None;
 						htlc_forwards = self.handle_channel_resumption(
 							&mut peer_state.pending_msg_events, chan, responses.raa, responses.commitment_update, responses.order,
-							Vec::new(), None, responses.channel_ready, responses.announcement_sigs);
+							Vec::new(), funding_broadcastable, responses.channel_ready, responses.announcement_sigs);
 						if let Some(upd) = channel_update {
 							peer_state.pending_msg_events.push(upd);
 						}
@@ -7176,6 +9704,7 @@ where
 							your_last_per_commitment_secret: [1u8; 32],
 							my_current_per_commitment_point: PublicKey::from_slice(&[2u8; 33]).unwrap(),
 							next_funding_txid: None,
+							channel_type: None,
 						},
 					});
 					return Err(MsgHandleErrInternal::send_err_msg_no_close(
@@ -7212,7 +9741,7 @@ where
 						let logger = WithContext::from(&self.logger, counterparty_node_id, Some(funding_outpoint.to_channel_id()));
 						if let Some(preimage) = htlc_update.payment_preimage {
 							log_trace!(logger, "Claiming HTLC with preimage {} from our monitor", preimage);
-							self.claim_funds_internal(htlc_update.source, preimage, htlc_update.htlc_value_satoshis.map(|v| v * 1000), true, false, counterparty_node_id, funding_outpoint);
+							self.claim_funds_internal(htlc_update.source, preimage, htlc_update.htlc_value_satoshis.map(|v| v * 1000), true, false, counterparty_node_id, funding_outpoint, funding_outpoint.to_channel_id());
 						} else {
 							log_trace!(logger, "Failing HTLC with hash {} from our monitor", &htlc_update.payment_hash);
 							let receiver = HTLCDestination::NextHopChannel { node_id: counterparty_node_id, channel_id: funding_outpoint.to_channel_id() };
@@ -7235,8 +9764,9 @@ where
 							if let Some(peer_state_mutex) = per_peer_state.get(&counterparty_node_id) {
 								let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 								let peer_state = &mut *peer_state_lock;
+								let channel_id = peer_state.channel_id_for_funding_outpoint(&funding_outpoint);
 								let pending_msg_events = &mut peer_state.pending_msg_events;
-								if let hash_map::Entry::Occupied(chan_phase_entry) = peer_state.channel_by_id.entry(funding_outpoint.to_channel_id()) {
+								if let hash_map::Entry::Occupied(chan_phase_entry) = peer_state.channel_by_id.entry(channel_id) {
 									if let ChannelPhase::Funded(mut chan) = remove_channel_phase!(self, chan_phase_entry) {
 										failed_channels.push(chan.context.force_shutdown(false));
 										if let Ok(update) = self.get_channel_update_for_broadcast(&chan) {
@@ -7369,6 +9899,8 @@ where
 					}
 				}
 				ChannelPhase::UnfundedInboundV1(_) => {},
+				#[cfg(dual_funding)]
+				ChannelPhase::UnfundedOutboundV2(_) | ChannelPhase::UnfundedInboundV2(_) => {},
 			}
 		};
 
@@ -7392,6 +9924,137 @@ where
 		}
 	}
 
+	/// Check whether any channels attempting to become quiescent have finished committing all
+	/// pending updates and can now send their `stfu`.
+	fn maybe_send_stfu(&self) {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		for (counterparty_node_id, peer_state_mutex) in per_peer_state.iter() {
+			let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+			let peer_state = &mut *peer_state_lock;
+			let pending_msg_events = &mut peer_state.pending_msg_events;
+			for (_, phase) in peer_state.channel_by_id.iter_mut() {
+				if let ChannelPhase::Funded(chan) = phase {
+					let logger = WithChannelContext::from(&self.logger, &chan.context);
+					if let Some(msg) = chan.try_send_stfu(&&logger) {
+						pending_msg_events.push(events::MessageSendEvent::SendStfu {
+							node_id: *counterparty_node_id, msg,
+						});
+					}
+				}
+			}
+		}
+	}
+
+	/// Check whether any channel type upgrades can make progress, either because the channel
+	/// became quiescent and our `dyn_propose` can be sent, or because both sides committed to the
+	/// new channel type and quiescence can be terminated.
+	///
+	/// Returns whether any upgrades were completed.
+	fn maybe_progress_channel_type_upgrades(&self) -> bool {
+		let mut has_update = false;
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		for (counterparty_node_id, peer_state_mutex) in per_peer_state.iter() {
+			let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+			let peer_state = &mut *peer_state_lock;
+			let pending_msg_events = &mut peer_state.pending_msg_events;
+			for (chan_id, phase) in peer_state.channel_by_id.iter_mut() {
+				if let ChannelPhase::Funded(chan) = phase {
+					let logger = WithChannelContext::from(&self.logger, &chan.context);
+					match chan.maybe_propose_channel_type_upgrade(&&logger) {
+						Ok(Some(msg)) => {
+							pending_msg_events.push(events::MessageSendEvent::SendDynPropose {
+								node_id: *counterparty_node_id, msg,
+							});
+						},
+						Ok(None) => {},
+						Err(e) => {
+							// Disconnecting is the only way to terminate quiescence on both sides.
+							pending_msg_events.push(events::MessageSendEvent::HandleError {
+								node_id: *counterparty_node_id,
+								action: msgs::ErrorAction::DisconnectPeerWithWarning {
+									msg: msgs::WarningMessage { channel_id: *chan_id, data: e.to_string() },
+								},
+							});
+						},
+					}
+					has_update |= chan.maybe_complete_channel_type_upgrade(&&logger);
+				}
+			}
+		}
+		has_update
+	}
+
+	/// Sends the latest backup of our channels to each connected peer which supports
+	/// `option_provide_storage` and hasn't received it yet.
+	///
+	/// Peers only store a backup for us if we have a funded channel with them, and only up to
+	/// [`MAX_PEER_STORAGE_SIZE`] bytes, so each peer's backup leads with the channels we have with
+	/// that peer, dropping channels with other peers if it would otherwise grow too large.
+	///
+	/// Returns whether any `peer_storage` messages were generated.
+	fn maybe_send_our_peer_storage(&self) -> bool {
+		if !self.default_configuration.enable_peer_storage {
+			return false;
+		}
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let mut all_channels = Vec::new();
+		for (_, peer_state_mutex) in per_peer_state.iter() {
+			let peer_state = peer_state_mutex.lock().unwrap();
+			for (_, phase) in peer_state.channel_by_id.iter() {
+				if let ChannelPhase::Funded(chan) = phase {
+					if let Some(funding_txo) = chan.context.get_funding_txo() {
+						all_channels.push(PeerStorageChannel {
+							counterparty_node_id: chan.context.get_counterparty_node_id(),
+							channel_id: chan.context.channel_id(),
+							funding_txo,
+							channel_value_satoshis: chan.context.get_value_satoshis(),
+							channel_keys_id: chan.context.get_channel_keys_id(),
+							channel_type: chan.context.get_channel_type().clone(),
+						});
+					}
+				}
+			}
+		}
+		// Keep the backup's contents stable so that we only resend it when something changed.
+		all_channels.sort_unstable_by_key(|chan| chan.channel_id);
+
+		let peer_storage_key = self.node_signer.get_peer_storage_key();
+		let mut has_update = false;
+		for (counterparty_node_id, peer_state_mutex) in per_peer_state.iter() {
+			let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+			let peer_state = &mut *peer_state_lock;
+			if !peer_state.is_connected || !peer_state.latest_features.supports_provide_storage() {
+				continue;
+			}
+
+			let (mut channels, other_channels): (Vec<_>, Vec<_>) = all_channels.iter().cloned()
+				.partition(|chan| chan.counterparty_node_id == *counterparty_node_id);
+			if channels.is_empty() {
+				continue;
+			}
+			channels.extend(other_channels);
+			let mut peer_storage = DecryptedOurPeerStorage { channels };
+			while peer_storage.encrypted_len() > MAX_PEER_STORAGE_SIZE {
+				peer_storage.channels.pop();
+			}
+			if peer_storage.channels.is_empty() || peer_state.sent_peer_storage.as_ref() == Some(&peer_storage) {
+				continue;
+			}
+
+			let logger = WithContext::from(&self.logger, Some(*counterparty_node_id), None);
+			log_debug!(logger, "Sending backup of {} channels to peer {}",
+				peer_storage.channels.len(), log_pubkey!(counterparty_node_id));
+			let msg = peer_storage.to_peer_storage_msg(&peer_storage_key, &self.entropy_source.get_secure_random_bytes());
+			peer_state.pending_msg_events.push(events::MessageSendEvent::SendPeerStorage {
+				node_id: *counterparty_node_id, msg,
+			});
+			peer_state.sent_peer_storage = Some(peer_storage);
+			has_update = true;
+		}
+		has_update
+	}
+
 	/// Check whether any channels have finished removing all pending updates after a shutdown
 	/// exchange and can now send a closing_signed.
 	/// Returns whether any closing_signed messages were generated.
@@ -7535,6 +10198,237 @@ where
 		Ok(builder)
 	}
 
+	/// Creates an [`OfferBuilder`] for an often-offline recipient, such that [`InvoiceRequest`]s
+	/// for the [`Offer`] it builds are sent over the given `message_paths` rather than to us.
+	///
+	/// The `message_paths` should terminate at an always-online node serving a [`StaticInvoice`]
+	/// for the offer on our behalf (e.g., our LSP, using [`Self::serve_static_invoice`]). Such
+	/// paths can be created by the LSP using [`Self::create_async_recipient_release_paths`].
+	///
+	/// Once built, use [`Self::create_static_invoice_builder`] to create the [`StaticInvoice`] for
+	/// the offer. Like [`Self::create_offer_builder`], uses a derived signing pubkey for recipient
+	/// privacy.
+	///
+	/// # Errors
+	///
+	/// Errors if `message_paths` is empty.
+	///
+	/// This is not exported to bindings users as builder patterns don't map outside of move semantics.
+	///
+	/// [`Offer`]: crate::offers::offer::Offer
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	pub fn create_async_receive_offer_builder(
+		&self, description: String, message_paths: Vec<BlindedPath>
+	) -> Result<OfferBuilder<DerivedMetadata, secp256k1::All>, Bolt12SemanticError> {
+		if message_paths.is_empty() {
+			return Err(Bolt12SemanticError::MissingPaths);
+		}
+
+		let node_id = self.get_our_node_id();
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		let builder = message_paths.into_iter().fold(
+			OfferBuilder::deriving_signing_pubkey(description, node_id, expanded_key, entropy, secp_ctx)
+				.chain_hash(self.chain_hash),
+			|builder, path| builder.path(path)
+		);
+
+		Ok(builder)
+	}
+
+	/// Creates a [`StaticInvoiceBuilder`] for an [`Offer`] built using
+	/// [`Self::create_async_receive_offer_builder`] or [`Self::create_offer_builder`]. The
+	/// resulting [`StaticInvoice`] can be handed to an always-online node to respond to
+	/// [`InvoiceRequest`]s for the offer on our behalf while we are offline.
+	///
+	/// Payers will notify us of their (held) payments over `message_paths`, which should be created
+	/// by our LSP using [`Self::create_async_recipient_release_paths`]. The invoice's payment paths
+	/// are created using our currently usable channels, so the invoice should be refreshed
+	/// periodically using [`Self::refresh_static_invoice`].
+	///
+	/// The builder will have a relative expiry of one week and MPP allowed.
+	///
+	/// # Errors
+	///
+	/// Errors if the offer wasn't created by us, has expired, or if the parameterized [`Router`]
+	/// is unable to create blinded payment paths.
+	///
+	/// This is not exported to bindings users as builder patterns don't map outside of move semantics.
+	///
+	/// [`Offer`]: crate::offers::offer::Offer
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	pub fn create_static_invoice_builder<'a>(
+		&self, offer: &'a Offer, message_paths: Vec<BlindedPath>
+	) -> Result<StaticInvoiceBuilder<'a>, Bolt12SemanticError> {
+		let expanded_key = &self.inbound_payment_key;
+		let secp_ctx = &self.secp_ctx;
+
+		let amount_msats = match offer.amount() {
+			Some(Amount::Bitcoin { amount_msats }) => *amount_msats,
+			Some(Amount::Currency { .. }) => return Err(Bolt12SemanticError::UnsupportedCurrency),
+			None => 0,
+		};
+
+		let relative_expiry = STATIC_INVOICE_RELATIVE_EXPIRY.as_secs() as u32;
+		let (_, payment_secret) = self.create_inbound_payment(None, relative_expiry, None)
+			.map_err(|()| Bolt12SemanticError::InvalidAmount)?;
+		let payment_paths = self.create_blinded_payment_paths(amount_msats, payment_secret)
+			.map_err(|()| Bolt12SemanticError::MissingPaths)?;
+
+		let created_at = self.duration_since_epoch();
+		let builder = StaticInvoiceBuilder::for_offer_using_derived_keys(
+			offer, payment_paths, message_paths, created_at, expanded_key, secp_ctx
+		)?
+			.relative_expiry(relative_expiry)
+			.allow_mpp();
+
+		Ok(builder)
+	}
+
+	/// Returns a new [`StaticInvoice`] replacing `invoice`, which was previously created for `offer`
+	/// using [`Self::create_static_invoice_builder`], if it's more than halfway through its
+	/// lifetime. Otherwise, returns `Ok(None)`, indicating `invoice` can still be used.
+	///
+	/// The new invoice uses the same message paths and has fresh payment paths. Any fallback
+	/// addresses set in `invoice` are not carried over; if needed, use
+	/// [`Self::create_static_invoice_builder`] instead.
+	///
+	/// The returned invoice should be handed to the node serving `invoice` on our behalf.
+	///
+	/// # Errors
+	///
+	/// Errors if `invoice` isn't for `offer` or if a new invoice couldn't be created as described
+	/// in [`Self::create_static_invoice_builder`].
+	pub fn refresh_static_invoice(
+		&self, offer: &Offer, invoice: &StaticInvoice
+	) -> Result<Option<StaticInvoice>, Bolt12SemanticError> {
+		if !invoice.is_for_offer(offer) {
+			return Err(Bolt12SemanticError::InvalidSigningPubkey);
+		}
+
+		let refresh_at = invoice.created_at().saturating_add(invoice.relative_expiry() / 2);
+		if self.duration_since_epoch() < refresh_at {
+			return Ok(None);
+		}
+
+		self.create_static_invoice_builder(offer, invoice.message_paths().to_vec())
+			.and_then(|builder| builder.build_and_sign(&self.secp_ctx))
+			.map(Some)
+	}
+
+	/// Serves `invoice` on behalf of an often-offline recipient (e.g., a client of ours as an LSP),
+	/// responding with it to any [`InvoiceRequest`]s for the corresponding offer until it expires.
+	/// Replaces any invoice previously served for the same offer, e.g., when the recipient
+	/// refreshed it using [`Self::refresh_static_invoice`].
+	///
+	/// For the [`InvoiceRequest`]s to reach us, the offer's paths should terminate at us, e.g., by
+	/// being created using [`Self::create_async_recipient_release_paths`].
+	///
+	/// Served invoices are persisted with the `ChannelManager`, so they continue to be served after
+	/// restarting.
+	///
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	pub fn serve_static_invoice(&self, invoice: StaticInvoice) {
+		self.served_static_invoices.lock().unwrap().insert(invoice.signing_pubkey(), invoice);
+	}
+
+	/// Stops serving the [`StaticInvoice`] for the offer with the given `offer_signing_pubkey`,
+	/// previously passed to [`Self::serve_static_invoice`], returning it if it was served.
+	pub fn stop_serving_static_invoice(&self, offer_signing_pubkey: &PublicKey) -> Option<StaticInvoice> {
+		self.served_static_invoices.lock().unwrap().remove(offer_signing_pubkey)
+	}
+
+	/// Sets the [`CurrencyConversion`] used to convert the amounts of [`Offer`]s denominated in a
+	/// currency other than bitcoin, both when paying for such offers using [`Self::pay_for_offer`]
+	/// and when responding to [`InvoiceRequest`]s for our own such offers.
+	///
+	/// Without one, paying for such offers requires explicitly giving an amount and invoice requests
+	/// for our own such offers are answered with [`Bolt12SemanticError::UnsupportedCurrency`], as
+	/// any amount given by the payer could not be checked against the offer.
+	///
+	/// The conversion is not persisted, so this must be called again after restarting.
+	///
+	/// This is not exported to bindings users as trait objects are not supported here.
+	pub fn set_currency_conversion(&self, currency_conversion: Arc<dyn CurrencyConversion + Send + Sync>) {
+		*self.currency_conversion.write().unwrap() = Some(currency_conversion);
+	}
+
+	/// Converts an offer's `amount` for the given `quantity` to msats using the configured
+	/// [`CurrencyConversion`], if needed.
+	fn offer_amount_msats(
+		&self, amount: &Amount, quantity: Option<u64>
+	) -> Result<u64, Bolt12SemanticError> {
+		let unit_msats = match amount {
+			Amount::Bitcoin { amount_msats } => *amount_msats,
+			Amount::Currency { .. } => match &*self.currency_conversion.read().unwrap() {
+				Some(currency_conversion) => amount.to_msats(currency_conversion)?,
+				None => return Err(Bolt12SemanticError::UnsupportedCurrency),
+			},
+		};
+
+		unit_msats.checked_mul(quantity.unwrap_or(1))
+			.filter(|amount_msats| *amount_msats <= msgs::MAX_VALUE_MSAT)
+			.ok_or(Bolt12SemanticError::InvalidAmount)
+	}
+
+	/// Determines the amount to invoice for an [`InvoiceRequest`], converting the offer's amount if
+	/// it is denominated in a currency other than bitcoin.
+	fn amount_msats_for_invoice_request(
+		&self, invoice_request: &InvoiceRequest
+	) -> Result<u64, Bolt12SemanticError> {
+		match invoice_request.amount() {
+			Some(amount @ Amount::Currency { .. }) => {
+				let converted_msats = self.offer_amount_msats(amount, invoice_request.quantity());
+				match (invoice_request.amount_msats(), converted_msats) {
+					(None, converted_msats) => converted_msats,
+					(Some(amount_msats), Ok(converted_msats)) => {
+						let tolerance_percent = core::cmp::min(
+							self.default_configuration.currency_conversion_tolerance_percent, 100
+						);
+						let min_msats = converted_msats as u128 * (100 - tolerance_percent as u128) / 100;
+						if (amount_msats as u128) < min_msats {
+							Err(Bolt12SemanticError::InsufficientAmount)
+						} else {
+							Ok(amount_msats)
+						}
+					},
+					(Some(_), Err(e)) => Err(e),
+				}
+			},
+			_ => InvoiceBuilder::<DerivedSigningPubkey>::amount_msats(invoice_request),
+		}
+	}
+
+	/// Returns the unexpired [`StaticInvoice`] we're serving for the offer with the given
+	/// `offer_signing_pubkey`, if any, no longer serving it if it has expired.
+	fn served_static_invoice(&self, offer_signing_pubkey: &PublicKey) -> Option<StaticInvoice> {
+		let mut served_static_invoices = self.served_static_invoices.lock().unwrap();
+		match served_static_invoices.get(offer_signing_pubkey) {
+			Some(invoice) if invoice.is_expired_no_std(self.duration_since_epoch()) => {
+				served_static_invoices.remove(offer_signing_pubkey);
+				None
+			},
+			Some(invoice) => Some(invoice.clone()),
+			None => None,
+		}
+	}
+
+	/// The current time as used when creating and refreshing [`StaticInvoice`]s and when checking
+	/// the paywindows of recurring [`Offer`]s.
+	fn duration_since_epoch(&self) -> Duration {
+		#[cfg(feature = "std")]
+		let duration_since_epoch = std::time::SystemTime::now()
+			.duration_since(std::time::SystemTime::UNIX_EPOCH)
+			.expect("SystemTime::now() should come after SystemTime::UNIX_EPOCH");
+		#[cfg(not(feature = "std"))]
+		let duration_since_epoch = Duration::from_secs(
+			self.highest_seen_timestamp.load(Ordering::Acquire) as u64
+		);
+		duration_since_epoch
+	}
+
 	/// Creates a [`RefundBuilder`] such that the [`Refund`] it builds is recognized by the
 	/// [`ChannelManager`] when handling [`Bolt12Invoice`] messages for the refund.
 	///
@@ -7603,7 +10497,7 @@ where
 		let expiration = StaleExpiration::AbsoluteTimeout(absolute_expiry);
 		self.pending_outbound_payments
 			.add_new_awaiting_invoice(
-				payment_id, expiration, retry_strategy, max_total_routing_fee_msat,
+				payment_id, expiration, retry_strategy, max_total_routing_fee_msat, None,
 			)
 			.map_err(|_| Bolt12SemanticError::DuplicatePaymentId)?;
 
@@ -7653,9 +10547,17 @@ where
 	/// Errors if:
 	/// - a duplicate `payment_id` is provided given the caveats in the aforementioned link,
 	/// - the provided parameters are invalid for the offer,
+	/// - the offer is denominated in a currency other than bitcoin, `amount_msats` is not given,
+	///   and the currency can't be converted using [`ChannelManager::set_currency_conversion`],
 	/// - the parameterized [`Router`] is unable to create a blinded reply path for the invoice
 	///   request.
 	///
+	/// # Currency
+	///
+	/// For offers denominated in a currency other than bitcoin, the recipient converts the amount
+	/// when responding unless `amount_msats` is given. The received invoice is only paid if its
+	/// amount is within [`UserConfig::currency_conversion_tolerance_percent`] of our own conversion.
+	///
 	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
 	/// [`InvoiceRequest::quantity`]: crate::offers::invoice_request::InvoiceRequest::quantity
 	/// [`InvoiceRequest::payer_note`]: crate::offers::invoice_request::InvoiceRequest::payer_note
@@ -7669,58 +10571,560 @@ where
 		max_total_routing_fee_msat: Option<u64>
 	) -> Result<(), Bolt12SemanticError> {
 		let expanded_key = &self.inbound_payment_key;
-		let entropy = &*self.entropy_source;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		let builder = offer
+			.request_invoice_deriving_payer_id(expanded_key, entropy, secp_ctx, payment_id)?
+			.chain_hash(self.chain_hash)?;
+		let builder = match quantity {
+			None => builder,
+			Some(quantity) => builder.quantity(quantity)?,
+		};
+		let builder = match amount_msats {
+			None => builder,
+			Some(amount_msats) => builder.amount_msats(amount_msats)?,
+		};
+		let builder = match payer_note {
+			None => builder,
+			Some(payer_note) => builder.payer_note(payer_note),
+		};
+		let invoice_request = builder.build_and_sign()?;
+
+		self.send_invoice_request(
+			offer, invoice_request, quantity, amount_msats, payment_id, retry_strategy,
+			max_total_routing_fee_msat
+		)
+	}
+
+	/// Enqueues an [`InvoiceRequest`] for `offer` to be sent via onion messages and waits for the
+	/// corresponding [`Bolt12Invoice`] to pay it.
+	fn send_invoice_request(
+		&self, offer: &Offer, invoice_request: InvoiceRequest, quantity: Option<u64>,
+		amount_msats: Option<u64>, payment_id: PaymentId, retry_strategy: Retry,
+		max_total_routing_fee_msat: Option<u64>
+	) -> Result<(), Bolt12SemanticError> {
+		let reply_path = self.create_blinded_path().map_err(|_| Bolt12SemanticError::MissingPaths)?;
+
+		// Bound what we're willing to pay for an offer denominated in another currency, since the
+		// recipient picks the amount when the invoice request doesn't set one.
+		let max_invoice_amount_msats = match (offer.amount(), amount_msats) {
+			(Some(amount @ Amount::Currency { .. }), None) => {
+				let converted_msats = self.offer_amount_msats(amount, quantity)?;
+				let tolerance_percent = self.default_configuration.currency_conversion_tolerance_percent;
+				let max_msats = converted_msats as u128 * (100 + tolerance_percent as u128) / 100;
+				Some(core::cmp::min(max_msats, msgs::MAX_VALUE_MSAT as u128) as u64)
+			},
+			_ => None,
+		};
+
+		let expiration = StaleExpiration::TimerTicks(1);
+		self.pending_outbound_payments
+			.add_new_awaiting_invoice(
+				payment_id, expiration, retry_strategy, max_total_routing_fee_msat,
+				max_invoice_amount_msats
+			)
+			.map_err(|_| Bolt12SemanticError::DuplicatePaymentId)?;
+
+		self.enqueue_invoice_request(offer, invoice_request, payment_id, reply_path);
+
+		Ok(())
+	}
+
+	/// Enqueues `invoice_request` to be sent to the recipient of `offer`, once per path in the offer
+	/// (with an upper bound).
+	///
+	/// Unless paying for a recurring offer, also remembers `offer` in case a [`StaticInvoice`] is
+	/// returned on behalf of the recipient, which is then paid for the requested amount.
+	fn enqueue_invoice_request(
+		&self, offer: &Offer, invoice_request: InvoiceRequest, payment_id: PaymentId,
+		reply_path: BlindedPath
+	) {
+		if invoice_request.recurrence_counter().is_none() {
+			if let Ok(amount_msats) = InvoiceBuilder::<DerivedSigningPubkey>::amount_msats(&invoice_request) {
+				let awaiting_static_invoice = AwaitingStaticInvoice { offer: offer.clone(), amount_msats };
+				self.awaiting_static_invoices.lock().unwrap().insert(payment_id, awaiting_static_invoice);
+			}
+		}
+
+		let mut pending_offers_messages = self.pending_offers_messages.lock().unwrap();
+		if offer.paths().is_empty() {
+			let message = new_pending_onion_message(
+				OffersMessage::InvoiceRequest(invoice_request),
+				Destination::Node(offer.signing_pubkey()),
+				Some(reply_path),
+			);
+			pending_offers_messages.push(message);
+		} else {
+			// Send as many invoice requests as there are paths in the offer (with an upper bound).
+			// Using only one path could result in a failure if the path no longer exists. But only
+			// one invoice for a given payment id will be paid, even if more than one is received.
+			const REQUEST_LIMIT: usize = 10;
+			for path in offer.paths().into_iter().take(REQUEST_LIMIT) {
+				let message = new_pending_onion_message(
+					OffersMessage::InvoiceRequest(invoice_request.clone()),
+					Destination::BlindedPath(path.clone()),
+					Some(reply_path.clone()),
+				);
+				pending_offers_messages.push(message);
+			}
+		}
+	}
+
+	/// Pays for an [`Offer`] resolved from a [BIP 353] human-readable name, such as
+	/// `₿alice@example.com`, in the same manner as [`pay_for_offer`].
+	///
+	/// Sends a [`DNSSECQuery`] for `name` to each of the given `dns_resolvers`, which must support
+	/// resolving names via onion messages as defined in [bLIP 32]. The first [`DNSSECProof`]
+	/// received in response that validates back to the DNS root trust anchor is used to find the
+	/// offer in the name's BIP 21 `bitcoin:` URI. An [`InvoiceRequest`] for `amount_msats` is then
+	/// sent for the offer and the resulting [`Bolt12Invoice`] is paid as in [`pay_for_offer`].
+	///
+	/// # Payment
+	///
+	/// The provided `payment_id` is used to ensure that only one invoice is paid, as with
+	/// [`pay_for_offer`].
+	///
+	/// To revoke the payment, use [`ChannelManager::abandon_payment`] prior to receiving the
+	/// invoice. If abandoned, or the name isn't resolved and an invoice received in a reasonable
+	/// amount of time, the payment will fail with an [`Event::InvoiceRequestFailed`].
+	///
+	/// # Privacy
+	///
+	/// Uses a one-hop [`BlindedPath`] for the reply path of the query with
+	/// [`ChannelManager::get_our_node_id`] as the introduction node. As such, currently, the node
+	/// must be announced. Otherwise, there is no way to find a path to the introduction node in
+	/// order to send the [`DNSSECProof`].
+	///
+	/// # Errors
+	///
+	/// Errors if:
+	/// - a duplicate `payment_id` is provided given the caveats of [`pay_for_offer`],
+	/// - `dns_resolvers` is empty, or
+	/// - a blinded reply path for the query can't be created.
+	///
+	/// [BIP 353]: https://github.com/bitcoin/bips/blob/master/bip-0353.mediawiki
+	/// [bLIP 32]: https://github.com/lightning/blips/blob/master/blip-0032.md
+	/// [`pay_for_offer`]: Self::pay_for_offer
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	/// [`Bolt12Invoice`]: crate::offers::invoice::Bolt12Invoice
+	pub fn pay_for_offer_from_human_readable_name(
+		&self, name: HumanReadableName, amount_msats: u64, payment_id: PaymentId,
+		retry_strategy: Retry, max_total_routing_fee_msat: Option<u64>,
+		dns_resolvers: Vec<Destination>
+	) -> Result<(), ()> {
+		if dns_resolvers.is_empty() {
+			return Err(());
+		}
+
+		let (query, path_id) = self.hrn_resolver.resolve_name(payment_id, name, &self.entropy_source)?;
+		let reply_path = BlindedPath::new_for_message_with_path_id(
+			&[self.get_our_node_id()], path_id, &*self.entropy_source, &self.secp_ctx
+		)?;
+
+		let expiration = StaleExpiration::TimerTicks(1);
+		self.pending_outbound_payments.add_new_awaiting_offer(
+			payment_id, expiration, retry_strategy, max_total_routing_fee_msat, amount_msats
+		)?;
+
+		// Query each resolver (with an upper bound), using the first valid proof received.
+		const QUERY_LIMIT: usize = 10;
+		let mut pending_dns_onion_messages = self.pending_dns_onion_messages.lock().unwrap();
+		for destination in dns_resolvers.into_iter().take(QUERY_LIMIT) {
+			let message = new_pending_onion_message(
+				DNSResolverMessage::DNSSECQuery(query.clone()), destination, Some(reply_path.clone()),
+			);
+			pending_dns_onion_messages.push(message);
+		}
+
+		Ok(())
+	}
+
+	/// Requests an invoice for `offer`, resolved for the payment with `payment_id` as initiated by
+	/// [`Self::pay_for_offer_from_human_readable_name`].
+	fn pay_for_resolved_offer(
+		&self, offer: &Offer, amount_msats: u64, payment_id: PaymentId
+	) -> Result<(), Bolt12SemanticError> {
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		let invoice_request = offer
+			.request_invoice_deriving_payer_id(expanded_key, entropy, secp_ctx, payment_id)?
+			.chain_hash(self.chain_hash)?
+			.amount_msats(amount_msats)?
+			.build_and_sign()?;
+		let reply_path = self.create_blinded_path().map_err(|_| Bolt12SemanticError::MissingPaths)?;
+
+		let expiration = StaleExpiration::TimerTicks(1);
+		self.pending_outbound_payments
+			.received_offer(payment_id, expiration)
+			.map_err(|_| Bolt12SemanticError::DuplicatePaymentId)?;
+
+		self.enqueue_invoice_request(offer, invoice_request, payment_id, reply_path);
+
+		Ok(())
+	}
+
+	/// Pays for the next period of a recurring [`Offer`] in the same manner as [`pay_for_offer`].
+	///
+	/// The first call for an offer starts paying it using a newly generated payer id, which is kept
+	/// along with the offer and reused for each later period so that the recipient can recognize
+	/// the payer. Each successive call then requests an invoice for the following period, i.e., with
+	/// an incremented [`InvoiceRequest::recurrence_counter`]. Calls are expected to be scheduled by
+	/// the user, typically once per [`Offer::recurrence`] period.
+	///
+	/// `recurrence_start` is the period to start paying at and is only used for the first call. It
+	/// must be given if and only if the offer's [`RecurrenceBase::start_any_period`] is set.
+	///
+	/// The period is considered paid, and the counter advanced, once the payment for the received
+	/// [`Bolt12Invoice`] succeeds, i.e., when [`Event::PaymentSent`] is generated for it. If the
+	/// payment fails instead, the next call requests an invoice for the same period again. Any
+	/// [`Bolt12Invoice`] for a period other than the next one is rejected. Use
+	/// [`ChannelManager::stop_offer_recurrence`] to stop paying the offer.
+	///
+	/// # Errors
+	///
+	/// In addition to the errors of [`pay_for_offer`], errors if:
+	/// - the offer is not recurring,
+	/// - a payment for the next period is still pending,
+	/// - the current time is outside the [`Offer::recurrence_paywindow`] of the next period, as
+	///   determined by the offer's [`RecurrenceBase`] or by the [`Bolt12Invoice::recurrence_basetime`]
+	///   of the first period paid, or
+	/// - the offer's [`Offer::recurrence_limit`] has been reached.
+	///
+	/// [`pay_for_offer`]: Self::pay_for_offer
+	/// [`InvoiceRequest::recurrence_counter`]: crate::offers::invoice_request::InvoiceRequest::recurrence_counter
+	/// [`RecurrenceBase`]: crate::offers::recurrence::RecurrenceBase
+	/// [`RecurrenceBase::start_any_period`]: crate::offers::recurrence::RecurrenceBase::start_any_period
+	/// [`Bolt12Invoice`]: crate::offers::invoice::Bolt12Invoice
+	/// [`Bolt12Invoice::recurrence_basetime`]: crate::offers::invoice::Bolt12Invoice::recurrence_basetime
+	/// [`Event::PaymentSent`]: events::Event::PaymentSent
+	pub fn pay_for_offer_recurrence(
+		&self, offer: &Offer, recurrence_start: Option<u32>, quantity: Option<u64>,
+		amount_msats: Option<u64>, payer_note: Option<String>, payment_id: PaymentId,
+		retry_strategy: Retry, max_total_routing_fee_msat: Option<u64>
+	) -> Result<(), Bolt12SemanticError> {
+		let recurrence = offer.recurrence().ok_or(Bolt12SemanticError::MissingRecurrence)?;
+
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let (payer_key, recurrence_start, recurrence_counter, basetime) = {
+			let mut outbound_recurrences = self.outbound_recurrences.lock().unwrap();
+			let existing_recurrence = outbound_recurrences.values()
+				.find(|recurrence| recurrence.offer.as_ref() == offer.as_ref());
+			match existing_recurrence {
+				Some(recurrence) if recurrence.pending_payment_id.is_some() => {
+					return Err(Bolt12SemanticError::InvalidRecurrenceCounter);
+				},
+				Some(recurrence) => (
+					recurrence.payer_key, recurrence.recurrence_start, recurrence.next_counter,
+					recurrence.basetime,
+				),
+				None => {
+					let payer_key = SecretKey::from_slice(
+						&self.entropy_source.get_secure_random_bytes()
+					).expect("RNG is bad!");
+					let payer_id = PublicKey::from_secret_key(&self.secp_ctx, &payer_key);
+					outbound_recurrences.insert(payer_id, OutboundRecurrence {
+						offer: offer.clone(), payer_key, recurrence_start, next_counter: 0,
+						basetime: None, pending_payment_id: None, pending_basetime: None,
+					});
+					(payer_key, recurrence_start, 0, None)
+				},
+			}
+		};
+
+		let basetime = offer.recurrence_base().map(|base| base.basetime).or(basetime);
+		if let Some(basetime) = basetime {
+			let period_index = recurrence_start.unwrap_or(0).checked_add(recurrence_counter)
+				.ok_or(Bolt12SemanticError::InvalidRecurrenceCounter)?;
+			let (paywindow_start, paywindow_end) = recurrence
+				.paywindow(Duration::from_secs(basetime), period_index, offer.recurrence_paywindow())
+				.ok_or(Bolt12SemanticError::InvalidRecurrence)?;
+			let now = self.duration_since_epoch();
+			if now < paywindow_start || now > paywindow_end {
+				return Err(Bolt12SemanticError::OutsideRecurrencePaywindow);
+			}
+		}
+
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let keys = KeyPair::from_secret_key(&self.secp_ctx, &payer_key);
+
+		let builder = offer
+			.request_invoice_deriving_metadata(keys.public_key(), expanded_key, entropy, payment_id)?
+			.chain_hash(self.chain_hash)?
+			.recurrence_counter(recurrence_counter);
+		let builder = match recurrence_start {
+			None => builder,
+			Some(recurrence_start) => builder.recurrence_start(recurrence_start),
+		};
+		let builder = match quantity {
+			None => builder,
+			Some(quantity) => builder.quantity(quantity)?,
+		};
+		let builder = match amount_msats {
+			None => builder,
+			Some(amount_msats) => builder.amount_msats(amount_msats)?,
+		};
+		let builder = match payer_note {
+			None => builder,
+			Some(payer_note) => builder.payer_note(payer_note),
+		};
+		let invoice_request = builder.build()?
+			.sign::<_, Infallible>(
+				|message| Ok(self.secp_ctx.sign_schnorr_no_aux_rand(message.as_ref().as_digest(), &keys))
+			)
+			.unwrap();
+
+		self.send_invoice_request(
+			offer, invoice_request, quantity, amount_msats, payment_id, retry_strategy,
+			max_total_routing_fee_msat
+		)
+	}
+
+	/// Stops paying a recurring [`Offer`] previously paid using
+	/// [`ChannelManager::pay_for_offer_recurrence`], forgetting the payer id used for it. Any
+	/// [`Bolt12Invoice`] received afterwards for the offer will not be paid.
+	///
+	/// Returns whether the offer was being paid.
+	///
+	/// [`Bolt12Invoice`]: crate::offers::invoice::Bolt12Invoice
+	pub fn stop_offer_recurrence(&self, offer: &Offer) -> bool {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let mut outbound_recurrences = self.outbound_recurrences.lock().unwrap();
+		let payer_id = outbound_recurrences.iter()
+			.find(|(_, recurrence)| recurrence.offer.as_ref() == offer.as_ref())
+			.map(|(payer_id, _)| *payer_id);
+		match payer_id {
+			Some(payer_id) => outbound_recurrences.remove(&payer_id).is_some(),
+			None => false,
+		}
+	}
+
+	/// Creates a [`PayerProof`] showing that we paid `invoice`, signed using the payer id we used
+	/// when requesting it. The proof discloses the invoice's description along with the fields
+	/// always required of a proof, as well as an optional `note` covered by our signature.
+	///
+	/// The invoice and preimage are given in [`Event::PaymentSent`] when paying for an [`Offer`]
+	/// using [`ChannelManager::pay_for_offer`] or for a [`Refund`] created using
+	/// [`ChannelManager::create_refund_builder`].
+	///
+	/// # Errors
+	///
+	/// Errors if `payment_preimage` does not match the invoice's payment hash or if the invoice was
+	/// not requested by us, in which case the payer keys can't be determined.
+	///
+	/// [`Event::PaymentSent`]: events::Event::PaymentSent
+	pub fn create_payer_proof(
+		&self, invoice: &Bolt12Invoice, payment_preimage: PaymentPreimage, note: Option<String>
+	) -> Result<PayerProof, Bolt12SemanticError> {
+		let expanded_key = &self.inbound_payment_key;
 		let secp_ctx = &self.secp_ctx;
 
-		let builder = offer
-			.request_invoice_deriving_payer_id(expanded_key, entropy, secp_ctx, payment_id)?
-			.chain_hash(self.chain_hash)?;
-		let builder = match quantity {
-			None => builder,
-			Some(quantity) => builder.quantity(quantity)?,
+		let keys = match invoice.verify_and_derive_payer_keys(expanded_key, secp_ctx) {
+			Ok((_, Some(keys))) => keys,
+			Ok((_, None)) => {
+				// Payer ids for recurring offers are chosen by us and stored rather than derived.
+				let outbound_recurrences = self.outbound_recurrences.lock().unwrap();
+				match outbound_recurrences.get(&invoice.payer_id()) {
+					Some(recurrence) => KeyPair::from_secret_key(secp_ctx, &recurrence.payer_key),
+					None => return Err(Bolt12SemanticError::InvalidMetadata),
+				}
+			},
+			Err(()) => return Err(Bolt12SemanticError::InvalidMetadata),
 		};
-		let builder = match amount_msats {
-			None => builder,
-			Some(amount_msats) => builder.amount_msats(amount_msats)?,
+
+		let mut builder = invoice.payer_proof_builder(payment_preimage)?.include_description();
+		if let Some(note) = note {
+			builder = builder.note(note);
+		}
+
+		builder
+			.build()?
+			.sign::<_, Infallible>(
+				|message| Ok(secp_ctx.sign_schnorr_no_aux_rand(message.as_ref().as_digest(), &keys))
+			)
+			.map_err(|_| Bolt12SemanticError::InvalidMetadata)
+	}
+
+	/// Checks that an invoice for a recurring offer we're paying is for the next period and, if so,
+	/// marks `payment_id` as the pending payment for it.
+	fn start_outbound_recurrence_payment(
+		&self, invoice: &Bolt12Invoice, payment_id: PaymentId
+	) -> Result<(), Bolt12SemanticError> {
+		let recurrence_counter = match invoice.recurrence_counter() {
+			None => return Ok(()),
+			Some(recurrence_counter) => recurrence_counter,
 		};
-		let builder = match payer_note {
-			None => builder,
-			Some(payer_note) => builder.payer_note(payer_note),
+
+		let mut outbound_recurrences = self.outbound_recurrences.lock().unwrap();
+		let recurrence = outbound_recurrences.get_mut(&invoice.payer_id())
+			.ok_or(Bolt12SemanticError::UnexpectedRecurrence)?;
+		if recurrence_counter != recurrence.next_counter {
+			return Err(Bolt12SemanticError::InvalidRecurrenceCounter);
+		}
+		if recurrence.pending_payment_id.map_or(false, |pending_id| pending_id != payment_id) {
+			return Err(Bolt12SemanticError::InvalidRecurrenceCounter);
+		}
+
+		let basetime = invoice.recurrence_basetime().map(|basetime| basetime.as_secs());
+		if recurrence.basetime.is_some() && recurrence.basetime != basetime {
+			return Err(Bolt12SemanticError::InvalidRecurrenceBasetime);
+		}
+
+		recurrence.pending_payment_id = Some(payment_id);
+		recurrence.pending_basetime = basetime;
+		Ok(())
+	}
+
+	/// Updates the recurring offer, if any, paid by `payment_id` once the payment has either
+	/// succeeded, marking the period as paid, or failed, allowing the period to be paid again.
+	fn complete_outbound_recurrence_payment(&self, payment_id: PaymentId, succeeded: bool) {
+		let mut outbound_recurrences = self.outbound_recurrences.lock().unwrap();
+		let recurrence = outbound_recurrences.values_mut()
+			.find(|recurrence| recurrence.pending_payment_id == Some(payment_id));
+		if let Some(recurrence) = recurrence {
+			let pending_basetime = recurrence.pending_basetime.take();
+			recurrence.pending_payment_id = None;
+			if succeeded {
+				recurrence.next_counter = recurrence.next_counter.saturating_add(1);
+				if recurrence.basetime.is_none() {
+					recurrence.basetime = pending_basetime;
+				}
+			}
+		}
+	}
+
+	/// Checks an invoice request for one of our recurring offers against the periods already paid
+	/// by the payer, returning the period to invoice along with the amount to invoice for it given
+	/// the otherwise invoiced `amount_msats`.
+	///
+	/// Requests may be for any period up to the one following the last period paid, such that
+	/// payers can't skip unpaid periods. Each must be made within the paywindow of the requested
+	/// period. If the offer's paywindow sets `proportional_amount`, the amount for a period which
+	/// has already started is reduced by the portion of it that has elapsed.
+	fn check_inbound_recurrence(
+		&self, invoice_request: &VerifiedInvoiceRequest, amount_msats: u64
+	) -> Result<Option<(InvoicedRecurrence, u64)>, InvoiceError> {
+		let recurrence = match invoice_request.recurrence() {
+			None => return Ok(None),
+			Some(recurrence) => recurrence,
 		};
-		let invoice_request = builder.build_and_sign()?;
-		let reply_path = self.create_blinded_path().map_err(|_| Bolt12SemanticError::MissingPaths)?;
+		let recurrence_counter = invoice_request.recurrence_counter()
+			.ok_or(Bolt12SemanticError::MissingRecurrenceCounter)?;
+		let period_index = invoice_request.recurrence_start().unwrap_or(0)
+			.checked_add(recurrence_counter)
+			.ok_or(Bolt12SemanticError::InvalidRecurrenceCounter)?;
+
+		if self.invoiced_recurrences.lock().unwrap().len() >= MAX_INVOICED_RECURRENCES {
+			return Err(InvoiceError::from_string("Too many unpaid recurring invoices".to_owned()));
+		}
 
-		let expiration = StaleExpiration::TimerTicks(1);
-		self.pending_outbound_payments
-			.add_new_awaiting_invoice(
-				payment_id, expiration, retry_strategy, max_total_routing_fee_msat
-			)
-			.map_err(|_| Bolt12SemanticError::DuplicatePaymentId)?;
+		let now = self.duration_since_epoch();
+		let inbound_recurrences = self.inbound_recurrences.lock().unwrap();
+		let inbound_recurrence = inbound_recurrences.get(&invoice_request.payer_id());
+		if recurrence_counter > inbound_recurrence.map_or(0, |recurrence| recurrence.next_counter) {
+			return Err(Bolt12SemanticError::InvalidRecurrenceCounter.into());
+		}
 
-		let mut pending_offers_messages = self.pending_offers_messages.lock().unwrap();
-		if offer.paths().is_empty() {
-			let message = new_pending_onion_message(
-				OffersMessage::InvoiceRequest(invoice_request),
-				Destination::Node(offer.signing_pubkey()),
-				Some(reply_path),
-			);
-			pending_offers_messages.push(message);
-		} else {
-			// Send as many invoice requests as there are paths in the offer (with an upper bound).
-			// Using only one path could result in a failure if the path no longer exists. But only
-			// one invoice for a given payment id will be paid, even if more than one is received.
-			const REQUEST_LIMIT: usize = 10;
-			for path in offer.paths().into_iter().take(REQUEST_LIMIT) {
-				let message = new_pending_onion_message(
-					OffersMessage::InvoiceRequest(invoice_request.clone()),
-					Destination::BlindedPath(path.clone()),
-					Some(reply_path.clone()),
-				);
-				pending_offers_messages.push(message);
+		let basetime = match (invoice_request.recurrence_base(), inbound_recurrence) {
+			(Some(base), _) => Duration::from_secs(base.basetime),
+			(None, Some(recurrence)) => Duration::from_secs(recurrence.basetime),
+			(None, None) => Duration::from_secs(now.as_secs()),
+		};
+
+		let paywindow = invoice_request.recurrence_paywindow();
+		let (paywindow_start, paywindow_end) = recurrence
+			.paywindow(basetime, period_index, paywindow)
+			.ok_or(Bolt12SemanticError::InvalidRecurrence)?;
+		if now < paywindow_start || now > paywindow_end {
+			return Err(Bolt12SemanticError::OutsideRecurrencePaywindow.into());
+		}
+
+		let proportional_amount = paywindow.map_or(false, |paywindow| paywindow.proportional_amount);
+		let amount_msats = match invoice_request.amount_msats() {
+			None if proportional_amount => recurrence
+				.proportional_amount_msats(basetime, period_index, amount_msats, now)
+				.ok_or(Bolt12SemanticError::InvalidRecurrence)?,
+			_ => amount_msats,
+		};
+
+		let next_paywindow_end = period_index.checked_add(1)
+			.and_then(|next_index| recurrence.paywindow(basetime, next_index, paywindow))
+			.map_or(u64::max_value(), |(_, next_paywindow_end)| next_paywindow_end.as_secs());
+		let invoiced_recurrence = InvoicedRecurrence {
+			payer_id: invoice_request.payer_id(),
+			recurrence_counter,
+			basetime: basetime.as_secs(),
+			next_paywindow_end,
+			expires_at: now.as_secs().saturating_add(DEFAULT_RELATIVE_EXPIRY.as_secs()),
+		};
+
+		Ok(Some((invoiced_recurrence, amount_msats)))
+	}
+
+	/// Records that a period of one of our recurring offers was invoiced with the given payment
+	/// hash, such that it can be marked as paid once claimed.
+	fn record_inbound_recurrence(
+		&self, payment_hash: PaymentHash, invoiced_recurrence: InvoicedRecurrence
+	) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		self.invoiced_recurrences.lock().unwrap().insert(payment_hash, invoiced_recurrence);
+	}
+
+	/// Marks the period of one of our recurring offers invoiced with the given payment hash, if any,
+	/// as paid.
+	fn advance_inbound_recurrence(&self, payment_hash: &PaymentHash) {
+		let invoiced_recurrence = match self.invoiced_recurrences.lock().unwrap().remove(payment_hash) {
+			Some(invoiced_recurrence) => invoiced_recurrence,
+			None => return,
+		};
+
+		let mut inbound_recurrences = self.inbound_recurrences.lock().unwrap();
+		let recurrence = inbound_recurrences.entry(invoiced_recurrence.payer_id)
+			.or_insert(InboundRecurrence {
+				basetime: invoiced_recurrence.basetime,
+				next_counter: 0,
+				lapses_at: invoiced_recurrence.next_paywindow_end,
+			});
+		if invoiced_recurrence.recurrence_counter >= recurrence.next_counter {
+			recurrence.next_counter = invoiced_recurrence.recurrence_counter.saturating_add(1);
+			recurrence.lapses_at = invoiced_recurrence.next_paywindow_end;
+		}
+	}
+
+	/// Forgets unpaid invoices for periods of our recurring offers once they've expired, as well as
+	/// recurrences whose payers can no longer continue paying.
+	fn prune_inbound_recurrences(&self) {
+		let now = self.duration_since_epoch().as_secs();
+
+		let expired_payment_hashes = self.invoiced_recurrences.lock().unwrap().iter()
+			.filter(|(_, invoiced_recurrence)| invoiced_recurrence.expires_at < now)
+			.map(|(payment_hash, _)| *payment_hash)
+			.collect::<Vec<_>>();
+		if !expired_payment_hashes.is_empty() {
+			// Payments already received before the invoice expired may still be claimed.
+			let claimable_payments = self.claimable_payments.lock().unwrap();
+			let expired_payment_hashes = expired_payment_hashes.into_iter()
+				.filter(|payment_hash| {
+					!claimable_payments.claimable_payments.contains_key(payment_hash) &&
+						!claimable_payments.pending_claiming_payments.contains_key(payment_hash)
+				})
+				.collect::<Vec<_>>();
+			mem::drop(claimable_payments);
+
+			let mut invoiced_recurrences = self.invoiced_recurrences.lock().unwrap();
+			for payment_hash in expired_payment_hashes {
+				invoiced_recurrences.remove(&payment_hash);
 			}
 		}
 
-		Ok(())
+		self.inbound_recurrences.lock().unwrap()
+			.retain(|_, recurrence| recurrence.lapses_at >= now);
 	}
 
 	/// Creates a [`Bolt12Invoice`] for a [`Refund`] and enqueues it to be sent via an onion
@@ -7889,6 +11293,143 @@ where
 			min_final_cltv_expiry)
 	}
 
+	/// Gets a [`PaymentSecret`] for a hold invoice with the given [`PaymentHash`], for which the
+	/// payment preimage isn't yet known. Unlike with [`create_inbound_payment_for_hash`], we track
+	/// the state of the payment (see [`hold_invoice_state`]) until it is either settled with the
+	/// preimage via [`settle_hold_invoice`] or cancelled via [`cancel_hold_invoice`].
+	///
+	/// Once the full amount has been received, a [`PaymentClaimable`] event is generated as usual.
+	/// Rather than letting the HTLCs time out at [`PaymentClaimable::claim_deadline`], we'll cancel
+	/// the invoice and fail them back automatically
+	/// [`UserConfig::hold_invoice_cancel_buffer_blocks`] blocks earlier. Hold invoices are
+	/// persisted with the `ChannelManager` and survive restarts.
+	///
+	/// `min_final_cltv_expiry_delta` bounds how long the payment may be held, and thus must be at
+	/// least [`MIN_FINAL_CLTV_EXPIRY_DELTA`] plus [`UserConfig::hold_invoice_cancel_buffer_blocks`].
+	/// See [`create_inbound_payment_for_hash`] for the other parameters.
+	///
+	/// Errors if `min_final_cltv_expiry_delta` is too small, if `min_value_msat` is greater than
+	/// total bitcoin supply, or if a hold invoice with the same payment hash already exists.
+	///
+	/// [`create_inbound_payment_for_hash`]: Self::create_inbound_payment_for_hash
+	/// [`hold_invoice_state`]: Self::hold_invoice_state
+	/// [`settle_hold_invoice`]: Self::settle_hold_invoice
+	/// [`cancel_hold_invoice`]: Self::cancel_hold_invoice
+	/// [`PaymentClaimable`]: events::Event::PaymentClaimable
+	/// [`PaymentClaimable::claim_deadline`]: events::Event::PaymentClaimable::claim_deadline
+	pub fn create_inbound_hold_payment(&self, payment_hash: PaymentHash, min_value_msat: Option<u64>,
+		invoice_expiry_delta_secs: u32, min_final_cltv_expiry_delta: u16) -> Result<PaymentSecret, ()> {
+		let cancel_buffer_blocks = self.default_configuration.hold_invoice_cancel_buffer_blocks;
+		if min_final_cltv_expiry_delta < MIN_FINAL_CLTV_EXPIRY_DELTA.saturating_add(cancel_buffer_blocks) {
+			return Err(());
+		}
+
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let mut hold_invoices = self.hold_invoices.lock().unwrap();
+		if hold_invoices.contains_key(&payment_hash) {
+			return Err(());
+		}
+
+		let highest_seen_timestamp = self.highest_seen_timestamp.load(Ordering::Acquire) as u64;
+		let payment_secret = inbound_payment::create_from_hash(&self.inbound_payment_key,
+			min_value_msat, payment_hash, invoice_expiry_delta_secs, highest_seen_timestamp,
+			Some(min_final_cltv_expiry_delta))?;
+		hold_invoices.insert(payment_hash, HoldInvoice {
+			expiry_time: highest_seen_timestamp + invoice_expiry_delta_secs as u64,
+			status: HoldInvoiceStatus::Open,
+		});
+		Ok(payment_secret)
+	}
+
+	/// Settles a hold invoice created via [`create_inbound_hold_payment`] whose HTLCs we're
+	/// holding (i.e. in [`HoldInvoiceState::Accepted`]), claiming them with the given preimage. An
+	/// [`Event::PaymentClaimed`] is generated once the claim completes.
+	///
+	/// Errors if there's no hold invoice for the preimage's payment hash or if it isn't accepted.
+	///
+	/// [`create_inbound_hold_payment`]: Self::create_inbound_hold_payment
+	pub fn settle_hold_invoice(&self, payment_preimage: PaymentPreimage) -> Result<(), APIError> {
+		let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).to_byte_array());
+
+		let mut hold_invoices = self.hold_invoices.lock().unwrap();
+		let hold = hold_invoices.get_mut(&payment_hash).ok_or_else(|| APIError::APIMisuseError {
+			err: format!("No hold invoice with payment hash {}", payment_hash),
+		})?;
+		let accepted = hold.status == HoldInvoiceStatus::Open && self.claimable_payments.lock().unwrap()
+			.claimable_payments.get(&payment_hash)
+			.map_or(false, |payment| self.hold_invoice_cancel_height(payment).is_some());
+		if !accepted {
+			return Err(APIError::APIMisuseError {
+				err: format!("Hold invoice with payment hash {} isn't awaiting settlement", payment_hash),
+			});
+		}
+		hold.status = HoldInvoiceStatus::Settled;
+		mem::drop(hold_invoices);
+
+		self.claim_funds(payment_preimage);
+		Ok(())
+	}
+
+	/// Cancels a hold invoice created via [`create_inbound_hold_payment`] which hasn't been
+	/// settled, failing back any HTLCs we're holding for it and any we receive later.
+	///
+	/// Errors if there's no hold invoice with the given payment hash or if it was already settled.
+	///
+	/// [`create_inbound_hold_payment`]: Self::create_inbound_hold_payment
+	pub fn cancel_hold_invoice(&self, payment_hash: &PaymentHash) -> Result<(), APIError> {
+		let mut hold_invoices = self.hold_invoices.lock().unwrap();
+		match hold_invoices.get_mut(payment_hash) {
+			None => return Err(APIError::APIMisuseError {
+				err: format!("No hold invoice with payment hash {}", payment_hash),
+			}),
+			Some(hold) if hold.status == HoldInvoiceStatus::Settled => return Err(APIError::APIMisuseError {
+				err: format!("Hold invoice with payment hash {} was already settled", payment_hash),
+			}),
+			Some(hold) => hold.status = HoldInvoiceStatus::Cancelled,
+		}
+		mem::drop(hold_invoices);
+
+		self.fail_htlc_backwards(payment_hash);
+		Ok(())
+	}
+
+	/// Returns the state of a hold invoice created via [`create_inbound_hold_payment`], or `None`
+	/// if there's no such invoice or it expired without any HTLCs pending.
+	///
+	/// [`create_inbound_hold_payment`]: Self::create_inbound_hold_payment
+	pub fn hold_invoice_state(&self, payment_hash: &PaymentHash) -> Option<HoldInvoiceState> {
+		let hold_invoices = self.hold_invoices.lock().unwrap();
+		let state = match hold_invoices.get(payment_hash)?.status {
+			HoldInvoiceStatus::Open => {
+				let claimable_payments = self.claimable_payments.lock().unwrap();
+				claimable_payments.claimable_payments.get(payment_hash)
+					.and_then(|payment| self.hold_invoice_cancel_height(payment).map(|cancel_height| {
+						let amount_msat = payment.htlcs.iter().map(|htlc| htlc.value).sum();
+						HoldInvoiceState::Accepted { amount_msat, cancel_height }
+					}))
+					.unwrap_or(HoldInvoiceState::Open)
+			},
+			HoldInvoiceStatus::Settled => HoldInvoiceState::Settled,
+			HoldInvoiceStatus::Cancelled => HoldInvoiceState::Cancelled,
+		};
+		Some(state)
+	}
+
+	/// Returns the height at which a hold invoice paid by `payment` is cancelled automatically, or
+	/// `None` if `payment` doesn't yet pay the full amount.
+	fn hold_invoice_cancel_height(&self, payment: &ClaimablePayment) -> Option<u32> {
+		// This condition determining whether the MPP is complete must match exactly the condition
+		// used in `process_pending_htlc_forwards`.
+		let total_value: u64 = payment.htlcs.iter().map(|htlc| htlc.sender_intended_value).sum();
+		let total_msat = payment.htlcs.first()?.total_msat;
+		if total_value < total_msat {
+			return None;
+		}
+		let earliest_expiry = payment.htlcs.iter().map(|htlc| htlc.cltv_expiry).min()?;
+		let cancel_buffer_blocks = self.default_configuration.hold_invoice_cancel_buffer_blocks as u32;
+		Some(earliest_expiry.saturating_sub(HTLC_FAIL_BACK_BUFFER + cancel_buffer_blocks))
+	}
+
 	/// Gets an LDK-generated payment preimage from a payment hash and payment secret that were
 	/// previously returned from [`create_inbound_payment`].
 	///
@@ -7897,6 +11438,33 @@ where
 		inbound_payment::get_payment_preimage(payment_hash, payment_secret, &self.inbound_payment_key)
 	}
 
+	/// Creates [`BlindedPath`]s to us which an often-offline `recipient` we're the LSP for may hand
+	/// out to senders of async payments (see [`Self::send_async_payment_with_route`]).
+	///
+	/// Once a sender's LSP is holding an HTLC for `recipient`, we'll receive a
+	/// [`HeldHtlcAvailable`] onion message over one of these paths and reply with a
+	/// [`ReleaseHeldHtlc`] message as soon as `recipient` is connected to us.
+	///
+	/// Errors if we fail to create a blinded path to ourselves.
+	pub fn create_async_recipient_release_paths(&self, recipient: PublicKey) -> Result<Vec<BlindedPath>, ()> {
+		let path_id = self.async_recipient_path_id(&recipient);
+		let path = BlindedPath::new_for_message_with_path_id(
+			&[self.get_our_node_id()], path_id, &*self.entropy_source, &self.secp_ctx
+		)?;
+		Ok(vec![path])
+	}
+
+	/// Computes the `path_id` used in the paths returned by
+	/// [`Self::create_async_recipient_release_paths`], identifying `recipient` without revealing it
+	/// to the sender.
+	fn async_recipient_path_id(&self, recipient: &PublicKey) -> [u8; 32] {
+		let key_material = self.node_signer.get_inbound_payment_key_material();
+		let mut hmac = HmacEngine::<Sha256>::new(&key_material.0);
+		hmac.input(b"LDK Async Payments Recipient");
+		hmac.input(&recipient.serialize());
+		Hmac::from_engine(hmac).to_byte_array()
+	}
+
 	/// Creates a blinded path by delegating to [`MessageRouter::create_blinded_paths`].
 	///
 	/// Errors if the `MessageRouter` errors or returns an empty `Vec`.
@@ -8051,17 +11619,18 @@ where
 			if let Some(peer_state_mtx) = per_peer_state.get(&counterparty_node_id) {
 				let mut peer_state_lck = peer_state_mtx.lock().unwrap();
 				let peer_state = &mut *peer_state_lck;
+				let channel_id = peer_state.channel_id_for_funding_outpoint(&channel_funding_outpoint);
 				if let Some(blocker) = completed_blocker.take() {
 					// Only do this on the first iteration of the loop.
 					if let Some(blockers) = peer_state.actions_blocking_raa_monitor_updates
-						.get_mut(&channel_funding_outpoint.to_channel_id())
+						.get_mut(&channel_id)
 					{
 						blockers.retain(|iter| iter != &blocker);
 					}
 				}
 
 				if self.raa_monitor_updates_held(&peer_state.actions_blocking_raa_monitor_updates,
-					channel_funding_outpoint, counterparty_node_id) {
+					channel_funding_outpoint, channel_id, counterparty_node_id) {
 					// Check that, while holding the peer lock, we don't have anything else
 					// blocking monitor updates for this channel. If we do, release the monitor
 					// update(s) when those blockers complete.
@@ -8070,7 +11639,7 @@ where
 					break;
 				}
 
-				if let hash_map::Entry::Occupied(mut chan_phase_entry) = peer_state.channel_by_id.entry(channel_funding_outpoint.to_channel_id()) {
+				if let hash_map::Entry::Occupied(mut chan_phase_entry) = peer_state.channel_by_id.entry(channel_id) {
 					if let ChannelPhase::Funded(chan) = chan_phase_entry.get_mut() {
 						debug_assert_eq!(chan.context.get_funding_txo().unwrap(), channel_funding_outpoint);
 						if let Some((monitor_update, further_update_exists)) = chan.unblock_next_blocked_monitor_update() {
@@ -8098,6 +11667,96 @@ where
 		}
 	}
 
+	/// Advances recurring offers once the payments for their periods have been claimed or sent, or
+	/// allows a period to be paid again once its payment failed.
+	fn update_recurrences_for_event(&self, event: &Event) {
+		match event {
+			Event::PaymentClaimed { payment_hash, .. } => self.advance_inbound_recurrence(payment_hash),
+			Event::PaymentSent { payment_id: Some(payment_id), .. } => {
+				self.complete_outbound_recurrence_payment(*payment_id, true);
+			},
+			Event::PaymentFailed { payment_id, .. } => {
+				self.complete_outbound_recurrence_payment(*payment_id, false);
+			},
+			_ => {},
+		}
+	}
+
+	/// Claims the circular payments of rebalances started via [`Self::rebalance`] and replaces the
+	/// payment events generated for them with [`Event::RebalanceSucceeded`] and
+	/// [`Event::RebalanceFailed`]. Returns `None` if the event should not be handed to the user.
+	fn map_rebalance_event(&self, event: Event) -> Option<Event> {
+		let mut pending_rebalances = self.pending_rebalances.lock().unwrap();
+		if pending_rebalances.is_empty() { return Some(event); }
+		match event {
+			Event::PaymentClaimable { payment_hash, .. } => {
+				let payment_preimage = pending_rebalances.values()
+					.find(|rebalance| rebalance.payment_hash == payment_hash)
+					.map(|rebalance| rebalance.payment_preimage);
+				match payment_preimage {
+					Some(payment_preimage) => {
+						mem::drop(pending_rebalances);
+						self.claim_funds(payment_preimage);
+						None
+					},
+					None => Some(event),
+				}
+			},
+			Event::PaymentClaimed { payment_hash, .. } => {
+				let rebalance = pending_rebalances.iter_mut()
+					.find(|(_, rebalance)| rebalance.payment_hash == payment_hash);
+				match rebalance {
+					Some((payment_id, rebalance)) => {
+						rebalance.claimed = true;
+						if rebalance.succeeded {
+							let payment_id = *payment_id;
+							pending_rebalances.remove(&payment_id);
+						}
+						None
+					},
+					None => Some(event),
+				}
+			},
+			Event::PaymentSent { payment_id: Some(payment_id), fee_paid_msat, .. } => {
+				match pending_rebalances.get_mut(&payment_id) {
+					Some(rebalance) => {
+						rebalance.succeeded = true;
+						let event = Event::RebalanceSucceeded {
+							payment_id,
+							outbound_channel_id: rebalance.outbound_channel_id,
+							inbound_channel_id: rebalance.inbound_channel_id,
+							amount_msat: rebalance.amount_msat,
+							fee_paid_msat,
+						};
+						if rebalance.claimed {
+							pending_rebalances.remove(&payment_id);
+						}
+						Some(event)
+					},
+					None => Some(event),
+				}
+			},
+			Event::PaymentFailed { payment_id, reason, .. } => {
+				match pending_rebalances.get(&payment_id) {
+					// In rare cases a `PaymentFailed` may follow a `PaymentSent`, which must be ignored.
+					Some(rebalance) if rebalance.succeeded => None,
+					Some(_) => {
+						let rebalance = pending_rebalances.remove(&payment_id).unwrap();
+						Some(Event::RebalanceFailed {
+							payment_id,
+							outbound_channel_id: rebalance.outbound_channel_id,
+							inbound_channel_id: rebalance.inbound_channel_id,
+							amount_msat: rebalance.amount_msat,
+							reason,
+						})
+					},
+					None => Some(event),
+				}
+			},
+			_ => Some(event),
+		}
+	}
+
 	fn handle_post_event_actions(&self, actions: Vec<EventCompletionAction>) {
 		for action in actions {
 			match action {
@@ -8157,12 +11816,18 @@ where
 				result = NotifyOption::DoPersist;
 			}
 
+			// Completing a channel type upgrade terminates quiescence, so do so before freeing
+			// any updates held in the meantime.
+			if self.maybe_progress_channel_type_upgrades() {
+				result = NotifyOption::DoPersist;
+			}
 			if self.check_free_holding_cells() {
 				result = NotifyOption::DoPersist;
 			}
 			if self.maybe_generate_initial_closing_signed() {
 				result = NotifyOption::DoPersist;
 			}
+			self.maybe_send_stfu();
 
 			let mut pending_events = Vec::new();
 			let per_peer_state = self.per_peer_state.read().unwrap();
@@ -8244,6 +11909,7 @@ where
 		}
 
 		self.do_chain_event(Some(new_height), |channel| channel.best_block_updated(new_height, header.time, self.chain_hash, &self.node_signer, &self.default_configuration, &&WithChannelContext::from(&self.logger, &channel.context)));
+		self.closing_txs_unconfirmed(|closed_channel| closed_channel.closing_tx_confirmation_height == Some(height));
 	}
 }
 
@@ -8272,6 +11938,18 @@ where
 		self.do_chain_event(Some(height), |channel| channel.transactions_confirmed(&block_hash, height, txdata, self.chain_hash, &self.node_signer, &self.default_configuration, &&WithChannelContext::from(&self.logger, &channel.context))
 			.map(|(a, b)| (a, Vec::new(), b)));
 
+		for closed_channel in self.closed_channels.lock().unwrap().iter_mut() {
+			if closed_channel.closing_txid.is_some() { continue; }
+			let funding_outpoint = closed_channel.funding_txo.into_bitcoin_outpoint();
+			let closing_tx = txdata.iter().find(|(_, tx)| {
+				tx.input.iter().any(|input| input.previous_output == funding_outpoint)
+			});
+			if let Some((_, closing_tx)) = closing_tx {
+				closed_channel.closing_txid = Some(closing_tx.txid());
+				closed_channel.closing_tx_confirmation_height = Some(height);
+			}
+		}
+
 		let last_best_block_height = self.best_block.read().unwrap().height();
 		if height < last_best_block_height {
 			let timestamp = self.highest_seen_timestamp.load(Ordering::Acquire);
@@ -8311,6 +11989,7 @@ where
 			}
 		}
 		max_time!(self.highest_seen_timestamp);
+		self.hrn_resolver.new_best_block(header.time);
 		let mut payment_secrets = self.pending_inbound_payments.lock().unwrap();
 		payment_secrets.retain(|_, inbound_payment| {
 			inbound_payment.expiry_time > header.time as u64
@@ -8345,6 +12024,7 @@ where
 				} else { Ok((None, Vec::new(), None)) }
 			} else { Ok((None, Vec::new(), None)) }
 		});
+		self.closing_txs_unconfirmed(|closed_channel| closed_channel.closing_txid == Some(*txid));
 	}
 }
 
@@ -8359,6 +12039,17 @@ where
 	R::Target: Router,
 	L::Target: Logger,
 {
+	/// Forgets the closing transaction of the archived closed channels matching `f`, as it has
+	/// been reorganized out of the chain.
+	fn closing_txs_unconfirmed<FN: Fn(&ClosedChannelDetails) -> bool>(&self, f: FN) {
+		for closed_channel in self.closed_channels.lock().unwrap().iter_mut() {
+			if f(closed_channel) {
+				closed_channel.closing_txid = None;
+				closed_channel.closing_tx_confirmation_height = None;
+			}
+		}
+	}
+
 	/// Calls a function which handles an on-chain event (blocks dis/connected, transactions
 	/// un/confirmed, etc) on each channel, handling any resulting errors or messages generated by
 	/// the function.
@@ -8380,6 +12071,8 @@ where
 					match phase {
 						// Retain unfunded channels.
 						ChannelPhase::UnfundedOutboundV1(_) | ChannelPhase::UnfundedInboundV1(_) => true,
+						#[cfg(dual_funding)]
+						ChannelPhase::UnfundedOutboundV2(_) | ChannelPhase::UnfundedInboundV2(_) => true,
 						ChannelPhase::Funded(channel) => {
 							let res = f(channel);
 							if let Ok((channel_ready_opt, mut timed_out_pending_htlcs, announcement_sigs)) = res {
@@ -8472,7 +12165,26 @@ where
 		}
 
 		if let Some(height) = height_opt {
+			let mut hold_invoices = self.hold_invoices.lock().unwrap();
 			self.claimable_payments.lock().unwrap().claimable_payments.retain(|payment_hash, payment| {
+				// Give up on held payments early enough to fail them back off-chain, even though the
+				// sender may still be waiting on us to settle.
+				if let Some(hold) = hold_invoices.get_mut(payment_hash) {
+					let cancel_height = self.hold_invoice_cancel_height(payment);
+					if hold.status == HoldInvoiceStatus::Open && cancel_height.map_or(false, |h| height >= h) {
+						log_debug!(self.logger, "Cancelling hold invoice with payment_hash {} as its HTLCs are about to expire", payment_hash);
+						for htlc in payment.htlcs.drain(..) {
+							let mut htlc_msat_height_data = htlc.value.to_be_bytes().to_vec();
+							htlc_msat_height_data.extend_from_slice(&height.to_be_bytes());
+
+							timed_out_htlcs.push((HTLCSource::PreviousHopData(htlc.prev_hop), payment_hash.clone(),
+								HTLCFailReason::reason(0x4000 | 15, htlc_msat_height_data),
+								HTLCDestination::FailedPayment { payment_hash: payment_hash.clone() }));
+						}
+						hold.status = HoldInvoiceStatus::Cancelled;
+						return false;
+					}
+				}
 				payment.htlcs.retain(|htlc| {
 					// If height is approaching the number of blocks we think it takes us to get
 					// our commitment transaction confirmed before the HTLC expires, plus the
@@ -8490,6 +12202,7 @@ where
 				});
 				!payment.htlcs.is_empty() // Only retain this entry if htlcs has at least one entry.
 			});
+			mem::drop(hold_invoices);
 
 			let mut intercepted_htlcs = self.pending_intercepted_htlcs.lock().unwrap();
 			intercepted_htlcs.retain(|_, htlc| {
@@ -8501,7 +12214,9 @@ where
 						incoming_packet_shared_secret: htlc.forward_info.incoming_shared_secret,
 						phantom_shared_secret: None,
 						outpoint: htlc.prev_funding_outpoint,
+						channel_id: htlc.prev_channel_id,
 						blinded_failure: htlc.forward_info.routing.blinded_failure(),
+						trampoline_forward: htlc.forward_info.routing.trampoline_forward(),
 					});
 
 					let requested_forward_scid /* intercept scid */ = match htlc.forward_info.routing {
@@ -8512,12 +12227,42 @@ where
 							HTLCFailReason::from_failure_code(0x2000 | 2),
 							HTLCDestination::InvalidForward { requested_forward_scid }));
 					let logger = WithContext::from(
-						&self.logger, None, Some(htlc.prev_funding_outpoint.to_channel_id())
+						&self.logger, None, Some(htlc.prev_channel_id)
 					);
 					log_trace!(logger, "Timing out intercepted HTLC with requested forward scid {}", requested_forward_scid);
 					false
 				} else { true }
 			});
+			mem::drop(intercepted_htlcs);
+
+			self.pending_held_htlcs.lock().unwrap().htlcs.retain(|_, htlc| {
+				if height >= htlc.forward_info.outgoing_cltv_value - HTLC_FAIL_BACK_BUFFER {
+					let prev_hop_data = HTLCSource::PreviousHopData(HTLCPreviousHopData {
+						short_channel_id: htlc.prev_short_channel_id,
+						user_channel_id: Some(htlc.prev_user_channel_id),
+						htlc_id: htlc.prev_htlc_id,
+						incoming_packet_shared_secret: htlc.forward_info.incoming_shared_secret,
+						phantom_shared_secret: None,
+						outpoint: htlc.prev_funding_outpoint,
+						channel_id: htlc.prev_channel_id,
+						blinded_failure: htlc.forward_info.routing.blinded_failure(),
+						trampoline_forward: htlc.forward_info.routing.trampoline_forward(),
+					});
+
+					let requested_forward_scid = match htlc.forward_info.routing {
+						PendingHTLCRouting::Forward { short_channel_id, .. } => short_channel_id,
+						_ => unreachable!(), // Only `PendingHTLCRouting::Forward`s are held
+					};
+					timed_out_htlcs.push((prev_hop_data, htlc.forward_info.payment_hash,
+							HTLCFailReason::from_failure_code(0x2000 | 2),
+							HTLCDestination::InvalidForward { requested_forward_scid }));
+					let logger = WithContext::from(
+						&self.logger, None, Some(htlc.prev_channel_id)
+					);
+					log_trace!(logger, "Timing out held HTLC with payment hash {} as it was never released", &htlc.forward_info.payment_hash);
+					false
+				} else { true }
+			});
 		}
 
 		self.handle_init_event_channel_failures(failed_channels);
@@ -8626,6 +12371,26 @@ where
 		});
 	}
 
+	#[cfg(dual_funding)]
+	fn handle_open_channel_v2(&self, counterparty_node_id: &PublicKey, msg: &msgs::OpenChannelV2) {
+		// Note that we never need to persist the updated ChannelManager for an inbound
+		// open_channel2 message - pre-funded channels are never written so there should be no
+		// change to the contents.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let res = self.internal_open_channel_v2(counterparty_node_id, msg);
+			let persist = match &res {
+				Err(e) if e.closes_channel() => {
+					debug_assert!(false, "We shouldn't close a new channel");
+					NotifyOption::DoPersist
+				},
+				_ => NotifyOption::SkipPersistHandleEvents,
+			};
+			let _ = handle_error!(self, res, *counterparty_node_id);
+			persist
+		});
+	}
+
+	#[cfg(not(dual_funding))]
 	fn handle_open_channel_v2(&self, counterparty_node_id: &PublicKey, msg: &msgs::OpenChannelV2) {
 		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
 			"Dual-funded channels not supported".to_owned(),
@@ -8642,6 +12407,18 @@ where
 		});
 	}
 
+	#[cfg(dual_funding)]
+	fn handle_accept_channel_v2(&self, counterparty_node_id: &PublicKey, msg: &msgs::AcceptChannelV2) {
+		// Note that we never need to persist the updated ChannelManager for an inbound
+		// accept_channel2 message - pre-funded channels are never written so there should be no
+		// change to the contents.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let _ = handle_error!(self, self.internal_accept_channel_v2(counterparty_node_id, msg), *counterparty_node_id);
+			NotifyOption::SkipPersistHandleEvents
+		});
+	}
+
+	#[cfg(not(dual_funding))]
 	fn handle_accept_channel_v2(&self, counterparty_node_id: &PublicKey, msg: &msgs::AcceptChannelV2) {
 		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
 			"Dual-funded channels not supported".to_owned(),
@@ -8675,9 +12452,42 @@ where
 	}
 
 	fn handle_stfu(&self, counterparty_node_id: &PublicKey, msg: &msgs::Stfu) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Quiescence not supported".to_owned(),
-			 msg.channel_id.clone())), *counterparty_node_id);
+		// Note that we never need to persist the updated ChannelManager for an inbound stfu message
+		// - quiescence is never written and is terminated upon reconnection.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let res = self.internal_stfu(counterparty_node_id, msg);
+			let persist = match &res {
+				Err(e) if e.closes_channel() => NotifyOption::DoPersist,
+				_ => NotifyOption::SkipPersistHandleEvents,
+			};
+			let _ = handle_error!(self, res, *counterparty_node_id);
+			persist
+		});
+	}
+
+	fn handle_dyn_propose(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynPropose) {
+		// Accepting a proposal changes the channel type, which we need to persist.
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_dyn_propose(counterparty_node_id, msg), *counterparty_node_id);
+	}
+
+	fn handle_dyn_ack(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynAck) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_dyn_ack(counterparty_node_id, msg), *counterparty_node_id);
+	}
+
+	fn handle_dyn_reject(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynReject) {
+		// Note that we never need to persist the updated ChannelManager for an inbound dyn_reject
+		// message - a proposed channel type upgrade is never written.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let res = self.internal_dyn_reject(counterparty_node_id, msg);
+			let persist = match &res {
+				Err(e) if e.closes_channel() => NotifyOption::DoPersist,
+				_ => NotifyOption::SkipPersistHandleEvents,
+			};
+			let _ = handle_error!(self, res, *counterparty_node_id);
+			persist
+		});
 	}
 
 	fn handle_splice(&self, counterparty_node_id: &PublicKey, msg: &msgs::Splice) {
@@ -8802,6 +12612,14 @@ where
 		});
 	}
 
+	fn handle_peer_storage(&self, counterparty_node_id: &PublicKey, msg: &msgs::PeerStorage) {
+		PersistenceNotifierGuard::optionally_notify(self, || self.internal_peer_storage(counterparty_node_id, msg));
+	}
+
+	fn handle_your_peer_storage(&self, counterparty_node_id: &PublicKey, msg: &msgs::YourPeerStorage) {
+		PersistenceNotifierGuard::optionally_notify(self, || self.internal_your_peer_storage(counterparty_node_id, msg));
+	}
+
 	fn handle_channel_reestablish(&self, counterparty_node_id: &PublicKey, msg: &msgs::ChannelReestablish) {
 		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
 			let res = self.internal_channel_reestablish(counterparty_node_id, msg);
@@ -8847,6 +12665,14 @@ where
 						ChannelPhase::UnfundedInboundV1(chan) => {
 							&mut chan.context
 						},
+						#[cfg(dual_funding)]
+						ChannelPhase::UnfundedOutboundV2(chan) => {
+							&mut chan.context
+						},
+						#[cfg(dual_funding)]
+						ChannelPhase::UnfundedInboundV2(chan) => {
+							&mut chan.context
+						},
 					};
 					// Clean up for removal.
 					update_maps_on_chan_removal!(self, &context);
@@ -8872,6 +12698,10 @@ where
 						&events::MessageSendEvent::SendAnnouncementSignatures { .. } => false,
 						// Quiescence
 						&events::MessageSendEvent::SendStfu { .. } => false,
+						// Dynamic Commitments
+						&events::MessageSendEvent::SendDynPropose { .. } => false,
+						&events::MessageSendEvent::SendDynAck { .. } => false,
+						&events::MessageSendEvent::SendDynReject { .. } => false,
 						// Splicing
 						&events::MessageSendEvent::SendSplice { .. } => false,
 						&events::MessageSendEvent::SendSpliceAck { .. } => false,
@@ -8892,6 +12722,9 @@ where
 						&events::MessageSendEvent::SendClosingSigned { .. } => false,
 						&events::MessageSendEvent::SendShutdown { .. } => false,
 						&events::MessageSendEvent::SendChannelReestablish { .. } => false,
+						// Peer Storage
+						&events::MessageSendEvent::SendPeerStorage { .. } => false,
+						&events::MessageSendEvent::SendYourPeerStorage { .. } => false,
 						&events::MessageSendEvent::HandleError { .. } => false,
 						// Gossip
 						&events::MessageSendEvent::SendChannelAnnouncement { .. } => false,
@@ -8907,6 +12740,7 @@ where
 				});
 				debug_assert!(peer_state.is_connected, "A disconnected peer cannot disconnect");
 				peer_state.is_connected = false;
+				peer_state.sent_peer_storage = None;
 				peer_state.ok_to_remove(true)
 			} else { debug_assert!(false, "Unconnected peer disconnected"); true }
 		};
@@ -8954,6 +12788,9 @@ where
 							monitor_update_blocked_actions: BTreeMap::new(),
 							actions_blocking_raa_monitor_updates: BTreeMap::new(),
 							is_connected: true,
+							peer_storage: Vec::new(),
+							sent_peer_storage: None,
+							held_htlc_releases: Vec::new(),
 						}));
 					},
 					hash_map::Entry::Occupied(e) => {
@@ -8977,12 +12814,21 @@ where
 
 			log_debug!(logger, "Generating channel_reestablish events for {}", log_pubkey!(counterparty_node_id));
 
+			let mut held_htlc_releases = Vec::new();
 			let per_peer_state = self.per_peer_state.read().unwrap();
 			if let Some(peer_state_mutex) = per_peer_state.get(counterparty_node_id) {
 				let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 				let peer_state = &mut *peer_state_lock;
+				mem::swap(&mut held_htlc_releases, &mut peer_state.held_htlc_releases);
 				let pending_msg_events = &mut peer_state.pending_msg_events;
 
+				if !peer_state.peer_storage.is_empty() {
+					pending_msg_events.push(events::MessageSendEvent::SendYourPeerStorage {
+						node_id: *counterparty_node_id,
+						msg: msgs::YourPeerStorage { data: peer_state.peer_storage.clone() },
+					});
+				}
+
 				peer_state.channel_by_id.iter_mut().filter_map(|(_, phase)|
 					if let ChannelPhase::Funded(chan) = phase { Some(chan) } else {
 						// Since unfunded channel maps are cleared upon disconnecting a peer, and they're not persisted
@@ -8999,6 +12845,15 @@ where
 					});
 				});
 			}
+			mem::drop(per_peer_state);
+
+			if !held_htlc_releases.is_empty() {
+				log_debug!(logger, "Releasing {} held HTLC(s) for {} now that it's online",
+					held_htlc_releases.len(), log_pubkey!(counterparty_node_id));
+				self.pending_async_payments_messages.lock().unwrap().append(&mut held_htlc_releases);
+			}
+
+			self.maybe_send_our_peer_storage();
 
 			return NotifyOption::SkipPersistHandleEvents;
 			//TODO: Also re-broadcast announcement_signatures
@@ -9101,36 +12956,147 @@ where
 		Some(vec![self.chain_hash])
 	}
 
+	#[cfg(dual_funding)]
+	fn handle_tx_add_input(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAddInput) {
+		// Note that we never need to persist the updated ChannelManager for an inbound
+		// tx_add_input message - a funding transaction being negotiated is never written.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let res = self.internal_tx_msg(counterparty_node_id, msg.channel_id, |channel_phase| {
+				match channel_phase {
+					ChannelPhase::UnfundedOutboundV2(chan) => chan.tx_add_input(msg),
+					ChannelPhase::UnfundedInboundV2(chan) => chan.tx_add_input(msg),
+					_ => Err(ChannelError::Close("Got a tx_add_input message for a channel not being dual-funded".to_owned())),
+				}
+			});
+			let persist = match &res {
+				Err(e) if e.closes_channel() => NotifyOption::DoPersist,
+				_ => NotifyOption::SkipPersistHandleEvents,
+			};
+			let _ = handle_error!(self, res, *counterparty_node_id);
+			persist
+		});
+	}
+
+	#[cfg(not(dual_funding))]
 	fn handle_tx_add_input(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAddInput) {
 		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
 			"Dual-funded channels not supported".to_owned(),
 			 msg.channel_id.clone())), *counterparty_node_id);
 	}
 
+	#[cfg(dual_funding)]
+	fn handle_tx_add_output(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAddOutput) {
+		// Note that we never need to persist the updated ChannelManager for an inbound
+		// tx_add_output message - a funding transaction being negotiated is never written.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let res = self.internal_tx_msg(counterparty_node_id, msg.channel_id, |channel_phase| {
+				match channel_phase {
+					ChannelPhase::UnfundedOutboundV2(chan) => chan.tx_add_output(msg),
+					ChannelPhase::UnfundedInboundV2(chan) => chan.tx_add_output(msg),
+					_ => Err(ChannelError::Close("Got a tx_add_output message for a channel not being dual-funded".to_owned())),
+				}
+			});
+			let persist = match &res {
+				Err(e) if e.closes_channel() => NotifyOption::DoPersist,
+				_ => NotifyOption::SkipPersistHandleEvents,
+			};
+			let _ = handle_error!(self, res, *counterparty_node_id);
+			persist
+		});
+	}
+
+	#[cfg(not(dual_funding))]
 	fn handle_tx_add_output(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAddOutput) {
 		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
 			"Dual-funded channels not supported".to_owned(),
 			 msg.channel_id.clone())), *counterparty_node_id);
 	}
 
+	#[cfg(dual_funding)]
+	fn handle_tx_remove_input(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxRemoveInput) {
+		// Note that we never need to persist the updated ChannelManager for an inbound
+		// tx_remove_input message - a funding transaction being negotiated is never written.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let res = self.internal_tx_msg(counterparty_node_id, msg.channel_id, |channel_phase| {
+				match channel_phase {
+					ChannelPhase::UnfundedOutboundV2(chan) => chan.tx_remove_input(msg),
+					ChannelPhase::UnfundedInboundV2(chan) => chan.tx_remove_input(msg),
+					_ => Err(ChannelError::Close("Got a tx_remove_input message for a channel not being dual-funded".to_owned())),
+				}
+			});
+			let persist = match &res {
+				Err(e) if e.closes_channel() => NotifyOption::DoPersist,
+				_ => NotifyOption::SkipPersistHandleEvents,
+			};
+			let _ = handle_error!(self, res, *counterparty_node_id);
+			persist
+		});
+	}
+
+	#[cfg(not(dual_funding))]
 	fn handle_tx_remove_input(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxRemoveInput) {
 		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
 			"Dual-funded channels not supported".to_owned(),
 			 msg.channel_id.clone())), *counterparty_node_id);
 	}
 
+	#[cfg(dual_funding)]
+	fn handle_tx_remove_output(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxRemoveOutput) {
+		// Note that we never need to persist the updated ChannelManager for an inbound
+		// tx_remove_output message - a funding transaction being negotiated is never written.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let res = self.internal_tx_msg(counterparty_node_id, msg.channel_id, |channel_phase| {
+				match channel_phase {
+					ChannelPhase::UnfundedOutboundV2(chan) => chan.tx_remove_output(msg),
+					ChannelPhase::UnfundedInboundV2(chan) => chan.tx_remove_output(msg),
+					_ => Err(ChannelError::Close("Got a tx_remove_output message for a channel not being dual-funded".to_owned())),
+				}
+			});
+			let persist = match &res {
+				Err(e) if e.closes_channel() => NotifyOption::DoPersist,
+				_ => NotifyOption::SkipPersistHandleEvents,
+			};
+			let _ = handle_error!(self, res, *counterparty_node_id);
+			persist
+		});
+	}
+
+	#[cfg(not(dual_funding))]
 	fn handle_tx_remove_output(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxRemoveOutput) {
 		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
 			"Dual-funded channels not supported".to_owned(),
 			 msg.channel_id.clone())), *counterparty_node_id);
 	}
 
+	#[cfg(dual_funding)]
+	fn handle_tx_complete(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxComplete) {
+		// Note that we never need to persist the updated ChannelManager for an inbound
+		// tx_complete message - a funding transaction being negotiated is never written.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let res = self.internal_tx_complete(counterparty_node_id, msg);
+			let persist = match &res {
+				Err(e) if e.closes_channel() => NotifyOption::DoPersist,
+				_ => NotifyOption::SkipPersistHandleEvents,
+			};
+			let _ = handle_error!(self, res, *counterparty_node_id);
+			persist
+		});
+	}
+
+	#[cfg(not(dual_funding))]
 	fn handle_tx_complete(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxComplete) {
 		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
 			"Dual-funded channels not supported".to_owned(),
 			 msg.channel_id.clone())), *counterparty_node_id);
 	}
 
+	#[cfg(dual_funding)]
+	fn handle_tx_signatures(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxSignatures) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_tx_signatures(counterparty_node_id, msg), *counterparty_node_id);
+	}
+
+	#[cfg(not(dual_funding))]
 	fn handle_tx_signatures(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxSignatures) {
 		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
 			"Dual-funded channels not supported".to_owned(),
@@ -9149,6 +13115,22 @@ where
 			 msg.channel_id.clone())), *counterparty_node_id);
 	}
 
+	#[cfg(dual_funding)]
+	fn handle_tx_abort(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAbort) {
+		// Note that we never need to persist the updated ChannelManager for an inbound
+		// tx_abort message - a funding transaction being negotiated is never written.
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let res = self.internal_tx_abort(counterparty_node_id, msg);
+			let persist = match &res {
+				Err(e) if e.closes_channel() => NotifyOption::DoPersist,
+				_ => NotifyOption::SkipPersistHandleEvents,
+			};
+			let _ = handle_error!(self, res, *counterparty_node_id);
+			persist
+		});
+	}
+
+	#[cfg(not(dual_funding))]
 	fn handle_tx_abort(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAbort) {
 		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
 			"Dual-funded channels not supported".to_owned(),
@@ -9174,12 +13156,17 @@ where
 
 		match message {
 			OffersMessage::InvoiceRequest(invoice_request) => {
-				let amount_msats = match InvoiceBuilder::<DerivedSigningPubkey>::amount_msats(
-					&invoice_request
-				) {
+				if let Some(invoice) = self.served_static_invoice(&invoice_request.signing_pubkey()) {
+					return Some(OffersMessage::StaticInvoice(invoice));
+				}
+
+				let amount_msats = match self.amount_msats_for_invoice_request(&invoice_request) {
 					Ok(amount_msats) => amount_msats,
 					Err(error) => return Some(OffersMessage::InvoiceError(error.into())),
 				};
+				let needs_converted_amount =
+					matches!(invoice_request.amount(), Some(Amount::Currency { .. })) &&
+					invoice_request.amount_msats().is_none();
 				let invoice_request = match invoice_request.verify(expanded_key, secp_ctx) {
 					Ok(invoice_request) => invoice_request,
 					Err(()) => {
@@ -9187,10 +13174,20 @@ where
 						return Some(OffersMessage::InvoiceError(error.into()));
 					},
 				};
+				let (invoiced_recurrence, invoice_amount_msats) =
+					match self.check_inbound_recurrence(&invoice_request, amount_msats) {
+						Ok(Some((invoiced_recurrence, invoice_amount_msats))) =>
+							(Some(invoiced_recurrence), invoice_amount_msats),
+						Ok(None) => (None, amount_msats),
+						Err(error) => return Some(OffersMessage::InvoiceError(error)),
+					};
+				let recurrence_basetime = invoiced_recurrence.as_ref()
+					.map(|invoiced_recurrence| Duration::from_secs(invoiced_recurrence.basetime));
+				let needs_proportional_amount = invoice_amount_msats != amount_msats;
 
 				let relative_expiry = DEFAULT_RELATIVE_EXPIRY.as_secs() as u32;
 				let (payment_hash, payment_secret) = match self.create_inbound_payment(
-					Some(amount_msats), relative_expiry, None
+					Some(invoice_amount_msats), relative_expiry, None
 				) {
 					Ok((payment_hash, payment_secret)) => (payment_hash, payment_secret),
 					Err(()) => {
@@ -9200,7 +13197,7 @@ where
 				};
 
 				let payment_paths = match self.create_blinded_payment_paths(
-					amount_msats, payment_secret
+					invoice_amount_msats, payment_secret
 				) {
 					Ok(payment_paths) => payment_paths,
 					Err(()) => {
@@ -9223,8 +13220,25 @@ where
 					let builder = invoice_request.respond_using_derived_keys_no_std(
 						payment_paths, payment_hash, created_at
 					);
+					let builder = match needs_converted_amount {
+						true => builder.and_then(|b| b.converted_amount_msats(amount_msats)),
+						false => builder,
+					};
+					let builder = match needs_proportional_amount {
+						true => builder.and_then(|b| b.proportional_amount_msats(invoice_amount_msats)),
+						false => builder,
+					};
+					let builder = match recurrence_basetime {
+						Some(basetime) => builder.and_then(|b| b.recurrence_basetime(basetime)),
+						None => builder,
+					};
 					match builder.and_then(|b| b.allow_mpp().build_and_sign(secp_ctx)) {
-						Ok(invoice) => Some(OffersMessage::Invoice(invoice)),
+						Ok(invoice) => {
+							if let Some(invoiced_recurrence) = invoiced_recurrence {
+								self.record_inbound_recurrence(payment_hash, invoiced_recurrence);
+							}
+							Some(OffersMessage::Invoice(invoice))
+						},
 						Err(error) => Some(OffersMessage::InvoiceError(error.into())),
 					}
 				} else {
@@ -9234,11 +13248,28 @@ where
 					let builder = invoice_request.respond_with_no_std(
 						payment_paths, payment_hash, created_at
 					);
+					let builder = match needs_converted_amount {
+						true => builder.and_then(|b| b.converted_amount_msats(amount_msats)),
+						false => builder,
+					};
+					let builder = match needs_proportional_amount {
+						true => builder.and_then(|b| b.proportional_amount_msats(invoice_amount_msats)),
+						false => builder,
+					};
+					let builder = match recurrence_basetime {
+						Some(basetime) => builder.and_then(|b| b.recurrence_basetime(basetime)),
+						None => builder,
+					};
 					let response = builder.and_then(|builder| builder.allow_mpp().build())
 						.map_err(|e| OffersMessage::InvoiceError(e.into()))
 						.and_then(|invoice|
 							match invoice.sign(|invoice| self.node_signer.sign_bolt12_invoice(invoice)) {
-								Ok(invoice) => Ok(OffersMessage::Invoice(invoice)),
+								Ok(invoice) => {
+									if let Some(invoiced_recurrence) = invoiced_recurrence {
+										self.record_inbound_recurrence(payment_hash, invoiced_recurrence);
+									}
+									Ok(OffersMessage::Invoice(invoice))
+								},
 								Err(SignError::Signing(())) => Err(OffersMessage::InvoiceError(
 										InvoiceError::from_string("Failed signing invoice".to_string())
 								)),
@@ -9261,8 +13292,14 @@ where
 						Some(OffersMessage::InvoiceError(Bolt12SemanticError::UnknownRequiredFeatures.into()))
 					},
 					Ok(payment_id) => {
+						self.awaiting_static_invoices.lock().unwrap().remove(&payment_id);
+						if let Err(e) = self.start_outbound_recurrence_payment(&invoice, payment_id) {
+							log_trace!(self.logger, "Rejecting invoice for recurring offer: {:?}", e);
+							return Some(OffersMessage::InvoiceError(e.into()));
+						}
 						if let Err(e) = self.send_payment_for_bolt12_invoice(&invoice, payment_id) {
 							log_trace!(self.logger, "Failed paying invoice: {:?}", e);
+							self.complete_outbound_recurrence_payment(payment_id, false);
 							Some(OffersMessage::InvoiceError(InvoiceError::from_string(format!("{:?}", e))))
 						} else {
 							None
@@ -9274,6 +13311,35 @@ where
 				log_trace!(self.logger, "Received invoice_error: {}", invoice_error);
 				None
 			},
+			OffersMessage::StaticInvoice(invoice) => {
+				// Static invoices aren't tied to our invoice request, so find it by the offer instead.
+				let awaiting_static_invoice = {
+					let mut awaiting_static_invoices = self.awaiting_static_invoices.lock().unwrap();
+					let payment_id = awaiting_static_invoices.iter()
+						.find(|(_, awaiting)| invoice.is_for_offer(&awaiting.offer))
+						.map(|(payment_id, _)| *payment_id);
+					payment_id.and_then(|payment_id| awaiting_static_invoices.remove(&payment_id)
+						.map(|awaiting| (payment_id, awaiting.amount_msats)))
+				};
+				let (payment_id, amount_msats) = match awaiting_static_invoice {
+					Some(awaiting_static_invoice) => awaiting_static_invoice,
+					None => return Some(OffersMessage::InvoiceError(
+						InvoiceError::from_string("Unrecognized invoice".to_owned())
+					)),
+				};
+				if invoice.is_expired_no_std(self.duration_since_epoch()) {
+					return Some(OffersMessage::InvoiceError(Bolt12SemanticError::AlreadyExpired.into()));
+				}
+				if invoice.invoice_features().requires_unknown_bits_from(&self.bolt12_invoice_features()) {
+					return Some(OffersMessage::InvoiceError(Bolt12SemanticError::UnknownRequiredFeatures.into()));
+				}
+				if let Err(e) = self.send_payment_for_static_invoice(&invoice, amount_msats, payment_id) {
+					log_trace!(self.logger, "Failed paying static invoice: {:?}", e);
+					Some(OffersMessage::InvoiceError(InvoiceError::from_string(format!("{:?}", e))))
+				} else {
+					None
+				}
+			},
 		}
 	}
 
@@ -9282,6 +13348,167 @@ where
 	}
 }
 
+impl<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>
+AsyncPaymentsMessageHandler for ChannelManager<M, T, ES, NS, SP, F, R, L>
+where
+	M::Target: chain::Watch<<SP::Target as SignerProvider>::EcdsaSigner>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+{
+	fn held_htlc_available(
+		&self, message: HeldHtlcAvailable, path_id: Option<[u8; 32]>, reply_path: Option<BlindedPath>
+	) {
+		let (path_id, reply_path) = match (path_id, reply_path) {
+			(Some(path_id), Some(reply_path)) => (path_id, reply_path),
+			_ => {
+				log_trace!(self.logger, "Ignoring held_htlc_available message without a path_id or reply path");
+				return;
+			},
+		};
+
+		let mut release = Some(new_pending_onion_message(
+			AsyncPaymentsMessage::ReleaseHeldHtlc(ReleaseHeldHtlc {
+				payment_release_secret: message.payment_release_secret,
+			}),
+			Destination::BlindedPath(reply_path),
+			None,
+		));
+		{
+			let per_peer_state = self.per_peer_state.read().unwrap();
+			let recipient = per_peer_state.iter()
+				.find(|(node_id, _)| self.async_recipient_path_id(node_id) == path_id);
+			let (recipient_node_id, peer_state_mutex) = match recipient {
+				Some(recipient) => recipient,
+				None => {
+					log_trace!(self.logger, "Ignoring held_htlc_available message for an unknown recipient");
+					return;
+				},
+			};
+			let logger = WithContext::from(&self.logger, Some(*recipient_node_id), None);
+			let mut peer_state = peer_state_mutex.lock().unwrap();
+			if !peer_state.is_connected {
+				if peer_state.held_htlc_releases.len() < MAX_HELD_HTLC_RELEASES_PER_PEER {
+					log_trace!(logger, "Waiting for {} to come online to release its held HTLC", log_pubkey!(recipient_node_id));
+					peer_state.held_htlc_releases.extend(release.take());
+				} else {
+					log_trace!(logger, "Dropping held_htlc_available message as too many are pending for {}", log_pubkey!(recipient_node_id));
+					return;
+				}
+			}
+		}
+
+		if let Some(release) = release {
+			self.pending_async_payments_messages.lock().unwrap().push(release);
+		}
+	}
+
+	fn release_held_htlc(&self, message: ReleaseHeldHtlc) {
+		let _persistence_guard = PersistenceNotifierGuard::optionally_notify(self, || {
+			let payment_release_secret = message.payment_release_secret;
+			let mut released_htlcs = Vec::new();
+			{
+				let mut held_htlcs = self.pending_held_htlcs.lock().unwrap();
+				let released_ids = held_htlcs.htlcs.iter()
+					.filter(|(_, htlc)| htlc.forward_info.hold_htlc == Some(payment_release_secret))
+					.map(|(intercept_id, _)| *intercept_id)
+					.collect::<Vec<_>>();
+				for intercept_id in released_ids {
+					released_htlcs.extend(held_htlcs.htlcs.remove(&intercept_id));
+				}
+
+				if released_htlcs.is_empty() {
+					// The HTLC may not have been irrevocably committed to yet, so remember the secret
+					// for a bit in case it shows up.
+					if held_htlcs.early_releases.len() < MAX_EARLY_HELD_HTLC_RELEASES {
+						held_htlcs.early_releases
+							.insert(payment_release_secret, EARLY_HELD_HTLC_RELEASE_TIMEOUT_TICKS);
+					}
+					return NotifyOption::SkipPersistNoEvents;
+				}
+			}
+
+			for htlc in released_htlcs {
+				let PendingAddHTLCInfo {
+					mut forward_info, prev_short_channel_id, prev_htlc_id, prev_channel_id,
+					prev_funding_outpoint, prev_user_channel_id,
+				} = htlc;
+				let logger = WithContext::from(&self.logger, None, Some(prev_channel_id));
+				log_trace!(logger, "Releasing held HTLC with payment hash {}", &forward_info.payment_hash);
+				forward_info.hold_htlc = None;
+				self.forward_htlcs(&mut [(
+					prev_short_channel_id, prev_funding_outpoint, prev_channel_id, prev_user_channel_id,
+					vec![(forward_info, prev_htlc_id)]
+				)]);
+			}
+			NotifyOption::DoPersist
+		});
+	}
+
+	fn release_pending_messages(&self) -> Vec<PendingOnionMessage<AsyncPaymentsMessage>> {
+		core::mem::take(&mut self.pending_async_payments_messages.lock().unwrap())
+	}
+}
+
+impl<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>
+DNSResolverMessageHandler for ChannelManager<M, T, ES, NS, SP, F, R, L>
+where
+	M::Target: chain::Watch<<SP::Target as SignerProvider>::EcdsaSigner>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+{
+	fn handle_dnssec_query(&self, _message: DNSSECQuery) -> Option<DNSResolverMessage> {
+		// We don't resolve names for others.
+		None
+	}
+
+	fn handle_dnssec_proof(&self, message: DNSSECProof, path_id: Option<[u8; 32]>) {
+		let path_id = match path_id {
+			Some(path_id) => path_id,
+			None => {
+				log_trace!(self.logger, "Ignoring DNSSEC proof without a path_id");
+				return;
+			},
+		};
+
+		let (payments, offer) = match self.hrn_resolver.handle_dnssec_proof_for_offer(message, path_id) {
+			Some(resolution) => resolution,
+			None => {
+				log_trace!(self.logger, "Ignoring unexpected or invalid DNSSEC proof");
+				return;
+			},
+		};
+
+		for (name, payment_id) in payments {
+			let amount_msats = match self.pending_outbound_payments.amount_for_awaiting_offer(payment_id) {
+				Ok(amount_msats) => amount_msats,
+				Err(()) => {
+					log_trace!(self.logger, "Ignoring offer resolved from {} for payment {} no longer awaiting it", name, payment_id);
+					continue;
+				},
+			};
+			log_trace!(self.logger, "Resolved {} to offer {} for payment {}", name, offer, payment_id);
+			if let Err(e) = self.pay_for_resolved_offer(&offer, amount_msats, payment_id) {
+				log_trace!(self.logger, "Failed requesting an invoice for the offer resolved from {}: {:?}", name, e);
+				self.abandon_payment(payment_id);
+			}
+		}
+	}
+
+	fn release_pending_messages(&self) -> Vec<PendingOnionMessage<DNSResolverMessage>> {
+		core::mem::take(&mut self.pending_dns_onion_messages.lock().unwrap())
+	}
+}
+
 /// Fetches the set of [`NodeFeatures`] flags that are provided by or required by
 /// [`ChannelManager`].
 pub(crate) fn provided_node_features(config: &UserConfig) -> NodeFeatures {
@@ -9336,8 +13563,20 @@ pub fn provided_init_features(config: &UserConfig) -> InitFeatures {
 	features.set_channel_type_optional();
 	features.set_scid_privacy_optional();
 	features.set_zero_conf_optional();
+	features.set_quiescence_optional();
+	#[cfg(dual_funding)]
+	features.set_dual_fund_optional();
 	if config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx {
 		features.set_anchors_zero_fee_htlc_tx_optional();
+		if config.channel_handshake_config.negotiate_zero_fee_commitments {
+			features.set_zero_fee_commitments_optional();
+		}
+	}
+	if config.enable_peer_storage {
+		features.set_provide_storage_optional();
+	}
+	if config.accept_trampoline_forwards {
+		features.set_trampoline_routing_optional();
 	}
 	features
 }
@@ -9483,11 +13722,18 @@ impl_writeable_tlv_based!(BlindedForward, {
 	(0, inbound_blinding_point, required),
 });
 
+impl_writeable_tlv_based!(TrampolineForward, {
+	(0, amt_to_forward_msat, required),
+	(2, hops, required_vec),
+	(4, session_priv, required),
+});
+
 impl_writeable_tlv_based_enum!(PendingHTLCRouting,
 	(0, Forward) => {
 		(0, onion_packet, required),
 		(1, blinded, option),
 		(2, short_channel_id, required),
+		(3, trampoline_forward, option),
 	},
 	(1, Receive) => {
 		(0, payment_data, required),
@@ -9503,6 +13749,19 @@ impl_writeable_tlv_based_enum!(PendingHTLCRouting,
 		(3, payment_metadata, option),
 		(4, payment_data, option), // Added in 0.0.116
 		(5, custom_tlvs, optional_vec),
+		(7, requires_blinded_error, (default_value, false)),
+	},
+	(3, TrampolineForward) => {
+		(0, onion_packet, (required: LengthReadable)),
+		(2, node_id, required),
+		(4, incoming_cltv_expiry, required),
+	},
+	(4, ReceiveAmp) => {
+		(0, payment_data, required),
+		(2, amp_record, required),
+		(4, incoming_cltv_expiry, required),
+		(5, payment_metadata, option),
+		(7, custom_tlvs, optional_vec),
 	},
 ;);
 
@@ -9514,6 +13773,8 @@ impl_writeable_tlv_based!(PendingHTLCInfo, {
 	(8, outgoing_cltv_value, required),
 	(9, incoming_amt_msat, option),
 	(10, skimmed_fee_msat, option),
+	(11, hold_htlc, option),
+	(13, incoming_endorsed, option),
 });
 
 
@@ -9602,13 +13863,16 @@ impl_writeable_tlv_based!(HTLCPreviousHopData, {
 	(4, htlc_id, required),
 	(6, incoming_packet_shared_secret, required),
 	(7, user_channel_id, option),
+	(9, channel_id, (default_value, OutPoint::to_channel_id(&outpoint.0.unwrap()))),
+	(11, trampoline_forward, option),
 });
 
 impl Writeable for ClaimableHTLC {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
-		let (payment_data, keysend_preimage) = match &self.onion_payload {
-			OnionPayload::Invoice { _legacy_hop_data } => (_legacy_hop_data.as_ref(), None),
-			OnionPayload::Spontaneous(preimage) => (None, Some(preimage)),
+		let (payment_data, keysend_preimage, amp_record, amp_payment_hash) = match &self.onion_payload {
+			OnionPayload::Invoice { _legacy_hop_data } => (_legacy_hop_data.as_ref(), None, None, None),
+			OnionPayload::Spontaneous(preimage) => (None, Some(preimage), None, None),
+			OnionPayload::Amp { amp_record, payment_hash } => (None, None, Some(amp_record), Some(payment_hash)),
 		};
 		write_tlv_fields!(writer, {
 			(0, self.prev_hop, required),
@@ -9620,6 +13884,8 @@ impl Writeable for ClaimableHTLC {
 			(6, self.cltv_expiry, required),
 			(8, keysend_preimage, option),
 			(10, self.counterparty_skimmed_fee_msat, option),
+			(12, amp_record, option),
+			(14, amp_payment_hash, option),
 		});
 		Ok(())
 	}
@@ -9637,11 +13903,20 @@ impl Readable for ClaimableHTLC {
 			(6, cltv_expiry, required),
 			(8, keysend_preimage, option),
 			(10, counterparty_skimmed_fee_msat, option),
+			(12, amp_record, option),
+			(14, amp_payment_hash, option),
 		});
 		let payment_data: Option<msgs::FinalOnionHopData> = payment_data_opt;
 		let value = value_ser.0.unwrap();
-		let onion_payload = match keysend_preimage {
-			Some(p) => {
+		let onion_payload = match (keysend_preimage, amp_record, amp_payment_hash) {
+			(None, Some(amp_record), Some(payment_hash)) => {
+				if total_msat.is_none() {
+					return Err(DecodeError::InvalidValue)
+				}
+				OnionPayload::Amp { amp_record, payment_hash }
+			},
+			(_, Some(_), _) | (_, _, Some(_)) => return Err(DecodeError::InvalidValue),
+			(Some(p), None, None) => {
 				if payment_data.is_some() {
 					return Err(DecodeError::InvalidValue)
 				}
@@ -9650,7 +13925,7 @@ impl Readable for ClaimableHTLC {
 				}
 				OnionPayload::Spontaneous(p)
 			},
-			None => {
+			(None, None, None) => {
 				if total_msat.is_none() {
 					if payment_data.is_none() {
 						return Err(DecodeError::InvalidValue)
@@ -9753,6 +14028,7 @@ impl_writeable_tlv_based!(PendingAddHTLCInfo, {
 	(2, prev_short_channel_id, required),
 	(4, prev_htlc_id, required),
 	(6, prev_funding_outpoint, required),
+	(7, prev_channel_id, (default_value, OutPoint::to_channel_id(&prev_funding_outpoint.0.unwrap()))),
 });
 
 impl Writeable for HTLCForwardInfo {
@@ -9861,7 +14137,7 @@ where
 				}
 
 				number_of_funded_channels += peer_state.channel_by_id.iter().filter(
-					|(_, phase)| if let ChannelPhase::Funded(chan) = phase { chan.context.is_funding_broadcast() } else { false }
+					|(_, phase)| if let ChannelPhase::Funded(chan) = phase { chan.context.is_persistable() } else { false }
 				).count();
 			}
 
@@ -9872,7 +14148,7 @@ where
 				let peer_state = &mut *peer_state_lock;
 				for channel in peer_state.channel_by_id.iter().filter_map(
 					|(_, phase)| if let ChannelPhase::Funded(channel) = phase {
-						if channel.context.is_funding_broadcast() { Some(channel) } else { None }
+						if channel.context.is_persistable() { Some(channel) } else { None }
 					} else { None }
 				) {
 					channel.write(writer)?;
@@ -9895,6 +14171,7 @@ where
 		let per_peer_state = self.per_peer_state.write().unwrap();
 
 		let pending_inbound_payments = self.pending_inbound_payments.lock().unwrap();
+		let our_hold_invoices = self.hold_invoices.lock().unwrap();
 		let claimable_payments = self.claimable_payments.lock().unwrap();
 		let pending_outbound_payments = self.pending_outbound_payments.pending_outbound_payments.lock().unwrap();
 
@@ -9912,6 +14189,7 @@ where
 		}
 
 		let mut monitor_update_blocked_actions_per_peer = None;
+		let mut peer_storage_dir = None;
 		let mut peer_states = Vec::new();
 		for (_, peer_state_mutex) in per_peer_state.iter() {
 			// Because we're holding the owning `per_peer_state` write lock here there's no chance
@@ -9934,6 +14212,11 @@ where
 						.get_or_insert_with(Vec::new)
 						.push((*peer_pubkey, &peer_state.monitor_update_blocked_actions));
 				}
+				if !peer_state.peer_storage.is_empty() {
+					peer_storage_dir
+						.get_or_insert_with(Vec::new)
+						.push((*peer_pubkey, &peer_state.peer_storage));
+				}
 			}
 		}
 
@@ -9988,6 +14271,7 @@ where
 						session_priv.write(writer)?;
 					}
 				}
+				PendingOutboundPayment::AwaitingOffer { .. } => {},
 				PendingOutboundPayment::AwaitingInvoice { .. } => {},
 				PendingOutboundPayment::InvoiceReceived { .. } => {},
 				PendingOutboundPayment::Fulfilled { .. } => {},
@@ -10013,6 +14297,39 @@ where
 			pending_intercepted_htlcs = Some(our_pending_intercepts);
 		}
 
+		let held_htlcs = self.pending_held_htlcs.lock().unwrap();
+		let pending_held_htlcs = if held_htlcs.htlcs.is_empty() { None } else { Some(&held_htlcs.htlcs) };
+
+		let our_outbound_recurrences = self.outbound_recurrences.lock().unwrap();
+		let outbound_recurrences =
+			if our_outbound_recurrences.is_empty() { None } else { Some(&*our_outbound_recurrences) };
+		let our_inbound_recurrences = self.inbound_recurrences.lock().unwrap();
+		let inbound_recurrences =
+			if our_inbound_recurrences.is_empty() { None } else { Some(&*our_inbound_recurrences) };
+		let our_invoiced_recurrences = self.invoiced_recurrences.lock().unwrap();
+		let invoiced_recurrences =
+			if our_invoiced_recurrences.is_empty() { None } else { Some(&*our_invoiced_recurrences) };
+
+		let our_served_static_invoices = self.served_static_invoices.lock().unwrap();
+		let served_static_invoices: Option<Vec<(PublicKey, Vec<u8>)>> =
+			if our_served_static_invoices.is_empty() { None } else {
+				Some(our_served_static_invoices.iter().map(|(key, invoice)| (*key, invoice.encode())).collect())
+			};
+
+		let hold_invoices =
+			if our_hold_invoices.is_empty() { None } else { Some(&*our_hold_invoices) };
+
+		let our_htlc_reputation = self.htlc_reputation.lock().unwrap();
+		let htlc_reputation =
+			if our_htlc_reputation.is_empty() { None } else { Some(&*our_htlc_reputation) };
+
+		let our_pending_rebalances = self.pending_rebalances.lock().unwrap();
+		let pending_rebalances =
+			if our_pending_rebalances.is_empty() { None } else { Some(&*our_pending_rebalances) };
+
+		let our_closed_channels = self.closed_channels.lock().unwrap();
+		let closed_channels: Vec<&ClosedChannelDetails> = our_closed_channels.iter().collect();
+
 		let mut pending_claiming_payments = Some(&claimable_payments.pending_claiming_payments);
 		if pending_claiming_payments.as_ref().unwrap().is_empty() {
 			// LDK versions prior to 0.0.113 do not know how to read the pending claimed payments
@@ -10043,6 +14360,16 @@ where
 			(10, in_flight_monitor_updates, option),
 			(11, self.probing_cookie_secret, required),
 			(13, htlc_onion_fields, optional_vec),
+			(15, peer_storage_dir, option),
+			(17, pending_held_htlcs, option),
+			(19, outbound_recurrences, option),
+			(21, inbound_recurrences, option),
+			(23, hold_invoices, option),
+			(25, htlc_reputation, option),
+			(27, pending_rebalances, option),
+			(29, closed_channels, optional_vec),
+			(31, invoiced_recurrences, option),
+			(33, served_static_invoices, option),
 		});
 
 		Ok(())
@@ -10265,6 +14592,7 @@ where
 		let mut outpoint_to_peer = HashMap::with_capacity(cmp::min(channel_count as usize, 128));
 		let mut short_to_chan_info = HashMap::with_capacity(cmp::min(channel_count as usize, 128));
 		let mut channel_closures = VecDeque::new();
+		let mut closed_channels_on_read = Vec::new();
 		let mut close_background_events = Vec::new();
 		for _ in 0..channel_count {
 			let mut channel: Channel<SP> = Channel::read(reader, (
@@ -10307,6 +14635,8 @@ where
 						});
 					}
 					failed_htlcs.append(&mut shutdown_result.dropped_outbound_htlcs);
+					closed_channels_on_read.extend(ClosedChannelDetails::from_channel_context(
+						&channel.context, ClosureReason::OutdatedChannelManager, best_block_height));
 					channel_closures.push_back((events::Event::ChannelClosed {
 						channel_id: channel.context.channel_id(),
 						user_channel_id: channel.context.get_user_id(),
@@ -10426,6 +14756,9 @@ where
 				monitor_update_blocked_actions: BTreeMap::new(),
 				actions_blocking_raa_monitor_updates: BTreeMap::new(),
 				is_connected: false,
+				peer_storage: Vec::new(),
+				sent_peer_storage: None,
+				held_htlc_releases: Vec::new(),
 			}
 		};
 
@@ -10500,6 +14833,16 @@ where
 		let mut monitor_update_blocked_actions_per_peer: Option<Vec<(_, BTreeMap<_, Vec<_>>)>> = Some(Vec::new());
 		let mut events_override = None;
 		let mut in_flight_monitor_updates: Option<HashMap<(PublicKey, OutPoint), Vec<ChannelMonitorUpdate>>> = None;
+		let mut peer_storage_dir: Option<Vec<(PublicKey, Vec<u8>)>> = None;
+		let mut pending_held_htlcs: Option<HashMap<InterceptId, PendingAddHTLCInfo>> = None;
+		let mut outbound_recurrences: Option<HashMap<PublicKey, OutboundRecurrence>> = None;
+		let mut inbound_recurrences: Option<HashMap<PublicKey, InboundRecurrence>> = None;
+		let mut invoiced_recurrences: Option<HashMap<PaymentHash, InvoicedRecurrence>> = None;
+		let mut served_static_invoice_bytes: Option<Vec<(PublicKey, Vec<u8>)>> = None;
+		let mut hold_invoices: Option<HashMap<PaymentHash, HoldInvoice>> = None;
+		let mut htlc_reputation: Option<HTLCReputationTracker> = None;
+		let mut pending_rebalances: Option<HashMap<PaymentId, PendingRebalance>> = None;
+		let mut archived_closed_channels: Option<Vec<ClosedChannelDetails>> = None;
 		read_tlv_fields!(reader, {
 			(1, pending_outbound_payments_no_retry, option),
 			(2, pending_intercepted_htlcs, option),
@@ -10513,6 +14856,16 @@ where
 			(10, in_flight_monitor_updates, option),
 			(11, probing_cookie_secret, option),
 			(13, claimable_htlc_onion_fields, optional_vec),
+			(15, peer_storage_dir, option),
+			(17, pending_held_htlcs, option),
+			(19, outbound_recurrences, option),
+			(21, inbound_recurrences, option),
+			(23, hold_invoices, option),
+			(25, htlc_reputation, option),
+			(27, pending_rebalances, option),
+			(29, archived_closed_channels, optional_vec),
+			(31, invoiced_recurrences, option),
+			(33, served_static_invoice_bytes, option),
 		});
 		if fake_scid_rand_bytes.is_none() {
 			fake_scid_rand_bytes = Some(args.entropy_source.get_secure_random_bytes());
@@ -10707,11 +15060,14 @@ where
 										payment_metadata: None, // only used for retries, and we'll never retry on startup
 										keysend_preimage: None, // only used for retries, and we'll never retry on startup
 										custom_tlvs: Vec::new(), // only used for retries, and we'll never retry on startup
+										trampoline_packet: None, // only used for retries, and we'll never retry on startup
+										amp: None,
 										pending_amt_msat: path_amt,
 										pending_fee_msat: Some(path_fee),
 										total_msat: path_amt,
 										starting_block_height: best_block_height,
 										remaining_max_total_routing_fee_msat: None, // only used for retries, and we'll never retry on startup
+										bolt12_invoice: None,
 									});
 									log_info!(logger, "Added a pending payment for {} msat with payment hash {} for path with session priv {}",
 										path_amt, &htlc.payment_hash,  log_bytes!(session_priv_bytes));
@@ -10877,6 +15233,8 @@ where
 					},
 					OnionPayload::Spontaneous(payment_preimage) =>
 						events::PaymentPurpose::SpontaneousPayment(*payment_preimage),
+					// AMP payments were added long after purposes started being written.
+					OnionPayload::Amp { .. } => return Err(DecodeError::InvalidValue),
 				};
 				claimable_payments.insert(payment_hash, ClaimablePayment {
 					purpose, htlcs, onion_fields: None,
@@ -10938,8 +15296,32 @@ where
 
 		let bounded_fee_estimator = LowerBoundedFeeEstimator::new(args.fee_estimator);
 
+		// Each part of an AMP payment is claimed with the preimage of its own payment hash, which is
+		// what the monitors store it under, while the payment itself is tracked under its set id.
+		let mut amp_set_ids = HashMap::new();
+		for (set_id, payment) in claimable_payments.iter() {
+			for htlc in payment.htlcs.iter() {
+				if let OnionPayload::Amp { payment_hash, .. } = &htlc.onion_payload {
+					amp_set_ids.insert(*payment_hash, *set_id);
+				}
+			}
+		}
+
 		for (_, monitor) in args.channel_monitors.iter() {
-			for (payment_hash, payment_preimage) in monitor.get_stored_preimages() {
+			for (stored_payment_hash, stored_payment_preimage) in monitor.get_stored_preimages() {
+				// A payment is only claimed once it's complete, at which point the root seed of an
+				// AMP payment is known and stored in its purpose.
+				let (payment_hash, payment_preimage) = match amp_set_ids.get(&stored_payment_hash) {
+					Some(set_id) => match claimable_payments.get(set_id).map(|payment| &payment.purpose) {
+						Some(events::PaymentPurpose::SpontaneousPayment(root_seed))
+							if Sha256::hash(&root_seed.0).to_byte_array() == set_id.0 => (*set_id, *root_seed),
+						_ => {
+							debug_assert!(false, "AMP payments must be complete before any part is claimed");
+							continue;
+						},
+					},
+					None => (stored_payment_hash, stored_payment_preimage),
+				};
 				if let Some(payment) = claimable_payments.remove(&payment_hash) {
 					log_info!(args.logger, "Re-claiming HTLCs with payment hash {} as we've released the preimage to a ChannelMonitor!", &payment_hash);
 					let mut claimable_amt_msat = 0;
@@ -10968,18 +15350,23 @@ where
 						// this channel as well. On the flip side, there's no harm in restarting
 						// without the new monitor persisted - we'll end up right back here on
 						// restart.
-						let previous_channel_id = claimable_htlc.prev_hop.outpoint.to_channel_id();
+						let (htlc_payment_hash, htlc_preimage) = match &claimable_htlc.onion_payload {
+							OnionPayload::Amp { amp_record, payment_hash: htlc_payment_hash } => (*htlc_payment_hash,
+								onion_utils::amp_child_preimage(&payment_preimage.0, amp_record.child_index)),
+							_ => (payment_hash, payment_preimage),
+						};
+						let previous_channel_id = claimable_htlc.prev_hop.channel_id;
 						if let Some(peer_node_id) = outpoint_to_peer.get(&claimable_htlc.prev_hop.outpoint) {
 							let peer_state_mutex = per_peer_state.get(peer_node_id).unwrap();
 							let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 							let peer_state = &mut *peer_state_lock;
 							if let Some(ChannelPhase::Funded(channel)) = peer_state.channel_by_id.get_mut(&previous_channel_id) {
 								let logger = WithChannelContext::from(&args.logger, &channel.context);
-								channel.claim_htlc_while_disconnected_dropping_mon_update(claimable_htlc.prev_hop.htlc_id, payment_preimage, &&logger);
+								channel.claim_htlc_while_disconnected_dropping_mon_update(claimable_htlc.prev_hop.htlc_id, htlc_preimage, &&logger);
 							}
 						}
 						if let Some(previous_hop_monitor) = args.channel_monitors.get(&claimable_htlc.prev_hop.outpoint) {
-							previous_hop_monitor.provide_payment_preimage(&payment_hash, &payment_preimage, &args.tx_broadcaster, &bounded_fee_estimator, &args.logger);
+							previous_hop_monitor.provide_payment_preimage(&htlc_payment_hash, &htlc_preimage, &args.tx_broadcaster, &bounded_fee_estimator, &args.logger);
 						}
 					}
 					pending_events_read.push_back((events::Event::PaymentClaimed {
@@ -10994,6 +15381,18 @@ where
 			}
 		}
 
+		for (node_id, peer_storage) in peer_storage_dir.unwrap_or_default() {
+			if let Some(peer_state) = per_peer_state.get(&node_id) {
+				peer_state.lock().unwrap().peer_storage = peer_storage;
+			}
+		}
+
+		let mut served_static_invoices = HashMap::new();
+		for (offer_signing_pubkey, bytes) in served_static_invoice_bytes.unwrap_or_default() {
+			let invoice = StaticInvoice::try_from(bytes).map_err(|_| DecodeError::InvalidValue)?;
+			served_static_invoices.insert(offer_signing_pubkey, invoice);
+		}
+
 		for (node_id, monitor_update_blocked_actions) in monitor_update_blocked_actions_per_peer.unwrap() {
 			if let Some(peer_state) = per_peer_state.get(&node_id) {
 				for (channel_id, actions) in monitor_update_blocked_actions.iter() {
@@ -11030,6 +15429,12 @@ where
 			}
 		}
 
+		let mut closed_channels: VecDeque<ClosedChannelDetails> =
+			archived_closed_channels.unwrap_or_else(Vec::new).into();
+		for closed_channel in closed_channels_on_read {
+			push_closed_channel(&mut closed_channels, closed_channel);
+		}
+
 		let channel_manager = ChannelManager {
 			chain_hash,
 			fee_estimator: bounded_fee_estimator,
@@ -11043,6 +15448,10 @@ where
 			pending_inbound_payments: Mutex::new(pending_inbound_payments),
 			pending_outbound_payments: pending_outbounds,
 			pending_intercepted_htlcs: Mutex::new(pending_intercepted_htlcs.unwrap()),
+			pending_held_htlcs: Mutex::new(HeldHtlcs {
+				htlcs: pending_held_htlcs.unwrap_or_else(HashMap::new),
+				early_releases: HashMap::new(),
+			}),
 
 			forward_htlcs: Mutex::new(forward_htlcs),
 			claimable_payments: Mutex::new(ClaimablePayments { claimable_payments, pending_claiming_payments: pending_claiming_payments.unwrap() }),
@@ -11072,6 +15481,21 @@ where
 			funding_batch_states: Mutex::new(BTreeMap::new()),
 
 			pending_offers_messages: Mutex::new(Vec::new()),
+			pending_async_payments_messages: Mutex::new(Vec::new()),
+			pending_dns_onion_messages: Mutex::new(Vec::new()),
+			hrn_resolver: OMNameResolver::new(highest_seen_timestamp),
+			served_static_invoices: Mutex::new(served_static_invoices),
+			awaiting_static_invoices: Mutex::new(HashMap::new()),
+			currency_conversion: RwLock::new(None),
+			outbound_recurrences: Mutex::new(outbound_recurrences.unwrap_or_else(HashMap::new)),
+			inbound_recurrences: Mutex::new(inbound_recurrences.unwrap_or_else(HashMap::new)),
+			invoiced_recurrences: Mutex::new(invoiced_recurrences.unwrap_or_else(HashMap::new)),
+			hold_invoices: Mutex::new(hold_invoices.unwrap_or_else(HashMap::new)),
+			htlc_reputation: Mutex::new(htlc_reputation.unwrap_or_else(HTLCReputationTracker::new)),
+			forwarding_usage: Mutex::new(ForwardingUsage::new()),
+			dynamic_fees: Mutex::new(DynamicFeeManager::new()),
+			pending_rebalances: Mutex::new(pending_rebalances.unwrap_or_else(HashMap::new)),
+			closed_channels: Mutex::new(closed_channels),
 
 			entropy_source: args.entropy_source,
 			node_signer: args.node_signer,
@@ -11093,7 +15517,7 @@ where
 			// don't remember in the `ChannelMonitor` where we got a preimage from, but if the
 			// channel is closed we just assume that it probably came from an on-chain claim.
 			channel_manager.claim_funds_internal(source, preimage, Some(downstream_value),
-				downstream_closed, true, downstream_node_id, downstream_funding);
+				downstream_closed, true, downstream_node_id, downstream_funding, downstream_funding.to_channel_id());
 		}
 
 		//TODO: Broadcast channel update for closed channels, but only after we've made a
@@ -11121,7 +15545,7 @@ mod tests {
 	use crate::util::errors::APIError;
 	use crate::util::ser::Writeable;
 	use crate::util::test_utils;
-	use crate::util::config::{ChannelConfig, ChannelConfigUpdate};
+	use crate::util::config::{ChannelConfig, ChannelConfigUpdate, DynamicFeeConfig};
 	use crate::sign::EntropySource;
 
 	#[test]
@@ -12324,6 +16748,60 @@ mod tests {
 		assert_eq!(events.len(), 0);
 	}
 
+	#[test]
+	fn test_dynamic_fees() {
+		let chanmon_cfg = create_chanmon_cfgs(2);
+		let node_cfg = create_node_cfgs(2, &chanmon_cfg);
+		let mut user_config = test_default_channel_config();
+		user_config.dynamic_fee_config = DynamicFeeConfig {
+			enabled: true,
+			min_fee_proportional_millionths: 100,
+			max_fee_proportional_millionths: 1_100,
+			min_fee_base_msat: 0,
+			max_fee_base_msat: 1_000,
+			volume_fee_increase_percent: 0,
+			update_interval_ticks: 2,
+			min_change_percent: 10,
+		};
+		let node_chanmgr = create_node_chanmgrs(2, &node_cfg, &[Some(user_config), Some(user_config)]);
+		let nodes = create_network(2, &node_cfg, &node_chanmgr);
+		let _ = create_announced_chan_between_nodes(&nodes, 0, 1);
+
+		for node in nodes.iter() {
+			node.node.timer_tick_occurred();
+			assert!(node.node.get_and_clear_pending_msg_events().is_empty());
+		}
+
+		// Once the update interval ends, the funder, which has nearly all of the channel's liquidity,
+		// sets fees close to the minimum and its counterparty close to the maximum.
+		for node in nodes.iter() {
+			node.node.timer_tick_occurred();
+			let channel = &node.node.list_channels()[0];
+			let liquidity_share = 1_000 * channel.balance_msat / (channel.channel_value_satoshis * 1000);
+			let config = channel.config.unwrap();
+			assert_eq!(config.forwarding_fee_base_msat as u64, 1_000 - liquidity_share);
+			assert_eq!(config.forwarding_fee_proportional_millionths as u64, 1_100 - liquidity_share);
+			let events = node.node.get_and_clear_pending_msg_events();
+			assert_eq!(events.len(), 1);
+			match &events[0] {
+				MessageSendEvent::BroadcastChannelUpdate { msg } => {
+					assert_eq!(msg.contents.fee_base_msat, config.forwarding_fee_base_msat);
+					assert_eq!(msg.contents.fee_proportional_millionths, config.forwarding_fee_proportional_millionths);
+				},
+				_ => panic!("expected BroadcastChannelUpdate event"),
+			}
+		}
+		assert!(nodes[0].node.list_channels()[0].config.unwrap().forwarding_fee_proportional_millionths < 200);
+		assert!(nodes[1].node.list_channels()[0].config.unwrap().forwarding_fee_proportional_millionths > 1_000);
+
+		// Fees aren't updated again while the channels' liquidity doesn't change.
+		for node in nodes.iter() {
+			node.node.timer_tick_occurred();
+			node.node.timer_tick_occurred();
+			assert!(node.node.get_and_clear_pending_msg_events().is_empty());
+		}
+	}
+
 	#[test]
 	fn test_payment_display() {
 		let payment_id = PaymentId([42; 32]);
@@ -0,0 +1,256 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests of paying BIP 353 human-readable names, where the payer resolves the name to an offer by
+//! querying a DNS resolver via onion messages.
+
+use bitcoin::secp256k1::Secp256k1;
+use dnssec_prover::rr::Name;
+use core::convert::TryFrom;
+use core::sync::atomic::Ordering;
+
+use crate::blinded_path::BlindedPath;
+use crate::events::{Event, PaymentPurpose};
+use crate::ln::channelmanager::{PaymentId, RecentPaymentDetails};
+use crate::ln::functional_test_utils::*;
+use crate::ln::outbound_payment::Retry;
+use crate::ln::peer_handler::IgnoringMessageHandler;
+use crate::offers::offer::Offer;
+use crate::onion_message::{DNSResolverMessage, DNSResolverMessageHandler, DNSSECProof, DNSSECQuery, Destination, HumanReadableName, OffersMessage, OffersMessageHandler, OnionMessagePath, ParsedOnionMessageContents, PeeledOnion, PendingOnionMessage, create_onion_message, new_pending_onion_message, peel_onion_message};
+
+use crate::prelude::*;
+
+/// Encodes `message` in an onion message sent by `from` and decodes it at `to`, returning what
+/// `to`'s `OnionMessenger` would pass to its [`DNSResolverMessageHandler`].
+fn pass_dns_resolver_message<'a, 'b, 'c>(
+	from: &Node<'a, 'b, 'c>, to: &Node<'a, 'b, 'c>, message: PendingOnionMessage<DNSResolverMessage>
+) -> (DNSResolverMessage, Option<[u8; 32]>, Option<BlindedPath>) {
+	#[cfg(not(c_bindings))]
+	let PendingOnionMessage { contents, destination, reply_path } = message;
+	#[cfg(c_bindings)]
+	let (contents, destination, reply_path) = message;
+	let secp_ctx = Secp256k1::new();
+	let path = OnionMessagePath { intermediate_nodes: Vec::new(), destination, first_node_addresses: None };
+	let (first_node_id, onion_message, _) = create_onion_message(
+		&from.keys_manager, &from.keys_manager, &secp_ctx, path, contents, reply_path
+	).unwrap();
+	assert_eq!(first_node_id, to.node.get_our_node_id());
+	match peel_onion_message(&onion_message, &secp_ctx, to.keys_manager, to.logger, &IgnoringMessageHandler {}) {
+		Ok(PeeledOnion::Receive(ParsedOnionMessageContents::DNSResolver(message), path_id, reply_path)) =>
+			(message, path_id, reply_path),
+		_ => panic!("Unexpected onion message"),
+	}
+}
+
+/// Passes the single [`DNSSECQuery`] queued by `payer` to the stand-in resolver `resolver`, which
+/// responds with a [`DNSSECProof`] containing `proof` that is handled by `payer`.
+fn resolve_with_proof<'a, 'b, 'c>(
+	payer: &Node<'a, 'b, 'c>, resolver: &Node<'a, 'b, 'c>, expected_name: &Name, proof: Vec<u8>
+) {
+	let mut messages = DNSResolverMessageHandler::release_pending_messages(payer.node);
+	assert_eq!(messages.len(), 1);
+	let reply_path = match pass_dns_resolver_message(payer, resolver, messages.pop().unwrap()) {
+		(DNSResolverMessage::DNSSECQuery(DNSSECQuery(name)), None, Some(reply_path)) => {
+			assert_eq!(name, *expected_name);
+			reply_path
+		},
+		_ => panic!("Unexpected message"),
+	};
+
+	// A real resolver's `ChannelManager` doesn't answer queries itself.
+	assert!(resolver.node.handle_dnssec_query(DNSSECQuery(expected_name.clone())).is_none());
+
+	let response = new_pending_onion_message(
+		DNSResolverMessage::DNSSECProof(DNSSECProof { name: expected_name.clone(), proof }),
+		Destination::BlindedPath(reply_path),
+		None,
+	);
+	match pass_dns_resolver_message(resolver, payer, response) {
+		(DNSResolverMessage::DNSSECProof(message), path_id @ Some(_), None) => {
+			payer.node.handle_dnssec_proof(message, path_id);
+		},
+		_ => panic!("Unexpected message"),
+	}
+}
+
+/// Encodes a proof consisting of a single `TXT` record for `name` containing a BIP 21 URI with
+/// `offer`, in the DNS wire format used by [`DNSSECProof`].
+fn txt_record_proof(name: &str, offer: &Offer) -> Vec<u8> {
+	let mut proof = Vec::new();
+	for label in name.trim_end_matches('.').split('.') {
+		proof.push(label.len() as u8);
+		proof.extend_from_slice(label.as_bytes());
+	}
+	proof.push(0);
+	proof.extend_from_slice(&16u16.to_be_bytes()); // TXT
+	proof.extend_from_slice(&1u16.to_be_bytes()); // IN
+	proof.extend_from_slice(&3600u32.to_be_bytes());
+
+	let uri = format!("bitcoin:?lno={}", offer);
+	let mut rdata = Vec::new();
+	for chunk in uri.as_bytes().chunks(255) {
+		rdata.push(chunk.len() as u8);
+		rdata.extend_from_slice(chunk);
+	}
+	proof.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+	proof.extend_from_slice(&rdata);
+	proof
+}
+
+#[test]
+fn pays_human_readable_name() {
+	// nodes[0] pays a human-readable name resolved by the stand-in resolver nodes[1] to an offer of
+	// nodes[1]. Records can't be signed back to the DNS root trust anchor in tests, so only the
+	// proof's validation is skipped.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	nodes[0].node.hrn_resolver.skip_proof_validation.store(true, Ordering::Release);
+
+	let offer = nodes[1].node.create_offer_builder("coffee".to_string()).unwrap().build().unwrap();
+	let name = HumanReadableName::from_encoded("₿alice@example.com").unwrap();
+	let dns_name = "alice.user._bitcoin-payment.example.com.";
+	let payment_id = PaymentId([42; 32]);
+	let amount_msats = 10_000;
+	let resolver = Destination::Node(nodes[1].node.get_our_node_id());
+
+	nodes[0].node.pay_for_offer_from_human_readable_name(
+		name, amount_msats, payment_id, Retry::Attempts(0), None, vec![resolver]
+	).unwrap();
+	resolve_with_proof(
+		&nodes[0], &nodes[1], &Name::try_from(dns_name).unwrap(), txt_record_proof(dns_name, &offer)
+	);
+	assert_eq!(
+		nodes[0].node.list_recent_payments(),
+		vec![RecentPaymentDetails::AwaitingInvoice { payment_id }]
+	);
+
+	// The invoice request is sent for the resolved offer.
+	let mut messages = OffersMessageHandler::release_pending_messages(nodes[0].node);
+	assert!(!messages.is_empty());
+	#[cfg(not(c_bindings))]
+	let PendingOnionMessage { contents, .. } = messages.remove(0);
+	#[cfg(c_bindings)]
+	let (contents, _, _) = messages.remove(0);
+	let invoice = match nodes[1].node.handle_message(contents) {
+		Some(OffersMessage::Invoice(invoice)) => invoice,
+		_ => panic!("Expected invoice"),
+	};
+	assert_eq!(invoice.amount_msats(), amount_msats);
+
+	assert!(nodes[0].node.handle_message(OffersMessage::Invoice(invoice.clone())).is_none());
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	do_pass_along_path(
+		&nodes[0], &[&nodes[1]], amount_msats, invoice.payment_hash(), None, events.remove(0),
+		true, false, None, false
+	);
+
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	let payment_preimage = match &events[0] {
+		Event::PaymentClaimable {
+			payment_hash, purpose: PaymentPurpose::InvoicePayment { payment_preimage: Some(payment_preimage), .. }, ..
+		} => {
+			assert_eq!(*payment_hash, invoice.payment_hash());
+			*payment_preimage
+		},
+		_ => panic!("Unexpected event"),
+	};
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+	assert!(nodes[0].node.list_recent_payments().iter().all(|payment| match payment {
+		RecentPaymentDetails::Fulfilled { .. } => true,
+		_ => false,
+	}));
+}
+
+#[test]
+fn ignores_invalid_dnssec_proof() {
+	// nodes[0] pays a human-readable name using nodes[1] as its DNS resolver, which responds with
+	// a proof that doesn't validate. The payment stays pending until it times out.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let name = HumanReadableName::from_encoded("₿alice@example.com").unwrap();
+	let dns_name = Name::try_from("alice.user._bitcoin-payment.example.com.").unwrap();
+	let payment_id = PaymentId([42; 32]);
+	let resolver = Destination::Node(nodes[1].node.get_our_node_id());
+
+	nodes[0].node.pay_for_offer_from_human_readable_name(
+		name, 10_000, payment_id, Retry::Attempts(0), None, vec![resolver]
+	).unwrap();
+	assert_eq!(
+		nodes[0].node.list_recent_payments(),
+		vec![RecentPaymentDetails::AwaitingInvoice { payment_id }]
+	);
+
+	resolve_with_proof(&nodes[0], &nodes[1], &dns_name, vec![42; 128]);
+	assert!(OffersMessageHandler::release_pending_messages(nodes[0].node).is_empty());
+	assert_eq!(
+		nodes[0].node.list_recent_payments(),
+		vec![RecentPaymentDetails::AwaitingInvoice { payment_id }]
+	);
+
+	nodes[0].node.timer_tick_occurred();
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	nodes[0].node.timer_tick_occurred();
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::InvoiceRequestFailed { payment_id: failed_payment_id } => {
+			assert_eq!(failed_payment_id, payment_id);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	assert!(nodes[0].node.list_recent_payments().is_empty());
+}
+
+#[test]
+fn fails_paying_human_readable_name() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let name = HumanReadableName::from_encoded("alice@example.com").unwrap();
+	let payment_id = PaymentId([42; 32]);
+	let resolver = Destination::Node(nodes[1].node.get_our_node_id());
+
+	// At least one resolver is needed.
+	assert!(nodes[0].node.pay_for_offer_from_human_readable_name(
+		name.clone(), 10_000, payment_id, Retry::Attempts(0), None, vec![]
+	).is_err());
+	assert!(nodes[0].node.list_recent_payments().is_empty());
+
+	nodes[0].node.pay_for_offer_from_human_readable_name(
+		name.clone(), 10_000, payment_id, Retry::Attempts(0), None, vec![resolver.clone()]
+	).unwrap();
+	assert!(nodes[0].node.pay_for_offer_from_human_readable_name(
+		name, 10_000, payment_id, Retry::Attempts(0), None, vec![resolver]
+	).is_err());
+
+	// Abandoning the payment fails it without waiting for the name to be resolved.
+	nodes[0].node.abandon_payment(payment_id);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::InvoiceRequestFailed { payment_id: failed_payment_id } => {
+			assert_eq!(failed_payment_id, payment_id);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	assert!(nodes[0].node.list_recent_payments().is_empty());
+}
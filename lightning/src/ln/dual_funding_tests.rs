@@ -0,0 +1,472 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests of dual-funded channel establishment.
+
+use crate::events::{ClosureReason, Event, MessageSendEvent, MessageSendEventsProvider};
+use crate::ln::msgs::{self, ChannelMessageHandler, ErrorAction};
+use crate::util::errors::APIError;
+use crate::util::ser::Writeable;
+use crate::util::test_utils;
+
+use bitcoin::blockdata::locktime::absolute::LockTime;
+use bitcoin::blockdata::script::ScriptBuf;
+use bitcoin::blockdata::transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut};
+use bitcoin::hashes::Hash;
+use bitcoin::{WPubkeyHash, Witness};
+
+use crate::ln::functional_test_utils::*;
+
+fn funding_input(value_satoshis: u64) -> (TxIn, Transaction) {
+	let prev_tx = Transaction {
+		version: 2,
+		lock_time: LockTime::ZERO,
+		input: Vec::new(),
+		output: vec![TxOut {
+			value: value_satoshis,
+			script_pubkey: ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros()),
+		}],
+	};
+	let txin = TxIn {
+		previous_output: OutPoint { txid: prev_tx.txid(), vout: 0 },
+		script_sig: ScriptBuf::new(),
+		sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+		witness: Witness::new(),
+	};
+	(txin, prev_tx)
+}
+
+/// Delivers interactive transaction construction messages between the two nodes until both have
+/// sent their initial `commitment_signed`, returning them as sent by `initiator` and `acceptor`.
+fn do_interactive_tx_construction<'a, 'b, 'c>(initiator: &Node<'a, 'b, 'c>, acceptor: &Node<'a, 'b, 'c>)
+-> (msgs::CommitmentSigned, msgs::CommitmentSigned) {
+	let mut initiator_commitment_signed = None;
+	let mut acceptor_commitment_signed = None;
+	let mut sender = initiator;
+	let mut receiver = acceptor;
+	while initiator_commitment_signed.is_none() || acceptor_commitment_signed.is_none() {
+		let events = sender.node.get_and_clear_pending_msg_events();
+		assert!(!events.is_empty() || receiver.node.get_and_clear_pending_msg_events().is_empty());
+		for event in events {
+			let receiver_node_id = receiver.node.get_our_node_id();
+			let sender_node_id = sender.node.get_our_node_id();
+			match event {
+				MessageSendEvent::SendTxAddInput { node_id, msg } => {
+					assert_eq!(node_id, receiver_node_id);
+					receiver.node.handle_tx_add_input(&sender_node_id, &msg);
+				},
+				MessageSendEvent::SendTxAddOutput { node_id, msg } => {
+					assert_eq!(node_id, receiver_node_id);
+					receiver.node.handle_tx_add_output(&sender_node_id, &msg);
+				},
+				MessageSendEvent::SendTxComplete { node_id, msg } => {
+					assert_eq!(node_id, receiver_node_id);
+					receiver.node.handle_tx_complete(&sender_node_id, &msg);
+				},
+				MessageSendEvent::SendTxAbort { node_id, msg } => {
+					assert_eq!(node_id, receiver_node_id);
+					receiver.node.handle_tx_abort(&sender_node_id, &msg);
+				},
+				MessageSendEvent::UpdateHTLCs { node_id, updates } => {
+					assert_eq!(node_id, receiver_node_id);
+					assert!(updates.update_add_htlcs.is_empty());
+					if sender_node_id == initiator.node.get_our_node_id() {
+						initiator_commitment_signed = Some(updates.commitment_signed);
+					} else {
+						acceptor_commitment_signed = Some(updates.commitment_signed);
+					}
+				},
+				_ => panic!("Unexpected event {:?}", event),
+			}
+		}
+		core::mem::swap(&mut sender, &mut receiver);
+	}
+	(initiator_commitment_signed.unwrap(), acceptor_commitment_signed.unwrap())
+}
+
+/// Handles the counterparty's initial `commitment_signed` and signs the `inputs` `node` contributed
+/// to the funding transaction, returning the signed transaction.
+fn handle_initial_commitment_signed_and_sign(
+	node: &Node, counterparty: &Node, commitment_signed: &msgs::CommitmentSigned, inputs: &[(TxIn, Transaction)],
+) -> Transaction {
+	node.node.handle_commitment_signed(&counterparty.node.get_our_node_id(), commitment_signed);
+	check_added_monitors!(node, 1);
+	let events = node.node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	let mut tx = match &events[0] {
+		Event::FundingTransactionReadyForSigning { channel_id, counterparty_node_id, unsigned_transaction, .. } => {
+			assert_eq!(*channel_id, commitment_signed.channel_id);
+			assert_eq!(*counterparty_node_id, counterparty.node.get_our_node_id());
+			unsigned_transaction.clone()
+		},
+		_ => panic!("Unexpected event {:?}", events[0]),
+	};
+	for input in tx.input.iter_mut() {
+		if inputs.iter().any(|(txin, _)| txin.previous_output == input.previous_output) {
+			input.witness = Witness::from_slice(&[vec![0; 73], vec![2; 33]]);
+		}
+	}
+	tx
+}
+
+/// Opens a dual-funded channel from `initiator` to `acceptor`, each contributing a single input,
+/// and negotiates its funding transaction, returning the initial `commitment_signed` sent by
+/// `initiator` and `acceptor`.
+fn negotiate_dual_funded_channel<'a, 'b, 'c>(
+	initiator: &Node<'a, 'b, 'c>, acceptor: &Node<'a, 'b, 'c>, initiator_input: (TxIn, Transaction),
+	acceptor_input: (TxIn, Transaction),
+) -> (msgs::CommitmentSigned, msgs::CommitmentSigned) {
+	initiator.node.create_dual_funded_channel(acceptor.node.get_our_node_id(), 100_000,
+		vec![initiator_input], None, 253, 42, None).unwrap();
+	let open_channel = get_event_msg!(initiator, MessageSendEvent::SendOpenChannelV2, acceptor.node.get_our_node_id());
+	acceptor.node.handle_open_channel_v2(&initiator.node.get_our_node_id(), &open_channel);
+	let events = acceptor.node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	assert!(matches!(events[0], Event::OpenDualFundedChannelRequest { .. }));
+	acceptor.node.accept_inbound_dual_funded_channel(&open_channel.temporary_channel_id,
+		&initiator.node.get_our_node_id(), 43, 50_000, vec![acceptor_input], None).unwrap();
+	let accept_channel = get_event_msg!(acceptor, MessageSendEvent::SendAcceptChannelV2, initiator.node.get_our_node_id());
+	initiator.node.handle_accept_channel_v2(&acceptor.node.get_our_node_id(), &accept_channel);
+	do_interactive_tx_construction(initiator, acceptor)
+}
+
+/// Connects `node_a` and `node_b`, returning the `channel_reestablish` sent by each.
+fn connect_and_get_reestablish<'a, 'b, 'c>(node_a: &Node<'a, 'b, 'c>, node_b: &Node<'a, 'b, 'c>)
+-> (msgs::ChannelReestablish, msgs::ChannelReestablish) {
+	node_a.node.peer_connected(&node_b.node.get_our_node_id(), &msgs::Init {
+		features: node_b.node.init_features(), networks: None, remote_network_address: None
+	}, true).unwrap();
+	let mut a_reestablish = get_chan_reestablish_msgs!(node_a, node_b);
+	node_b.node.peer_connected(&node_a.node.get_our_node_id(), &msgs::Init {
+		features: node_a.node.init_features(), networks: None, remote_network_address: None
+	}, false).unwrap();
+	let mut b_reestablish = get_chan_reestablish_msgs!(node_b, node_a);
+	assert_eq!(a_reestablish.len(), 1);
+	assert_eq!(b_reestablish.len(), 1);
+	(a_reestablish.pop().unwrap(), b_reestablish.pop().unwrap())
+}
+
+#[test]
+fn test_dual_funded_channel_open() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let initiator_input = funding_input(150_000);
+	let acceptor_input = funding_input(80_000);
+
+	nodes[0].node.create_dual_funded_channel(nodes[1].node.get_our_node_id(), 100_000,
+		vec![initiator_input.clone()], None, 253, 42, None).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannelV2, nodes[1].node.get_our_node_id());
+	assert_eq!(open_channel.funding_satoshis, 100_000);
+
+	nodes[1].node.handle_open_channel_v2(&nodes[0].node.get_our_node_id(), &open_channel);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match &events[0] {
+		Event::OpenDualFundedChannelRequest { temporary_channel_id, counterparty_funding_satoshis, funding_feerate_sat_per_1000_weight, .. } => {
+			assert_eq!(*temporary_channel_id, open_channel.temporary_channel_id);
+			assert_eq!(*counterparty_funding_satoshis, 100_000);
+			assert_eq!(*funding_feerate_sat_per_1000_weight, 253);
+		},
+		_ => panic!("Unexpected event {:?}", events[0]),
+	}
+	nodes[1].node.accept_inbound_dual_funded_channel(&open_channel.temporary_channel_id,
+		&nodes[0].node.get_our_node_id(), 43, 50_000, vec![acceptor_input.clone()], None).unwrap();
+	let accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannelV2, nodes[0].node.get_our_node_id());
+	assert_eq!(accept_channel.funding_satoshis, 50_000);
+
+	nodes[0].node.handle_accept_channel_v2(&nodes[1].node.get_our_node_id(), &accept_channel);
+	let (initiator_commitment_signed, acceptor_commitment_signed) =
+		do_interactive_tx_construction(&nodes[0], &nodes[1]);
+	let channel_id = initiator_commitment_signed.channel_id;
+	assert_eq!(channel_id, acceptor_commitment_signed.channel_id);
+	assert_ne!(channel_id, open_channel.temporary_channel_id);
+
+	let initiator_signed_tx = handle_initial_commitment_signed_and_sign(&nodes[0], &nodes[1], &acceptor_commitment_signed, &[initiator_input]);
+	let acceptor_signed_tx = handle_initial_commitment_signed_and_sign(&nodes[1], &nodes[0], &initiator_commitment_signed, &[acceptor_input]);
+	assert_eq!(initiator_signed_tx.txid(), acceptor_signed_tx.txid());
+	let funding_outputs = initiator_signed_tx.output.iter()
+		.filter(|output| output.value == 150_000).count();
+	assert_eq!(funding_outputs, 1);
+
+	// The acceptor contributed the lower total input value, so it sends its tx_signatures first.
+	nodes[0].node.funding_transaction_signed(&channel_id, &nodes[1].node.get_our_node_id(), initiator_signed_tx).unwrap();
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	nodes[1].node.funding_transaction_signed(&channel_id, &nodes[0].node.get_our_node_id(), acceptor_signed_tx).unwrap();
+	let acceptor_tx_signatures = get_event_msg!(nodes[1], MessageSendEvent::SendTxSignatures, nodes[0].node.get_our_node_id());
+
+	nodes[0].node.handle_tx_signatures(&nodes[1].node.get_our_node_id(), &acceptor_tx_signatures);
+	let initiator_tx_signatures = get_event_msg!(nodes[0], MessageSendEvent::SendTxSignatures, nodes[1].node.get_our_node_id());
+	expect_channel_pending_event(&nodes[0], &nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_tx_signatures(&nodes[0].node.get_our_node_id(), &initiator_tx_signatures);
+	expect_channel_pending_event(&nodes[1], &nodes[0].node.get_our_node_id());
+
+	let funding_tx = {
+		let initiator_broadcast = nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0);
+		let acceptor_broadcast = nodes[1].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0);
+		assert_eq!(initiator_broadcast.len(), 1);
+		assert_eq!(initiator_broadcast, acceptor_broadcast);
+		initiator_broadcast[0].clone()
+	};
+	assert_eq!(funding_tx.input.len(), 2);
+	assert!(funding_tx.input.iter().all(|input| !input.witness.is_empty()));
+
+	let (channel_ready, _) = create_chan_between_nodes_with_value_confirm(&nodes[0], &nodes[1], &funding_tx);
+	let (announcement, as_update, bs_update) = create_chan_between_nodes_with_value_b(&nodes[0], &nodes[1], &channel_ready);
+	update_nodes_with_chan_announce(&nodes, 0, 1, &announcement, &as_update, &bs_update);
+
+	let channel = &nodes[0].node.list_channels()[0];
+	assert_eq!(channel.channel_id, channel_id);
+	assert_eq!(channel.channel_value_satoshis, 150_000);
+	assert_eq!(channel.user_channel_id, 42);
+	assert_eq!(nodes[1].node.list_channels()[0].user_channel_id, 43);
+
+	// Both sides can pay out of their own contribution.
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 5_000_000);
+}
+
+#[test]
+fn test_dual_funded_channel_open_without_acceptor_contribution() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let initiator_input = funding_input(150_000);
+	nodes[0].node.create_dual_funded_channel(nodes[1].node.get_our_node_id(), 100_000,
+		vec![initiator_input.clone()], None, 253, 42, None).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannelV2, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_open_channel_v2(&nodes[0].node.get_our_node_id(), &open_channel);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	assert!(matches!(events[0], Event::OpenDualFundedChannelRequest { .. }));
+
+	// Dual-funded requests must be accepted through the dual-funded API.
+	assert!(matches!(
+		nodes[1].node.accept_inbound_channel(&open_channel.temporary_channel_id, &nodes[0].node.get_our_node_id(), 43),
+		Err(APIError::APIMisuseError { .. })
+	));
+	nodes[1].node.accept_inbound_dual_funded_channel(&open_channel.temporary_channel_id,
+		&nodes[0].node.get_our_node_id(), 43, 0, Vec::new(), None).unwrap();
+	let accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannelV2, nodes[0].node.get_our_node_id());
+	assert_eq!(accept_channel.funding_satoshis, 0);
+
+	nodes[0].node.handle_accept_channel_v2(&nodes[1].node.get_our_node_id(), &accept_channel);
+	let (initiator_commitment_signed, acceptor_commitment_signed) =
+		do_interactive_tx_construction(&nodes[0], &nodes[1]);
+	let channel_id = initiator_commitment_signed.channel_id;
+
+	// Having contributed no inputs, the acceptor has nothing to sign and sends its tx_signatures
+	// first as soon as its monitor has been persisted.
+	nodes[1].node.handle_commitment_signed(&nodes[0].node.get_our_node_id(), &initiator_commitment_signed);
+	check_added_monitors!(nodes[1], 1);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	let acceptor_tx_signatures = get_event_msg!(nodes[1], MessageSendEvent::SendTxSignatures, nodes[0].node.get_our_node_id());
+	assert_eq!(acceptor_tx_signatures.channel_id, channel_id);
+	assert!(acceptor_tx_signatures.witnesses.is_empty());
+
+	let initiator_signed_tx = handle_initial_commitment_signed_and_sign(&nodes[0], &nodes[1], &acceptor_commitment_signed, &[initiator_input]);
+	nodes[0].node.handle_tx_signatures(&nodes[1].node.get_our_node_id(), &acceptor_tx_signatures);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	nodes[0].node.funding_transaction_signed(&channel_id, &nodes[1].node.get_our_node_id(), initiator_signed_tx).unwrap();
+	let initiator_tx_signatures = get_event_msg!(nodes[0], MessageSendEvent::SendTxSignatures, nodes[1].node.get_our_node_id());
+	expect_channel_pending_event(&nodes[0], &nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_tx_signatures(&nodes[0].node.get_our_node_id(), &initiator_tx_signatures);
+	expect_channel_pending_event(&nodes[1], &nodes[0].node.get_our_node_id());
+
+	let funding_tx = nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0);
+	assert_eq!(funding_tx.len(), 1);
+	assert_eq!(funding_tx[0].input.len(), 1);
+	assert_eq!(nodes[1].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0), funding_tx);
+	assert_eq!(nodes[0].node.list_channels()[0].channel_value_satoshis, 100_000);
+}
+
+#[test]
+fn test_dual_funded_channel_insufficient_inputs() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	nodes[0].node.create_dual_funded_channel(nodes[1].node.get_our_node_id(), 100_000,
+		vec![funding_input(150_000)], None, 253, 42, None).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannelV2, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_open_channel_v2(&nodes[0].node.get_our_node_id(), &open_channel);
+	nodes[1].node.get_and_clear_pending_events();
+
+	// Our inputs don't cover our contribution, so we refuse to accept the channel.
+	let err = match nodes[1].node.accept_inbound_dual_funded_channel(&open_channel.temporary_channel_id,
+		&nodes[0].node.get_our_node_id(), 43, 50_000, vec![funding_input(50_000)], None)
+	{
+		Err(APIError::APIMisuseError { err }) => err,
+		res => panic!("Unexpected result {:?}", res),
+	};
+	assert!(err.contains("insufficient"));
+	let msg_events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	match &msg_events[0] {
+		MessageSendEvent::HandleError { action: ErrorAction::SendErrorMessage { .. }, node_id } =>
+			assert_eq!(*node_id, nodes[0].node.get_our_node_id()),
+		_ => panic!("Unexpected event {:?}", msg_events[0]),
+	}
+	check_closed_event(&nodes[1], 1, ClosureReason::ProcessingError { err }, false,
+		&[nodes[0].node.get_our_node_id()], 150_000);
+	assert!(nodes[1].node.list_channels().is_empty());
+}
+
+#[test]
+fn test_dual_funded_channel_negotiation_aborted() {
+	// Tests that a failed negotiation of the funding transaction is aborted with `tx_abort` rather
+	// than closing the channel, after which the negotiation is restarted and can complete.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let initiator_input = funding_input(150_000);
+	let acceptor_input = funding_input(80_000);
+	nodes[0].node.create_dual_funded_channel(nodes[1].node.get_our_node_id(), 100_000,
+		vec![initiator_input.clone()], None, 253, 42, None).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannelV2, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_open_channel_v2(&nodes[0].node.get_our_node_id(), &open_channel);
+	nodes[1].node.get_and_clear_pending_events();
+	nodes[1].node.accept_inbound_dual_funded_channel(&open_channel.temporary_channel_id,
+		&nodes[0].node.get_our_node_id(), 43, 50_000, vec![acceptor_input.clone()], None).unwrap();
+	let accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannelV2, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_accept_channel_v2(&nodes[1].node.get_our_node_id(), &accept_channel);
+
+	// Replaying the initiator's input spends the same outpoint twice, failing the negotiation.
+	let tx_add_input = get_event_msg!(nodes[0], MessageSendEvent::SendTxAddInput, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_tx_add_input(&nodes[0].node.get_our_node_id(), &tx_add_input);
+	let _ = get_event_msg!(nodes[1], MessageSendEvent::SendTxAddInput, nodes[0].node.get_our_node_id());
+	nodes[1].node.handle_tx_add_input(&nodes[0].node.get_our_node_id(), &tx_add_input);
+	let tx_abort = get_event_msg!(nodes[1], MessageSendEvent::SendTxAbort, nodes[0].node.get_our_node_id());
+	assert!(!tx_abort.data.is_empty());
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	// Any messages for the aborted negotiation still in flight are ignored.
+	nodes[1].node.handle_tx_add_input(&nodes[0].node.get_our_node_id(), &tx_add_input);
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	// The initiator echoes the `tx_abort` and restarts the negotiation, which then completes.
+	nodes[0].node.handle_tx_abort(&nodes[1].node.get_our_node_id(), &tx_abort);
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	let (initiator_commitment_signed, acceptor_commitment_signed) =
+		do_interactive_tx_construction(&nodes[0], &nodes[1]);
+	let channel_id = initiator_commitment_signed.channel_id;
+
+	let initiator_signed_tx = handle_initial_commitment_signed_and_sign(&nodes[0], &nodes[1], &acceptor_commitment_signed, &[initiator_input]);
+	let acceptor_signed_tx = handle_initial_commitment_signed_and_sign(&nodes[1], &nodes[0], &initiator_commitment_signed, &[acceptor_input]);
+	nodes[0].node.funding_transaction_signed(&channel_id, &nodes[1].node.get_our_node_id(), initiator_signed_tx).unwrap();
+	nodes[1].node.funding_transaction_signed(&channel_id, &nodes[0].node.get_our_node_id(), acceptor_signed_tx).unwrap();
+	let acceptor_tx_signatures = get_event_msg!(nodes[1], MessageSendEvent::SendTxSignatures, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_tx_signatures(&nodes[1].node.get_our_node_id(), &acceptor_tx_signatures);
+	let initiator_tx_signatures = get_event_msg!(nodes[0], MessageSendEvent::SendTxSignatures, nodes[1].node.get_our_node_id());
+	expect_channel_pending_event(&nodes[0], &nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_tx_signatures(&nodes[0].node.get_our_node_id(), &initiator_tx_signatures);
+	expect_channel_pending_event(&nodes[1], &nodes[0].node.get_our_node_id());
+
+	let funding_tx = nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0);
+	assert_eq!(funding_tx.len(), 1);
+	assert_eq!(funding_tx[0].input.len(), 2);
+	assert_eq!(nodes[1].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0), funding_tx);
+}
+
+#[test]
+fn test_dual_funded_channel_tx_signatures_resumed() {
+	// Tests that neither a restart nor a disconnection while exchanging `tx_signatures` closes a
+	// dual-funded channel, with any lost `tx_signatures` retransmitted on reestablish.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes_0_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let initiator_input = funding_input(150_000);
+	let acceptor_input = funding_input(80_000);
+	let (initiator_commitment_signed, acceptor_commitment_signed) =
+		negotiate_dual_funded_channel(&nodes[0], &nodes[1], initiator_input.clone(), acceptor_input.clone());
+	let channel_id = initiator_commitment_signed.channel_id;
+
+	// The acceptor sends its tx_signatures first, but they're lost as the initiator restarts before
+	// handling its `FundingTransactionReadyForSigning` event.
+	let acceptor_signed_tx = handle_initial_commitment_signed_and_sign(&nodes[1], &nodes[0], &initiator_commitment_signed, &[acceptor_input]);
+	nodes[1].node.funding_transaction_signed(&channel_id, &nodes[0].node.get_our_node_id(), acceptor_signed_tx).unwrap();
+	get_event_msg!(nodes[1], MessageSendEvent::SendTxSignatures, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_commitment_signed(&nodes[1].node.get_our_node_id(), &acceptor_commitment_signed);
+	check_added_monitors!(nodes[0], 1);
+
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	let funding_txo = nodes[0].node.list_channels()[0].funding_txo.unwrap();
+	let initiator_monitor = nodes[0].chain_monitor.chain_monitor.get_monitor(funding_txo).unwrap().encode();
+	reload_node!(nodes[0], nodes[0].node.encode(), &[&initiator_monitor], persister, new_chain_monitor, nodes_0_deserialized);
+
+	// Neither side has the other's tx_signatures, so both ask for them.
+	let (initiator_reestablish, acceptor_reestablish) = connect_and_get_reestablish(&nodes[0], &nodes[1]);
+	let funding_txid = Some(funding_txo.txid);
+	assert_eq!(initiator_reestablish.next_funding_txid, funding_txid);
+	assert_eq!(acceptor_reestablish.next_funding_txid, funding_txid);
+	nodes[1].node.handle_channel_reestablish(&nodes[0].node.get_our_node_id(), &initiator_reestablish);
+	let acceptor_tx_signatures = get_event_msg!(nodes[1], MessageSendEvent::SendTxSignatures, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_channel_reestablish(&nodes[1].node.get_our_node_id(), &acceptor_reestablish);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	// The signing request is replayed after the restart.
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	let mut initiator_signed_tx = match &events[0] {
+		Event::FundingTransactionReadyForSigning { channel_id: event_channel_id, unsigned_transaction, .. } => {
+			assert_eq!(*event_channel_id, channel_id);
+			unsigned_transaction.clone()
+		},
+		_ => panic!("Unexpected event {:?}", events[0]),
+	};
+	for input in initiator_signed_tx.input.iter_mut() {
+		if input.previous_output == initiator_input.0.previous_output {
+			input.witness = Witness::from_slice(&[vec![0; 73], vec![2; 33]]);
+		}
+	}
+	nodes[0].node.handle_tx_signatures(&nodes[1].node.get_our_node_id(), &acceptor_tx_signatures);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	nodes[0].node.funding_transaction_signed(&channel_id, &nodes[1].node.get_our_node_id(), initiator_signed_tx).unwrap();
+	get_event_msg!(nodes[0], MessageSendEvent::SendTxSignatures, nodes[1].node.get_our_node_id());
+	expect_channel_pending_event(&nodes[0], &nodes[1].node.get_our_node_id());
+
+	// The initiator's tx_signatures are lost to a disconnection, so only the acceptor asks for them.
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	let (initiator_reestablish, acceptor_reestablish) = connect_and_get_reestablish(&nodes[0], &nodes[1]);
+	assert_eq!(initiator_reestablish.next_funding_txid, None);
+	assert_eq!(acceptor_reestablish.next_funding_txid, funding_txid);
+	nodes[1].node.handle_channel_reestablish(&nodes[0].node.get_our_node_id(), &initiator_reestablish);
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+	nodes[0].node.handle_channel_reestablish(&nodes[1].node.get_our_node_id(), &acceptor_reestablish);
+	let initiator_tx_signatures = get_event_msg!(nodes[0], MessageSendEvent::SendTxSignatures, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_tx_signatures(&nodes[0].node.get_our_node_id(), &initiator_tx_signatures);
+	expect_channel_pending_event(&nodes[1], &nodes[0].node.get_our_node_id());
+
+	let funding_tx = {
+		let initiator_broadcast = nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0);
+		let acceptor_broadcast = nodes[1].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0);
+		assert_eq!(initiator_broadcast.len(), 1);
+		assert_eq!(initiator_broadcast, acceptor_broadcast);
+		initiator_broadcast[0].clone()
+	};
+	assert_eq!(funding_tx.txid(), funding_txo.txid);
+
+	let (channel_ready, _) = create_chan_between_nodes_with_value_confirm(&nodes[0], &nodes[1], &funding_tx);
+	let (announcement, as_update, bs_update) = create_chan_between_nodes_with_value_b(&nodes[0], &nodes[1], &channel_ready);
+	update_nodes_with_chan_announce(&nodes, 0, 1, &announcement, &as_update, &bs_update);
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+}
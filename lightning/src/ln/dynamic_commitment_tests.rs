@@ -0,0 +1,321 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests of upgrading the channel type of open channels via dynamic commitments.
+
+use crate::events::{ClosureReason, MessageSendEvent, MessageSendEventsProvider};
+use crate::ln::ChannelId;
+use crate::ln::chan_utils::OFFERED_HTLC_SCRIPT_WEIGHT;
+use crate::ln::channelmanager::RAACommitmentOrder;
+use crate::ln::features::ChannelTypeFeatures;
+use crate::ln::msgs::{self, ChannelMessageHandler};
+use crate::util::config::UserConfig;
+use crate::util::errors::APIError;
+
+use crate::ln::functional_test_utils::*;
+
+fn anchors_config() -> UserConfig {
+	let mut config = test_default_channel_config();
+	config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx = true;
+	config
+}
+
+/// Opens a channel from `nodes[0]` to `nodes[1]` which doesn't use anchor outputs, even though
+/// both nodes support them.
+fn create_legacy_chan<'a, 'b, 'c: 'd, 'd>(nodes: &'a Vec<Node<'b, 'c, 'd>>) -> ChannelId {
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+	let mut legacy_config = anchors_config();
+	legacy_config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx = false;
+
+	let temporary_channel_id = nodes[0].node.create_channel(node_id_1, 100_000, 50_000_000, 42, None, Some(legacy_config)).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannel, node_id_1);
+	assert_eq!(open_channel.channel_type, Some(ChannelTypeFeatures::only_static_remote_key()));
+	nodes[1].node.handle_open_channel(&node_id_0, &open_channel);
+	let accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannel, node_id_0);
+	nodes[0].node.handle_accept_channel(&node_id_1, &accept_channel);
+
+	let funding_tx = sign_funding_transaction(&nodes[0], &nodes[1], 100_000, temporary_channel_id);
+	let (channel_ready, channel_id) = create_chan_between_nodes_with_value_confirm(&nodes[0], &nodes[1], &funding_tx);
+	let (announcement, as_update, bs_update) = create_chan_between_nodes_with_value_b(&nodes[0], &nodes[1], &channel_ready);
+	update_nodes_with_chan_announce(nodes, 0, 1, &announcement, &as_update, &bs_update);
+	channel_id
+}
+
+fn get_channel_type<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>, channel_id: &ChannelId) -> ChannelTypeFeatures {
+	node.node.list_channels().into_iter().find(|chan| chan.channel_id == *channel_id).unwrap()
+		.channel_type.unwrap()
+}
+
+/// Starts upgrading the channel to anchors from `initiator`, returning the resulting
+/// `dyn_propose` once the channel is quiescent.
+fn propose_anchors_upgrade<'a, 'b, 'c>(initiator: &Node<'a, 'b, 'c>, responder: &Node<'a, 'b, 'c>, channel_id: &ChannelId) -> msgs::DynPropose {
+	let initiator_node_id = initiator.node.get_our_node_id();
+	let responder_node_id = responder.node.get_our_node_id();
+	initiator.node.upgrade_channel_type(channel_id, &responder_node_id,
+		ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies()).unwrap();
+
+	let stfu = get_event_msg!(initiator, MessageSendEvent::SendStfu, responder_node_id);
+	responder.node.handle_stfu(&initiator_node_id, &stfu);
+	let stfu_resp = get_event_msg!(responder, MessageSendEvent::SendStfu, initiator_node_id);
+	initiator.node.handle_stfu(&responder_node_id, &stfu_resp);
+
+	let dyn_propose = get_event_msg!(initiator, MessageSendEvent::SendDynPropose, responder_node_id);
+	assert_eq!(dyn_propose.channel_id, *channel_id);
+	assert_eq!(dyn_propose.channel_type, ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies());
+	dyn_propose
+}
+
+#[test]
+fn test_upgrade_channel_type_to_anchors() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(anchors_config()), Some(anchors_config())]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+	let channel_id = create_legacy_chan(&nodes);
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+
+	let dyn_propose = propose_anchors_upgrade(&nodes[0], &nodes[1], &channel_id);
+	// A second upgrade can't be requested while one is in progress.
+	assert!(matches!(nodes[0].node.upgrade_channel_type(&channel_id, &node_id_1,
+		ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies()), Err(APIError::APIMisuseError { .. })));
+
+	// The responder switches over as soon as it accepts the new channel type.
+	nodes[1].node.handle_dyn_propose(&node_id_0, &dyn_propose);
+	let dyn_ack = get_event_msg!(nodes[1], MessageSendEvent::SendDynAck, node_id_0);
+	assert_eq!(get_channel_type(&nodes[1], &channel_id), ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies());
+
+	// The initiator switches over once it receives the `dyn_ack` and immediately sends its first
+	// commitment transaction using the new channel type.
+	nodes[0].node.handle_dyn_ack(&node_id_1, &dyn_ack);
+	check_added_monitors(&nodes[0], 1);
+	assert_eq!(get_channel_type(&nodes[0], &channel_id), ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies());
+	let as_commitment_update = get_htlc_update_msgs!(nodes[0], node_id_1);
+	assert!(as_commitment_update.update_add_htlcs.is_empty());
+
+	// Both sides have to sign a commitment transaction using the new type, so the responder
+	// replies with its own `commitment_signed` even though nothing else changed.
+	nodes[1].node.handle_commitment_signed(&node_id_0, &as_commitment_update.commitment_signed);
+	check_added_monitors(&nodes[1], 1);
+	let (bs_raa, bs_commitment_signed) = get_revoke_commit_msgs!(nodes[1], node_id_0);
+	nodes[0].node.handle_revoke_and_ack(&node_id_1, &bs_raa);
+	check_added_monitors(&nodes[0], 1);
+	nodes[0].node.handle_commitment_signed(&node_id_1, &bs_commitment_signed);
+	check_added_monitors(&nodes[0], 1);
+	let as_raa = get_event_msg!(nodes[0], MessageSendEvent::SendRevokeAndACK, node_id_1);
+	nodes[1].node.handle_revoke_and_ack(&node_id_0, &as_raa);
+	check_added_monitors(&nodes[1], 1);
+
+	// With both sides committed to the new channel type, quiescence is terminated.
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(!nodes[0].node.is_channel_quiescent(&channel_id, &node_id_1));
+	assert!(!nodes[1].node.is_channel_quiescent(&channel_id, &node_id_0));
+
+	// Our latest commitment transactions now carry anchor outputs.
+	for node in [&nodes[0], &nodes[1]] {
+		let commitment_tx = get_local_commitment_txn!(node, channel_id);
+		assert_eq!(commitment_tx[0].output.len(), 4);
+		assert_eq!(commitment_tx[0].output.iter().filter(|output| output.value == 330).count(), 2);
+	}
+
+	// Payments continue to work using the new channel type.
+	let (payment_preimage, ..) = route_payment(&nodes[0], &[&nodes[1]], 2_000_000);
+	let commitment_tx = get_local_commitment_txn!(nodes[0], channel_id);
+	assert_eq!(commitment_tx[0].output.len(), 5);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+	send_payment(&nodes[1], &[&nodes[0]], 1_000_000);
+}
+
+#[test]
+fn test_upgrade_channel_type_rejected() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(anchors_config()), Some(anchors_config())]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+	let channel_id = create_legacy_chan(&nodes);
+
+	// Only upgrades to anchors are supported.
+	assert!(matches!(nodes[0].node.upgrade_channel_type(&channel_id, &node_id_1,
+		ChannelTypeFeatures::only_static_remote_key()), Err(APIError::APIMisuseError { .. })));
+
+	// The responder rejects any channel type it doesn't allow upgrading to, terminating
+	// quiescence.
+	let mut dyn_propose = propose_anchors_upgrade(&nodes[0], &nodes[1], &channel_id);
+	dyn_propose.channel_type.set_scid_privacy_required();
+	nodes[1].node.handle_dyn_propose(&node_id_0, &dyn_propose);
+	let dyn_reject = get_event_msg!(nodes[1], MessageSendEvent::SendDynReject, node_id_0);
+	assert!(!nodes[1].node.is_channel_quiescent(&channel_id, &node_id_0));
+	assert_eq!(get_channel_type(&nodes[1], &channel_id), ChannelTypeFeatures::only_static_remote_key());
+
+	nodes[0].node.handle_dyn_reject(&node_id_1, &dyn_reject);
+	assert!(!nodes[0].node.is_channel_quiescent(&channel_id, &node_id_1));
+	assert_eq!(get_channel_type(&nodes[0], &channel_id), ChannelTypeFeatures::only_static_remote_key());
+	check_added_monitors(&nodes[0], 0);
+	check_added_monitors(&nodes[1], 0);
+
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+
+	// The upgrade can be retried once the previous attempt was rejected.
+	propose_anchors_upgrade(&nodes[0], &nodes[1], &channel_id);
+}
+
+#[test]
+fn test_upgrade_channel_type_requires_peer_support() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(anchors_config()), None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_1 = nodes[1].node.get_our_node_id();
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+	assert!(matches!(nodes[0].node.upgrade_channel_type(&channel_id, &node_id_1,
+		ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies()), Err(APIError::ChannelUnavailable { .. })));
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+}
+
+#[test]
+fn test_upgrade_channel_type_reverted_on_reconnect() {
+	// If the initiator never receives our `dyn_ack`, it never commits to the new channel type and
+	// we have to revert to the previous one upon reconnecting.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(anchors_config()), Some(anchors_config())]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+	let channel_id = create_legacy_chan(&nodes);
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+
+	let dyn_propose = propose_anchors_upgrade(&nodes[0], &nodes[1], &channel_id);
+	nodes[1].node.handle_dyn_propose(&node_id_0, &dyn_propose);
+	let _ = get_event_msg!(nodes[1], MessageSendEvent::SendDynAck, node_id_0);
+	assert_eq!(get_channel_type(&nodes[1], &channel_id), ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies());
+
+	nodes[0].node.peer_disconnected(&node_id_1);
+	nodes[1].node.peer_disconnected(&node_id_0);
+	reconnect_nodes(ReconnectArgs::new(&nodes[0], &nodes[1]));
+
+	assert_eq!(get_channel_type(&nodes[0], &channel_id), ChannelTypeFeatures::only_static_remote_key());
+	assert_eq!(get_channel_type(&nodes[1], &channel_id), ChannelTypeFeatures::only_static_remote_key());
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+}
+
+#[test]
+fn test_upgrade_channel_type_completes_after_reconnect() {
+	// Once the initiator sent its first commitment transaction using the new channel type, the
+	// upgrade has to complete even if we disconnect before the responder receives it.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(anchors_config()), Some(anchors_config())]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+	let channel_id = create_legacy_chan(&nodes);
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+
+	let dyn_propose = propose_anchors_upgrade(&nodes[0], &nodes[1], &channel_id);
+	nodes[1].node.handle_dyn_propose(&node_id_0, &dyn_propose);
+	let dyn_ack = get_event_msg!(nodes[1], MessageSendEvent::SendDynAck, node_id_0);
+	nodes[0].node.handle_dyn_ack(&node_id_1, &dyn_ack);
+	check_added_monitors(&nodes[0], 1);
+	let _ = get_htlc_update_msgs!(nodes[0], node_id_1);
+
+	nodes[0].node.peer_disconnected(&node_id_1);
+	nodes[1].node.peer_disconnected(&node_id_0);
+	let init_0 = msgs::Init { features: nodes[0].node.init_features(), networks: None, remote_network_address: None };
+	let init_1 = msgs::Init { features: nodes[1].node.init_features(), networks: None, remote_network_address: None };
+	nodes[0].node.peer_connected(&node_id_1, &init_1, true).unwrap();
+	let as_reestablish = get_chan_reestablish_msgs!(nodes[0], nodes[1]);
+	nodes[1].node.peer_connected(&node_id_0, &init_0, false).unwrap();
+	let bs_reestablish = get_chan_reestablish_msgs!(nodes[1], nodes[0]);
+	assert_eq!(as_reestablish[0].channel_type, Some(ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies()));
+	assert_eq!(bs_reestablish[0].channel_type, Some(ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies()));
+
+	nodes[1].node.handle_channel_reestablish(&node_id_0, &as_reestablish[0]);
+	let (channel_ready, raa, commitment_update, _) = handle_chan_reestablish_msgs!(nodes[1], nodes[0]);
+	assert!(channel_ready.is_none() && raa.is_none() && commitment_update.is_none());
+	nodes[0].node.handle_channel_reestablish(&node_id_1, &bs_reestablish[0]);
+	let (channel_ready, raa, commitment_update, _) = handle_chan_reestablish_msgs!(nodes[0], nodes[1]);
+	assert!(channel_ready.is_none() && raa.is_none());
+
+	// The initiator retransmits its `commitment_signed`, completing the upgrade as usual.
+	nodes[1].node.handle_commitment_signed(&node_id_0, &commitment_update.unwrap().commitment_signed);
+	check_added_monitors(&nodes[1], 1);
+	let (bs_raa, bs_commitment_signed) = get_revoke_commit_msgs!(nodes[1], node_id_0);
+	nodes[0].node.handle_revoke_and_ack(&node_id_1, &bs_raa);
+	check_added_monitors(&nodes[0], 1);
+	nodes[0].node.handle_commitment_signed(&node_id_1, &bs_commitment_signed);
+	check_added_monitors(&nodes[0], 1);
+	let as_raa = get_event_msg!(nodes[0], MessageSendEvent::SendRevokeAndACK, node_id_1);
+	nodes[1].node.handle_revoke_and_ack(&node_id_0, &as_raa);
+	check_added_monitors(&nodes[1], 1);
+	assert!(!nodes[0].node.is_channel_quiescent(&channel_id, &node_id_1));
+	assert!(!nodes[1].node.is_channel_quiescent(&channel_id, &node_id_0));
+
+	assert_eq!(get_channel_type(&nodes[0], &channel_id), ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies());
+	assert_eq!(get_channel_type(&nodes[1], &channel_id), ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies());
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+}
+
+#[test]
+fn test_claim_previous_commitment_after_upgrade() {
+	// Our previous commitment transaction is still valid for a bit after the upgrade completes, so
+	// if it confirms, its HTLCs have to be claimed using the channel type it was signed with.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(anchors_config()), Some(anchors_config())]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+	let channel_id = create_legacy_chan(&nodes);
+	route_payment(&nodes[0], &[&nodes[1]], 2_000_000);
+	let legacy_commitment_tx = get_local_commitment_txn!(nodes[0], channel_id)[0].clone();
+
+	let dyn_propose = propose_anchors_upgrade(&nodes[0], &nodes[1], &channel_id);
+	nodes[1].node.handle_dyn_propose(&node_id_0, &dyn_propose);
+	let dyn_ack = get_event_msg!(nodes[1], MessageSendEvent::SendDynAck, node_id_0);
+	nodes[0].node.handle_dyn_ack(&node_id_1, &dyn_ack);
+	check_added_monitors(&nodes[0], 1);
+	let as_commitment_update = get_htlc_update_msgs!(nodes[0], node_id_1);
+	nodes[1].node.handle_commitment_signed(&node_id_0, &as_commitment_update.commitment_signed);
+	check_added_monitors(&nodes[1], 1);
+	let (bs_raa, bs_commitment_signed) = get_revoke_commit_msgs!(nodes[1], node_id_0);
+	nodes[0].node.handle_revoke_and_ack(&node_id_1, &bs_raa);
+	check_added_monitors(&nodes[0], 1);
+	nodes[0].node.handle_commitment_signed(&node_id_1, &bs_commitment_signed);
+	check_added_monitors(&nodes[0], 1);
+	let as_raa = get_event_msg!(nodes[0], MessageSendEvent::SendRevokeAndACK, node_id_1);
+	nodes[1].node.handle_revoke_and_ack(&node_id_0, &as_raa);
+	check_added_monitors(&nodes[1], 1);
+	assert_eq!(get_channel_type(&nodes[0], &channel_id), ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies());
+
+	mine_transaction(&nodes[0], &legacy_commitment_tx);
+	check_closed_broadcast!(nodes[0], true);
+	check_added_monitors(&nodes[0], 1);
+	check_closed_event!(nodes[0], 1, ClosureReason::CommitmentTxConfirmed, [node_id_1], 100_000);
+	connect_blocks(&nodes[0], TEST_FINAL_CLTV); // Confirm blocks until the HTLC expires
+
+	// The HTLC is timed out with a fully signed, legacy HTLC-Timeout transaction rather than one
+	// which has to be bumped as if the commitment had anchor outputs.
+	let htlc_timeout_txn = nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0).into_iter()
+		.filter(|tx| tx.input[0].previous_output.txid == legacy_commitment_tx.txid())
+		.collect::<Vec<_>>();
+	assert!(!htlc_timeout_txn.is_empty());
+	for htlc_timeout_tx in htlc_timeout_txn.iter() {
+		check_spends!(htlc_timeout_tx, legacy_commitment_tx);
+		assert_eq!(htlc_timeout_tx.input.len(), 1);
+		assert_eq!(htlc_timeout_tx.input[0].witness.last().unwrap().len(), OFFERED_HTLC_SCRIPT_WEIGHT);
+	}
+	assert!(nodes[0].chain_monitor.chain_monitor.get_and_clear_pending_events().is_empty());
+}
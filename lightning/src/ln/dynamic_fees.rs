@@ -0,0 +1,158 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Automatically adjusts the forwarding fees of our channels based on their liquidity and recent
+//! forwarding volume.
+//!
+//! See [`DynamicFeeConfig`] for details.
+
+use crate::ln::ChannelId;
+use crate::util::config::DynamicFeeConfig;
+
+use crate::prelude::*;
+
+/// The state we keep for each channel in order to update its fees.
+#[derive(Default)]
+struct ChannelFeeState {
+	/// The value forwarded over the channel as of the end of the last update interval, halving
+	/// every interval.
+	recent_volume_msat: u64,
+	/// The value forwarded over the channel during the current update interval.
+	pending_volume_msat: u64,
+	ticks_since_update: u16,
+}
+
+/// Tracks the forwarding volume of our channels and computes their fees.
+///
+/// This is not persisted, so recent forwarding volume is forgotten on restart.
+pub(crate) struct DynamicFeeManager {
+	channels: HashMap<ChannelId, ChannelFeeState>,
+}
+
+impl DynamicFeeManager {
+	pub(crate) fn new() -> Self {
+		Self { channels: HashMap::new() }
+	}
+
+	/// Records that we forwarded an HTLC of `amount_msat` over the given outbound channel.
+	pub(crate) fn htlc_forwarded(&mut self, channel_id: ChannelId, amount_msat: u64) {
+		let state = self.channels.entry(channel_id).or_default();
+		state.pending_volume_msat = state.pending_volume_msat.saturating_add(amount_msat);
+	}
+
+	pub(crate) fn channel_closed(&mut self, channel_id: &ChannelId) {
+		self.channels.remove(channel_id);
+	}
+
+	/// Called once per timer tick for each usable channel. Returns the channel's new base and
+	/// proportional fees if an update interval ended and they moved far enough from the current
+	/// ones to be worth broadcasting.
+	pub(crate) fn timer_tick(
+		&mut self, config: &DynamicFeeConfig, channel_id: ChannelId, channel_value_msat: u64,
+		balance_msat: u64, current_fee_base_msat: u32, current_fee_proportional_millionths: u32,
+	) -> Option<(u32, u32)> {
+		let state = self.channels.entry(channel_id).or_default();
+		state.ticks_since_update = state.ticks_since_update.saturating_add(1);
+		if state.ticks_since_update < config.update_interval_ticks { return None; }
+		state.ticks_since_update = 0;
+		state.recent_volume_msat = (state.recent_volume_msat / 2).saturating_add(state.pending_volume_msat);
+		state.pending_volume_msat = 0;
+
+		let (fee_base_msat, fee_proportional_millionths) =
+			target_fees(config, channel_value_msat, balance_msat, state.recent_volume_msat);
+		let changed_enough = |current: u32, target: u32| {
+			(current as u64).abs_diff(target as u64) * 100 >= current as u64 * config.min_change_percent as u64 &&
+				current != target
+		};
+		if changed_enough(current_fee_base_msat, fee_base_msat) ||
+			changed_enough(current_fee_proportional_millionths, fee_proportional_millionths)
+		{
+			Some((fee_base_msat, fee_proportional_millionths))
+		} else {
+			None
+		}
+	}
+}
+
+/// Computes the base and proportional fees for a channel from our share of its liquidity and its
+/// recent forwarding volume.
+fn target_fees(
+	config: &DynamicFeeConfig, channel_value_msat: u64, balance_msat: u64, recent_volume_msat: u64,
+) -> (u32, u32) {
+	if channel_value_msat == 0 {
+		return (config.max_fee_base_msat, config.max_fee_proportional_millionths);
+	}
+	let balance_msat = core::cmp::min(balance_msat, channel_value_msat) as u128;
+	let volume_msat = core::cmp::min(recent_volume_msat, channel_value_msat) as u128;
+	let increase_percent = config.volume_fee_increase_percent as u128 * volume_msat / channel_value_msat as u128;
+	let fee = |min: u32, max: u32| -> u32 {
+		let max = core::cmp::max(min, max) as u128;
+		let fee = max - (max - min as u128) * balance_msat / channel_value_msat as u128;
+		core::cmp::min(fee * (100 + increase_percent) / 100, max) as u32
+	};
+	(
+		fee(config.min_fee_base_msat, config.max_fee_base_msat),
+		fee(config.min_fee_proportional_millionths, config.max_fee_proportional_millionths),
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::ln::ChannelId;
+	use crate::util::config::DynamicFeeConfig;
+	use super::{DynamicFeeManager, target_fees};
+
+	fn config() -> DynamicFeeConfig {
+		DynamicFeeConfig {
+			enabled: true,
+			min_fee_proportional_millionths: 100,
+			max_fee_proportional_millionths: 1_100,
+			min_fee_base_msat: 0,
+			max_fee_base_msat: 1_000,
+			volume_fee_increase_percent: 50,
+			update_interval_ticks: 2,
+			min_change_percent: 10,
+		}
+	}
+
+	#[test]
+	fn fees_follow_liquidity_and_volume() {
+		let config = config();
+		assert_eq!(target_fees(&config, 1_000_000, 1_000_000, 0), (0, 100));
+		assert_eq!(target_fees(&config, 1_000_000, 0, 0), (1_000, 1_100));
+		assert_eq!(target_fees(&config, 1_000_000, 500_000, 0), (500, 600));
+		assert_eq!(target_fees(&config, 1_000_000, 750_000, 0), (250, 350));
+
+		// Volume increases fees, up to the configured maximum.
+		assert_eq!(target_fees(&config, 1_000_000, 500_000, 500_000), (625, 750));
+		assert_eq!(target_fees(&config, 1_000_000, 500_000, 2_000_000), (750, 900));
+		assert_eq!(target_fees(&config, 1_000_000, 100_000, 1_000_000), (1_000, 1_100));
+	}
+
+	#[test]
+	fn updates_fees_once_per_interval_if_changed_enough() {
+		let config = config();
+		let mut manager = DynamicFeeManager::new();
+		let channel_id = ChannelId::from_bytes([42; 32]);
+
+		assert_eq!(manager.timer_tick(&config, channel_id, 1_000_000, 500_000, 1_000, 1_000), None);
+		assert_eq!(manager.timer_tick(&config, channel_id, 1_000_000, 500_000, 1_000, 1_000), Some((500, 600)));
+
+		// Changes below `min_change_percent` aren't worth broadcasting.
+		assert_eq!(manager.timer_tick(&config, channel_id, 1_000_000, 510_000, 500, 600), None);
+		assert_eq!(manager.timer_tick(&config, channel_id, 1_000_000, 510_000, 500, 600), None);
+
+		// Forwarding volume is only accounted for at the end of each interval and decays over time.
+		manager.htlc_forwarded(channel_id, 500_000);
+		assert_eq!(manager.timer_tick(&config, channel_id, 1_000_000, 500_000, 500, 600), None);
+		assert_eq!(manager.timer_tick(&config, channel_id, 1_000_000, 500_000, 500, 600), Some((625, 750)));
+		assert_eq!(manager.timer_tick(&config, channel_id, 1_000_000, 500_000, 625, 750), None);
+		assert_eq!(manager.timer_tick(&config, channel_id, 1_000_000, 500_000, 625, 750), Some((560, 672)));
+	}
+}
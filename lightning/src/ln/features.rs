@@ -49,9 +49,18 @@
 //!     (see [BOLT-4](https://github.com/lightning/bolts/blob/master/04-onion-routing.md#route-blinding) for more information).
 //! - `ShutdownAnySegwit` - requires/supports that future segwit versions are allowed in `shutdown`
 //!     (see [BOLT-2](https://github.com/lightning/bolts/blob/master/02-peer-protocol.md) for more information).
+//! - `DualFund` - requires/supports V2 channel establishment, in which both peers may contribute
+//!     inputs to the funding transaction
+//!     (see [BOLT-2](https://github.com/lightning/bolts/blob/master/02-peer-protocol.md#channel-establishment-v2) for more information).
+//! - `Quiescence` - requires/supports the `stfu` protocol to make a channel quiescent
+//!     (see [BOLT-2](https://github.com/lightning/bolts/blob/master/02-peer-protocol.md#channel-quiescence) for more information).
 //! - `OnionMessages` - requires/supports forwarding onion messages
 //!     (see [BOLT-7](https://github.com/lightning/bolts/pull/759/files) for more information).
 //     TODO: update link
+//! - `ProvideStorage` - requires/supports storing an encrypted backup blob on behalf of peers we
+//!     have channels with
+//!     (see [BOLT-1](https://github.com/lightning/bolts/pull/1110/files) for more information).
+//     TODO: update link
 //! - `ChannelType` - node supports the channel_type field in open/accept
 //!     (see [BOLT-2](https://github.com/lightning/bolts/blob/master/02-peer-protocol.md) for more information).
 //! - `SCIDPrivacy` - supply channel aliases for routing
@@ -66,6 +75,10 @@
 //!      for more info).
 //! - `Keysend` - send funds to a node without an invoice
 //!     (see the [`Keysend` feature assignment proposal](https://github.com/lightning/bolts/issues/605#issuecomment-606679798) for more information).
+//! - `Trampoline` - supports receiving and forwarding payments using trampoline onions, finding
+//!     routes to the next trampoline hop on behalf of the sender
+//!     (see [BOLT-4](https://github.com/lightning/bolts/pull/836/files) for more information).
+//     TODO: update link
 //!
 //! LDK knows about the following features, but does not support them:
 //! - `AnchorsNonzeroFeeHtlcTx` - the initial version of anchor outputs, which was later found to be
@@ -145,13 +158,15 @@ mod sealed {
 		// Byte 2
 		BasicMPP | Wumbo | AnchorsNonzeroFeeHtlcTx | AnchorsZeroFeeHtlcTx,
 		// Byte 3
-		RouteBlinding | ShutdownAnySegwit | Taproot,
+		RouteBlinding | ShutdownAnySegwit | DualFund | Taproot,
 		// Byte 4
-		OnionMessages,
+		Quiescence | OnionMessages,
 		// Byte 5
-		ChannelType | SCIDPrivacy,
+		ZeroFeeCommitments | ProvideStorage | ChannelType | SCIDPrivacy,
 		// Byte 6
 		ZeroConf,
+		// Byte 7
+		Trampoline,
 	]);
 	define_context!(NodeContext, [
 		// Byte 0
@@ -161,13 +176,15 @@ mod sealed {
 		// Byte 2
 		BasicMPP | Wumbo | AnchorsNonzeroFeeHtlcTx | AnchorsZeroFeeHtlcTx,
 		// Byte 3
-		RouteBlinding | ShutdownAnySegwit | Taproot,
+		RouteBlinding | ShutdownAnySegwit | DualFund | Taproot,
 		// Byte 4
-		OnionMessages,
+		Quiescence | OnionMessages,
 		// Byte 5
-		ChannelType | SCIDPrivacy,
+		ZeroFeeCommitments | ProvideStorage | ChannelType | SCIDPrivacy,
 		// Byte 6
 		ZeroConf | Keysend,
+		// Byte 7
+		Trampoline,
 	]);
 	define_context!(ChannelContext, []);
 	define_context!(Bolt11InvoiceContext, [
@@ -185,6 +202,8 @@ mod sealed {
 		,
 		// Byte 6
 		PaymentMetadata,
+		// Byte 7
+		Trampoline,
 	]);
 	define_context!(OfferContext, []);
 	define_context!(InvoiceRequestContext, []);
@@ -211,7 +230,7 @@ mod sealed {
 		// Byte 4
 		,
 		// Byte 5
-		SCIDPrivacy,
+		ZeroFeeCommitments | SCIDPrivacy,
 		// Byte 6
 		ZeroConf,
 	]);
@@ -399,12 +418,24 @@ mod sealed {
 	define_feature!(27, ShutdownAnySegwit, [InitContext, NodeContext],
 		"Feature flags for `opt_shutdown_anysegwit`.", set_shutdown_any_segwit_optional,
 		set_shutdown_any_segwit_required, supports_shutdown_anysegwit, requires_shutdown_anysegwit);
+	define_feature!(29, DualFund, [InitContext, NodeContext],
+		"Feature flags for `option_dual_fund`.", set_dual_fund_optional, set_dual_fund_required,
+		supports_dual_fund, requires_dual_fund);
 	define_feature!(31, Taproot, [InitContext, NodeContext, ChannelTypeContext],
 		"Feature flags for `option_taproot`.", set_taproot_optional,
 		set_taproot_required, supports_taproot, requires_taproot);
+	define_feature!(35, Quiescence, [InitContext, NodeContext],
+		"Feature flags for `option_quiesce`.", set_quiescence_optional, set_quiescence_required,
+		supports_quiescence, requires_quiescence);
 	define_feature!(39, OnionMessages, [InitContext, NodeContext],
 		"Feature flags for `option_onion_messages`.", set_onion_messages_optional,
 		set_onion_messages_required, supports_onion_messages, requires_onion_messages);
+	define_feature!(41, ZeroFeeCommitments, [InitContext, NodeContext, ChannelTypeContext],
+		"Feature flags for `option_zero_fee_commitments`.", set_zero_fee_commitments_optional,
+		set_zero_fee_commitments_required, supports_zero_fee_commitments, requires_zero_fee_commitments);
+	define_feature!(43, ProvideStorage, [InitContext, NodeContext],
+		"Feature flags for `option_provide_storage`.", set_provide_storage_optional,
+		set_provide_storage_required, supports_provide_storage, requires_provide_storage);
 	define_feature!(45, ChannelType, [InitContext, NodeContext],
 		"Feature flags for `option_channel_type`.", set_channel_type_optional,
 		set_channel_type_required, supports_channel_type, requires_channel_type);
@@ -420,6 +451,9 @@ mod sealed {
 	define_feature!(55, Keysend, [NodeContext],
 		"Feature flags for keysend payments.", set_keysend_optional, set_keysend_required,
 		supports_keysend, requires_keysend);
+	define_feature!(57, Trampoline, [InitContext, NodeContext, Bolt11InvoiceContext],
+		"Feature flags for `option_trampoline_routing`.", set_trampoline_routing_optional,
+		set_trampoline_routing_required, supports_trampoline_routing, requires_trampoline_routing);
 	// Note: update the module-level docs when a new feature bit is added!
 
 	#[cfg(test)]
@@ -610,6 +644,15 @@ impl ChannelTypeFeatures {
 		<sealed::ChannelTypeContext as sealed::AnchorsZeroFeeHtlcTx>::set_required_bit(&mut ret.flags);
 		ret
 	}
+
+	/// Constructs a ChannelTypeFeatures with zero-fee commitments support. Such channels build on
+	/// anchors, whose zero-fee HTLC transactions and output scripts they share, but replace both
+	/// anchor outputs with a single ephemeral anchor on a version 3 commitment transaction.
+	pub(crate) fn zero_fee_commitments_and_dependencies() -> Self {
+		let mut ret = Self::anchors_zero_htlc_fee_and_dependencies();
+		<sealed::ChannelTypeContext as sealed::ZeroFeeCommitments>::set_required_bit(&mut ret.flags);
+		ret
+	}
 }
 
 impl ToBase32 for Bolt11InvoiceFeatures {
@@ -920,6 +963,12 @@ impl<T: sealed::AnchorsZeroFeeHtlcTx> Features<T> {
 	}
 }
 
+impl<T: sealed::ZeroFeeCommitments> Features<T> {
+	pub(crate) fn clear_zero_fee_commitments(&mut self) {
+		<T as sealed::ZeroFeeCommitments>::clear_bits(&mut self.flags);
+	}
+}
+
 #[cfg(test)]
 impl<T: sealed::UnknownFeature> Features<T> {
 	pub(crate) fn unknown() -> Self {
@@ -1060,6 +1109,7 @@ mod tests {
 		init_features.set_anchors_zero_fee_htlc_tx_optional();
 		init_features.set_route_blinding_optional();
 		init_features.set_shutdown_any_segwit_optional();
+		init_features.set_quiescence_optional();
 		init_features.set_onion_messages_optional();
 		init_features.set_channel_type_optional();
 		init_features.set_scid_privacy_optional();
@@ -1076,7 +1126,7 @@ mod tests {
 			// - var_onion_optin (req) | static_remote_key (req) | payment_secret(req)
 			// - basic_mpp | wumbo | option_anchors_zero_fee_htlc_tx
 			// - option_route_blinding | opt_shutdown_anysegwit
-			// - onion_messages
+			// - option_quiesce | onion_messages
 			// - option_channel_type | option_scid_alias
 			// - option_zeroconf
 			assert_eq!(node_features.flags.len(), 7);
@@ -1084,7 +1134,7 @@ mod tests {
 			assert_eq!(node_features.flags[1], 0b01010001);
 			assert_eq!(node_features.flags[2], 0b10001010);
 			assert_eq!(node_features.flags[3], 0b00001010);
-			assert_eq!(node_features.flags[4], 0b10000000);
+			assert_eq!(node_features.flags[4], 0b10001000);
 			assert_eq!(node_features.flags[5], 0b10100000);
 			assert_eq!(node_features.flags[6], 0b00001000);
 		}
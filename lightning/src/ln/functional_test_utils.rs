@@ -579,6 +579,7 @@ impl<'a, 'b, 'c> Drop for Node<'a, 'b, 'c> {
 
 			let broadcaster = test_utils::TestBroadcaster {
 				txn_broadcasted: Mutex::new(self.tx_broadcaster.txn_broadcasted.lock().unwrap().clone()),
+				packages_broadcasted: Mutex::new(self.tx_broadcaster.packages_broadcasted.lock().unwrap().clone()),
 				blocks: Arc::new(Mutex::new(self.tx_broadcaster.blocks.lock().unwrap().clone())),
 			};
 
@@ -786,6 +787,12 @@ pub fn remove_first_msg_event_to_node(msg_node_id: &PublicKey, msg_events: &mut
 		MessageSendEvent::SendChannelReestablish { node_id, .. } => {
 			node_id == msg_node_id
 		},
+		MessageSendEvent::SendPeerStorage { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendYourPeerStorage { node_id, .. } => {
+			node_id == msg_node_id
+		},
 		MessageSendEvent::SendChannelAnnouncement { node_id, .. } => {
 			node_id == msg_node_id
 		},
@@ -825,6 +832,15 @@ pub fn remove_first_msg_event_to_node(msg_node_id: &PublicKey, msg_events: &mut
 		MessageSendEvent::SendStfu { node_id, .. } => {
 			node_id == msg_node_id
 		},
+		MessageSendEvent::SendDynPropose { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendDynAck { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendDynReject { node_id, .. } => {
+			node_id == msg_node_id
+		},
 		MessageSendEvent::SendSplice { node_id, .. } => {
 			node_id == msg_node_id
 		},
@@ -1434,6 +1450,8 @@ macro_rules! check_spends {
 		{
 			$(
 			for outp in $spends_txn.output.iter() {
+				// Ephemeral anchors are allowed to be dust as they must be spent alongside their parent.
+				if outp.script_pubkey == $crate::ln::chan_utils::get_ephemeral_anchor_script_pubkey() { continue; }
 				assert!(outp.value >= outp.script_pubkey.dust_value().to_sat(), "Input tx output didn't meet dust limit");
 			}
 			)*
@@ -2094,7 +2112,7 @@ pub fn expect_payment_sent<CM: AChannelManager, H: NodeHolder<CM=CM>>(node: &H,
 		check_added_monitors(node, 1);
 	}
 	let expected_payment_id = match events[0] {
-		Event::PaymentSent { ref payment_id, ref payment_preimage, ref payment_hash, ref fee_paid_msat } => {
+		Event::PaymentSent { ref payment_id, ref payment_preimage, ref payment_hash, ref fee_paid_msat, .. } => {
 			assert_eq!(expected_payment_preimage, *payment_preimage);
 			assert_eq!(expected_payment_hash, *payment_hash);
 			if let Some(expected_fee_msat) = expected_fee_msat_opt {
@@ -2153,7 +2171,7 @@ pub fn expect_payment_forwarded<CM: AChannelManager, H: NodeHolder<CM=CM>>(
 	match event {
 		Event::PaymentForwarded {
 			fee_earned_msat, prev_channel_id, claim_from_onchain_tx, next_channel_id,
-			outbound_amount_forwarded_msat: _
+			outbound_amount_forwarded_msat: _, trampoline_route_fee_msat: _,
 		} => {
 			assert_eq!(fee_earned_msat, expected_fee);
 			if !upstream_force_closed {
@@ -0,0 +1,893 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Interactive construction of a transaction with a channel counterparty, as used to build the
+//! funding transaction of a dual-funded channel.
+//!
+//! Both peers take turns adding (or removing) inputs and outputs with `tx_add_input`,
+//! `tx_add_output`, `tx_remove_input` and `tx_remove_output` until each has sent a `tx_complete`
+//! in response to the other's, at which point the transaction is fully specified and may be
+//! validated and signed.
+
+use bitcoin::blockdata::constants::WITNESS_SCALE_FACTOR;
+use bitcoin::blockdata::locktime::absolute::LockTime;
+use bitcoin::blockdata::script::ScriptBuf;
+use bitcoin::blockdata::transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut};
+use bitcoin::policy::MAX_STANDARD_TX_WEIGHT;
+use bitcoin::VarInt;
+
+use crate::chain::chaininterface::fee_for_weight;
+use crate::events::MessageSendEvent;
+use crate::ln::channel::{TOTAL_BITCOIN_SUPPLY_SATOSHIS, TX_COMMON_FIELDS_WEIGHT};
+use crate::ln::msgs;
+use crate::ln::ChannelId;
+use crate::sign::{EntropySource, P2WPKH_WITNESS_WEIGHT};
+use crate::util::ser::{Readable, TransactionU16LenLimited, Writeable, Writer};
+
+use bitcoin::secp256k1::PublicKey;
+
+use crate::io;
+use crate::prelude::*;
+use core::fmt;
+use core::ops::Deref;
+
+/// The number of received `tx_add_input` messages during a negotiation at which point the
+/// negotiation MUST be failed.
+const MAX_RECEIVED_TX_ADD_INPUT_COUNT: u16 = 4096;
+
+/// The number of received `tx_add_output` messages during a negotiation at which point the
+/// negotiation MUST be failed.
+const MAX_RECEIVED_TX_ADD_OUTPUT_COUNT: u16 = 4096;
+
+/// The number of inputs or outputs that the constructed transaction may contain before the
+/// negotiation MUST be failed.
+const MAX_INPUTS_OUTPUTS_COUNT: usize = 252;
+
+/// The weight of the non-witness fields of an input with an empty `script_sig`.
+const BASE_INPUT_WEIGHT: u64 = (32 /* txid */ + 4 /* vout */ + 4 /* sequence */ +
+	1 /* script sig len */) * WITNESS_SCALE_FACTOR as u64;
+
+/// The weight of the witness spending a P2TR output via its key path with a default sighash.
+///
+/// This is also used as a lower bound for the witness weight of inputs with other spending
+/// conditions, which we can't know in advance.
+const P2TR_KEY_PATH_WITNESS_WEIGHT: u64 = 1 /* num stack items */ + 1 /* sig len */ + 64 /* sig */;
+
+/// A unique identifier for an input or output, chosen by the peer which added it. Serial IDs
+/// added by the initiator are always even and those added by the non-initiator always odd.
+type SerialId = u64;
+
+/// The reason an interactive transaction negotiation failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AbortReason {
+	/// A message was received while it was our turn to send one, or after the negotiation ended.
+	UnexpectedCounterpartyMessage,
+	/// The counterparty sent more `tx_add_input` messages than allowed.
+	ReceivedTooManyTxAddInputs,
+	/// The counterparty sent more `tx_add_output` messages than allowed.
+	ReceivedTooManyTxAddOutputs,
+	/// An input's `nSequence` would have made the transaction non-replaceable.
+	IncorrectInputSequenceValue,
+	/// A serial ID's parity did not match the role of the peer which sent it.
+	IncorrectSerialIdParity,
+	/// A `tx_remove_*` message referenced a serial ID which was never added.
+	SerialIdUnknown,
+	/// A serial ID was added twice.
+	DuplicateSerialId,
+	/// An input's `prevtx_out` does not reference a segwit output of its `prevtx`, or the
+	/// referenced output was already added.
+	PrevTxOutInvalid,
+	/// An output's value, or the sum of all input values, exceeded the total bitcoin supply.
+	ExceededMaximumSatsAllowed,
+	/// The transaction had more inputs or outputs than allowed.
+	ExceededNumberOfInputsOrOutputs,
+	/// The transaction would be too large to be relayed.
+	TransactionTooLarge,
+	/// An output's value was below its dust limit.
+	BelowDustLimit,
+	/// An output's script was not a standard segwit script.
+	InvalidOutputScript,
+	/// The counterparty's contributions did not pay for their share of the fee.
+	InsufficientFees,
+	/// The counterparty's outputs (including their share of the funding output) are worth more
+	/// than their inputs.
+	OutputsValueExceedsInputsValue,
+	/// The transaction is missing the funding output we expected.
+	MissingFundingOutput,
+	/// The funding output was added more than once.
+	DuplicateFundingOutput,
+}
+
+impl fmt::Display for AbortReason {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			AbortReason::UnexpectedCounterpartyMessage => "Unexpected message",
+			AbortReason::ReceivedTooManyTxAddInputs => "Too many tx_add_input messages",
+			AbortReason::ReceivedTooManyTxAddOutputs => "Too many tx_add_output messages",
+			AbortReason::IncorrectInputSequenceValue => "Input has a sequence value greater than 0xFFFFFFFD",
+			AbortReason::IncorrectSerialIdParity => "Parity for `serial_id` was incorrect",
+			AbortReason::SerialIdUnknown => "No input or output matching the provided `serial_id`",
+			AbortReason::DuplicateSerialId => "The `serial_id` already exists",
+			AbortReason::PrevTxOutInvalid => "Invalid previous transaction output",
+			AbortReason::ExceededMaximumSatsAllowed => "Output amount exceeded total bitcoin supply",
+			AbortReason::ExceededNumberOfInputsOrOutputs => "Too many inputs or outputs",
+			AbortReason::TransactionTooLarge => "Transaction weight is too large",
+			AbortReason::BelowDustLimit => "Output amount is below the dust limit",
+			AbortReason::InvalidOutputScript => "The output script is non-standard",
+			AbortReason::InsufficientFees => "Insufficient fees paid",
+			AbortReason::OutputsValueExceedsInputsValue => "Total value of outputs exceeds total value of inputs",
+			AbortReason::MissingFundingOutput => "No shared funding output found",
+			AbortReason::DuplicateFundingOutput => "More than one funding output found",
+		})
+	}
+}
+
+/// A message we should send to our counterparty as part of the negotiation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum InteractiveTxMessageSend {
+	TxAddInput(msgs::TxAddInput),
+	TxAddOutput(msgs::TxAddOutput),
+	TxComplete(msgs::TxComplete),
+	TxAbort(msgs::TxAbort),
+}
+
+impl InteractiveTxMessageSend {
+	pub fn into_msg_send_event(self, counterparty_node_id: PublicKey) -> MessageSendEvent {
+		match self {
+			InteractiveTxMessageSend::TxAddInput(msg) =>
+				MessageSendEvent::SendTxAddInput { node_id: counterparty_node_id, msg },
+			InteractiveTxMessageSend::TxAddOutput(msg) =>
+				MessageSendEvent::SendTxAddOutput { node_id: counterparty_node_id, msg },
+			InteractiveTxMessageSend::TxComplete(msg) =>
+				MessageSendEvent::SendTxComplete { node_id: counterparty_node_id, msg },
+			InteractiveTxMessageSend::TxAbort(msg) =>
+				MessageSendEvent::SendTxAbort { node_id: counterparty_node_id, msg },
+		}
+	}
+}
+
+/// The outcome of a successful negotiation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ConstructedTransaction {
+	/// The negotiated transaction, with its inputs and outputs ordered by serial ID and without
+	/// any witnesses.
+	pub tx: Transaction,
+	/// The index of the shared funding output in [`Self::tx`].
+	pub funding_output_index: u16,
+	/// The indices of the inputs we contributed in [`Self::tx`].
+	pub holder_input_indices: Vec<usize>,
+	/// The total value of the outputs spent by the inputs we contributed.
+	pub holder_inputs_value: u64,
+	/// The total value of the outputs spent by the inputs our counterparty contributed.
+	pub counterparty_inputs_value: u64,
+}
+
+impl ConstructedTransaction {
+	/// The number of inputs our counterparty contributed.
+	pub fn counterparty_input_count(&self) -> usize {
+		self.tx.input.len() - self.holder_input_indices.len()
+	}
+}
+
+impl Writeable for ConstructedTransaction {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		// A transaction constructed interactively has at most `MAX_INPUTS_OUTPUTS_COUNT` inputs.
+		let holder_input_indices: Vec<u16> = self.holder_input_indices.iter().map(|idx| *idx as u16).collect();
+		write_tlv_fields!(writer, {
+			(0, self.tx, required),
+			(2, self.funding_output_index, required),
+			(4, holder_input_indices, required_vec),
+			(6, self.holder_inputs_value, required),
+			(8, self.counterparty_inputs_value, required),
+		});
+		Ok(())
+	}
+}
+
+impl Readable for ConstructedTransaction {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, msgs::DecodeError> {
+		_init_and_read_len_prefixed_tlv_fields!(reader, {
+			(0, tx, required),
+			(2, funding_output_index, required),
+			(4, holder_input_indices, required_vec),
+			(6, holder_inputs_value, required),
+			(8, counterparty_inputs_value, required),
+		});
+		let tx: Transaction = tx.0.unwrap();
+		let holder_input_indices: Vec<u16> = holder_input_indices;
+		if holder_input_indices.iter().any(|idx| *idx as usize >= tx.input.len()) {
+			return Err(msgs::DecodeError::InvalidValue);
+		}
+		Ok(Self {
+			tx,
+			funding_output_index: funding_output_index.0.unwrap(),
+			holder_input_indices: holder_input_indices.into_iter().map(|idx| idx as usize).collect(),
+			holder_inputs_value: holder_inputs_value.0.unwrap(),
+			counterparty_inputs_value: counterparty_inputs_value.0.unwrap(),
+		})
+	}
+}
+
+/// Estimates the weight of an input spending `prev_output`, including its witness.
+///
+/// For anything other than P2WPKH and P2TR key path spends this is only a lower bound.
+pub(crate) fn estimate_input_weight(prev_output: &TxOut) -> u64 {
+	BASE_INPUT_WEIGHT + if prev_output.script_pubkey.is_v0_p2wpkh() {
+		P2WPKH_WITNESS_WEIGHT
+	} else {
+		P2TR_KEY_PATH_WITNESS_WEIGHT
+	}
+}
+
+/// Returns the weight of an output paying to `script_pubkey`.
+pub(crate) fn get_output_weight(script_pubkey: &ScriptBuf) -> u64 {
+	(8 /* value */ + VarInt(script_pubkey.len() as u64).len() as u64 +
+		script_pubkey.len() as u64) * WITNESS_SCALE_FACTOR as u64
+}
+
+/// Returns the output of `prevtx` spent by `input`, if `input` actually spends one of its outputs.
+fn get_prev_output<'a>(input: &TxIn, prevtx: &'a TransactionU16LenLimited) -> Option<&'a TxOut> {
+	let prevtx = prevtx.as_transaction();
+	if input.previous_output.txid != prevtx.txid() {
+		return None;
+	}
+	prevtx.output.get(input.previous_output.vout as usize)
+}
+
+/// Checks that the inputs a user wishes to contribute to a funding transaction each spend a segwit
+/// output of the provided previous transaction, and that each previous transaction can be sent to
+/// our counterparty in a `tx_add_input`.
+pub(crate) fn check_funding_inputs(
+	funding_inputs: Vec<(TxIn, Transaction)>,
+) -> Result<Vec<(TxIn, TransactionU16LenLimited)>, String> {
+	let mut checked_inputs = Vec::with_capacity(funding_inputs.len());
+	for (input, prevtx) in funding_inputs {
+		let prevtx = TransactionU16LenLimited::new(prevtx).map_err(|_|
+			format!("Previous transaction for input {} is too large", input.previous_output))?;
+		match get_prev_output(&input, &prevtx) {
+			Some(prev_output) if prev_output.script_pubkey.is_witness_program() => {},
+			Some(_) => return Err(format!("Input {} does not spend a segwit output", input.previous_output)),
+			None => return Err(format!("Input {} does not spend an output of its provided previous transaction",
+				input.previous_output)),
+		}
+		checked_inputs.push((input, prevtx));
+	}
+	if checked_inputs.len() > MAX_INPUTS_OUTPUTS_COUNT {
+		return Err(format!("Cannot contribute more than {} inputs", MAX_INPUTS_OUTPUTS_COUNT));
+	}
+	Ok(checked_inputs)
+}
+
+/// Calculates the value of the change output, if any, for our contribution to a funding
+/// transaction, given the inputs we add (see [`check_funding_inputs`]) and the amount we wish to
+/// contribute to the channel.
+///
+/// The fee we pay covers our inputs and change output and, if we're the initiator, the common
+/// transaction fields and the shared funding output. Returns `Err` if our inputs aren't enough to
+/// pay for our contribution and fees, and `Ok(None)` if any change would be dust and is thus
+/// better left to fees.
+pub(crate) fn calculate_change_output_value(
+	is_initiator: bool, our_contribution_satoshis: u64,
+	funding_inputs: &[(TxIn, TransactionU16LenLimited)], funding_output_script: &ScriptBuf,
+	change_script: &ScriptBuf, funding_feerate_sat_per_1000_weight: u32,
+) -> Result<Option<u64>, ()> {
+	let mut inputs_value: u64 = 0;
+	let mut weight = 0;
+	for (input, prevtx) in funding_inputs.iter() {
+		let prev_output = get_prev_output(input, prevtx).ok_or(())?;
+		inputs_value = inputs_value.saturating_add(prev_output.value);
+		weight += estimate_input_weight(prev_output);
+	}
+	if is_initiator {
+		weight += TX_COMMON_FIELDS_WEIGHT + get_output_weight(funding_output_script);
+	}
+
+	let fee_without_change = fee_for_weight(funding_feerate_sat_per_1000_weight, weight);
+	let remaining_value = inputs_value
+		.checked_sub(our_contribution_satoshis)
+		.and_then(|value| value.checked_sub(fee_without_change))
+		.ok_or(())?;
+
+	let change_output_fee = fee_for_weight(funding_feerate_sat_per_1000_weight,
+		weight + get_output_weight(change_script)) - fee_without_change;
+	match remaining_value.checked_sub(change_output_fee) {
+		Some(change_value) if change_value >= change_script.dust_value().to_sat() => Ok(Some(change_value)),
+		_ => Ok(None),
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct InteractiveTxInput {
+	input: TxIn,
+	prev_output: TxOut,
+}
+
+/// Where we are in the turn-based negotiation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NegotiationState {
+	/// We've sent a message and are waiting on our counterparty's response.
+	AwaitingCounterparty { holder_sent_tx_complete: bool },
+	/// Both peers have sent consecutive `tx_complete` messages.
+	Complete,
+	/// The negotiation has failed and no further messages may be handled.
+	Aborted,
+}
+
+/// Drives the construction of a transaction with our counterparty, contributing our own inputs
+/// and outputs and validating theirs.
+///
+/// The initiator adds the shared funding output, whose value each peer pays for its own
+/// contribution to.
+pub(crate) struct InteractiveTxConstructor {
+	holder_is_initiator: bool,
+	channel_id: ChannelId,
+	tx_locktime: LockTime,
+	feerate_sat_per_kw: u32,
+	funding_output: TxOut,
+	counterparty_funding_satoshis: u64,
+	inputs: HashMap<SerialId, InteractiveTxInput>,
+	prevtx_outpoints: HashSet<OutPoint>,
+	outputs: HashMap<SerialId, TxOut>,
+	received_tx_add_input_count: u16,
+	received_tx_add_output_count: u16,
+	inputs_to_contribute: Vec<(SerialId, TxIn, TransactionU16LenLimited)>,
+	outputs_to_contribute: Vec<(SerialId, TxOut)>,
+	state: NegotiationState,
+}
+
+impl InteractiveTxConstructor {
+	/// Creates a new constructor, returning the first message to send if we're the initiator.
+	///
+	/// The inputs we contribute are expected to have been checked with [`check_funding_inputs`].
+	/// If we're the initiator, `outputs_to_contribute` must include the `funding_output`.
+	pub fn new<ES: Deref>(
+		entropy_source: &ES, channel_id: ChannelId, feerate_sat_per_kw: u32, holder_is_initiator: bool,
+		tx_locktime: LockTime, inputs_to_contribute: Vec<(TxIn, TransactionU16LenLimited)>,
+		outputs_to_contribute: Vec<TxOut>, funding_output: TxOut, counterparty_funding_satoshis: u64,
+	) -> (Self, Option<InteractiveTxMessageSend>)
+	where ES::Target: EntropySource
+	{
+		debug_assert_eq!(holder_is_initiator, outputs_to_contribute.contains(&funding_output));
+		let generate_serial_id = || {
+			let mut bytes = [0u8; 8];
+			bytes.copy_from_slice(&entropy_source.get_secure_random_bytes()[..8]);
+			let serial_id = u64::from_be_bytes(bytes);
+			if holder_is_initiator { serial_id & !1 } else { serial_id | 1 }
+		};
+		// We pop our contributions off the back, so reverse them to send them in the given order.
+		let inputs_to_contribute = inputs_to_contribute.into_iter().rev()
+			.map(|(input, prevtx)| (generate_serial_id(), input, prevtx)).collect();
+		let outputs_to_contribute = outputs_to_contribute.into_iter().rev()
+			.map(|output| (generate_serial_id(), output)).collect();
+
+		let mut constructor = Self {
+			holder_is_initiator,
+			channel_id,
+			tx_locktime,
+			feerate_sat_per_kw,
+			funding_output,
+			counterparty_funding_satoshis,
+			inputs: HashMap::new(),
+			prevtx_outpoints: HashSet::new(),
+			outputs: HashMap::new(),
+			received_tx_add_input_count: 0,
+			received_tx_add_output_count: 0,
+			inputs_to_contribute,
+			outputs_to_contribute,
+			state: NegotiationState::AwaitingCounterparty { holder_sent_tx_complete: false },
+		};
+		let first_msg = if holder_is_initiator {
+			Some(constructor.next_holder_message())
+		} else {
+			None
+		};
+		(constructor, first_msg)
+	}
+
+	/// Returns true if the negotiation has completed.
+	#[cfg(test)]
+	pub fn is_complete(&self) -> bool {
+		self.state == NegotiationState::Complete
+	}
+
+	fn is_serial_id_valid_for_counterparty(&self, serial_id: SerialId) -> bool {
+		// The counterparty's serial IDs are odd if we're the initiator and even otherwise.
+		self.holder_is_initiator == (serial_id % 2 == 1)
+	}
+
+	fn abort(&mut self, reason: AbortReason) -> AbortReason {
+		self.state = NegotiationState::Aborted;
+		reason
+	}
+
+	/// Checks that it's our counterparty's turn to send a message.
+	fn check_counterparty_turn(&mut self) -> Result<bool, AbortReason> {
+		match self.state {
+			NegotiationState::AwaitingCounterparty { holder_sent_tx_complete } => Ok(holder_sent_tx_complete),
+			_ => Err(self.abort(AbortReason::UnexpectedCounterpartyMessage)),
+		}
+	}
+
+	/// Produces our next message, adding our own contributions to the transaction as we send them.
+	fn next_holder_message(&mut self) -> InteractiveTxMessageSend {
+		if let Some((serial_id, input, prevtx)) = self.inputs_to_contribute.pop() {
+			let prev_output = get_prev_output(&input, &prevtx)
+				.expect("Our inputs were checked when the negotiation started").clone();
+			let msg = msgs::TxAddInput {
+				channel_id: self.channel_id,
+				serial_id,
+				prevtx,
+				prevtx_out: input.previous_output.vout,
+				sequence: input.sequence.0,
+			};
+			self.prevtx_outpoints.insert(input.previous_output);
+			self.inputs.insert(serial_id, InteractiveTxInput { input, prev_output });
+			self.state = NegotiationState::AwaitingCounterparty { holder_sent_tx_complete: false };
+			InteractiveTxMessageSend::TxAddInput(msg)
+		} else if let Some((serial_id, output)) = self.outputs_to_contribute.pop() {
+			let msg = msgs::TxAddOutput {
+				channel_id: self.channel_id,
+				serial_id,
+				sats: output.value,
+				script: output.script_pubkey.clone(),
+			};
+			self.outputs.insert(serial_id, output);
+			self.state = NegotiationState::AwaitingCounterparty { holder_sent_tx_complete: false };
+			InteractiveTxMessageSend::TxAddOutput(msg)
+		} else {
+			self.state = NegotiationState::AwaitingCounterparty { holder_sent_tx_complete: true };
+			InteractiveTxMessageSend::TxComplete(msgs::TxComplete { channel_id: self.channel_id })
+		}
+	}
+
+	pub fn handle_tx_add_input(&mut self, msg: &msgs::TxAddInput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.check_counterparty_turn()?;
+		if !self.is_serial_id_valid_for_counterparty(msg.serial_id) {
+			return Err(self.abort(AbortReason::IncorrectSerialIdParity));
+		}
+		self.received_tx_add_input_count += 1;
+		if self.received_tx_add_input_count > MAX_RECEIVED_TX_ADD_INPUT_COUNT {
+			return Err(self.abort(AbortReason::ReceivedTooManyTxAddInputs));
+		}
+		if msg.sequence >= 0xFFFFFFFE {
+			return Err(self.abort(AbortReason::IncorrectInputSequenceValue));
+		}
+		let prevtx = msg.prevtx.as_transaction();
+		let prev_output = match prevtx.output.get(msg.prevtx_out as usize) {
+			Some(prev_output) if prev_output.script_pubkey.is_witness_program() => prev_output.clone(),
+			_ => return Err(self.abort(AbortReason::PrevTxOutInvalid)),
+		};
+		let previous_output = OutPoint { txid: prevtx.txid(), vout: msg.prevtx_out };
+		if self.prevtx_outpoints.contains(&previous_output) {
+			return Err(self.abort(AbortReason::PrevTxOutInvalid));
+		}
+		if self.inputs.contains_key(&msg.serial_id) {
+			return Err(self.abort(AbortReason::DuplicateSerialId));
+		}
+		self.prevtx_outpoints.insert(previous_output);
+		self.inputs.insert(msg.serial_id, InteractiveTxInput {
+			input: TxIn {
+				previous_output,
+				sequence: Sequence(msg.sequence),
+				..Default::default()
+			},
+			prev_output,
+		});
+		Ok(self.next_holder_message())
+	}
+
+	pub fn handle_tx_remove_input(&mut self, msg: &msgs::TxRemoveInput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.check_counterparty_turn()?;
+		if !self.is_serial_id_valid_for_counterparty(msg.serial_id) {
+			return Err(self.abort(AbortReason::IncorrectSerialIdParity));
+		}
+		match self.inputs.remove(&msg.serial_id) {
+			Some(removed) => { self.prevtx_outpoints.remove(&removed.input.previous_output); },
+			None => return Err(self.abort(AbortReason::SerialIdUnknown)),
+		}
+		Ok(self.next_holder_message())
+	}
+
+	pub fn handle_tx_add_output(&mut self, msg: &msgs::TxAddOutput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.check_counterparty_turn()?;
+		if !self.is_serial_id_valid_for_counterparty(msg.serial_id) {
+			return Err(self.abort(AbortReason::IncorrectSerialIdParity));
+		}
+		self.received_tx_add_output_count += 1;
+		if self.received_tx_add_output_count > MAX_RECEIVED_TX_ADD_OUTPUT_COUNT {
+			return Err(self.abort(AbortReason::ReceivedTooManyTxAddOutputs));
+		}
+		if msg.sats > TOTAL_BITCOIN_SUPPLY_SATOSHIS {
+			return Err(self.abort(AbortReason::ExceededMaximumSatsAllowed));
+		}
+		if msg.sats < msg.script.dust_value().to_sat() {
+			return Err(self.abort(AbortReason::BelowDustLimit));
+		}
+		if !msg.script.is_v0_p2wpkh() && !msg.script.is_v0_p2wsh() && !msg.script.is_v1_p2tr() {
+			return Err(self.abort(AbortReason::InvalidOutputScript));
+		}
+		if self.outputs.contains_key(&msg.serial_id) {
+			return Err(self.abort(AbortReason::DuplicateSerialId));
+		}
+		self.outputs.insert(msg.serial_id, TxOut { value: msg.sats, script_pubkey: msg.script.clone() });
+		Ok(self.next_holder_message())
+	}
+
+	pub fn handle_tx_remove_output(&mut self, msg: &msgs::TxRemoveOutput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.check_counterparty_turn()?;
+		if !self.is_serial_id_valid_for_counterparty(msg.serial_id) {
+			return Err(self.abort(AbortReason::IncorrectSerialIdParity));
+		}
+		if self.outputs.remove(&msg.serial_id).is_none() {
+			return Err(self.abort(AbortReason::SerialIdUnknown));
+		}
+		Ok(self.next_holder_message())
+	}
+
+	/// Handles a `tx_complete`, returning the message we should respond with, if any, and the
+	/// constructed transaction if this completed the negotiation.
+	pub fn handle_tx_complete(&mut self, _msg: &msgs::TxComplete)
+	-> Result<(Option<InteractiveTxMessageSend>, Option<ConstructedTransaction>), AbortReason> {
+		let holder_sent_tx_complete = self.check_counterparty_turn()?;
+		if holder_sent_tx_complete {
+			return self.complete_negotiation().map(|tx| (None, Some(tx)));
+		}
+		let msg = self.next_holder_message();
+		if let InteractiveTxMessageSend::TxComplete(_) = msg {
+			self.complete_negotiation().map(|tx| (Some(msg), Some(tx)))
+		} else {
+			Ok((Some(msg), None))
+		}
+	}
+
+	/// Validates the transaction once both peers have sent `tx_complete` and builds it.
+	fn complete_negotiation(&mut self) -> Result<ConstructedTransaction, AbortReason> {
+		match self.build_transaction() {
+			Ok(tx) => {
+				self.state = NegotiationState::Complete;
+				Ok(tx)
+			},
+			Err(reason) => Err(self.abort(reason)),
+		}
+	}
+
+	fn build_transaction(&self) -> Result<ConstructedTransaction, AbortReason> {
+		if self.inputs.len() > MAX_INPUTS_OUTPUTS_COUNT || self.outputs.len() > MAX_INPUTS_OUTPUTS_COUNT {
+			return Err(AbortReason::ExceededNumberOfInputsOrOutputs);
+		}
+
+		let mut inputs: Vec<(&SerialId, &InteractiveTxInput)> = self.inputs.iter().collect();
+		inputs.sort_unstable_by_key(|(serial_id, _)| **serial_id);
+		let mut outputs: Vec<(&SerialId, &TxOut)> = self.outputs.iter().collect();
+		outputs.sort_unstable_by_key(|(serial_id, _)| **serial_id);
+
+		let mut funding_output_index = None;
+		for (idx, (_, output)) in outputs.iter().enumerate() {
+			if output.script_pubkey == self.funding_output.script_pubkey {
+				if funding_output_index.is_some() {
+					return Err(AbortReason::DuplicateFundingOutput);
+				}
+				if output.value != self.funding_output.value {
+					return Err(AbortReason::MissingFundingOutput);
+				}
+				funding_output_index = Some(idx as u16);
+			}
+		}
+		let funding_output_index = funding_output_index.ok_or(AbortReason::MissingFundingOutput)?;
+
+		let mut holder_input_indices = Vec::new();
+		let mut holder_inputs_value: u64 = 0;
+		let mut counterparty_inputs_value: u64 = 0;
+		let mut counterparty_weight = 0;
+		for (idx, (serial_id, input)) in inputs.iter().enumerate() {
+			if self.is_serial_id_valid_for_counterparty(**serial_id) {
+				counterparty_inputs_value = counterparty_inputs_value.saturating_add(input.prev_output.value);
+				counterparty_weight += estimate_input_weight(&input.prev_output);
+			} else {
+				holder_inputs_value = holder_inputs_value.saturating_add(input.prev_output.value);
+				holder_input_indices.push(idx);
+			}
+		}
+		if holder_inputs_value.saturating_add(counterparty_inputs_value) > TOTAL_BITCOIN_SUPPLY_SATOSHIS {
+			return Err(AbortReason::ExceededMaximumSatsAllowed);
+		}
+
+		// The counterparty's share of the funding output counts towards the value they spend,
+		// regardless of which peer added it.
+		let mut counterparty_outputs_value = self.counterparty_funding_satoshis;
+		for (serial_id, output) in outputs.iter() {
+			if !self.is_serial_id_valid_for_counterparty(**serial_id) { continue; }
+			counterparty_weight += get_output_weight(&output.script_pubkey);
+			if output.script_pubkey != self.funding_output.script_pubkey {
+				counterparty_outputs_value = counterparty_outputs_value.saturating_add(output.value);
+			}
+		}
+		if !self.holder_is_initiator {
+			counterparty_weight += TX_COMMON_FIELDS_WEIGHT;
+		}
+		let counterparty_fees_paid = counterparty_inputs_value.checked_sub(counterparty_outputs_value)
+			.ok_or(AbortReason::OutputsValueExceedsInputsValue)?;
+		if counterparty_fees_paid < fee_for_weight(self.feerate_sat_per_kw, counterparty_weight) {
+			return Err(AbortReason::InsufficientFees);
+		}
+
+		let tx = Transaction {
+			version: 2,
+			lock_time: self.tx_locktime,
+			input: inputs.iter().map(|(_, input)| input.input.clone()).collect(),
+			output: outputs.iter().map(|(_, output)| (*output).clone()).collect(),
+		};
+		let estimated_witness_weight: u64 = inputs.iter()
+			.map(|(_, input)| estimate_input_weight(&input.prev_output) - BASE_INPUT_WEIGHT)
+			.sum();
+		if tx.weight().to_wu() + estimated_witness_weight > MAX_STANDARD_TX_WEIGHT as u64 {
+			return Err(AbortReason::TransactionTooLarge);
+		}
+
+		Ok(ConstructedTransaction {
+			tx,
+			funding_output_index,
+			holder_input_indices,
+			holder_inputs_value,
+			counterparty_inputs_value,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use bitcoin::blockdata::opcodes;
+	use bitcoin::blockdata::script::Builder;
+	use bitcoin::hashes::Hash;
+	use bitcoin::{Network, PubkeyHash, WPubkeyHash, WScriptHash};
+
+	use crate::util::test_utils::TestKeysInterface;
+
+	const FEERATE: u32 = 253;
+
+	fn p2wpkh_script(seed: u8) -> ScriptBuf {
+		ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::from_byte_array([seed; 20]))
+	}
+
+	fn funding_output(value: u64) -> TxOut {
+		TxOut { value, script_pubkey: ScriptBuf::new_v0_p2wsh(&WScriptHash::from_byte_array([42; 32])) }
+	}
+
+	fn funding_input(seed: u8, value: u64) -> (TxIn, TransactionU16LenLimited) {
+		let prevtx = Transaction {
+			version: 2,
+			lock_time: LockTime::from_consensus(seed as u32),
+			input: vec![],
+			output: vec![TxOut { value, script_pubkey: p2wpkh_script(seed) }],
+		};
+		let input = TxIn {
+			previous_output: OutPoint { txid: prevtx.txid(), vout: 0 },
+			sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+			..Default::default()
+		};
+		(input, TransactionU16LenLimited::new(prevtx).unwrap())
+	}
+
+	fn constructor(
+		keys: &TestKeysInterface, is_initiator: bool, inputs: Vec<(TxIn, TransactionU16LenLimited)>,
+		outputs: Vec<TxOut>, funding_output: TxOut, counterparty_funding_satoshis: u64,
+	) -> (InteractiveTxConstructor, Option<InteractiveTxMessageSend>) {
+		InteractiveTxConstructor::new(&keys, ChannelId::new_zero(), FEERATE, is_initiator,
+			LockTime::ZERO, inputs, outputs, funding_output, counterparty_funding_satoshis)
+	}
+
+	/// Drives a negotiation between two constructors to completion, returning the transactions
+	/// each side built or the first failure.
+	fn do_negotiation(
+		initiator: &mut InteractiveTxConstructor, first_msg: InteractiveTxMessageSend,
+		acceptor: &mut InteractiveTxConstructor,
+	) -> Result<(ConstructedTransaction, ConstructedTransaction), AbortReason> {
+		let mut initiator_tx = None;
+		let mut acceptor_tx = None;
+		let mut next_msg = Some(first_msg);
+		let mut initiator_sent = true;
+		while let Some(msg) = next_msg.take() {
+			let receiver = if initiator_sent { &mut *acceptor } else { &mut *initiator };
+			let (response, tx) = match msg {
+				InteractiveTxMessageSend::TxAddInput(msg) => (Some(receiver.handle_tx_add_input(&msg)?), None),
+				InteractiveTxMessageSend::TxAddOutput(msg) => (Some(receiver.handle_tx_add_output(&msg)?), None),
+				InteractiveTxMessageSend::TxComplete(msg) => receiver.handle_tx_complete(&msg)?,
+				InteractiveTxMessageSend::TxAbort(_) => panic!("The constructor never sends tx_abort"),
+			};
+			if let Some(tx) = tx {
+				if initiator_sent { acceptor_tx = Some(tx); } else { initiator_tx = Some(tx); }
+			}
+			// A `tx_complete` sent in response to a `tx_complete` also completes the sender's
+			// negotiation, so the final `tx_complete` must still be delivered.
+			next_msg = response;
+			initiator_sent = !initiator_sent;
+		}
+		Ok((initiator_tx.unwrap(), acceptor_tx.unwrap()))
+	}
+
+	#[test]
+	fn test_dual_funded_negotiation() {
+		let initiator_keys = TestKeysInterface::new(&[1; 32], Network::Testnet);
+		let acceptor_keys = TestKeysInterface::new(&[2; 32], Network::Testnet);
+		let funding_output = funding_output(150_000);
+		let initiator_change = TxOut { value: 49_000, script_pubkey: p2wpkh_script(3) };
+
+		let (mut initiator, first_msg) = constructor(&initiator_keys, true,
+			vec![funding_input(1, 150_000)], vec![funding_output.clone(), initiator_change.clone()],
+			funding_output.clone(), 50_000);
+		let (mut acceptor, none_msg) = constructor(&acceptor_keys, false,
+			vec![funding_input(2, 60_000)], vec![], funding_output.clone(), 100_000);
+		assert!(none_msg.is_none());
+
+		let (initiator_tx, acceptor_tx) = do_negotiation(&mut initiator, first_msg.unwrap(), &mut acceptor).unwrap();
+		assert!(initiator.is_complete() && acceptor.is_complete());
+		assert_eq!(initiator_tx.tx, acceptor_tx.tx);
+		assert_eq!(initiator_tx.tx.input.len(), 2);
+		assert_eq!(initiator_tx.tx.output.len(), 2);
+		assert_eq!(initiator_tx.funding_output_index, acceptor_tx.funding_output_index);
+		assert_eq!(initiator_tx.tx.output[initiator_tx.funding_output_index as usize], funding_output);
+		assert_eq!(initiator_tx.holder_inputs_value, 150_000);
+		assert_eq!(initiator_tx.counterparty_inputs_value, 60_000);
+		assert_eq!(acceptor_tx.holder_inputs_value, 60_000);
+		assert_eq!(initiator_tx.holder_input_indices.len(), 1);
+		assert_eq!(acceptor_tx.counterparty_input_count(), 1);
+		assert_ne!(initiator_tx.holder_input_indices, acceptor_tx.holder_input_indices);
+	}
+
+	#[test]
+	fn test_negotiation_insufficient_counterparty_fees() {
+		let initiator_keys = TestKeysInterface::new(&[1; 32], Network::Testnet);
+		let acceptor_keys = TestKeysInterface::new(&[2; 32], Network::Testnet);
+		let funding_output = funding_output(150_000);
+
+		// The acceptor's input only covers its contribution, leaving nothing for fees.
+		let (mut initiator, first_msg) = constructor(&initiator_keys, true,
+			vec![funding_input(1, 100_000)], vec![funding_output.clone()], funding_output.clone(), 50_000);
+		let (mut acceptor, _) = constructor(&acceptor_keys, false,
+			vec![funding_input(2, 50_000)], vec![], funding_output.clone(), 100_000);
+		assert_eq!(do_negotiation(&mut initiator, first_msg.unwrap(), &mut acceptor),
+			Err(AbortReason::InsufficientFees));
+
+		// The acceptor contributes no inputs at all, but claims a share of the funding output.
+		let (mut initiator, first_msg) = constructor(&initiator_keys, true,
+			vec![funding_input(1, 200_000)], vec![funding_output.clone()], funding_output.clone(), 50_000);
+		let (mut acceptor, _) = constructor(&acceptor_keys, false, vec![], vec![],
+			funding_output.clone(), 100_000);
+		assert_eq!(do_negotiation(&mut initiator, first_msg.unwrap(), &mut acceptor),
+			Err(AbortReason::OutputsValueExceedsInputsValue));
+	}
+
+	#[test]
+	fn test_negotiation_missing_funding_output() {
+		let initiator_keys = TestKeysInterface::new(&[1; 32], Network::Testnet);
+		let acceptor_keys = TestKeysInterface::new(&[2; 32], Network::Testnet);
+		let funding_output = funding_output(150_000);
+		let wrong_funding_output = TxOut { value: 140_000, ..funding_output.clone() };
+
+		let (mut initiator, first_msg) = constructor(&initiator_keys, true,
+			vec![funding_input(1, 200_000)], vec![wrong_funding_output.clone()], wrong_funding_output, 0);
+		let (mut acceptor, _) = constructor(&acceptor_keys, false, vec![], vec![],
+			funding_output.clone(), 150_000);
+		assert_eq!(do_negotiation(&mut initiator, first_msg.unwrap(), &mut acceptor),
+			Err(AbortReason::MissingFundingOutput));
+	}
+
+	#[test]
+	fn test_invalid_counterparty_messages() {
+		let keys = TestKeysInterface::new(&[2; 32], Network::Testnet);
+		let funding_output = funding_output(150_000);
+		let (_, prevtx) = funding_input(1, 100_000);
+		let add_input = msgs::TxAddInput {
+			channel_id: ChannelId::new_zero(), serial_id: 2, prevtx: prevtx.clone(), prevtx_out: 0,
+			sequence: Sequence::ENABLE_RBF_NO_LOCKTIME.0,
+		};
+
+		let new_acceptor = || constructor(&keys, false, vec![], vec![], funding_output.clone(), 150_000).0;
+
+		// The initiator's serial IDs must be even.
+		assert_eq!(new_acceptor().handle_tx_add_input(&msgs::TxAddInput { serial_id: 3, ..add_input.clone() }),
+			Err(AbortReason::IncorrectSerialIdParity));
+		// Inputs must signal RBF.
+		assert_eq!(new_acceptor().handle_tx_add_input(&msgs::TxAddInput { sequence: 0xFFFFFFFE, ..add_input.clone() }),
+			Err(AbortReason::IncorrectInputSequenceValue));
+		// The referenced output must exist and be segwit.
+		assert_eq!(new_acceptor().handle_tx_add_input(&msgs::TxAddInput { prevtx_out: 1, ..add_input.clone() }),
+			Err(AbortReason::PrevTxOutInvalid));
+		let non_segwit_prevtx = Transaction {
+			output: vec![TxOut { value: 100_000, script_pubkey: ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array([1; 20])) }],
+			..prevtx.clone().into_transaction()
+		};
+		assert_eq!(new_acceptor().handle_tx_add_input(&msgs::TxAddInput {
+			prevtx: TransactionU16LenLimited::new(non_segwit_prevtx).unwrap(), ..add_input.clone()
+		}), Err(AbortReason::PrevTxOutInvalid));
+
+		// The same output can't be spent twice, nor can a serial ID be reused.
+		let mut acceptor = new_acceptor();
+		assert!(acceptor.handle_tx_add_input(&add_input).is_ok());
+		assert_eq!(acceptor.handle_tx_add_input(&msgs::TxAddInput { serial_id: 4, ..add_input.clone() }),
+			Err(AbortReason::PrevTxOutInvalid));
+		let mut acceptor = new_acceptor();
+		assert!(acceptor.handle_tx_add_input(&add_input).is_ok());
+		let (_, other_prevtx) = funding_input(3, 100_000);
+		assert_eq!(acceptor.handle_tx_add_input(&msgs::TxAddInput { prevtx: other_prevtx, ..add_input.clone() }),
+			Err(AbortReason::DuplicateSerialId));
+		// Once aborted, no further messages are accepted.
+		assert_eq!(acceptor.handle_tx_add_input(&add_input), Err(AbortReason::UnexpectedCounterpartyMessage));
+
+		// Inputs can be removed, but only if they were added.
+		let mut acceptor = new_acceptor();
+		assert!(acceptor.handle_tx_add_input(&add_input).is_ok());
+		assert!(acceptor.handle_tx_remove_input(&msgs::TxRemoveInput { channel_id: ChannelId::new_zero(), serial_id: 2 }).is_ok());
+		assert_eq!(acceptor.handle_tx_remove_input(&msgs::TxRemoveInput { channel_id: ChannelId::new_zero(), serial_id: 2 }),
+			Err(AbortReason::SerialIdUnknown));
+
+		// Outputs must be standard and above the dust limit.
+		let add_output = msgs::TxAddOutput {
+			channel_id: ChannelId::new_zero(), serial_id: 2, sats: 10_000, script: p2wpkh_script(1),
+		};
+		assert!(new_acceptor().handle_tx_add_output(&add_output).is_ok());
+		assert_eq!(new_acceptor().handle_tx_add_output(&msgs::TxAddOutput { sats: 100, ..add_output.clone() }),
+			Err(AbortReason::BelowDustLimit));
+		assert_eq!(new_acceptor().handle_tx_add_output(&msgs::TxAddOutput {
+			script: Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), sats: 10_000, ..add_output.clone()
+		}), Err(AbortReason::InvalidOutputScript));
+		assert_eq!(new_acceptor().handle_tx_add_output(&msgs::TxAddOutput { sats: TOTAL_BITCOIN_SUPPLY_SATOSHIS + 1, ..add_output.clone() }),
+			Err(AbortReason::ExceededMaximumSatsAllowed));
+
+		// The initiator may not send two messages in a row.
+		let (mut initiator, _) = constructor(&keys, true, vec![], vec![funding_output.clone()],
+			funding_output.clone(), 0);
+		assert_eq!(initiator.handle_tx_complete(&msgs::TxComplete { channel_id: ChannelId::new_zero() }).map(|_| ()),
+			Ok(()));
+		assert_eq!(initiator.handle_tx_add_output(&msgs::TxAddOutput { serial_id: 3, ..add_output }),
+			Err(AbortReason::UnexpectedCounterpartyMessage));
+	}
+
+	#[test]
+	fn test_calculate_change_output_value() {
+		let funding_script = funding_output(0).script_pubkey;
+		let change_script = p2wpkh_script(3);
+		let inputs = vec![funding_input(1, 100_000)];
+
+		let input_weight = estimate_input_weight(&TxOut { value: 0, script_pubkey: p2wpkh_script(1) });
+		let change_weight = get_output_weight(&change_script);
+
+		// As the acceptor, we only pay for our input and change output.
+		let fee = fee_for_weight(FEERATE, input_weight + change_weight);
+		assert_eq!(calculate_change_output_value(false, 50_000, &inputs, &funding_script, &change_script, FEERATE),
+			Ok(Some(50_000 - fee)));
+
+		// As the initiator, we also pay for the common fields and the funding output.
+		let fee = fee_for_weight(FEERATE, input_weight + change_weight + TX_COMMON_FIELDS_WEIGHT +
+			get_output_weight(&funding_script));
+		assert_eq!(calculate_change_output_value(true, 50_000, &inputs, &funding_script, &change_script, FEERATE),
+			Ok(Some(50_000 - fee)));
+
+		// Dust change is left to fees, and insufficient inputs are rejected.
+		assert_eq!(calculate_change_output_value(false, 99_700, &inputs, &funding_script, &change_script, FEERATE),
+			Ok(None));
+		assert_eq!(calculate_change_output_value(false, 100_000, &inputs, &funding_script, &change_script, FEERATE),
+			Err(()));
+	}
+
+	#[test]
+	fn test_check_funding_inputs() {
+		let (input, prevtx) = funding_input(1, 100_000);
+		let prevtx = prevtx.into_transaction();
+		assert!(check_funding_inputs(vec![(input.clone(), prevtx.clone())]).is_ok());
+
+		let wrong_vout = TxIn { previous_output: OutPoint { vout: 1, ..input.previous_output }, ..input.clone() };
+		assert!(check_funding_inputs(vec![(wrong_vout, prevtx.clone())]).is_err());
+
+		let (_, other_prevtx) = funding_input(2, 100_000);
+		assert!(check_funding_inputs(vec![(input, other_prevtx.into_transaction())]).is_err());
+	}
+}
@@ -22,6 +22,7 @@ pub mod peer_handler;
 pub mod chan_utils;
 pub mod features;
 pub mod script;
+pub mod our_peer_storage;
 mod channel_id;
 
 #[cfg(fuzzing)]
@@ -38,7 +39,11 @@ pub(crate) mod channel;
 pub use channel_id::ChannelId;
 
 pub(crate) mod onion_utils;
+#[cfg(dual_funding)]
+pub(crate) mod interactivetxs;
 mod outbound_payment;
+pub(crate) mod reputation;
+pub(crate) mod dynamic_fees;
 pub mod wire;
 
 pub use onion_utils::create_payment_onion;
@@ -76,9 +81,30 @@ mod monitor_tests;
 #[cfg(test)]
 #[allow(unused_mut)]
 mod shutdown_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod quiescence_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod dynamic_commitment_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod peer_storage_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod async_payments_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod dns_resolution_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod offers_tests;
 #[cfg(all(test, async_signing))]
 #[allow(unused_mut)]
 mod async_signer_tests;
+#[cfg(all(test, dual_funding))]
+#[allow(unused_mut)]
+mod dual_funding_tests;
 
 pub use self::peer_channel_encryptor::LN_MAX_MSG_LEN;
 
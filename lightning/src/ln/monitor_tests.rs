@@ -16,10 +16,12 @@ use crate::chain::chaininterface::{LowerBoundedFeeEstimator, compute_feerate_sat
 use crate::events::bump_transaction::{BumpTransactionEvent, WalletSource};
 use crate::events::{Event, MessageSendEvent, MessageSendEventsProvider, ClosureReason, HTLCDestination};
 use crate::ln::channel;
+use crate::ln::chan_utils;
 use crate::ln::channelmanager::{BREAKDOWN_TIMEOUT, PaymentId, RecipientOnionFields};
-use crate::ln::msgs::ChannelMessageHandler;
+use crate::ln::msgs::{ChannelMessageHandler, ErrorAction};
 use crate::util::config::UserConfig;
 use crate::util::crypto::sign;
+use crate::util::errors::APIError;
 use crate::util::ser::Writeable;
 use crate::util::scid_utils::block_from_scid;
 use crate::util::test_utils;
@@ -267,6 +269,57 @@ fn chanmon_claim_value_coop_close() {
 	do_chanmon_claim_value_coop_close(true);
 }
 
+#[test]
+fn archive_fully_resolved_monitors() {
+	// Tests that a `ChannelMonitor` is only archived once its channel has closed and it has had no
+	// claimable balances for the requested number of blocks.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let (_, _, chan_id, funding_tx) =
+		create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 1_000_000);
+	let funding_outpoint = OutPoint { txid: funding_tx.txid(), index: 0 };
+
+	// Monitors for open channels are never archived.
+	nodes[0].chain_monitor.chain_monitor.archive_fully_resolved_channel_monitors(0);
+	assert_eq!(nodes[0].chain_monitor.chain_monitor.list_monitors(), vec![funding_outpoint]);
+
+	let (_, _, closing_tx) = close_channel(&nodes[0], &nodes[1], &chan_id, funding_tx, true);
+	check_closed_event!(nodes[0], 1, ClosureReason::CooperativeClosure, [nodes[1].node.get_our_node_id()], 1000000);
+	check_closed_event!(nodes[1], 1, ClosureReason::CooperativeClosure, [nodes[0].node.get_our_node_id()], 1000000);
+
+	mine_transaction(&nodes[0], &closing_tx);
+	connect_blocks(&nodes[0], ANTI_REORG_DELAY - 1);
+	assert!(get_monitor!(nodes[0], chan_id).get_claimable_balances().is_empty());
+	test_spendable_output(&nodes[0], &closing_tx, false);
+
+	// The first call only notes the height at which the monitor ran out of claimable balances.
+	nodes[0].chain_monitor.chain_monitor.archive_fully_resolved_channel_monitors(4032);
+	assert_eq!(nodes[0].chain_monitor.chain_monitor.list_monitors(), vec![funding_outpoint]);
+
+	connect_blocks(&nodes[0], 4031);
+	nodes[0].chain_monitor.chain_monitor.archive_fully_resolved_channel_monitors(4032);
+	assert_eq!(nodes[0].chain_monitor.chain_monitor.list_monitors(), vec![funding_outpoint]);
+	assert!(chanmon_cfgs[0].persister.archived_channels.lock().unwrap().is_empty());
+
+	connect_blocks(&nodes[0], 1);
+	nodes[0].chain_monitor.chain_monitor.archive_fully_resolved_channel_monitors(4032);
+	assert!(nodes[0].chain_monitor.chain_monitor.list_monitors().is_empty());
+	assert!(chanmon_cfgs[0].persister.archived_channels.lock().unwrap().contains(&funding_outpoint));
+
+	// Everything registered to be watched was for the archived monitor's channel, so won't be
+	// registered again when the remaining monitors are reloaded on shutdown.
+	let mut watched_txn = nodes[0].chain_source.watched_txn.lock().unwrap();
+	assert!(!watched_txn.is_empty());
+	assert!(watched_txn.iter().all(|(txid, _)| *txid == funding_outpoint.txid));
+	watched_txn.clear();
+	let mut watched_outputs = nodes[0].chain_source.watched_outputs.lock().unwrap();
+	assert!(watched_outputs.iter().all(|(outpoint, _)| *outpoint == funding_outpoint));
+	watched_outputs.clear();
+}
+
 fn sorted_vec<T: Ord>(mut v: Vec<T>) -> Vec<T> {
 	v.sort_unstable();
 	v
@@ -2253,6 +2306,7 @@ fn test_yield_anchors_events() {
 			let commitment_tx = txn.pop().unwrap();
 			check_spends!(commitment_tx, funding_tx);
 			check_spends!(anchor_tx, coinbase_tx, commitment_tx);
+			assert_eq!(nodes[0].tx_broadcaster.packages_broadcast(), vec![vec![commitment_tx.clone(), anchor_tx.clone()]]);
 			(commitment_tx, anchor_tx)
 		},
 		_ => panic!("Unexpected event"),
@@ -2333,6 +2387,173 @@ fn test_yield_anchors_events() {
 	nodes[1].node.get_and_clear_pending_msg_events();
 }
 
+#[test]
+fn test_zero_fee_commitments_force_close() {
+	// Tests that two parties supporting zero-fee commitments open a channel whose commitment
+	// transactions pay no fee, carry a single ephemeral anchor holding any trimmed value, and are
+	// never updated via `update_fee`. Upon force closing, the commitment transaction must be
+	// broadcast along with a v3 child spending its ephemeral anchor.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut zero_fee_config = test_default_channel_config();
+	zero_fee_config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx = true;
+	zero_fee_config.channel_handshake_config.negotiate_zero_fee_commitments = true;
+	zero_fee_config.manually_accept_inbound_channels = true;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(zero_fee_config), Some(zero_fee_config)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let (_, _, chan_id, funding_tx) = create_announced_chan_between_nodes_with_value(
+		&nodes, 0, 1, 1_000_000, 500_000_000
+	);
+	for node in nodes.iter() {
+		let channels = node.node.list_channels();
+		assert_eq!(channels.len(), 1);
+		assert!(channels[0].channel_type.as_ref().unwrap().supports_zero_fee_commitments());
+		assert_eq!(channels[0].feerate_sat_per_1000_weight, Some(0));
+	}
+
+	// Route a non-dust and a dust HTLC. The dust HTLC's value is trimmed to the ephemeral anchor.
+	route_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+	route_payment(&nodes[0], &[&nodes[1]], 100_000);
+
+	let holder_commitment_txn = get_local_commitment_txn!(nodes[0], chan_id);
+	assert_eq!(holder_commitment_txn.len(), 1);
+	let commitment_tx = &holder_commitment_txn[0];
+	assert_eq!(commitment_tx.version, 3);
+	assert_eq!(commitment_tx.output.len(), 4);
+	let (_, anchor_output) = chan_utils::get_ephemeral_anchor_output(commitment_tx).unwrap();
+	assert_eq!(anchor_output.value, 100);
+	assert_eq!(commitment_tx.output.iter().map(|output| output.value).sum::<u64>(), 1_000_000);
+
+	// Feerate changes never result in an `update_fee`.
+	*nodes[0].fee_estimator.sat_per_kw.lock().unwrap() *= 2;
+	nodes[0].node.timer_tick_occurred();
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	nodes[0].node.force_close_broadcasting_latest_txn(&chan_id, &nodes[1].node.get_our_node_id()).unwrap();
+	check_added_monitors(&nodes[0], 1);
+	check_closed_broadcast(&nodes[0], 1, true);
+	check_closed_event!(&nodes[0], 1, ClosureReason::HolderForceClosed, false,
+		 [nodes[1].node.get_our_node_id()], 1_000_000);
+
+	// The commitment transaction can't be relayed on its own, so nothing is broadcast until the
+	// bump event is handled.
+	assert!(nodes[0].tx_broadcaster.txn_broadcast().is_empty());
+
+	let mut holder_events = nodes[0].chain_monitor.chain_monitor.get_and_clear_pending_events();
+	assert_eq!(holder_events.len(), 1);
+	match holder_events.pop().unwrap() {
+		Event::BumpTransaction(event) => {
+			let package_target_feerate_sat_per_1000_weight = match event {
+				BumpTransactionEvent::ChannelClose {
+					ref commitment_tx, commitment_tx_fee_satoshis, ref anchor_descriptor,
+					package_target_feerate_sat_per_1000_weight, ..
+				} => {
+					assert_eq!(commitment_tx.version, 3);
+					assert_eq!(commitment_tx_fee_satoshis, 0);
+					assert!(anchor_descriptor.is_ephemeral());
+					package_target_feerate_sat_per_1000_weight
+				},
+				_ => panic!("Unexpected event"),
+			};
+			let coinbase_tx = Transaction {
+				version: 2,
+				lock_time: LockTime::ZERO,
+				input: vec![TxIn { ..Default::default() }],
+				output: vec![TxOut { // UTXO to attach fees to `anchor_tx`
+					value: Amount::ONE_BTC.to_sat(),
+					script_pubkey: nodes[0].wallet_source.get_change_script().unwrap(),
+				}],
+			};
+			nodes[0].wallet_source.add_utxo(bitcoin::OutPoint { txid: coinbase_tx.txid(), vout: 0 }, coinbase_tx.output[0].value);
+			nodes[0].bump_tx_handler.handle_event(&event);
+			let mut txn = nodes[0].tx_broadcaster.unique_txn_broadcast();
+			assert_eq!(txn.len(), 2);
+			let anchor_tx = txn.pop().unwrap();
+			let commitment_tx = txn.pop().unwrap();
+			assert_eq!(commitment_tx.input[0].previous_output.txid, funding_tx.txid());
+			assert_eq!(anchor_tx.version, 3);
+			assert!(anchor_tx.input[0].witness.is_empty());
+			check_spends!(anchor_tx, coinbase_tx, commitment_tx);
+			// Both must be submitted together, as the commitment can't be relayed on its own.
+			assert_eq!(nodes[0].tx_broadcaster.packages_broadcast(), vec![vec![commitment_tx.clone(), anchor_tx.clone()]]);
+
+			// The anchor transaction pays for the whole package.
+			let package_fee = coinbase_tx.output[0].value + anchor_output.value -
+				anchor_tx.output.iter().map(|output| output.value).sum::<u64>();
+			let package_weight = commitment_tx.weight().to_wu() + anchor_tx.weight().to_wu();
+			assert!(compute_feerate_sat_per_1000_weight(package_fee, package_weight) >=
+				package_target_feerate_sat_per_1000_weight);
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+fn test_zero_fee_commitments_max_accepted_htlcs() {
+	// Zero-fee commitment transactions must fit within the TRUC size limit, which only leaves room
+	// for `MAX_ZERO_FEE_COMMITMENT_HTLCS` HTLCs in each direction. Check that we never offer to
+	// accept more than that, even if configured to, and that we reject counterparties which do.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut zero_fee_config = test_default_channel_config();
+	zero_fee_config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx = true;
+	zero_fee_config.channel_handshake_config.negotiate_zero_fee_commitments = true;
+	zero_fee_config.channel_handshake_config.our_max_accepted_htlcs = chan_utils::MAX_HTLCS;
+	zero_fee_config.manually_accept_inbound_channels = true;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(zero_fee_config), Some(zero_fee_config)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 1_000_000, 0, 42, None, None).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannel, nodes[1].node.get_our_node_id());
+	assert!(open_channel.channel_type.as_ref().unwrap().supports_zero_fee_commitments());
+	assert_eq!(open_channel.max_accepted_htlcs, chan_utils::MAX_ZERO_FEE_COMMITMENT_HTLCS);
+
+	// An `open_channel` allowing more HTLCs than fit in a zero-fee commitment is rejected.
+	let mut bogus_open_channel = open_channel.clone();
+	bogus_open_channel.max_accepted_htlcs = chan_utils::MAX_ZERO_FEE_COMMITMENT_HTLCS + 1;
+	nodes[1].node.handle_open_channel(&nodes[0].node.get_our_node_id(), &bogus_open_channel);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match &events[0] {
+		Event::OpenChannelRequest { temporary_channel_id, .. } => {
+			match nodes[1].node.accept_inbound_channel(temporary_channel_id, &nodes[0].node.get_our_node_id(), 0) {
+				Err(APIError::ChannelUnavailable { err }) =>
+					assert_eq!(err, "max_accepted_htlcs was 115. It must not be larger than 114"),
+				_ => panic!("Unexpected result"),
+			}
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// Once accepted, our `accept_channel` is limited in the same way, as is our counterparty's.
+	nodes[1].node.handle_open_channel(&nodes[0].node.get_our_node_id(), &open_channel);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match &events[0] {
+		Event::OpenChannelRequest { temporary_channel_id, .. } => {
+			nodes[1].node.accept_inbound_channel(temporary_channel_id, &nodes[0].node.get_our_node_id(), 0).unwrap();
+		},
+		_ => panic!("Unexpected event"),
+	}
+	let mut accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannel, nodes[0].node.get_our_node_id());
+	assert_eq!(accept_channel.max_accepted_htlcs, chan_utils::MAX_ZERO_FEE_COMMITMENT_HTLCS);
+
+	accept_channel.max_accepted_htlcs = chan_utils::MAX_ZERO_FEE_COMMITMENT_HTLCS + 1;
+	nodes[0].node.handle_accept_channel(&nodes[1].node.get_our_node_id(), &accept_channel);
+	let msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	let reason_msg = match &msg_events[0] {
+		MessageSendEvent::HandleError { action: ErrorAction::SendErrorMessage { msg }, .. } => {
+			assert_eq!(msg.data, "max_accepted_htlcs was 115. It must not be larger than 114");
+			msg.data.clone()
+		},
+		_ => panic!("Unexpected event"),
+	};
+	check_closed_event!(nodes[0], 1, ClosureReason::ProcessingError { err: reason_msg },
+		[nodes[1].node.get_our_node_id()], 1_000_000);
+}
+
 #[test]
 fn test_anchors_aggregated_revoked_htlc_tx() {
 	// Test that `ChannelMonitor`s can properly detect and claim funds from a counterparty claiming
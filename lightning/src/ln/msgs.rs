@@ -55,7 +55,7 @@ use crate::io_extras::read_to_end;
 use crate::events::{EventsProvider, MessageSendEventsProvider};
 use crate::util::chacha20poly1305rfc::ChaChaPolyReadAdapter;
 use crate::util::logger;
-use crate::util::ser::{LengthReadable, LengthReadableArgs, Readable, ReadableArgs, Writeable, Writer, WithoutLength, FixedLengthReader, HighZeroBytesDroppedBigSize, Hostname, TransactionU16LenLimited, BigSize};
+use crate::util::ser::{LengthRead, LengthReadable, LengthReadableArgs, Readable, ReadableArgs, Writeable, Writer, WithoutLength, FixedLengthReader, HighZeroBytesDroppedBigSize, Hostname, TransactionU16LenLimited, BigSize};
 use crate::util::base32;
 
 use crate::routing::gossip::{NodeAlias, NodeId};
@@ -172,6 +172,29 @@ pub struct Pong {
 	pub byteslen: u16,
 }
 
+/// A [`peer_storage`] message to be sent to a peer which supports `option_provide_storage`,
+/// asking it to store the given blob on our behalf and return it to us via [`YourPeerStorage`]
+/// whenever we reconnect.
+///
+/// [`peer_storage`]: https://github.com/lightning/bolts/pull/1110
+// TODO(peer_storage): Update the spec link once the proposal is merged.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct PeerStorage {
+	/// The (encrypted) blob to be stored.
+	pub data: Vec<u8>,
+}
+
+/// A [`your_peer_storage`] message to be sent to or received from a peer, returning the latest
+/// blob it asked us to store via [`PeerStorage`].
+///
+/// [`your_peer_storage`]: https://github.com/lightning/bolts/pull/1110
+// TODO(peer_storage): Update the spec link once the proposal is merged.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct YourPeerStorage {
+	/// The (encrypted) blob being returned.
+	pub data: Vec<u8>,
+}
+
 /// An [`open_channel`] message to be sent to or received from a peer.
 ///
 /// Used in V1 channel establishment
@@ -451,6 +474,33 @@ pub struct Stfu {
 	pub initiator: u8,
 }
 
+/// A `dyn_propose` message sent by the quiescence initiator to propose changing the parameters of
+/// an existing channel, currently limited to its channel type.
+// TODO(dyn_commitments): Add spec link for `dyn_propose`; dynamic commitments are still in draft.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynPropose {
+	/// The channel ID of the channel to be updated
+	pub channel_id: ChannelId,
+	/// The channel type the channel's commitment transactions should be upgraded to
+	pub channel_type: ChannelTypeFeatures,
+}
+
+/// A `dyn_ack` message sent in response to a [`DynPropose`] to accept the proposed changes.
+// TODO(dyn_commitments): Add spec link for `dyn_ack`; dynamic commitments are still in draft.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynAck {
+	/// The channel ID of the channel to be updated
+	pub channel_id: ChannelId,
+}
+
+/// A `dyn_reject` message sent in response to a [`DynPropose`] to reject the proposed changes.
+// TODO(dyn_commitments): Add spec link for `dyn_reject`; dynamic commitments are still in draft.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynReject {
+	/// The channel ID of the channel which was to be updated
+	pub channel_id: ChannelId,
+}
+
 /// A splice message to be sent by or received from the stfu initiator (splice initiator).
 // TODO(splicing): Add spec link for `splice`; still in draft, using from https://github.com/lightning/bolts/pull/863
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -685,6 +735,17 @@ pub struct UpdateAddHTLC {
 	/// Provided if we are relaying or receiving a payment within a blinded path, to decrypt the onion
 	/// routing packet and the recipient-provided encrypted payload within.
 	pub blinding_point: Option<PublicKey>,
+	/// Set by an often-offline sender to request that its counterparty hold this HTLC until it
+	/// receives a [`ReleaseHeldHtlc`] onion message with the given secret.
+	///
+	/// [`ReleaseHeldHtlc`]: crate::onion_message::ReleaseHeldHtlc
+	pub hold_htlc: Option<[u8; 32]>,
+	/// The experimental HTLC endorsement signal, set by the sender of this message if it believes
+	/// the HTLC is being forwarded on behalf of an honest peer. Used to protect against channel
+	/// jamming, see [`HTLCEndorsementConfig`].
+	///
+	/// [`HTLCEndorsementConfig`]: crate::util::config::HTLCEndorsementConfig
+	pub endorsed: Option<bool>,
 }
 
  /// An onion message to be sent to or received from a peer.
@@ -798,6 +859,10 @@ pub struct ChannelReestablish {
 	pub my_current_per_commitment_point: PublicKey,
 	/// The next funding transaction ID
 	pub next_funding_txid: Option<Txid>,
+	/// The channel type the sender is currently using, allowing peers to detect a channel type
+	/// upgrade which only one side committed to before disconnecting.
+	// TODO(dyn_commitments): Update the TLV type once the dynamic commitments spec is finalized.
+	pub channel_type: Option<ChannelTypeFeatures>,
 }
 
 /// An [`announcement_signatures`] message to be sent to or received from a peer.
@@ -1471,6 +1536,14 @@ pub trait ChannelMessageHandler : MessageSendEventsProvider {
 	/// Handle an incoming `stfu` message from the given peer.
 	fn handle_stfu(&self, their_node_id: &PublicKey, msg: &Stfu);
 
+	// Dynamic commitments
+	/// Handle an incoming `dyn_propose` message from the given peer.
+	fn handle_dyn_propose(&self, their_node_id: &PublicKey, msg: &DynPropose);
+	/// Handle an incoming `dyn_ack` message from the given peer.
+	fn handle_dyn_ack(&self, their_node_id: &PublicKey, msg: &DynAck);
+	/// Handle an incoming `dyn_reject` message from the given peer.
+	fn handle_dyn_reject(&self, their_node_id: &PublicKey, msg: &DynReject);
+
 	// Splicing
 	/// Handle an incoming `splice` message from the given peer.
 	fn handle_splice(&self, their_node_id: &PublicKey, msg: &Splice);
@@ -1520,6 +1593,12 @@ pub trait ChannelMessageHandler : MessageSendEventsProvider {
 	/// Handle an incoming `announcement_signatures` message from the given peer.
 	fn handle_announcement_signatures(&self, their_node_id: &PublicKey, msg: &AnnouncementSignatures);
 
+	// Peer storage:
+	/// Handle an incoming `peer_storage` message from the given peer.
+	fn handle_peer_storage(&self, their_node_id: &PublicKey, msg: &PeerStorage);
+	/// Handle an incoming `your_peer_storage` message from the given peer.
+	fn handle_your_peer_storage(&self, their_node_id: &PublicKey, msg: &YourPeerStorage);
+
 	// Connection loss/reestablish:
 	/// Indicates a connection to the peer failed/an existing connection was lost.
 	fn peer_disconnected(&self, their_node_id: &PublicKey);
@@ -1683,13 +1762,29 @@ pub struct FinalOnionHopData {
 	pub total_msat: u64,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Information communicated in the onion to the recipient of an atomic multi-path (AMP) payment.
+///
+/// Each part of an AMP payment carries a share of a root seed chosen by the sender. Only once all
+/// parts have arrived can the recipient XOR the shares together to recover the root seed and
+/// derive the preimage of each part from it.
+pub struct AmpRecord {
+	/// This part's share of the payment's root seed.
+	pub root_share: [u8; 32],
+	/// Identifies the set of parts belonging to the same payment. This is the SHA256 hash of the
+	/// root seed, which allows the recipient to check the seed it reconstructs.
+	pub set_id: [u8; 32],
+	/// The index from which this part's preimage is derived, unique among the payment's parts.
+	pub child_index: u32,
+}
+
 mod fuzzy_internal_msgs {
 	use bitcoin::secp256k1::PublicKey;
 	use crate::blinded_path::payment::{PaymentConstraints, PaymentRelay};
 	use crate::prelude::*;
 	use crate::ln::{PaymentPreimage, PaymentSecret};
 	use crate::ln::features::BlindedHopFeatures;
-	use super::FinalOnionHopData;
+	use super::{AmpRecord, FinalOnionHopData, TrampolineOnionPacket};
 
 	// These types aren't intended to be pub, but are exposed for direct fuzzing (as we deserialize
 	// them from untrusted input):
@@ -1705,6 +1800,7 @@ mod fuzzy_internal_msgs {
 			payment_data: Option<FinalOnionHopData>,
 			payment_metadata: Option<Vec<u8>>,
 			keysend_preimage: Option<PaymentPreimage>,
+			amp_record: Option<AmpRecord>,
 			custom_tlvs: Vec<(u64, Vec<u8>)>,
 			amt_msat: u64,
 			outgoing_cltv_value: u32,
@@ -1723,7 +1819,15 @@ mod fuzzy_internal_msgs {
 			payment_secret: PaymentSecret,
 			payment_constraints: PaymentConstraints,
 			intro_node_blinding_point: Option<PublicKey>,
-		}
+			/// Set when paying a static invoice, which has no payment hash for the sender to use.
+			keysend_preimage: Option<PaymentPreimage>,
+		},
+		TrampolineEntrypoint {
+			/// The value, in msat, the sender intends for us to receive as the trampoline node.
+			amt_to_forward: u64,
+			multipath_trampoline_data: Option<FinalOnionHopData>,
+			trampoline_packet: TrampolineOnionPacket,
+		},
 	}
 
 	pub(crate) enum OutboundOnionPayload {
@@ -1737,6 +1841,7 @@ mod fuzzy_internal_msgs {
 			payment_data: Option<FinalOnionHopData>,
 			payment_metadata: Option<Vec<u8>>,
 			keysend_preimage: Option<PaymentPreimage>,
+			amp_record: Option<AmpRecord>,
 			custom_tlvs: Vec<(u64, Vec<u8>)>,
 			amt_msat: u64,
 			outgoing_cltv_value: u32,
@@ -1751,7 +1856,47 @@ mod fuzzy_internal_msgs {
 			outgoing_cltv_value: u32,
 			encrypted_tlvs: Vec<u8>,
 			intro_node_blinding_point: Option<PublicKey>, // Set if the introduction node of the blinded path is the final node
-		}
+			keysend_preimage: Option<PaymentPreimage>,
+		},
+		TrampolineEntrypoint {
+			amt_to_forward: u64,
+			outgoing_cltv_value: u32,
+			multipath_trampoline_data: Option<FinalOnionHopData>,
+			trampoline_packet: TrampolineOnionPacket,
+		},
+	}
+
+	/// A payload decrypted from a [`TrampolineOnionPacket`].
+	pub enum InboundTrampolinePayload {
+		Forward {
+			/// The value, in msat, to deliver to the next trampoline node.
+			amt_to_forward: u64,
+			outgoing_cltv_value: u32,
+			/// The node to which we should find a route and forward the payment.
+			outgoing_node_id: PublicKey,
+		},
+		Receive {
+			payment_data: Option<FinalOnionHopData>,
+			payment_metadata: Option<Vec<u8>>,
+			custom_tlvs: Vec<(u64, Vec<u8>)>,
+			amt_msat: u64,
+			outgoing_cltv_value: u32,
+		},
+	}
+
+	pub(crate) enum OutboundTrampolinePayload {
+		Forward {
+			amt_to_forward: u64,
+			outgoing_cltv_value: u32,
+			outgoing_node_id: PublicKey,
+		},
+		Receive {
+			payment_data: Option<FinalOnionHopData>,
+			payment_metadata: Option<Vec<u8>>,
+			custom_tlvs: Vec<(u64, Vec<u8>)>,
+			amt_msat: u64,
+			outgoing_cltv_value: u32,
+		},
 	}
 
 	pub struct DecodedOnionErrorPacket {
@@ -1800,6 +1945,40 @@ impl fmt::Debug for OnionPacket {
 	}
 }
 
+/// BOLT 4 trampoline onion packet, carried in the onion payload of the last hop of a route to a
+/// trampoline node and including hop data for the trampoline node and the hops after it.
+///
+/// Unlike an [`OnionPacket`], the hop data of a trampoline onion is not of a fixed size.
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct TrampolineOnionPacket {
+	/// BOLT 4 version number.
+	pub version: u8,
+	/// A random secp256k1 point, used to build the ECDH shared secret to decrypt `hop_data`.
+	pub public_key: PublicKey,
+	/// Encrypted payload for the next trampoline hop.
+	pub hop_data: Vec<u8>,
+	/// HMAC to verify the integrity of `hop_data`.
+	pub hmac: [u8; 32],
+}
+
+impl onion_utils::Packet for TrampolineOnionPacket {
+	type Data = Vec<u8>;
+	fn new(public_key: PublicKey, hop_data: Vec<u8>, hmac: [u8; 32]) -> Self {
+		Self {
+			version: 0,
+			public_key,
+			hop_data,
+			hmac,
+		}
+	}
+}
+
+impl fmt::Debug for TrampolineOnionPacket {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_fmt(format_args!("TrampolineOnionPacket version {} with hmac {:?}", self.version, &self.hmac[..]))
+	}
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub(crate) struct OnionErrorPacket {
 	// This really should be a constant size slice, but the spec lets these things be up to 128KB?
@@ -1896,11 +2075,50 @@ impl_writeable_msg!(AcceptChannelV2, {
 	(2, require_confirmed_inputs, option),
 });
 
+impl_writeable_msg!(PeerStorage, {
+	data,
+}, {});
+
+impl_writeable_msg!(YourPeerStorage, {
+	data,
+}, {});
+
 impl_writeable_msg!(Stfu, {
 	channel_id,
 	initiator,
 }, {});
 
+impl Writeable for DynPropose {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.channel_id.write(w)?;
+		// Unlike other feature types, channel type features are written without a length prefix
+		// as they usually live in a TLV, so prefix them here ourselves.
+		self.channel_type.encode().write(w)?;
+		encode_tlv_stream!(w, {});
+		Ok(())
+	}
+}
+
+impl Readable for DynPropose {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let channel_id = Readable::read(r)?;
+		let channel_type_bytes: Vec<u8> = Readable::read(r)?;
+		decode_tlv_stream!(r, {});
+		Ok(DynPropose {
+			channel_id,
+			channel_type: ChannelTypeFeatures::from_be_bytes(channel_type_bytes),
+		})
+	}
+}
+
+impl_writeable_msg!(DynAck, {
+	channel_id,
+}, {});
+
+impl_writeable_msg!(DynReject, {
+	channel_id,
+}, {});
+
 impl_writeable_msg!(Splice, {
 	channel_id,
 	chain_hash,
@@ -1990,6 +2208,7 @@ impl_writeable_msg!(ChannelReestablish, {
 	my_current_per_commitment_point,
 }, {
 	(0, next_funding_txid, option),
+	(1, channel_type, option),
 });
 
 impl_writeable_msg!(ClosingSigned,
@@ -2228,6 +2447,36 @@ impl Readable for OnionPacket {
 	}
 }
 
+impl Writeable for TrampolineOnionPacket {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.version.write(w)?;
+		self.public_key.write(w)?;
+		w.write_all(&self.hop_data)?;
+		self.hmac.write(w)?;
+		Ok(())
+	}
+}
+
+impl LengthReadable for TrampolineOnionPacket {
+	fn read<R: LengthRead>(r: &mut R) -> Result<Self, DecodeError> {
+		let version = Readable::read(r)?;
+		let public_key = Readable::read(r)?;
+
+		// 1 (version) + 33 (pubkey) + 32 (HMAC) = 66
+		let hop_data_len = r.total_bytes().checked_sub(66).ok_or(DecodeError::ShortRead)?;
+		let mut rd = FixedLengthReader::new(&mut *r, hop_data_len);
+		let hop_data = WithoutLength::<Vec<u8>>::read(&mut rd)?.0;
+
+		let hmac = Readable::read(r)?;
+		Ok(TrampolineOnionPacket {
+			version,
+			public_key,
+			hop_data,
+			hmac,
+		})
+	}
+}
+
 impl_writeable_msg!(UpdateAddHTLC, {
 	channel_id,
 	htlc_id,
@@ -2237,7 +2486,9 @@ impl_writeable_msg!(UpdateAddHTLC, {
 	onion_routing_packet,
 }, {
 	(0, blinding_point, option),
-	(65537, skimmed_fee_msat, option)
+	(65537, skimmed_fee_msat, option),
+	(65539, hold_htlc, option),
+	(106823, endorsed, option),
 });
 
 impl Readable for OnionMessage {
@@ -2278,6 +2529,23 @@ impl Readable for FinalOnionHopData {
 	}
 }
 
+impl Writeable for AmpRecord {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.root_share.write(w)?;
+		self.set_id.write(w)?;
+		HighZeroBytesDroppedBigSize(self.child_index).write(w)
+	}
+}
+
+impl Readable for AmpRecord {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let root_share = Readable::read(r)?;
+		let set_id = Readable::read(r)?;
+		let child_index: HighZeroBytesDroppedBigSize<u32> = Readable::read(r)?;
+		Ok(Self { root_share, set_id, child_index: child_index.0 })
+	}
+}
+
 impl Writeable for OutboundOnionPayload {
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
 		match self {
@@ -2289,7 +2557,7 @@ impl Writeable for OutboundOnionPayload {
 				});
 			},
 			Self::Receive {
-				ref payment_data, ref payment_metadata, ref keysend_preimage, amt_msat,
+				ref payment_data, ref payment_metadata, ref keysend_preimage, ref amp_record, amt_msat,
 				outgoing_cltv_value, ref custom_tlvs,
 			} => {
 				// We need to update [`ln::outbound_payment::RecipientOnionFields::with_custom_tlvs`]
@@ -2302,6 +2570,7 @@ impl Writeable for OutboundOnionPayload {
 					(2, HighZeroBytesDroppedBigSize(*amt_msat), required),
 					(4, HighZeroBytesDroppedBigSize(*outgoing_cltv_value), required),
 					(8, payment_data, option),
+					(14, amp_record, option),
 					(16, payment_metadata.as_ref().map(|m| WithoutLength(m)), option)
 				}, custom_tlvs.iter());
 			},
@@ -2313,14 +2582,26 @@ impl Writeable for OutboundOnionPayload {
 			},
 			Self::BlindedReceive {
 				amt_msat, total_msat, outgoing_cltv_value, encrypted_tlvs,
-				intro_node_blinding_point,
+				intro_node_blinding_point, keysend_preimage,
 			} => {
 				_encode_varint_length_prefixed_tlv!(w, {
 					(2, HighZeroBytesDroppedBigSize(*amt_msat), required),
 					(4, HighZeroBytesDroppedBigSize(*outgoing_cltv_value), required),
 					(10, *encrypted_tlvs, required_vec),
 					(12, intro_node_blinding_point, option),
-					(18, HighZeroBytesDroppedBigSize(*total_msat), required)
+					(18, HighZeroBytesDroppedBigSize(*total_msat), required),
+					(5482373484, keysend_preimage, option)
+				});
+			},
+			Self::TrampolineEntrypoint {
+				amt_to_forward, outgoing_cltv_value, ref multipath_trampoline_data,
+				ref trampoline_packet,
+			} => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(2, HighZeroBytesDroppedBigSize(*amt_to_forward), required),
+					(4, HighZeroBytesDroppedBigSize(*outgoing_cltv_value), required),
+					(8, multipath_trampoline_data, option),
+					(20, trampoline_packet, required)
 				});
 			},
 		}
@@ -2328,6 +2609,31 @@ impl Writeable for OutboundOnionPayload {
 	}
 }
 
+impl Writeable for OutboundTrampolinePayload {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		match self {
+			Self::Forward { amt_to_forward, outgoing_cltv_value, outgoing_node_id } => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(2, HighZeroBytesDroppedBigSize(*amt_to_forward), required),
+					(4, HighZeroBytesDroppedBigSize(*outgoing_cltv_value), required),
+					(14, outgoing_node_id, required)
+				});
+			},
+			Self::Receive {
+				ref payment_data, ref payment_metadata, ref custom_tlvs, amt_msat, outgoing_cltv_value,
+			} => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(2, HighZeroBytesDroppedBigSize(*amt_msat), required),
+					(4, HighZeroBytesDroppedBigSize(*outgoing_cltv_value), required),
+					(8, payment_data, option),
+					(16, payment_metadata.as_ref().map(|m| WithoutLength(m)), option)
+				}, custom_tlvs.iter());
+			},
+		}
+		Ok(())
+	}
+}
+
 impl<NS: Deref> ReadableArgs<(Option<PublicKey>, &NS)> for InboundOnionPayload where NS::Target: NodeSigner {
 	fn read<R: Read>(r: &mut R, args: (Option<PublicKey>, &NS)) -> Result<Self, DecodeError> {
 		let (update_add_blinding_point, node_signer) = args;
@@ -2341,6 +2647,8 @@ impl<NS: Deref> ReadableArgs<(Option<PublicKey>, &NS)> for InboundOnionPayload w
 		let mut payment_metadata: Option<WithoutLength<Vec<u8>>> = None;
 		let mut total_msat = None;
 		let mut keysend_preimage: Option<PaymentPreimage> = None;
+		let mut amp_record: Option<AmpRecord> = None;
+		let mut trampoline_packet: Option<TrampolineOnionPacket> = None;
 		let mut custom_tlvs = Vec::new();
 
 		let tlv_len = BigSize::read(r)?;
@@ -2352,8 +2660,10 @@ impl<NS: Deref> ReadableArgs<(Option<PublicKey>, &NS)> for InboundOnionPayload w
 			(8, payment_data, option),
 			(10, encrypted_tlvs_opt, option),
 			(12, intro_node_blinding_point, option),
+			(14, amp_record, option),
 			(16, payment_metadata, option),
 			(18, total_msat, (option, encoding: (u64, HighZeroBytesDroppedBigSize))),
+			(20, trampoline_packet, (option: LengthReadable)),
 			// See https://github.com/lightning/blips/blob/master/blip-0003.md
 			(5482373484, keysend_preimage, option)
 		}, |msg_type: u64, msg_reader: &mut FixedLengthReader<_>| -> Result<bool, DecodeError> {
@@ -2371,7 +2681,7 @@ impl<NS: Deref> ReadableArgs<(Option<PublicKey>, &NS)> for InboundOnionPayload w
 
 		if let Some(blinding_point) = intro_node_blinding_point.or(update_add_blinding_point) {
 			if short_id.is_some() || payment_data.is_some() || payment_metadata.is_some() ||
-				keysend_preimage.is_some()
+				amp_record.is_some() || trampoline_packet.is_some()
 			{
 				return Err(DecodeError::InvalidValue)
 			}
@@ -2385,7 +2695,9 @@ impl<NS: Deref> ReadableArgs<(Option<PublicKey>, &NS)> for InboundOnionPayload w
 				ChaChaPolyReadAdapter { readable: BlindedPaymentTlvs::Forward(ForwardTlvs {
 					short_channel_id, payment_relay, payment_constraints, features
 				})} => {
-					if amt.is_some() || cltv_value.is_some() || total_msat.is_some() {
+					if amt.is_some() || cltv_value.is_some() || total_msat.is_some() ||
+						keysend_preimage.is_some()
+					{
 						return Err(DecodeError::InvalidValue)
 					}
 					Ok(Self::BlindedForward {
@@ -2407,22 +2719,42 @@ impl<NS: Deref> ReadableArgs<(Option<PublicKey>, &NS)> for InboundOnionPayload w
 						payment_secret,
 						payment_constraints,
 						intro_node_blinding_point,
+						keysend_preimage,
 					})
 				},
 			}
 		} else if let Some(short_channel_id) = short_id {
 			if payment_data.is_some() || payment_metadata.is_some() || encrypted_tlvs_opt.is_some() ||
-				total_msat.is_some()
+				total_msat.is_some() || amp_record.is_some() || trampoline_packet.is_some()
 			{ return Err(DecodeError::InvalidValue) }
 			Ok(Self::Forward {
 				short_channel_id,
 				amt_to_forward: amt.ok_or(DecodeError::InvalidValue)?,
 				outgoing_cltv_value: cltv_value.ok_or(DecodeError::InvalidValue)?,
 			})
+		} else if let Some(trampoline_packet) = trampoline_packet {
+			if payment_metadata.is_some() || encrypted_tlvs_opt.is_some() || total_msat.is_some() ||
+				keysend_preimage.is_some() || amp_record.is_some() || !custom_tlvs.is_empty()
+			{ return Err(DecodeError::InvalidValue) }
+			if let Some(data) = &payment_data {
+				if data.total_msat > MAX_VALUE_MSAT {
+					return Err(DecodeError::InvalidValue);
+				}
+			}
+			if cltv_value.is_none() { return Err(DecodeError::InvalidValue) }
+			Ok(Self::TrampolineEntrypoint {
+				amt_to_forward: amt.ok_or(DecodeError::InvalidValue)?,
+				multipath_trampoline_data: payment_data,
+				trampoline_packet,
+			})
 		} else {
 			if encrypted_tlvs_opt.is_some() || total_msat.is_some() {
 				return Err(DecodeError::InvalidValue)
 			}
+			// AMP payments derive their preimages from the root seed rather than carrying one.
+			if amp_record.is_some() && keysend_preimage.is_some() {
+				return Err(DecodeError::InvalidValue)
+			}
 			if let Some(data) = &payment_data {
 				if data.total_msat > MAX_VALUE_MSAT {
 					return Err(DecodeError::InvalidValue);
@@ -2432,6 +2764,7 @@ impl<NS: Deref> ReadableArgs<(Option<PublicKey>, &NS)> for InboundOnionPayload w
 				payment_data,
 				payment_metadata: payment_metadata.map(|w| w.0),
 				keysend_preimage,
+				amp_record,
 				amt_msat: amt.ok_or(DecodeError::InvalidValue)?,
 				outgoing_cltv_value: cltv_value.ok_or(DecodeError::InvalidValue)?,
 				custom_tlvs,
@@ -2440,6 +2773,59 @@ impl<NS: Deref> ReadableArgs<(Option<PublicKey>, &NS)> for InboundOnionPayload w
 	}
 }
 
+impl ReadableArgs<()> for InboundTrampolinePayload {
+	fn read<R: Read>(r: &mut R, _args: ()) -> Result<Self, DecodeError> {
+		let mut amt = None;
+		let mut cltv_value = None;
+		let mut payment_data: Option<FinalOnionHopData> = None;
+		let mut outgoing_node_id: Option<PublicKey> = None;
+		let mut payment_metadata: Option<WithoutLength<Vec<u8>>> = None;
+		let mut custom_tlvs = Vec::new();
+
+		let tlv_len = BigSize::read(r)?;
+		let rd = FixedLengthReader::new(r, tlv_len.0);
+		decode_tlv_stream_with_custom_tlv_decode!(rd, {
+			(2, amt, (option, encoding: (u64, HighZeroBytesDroppedBigSize))),
+			(4, cltv_value, (option, encoding: (u32, HighZeroBytesDroppedBigSize))),
+			(8, payment_data, option),
+			(14, outgoing_node_id, option),
+			(16, payment_metadata, option),
+		}, |msg_type: u64, msg_reader: &mut FixedLengthReader<_>| -> Result<bool, DecodeError> {
+			if msg_type < 1 << 16 { return Ok(false) }
+			let mut value = Vec::new();
+			msg_reader.read_to_end(&mut value)?;
+			custom_tlvs.push((msg_type, value));
+			Ok(true)
+		});
+
+		if amt.unwrap_or(0) > MAX_VALUE_MSAT { return Err(DecodeError::InvalidValue) }
+		if let Some(data) = &payment_data {
+			if data.total_msat > MAX_VALUE_MSAT {
+				return Err(DecodeError::InvalidValue);
+			}
+		}
+
+		if let Some(outgoing_node_id) = outgoing_node_id {
+			if payment_data.is_some() || payment_metadata.is_some() || !custom_tlvs.is_empty() {
+				return Err(DecodeError::InvalidValue)
+			}
+			Ok(Self::Forward {
+				amt_to_forward: amt.ok_or(DecodeError::InvalidValue)?,
+				outgoing_cltv_value: cltv_value.ok_or(DecodeError::InvalidValue)?,
+				outgoing_node_id,
+			})
+		} else {
+			Ok(Self::Receive {
+				payment_data,
+				payment_metadata: payment_metadata.map(|w| w.0),
+				custom_tlvs,
+				amt_msat: amt.ok_or(DecodeError::InvalidValue)?,
+				outgoing_cltv_value: cltv_value.ok_or(DecodeError::InvalidValue)?,
+			})
+		}
+	}
+}
+
 impl Writeable for Ping {
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
 		self.ponglen.write(w)?;
@@ -2856,7 +3242,7 @@ mod tests {
 	use crate::ln::msgs::{self, FinalOnionHopData, OnionErrorPacket};
 	use crate::ln::msgs::SocketAddress;
 	use crate::routing::gossip::{NodeAlias, NodeId};
-	use crate::util::ser::{Writeable, Readable, ReadableArgs, Hostname, TransactionU16LenLimited};
+	use crate::util::ser::{BigSize, Writeable, Readable, ReadableArgs, Hostname, TransactionU16LenLimited};
 	use crate::util::test_utils;
 
 	use bitcoin::hashes::hex::FromHex;
@@ -2895,6 +3281,7 @@ mod tests {
 			your_last_per_commitment_secret: [9;32],
 			my_current_per_commitment_point: public_key,
 			next_funding_txid: None,
+			channel_type: None,
 		};
 
 		let encoded_value = cr.encode();
@@ -2926,6 +3313,7 @@ mod tests {
 			next_funding_txid: Some(Txid::from_raw_hash(bitcoin::hashes::Hash::from_slice(&[
 				48, 167, 250, 69, 152, 48, 103, 172, 164, 99, 59, 19, 23, 11, 92, 84, 15, 80, 4, 12, 98, 82, 75, 31, 201, 11, 91, 23, 98, 23, 53, 124,
 			]).unwrap())),
+			channel_type: None,
 		};
 
 		let encoded_value = cr.encode();
@@ -3524,6 +3912,43 @@ mod tests {
 		assert_eq!(encoded_value.as_hex().to_string(), "020202020202020202020202020202020202020202020202020202020202020201");
 	}
 
+	#[test]
+	fn encoding_peer_storage() {
+		let peer_storage = msgs::PeerStorage {
+			data: vec![1, 2, 3],
+		};
+		let encoded_value = peer_storage.encode();
+		assert_eq!(encoded_value.as_hex().to_string(), "0003010203");
+		assert_eq!(msgs::PeerStorage::read(&mut Cursor::new(&encoded_value)).unwrap(), peer_storage);
+
+		let your_peer_storage = msgs::YourPeerStorage {
+			data: vec![1, 2, 3],
+		};
+		let encoded_value = your_peer_storage.encode();
+		assert_eq!(encoded_value.as_hex().to_string(), "0003010203");
+		assert_eq!(msgs::YourPeerStorage::read(&mut Cursor::new(&encoded_value)).unwrap(), your_peer_storage);
+	}
+
+	#[test]
+	fn encoding_dyn_propose() {
+		let dyn_propose = msgs::DynPropose {
+			channel_id: ChannelId::from_bytes([2; 32]),
+			channel_type: ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies(),
+		};
+		let encoded_value = dyn_propose.encode();
+		assert_eq!(encoded_value.as_hex().to_string(), "02020202020202020202020202020202020202020202020202020202020202020003401000");
+		assert_eq!(msgs::DynPropose::read(&mut Cursor::new(&encoded_value)).unwrap(), dyn_propose);
+	}
+
+	#[test]
+	fn encoding_dyn_ack() {
+		let dyn_ack = msgs::DynAck {
+			channel_id: ChannelId::from_bytes([2; 32]),
+		};
+		let encoded_value = dyn_ack.encode();
+		assert_eq!(encoded_value.as_hex().to_string(), "0202020202020202020202020202020202020202020202020202020202020202");
+	}
+
 	#[test]
 	fn encoding_splice_ack() {
 		let secp_ctx = Secp256k1::new();
@@ -3806,12 +4231,41 @@ mod tests {
 			onion_routing_packet,
 			skimmed_fee_msat: None,
 			blinding_point: None,
+			hold_htlc: None,
+			endorsed: None,
 		};
 		let encoded_value = update_add_htlc.encode();
 		let target_value = <Vec<u8>>::from_hex("020202020202020202020202020202020202020202020202020202020202020200083a840000034d32144668701144760101010101010101010101010101010101010101010101010101010101010101000c89d4ff031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010202020202020202020202020202020202020202020202020202020202020202").unwrap();
 		assert_eq!(encoded_value, target_value);
 	}
 
+	#[test]
+	fn encoding_update_add_htlc_endorsed() {
+		let secp_ctx = Secp256k1::new();
+		let (_, pubkey_1) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
+		let update_add_htlc = msgs::UpdateAddHTLC {
+			channel_id: ChannelId::from_bytes([2; 32]),
+			htlc_id: 2316138423780173,
+			amount_msat: 3608586615801332854,
+			payment_hash: PaymentHash([1; 32]),
+			cltv_expiry: 821716,
+			onion_routing_packet: msgs::OnionPacket {
+				version: 255,
+				public_key: Ok(pubkey_1),
+				hop_data: [1; 20*65],
+				hmac: [2; 32]
+			},
+			skimmed_fee_msat: None,
+			blinding_point: None,
+			hold_htlc: None,
+			endorsed: Some(true),
+		};
+		let encoded_value = update_add_htlc.encode();
+		assert!(encoded_value.ends_with(&<Vec<u8>>::from_hex("fe0001a1470101").unwrap()));
+		let decoded: msgs::UpdateAddHTLC = Readable::read(&mut &encoded_value[..]).unwrap();
+		assert_eq!(decoded, update_add_htlc);
+	}
+
 	#[test]
 	fn encoding_update_fulfill_htlc() {
 		let update_fulfill_htlc = msgs::UpdateFulfillHTLC {
@@ -4019,6 +4473,7 @@ mod tests {
 			payment_data: None,
 			payment_metadata: None,
 			keysend_preimage: None,
+			amp_record: None,
 			amt_msat: 0x0badf00d01020304,
 			outgoing_cltv_value: 0xffffffff,
 			custom_tlvs: vec![],
@@ -4047,6 +4502,7 @@ mod tests {
 			}),
 			payment_metadata: None,
 			keysend_preimage: None,
+			amp_record: None,
 			amt_msat: 0x0badf00d01020304,
 			outgoing_cltv_value: 0xffffffff,
 			custom_tlvs: vec![],
@@ -4065,6 +4521,7 @@ mod tests {
 			amt_msat, outgoing_cltv_value,
 			payment_metadata: None,
 			keysend_preimage: None,
+			amp_record: None,
 			custom_tlvs,
 		} = inbound_msg  {
 			assert_eq!(payment_secret, expected_payment_secret);
@@ -4074,6 +4531,145 @@ mod tests {
 		} else { panic!(); }
 	}
 
+	#[test]
+	fn encoding_final_onion_hop_data_with_amp_record() {
+		let amp_record = msgs::AmpRecord {
+			root_share: [0x42; 32],
+			set_id: [0x43; 32],
+			child_index: 0x0102,
+		};
+		let outbound_msg = msgs::OutboundOnionPayload::Receive {
+			payment_data: Some(FinalOnionHopData {
+				payment_secret: PaymentSecret([0x44; 32]),
+				total_msat: 0x1badca1f
+			}),
+			payment_metadata: None,
+			keysend_preimage: None,
+			amp_record: Some(amp_record.clone()),
+			amt_msat: 0x0badf00d01020304,
+			outgoing_cltv_value: 0xffffffff,
+			custom_tlvs: vec![],
+		};
+		let encoded_value = outbound_msg.encode();
+		let target_value = <Vec<u8>>::from_hex("7a02080badf00d010203040404ffffffff082444444444444444444444444444444444444444444444444444444444444444441badca1f0e42424242424242424242424242424242424242424242424242424242424242424243434343434343434343434343434343434343434343434343434343434343430102").unwrap();
+		assert_eq!(encoded_value, target_value);
+
+		let node_signer = test_utils::TestKeysInterface::new(&[42; 32], Network::Testnet);
+		let inbound_msg = ReadableArgs::read(&mut Cursor::new(&target_value[..]), (None, &&node_signer)).unwrap();
+		if let msgs::InboundOnionPayload::Receive {
+			amp_record: Some(decoded_amp_record), keysend_preimage: None, ..
+		} = inbound_msg {
+			assert_eq!(decoded_amp_record, amp_record);
+		} else { panic!(); }
+
+		// An AMP record is mutually exclusive with a keysend preimage.
+		let outbound_msg = msgs::OutboundOnionPayload::Receive {
+			payment_data: None,
+			payment_metadata: None,
+			keysend_preimage: Some(PaymentPreimage([0x45; 32])),
+			amp_record: Some(amp_record),
+			amt_msat: 0x0badf00d01020304,
+			outgoing_cltv_value: 0xffffffff,
+			custom_tlvs: vec![],
+		};
+		let encoded_value = outbound_msg.encode();
+		let inbound_res: Result<msgs::InboundOnionPayload, _> = ReadableArgs::read(&mut Cursor::new(&encoded_value[..]), (None, &&node_signer));
+		assert_eq!(inbound_res.err().unwrap(), msgs::DecodeError::InvalidValue);
+	}
+
+	#[test]
+	fn encoding_trampoline_entrypoint_onion_hop_data() {
+		let secp_ctx = Secp256k1::new();
+		let trampoline_packet = msgs::TrampolineOnionPacket {
+			version: 0,
+			public_key: PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap()),
+			hop_data: vec![0x42; 100],
+			hmac: [0x43; 32],
+		};
+		let outbound_msg = msgs::OutboundOnionPayload::TrampolineEntrypoint {
+			amt_to_forward: 0x0badf00d01020304,
+			outgoing_cltv_value: 0xffffffff,
+			multipath_trampoline_data: Some(FinalOnionHopData {
+				payment_secret: PaymentSecret([0x44; 32]),
+				total_msat: 0x0badf00d01020304,
+			}),
+			trampoline_packet: trampoline_packet.clone(),
+		};
+		let encoded_value = outbound_msg.encode();
+
+		let node_signer = test_utils::TestKeysInterface::new(&[42; 32], Network::Testnet);
+		let inbound_msg = ReadableArgs::read(&mut Cursor::new(&encoded_value[..]), (None, &&node_signer)).unwrap();
+		if let msgs::InboundOnionPayload::TrampolineEntrypoint {
+			amt_to_forward, multipath_trampoline_data: Some(FinalOnionHopData { payment_secret, total_msat }),
+			trampoline_packet: decoded_packet,
+		} = inbound_msg {
+			assert_eq!(amt_to_forward, 0x0badf00d01020304);
+			assert_eq!(payment_secret, PaymentSecret([0x44; 32]));
+			assert_eq!(total_msat, 0x0badf00d01020304);
+			assert_eq!(decoded_packet, trampoline_packet);
+		} else { panic!(); }
+
+		// A trampoline onion alongside a short channel id is invalid.
+		let mut encoded_forward = msgs::OutboundOnionPayload::Forward {
+			short_channel_id: 0xdeadbeef1bad1dea,
+			amt_to_forward: 0x0badf00d01020304,
+			outgoing_cltv_value: 0xffffffff,
+		}.encode();
+		let mut tlv_stream = encoded_forward.split_off(1);
+		tlv_stream.push(20);
+		BigSize(trampoline_packet.serialized_length() as u64).write(&mut tlv_stream).unwrap();
+		trampoline_packet.write(&mut tlv_stream).unwrap();
+		encoded_forward.clear();
+		BigSize(tlv_stream.len() as u64).write(&mut encoded_forward).unwrap();
+		encoded_forward.extend_from_slice(&tlv_stream);
+		let inbound_res: Result<msgs::InboundOnionPayload, _> = ReadableArgs::read(&mut Cursor::new(&encoded_forward[..]), (None, &&node_signer));
+		assert_eq!(inbound_res.err().unwrap(), msgs::DecodeError::InvalidValue);
+	}
+
+	#[test]
+	fn encoding_trampoline_onion_hop_data() {
+		let secp_ctx = Secp256k1::new();
+		let outgoing_node_id = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		let outbound_forward = msgs::OutboundTrampolinePayload::Forward {
+			amt_to_forward: 0x0badf00d01020304,
+			outgoing_cltv_value: 0xffffffff,
+			outgoing_node_id,
+		};
+		let encoded_forward = outbound_forward.encode();
+		let inbound_forward = ReadableArgs::read(&mut Cursor::new(&encoded_forward[..]), ()).unwrap();
+		if let msgs::InboundTrampolinePayload::Forward {
+			amt_to_forward, outgoing_cltv_value, outgoing_node_id: decoded_node_id
+		} = inbound_forward {
+			assert_eq!(amt_to_forward, 0x0badf00d01020304);
+			assert_eq!(outgoing_cltv_value, 0xffffffff);
+			assert_eq!(decoded_node_id, outgoing_node_id);
+		} else { panic!(); }
+
+		let outbound_receive = msgs::OutboundTrampolinePayload::Receive {
+			payment_data: Some(FinalOnionHopData {
+				payment_secret: PaymentSecret([0x42; 32]),
+				total_msat: 0x1badca1f,
+			}),
+			payment_metadata: Some(vec![0x43; 10]),
+			custom_tlvs: vec![((1 << 16) + 1, vec![0x44; 4])],
+			amt_msat: 0x1badca1f,
+			outgoing_cltv_value: 0xffffffff,
+		};
+		let encoded_receive = outbound_receive.encode();
+		let inbound_receive = ReadableArgs::read(&mut Cursor::new(&encoded_receive[..]), ()).unwrap();
+		if let msgs::InboundTrampolinePayload::Receive {
+			payment_data: Some(FinalOnionHopData { payment_secret, total_msat }), payment_metadata,
+			custom_tlvs, amt_msat, outgoing_cltv_value,
+		} = inbound_receive {
+			assert_eq!(payment_secret, PaymentSecret([0x42; 32]));
+			assert_eq!(total_msat, 0x1badca1f);
+			assert_eq!(payment_metadata, Some(vec![0x43; 10]));
+			assert_eq!(custom_tlvs, vec![((1 << 16) + 1, vec![0x44; 4])]);
+			assert_eq!(amt_msat, 0x1badca1f);
+			assert_eq!(outgoing_cltv_value, 0xffffffff);
+		} else { panic!(); }
+	}
+
 	#[test]
 	fn encoding_final_onion_hop_data_with_bad_custom_tlvs() {
 		// If custom TLVs have type number within the range reserved for protocol, treat them as if
@@ -4086,6 +4682,7 @@ mod tests {
 			payment_data: None,
 			payment_metadata: None,
 			keysend_preimage: None,
+			amp_record: None,
 			custom_tlvs: bad_type_range_tlvs,
 			amt_msat: 0x0badf00d01020304,
 			outgoing_cltv_value: 0xffffffff,
@@ -4118,6 +4715,7 @@ mod tests {
 			payment_data: None,
 			payment_metadata: None,
 			keysend_preimage: None,
+			amp_record: None,
 			custom_tlvs: expected_custom_tlvs.clone(),
 			amt_msat: 0x0badf00d01020304,
 			outgoing_cltv_value: 0xffffffff,
@@ -4131,6 +4729,7 @@ mod tests {
 			payment_data: None,
 			payment_metadata: None,
 			keysend_preimage: None,
+			amp_record: None,
 			custom_tlvs,
 			amt_msat,
 			outgoing_cltv_value,
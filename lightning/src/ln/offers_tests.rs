@@ -0,0 +1,357 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Functional tests for paying [`Offer`]s, where the payer sends an [`InvoiceRequest`] and pays
+//! the [`Bolt12Invoice`] the recipient responds with.
+//!
+//! Onion messages are handed directly to each node's [`OffersMessageHandler`] rather than sent
+//! through an `OnionMessenger`.
+//!
+//! [`Offer`]: crate::offers::offer::Offer
+
+use bitcoin::secp256k1::{KeyPair, Secp256k1, SecretKey};
+use core::convert::Infallible;
+
+use crate::events::{Event, PaymentPurpose};
+use crate::ln::PaymentPreimage;
+use crate::ln::channelmanager::{PaymentId, RecipientOnionFields};
+use crate::ln::functional_test_utils::*;
+use crate::ln::outbound_payment::Retry;
+use crate::offers::invoice::Bolt12Invoice;
+use crate::offers::invoice_error::InvoiceError;
+use crate::offers::invoice_request::InvoiceRequest;
+use crate::offers::offer::{Amount, Offer};
+use crate::offers::parse::Bolt12SemanticError;
+use crate::offers::recurrence::{Recurrence, RecurrenceBase, RecurrencePaywindow, RecurrenceTimeUnit};
+use crate::onion_message::{OffersMessage, OffersMessageHandler, PendingOnionMessage};
+use crate::routing::router::{PaymentParameters, RouteParameters};
+use crate::util::test_utils::TestCurrencyConversion;
+
+use crate::prelude::*;
+use crate::sync::Arc;
+
+/// Returns the single [`InvoiceRequest`] queued by `payer`.
+fn extract_invoice_request<'a, 'b, 'c>(payer: &Node<'a, 'b, 'c>) -> InvoiceRequest {
+	let mut messages = OffersMessageHandler::release_pending_messages(payer.node);
+	assert_eq!(messages.len(), 1);
+	#[cfg(not(c_bindings))]
+	let PendingOnionMessage { contents, .. } = messages.pop().unwrap();
+	#[cfg(c_bindings)]
+	let (contents, _, _) = messages.pop().unwrap();
+	match contents {
+		OffersMessage::InvoiceRequest(invoice_request) => invoice_request,
+		_ => panic!("Unexpected message"),
+	}
+}
+
+/// Hands `invoice_request` to `payee`, returning the [`Bolt12Invoice`] it responds with.
+fn respond_with_invoice<'a, 'b, 'c>(
+	payee: &Node<'a, 'b, 'c>, invoice_request: InvoiceRequest
+) -> Bolt12Invoice {
+	match payee.node.handle_message(OffersMessage::InvoiceRequest(invoice_request)) {
+		Some(OffersMessage::Invoice(invoice)) => invoice,
+		_ => panic!("Expected invoice"),
+	}
+}
+
+/// Passes the HTLC already sent by `payer` for `invoice` over a direct channel to `payee`,
+/// returning the preimage given when the payment is claimable.
+fn pass_invoice_payment<'a, 'b, 'c>(
+	payer: &Node<'a, 'b, 'c>, payee: &Node<'a, 'b, 'c>, invoice: &Bolt12Invoice
+) -> PaymentPreimage {
+	let payment_hash = invoice.payment_hash();
+	let amount_msats = invoice.amount_msats();
+	let mut events = payer.node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let ev = remove_first_msg_event_to_node(&payee.node.get_our_node_id(), &mut events);
+	do_pass_along_path(payer, &[payee], amount_msats, payment_hash, None, ev, true, false, None, false);
+
+	let events = payee.node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match &events[0] {
+		Event::PaymentClaimable {
+			payment_hash: claimable_hash, amount_msat,
+			purpose: PaymentPurpose::InvoicePayment { payment_preimage: Some(payment_preimage), .. }, ..
+		} => {
+			assert_eq!(*claimable_hash, payment_hash);
+			assert_eq!(*amount_msat, amount_msats);
+			*payment_preimage
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+/// Hands `invoice` to `payer`, which pays it over a direct channel to `payee`, and claims the
+/// payment.
+fn pay_invoice<'a, 'b, 'c>(
+	payer: &Node<'a, 'b, 'c>, payee: &Node<'a, 'b, 'c>, invoice: Bolt12Invoice
+) {
+	assert!(payer.node.handle_message(OffersMessage::Invoice(invoice.clone())).is_none());
+	check_added_monitors!(payer, 1);
+	let payment_preimage = pass_invoice_payment(payer, payee, &invoice);
+	claim_payment(payer, &[payee], payment_preimage);
+}
+
+/// Builds an [`InvoiceRequest`] for the given period of a recurring `offer`, signed by
+/// `payer_keys`.
+fn recurring_invoice_request(
+	offer: &Offer, payer_keys: &KeyPair, recurrence_counter: u32
+) -> InvoiceRequest {
+	let secp_ctx = Secp256k1::new();
+	offer.request_invoice(vec![1; 32], payer_keys.public_key()).unwrap()
+		.chain_hash(offer.chains()[0]).unwrap()
+		.recurrence_counter(recurrence_counter)
+		.build().unwrap()
+		.sign::<_, Infallible>(
+			|message| Ok(secp_ctx.sign_schnorr_no_aux_rand(message.as_ref().as_digest(), payer_keys))
+		)
+		.unwrap()
+}
+
+fn expect_invoice_error<'a, 'b, 'c>(
+	payee: &Node<'a, 'b, 'c>, invoice_request: InvoiceRequest, error: Bolt12SemanticError
+) {
+	match payee.node.handle_message(OffersMessage::InvoiceRequest(invoice_request)) {
+		Some(OffersMessage::InvoiceError(invoice_error)) => {
+			assert_eq!(invoice_error, InvoiceError::from(error));
+		},
+		_ => panic!("Expected invoice error"),
+	}
+}
+
+#[test]
+fn pays_for_offer_in_currency() {
+	// nodes[1] creates an offer denominated in USD cents, which both nodes convert using their
+	// own `CurrencyConversion`. nodes[0] leaves the amount to nodes[1], which invoices for the
+	// converted amount.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	for node in nodes.iter() {
+		let currency_conversion = TestCurrencyConversion::new();
+		currency_conversion.set_rate(*b"USD", 10_000);
+		node.node.set_currency_conversion(Arc::new(currency_conversion));
+	}
+
+	let offer = nodes[1].node.create_offer_builder("coffee".to_string()).unwrap()
+		.amount(Amount::Currency { iso4217_code: *b"USD", amount: 1_000 })
+		.build().unwrap();
+
+	let payment_id = PaymentId([42; 32]);
+	nodes[0].node.pay_for_offer(&offer, None, None, None, payment_id, Retry::Attempts(0), None)
+		.unwrap();
+	let invoice_request = extract_invoice_request(&nodes[0]);
+	assert_eq!(invoice_request.amount_msats(), None);
+
+	let invoice = respond_with_invoice(&nodes[1], invoice_request);
+	assert_eq!(invoice.amount_msats(), 10_000_000);
+	pay_invoice(&nodes[0], &nodes[1], invoice);
+}
+
+#[test]
+fn rejects_invoice_request_for_offer_in_currency() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let offer = nodes[1].node.create_offer_builder("coffee".to_string()).unwrap()
+		.amount(Amount::Currency { iso4217_code: *b"USD", amount: 1_000 })
+		.build().unwrap();
+
+	// Without a conversion, the payer must give an amount.
+	assert_eq!(
+		nodes[0].node.pay_for_offer(&offer, None, None, None, PaymentId([1; 32]), Retry::Attempts(0), None),
+		Err(Bolt12SemanticError::UnsupportedCurrency)
+	);
+
+	// Without a conversion, the recipient can't check the payer's amount.
+	nodes[0].node.pay_for_offer(
+		&offer, None, Some(1_000), None, PaymentId([2; 32]), Retry::Attempts(0), None
+	).unwrap();
+	let invoice_request = extract_invoice_request(&nodes[0]);
+	expect_invoice_error(&nodes[1], invoice_request.clone(), Bolt12SemanticError::UnsupportedCurrency);
+
+	// With a conversion, the payer's amount must be close enough to the converted amount.
+	let currency_conversion = TestCurrencyConversion::new();
+	currency_conversion.set_rate(*b"USD", 10_000);
+	nodes[1].node.set_currency_conversion(Arc::new(currency_conversion));
+	expect_invoice_error(&nodes[1], invoice_request, Bolt12SemanticError::InsufficientAmount);
+
+	nodes[0].node.pay_for_offer(
+		&offer, None, Some(10_000_000), None, PaymentId([3; 32]), Retry::Attempts(0), None
+	).unwrap();
+	let invoice = respond_with_invoice(&nodes[1], extract_invoice_request(&nodes[0]));
+	assert_eq!(invoice.amount_msats(), 10_000_000);
+	pay_invoice(&nodes[0], &nodes[1], invoice);
+}
+
+#[test]
+fn pays_for_offer_recurrence() {
+	// nodes[0] pays for successive periods of nodes[1]'s recurring offer, where a period is only
+	// considered paid once its payment succeeds.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let offer = nodes[1].node.create_offer_builder("coffee subscription".to_string()).unwrap()
+		.amount_msats(1_000_000)
+		.recurrence(Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 30 })
+		.build().unwrap();
+
+	// A non-recurring offer can't be paid as one.
+	let other_offer = nodes[1].node.create_offer_builder("coffee".to_string()).unwrap()
+		.amount_msats(1_000_000)
+		.build().unwrap();
+	assert_eq!(
+		nodes[0].node.pay_for_offer_recurrence(
+			&other_offer, None, None, None, None, PaymentId([1; 32]), Retry::Attempts(0), None
+		),
+		Err(Bolt12SemanticError::MissingRecurrence)
+	);
+
+	nodes[0].node.pay_for_offer_recurrence(
+		&offer, None, None, None, None, PaymentId([2; 32]), Retry::Attempts(0), None
+	).unwrap();
+	let invoice_request = extract_invoice_request(&nodes[0]);
+	assert_eq!(invoice_request.recurrence_counter(), Some(0));
+	let payer_id = invoice_request.payer_id();
+	let invoice = respond_with_invoice(&nodes[1], invoice_request);
+	assert!(nodes[0].node.handle_message(OffersMessage::Invoice(invoice.clone())).is_none());
+	check_added_monitors!(nodes[0], 1);
+
+	// The next period can't be requested while the payment for this one is pending.
+	assert_eq!(
+		nodes[0].node.pay_for_offer_recurrence(
+			&offer, None, None, None, None, PaymentId([3; 32]), Retry::Attempts(0), None
+		),
+		Err(Bolt12SemanticError::InvalidRecurrenceCounter)
+	);
+
+	// Once the payment fails, the same period is requested again.
+	pass_invoice_payment(&nodes[0], &nodes[1], &invoice);
+	fail_payment(&nodes[0], &[&nodes[1]], invoice.payment_hash());
+	nodes[0].node.pay_for_offer_recurrence(
+		&offer, None, None, None, None, PaymentId([3; 32]), Retry::Attempts(0), None
+	).unwrap();
+	let invoice_request = extract_invoice_request(&nodes[0]);
+	assert_eq!(invoice_request.recurrence_counter(), Some(0));
+	assert_eq!(invoice_request.payer_id(), payer_id);
+	let invoice = respond_with_invoice(&nodes[1], invoice_request);
+	let basetime = invoice.recurrence_basetime().unwrap();
+	pay_invoice(&nodes[0], &nodes[1], invoice);
+
+	// Once paid, the following period is requested using the same payer id.
+	nodes[0].node.pay_for_offer_recurrence(
+		&offer, None, None, None, None, PaymentId([4; 32]), Retry::Attempts(0), None
+	).unwrap();
+	let invoice_request = extract_invoice_request(&nodes[0]);
+	assert_eq!(invoice_request.recurrence_counter(), Some(1));
+	assert_eq!(invoice_request.payer_id(), payer_id);
+	let invoice = respond_with_invoice(&nodes[1], invoice_request);
+	assert_eq!(invoice.recurrence_basetime(), Some(basetime));
+	pay_invoice(&nodes[0], &nodes[1], invoice);
+
+	assert!(nodes[0].node.stop_offer_recurrence(&offer));
+	assert!(!nodes[0].node.stop_offer_recurrence(&offer));
+}
+
+#[test]
+fn enforces_inbound_recurrence_periods() {
+	// nodes[1] only invoices a period of its recurring offer once the previous one has been paid,
+	// regardless of whether it was invoiced.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let offer = nodes[1].node.create_offer_builder("coffee subscription".to_string()).unwrap()
+		.amount_msats(1_000_000)
+		.recurrence(Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 30 })
+		.build().unwrap();
+	let secp_ctx = Secp256k1::new();
+	let payer_keys = KeyPair::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+
+	expect_invoice_error(
+		&nodes[1], recurring_invoice_request(&offer, &payer_keys, 1),
+		Bolt12SemanticError::InvalidRecurrenceCounter
+	);
+	let invoice = respond_with_invoice(&nodes[1], recurring_invoice_request(&offer, &payer_keys, 0));
+	assert_eq!(invoice.recurrence_counter(), Some(0));
+
+	// Invoicing the first period doesn't allow skipping ahead without paying it.
+	expect_invoice_error(
+		&nodes[1], recurring_invoice_request(&offer, &payer_keys, 1),
+		Bolt12SemanticError::InvalidRecurrenceCounter
+	);
+
+	let route_params = RouteParameters::from_payment_params_and_value(
+		PaymentParameters::from_bolt12_invoice(&invoice), invoice.amount_msats()
+	);
+	nodes[0].node.send_payment(
+		invoice.payment_hash(), RecipientOnionFields::spontaneous_empty(),
+		PaymentId(invoice.payment_hash().0), route_params, Retry::Attempts(0)
+	).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_preimage = pass_invoice_payment(&nodes[0], &nodes[1], &invoice);
+
+	// The period is only paid once the payment is claimed.
+	expect_invoice_error(
+		&nodes[1], recurring_invoice_request(&offer, &payer_keys, 1),
+		Bolt12SemanticError::InvalidRecurrenceCounter
+	);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+
+	let next_invoice =
+		respond_with_invoice(&nodes[1], recurring_invoice_request(&offer, &payer_keys, 1));
+	assert_eq!(next_invoice.recurrence_counter(), Some(1));
+	assert_eq!(next_invoice.recurrence_basetime(), invoice.recurrence_basetime());
+	expect_invoice_error(
+		&nodes[1], recurring_invoice_request(&offer, &payer_keys, 2),
+		Bolt12SemanticError::InvalidRecurrenceCounter
+	);
+
+	// Paid periods may be requested again.
+	respond_with_invoice(&nodes[1], recurring_invoice_request(&offer, &payer_keys, 0));
+}
+
+#[test]
+fn invoices_proportional_amount_for_recurrence() {
+	// nodes[1]'s recurring offer reduces the amount for a period which has already started.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let now = std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH).unwrap();
+	let offer = nodes[1].node.create_offer_builder("coffee subscription".to_string()).unwrap()
+		.amount_msats(1_000_000)
+		.recurrence(Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 1 })
+		.recurrence_base(RecurrenceBase { start_any_period: false, basetime: now.as_secs() - 43_200 })
+		.recurrence_paywindow(RecurrencePaywindow {
+			seconds_before: 0, proportional_amount: true, seconds_after: 86_399,
+		})
+		.build().unwrap();
+
+	nodes[0].node.pay_for_offer_recurrence(
+		&offer, None, None, None, None, PaymentId([1; 32]), Retry::Attempts(0), None
+	).unwrap();
+	let invoice = respond_with_invoice(&nodes[1], extract_invoice_request(&nodes[0]));
+	assert!(invoice.amount_msats() <= 500_000);
+	assert!(invoice.amount_msats() >= 499_000);
+	pay_invoice(&nodes[0], &nodes[1], invoice);
+}
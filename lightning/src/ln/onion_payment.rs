@@ -93,7 +93,8 @@ pub(super) fn create_fwd_pending_htlc_info(
 			})?;
 			(short_channel_id, amt_to_forward, outgoing_cltv_value, Some(intro_node_blinding_point))
 		},
-		msgs::InboundOnionPayload::Receive { .. } | msgs::InboundOnionPayload::BlindedReceive { .. } =>
+		msgs::InboundOnionPayload::Receive { .. } | msgs::InboundOnionPayload::BlindedReceive { .. } |
+			msgs::InboundOnionPayload::TrampolineEntrypoint { .. } =>
 			return Err(InboundOnionErr {
 				msg: "Final Node OnionHopData provided for us as an intermediary node",
 				err_code: 0x4000 | 22,
@@ -106,6 +107,7 @@ pub(super) fn create_fwd_pending_htlc_info(
 			onion_packet: outgoing_packet,
 			short_channel_id,
 			blinded: inbound_blinding_point.map(|bp| BlindedForward { inbound_blinding_point: bp }),
+			trampoline_forward: None,
 		},
 		payment_hash: msg.payment_hash,
 		incoming_shared_secret: shared_secret,
@@ -113,6 +115,8 @@ pub(super) fn create_fwd_pending_htlc_info(
 		outgoing_amt_msat: amt_to_forward,
 		outgoing_cltv_value,
 		skimmed_fee_msat: None,
+		hold_htlc: msg.hold_htlc,
+		incoming_endorsed: msg.endorsed,
 	})
 }
 
@@ -122,17 +126,18 @@ pub(super) fn create_recv_pending_htlc_info(
 	counterparty_skimmed_fee_msat: Option<u64>, current_height: u32, accept_mpp_keysend: bool,
 ) -> Result<PendingHTLCInfo, InboundOnionErr> {
 	let (
-		payment_data, keysend_preimage, custom_tlvs, onion_amt_msat, outgoing_cltv_value,
-		payment_metadata, requires_blinded_error
+		payment_data, keysend_preimage, amp_record, custom_tlvs, onion_amt_msat, outgoing_cltv_value,
+		payment_metadata, requires_blinded_error, is_blinded
 	) = match hop_data {
 		msgs::InboundOnionPayload::Receive {
-			payment_data, keysend_preimage, custom_tlvs, amt_msat, outgoing_cltv_value, payment_metadata, ..
+			payment_data, keysend_preimage, amp_record, custom_tlvs, amt_msat, outgoing_cltv_value,
+			payment_metadata,
 		} =>
-			(payment_data, keysend_preimage, custom_tlvs, amt_msat, outgoing_cltv_value, payment_metadata,
-			 false),
+			(payment_data, keysend_preimage, amp_record, custom_tlvs, amt_msat, outgoing_cltv_value,
+			 payment_metadata, false, false),
 		msgs::InboundOnionPayload::BlindedReceive {
 			amt_msat, total_msat, outgoing_cltv_value, payment_secret, intro_node_blinding_point,
-			payment_constraints, ..
+			payment_constraints, keysend_preimage,
 		} => {
 			check_blinded_payment_constraints(amt_msat, cltv_expiry, &payment_constraints)
 				.map_err(|()| {
@@ -143,8 +148,8 @@ pub(super) fn create_recv_pending_htlc_info(
 					}
 				})?;
 			let payment_data = msgs::FinalOnionHopData { payment_secret, total_msat };
-			(Some(payment_data), None, Vec::new(), amt_msat, outgoing_cltv_value, None,
-			 intro_node_blinding_point.is_none())
+			(Some(payment_data), keysend_preimage, None, Vec::new(), amt_msat, outgoing_cltv_value, None,
+			 intro_node_blinding_point.is_none(), true)
 		}
 		msgs::InboundOnionPayload::Forward { .. } => {
 			return Err(InboundOnionErr {
@@ -159,7 +164,15 @@ pub(super) fn create_recv_pending_htlc_info(
 				err_data: vec![0; 32],
 				msg: "Got blinded non final data with an HMAC of 0",
 			})
-		}
+		},
+		msgs::InboundOnionPayload::TrampolineEntrypoint { .. } => {
+			// The trampoline onion is always decoded in `onion_utils::decode_next_payment_hop`.
+			return Err(InboundOnionErr {
+				err_code: 0x4000|22,
+				err_data: Vec::new(),
+				msg: "Got trampoline data without a decoded trampoline onion",
+			})
+		},
 	};
 	// final_incorrect_cltv_expiry
 	if outgoing_cltv_value > cltv_expiry {
@@ -210,7 +223,9 @@ pub(super) fn create_recv_pending_htlc_info(
 				msg: "Payment preimage didn't match payment hash",
 			});
 		}
-		if !accept_mpp_keysend && payment_data.is_some() {
+		// The payment secret of a blinded path is one we created ourselves, e.g., for a static
+		// invoice, so keysend payments over it are always accepted.
+		if !accept_mpp_keysend && payment_data.is_some() && !is_blinded {
 			return Err(InboundOnionErr {
 				err_code: 0x4000|22,
 				err_data: Vec::new(),
@@ -223,6 +238,21 @@ pub(super) fn create_recv_pending_htlc_info(
 			payment_metadata,
 			incoming_cltv_expiry: outgoing_cltv_value,
 			custom_tlvs,
+			requires_blinded_error,
+		}
+	} else if let Some(amp_record) = amp_record {
+		// AMP parts are tied together using the MPP payment secret.
+		let payment_data = payment_data.ok_or(InboundOnionErr {
+			err_code: 0x4000|22,
+			err_data: Vec::new(),
+			msg: "AMP payments require payment_data",
+		})?;
+		PendingHTLCRouting::ReceiveAmp {
+			payment_data,
+			amp_record,
+			payment_metadata,
+			incoming_cltv_expiry: outgoing_cltv_value,
+			custom_tlvs,
 		}
 	} else if let Some(data) = payment_data {
 		PendingHTLCRouting::Receive {
@@ -248,9 +278,99 @@ pub(super) fn create_recv_pending_htlc_info(
 		outgoing_amt_msat: onion_amt_msat,
 		outgoing_cltv_value,
 		skimmed_fee_msat: counterparty_skimmed_fee_msat,
+		hold_htlc: None,
+		incoming_endorsed: None,
+	})
+}
+
+pub(super) fn create_trampoline_fwd_pending_htlc_info(
+	msg: &msgs::UpdateAddHTLC, trampoline_hop: onion_utils::Hop, shared_secret: [u8; 32],
+	next_trampoline_pubkey: Result<PublicKey, secp256k1::Error>, cur_height: u32,
+) -> Result<PendingHTLCInfo, InboundOnionErr> {
+	let (
+		outgoing_node_id, amt_to_forward, outgoing_cltv_value, next_trampoline_hmac,
+		new_trampoline_packet_bytes
+	) = match trampoline_hop {
+		onion_utils::Hop::TrampolineForward {
+			outgoing_node_id, amt_to_forward, outgoing_cltv_value, next_trampoline_hmac,
+			new_trampoline_packet_bytes, ..
+		} => (outgoing_node_id, amt_to_forward, outgoing_cltv_value, next_trampoline_hmac,
+			new_trampoline_packet_bytes),
+		_ => return Err(InboundOnionErr {
+			msg: "Non-trampoline OnionHopData provided for a trampoline forward",
+			err_code: 0x4000 | 22,
+			err_data: Vec::new(),
+		}),
+	};
+	let public_key = next_trampoline_pubkey.map_err(|_| InboundOnionErr {
+		msg: "Unable to derive the next trampoline onion ephemeral pubkey",
+		err_code: 0x4000 | 22,
+		err_data: Vec::new(),
+	})?;
+
+	// trampoline_fee_insufficient
+	if msg.amount_msat <= amt_to_forward {
+		return Err(InboundOnionErr {
+			msg: "Trampoline fee was insufficient to route to the next trampoline node",
+			err_code: 0x2000 | 51,
+			err_data: Vec::new(),
+		});
+	}
+	// trampoline_expiry_too_soon
+	if (msg.cltv_expiry as u64) <= outgoing_cltv_value as u64 + MIN_CLTV_EXPIRY_DELTA as u64 ||
+		msg.cltv_expiry <= cur_height + HTLC_FAIL_BACK_BUFFER ||
+		outgoing_cltv_value <= cur_height + LATENCY_GRACE_PERIOD_BLOCKS
+	{
+		return Err(InboundOnionErr {
+			msg: "Trampoline CLTV expiry delta was insufficient to route to the next trampoline node",
+			err_code: 0x2000 | 52,
+			err_data: Vec::new(),
+		});
+	}
+	if msg.cltv_expiry > cur_height + CLTV_FAR_FAR_AWAY {
+		return Err(InboundOnionErr {
+			msg: "CLTV expiry is too far in the future",
+			err_code: 21,
+			err_data: Vec::new(),
+		});
+	}
+
+	Ok(PendingHTLCInfo {
+		routing: PendingHTLCRouting::TrampolineForward {
+			onion_packet: msgs::TrampolineOnionPacket {
+				version: 0,
+				public_key,
+				hop_data: new_trampoline_packet_bytes,
+				hmac: next_trampoline_hmac,
+			},
+			node_id: outgoing_node_id,
+			incoming_cltv_expiry: msg.cltv_expiry,
+		},
+		payment_hash: msg.payment_hash,
+		incoming_shared_secret: shared_secret,
+		incoming_amt_msat: Some(msg.amount_msat),
+		outgoing_amt_msat: amt_to_forward,
+		outgoing_cltv_value,
+		skimmed_fee_msat: None,
+		hold_htlc: None,
+		incoming_endorsed: None,
 	})
 }
 
+/// Calculates the ephemeral pubkey of the trampoline onion we'll hand to the next trampoline node.
+pub(super) fn next_trampoline_pubkey<T: secp256k1::Verification>(
+	secp_ctx: &Secp256k1<T>, trampoline_hop: &onion_utils::Hop
+) -> Result<PublicKey, secp256k1::Error> {
+	match trampoline_hop {
+		onion_utils::Hop::TrampolineForward {
+			trampoline_shared_secret, incoming_trampoline_public_key, ..
+		} => onion_utils::next_hop_pubkey(
+			secp_ctx, *incoming_trampoline_public_key, trampoline_shared_secret
+		),
+		_ => Err(secp256k1::Error::InvalidPublicKey),
+	}
+}
+
 /// Peel one layer off an incoming onion, returning a [`PendingHTLCInfo`] that contains information
 /// about the intended next-hop for the HTLC.
 ///
@@ -315,7 +435,13 @@ where
 				received_data, shared_secret, msg.payment_hash, msg.amount_msat, msg.cltv_expiry,
 				None, allow_skimmed_fees, msg.skimmed_fee_msat, cur_height, accept_mpp_keysend,
 			)?
-		}
+		},
+		onion_utils::Hop::TrampolineForward { .. } => {
+			let next_trampoline_pubkey = next_trampoline_pubkey(secp_ctx, &hop);
+			create_trampoline_fwd_pending_htlc_info(
+				msg, hop, shared_secret, next_trampoline_pubkey, cur_height
+			)?
+		},
 	})
 }
 
@@ -440,9 +566,11 @@ where
 				outgoing_cltv_value
 			}
 		},
-		onion_utils::Hop::Receive { .. } => return Ok((next_hop, shared_secret, None)),
+		onion_utils::Hop::Receive { .. } | onion_utils::Hop::TrampolineForward { .. } =>
+			return Ok((next_hop, shared_secret, None)),
 		onion_utils::Hop::Forward { next_hop_data: msgs::InboundOnionPayload::Receive { .. }, .. } |
-			onion_utils::Hop::Forward { next_hop_data: msgs::InboundOnionPayload::BlindedReceive { .. }, .. } =>
+			onion_utils::Hop::Forward { next_hop_data: msgs::InboundOnionPayload::BlindedReceive { .. }, .. } |
+			onion_utils::Hop::Forward { next_hop_data: msgs::InboundOnionPayload::TrampolineEntrypoint { .. }, .. } =>
 		{
 			return_err!("Final Node OnionHopData provided for us as an intermediary node", 0x4000 | 22, &[0; 0]);
 		}
@@ -596,6 +724,8 @@ mod tests {
 			onion_routing_packet,
 			skimmed_fee_msat: None,
 			blinding_point: None,
+			hold_htlc: None,
+			endorsed: None,
 		}
 	}
 
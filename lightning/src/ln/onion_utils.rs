@@ -12,8 +12,8 @@ use crate::ln::channelmanager::{HTLCSource, RecipientOnionFields};
 use crate::ln::msgs;
 use crate::ln::wire::Encode;
 use crate::routing::gossip::NetworkUpdate;
-use crate::routing::router::{BlindedTail, Path, RouteHop};
-use crate::sign::NodeSigner;
+use crate::routing::router::{BlindedTail, Path, RouteHop, TrampolineHop};
+use crate::sign::{NodeSigner, Recipient};
 use crate::util::chacha20::{ChaCha20, ChaChaReader};
 use crate::util::errors::{self, APIError};
 use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer, LengthCalculatingWriter};
@@ -165,6 +165,40 @@ pub(super) fn construct_onion_keys<T: secp256k1::Signing>(secp_ctx: &Secp256k1<T
 	Ok(res)
 }
 
+// can only fail if a trampoline hop has an invalid public key or session_priv is invalid
+pub(super) fn construct_trampoline_onion_keys<T: secp256k1::Signing>(
+	secp_ctx: &Secp256k1<T>, hops: &[PublicKey], session_priv: &SecretKey
+) -> Result<Vec<OnionKeys>, secp256k1::Error> {
+	let mut res = Vec::with_capacity(hops.len());
+	let mut blinded_priv = session_priv.clone();
+	let mut blinded_pub = PublicKey::from_secret_key(secp_ctx, &blinded_priv);
+
+	for pubkey in hops.iter() {
+		let shared_secret = SharedSecret::new(pubkey, &blinded_priv);
+
+		let mut sha = Sha256::engine();
+		sha.input(&blinded_pub.serialize()[..]);
+		sha.input(shared_secret.as_ref());
+		let blinding_factor = Sha256::from_engine(sha).to_byte_array();
+
+		let (rho, mu) = gen_rho_mu_from_shared_secret(shared_secret.as_ref());
+		res.push(OnionKeys {
+			#[cfg(test)]
+			shared_secret,
+			#[cfg(test)]
+			blinding_factor,
+			ephemeral_pubkey: blinded_pub,
+			rho,
+			mu,
+		});
+
+		blinded_priv = blinded_priv.mul_tweak(&Scalar::from_be_bytes(blinding_factor).unwrap())?;
+		blinded_pub = PublicKey::from_secret_key(secp_ctx, &blinded_priv);
+	}
+
+	Ok(res)
+}
+
 /// returns the hop data, as well as the first-hop value_msat and CLTV value we should send.
 pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_onion: RecipientOnionFields, starting_htlc_offset: u32, keysend_preimage: &Option<PaymentPreimage>) -> Result<(Vec<msgs::OutboundOnionPayload>, u64, u32), APIError> {
 	let mut cur_value_msat = 0u64;
@@ -195,6 +229,7 @@ pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_o
 							outgoing_cltv_value: cltv,
 							encrypted_tlvs: blinded_hop.encrypted_payload.clone(),
 							intro_node_blinding_point: blinding_point.take(),
+							keysend_preimage: *keysend_preimage,
 						});
 					} else {
 						res.push(msgs::OutboundOnionPayload::BlindedForward {
@@ -203,6 +238,18 @@ pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_o
 						});
 					}
 				}
+			} else if let Some(trampoline_packet) = recipient_onion.trampoline_packet.take() {
+				res.push(msgs::OutboundOnionPayload::TrampolineEntrypoint {
+					amt_to_forward: value_msat,
+					outgoing_cltv_value: cltv,
+					multipath_trampoline_data: recipient_onion.payment_secret.take().map(|secret| {
+						msgs::FinalOnionHopData {
+							payment_secret: secret,
+							total_msat,
+						}
+					}),
+					trampoline_packet,
+				});
 			} else {
 				res.push(msgs::OutboundOnionPayload::Receive {
 					payment_data: if let Some(secret) = recipient_onion.payment_secret.take() {
@@ -213,6 +260,7 @@ pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_o
 					} else { None },
 					payment_metadata: recipient_onion.payment_metadata.take(),
 					keysend_preimage: *keysend_preimage,
+					amp_record: recipient_onion.amp_record.take(),
 					custom_tlvs: recipient_onion.custom_tlvs.clone(),
 					amt_msat: value_msat,
 					outgoing_cltv_value: cltv,
@@ -242,7 +290,32 @@ pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_o
 /// the hops can be of variable length.
 pub(crate) const ONION_DATA_LEN: usize = 20*65;
 
+/// Length of the data in a [`msgs::TrampolineOnionPacket`] we construct. Trampoline onions are
+/// carried inside the final hop payload of a regular onion, so must leave enough room for the rest
+/// of the route to the trampoline node.
+pub(crate) const TRAMPOLINE_ONION_DATA_LEN: usize = 650;
+
 pub(super) const INVALID_ONION_BLINDING: u16 = 0x8000 | 0x4000 | 24;
+pub(super) const TEMPORARY_TRAMPOLINE_FAILURE: u16 = 0x2000 | 25;
+pub(super) const TRAMPOLINE_FEE_INSUFFICIENT: u16 = 0x2000 | 51;
+pub(super) const TRAMPOLINE_EXPIRY_TOO_SOON: u16 = 0x2000 | 52;
+
+/// Derives the preimage of the part of an AMP payment with the given `child_index` from the
+/// payment's root seed.
+pub(super) fn amp_child_preimage(root_seed: &[u8; 32], child_index: u32) -> PaymentPreimage {
+	let mut engine = Sha256::engine();
+	engine.input(root_seed);
+	engine.input(&child_index.to_be_bytes());
+	PaymentPreimage(Sha256::from_engine(engine).to_byte_array())
+}
+
+/// XORs an AMP root seed `share` into `acc`. Shares of all parts of an AMP payment XOR to the
+/// payment's root seed.
+pub(super) fn xor_amp_share(acc: &mut [u8; 32], share: &[u8; 32]) {
+	for (a, b) in acc.iter_mut().zip(share.iter()) {
+		*a ^= b;
+	}
+}
 
 #[inline]
 fn shift_slice_right(arr: &mut [u8], amt: usize) {
@@ -280,6 +353,69 @@ pub(super) fn construct_onion_packet_with_writable_hopdata<HD: Writeable>(payloa
 		payloads, onion_keys, FixedSizeOnionPacket(packet_data), Some(associated_data))
 }
 
+pub(super) fn construct_trampoline_onion_packet(
+	payloads: Vec<msgs::OutboundTrampolinePayload>, onion_keys: Vec<OnionKeys>, prng_seed: [u8; 32],
+	associated_data: &PaymentHash
+) -> Result<msgs::TrampolineOnionPacket, ()> {
+	let mut packet_data = vec![0; TRAMPOLINE_ONION_DATA_LEN];
+
+	let mut chacha = ChaCha20::new(&prng_seed, &[0; 8]);
+	chacha.process_in_place(&mut packet_data);
+
+	construct_onion_packet_with_init_noise::<_, _>(
+		payloads, onion_keys, packet_data, Some(associated_data))
+}
+
+/// Build a trampoline onion which has `trampoline_hop` find a route to and pay `recipient`,
+/// returning the value_msat and CLTV value the trampoline node must be paid with as well.
+/// `cur_block_height` should be set to the best known block height + 1.
+pub(super) fn create_trampoline_onion<T: secp256k1::Signing>(
+	secp_ctx: &Secp256k1<T>, trampoline_hop: &TrampolineHop, recipient: PublicKey,
+	final_value_msat: u64, final_cltv_expiry_delta: u32, recipient_onion: &RecipientOnionFields,
+	cur_block_height: u32, payment_hash: &PaymentHash, session_priv: &SecretKey,
+	prng_seed: [u8; 32]
+) -> Result<(msgs::TrampolineOnionPacket, u64, u32), APIError> {
+	let onion_keys = construct_trampoline_onion_keys(
+		secp_ctx, &[trampoline_hop.pubkey, recipient], session_priv
+	).map_err(|_| APIError::InvalidRoute{
+		err: "Pubkey along trampoline hop was maliciously selected".to_owned()
+	})?;
+
+	let final_cltv = cur_block_height.checked_add(final_cltv_expiry_delta);
+	let trampoline_msat = final_value_msat.checked_add(trampoline_hop.fee_msat)
+		.filter(|amt| *amt < 21000000 * 100000000 * 1000);
+	let (final_cltv, trampoline_msat) = match (final_cltv, trampoline_msat) {
+		(Some(cltv), Some(msat)) => (cltv, msat),
+		_ => return Err(APIError::InvalidRoute{err: "Trampoline fees or CLTV overflowed?".to_owned()}),
+	};
+	let trampoline_cltv = final_cltv.saturating_add(trampoline_hop.cltv_expiry_delta);
+	if trampoline_cltv >= 500000000 {
+		return Err(APIError::InvalidRoute{err: "Trampoline CLTV overflowed?".to_owned()});
+	}
+
+	let payloads = vec![
+		msgs::OutboundTrampolinePayload::Forward {
+			amt_to_forward: final_value_msat,
+			outgoing_cltv_value: final_cltv,
+			outgoing_node_id: recipient,
+		},
+		msgs::OutboundTrampolinePayload::Receive {
+			payment_data: recipient_onion.payment_secret.map(|payment_secret| {
+				msgs::FinalOnionHopData { payment_secret, total_msat: final_value_msat }
+			}),
+			payment_metadata: recipient_onion.payment_metadata.clone(),
+			custom_tlvs: recipient_onion.custom_tlvs.clone(),
+			amt_msat: final_value_msat,
+			outgoing_cltv_value: final_cltv,
+		},
+	];
+	let onion_packet = construct_trampoline_onion_packet(payloads, onion_keys, prng_seed, payment_hash)
+		.map_err(|_| APIError::InvalidRoute{
+			err: "Trampoline payloads too large considering trampoline onion data".to_owned()
+		})?;
+	Ok((onion_packet, trampoline_msat, trampoline_cltv))
+}
+
 /// Since onion message packets and onion payment packets have different lengths but are otherwise
 /// identical, we use this trait to allow `construct_onion_packet_with_init_noise` to return either
 /// type.
@@ -824,6 +960,9 @@ impl HTLCFailReason {
 		else if failure_code == 21 { debug_assert!(data.is_empty()) }
 		else if failure_code == 22 | PERM { debug_assert!(data.len() <= 11) }
 		else if failure_code == 23 { debug_assert!(data.is_empty()) }
+		else if failure_code == 25 | NODE { debug_assert!(data.is_empty()) }
+		else if failure_code == 51 | NODE { debug_assert!(data.is_empty()) }
+		else if failure_code == 52 | NODE { debug_assert!(data.is_empty()) }
 		else if failure_code & BADONION != 0 {
 			// We set some bogus BADONION failure codes in test, so ignore unknown ones.
 		}
@@ -840,6 +979,48 @@ impl HTLCFailReason {
 		Self(HTLCFailReasonRepr::LightningError { err: msg.reason.clone() })
 	}
 
+	/// Converts the failure of an HTLC we forwarded as a trampoline node into one we can pass back
+	/// to the sender, given the route we found to the next trampoline node and the session key of
+	/// the onion we built for it.
+	///
+	/// Failures returned along that route are encrypted to us rather than to the sender. Those from
+	/// the next trampoline node itself concern the rest of the payment, so are passed back as if
+	/// they were our own, while any others are reported as a temporary trampoline failure.
+	pub(super) fn for_trampoline_forward<T: secp256k1::Signing>(
+		&self, secp_ctx: &Secp256k1<T>, path: &Path, session_priv: &SecretKey
+	) -> Self {
+		let mut encrypted_packet = match self.0 {
+			HTLCFailReasonRepr::LightningError { ref err } => err.data.clone(),
+			HTLCFailReasonRepr::Reason { .. } => return Self::from_failure_code(TEMPORARY_TRAMPOLINE_FAILURE),
+		};
+
+		let mut failuremsg = None;
+		let _ = construct_onion_keys_callback(secp_ctx, path, session_priv,
+			|shared_secret, _, _, _, route_hop_idx|
+		{
+			if failuremsg.is_some() { return; }
+
+			let err_packet = match decrypt_onion_error_packet(&mut encrypted_packet, shared_secret) {
+				Ok(p) => p,
+				Err(_) => return
+			};
+			let um = gen_um_from_shared_secret(shared_secret.as_ref());
+			let mut hmac = HmacEngine::<Sha256>::new(&um);
+			hmac.input(&err_packet.encode()[32..]);
+
+			if !fixed_time_eq(&Hmac::from_engine(hmac).to_byte_array(), &err_packet.hmac) { return }
+			failuremsg = Some((route_hop_idx + 1 == path.hops.len(), err_packet.failuremsg));
+		});
+
+		match failuremsg {
+			Some((true, failuremsg)) if failuremsg.len() >= 2 => Self(HTLCFailReasonRepr::Reason {
+				failure_code: u16::from_be_bytes([failuremsg[0], failuremsg[1]]),
+				data: failuremsg[2..].to_vec(),
+			}),
+			_ => Self::from_failure_code(TEMPORARY_TRAMPOLINE_FAILURE),
+		}
+	}
+
 	pub(super) fn get_encrypted_failure_packet(&self, incoming_packet_shared_secret: &[u8; 32], phantom_shared_secret: &Option<[u8; 32]>)
 	-> msgs::OnionErrorPacket {
 		match self.0 {
@@ -921,6 +1102,24 @@ pub(crate) enum Hop {
 		/// Bytes of the onion packet we're forwarding.
 		new_packet_bytes: [u8; ONION_DATA_LEN],
 	},
+	/// This onion payload was for us as a trampoline node, and the trampoline onion it carried needs
+	/// to be forwarded to the next trampoline node along a route of our choosing.
+	TrampolineForward {
+		/// The shared secret used to decrypt the trampoline onion.
+		trampoline_shared_secret: [u8; 32],
+		/// The ephemeral public key of the trampoline onion we received.
+		incoming_trampoline_public_key: PublicKey,
+		/// The node we need to find a route to.
+		outgoing_node_id: PublicKey,
+		/// The value, in msat, to deliver to `outgoing_node_id`.
+		amt_to_forward: u64,
+		/// The CLTV expiry the HTLC to `outgoing_node_id` must have.
+		outgoing_cltv_value: u32,
+		/// HMAC of the next trampoline onion packet.
+		next_trampoline_hmac: [u8; 32],
+		/// Bytes of the trampoline onion packet we're forwarding.
+		new_trampoline_packet_bytes: Vec<u8>,
+	},
 }
 
 /// Error returned when we fail to decode the onion packet.
@@ -945,6 +1144,13 @@ pub(crate) fn decode_next_payment_hop<NS: Deref>(
 	match decode_next_hop(
 		shared_secret, hop_data, hmac_bytes, Some(payment_hash), (blinding_point, node_signer)
 	) {
+		Ok((msgs::InboundOnionPayload::TrampolineEntrypoint {
+			amt_to_forward, multipath_trampoline_data, trampoline_packet, ..
+		}, None)) => {
+			decode_trampoline_hop(
+				amt_to_forward, multipath_trampoline_data, trampoline_packet, payment_hash, node_signer
+			)
+		},
 		Ok((next_hop_data, None)) => Ok(Hop::Receive(next_hop_data)),
 		Ok((next_hop_data, Some((next_hop_hmac, FixedSizeOnionPacket(new_packet_bytes))))) => {
 			Ok(Hop::Forward {
@@ -957,6 +1163,70 @@ pub(crate) fn decode_next_payment_hop<NS: Deref>(
 	}
 }
 
+fn decode_trampoline_hop<NS: Deref>(
+	amt_to_forward: u64, multipath_trampoline_data: Option<msgs::FinalOnionHopData>,
+	trampoline_packet: msgs::TrampolineOnionPacket, payment_hash: PaymentHash, node_signer: &NS,
+) -> Result<Hop, OnionDecodeErr> where NS::Target: NodeSigner {
+	// We don't yet support aggregating multiple incoming HTLCs into a single trampoline forward.
+	if multipath_trampoline_data.map_or(false, |data| data.total_msat != amt_to_forward) {
+		return Err(OnionDecodeErr::Relay {
+			err_msg: "Multi-part trampoline payments are not supported",
+			err_code: 0x4000 | 22,
+		});
+	}
+	if trampoline_packet.version != 0 {
+		return Err(OnionDecodeErr::Relay {
+			err_msg: "Unknown trampoline onion packet version",
+			err_code: 0x4000 | 22,
+		});
+	}
+	let trampoline_shared_secret = match node_signer.ecdh(
+		Recipient::Node, &trampoline_packet.public_key, None
+	) {
+		Ok(ss) => ss.secret_bytes(),
+		Err(()) => return Err(OnionDecodeErr::Relay {
+			err_msg: "Invalid trampoline onion ephemeral pubkey",
+			err_code: 0x4000 | 22,
+		}),
+	};
+	let res = decode_next_hop::<_, msgs::InboundTrampolinePayload, Vec<u8>>(
+		trampoline_shared_secret, &trampoline_packet.hop_data, trampoline_packet.hmac,
+		Some(payment_hash), ()
+	);
+	// Errors in the trampoline onion can't be reported as malformed, as our peer never saw it.
+	let res = res.map_err(|e| match e {
+		OnionDecodeErr::Malformed { err_msg, .. } => OnionDecodeErr::Relay { err_msg, err_code: 0x4000 | 22 },
+		e => e,
+	})?;
+	match res {
+		(msgs::InboundTrampolinePayload::Receive {
+			payment_data, payment_metadata, custom_tlvs, amt_msat, outgoing_cltv_value
+		}, None) => {
+			Ok(Hop::Receive(msgs::InboundOnionPayload::Receive {
+				payment_data, payment_metadata, custom_tlvs, amt_msat, outgoing_cltv_value,
+				keysend_preimage: None, amp_record: None,
+			}))
+		},
+		(msgs::InboundTrampolinePayload::Forward {
+			amt_to_forward, outgoing_cltv_value, outgoing_node_id
+		}, Some((next_trampoline_hmac, new_trampoline_packet_bytes))) => {
+			Ok(Hop::TrampolineForward {
+				trampoline_shared_secret,
+				incoming_trampoline_public_key: trampoline_packet.public_key,
+				outgoing_node_id,
+				amt_to_forward,
+				outgoing_cltv_value,
+				next_trampoline_hmac,
+				new_trampoline_packet_bytes,
+			})
+		},
+		_ => Err(OnionDecodeErr::Relay {
+			err_msg: "Trampoline payload did not match its position in the trampoline onion",
+			err_code: 0x4000 | 22,
+		}),
+	}
+}
+
 /// Build a payment onion, returning the first hop msat and cltv values as well.
 /// `cur_block_height` should be set to the best known block height + 1.
 pub fn create_payment_onion<T: secp256k1::Signing>(
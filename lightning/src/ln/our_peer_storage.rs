@@ -0,0 +1,179 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Types describing the backup of our channels which we ask our peers to store on our behalf via
+//! [`msgs::PeerStorage`] messages.
+//!
+//! The backup is encrypted with the key returned by [`NodeSigner::get_peer_storage_key`] and only
+//! contains the minimal information required to identify our channels and re-derive their keys,
+//! allowing a node which lost its state to ask its counterparties to force-close and recover its
+//! funds using only its seed.
+//!
+//! [`NodeSigner::get_peer_storage_key`]: crate::sign::NodeSigner::get_peer_storage_key
+
+use bitcoin::secp256k1::PublicKey;
+
+use crate::chain::transaction::OutPoint;
+use crate::ln::ChannelId;
+use crate::ln::features::ChannelTypeFeatures;
+use crate::ln::msgs::{self, DecodeError};
+use crate::sign::KeyMaterial;
+use crate::util::chacha20poly1305rfc::ChaCha20Poly1305RFC;
+use crate::util::ser::{Readable, Writeable};
+
+use crate::io::Cursor;
+use crate::prelude::*;
+
+/// The length of the random nonce prepended to the encrypted backup. Note that
+/// [`ChaCha20Poly1305RFC`] requires the first four bytes of its 12-byte nonce to be zero, so we only
+/// include the remaining eight.
+const NONCE_LEN: usize = 8;
+/// The length of the authentication tag appended to the encrypted backup.
+const TAG_LEN: usize = 16;
+
+/// The information required to recover the funds in one of our channels, as included in the
+/// backup we store with our peers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerStorageChannel {
+	/// The node id of our counterparty in the channel.
+	pub counterparty_node_id: PublicKey,
+	/// The channel's ID.
+	pub channel_id: ChannelId,
+	/// The channel's funding transaction output.
+	pub funding_txo: OutPoint,
+	/// The value, in satoshis, of the channel.
+	pub channel_value_satoshis: u64,
+	/// The identifier passed to [`SignerProvider::derive_channel_signer`] to derive the channel's
+	/// keys.
+	///
+	/// [`SignerProvider::derive_channel_signer`]: crate::sign::SignerProvider::derive_channel_signer
+	pub channel_keys_id: [u8; 32],
+	/// The channel's type, which determines the scripts our funds are locked in on-chain.
+	pub channel_type: ChannelTypeFeatures,
+}
+
+impl_writeable_tlv_based!(PeerStorageChannel, {
+	(0, counterparty_node_id, required),
+	(2, channel_id, required),
+	(4, funding_txo, required),
+	(6, channel_value_satoshis, required),
+	(8, channel_keys_id, required),
+	(10, channel_type, required),
+});
+
+/// The decrypted contents of the backup we store with our peers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecryptedOurPeerStorage {
+	/// The channels included in the backup.
+	pub channels: Vec<PeerStorageChannel>,
+}
+
+impl_writeable_tlv_based!(DecryptedOurPeerStorage, {
+	(0, channels, required_vec),
+});
+
+impl DecryptedOurPeerStorage {
+	/// The length of the blob [`Self::encrypt`] would return.
+	pub(crate) fn encrypted_len(&self) -> usize {
+		NONCE_LEN + self.serialized_length() + TAG_LEN
+	}
+
+	/// Encrypts the backup with the given key, using the first bytes of `random_bytes` as the
+	/// nonce, which is prepended to the resulting blob.
+	pub(crate) fn encrypt(&self, key: &KeyMaterial, random_bytes: &[u8; 32]) -> Vec<u8> {
+		let mut res = Vec::with_capacity(self.encrypted_len());
+		res.extend_from_slice(&random_bytes[..NONCE_LEN]);
+		res.extend_from_slice(&self.encode());
+		let mut tag = [0; TAG_LEN];
+		let mut chacha = ChaCha20Poly1305RFC::new(&key.0, &Self::chacha_nonce(&res[..NONCE_LEN]), b"");
+		chacha.encrypt_full_message_in_place(&mut res[NONCE_LEN..], &mut tag);
+		res.extend_from_slice(&tag);
+		res
+	}
+
+	/// Decrypts a blob previously returned by [`Self::encrypt`], failing if it wasn't encrypted with
+	/// the given key or has been tampered with.
+	pub(crate) fn decrypt(key: &KeyMaterial, blob: &[u8]) -> Result<Self, DecodeError> {
+		if blob.len() < NONCE_LEN + TAG_LEN {
+			return Err(DecodeError::InvalidValue);
+		}
+		let (nonce, ciphertext) = blob.split_at(NONCE_LEN);
+		let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_LEN);
+		let mut plaintext = ciphertext.to_vec();
+		let mut chacha = ChaCha20Poly1305RFC::new(&key.0, &Self::chacha_nonce(nonce), b"");
+		chacha.check_decrypt_in_place(&mut plaintext, tag)
+			.map_err(|()| DecodeError::InvalidValue)?;
+		Readable::read(&mut Cursor::new(&plaintext))
+	}
+
+	fn chacha_nonce(nonce: &[u8]) -> [u8; 12] {
+		let mut chacha_nonce = [0; 12];
+		chacha_nonce[4..].copy_from_slice(nonce);
+		chacha_nonce
+	}
+
+	/// Builds the [`msgs::PeerStorage`] message to be sent to a peer.
+	pub(crate) fn to_peer_storage_msg(&self, key: &KeyMaterial, random_bytes: &[u8; 32]) -> msgs::PeerStorage {
+		msgs::PeerStorage { data: self.encrypt(key, random_bytes) }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{DecryptedOurPeerStorage, PeerStorageChannel};
+
+	use bitcoin::hashes::Hash;
+	use bitcoin::hash_types::Txid;
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+	use crate::chain::transaction::OutPoint;
+	use crate::ln::ChannelId;
+	use crate::ln::features::ChannelTypeFeatures;
+	use crate::sign::KeyMaterial;
+
+	fn test_storage() -> DecryptedOurPeerStorage {
+		let secp_ctx = Secp256k1::new();
+		let counterparty_node_id = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		DecryptedOurPeerStorage {
+			channels: vec![PeerStorageChannel {
+				counterparty_node_id,
+				channel_id: ChannelId::from_bytes([1; 32]),
+				funding_txo: OutPoint { txid: Txid::all_zeros(), index: 1 },
+				channel_value_satoshis: 100_000,
+				channel_keys_id: [2; 32],
+				channel_type: ChannelTypeFeatures::only_static_remote_key(),
+			}],
+		}
+	}
+
+	#[test]
+	fn encrypt_decrypt_round_trip() {
+		let storage = test_storage();
+		let key = KeyMaterial([3; 32]);
+		let blob = storage.encrypt(&key, &[4; 32]);
+		assert_eq!(blob.len(), storage.encrypted_len());
+		assert_eq!(DecryptedOurPeerStorage::decrypt(&key, &blob).unwrap(), storage);
+
+		// A different nonce results in a different blob.
+		assert_ne!(storage.encrypt(&key, &[5; 32]), blob);
+	}
+
+	#[test]
+	fn fails_to_decrypt_with_wrong_key_or_tampered_blob() {
+		let storage = test_storage();
+		let key = KeyMaterial([3; 32]);
+		let mut blob = storage.encrypt(&key, &[4; 32]);
+		assert!(DecryptedOurPeerStorage::decrypt(&KeyMaterial([5; 32]), &blob).is_err());
+
+		let last_byte_idx = blob.len() - 1;
+		blob[last_byte_idx] ^= 1;
+		assert!(DecryptedOurPeerStorage::decrypt(&key, &blob).is_err());
+		assert!(DecryptedOurPeerStorage::decrypt(&key, &blob[..10]).is_err());
+	}
+}
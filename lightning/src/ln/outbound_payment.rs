@@ -9,7 +9,7 @@
 
 //! Utilities to send payments and manage outbound payment information.
 
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{Hash, HashEngine};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::secp256k1::{self, Secp256k1, SecretKey};
 
@@ -17,15 +17,17 @@ use crate::sign::{EntropySource, NodeSigner, Recipient};
 use crate::events::{self, PaymentFailureReason};
 use crate::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use crate::ln::channelmanager::{ChannelDetails, EventCompletionAction, HTLCSource, PaymentId};
-use crate::ln::onion_utils::{DecodedOnionFailure, HTLCFailReason};
+use crate::ln::msgs;
+use crate::ln::onion_utils::{self, DecodedOnionFailure, HTLCFailReason};
 use crate::offers::invoice::Bolt12Invoice;
+use crate::offers::static_invoice::StaticInvoice;
 use crate::routing::router::{InFlightHtlcs, Path, PaymentParameters, Route, RouteParameters, Router};
 use crate::util::errors::APIError;
 use crate::util::logger::Logger;
 use crate::util::time::Time;
 #[cfg(all(not(feature = "no-std"), test))]
 use crate::util::time::tests::SinceEpoch;
-use crate::util::ser::ReadableArgs;
+use crate::util::ser::{LengthReadable, ReadableArgs};
 
 use core::fmt::{self, Display, Formatter};
 use core::ops::Deref;
@@ -46,10 +48,25 @@ pub(crate) enum PendingOutboundPayment {
 	Legacy {
 		session_privs: HashSet<[u8; 32]>,
 	},
+	/// Used when we are waiting for an [`Offer`] to be resolved from a [`HumanReadableName`] before
+	/// requesting an invoice for it.
+	///
+	/// [`Offer`]: crate::offers::offer::Offer
+	/// [`HumanReadableName`]: crate::onion_message::HumanReadableName
+	AwaitingOffer {
+		expiration: StaleExpiration,
+		retry_strategy: Retry,
+		max_total_routing_fee_msat: Option<u64>,
+		/// The amount to pay the resolved offer.
+		amount_msats: u64,
+	},
 	AwaitingInvoice {
 		expiration: StaleExpiration,
 		retry_strategy: Retry,
 		max_total_routing_fee_msat: Option<u64>,
+		/// The most we're willing to pay for the invoice, set when paying for an offer denominated in
+		/// a currency other than bitcoin.
+		max_invoice_amount_msats: Option<u64>,
 	},
 	InvoiceReceived {
 		payment_hash: PaymentHash,
@@ -57,6 +74,12 @@ pub(crate) enum PendingOutboundPayment {
 		// Note this field is currently just replicated from AwaitingInvoice but not actually
 		// used anywhere.
 		max_total_routing_fee_msat: Option<u64>,
+		/// The invoice being paid, surfaced in [`Event::PaymentSent`] for creating a payer proof.
+		///
+		/// [`Event::PaymentSent`]: crate::events::Event::PaymentSent
+		bolt12_invoice: Option<Bolt12Invoice>,
+		/// The preimage we picked when paying a [`StaticInvoice`], which has no payment hash.
+		keysend_preimage: Option<PaymentPreimage>,
 	},
 	Retryable {
 		retry_strategy: Option<Retry>,
@@ -68,6 +91,7 @@ pub(crate) enum PendingOutboundPayment {
 		payment_metadata: Option<Vec<u8>>,
 		keysend_preimage: Option<PaymentPreimage>,
 		custom_tlvs: Vec<(u64, Vec<u8>)>,
+		trampoline_packet: Option<msgs::TrampolineOnionPacket>,
 		pending_amt_msat: u64,
 		/// Used to track the fee paid. Only present if the payment was serialized on 0.0.103+.
 		pending_fee_msat: Option<u64>,
@@ -76,6 +100,10 @@ pub(crate) enum PendingOutboundPayment {
 		/// Our best known block height at the time this payment was initiated.
 		starting_block_height: u32,
 		remaining_max_total_routing_fee_msat: Option<u64>,
+		/// The BOLT 12 invoice being paid, if any.
+		bolt12_invoice: Option<Bolt12Invoice>,
+		/// Set if this is an AMP payment, tracking how its root seed is split across parts.
+		amp: Option<AmpSendState>,
 	},
 	/// When a pending payment is fulfilled, we continue tracking it until all pending HTLCs have
 	/// been resolved. This ensures we don't look up pending payments in ChannelMonitors on restart
@@ -96,6 +124,20 @@ pub(crate) enum PendingOutboundPayment {
 	},
 }
 
+/// Tracks the root seed of an AMP payment and the share of it sent in each part in flight.
+pub(crate) struct AmpSendState {
+	root_seed: [u8; 32],
+	next_child_index: u32,
+	/// The root seed share sent in each part, keyed by the part's session_priv.
+	shares: HashMap<[u8; 32], [u8; 32]>,
+}
+
+impl_writeable_tlv_based!(AmpSendState, {
+	(0, root_seed, required),
+	(2, next_child_index, required),
+	(4, shares, required),
+});
+
 impl PendingOutboundPayment {
 	fn increment_attempts(&mut self) {
 		if let PendingOutboundPayment::Retryable { attempts, .. } = self {
@@ -131,6 +173,7 @@ impl PendingOutboundPayment {
 	}
 	fn is_awaiting_invoice(&self) -> bool {
 		match self {
+			PendingOutboundPayment::AwaitingOffer { .. } => true,
 			PendingOutboundPayment::AwaitingInvoice { .. } => true,
 			_ => false,
 		}
@@ -154,9 +197,68 @@ impl PendingOutboundPayment {
 		}
 	}
 
+	fn bolt12_invoice(&self) -> Option<&Bolt12Invoice> {
+		match self {
+			PendingOutboundPayment::InvoiceReceived { bolt12_invoice, .. } => bolt12_invoice.as_ref(),
+			PendingOutboundPayment::Retryable { bolt12_invoice, .. } => bolt12_invoice.as_ref(),
+			_ => None,
+		}
+	}
+
+	fn amp_root_seed(&self) -> Option<[u8; 32]> {
+		match self {
+			PendingOutboundPayment::Retryable { amp: Some(amp), .. } => Some(amp.root_seed),
+			_ => None,
+		}
+	}
+
+	/// Splits the root seed of an AMP payment across the new parts with the given session_privs,
+	/// such that the shares of all parts in flight XOR to the root seed once they're sent. Returns
+	/// the [`msgs::AmpRecord`] and payment hash for each new part, or `None` if this isn't an AMP
+	/// payment.
+	fn amp_shards(&mut self, session_privs: &[[u8; 32]]) -> Option<Vec<(msgs::AmpRecord, PaymentHash)>> {
+		let (pending_session_privs, amp) = match self {
+			PendingOutboundPayment::Retryable { session_privs, amp: Some(amp), .. } => (session_privs, amp),
+			_ => return None,
+		};
+		// Parts which have failed no longer count towards the root seed, so the new parts replace
+		// their shares.
+		amp.shares.retain(|session_priv, _| pending_session_privs.contains(session_priv));
+		let mut last_share = amp.root_seed;
+		for share in amp.shares.values() {
+			onion_utils::xor_amp_share(&mut last_share, share);
+		}
+
+		let set_id = Sha256::hash(&amp.root_seed).to_byte_array();
+		let mut shards = Vec::with_capacity(session_privs.len());
+		for (idx, session_priv) in session_privs.iter().enumerate() {
+			let root_share = if idx == session_privs.len() - 1 {
+				last_share
+			} else {
+				// The session_priv is random and never revealed, so a hash of it is as good a share
+				// as any.
+				let mut engine = Sha256::engine();
+				engine.input(b"LDK AMP root share");
+				engine.input(session_priv);
+				let share = Sha256::from_engine(engine).to_byte_array();
+				onion_utils::xor_amp_share(&mut last_share, &share);
+				share
+			};
+			let child_index = amp.next_child_index;
+			amp.next_child_index += 1;
+			amp.shares.insert(*session_priv, root_share);
+
+			let child_preimage = onion_utils::amp_child_preimage(&amp.root_seed, child_index);
+			let child_hash = PaymentHash(Sha256::hash(&child_preimage.0).to_byte_array());
+			shards.push((msgs::AmpRecord { root_share, set_id, child_index }, child_hash));
+		}
+		Some(shards)
+	}
+
 	fn payment_hash(&self) -> Option<PaymentHash> {
 		match self {
 			PendingOutboundPayment::Legacy { .. } => None,
+			PendingOutboundPayment::AwaitingOffer { .. } => None,
 			PendingOutboundPayment::AwaitingInvoice { .. } => None,
 			PendingOutboundPayment::InvoiceReceived { payment_hash, .. } => Some(*payment_hash),
 			PendingOutboundPayment::Retryable { payment_hash, .. } => Some(*payment_hash),
@@ -172,7 +274,8 @@ impl PendingOutboundPayment {
 				PendingOutboundPayment::Retryable { session_privs, .. } |
 				PendingOutboundPayment::Fulfilled { session_privs, .. } |
 				PendingOutboundPayment::Abandoned { session_privs, .. } => session_privs,
-			PendingOutboundPayment::AwaitingInvoice { .. } |
+			PendingOutboundPayment::AwaitingOffer { .. } |
+				PendingOutboundPayment::AwaitingInvoice { .. } |
 				PendingOutboundPayment::InvoiceReceived { .. } => { debug_assert!(false); return; },
 		});
 		let payment_hash = self.payment_hash();
@@ -206,7 +309,8 @@ impl PendingOutboundPayment {
 				PendingOutboundPayment::Abandoned { session_privs, .. } => {
 					session_privs.remove(session_priv)
 				},
-			PendingOutboundPayment::AwaitingInvoice { .. } |
+			PendingOutboundPayment::AwaitingOffer { .. } |
+				PendingOutboundPayment::AwaitingInvoice { .. } |
 				PendingOutboundPayment::InvoiceReceived { .. } => { debug_assert!(false); false },
 		};
 		if remove_res {
@@ -235,7 +339,8 @@ impl PendingOutboundPayment {
 				PendingOutboundPayment::Retryable { session_privs, .. } => {
 					session_privs.insert(session_priv)
 				},
-			PendingOutboundPayment::AwaitingInvoice { .. } |
+			PendingOutboundPayment::AwaitingOffer { .. } |
+				PendingOutboundPayment::AwaitingInvoice { .. } |
 				PendingOutboundPayment::InvoiceReceived { .. } => { debug_assert!(false); false },
 			PendingOutboundPayment::Fulfilled { .. } => false,
 			PendingOutboundPayment::Abandoned { .. } => false,
@@ -267,6 +372,7 @@ impl PendingOutboundPayment {
 				PendingOutboundPayment::Abandoned { session_privs, .. } => {
 					session_privs.len()
 				},
+			PendingOutboundPayment::AwaitingOffer { .. } => 0,
 			PendingOutboundPayment::AwaitingInvoice { .. } => 0,
 			PendingOutboundPayment::InvoiceReceived { .. } => 0,
 		}
@@ -378,7 +484,8 @@ impl<T: Time> Display for PaymentAttemptsUsingTime<T> {
 	}
 }
 
-/// How long before a [`PendingOutboundPayment::AwaitingInvoice`] should be considered stale and
+/// How long before a [`PendingOutboundPayment::AwaitingOffer`] or
+/// [`PendingOutboundPayment::AwaitingInvoice`] should be considered stale and
 /// candidate for removal in [`OutboundPayments::remove_stale_payments`].
 #[derive(Clone, Copy)]
 pub(crate) enum StaleExpiration {
@@ -495,6 +602,9 @@ pub(super) enum Bolt12PaymentError {
 	UnexpectedInvoice,
 	/// Payment for an invoice with the corresponding [`PaymentId`] was already initiated.
 	DuplicateInvoice,
+	/// The invoice amount exceeded what we expected to pay after converting the amount of an offer
+	/// denominated in a currency other than bitcoin.
+	ExcessiveInvoiceAmount,
 }
 
 /// Indicates that we failed to send a payment probe. Further errors may be surfaced later via
@@ -542,12 +652,26 @@ pub struct RecipientOnionFields {
 	pub payment_metadata: Option<Vec<u8>>,
 	/// See [`Self::custom_tlvs`] for more info.
 	pub(super) custom_tlvs: Vec<(u64, Vec<u8>)>,
+	/// A trampoline onion to hand to the last hop of the route, which will act as a trampoline
+	/// node and find the rest of the route to the recipient. Only set when sending via
+	/// [`ChannelManager::send_payment_via_trampoline`].
+	///
+	/// [`ChannelManager::send_payment_via_trampoline`]: super::channelmanager::ChannelManager::send_payment_via_trampoline
+	pub(super) trampoline_packet: Option<msgs::TrampolineOnionPacket>,
+	/// The AMP record to include in the onion when sending via
+	/// [`ChannelManager::send_amp_payment`]. Until the payment is split into parts, its
+	/// `root_share` holds the payment's entire root seed.
+	///
+	/// [`ChannelManager::send_amp_payment`]: super::channelmanager::ChannelManager::send_amp_payment
+	pub(super) amp_record: Option<msgs::AmpRecord>,
 }
 
 impl_writeable_tlv_based!(RecipientOnionFields, {
 	(0, payment_secret, option),
 	(1, custom_tlvs, optional_vec),
 	(2, payment_metadata, option),
+	(3, trampoline_packet, (option: LengthReadable)),
+	(5, amp_record, option),
 });
 
 impl RecipientOnionFields {
@@ -555,7 +679,7 @@ impl RecipientOnionFields {
 	/// set of onion fields for today's BOLT11 invoices - most nodes require a [`PaymentSecret`]
 	/// but do not require or provide any further data.
 	pub fn secret_only(payment_secret: PaymentSecret) -> Self {
-		Self { payment_secret: Some(payment_secret), payment_metadata: None, custom_tlvs: Vec::new() }
+		Self { payment_secret: Some(payment_secret), payment_metadata: None, custom_tlvs: Vec::new(), trampoline_packet: None, amp_record: None }
 	}
 
 	/// Creates a new [`RecipientOnionFields`] with no fields. This generally does not create
@@ -567,7 +691,7 @@ impl RecipientOnionFields {
 	/// [`ChannelManager::send_spontaneous_payment`]: super::channelmanager::ChannelManager::send_spontaneous_payment
 	/// [`RecipientOnionFields::secret_only`]: RecipientOnionFields::secret_only
 	pub fn spontaneous_empty() -> Self {
-		Self { payment_secret: None, payment_metadata: None, custom_tlvs: Vec::new() }
+		Self { payment_secret: None, payment_metadata: None, custom_tlvs: Vec::new(), trampoline_packet: None, amp_record: None }
 	}
 
 	/// Creates a new [`RecipientOnionFields`] from an existing one, adding custom TLVs. Each
@@ -660,6 +784,7 @@ pub(super) struct SendAlongPathArgs<'a> {
 	pub payment_id: PaymentId,
 	pub keysend_preimage: &'a Option<PaymentPreimage>,
 	pub session_priv_bytes: [u8; 32],
+	pub hold_htlc: Option<[u8; 32]>,
 }
 
 pub(super) struct OutboundPayments {
@@ -735,6 +860,36 @@ impl OutboundPayments {
 			.map(|()| payment_hash)
 	}
 
+	pub(super) fn send_amp_payment<R: Deref, ES: Deref, NS: Deref, IH, SP, L: Deref>(
+		&self, mut recipient_onion: RecipientOnionFields, payment_id: PaymentId,
+		retry_strategy: Retry, route_params: RouteParameters, router: &R,
+		first_hops: Vec<ChannelDetails>, inflight_htlcs: IH, entropy_source: &ES,
+		node_signer: &NS, best_block_height: u32, logger: &L,
+		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>, send_payment_along_path: SP
+	) -> Result<PaymentHash, RetryableSendFailure>
+	where
+		R::Target: Router,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		L::Target: Logger,
+		IH: Fn() -> InFlightHtlcs,
+		SP: Fn(SendAlongPathArgs) -> Result<(), APIError>,
+	{
+		let root_seed = entropy_source.get_secure_random_bytes();
+		let set_id = Sha256::hash(&root_seed).to_byte_array();
+		// AMP parts are tied together using the MPP payment secret, which the sender picks as
+		// there's no invoice to take it from.
+		if recipient_onion.payment_secret.is_none() {
+			recipient_onion.payment_secret = Some(PaymentSecret(entropy_source.get_secure_random_bytes()));
+		}
+		recipient_onion.amp_record = Some(msgs::AmpRecord { root_share: root_seed, set_id, child_index: 0 });
+		let payment_hash = PaymentHash(set_id);
+		self.send_payment_internal(payment_id, payment_hash, recipient_onion, None, retry_strategy,
+			route_params, router, first_hops, inflight_htlcs, entropy_source, node_signer,
+			best_block_height, logger, pending_events, send_payment_along_path)
+			.map(|()| payment_hash)
+	}
+
 	pub(super) fn send_spontaneous_payment_with_route<ES: Deref, NS: Deref, F>(
 		&self, route: &Route, payment_preimage: Option<PaymentPreimage>,
 		recipient_onion: RecipientOnionFields, payment_id: PaymentId, entropy_source: &ES,
@@ -781,12 +936,19 @@ impl OutboundPayments {
 		let max_total_routing_fee_msat;
 		match self.pending_outbound_payments.lock().unwrap().entry(payment_id) {
 			hash_map::Entry::Occupied(entry) => match entry.get() {
-				PendingOutboundPayment::AwaitingInvoice { retry_strategy, max_total_routing_fee_msat: max_total_fee, .. } => {
+				PendingOutboundPayment::AwaitingInvoice {
+					retry_strategy, max_total_routing_fee_msat: max_total_fee, max_invoice_amount_msats, ..
+				} => {
+					if max_invoice_amount_msats.map_or(false, |max_amount| invoice.amount_msats() > max_amount) {
+						return Err(Bolt12PaymentError::ExcessiveInvoiceAmount);
+					}
 					max_total_routing_fee_msat = *max_total_fee;
 					*entry.into_mut() = PendingOutboundPayment::InvoiceReceived {
 						payment_hash,
 						retry_strategy: *retry_strategy,
 						max_total_routing_fee_msat,
+						bolt12_invoice: Some(invoice.clone()),
+						keysend_preimage: None,
 					};
 				},
 				_ => return Err(Bolt12PaymentError::DuplicateInvoice),
@@ -810,6 +972,70 @@ impl OutboundPayments {
 		Ok(())
 	}
 
+	/// Pays `invoice` as an async payment, picking the preimage ourselves since static invoices
+	/// don't commit to a payment hash.
+	///
+	/// The HTLCs are held by our first hop until the recipient comes online, which a retried HTLC
+	/// wouldn't be, so the payment is never retried. For the same reason, the payment is limited to
+	/// a single path.
+	pub(super) fn send_payment_for_static_invoice<R: Deref, ES: Deref, NS: Deref, IH, SP, L: Deref>(
+		&self, invoice: &StaticInvoice, amount_msats: u64, payment_id: PaymentId, router: &R,
+		first_hops: Vec<ChannelDetails>, inflight_htlcs: IH, entropy_source: &ES, node_signer: &NS,
+		best_block_height: u32, logger: &L,
+		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>,
+		send_payment_along_path: SP,
+	) -> Result<(), Bolt12PaymentError>
+	where
+		R::Target: Router,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		L::Target: Logger,
+		IH: Fn() -> InFlightHtlcs,
+		SP: Fn(SendAlongPathArgs) -> Result<(), APIError>,
+	{
+		let keysend_preimage = PaymentPreimage(entropy_source.get_secure_random_bytes());
+		let payment_hash = PaymentHash(Sha256::hash(&keysend_preimage.0).to_byte_array());
+		let max_total_routing_fee_msat;
+		match self.pending_outbound_payments.lock().unwrap().entry(payment_id) {
+			hash_map::Entry::Occupied(entry) => match entry.get() {
+				PendingOutboundPayment::AwaitingInvoice {
+					max_total_routing_fee_msat: max_total_fee, max_invoice_amount_msats, ..
+				} => {
+					if max_invoice_amount_msats.map_or(false, |max_amount| amount_msats > max_amount) {
+						return Err(Bolt12PaymentError::ExcessiveInvoiceAmount);
+					}
+					max_total_routing_fee_msat = *max_total_fee;
+					*entry.into_mut() = PendingOutboundPayment::InvoiceReceived {
+						payment_hash,
+						retry_strategy: Retry::Attempts(0),
+						max_total_routing_fee_msat,
+						bolt12_invoice: None,
+						keysend_preimage: Some(keysend_preimage),
+					};
+				},
+				_ => return Err(Bolt12PaymentError::DuplicateInvoice),
+			},
+			hash_map::Entry::Vacant(_) => return Err(Bolt12PaymentError::UnexpectedInvoice),
+		};
+
+		let mut pay_params = PaymentParameters::blinded(invoice.payment_paths().to_vec())
+			.with_bolt12_features(invoice.invoice_features().clone()).unwrap()
+			.with_expiry_time(invoice.created_at().as_secs().saturating_add(invoice.relative_expiry().as_secs()));
+		pay_params.max_path_count = 1;
+		let mut route_params = RouteParameters::from_payment_params_and_value(pay_params, amount_msats);
+		if let Some(max_fee_msat) = max_total_routing_fee_msat {
+			route_params.max_total_routing_fee_msat = Some(max_fee_msat);
+		}
+
+		self.find_route_and_send_payment(
+			payment_hash, payment_id, route_params, router, first_hops, &inflight_htlcs,
+			entropy_source, node_signer, best_block_height, logger, pending_events,
+			&send_payment_along_path
+		);
+
+		Ok(())
+	}
+
 	pub(super) fn check_retry_payments<R: Deref, ES: Deref, NS: Deref, SP, IH, FH, L: Deref>(
 		&self, router: &R, first_hops: FH, inflight_htlcs: IH, entropy_source: &ES, node_signer: &NS,
 		best_block_height: u32,
@@ -1008,7 +1234,7 @@ impl OutboundPayments {
 					match payment.get() {
 						PendingOutboundPayment::Retryable {
 							total_msat, keysend_preimage, payment_secret, payment_metadata,
-							custom_tlvs, pending_amt_msat, ..
+							custom_tlvs, trampoline_packet, pending_amt_msat, ..
 						} => {
 							const RETRY_OVERFLOW_PERCENTAGE: u64 = 10;
 							let retry_amt_msat = route.get_total_amount();
@@ -1029,6 +1255,9 @@ impl OutboundPayments {
 								payment_secret: *payment_secret,
 								payment_metadata: payment_metadata.clone(),
 								custom_tlvs: custom_tlvs.clone(),
+								trampoline_packet: trampoline_packet.clone(),
+								// Each part's AMP record is filled in when it is sent.
+								amp_record: None,
 							};
 							let keysend_preimage = *keysend_preimage;
 
@@ -1049,25 +1278,32 @@ impl OutboundPayments {
 							log_error!(logger, "Unable to retry payments that were initially sent on LDK versions prior to 0.0.102");
 							return
 						},
-						PendingOutboundPayment::AwaitingInvoice { .. } => {
+						PendingOutboundPayment::AwaitingOffer { .. } |
+							PendingOutboundPayment::AwaitingInvoice { .. } =>
+						{
 							log_error!(logger, "Payment not yet sent");
 							return
 						},
-						PendingOutboundPayment::InvoiceReceived { payment_hash, retry_strategy, .. } => {
+						PendingOutboundPayment::InvoiceReceived {
+							payment_hash, retry_strategy, bolt12_invoice, keysend_preimage, ..
+						} => {
 							let total_amount = route_params.final_value_msat;
 							let recipient_onion = RecipientOnionFields {
 								payment_secret: None,
 								payment_metadata: None,
 								custom_tlvs: vec![],
+								trampoline_packet: None,
+								amp_record: None,
 							};
 							let retry_strategy = Some(*retry_strategy);
 							let payment_params = Some(route_params.payment_params.clone());
 							let (retryable_payment, onion_session_privs) = self.create_pending_payment(
-								*payment_hash, recipient_onion.clone(), None, &route,
-								retry_strategy, payment_params, entropy_source, best_block_height
+								*payment_hash, recipient_onion.clone(), *keysend_preimage, bolt12_invoice.clone(),
+								&route, retry_strategy, payment_params, entropy_source, best_block_height
 							);
+							let keysend_preimage = *keysend_preimage;
 							*payment.into_mut() = retryable_payment;
-							(total_amount, recipient_onion, None, onion_session_privs)
+							(total_amount, recipient_onion, keysend_preimage, onion_session_privs)
 						},
 						PendingOutboundPayment::Fulfilled { .. } => {
 							log_error!(logger, "Payment already completed");
@@ -1237,7 +1473,7 @@ impl OutboundPayments {
 			hash_map::Entry::Occupied(_) => Err(PaymentSendFailure::DuplicatePayment),
 			hash_map::Entry::Vacant(entry) => {
 				let (payment, onion_session_privs) = self.create_pending_payment(
-					payment_hash, recipient_onion, keysend_preimage, route, retry_strategy,
+					payment_hash, recipient_onion, keysend_preimage, None, route, retry_strategy,
 					payment_params, entropy_source, best_block_height
 				);
 				entry.insert(payment);
@@ -1248,8 +1484,9 @@ impl OutboundPayments {
 
 	fn create_pending_payment<ES: Deref>(
 		&self, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields,
-		keysend_preimage: Option<PaymentPreimage>, route: &Route, retry_strategy: Option<Retry>,
-		payment_params: Option<PaymentParameters>, entropy_source: &ES, best_block_height: u32
+		keysend_preimage: Option<PaymentPreimage>, bolt12_invoice: Option<Bolt12Invoice>,
+		route: &Route, retry_strategy: Option<Retry>, payment_params: Option<PaymentParameters>,
+		entropy_source: &ES, best_block_height: u32
 	) -> (PendingOutboundPayment, Vec<[u8; 32]>)
 	where
 		ES::Target: EntropySource,
@@ -1271,10 +1508,15 @@ impl OutboundPayments {
 			payment_metadata: recipient_onion.payment_metadata,
 			keysend_preimage,
 			custom_tlvs: recipient_onion.custom_tlvs,
+			trampoline_packet: recipient_onion.trampoline_packet,
 			starting_block_height: best_block_height,
 			total_msat: route.get_total_amount(),
 			remaining_max_total_routing_fee_msat:
 				route.route_params.as_ref().and_then(|p| p.max_total_routing_fee_msat),
+			bolt12_invoice,
+			amp: recipient_onion.amp_record.map(|record| AmpSendState {
+				root_seed: record.root_share, next_child_index: 0, shares: HashMap::new(),
+			}),
 		};
 
 		for (path, session_priv_bytes) in route.paths.iter().zip(onion_session_privs.iter()) {
@@ -1286,7 +1528,7 @@ impl OutboundPayments {
 
 	pub(super) fn add_new_awaiting_invoice(
 		&self, payment_id: PaymentId, expiration: StaleExpiration, retry_strategy: Retry,
-		max_total_routing_fee_msat: Option<u64>
+		max_total_routing_fee_msat: Option<u64>, max_invoice_amount_msats: Option<u64>
 	) -> Result<(), ()> {
 		let mut pending_outbounds = self.pending_outbound_payments.lock().unwrap();
 		match pending_outbounds.entry(payment_id) {
@@ -1296,6 +1538,27 @@ impl OutboundPayments {
 					expiration,
 					retry_strategy,
 					max_total_routing_fee_msat,
+					max_invoice_amount_msats,
+				});
+
+				Ok(())
+			},
+		}
+	}
+
+	pub(super) fn add_new_awaiting_offer(
+		&self, payment_id: PaymentId, expiration: StaleExpiration, retry_strategy: Retry,
+		max_total_routing_fee_msat: Option<u64>, amount_msats: u64
+	) -> Result<(), ()> {
+		let mut pending_outbounds = self.pending_outbound_payments.lock().unwrap();
+		match pending_outbounds.entry(payment_id) {
+			hash_map::Entry::Occupied(_) => Err(()),
+			hash_map::Entry::Vacant(entry) => {
+				entry.insert(PendingOutboundPayment::AwaitingOffer {
+					expiration,
+					retry_strategy,
+					max_total_routing_fee_msat,
+					amount_msats,
 				});
 
 				Ok(())
@@ -1303,6 +1566,53 @@ impl OutboundPayments {
 		}
 	}
 
+	pub(super) fn amount_for_awaiting_offer(&self, payment_id: PaymentId) -> Result<u64, ()> {
+		match self.pending_outbound_payments.lock().unwrap().get(&payment_id) {
+			Some(PendingOutboundPayment::AwaitingOffer { amount_msats, .. }) => Ok(*amount_msats),
+			_ => Err(()),
+		}
+	}
+
+	pub(super) fn is_awaiting_offer(&self, payment_id: PaymentId) -> bool {
+		match self.pending_outbound_payments.lock().unwrap().get(&payment_id) {
+			Some(PendingOutboundPayment::AwaitingOffer { .. }) => true,
+			_ => false,
+		}
+	}
+
+	pub(super) fn is_awaiting_invoice(&self, payment_id: PaymentId) -> bool {
+		match self.pending_outbound_payments.lock().unwrap().get(&payment_id) {
+			Some(PendingOutboundPayment::AwaitingInvoice { .. }) => true,
+			_ => false,
+		}
+	}
+
+	/// Transitions a payment awaiting an offer to awaiting an invoice for it, once an invoice request
+	/// has been built for the resolved offer.
+	pub(super) fn received_offer(
+		&self, payment_id: PaymentId, expiration: StaleExpiration
+	) -> Result<(), ()> {
+		match self.pending_outbound_payments.lock().unwrap().entry(payment_id) {
+			hash_map::Entry::Occupied(entry) => match entry.get() {
+				PendingOutboundPayment::AwaitingOffer {
+					retry_strategy, max_total_routing_fee_msat, ..
+				} => {
+					let retry_strategy = *retry_strategy;
+					let max_total_routing_fee_msat = *max_total_routing_fee_msat;
+					*entry.into_mut() = PendingOutboundPayment::AwaitingInvoice {
+						expiration,
+						retry_strategy,
+						max_total_routing_fee_msat,
+						max_invoice_amount_msats: None,
+					};
+					Ok(())
+				},
+				_ => Err(()),
+			},
+			hash_map::Entry::Vacant(_) => Err(()),
+		}
+	}
+
 	fn pay_route_internal<NS: Deref, F>(
 		&self, route: &Route, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields,
 		keysend_preimage: Option<PaymentPreimage>, payment_id: PaymentId, recv_value_msat: Option<u64>,
@@ -1357,10 +1667,20 @@ impl OutboundPayments {
 		let cur_height = best_block_height + 1;
 		let mut results = Vec::new();
 		debug_assert_eq!(route.paths.len(), onion_session_privs.len());
-		for (path, session_priv_bytes) in route.paths.iter().zip(onion_session_privs.into_iter()) {
+		let amp_shards = self.pending_outbound_payments.lock().unwrap().get_mut(&payment_id)
+			.and_then(|payment| payment.amp_shards(&onion_session_privs));
+		for (idx, (path, session_priv_bytes)) in route.paths.iter().zip(onion_session_privs.into_iter()).enumerate() {
+			let mut recipient_onion = recipient_onion.clone();
+			let mut path_payment_hash = payment_hash;
+			if let Some((amp_record, child_hash)) = amp_shards.as_ref().map(|shards| shards[idx].clone()) {
+				// Each part of an AMP payment is locked to its own payment hash.
+				recipient_onion.amp_record = Some(amp_record);
+				path_payment_hash = child_hash;
+			}
 			let mut path_res = send_payment_along_path(SendAlongPathArgs {
-				path: &path, payment_hash: &payment_hash, recipient_onion: recipient_onion.clone(),
-				total_value, cur_height, payment_id, keysend_preimage: &keysend_preimage, session_priv_bytes
+				path: &path, payment_hash: &path_payment_hash, recipient_onion,
+				total_value, cur_height, payment_id, keysend_preimage: &keysend_preimage, session_priv_bytes,
+				hold_htlc: None,
 			});
 			match path_res {
 				Ok(_) => {},
@@ -1469,15 +1789,21 @@ impl OutboundPayments {
 		let mut outbounds = self.pending_outbound_payments.lock().unwrap();
 		let mut pending_events = pending_events.lock().unwrap();
 		if let hash_map::Entry::Occupied(mut payment) = outbounds.entry(payment_id) {
+			// Each part of an AMP payment reveals only its own preimage, so report the root seed
+			// from which they're all derived instead.
+			let payment_preimage = payment.get().amp_root_seed().map(PaymentPreimage)
+				.unwrap_or(payment_preimage);
 			if !payment.get().is_fulfilled() {
 				let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).to_byte_array());
 				log_info!(logger, "Payment with id {} and hash {} sent!", payment_id, payment_hash);
 				let fee_paid_msat = payment.get().get_pending_fee_msat();
+				let bolt12_invoice = payment.get().bolt12_invoice().cloned();
 				pending_events.push_back((events::Event::PaymentSent {
 					payment_id: Some(payment_id),
 					payment_preimage,
 					payment_hash,
 					fee_paid_msat,
+					bolt12_invoice,
 				}, Some(ev_completion_action.clone())));
 				payment.get_mut().mark_fulfilled();
 			}
@@ -1567,7 +1893,9 @@ impl OutboundPayments {
 					true
 				}
 			},
-			PendingOutboundPayment::AwaitingInvoice { expiration, .. } => {
+			PendingOutboundPayment::AwaitingOffer { expiration, .. } |
+				PendingOutboundPayment::AwaitingInvoice { expiration, .. } =>
+			{
 				let is_stale = match expiration {
 					StaleExpiration::AbsoluteTimeout(absolute_expiry) => {
 						*absolute_expiry <= duration_since_epoch
@@ -1736,7 +2064,7 @@ impl OutboundPayments {
 					}, None));
 					payment.remove();
 				}
-			} else if let PendingOutboundPayment::AwaitingInvoice { .. } = payment.get() {
+			} else if payment.get().is_awaiting_invoice() {
 				pending_events.lock().unwrap().push_back((events::Event::InvoiceRequestFailed {
 					payment_id,
 				}, None));
@@ -1797,6 +2125,9 @@ impl_writeable_tlv_based_enum_upgradable!(PendingOutboundPayment,
 		(9, custom_tlvs, optional_vec),
 		(10, starting_block_height, required),
 		(11, remaining_max_total_routing_fee_msat, option),
+		(13, trampoline_packet, (option: LengthReadable)),
+		(15, bolt12_invoice, option),
+		(17, amp, option),
 		(not_written, retry_strategy, (static_value, None)),
 		(not_written, attempts, (static_value, PaymentAttempts::new())),
 	},
@@ -1809,11 +2140,20 @@ impl_writeable_tlv_based_enum_upgradable!(PendingOutboundPayment,
 		(0, expiration, required),
 		(2, retry_strategy, required),
 		(4, max_total_routing_fee_msat, option),
+		(6, max_invoice_amount_msats, option),
 	},
 	(7, InvoiceReceived) => {
 		(0, payment_hash, required),
 		(2, retry_strategy, required),
 		(4, max_total_routing_fee_msat, option),
+		(6, bolt12_invoice, option),
+		(7, keysend_preimage, option),
+	},
+	(9, AwaitingOffer) => {
+		(0, expiration, required),
+		(2, retry_strategy, required),
+		(4, max_total_routing_fee_msat, option),
+		(6, amount_msats, required),
 	},
 );
 
@@ -1832,7 +2172,7 @@ mod tests {
 	use crate::ln::outbound_payment::{Bolt12PaymentError, OutboundPayments, Retry, RetryableSendFailure, StaleExpiration};
 	#[cfg(feature = "std")]
 	use crate::offers::invoice::DEFAULT_RELATIVE_EXPIRY;
-	use crate::offers::offer::OfferBuilder;
+	use crate::offers::offer::{Amount, OfferBuilder};
 	use crate::offers::test_utils::*;
 	use crate::routing::gossip::NetworkGraph;
 	use crate::routing::router::{InFlightHtlcs, Path, PaymentParameters, Route, RouteHop, RouteParameters};
@@ -2046,7 +2386,7 @@ mod tests {
 		assert!(!outbound_payments.has_pending_payments());
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
-				payment_id, expiration, Retry::Attempts(0), None
+				payment_id, expiration, Retry::Attempts(0), None, None
 			).is_ok()
 		);
 		assert!(outbound_payments.has_pending_payments());
@@ -2072,14 +2412,14 @@ mod tests {
 
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
-				payment_id, expiration, Retry::Attempts(0), None
+				payment_id, expiration, Retry::Attempts(0), None, None
 			).is_ok()
 		);
 		assert!(outbound_payments.has_pending_payments());
 
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
-				payment_id, expiration, Retry::Attempts(0), None
+				payment_id, expiration, Retry::Attempts(0), None, None
 			).is_err()
 		);
 	}
@@ -2095,7 +2435,7 @@ mod tests {
 		assert!(!outbound_payments.has_pending_payments());
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
-				payment_id, expiration, Retry::Attempts(0), None
+				payment_id, expiration, Retry::Attempts(0), None, None
 			).is_ok()
 		);
 		assert!(outbound_payments.has_pending_payments());
@@ -2121,14 +2461,14 @@ mod tests {
 
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
-				payment_id, expiration, Retry::Attempts(0), None
+				payment_id, expiration, Retry::Attempts(0), None, None
 			).is_ok()
 		);
 		assert!(outbound_payments.has_pending_payments());
 
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
-				payment_id, expiration, Retry::Attempts(0), None
+				payment_id, expiration, Retry::Attempts(0), None, None
 			).is_err()
 		);
 	}
@@ -2143,7 +2483,7 @@ mod tests {
 		assert!(!outbound_payments.has_pending_payments());
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
-				payment_id, expiration, Retry::Attempts(0), None
+				payment_id, expiration, Retry::Attempts(0), None, None
 			).is_ok()
 		);
 		assert!(outbound_payments.has_pending_payments());
@@ -2176,7 +2516,7 @@ mod tests {
 
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
-				payment_id, expiration, Retry::Attempts(0), None
+				payment_id, expiration, Retry::Attempts(0), None, None
 			).is_ok()
 		);
 		assert!(outbound_payments.has_pending_payments());
@@ -2238,7 +2578,7 @@ mod tests {
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
 				payment_id, expiration, Retry::Attempts(0),
-				Some(invoice.amount_msats() / 100 + 50_000)
+				Some(invoice.amount_msats() / 100 + 50_000), None
 			).is_ok()
 		);
 		assert!(outbound_payments.has_pending_payments());
@@ -2297,7 +2637,7 @@ mod tests {
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
 				payment_id, expiration, Retry::Attempts(0),
-				Some(invoice.amount_msats() / 100 + 50_000)
+				Some(invoice.amount_msats() / 100 + 50_000), None
 			).is_ok()
 		);
 		assert!(outbound_payments.has_pending_payments());
@@ -2394,7 +2734,7 @@ mod tests {
 
 		assert!(
 			outbound_payments.add_new_awaiting_invoice(
-				payment_id, expiration, Retry::Attempts(0), Some(1234)
+				payment_id, expiration, Retry::Attempts(0), Some(1234), None
 			).is_ok()
 		);
 		assert!(outbound_payments.has_pending_payments());
@@ -2419,4 +2759,48 @@ mod tests {
 		assert!(outbound_payments.has_pending_payments());
 		assert!(pending_events.lock().unwrap().is_empty());
 	}
+
+	#[test]
+	fn fails_paying_for_bolt12_invoice_with_excessive_amount() {
+		let logger = test_utils::TestLogger::new();
+		let network_graph = Arc::new(NetworkGraph::new(Network::Testnet, &logger));
+		let scorer = RwLock::new(test_utils::TestScorer::new());
+		let router = test_utils::TestRouter::new(network_graph, &scorer);
+		let keys_manager = test_utils::TestKeysInterface::new(&[0; 32], Network::Testnet);
+
+		let pending_events = Mutex::new(VecDeque::new());
+		let outbound_payments = OutboundPayments::new();
+		let payment_id = PaymentId([0; 32]);
+		let expiration = StaleExpiration::AbsoluteTimeout(Duration::from_secs(100));
+
+		let invoice = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount(Amount::Currency { iso4217_code: *b"USD", amount: 10 })
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.converted_amount_msats(1001).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+
+		assert!(
+			outbound_payments.add_new_awaiting_invoice(
+				payment_id, expiration, Retry::Attempts(0), None, Some(1000)
+			).is_ok()
+		);
+		assert!(outbound_payments.has_pending_payments());
+
+		assert_eq!(
+			outbound_payments.send_payment_for_bolt12_invoice(
+				&invoice, payment_id, &&router, vec![], || InFlightHtlcs::new(), &&keys_manager,
+				&&keys_manager, 0, &&logger, &pending_events, |_| panic!()
+			),
+			Err(Bolt12PaymentError::ExcessiveInvoiceAmount),
+		);
+
+		// The payment is still awaiting an acceptable invoice.
+		assert!(outbound_payments.has_pending_payments());
+		assert!(pending_events.lock().unwrap().is_empty());
+	}
 }
@@ -15,16 +15,16 @@ use crate::chain::{ChannelMonitorUpdateStatus, Confirm, Listen, Watch};
 use crate::chain::channelmonitor::{ANTI_REORG_DELAY, HTLC_FAIL_BACK_BUFFER, LATENCY_GRACE_PERIOD_BLOCKS};
 use crate::sign::EntropySource;
 use crate::chain::transaction::OutPoint;
-use crate::events::{ClosureReason, Event, HTLCDestination, MessageSendEvent, MessageSendEventsProvider, PathFailure, PaymentFailureReason, PaymentPurpose};
+use crate::events::{ClosureReason, Event, HTLCDestination, MessageSendEvent, MessageSendEventsProvider, PathFailure, PaymentFailureReason, PaymentPurpose, PeerForwardingLimit};
 use crate::ln::channel::{EXPIRE_PREV_CONFIG_TICKS, commit_tx_fee_msat, get_holder_selected_channel_reserve_satoshis, ANCHOR_OUTPUT_VALUE_SATOSHI};
-use crate::ln::channelmanager::{BREAKDOWN_TIMEOUT, MPP_TIMEOUT_TICKS, MIN_CLTV_EXPIRY_DELTA, PaymentId, PaymentSendFailure, RecentPaymentDetails, RecipientOnionFields, HTLCForwardInfo, PendingHTLCRouting, PendingAddHTLCInfo};
+use crate::ln::channelmanager::{BREAKDOWN_TIMEOUT, MPP_TIMEOUT_TICKS, MIN_CLTV_EXPIRY_DELTA, MIN_FINAL_CLTV_EXPIRY_DELTA, HoldInvoiceState, PaymentId, PaymentSendFailure, RecentPaymentDetails, RecipientOnionFields, HTLCForwardInfo, PendingHTLCRouting, PendingAddHTLCInfo};
 use crate::ln::features::{Bolt11InvoiceFeatures, ChannelTypeFeatures};
 use crate::ln::{msgs, ChannelId, PaymentHash, PaymentSecret, PaymentPreimage};
 use crate::ln::msgs::ChannelMessageHandler;
 use crate::ln::onion_utils;
-use crate::ln::outbound_payment::{IDEMPOTENCY_TIMEOUT_TICKS, Retry};
+use crate::ln::outbound_payment::{IDEMPOTENCY_TIMEOUT_TICKS, Retry, RetryableSendFailure};
 use crate::routing::gossip::{EffectiveCapacity, RoutingFees};
-use crate::routing::router::{get_route, Path, PaymentParameters, Route, Router, RouteHint, RouteHintHop, RouteHop, RouteParameters, find_route};
+use crate::routing::router::{get_route, Path, PaymentParameters, Route, Router, RouteHint, RouteHintHop, RouteHop, RouteParameters, TrampolineHop, find_route};
 use crate::routing::scoring::ChannelUsage;
 use crate::util::config::UserConfig;
 use crate::util::test_utils;
@@ -465,6 +465,199 @@ fn test_mpp_keysend() {
 	claim_payment_along_route(&nodes[0], expected_route, false, payment_preimage);
 }
 
+#[test]
+fn amp_payment_success() {
+	// Send an AMP payment over two paths, checking that each part is locked to its own payment hash
+	// and that the recipient can only claim the payment once both parts have arrived.
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 0, 2);
+	create_announced_chan_between_nodes(&nodes, 1, 3);
+	create_announced_chan_between_nodes(&nodes, 2, 3);
+	let network_graph = nodes[0].network_graph;
+
+	let payer_pubkey = nodes[0].node.get_our_node_id();
+	let payee_pubkey = nodes[3].node.get_our_node_id();
+	let recv_value = 15_000_000;
+	let route_params = RouteParameters::from_payment_params_and_value(
+		PaymentParameters::for_keysend(payee_pubkey, 40, true), recv_value);
+	let scorer = test_utils::TestScorer::new();
+	let random_seed_bytes = chanmon_cfgs[0].keys_manager.get_secure_random_bytes();
+	let route = find_route(&payer_pubkey, &route_params, &network_graph, None, nodes[0].logger,
+		&scorer, &Default::default(), &random_seed_bytes).unwrap();
+	assert_eq!(route.paths.len(), 2);
+	nodes[0].router.expect_find_route(route_params.clone(), Ok(route));
+
+	let payment_id = PaymentId([42; 32]);
+	let payment_hash = nodes[0].node.send_amp_payment(RecipientOnionFields::spontaneous_empty(),
+		payment_id, route_params, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 2);
+
+	let expected_route: &[&[&Node]] = &[&[&nodes[1], &nodes[3]], &[&nodes[2], &nodes[3]]];
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 2);
+	let first_part = remove_first_msg_event_to_node(&nodes[1].node.get_our_node_id(), &mut events);
+	let second_part = remove_first_msg_event_to_node(&nodes[2].node.get_our_node_id(), &mut events);
+	let first_part_hash = SendEvent::from_event(first_part.clone()).msgs[0].payment_hash;
+	let second_part_hash = SendEvent::from_event(second_part.clone()).msgs[0].payment_hash;
+	assert_ne!(first_part_hash, second_part_hash);
+	assert_ne!(first_part_hash, payment_hash);
+	assert_ne!(second_part_hash, payment_hash);
+
+	pass_along_path(&nodes[0], expected_route[0], recv_value, payment_hash, None, first_part, false, None);
+	do_pass_along_path(&nodes[0], expected_route[1], recv_value, payment_hash, None, second_part,
+		true, false, None, false);
+
+	// Only once both parts have arrived can the recipient reconstruct the root seed.
+	let events = nodes[3].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	let root_seed = match events[0] {
+		Event::PaymentClaimable {
+			payment_hash: claimable_hash, purpose: PaymentPurpose::SpontaneousPayment(preimage),
+			amount_msat, ref onion_fields, ..
+		} => {
+			assert_eq!(claimable_hash, payment_hash);
+			assert_eq!(amount_msat, recv_value);
+			assert!(onion_fields.as_ref().unwrap().payment_secret.is_some());
+			preimage
+		},
+		_ => panic!("Unexpected event"),
+	};
+	assert_eq!(Sha256::hash(&root_seed.0).to_byte_array(), payment_hash.0);
+
+	// The sender learns the root seed, rather than the preimage of either part.
+	claim_payment_along_route(&nodes[0], expected_route, false, root_seed);
+}
+
+#[test]
+fn amp_partial_claim_before_restart() {
+	// Claim an AMP payment, but restart with the ChannelManager from before the claim and only one
+	// of the two updated ChannelMonitors. Each part was claimed under its own payment hash, so on
+	// startup the stored preimage has to be mapped back to the payment's set id for the remaining
+	// part to be claimed as well.
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, None]);
+	let nodes_3_deserialized;
+	let mut nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 0, 2);
+	let chan_id_persisted = create_announced_chan_between_nodes(&nodes, 1, 3).2;
+	let chan_id_not_persisted = create_announced_chan_between_nodes(&nodes, 2, 3).2;
+
+	let payee_pubkey = nodes[3].node.get_our_node_id();
+	let recv_value = 15_000_000;
+	let route_params = RouteParameters::from_payment_params_and_value(
+		PaymentParameters::for_keysend(payee_pubkey, 40, true), recv_value);
+	let scorer = test_utils::TestScorer::new();
+	let random_seed_bytes = chanmon_cfgs[0].keys_manager.get_secure_random_bytes();
+	let route = find_route(&nodes[0].node.get_our_node_id(), &route_params, &nodes[0].network_graph,
+		None, nodes[0].logger, &scorer, &Default::default(), &random_seed_bytes).unwrap();
+	assert_eq!(route.paths.len(), 2);
+	nodes[0].router.expect_find_route(route_params.clone(), Ok(route));
+
+	let payment_hash = nodes[0].node.send_amp_payment(RecipientOnionFields::spontaneous_empty(),
+		PaymentId([42; 32]), route_params, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 2);
+
+	let expected_route: &[&[&Node]] = &[&[&nodes[1], &nodes[3]], &[&nodes[2], &nodes[3]]];
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 2);
+	let first_part = remove_first_msg_event_to_node(&nodes[1].node.get_our_node_id(), &mut events);
+	let second_part = remove_first_msg_event_to_node(&nodes[2].node.get_our_node_id(), &mut events);
+	let first_part_hash = SendEvent::from_event(first_part.clone()).msgs[0].payment_hash;
+	let second_part_hash = SendEvent::from_event(second_part.clone()).msgs[0].payment_hash;
+	pass_along_path(&nodes[0], expected_route[0], recv_value, payment_hash, None, first_part, false, None);
+	do_pass_along_path(&nodes[0], expected_route[1], recv_value, payment_hash, None, second_part,
+		true, false, None, false);
+
+	// Snapshot the ChannelManager and the monitor of the channel through nodes[2] before claiming.
+	let original_monitor = get_monitor!(nodes[3], chan_id_not_persisted).encode();
+	let original_manager = nodes[3].node.encode();
+
+	let events = nodes[3].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	let root_seed = match events[0] {
+		Event::PaymentClaimable { purpose: PaymentPurpose::SpontaneousPayment(preimage), .. } => preimage,
+		_ => panic!("Unexpected event"),
+	};
+	nodes[3].node.claim_funds(root_seed);
+	check_added_monitors!(nodes[3], 2);
+	expect_payment_claimed!(nodes[3], payment_hash, recv_value);
+
+	// Restart with only the monitor of the channel through nodes[1] having seen the claim.
+	let updated_monitor = get_monitor!(nodes[3], chan_id_persisted).encode();
+	reload_node!(nodes[3], original_manager, &[&updated_monitor, &original_monitor], persister, new_chain_monitor, nodes_3_deserialized);
+
+	// The preimage of the second part was derived from the root seed and provided to its monitor.
+	assert!(get_monitor!(nodes[3], chan_id_persisted).get_stored_preimages().contains_key(&first_part_hash));
+	let not_persisted_preimages = get_monitor!(nodes[3], chan_id_not_persisted).get_stored_preimages();
+	assert!(!not_persisted_preimages.contains_key(&first_part_hash));
+	let second_part_preimage = *not_persisted_preimages.get(&second_part_hash).unwrap();
+
+	nodes[1].node.peer_disconnected(&nodes[3].node.get_our_node_id());
+	nodes[2].node.peer_disconnected(&nodes[3].node.get_our_node_id());
+
+	let events = nodes[3].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 3);
+	if let Event::PaymentClaimable { amount_msat, .. } = events[0] { assert_eq!(amount_msat, recv_value); } else { panic!(); }
+	if let Event::ChannelClosed { reason: ClosureReason::OutdatedChannelManager, .. } = events[1] { } else { panic!(); }
+	check_added_monitors(&nodes[3], 1);
+	// The claim is reported under the payment's set id rather than either part's payment hash.
+	match events[2] {
+		Event::PaymentClaimed { payment_hash: claimed_hash, amount_msat, .. } => {
+			assert_eq!(claimed_hash, payment_hash);
+			assert_eq!(amount_msat, recv_value);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	assert_eq!(nodes[3].node.list_channels().len(), 1);
+
+	// Once reconnected, the second part is claimed off-chain with its own preimage.
+	nodes[3].node.peer_connected(&nodes[2].node.get_our_node_id(), &msgs::Init {
+		features: nodes[2].node.init_features(), networks: None, remote_network_address: None
+	}, true).unwrap();
+	let reestablish_1 = get_chan_reestablish_msgs!(nodes[3], nodes[2]);
+	nodes[2].node.peer_connected(&nodes[3].node.get_our_node_id(), &msgs::Init {
+		features: nodes[3].node.init_features(), networks: None, remote_network_address: None
+	}, false).unwrap();
+	let reestablish_2 = get_chan_reestablish_msgs!(nodes[2], nodes[3]);
+
+	nodes[2].node.handle_channel_reestablish(&nodes[3].node.get_our_node_id(), &reestablish_1[0]);
+	get_event_msg!(nodes[2], MessageSendEvent::SendChannelUpdate, nodes[3].node.get_our_node_id());
+	assert!(nodes[2].node.get_and_clear_pending_msg_events().is_empty());
+
+	nodes[3].node.handle_channel_reestablish(&nodes[2].node.get_our_node_id(), &reestablish_2[0]);
+	let ds_msgs = nodes[3].node.get_and_clear_pending_msg_events();
+	check_added_monitors!(nodes[3], 1);
+	assert_eq!(ds_msgs.len(), 2);
+	if let MessageSendEvent::SendChannelUpdate { .. } = ds_msgs[0] {} else { panic!(); }
+
+	let cs_updates = match ds_msgs[1] {
+		MessageSendEvent::UpdateHTLCs { ref updates, .. } => {
+			assert_eq!(updates.update_fulfill_htlcs[0].payment_preimage, second_part_preimage);
+			nodes[2].node.handle_update_fulfill_htlc(&nodes[3].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+			check_added_monitors!(nodes[2], 1);
+			let cs_updates = get_htlc_update_msgs!(nodes[2], nodes[0].node.get_our_node_id());
+			expect_payment_forwarded!(nodes[2], nodes[0], nodes[3], Some(1000), false, false);
+			commitment_signed_dance!(nodes[2], nodes[3], updates.commitment_signed, false, true);
+			cs_updates
+		},
+		_ => panic!(),
+	};
+
+	nodes[0].node.handle_update_fulfill_htlc(&nodes[2].node.get_our_node_id(), &cs_updates.update_fulfill_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[2], cs_updates.commitment_signed, false, true);
+	expect_payment_sent!(nodes[0], root_seed);
+}
+
 #[test]
 fn test_reject_mpp_keysend_htlc() {
 	// This test enforces that we reject MPP keysend HTLCs if our config states we don't support
@@ -3690,7 +3883,9 @@ fn do_test_custom_tlvs(spontaneous: bool, even_tlvs: bool, known_tlvs: bool) {
 	let onion_fields = RecipientOnionFields {
 		payment_secret: if spontaneous { None } else { Some(our_payment_secret) },
 		payment_metadata: None,
-		custom_tlvs: custom_tlvs.clone()
+		custom_tlvs: custom_tlvs.clone(),
+		trampoline_packet: None,
+		amp_record: None,
 	};
 	if spontaneous {
 		nodes[0].node.send_spontaneous_payment(&route, Some(our_payment_preimage), onion_fields, payment_id).unwrap();
@@ -3886,7 +4081,9 @@ fn do_test_custom_tlvs_consistency(first_tlvs: Vec<(u64, Vec<u8>)>, second_tlvs:
 	let onion_fields = RecipientOnionFields {
 		payment_secret: Some(our_payment_secret),
 		payment_metadata: None,
-		custom_tlvs: first_tlvs
+		custom_tlvs: first_tlvs,
+		trampoline_packet: None,
+		amp_record: None,
 	};
 	let session_privs = nodes[0].node.test_add_new_pending_payment(our_payment_hash,
 			onion_fields.clone(), payment_id, &route).unwrap();
@@ -3908,7 +4105,9 @@ fn do_test_custom_tlvs_consistency(first_tlvs: Vec<(u64, Vec<u8>)>, second_tlvs:
 	let onion_fields = RecipientOnionFields {
 		payment_secret: Some(our_payment_secret),
 		payment_metadata: None,
-		custom_tlvs: second_tlvs
+		custom_tlvs: second_tlvs,
+		trampoline_packet: None,
+		amp_record: None,
 	};
 	nodes[0].node.test_send_payment_along_path(&route.paths[1], &our_payment_hash,
 		onion_fields.clone(), amt_msat, cur_height, payment_id, &None, session_privs[1]).unwrap();
@@ -4013,6 +4212,8 @@ fn do_test_payment_metadata_consistency(do_reload: bool, do_modify: bool) {
 	// Send the MPP payment, delivering the updated commitment state to nodes[1].
 	nodes[0].node.send_payment(payment_hash, RecipientOnionFields {
 			payment_secret: Some(payment_secret), payment_metadata: Some(payment_metadata), custom_tlvs: vec![],
+			trampoline_packet: None,
+			amp_record: None,
 		}, payment_id, route_params.clone(), Retry::Attempts(1)).unwrap();
 	check_added_monitors!(nodes[0], 2);
 
@@ -4250,6 +4451,8 @@ fn peel_payment_onion_custom_tlvs() {
 		skimmed_fee_msat: None,
 		onion_routing_packet,
 		blinding_point: None,
+		hold_htlc: None,
+		endorsed: None,
 	};
 	let peeled_onion = crate::ln::onion_payment::peel_payment_onion(
 		&update_add, &&chanmon_cfgs[1].keys_manager, &&chanmon_cfgs[1].logger, &secp_ctx,
@@ -4270,3 +4473,806 @@ fn peel_payment_onion_custom_tlvs() {
 		_ => panic!()
 	}
 }
+
+#[test]
+fn trampoline_payment_success() {
+	// Route a payment from nodes[0] to nodes[3] using nodes[1] as a trampoline. nodes[0] only
+	// picks a path to nodes[1], which then finds its own way to nodes[3] through nodes[2].
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let mut trampoline_config = test_default_channel_config();
+	trampoline_config.accept_trampoline_forwards = true;
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, Some(trampoline_config), None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+	create_announced_chan_between_nodes(&nodes, 2, 3);
+
+	let amt_msat = 100_000;
+	let trampoline_fee_msat = 5_000;
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[3]);
+	let payment_params = PaymentParameters::from_node_id(nodes[3].node.get_our_node_id(), TEST_FINAL_CLTV);
+	let route_params = RouteParameters::from_payment_params_and_value(payment_params, amt_msat);
+
+	// nodes[2] doesn't advertise trampoline support, so it can't be used as one.
+	let bad_trampoline_hop = TrampolineHop {
+		pubkey: nodes[2].node.get_our_node_id(), fee_msat: trampoline_fee_msat, cltv_expiry_delta: 144,
+	};
+	assert_eq!(nodes[0].node.send_payment_via_trampoline(payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0), route_params.clone(),
+		bad_trampoline_hop, Retry::Attempts(0)), Err(RetryableSendFailure::RouteNotFound));
+
+	let trampoline_hop = TrampolineHop {
+		pubkey: nodes[1].node.get_our_node_id(), fee_msat: trampoline_fee_msat, cltv_expiry_delta: 144,
+	};
+	nodes[0].node.send_payment_via_trampoline(payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0), route_params,
+		trampoline_hop, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let ev = events.remove(0);
+	assert_eq!(SendEvent::from_event(ev.clone()).msgs[0].amount_msat, amt_msat + trampoline_fee_msat);
+	pass_along_path(&nodes[0], &[&nodes[1], &nodes[2], &nodes[3]], amt_msat, payment_hash,
+		Some(payment_secret), ev, true, None);
+
+	nodes[3].node.claim_funds(payment_preimage);
+	check_added_monitors!(nodes[3], 1);
+	expect_payment_claimed!(nodes[3], payment_hash, amt_msat);
+
+	let updates = get_htlc_update_msgs!(nodes[3], nodes[2].node.get_our_node_id());
+	nodes[2].node.handle_update_fulfill_htlc(&nodes[3].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+	expect_payment_forwarded!(nodes[2], nodes[1], nodes[3], Some(1000), false, false);
+	check_added_monitors!(nodes[2], 1);
+	let cs_updates = get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+	commitment_signed_dance!(nodes[2], nodes[3], updates.commitment_signed, false);
+
+	// The trampoline node earns the difference between what it received and what it forwarded,
+	// and reports the fee it paid along the route it picked itself.
+	nodes[1].node.handle_update_fulfill_htlc(&nodes[2].node.get_our_node_id(), &cs_updates.update_fulfill_htlcs[0]);
+	let mut events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::PaymentForwarded { fee_earned_msat, trampoline_route_fee_msat, outbound_amount_forwarded_msat, .. } => {
+			assert_eq!(fee_earned_msat, Some(trampoline_fee_msat - 1000));
+			assert_eq!(trampoline_route_fee_msat, Some(1000));
+			assert_eq!(outbound_amount_forwarded_msat, Some(amt_msat + 1000));
+		},
+		_ => panic!("Unexpected event"),
+	}
+	expect_payment_forwarded(events.pop().unwrap(), &nodes[1], &nodes[0], &nodes[2],
+		Some(trampoline_fee_msat - 1000), false, false);
+	check_added_monitors!(nodes[1], 1);
+	let bs_updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	commitment_signed_dance!(nodes[1], nodes[2], cs_updates.commitment_signed, false);
+
+	nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &bs_updates.update_fulfill_htlcs[0]);
+	expect_payment_sent(&nodes[0], payment_preimage, None, false, false);
+	commitment_signed_dance!(nodes[0], nodes[1], bs_updates.commitment_signed, false);
+	expect_payment_path_successful!(nodes[0]);
+}
+
+#[test]
+fn trampoline_payment_failure_from_recipient() {
+	do_trampoline_payment_failure(true);
+}
+
+#[test]
+fn trampoline_payment_failure_along_route() {
+	do_trampoline_payment_failure(false);
+}
+
+fn do_trampoline_payment_failure(fail_at_recipient: bool) {
+	// Route a payment from nodes[0] to nodes[3] using nodes[1] as a trampoline, and fail it either
+	// at nodes[3] or at nodes[2] along the route nodes[1] found. The failure is encrypted to
+	// nodes[1], which decrypts it and fails back to nodes[0] with its own.
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let mut trampoline_config = test_default_channel_config();
+	trampoline_config.accept_trampoline_forwards = true;
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, Some(trampoline_config), None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+	let chan_2_3 = create_announced_chan_between_nodes(&nodes, 2, 3).2;
+
+	let amt_msat = 100_000;
+	let (_, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[3]);
+	let payment_params = PaymentParameters::from_node_id(nodes[3].node.get_our_node_id(), TEST_FINAL_CLTV);
+	let route_params = RouteParameters::from_payment_params_and_value(payment_params, amt_msat);
+	let trampoline_hop = TrampolineHop {
+		pubkey: nodes[1].node.get_our_node_id(), fee_msat: 5_000, cltv_expiry_delta: 144,
+	};
+	nodes[0].node.send_payment_via_trampoline(payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0), route_params,
+		trampoline_hop, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let ev = events.remove(0);
+	if fail_at_recipient {
+		pass_along_path(&nodes[0], &[&nodes[1], &nodes[2], &nodes[3]], amt_msat, payment_hash,
+			Some(payment_secret), ev, true, None);
+		nodes[3].node.fail_htlc_backwards(&payment_hash);
+		expect_pending_htlcs_forwardable_and_htlc_handling_failed!(
+			nodes[3], vec![HTLCDestination::FailedPayment { payment_hash }]
+		);
+		check_added_monitors!(nodes[3], 1);
+		let updates = get_htlc_update_msgs!(nodes[3], nodes[2].node.get_our_node_id());
+		nodes[2].node.handle_update_fail_htlc(&nodes[3].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+		commitment_signed_dance!(nodes[2], nodes[3], updates.commitment_signed, true);
+	} else {
+		pass_along_path(&nodes[0], &[&nodes[1]], amt_msat, payment_hash, Some(payment_secret), ev, false, None);
+		check_added_monitors!(nodes[1], 1);
+		let send_event = SendEvent::from_node(&nodes[1]);
+		nodes[2].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &send_event.msgs[0]);
+		commitment_signed_dance!(nodes[2], nodes[1], send_event.commitment_msg, false);
+
+		nodes[2].node.peer_disconnected(&nodes[3].node.get_our_node_id());
+		nodes[3].node.peer_disconnected(&nodes[2].node.get_our_node_id());
+		expect_pending_htlcs_forwardable!(nodes[2]);
+		expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[2],
+			vec![HTLCDestination::NextHopChannel { node_id: Some(nodes[3].node.get_our_node_id()), channel_id: chan_2_3 }]);
+		check_added_monitors!(nodes[2], 1);
+	}
+
+	let updates = get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_fail_htlc(&nodes[2].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[2], updates.commitment_signed, true);
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+
+	if fail_at_recipient {
+		// The recipient's failure is passed back as is.
+		let mut expected_failure_data = amt_msat.to_be_bytes().to_vec();
+		expected_failure_data.extend_from_slice(&nodes[3].best_block_info().1.to_be_bytes());
+		expect_payment_failed!(nodes[0], payment_hash, true, 0x4000 | 15, &expected_failure_data[..]);
+	} else {
+		// Failures along the route are reported as temporary_trampoline_failure.
+		expect_payment_failed!(nodes[0], payment_hash, false, 0x2000 | 25, [0; 0]);
+	}
+}
+
+#[test]
+fn settles_hold_invoice() {
+	// Create a hold invoice, restart, and only settle it once its HTLC has been received.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes_1_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let chan_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+	let amt_msat = 100_000;
+	let payment_preimage = PaymentPreimage([42; 32]);
+	let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).to_byte_array());
+	let cancel_buffer_blocks = UserConfig::default().hold_invoice_cancel_buffer_blocks;
+	let min_final_cltv_expiry_delta = MIN_FINAL_CLTV_EXPIRY_DELTA + cancel_buffer_blocks;
+
+	// The payment must be receivable for longer than we'd hold it.
+	assert!(nodes[1].node.create_inbound_hold_payment(
+		payment_hash, Some(amt_msat), 3600, min_final_cltv_expiry_delta - 1
+	).is_err());
+	let payment_secret = nodes[1].node.create_inbound_hold_payment(
+		payment_hash, Some(amt_msat), 3600, min_final_cltv_expiry_delta
+	).unwrap();
+	assert!(nodes[1].node.create_inbound_hold_payment(
+		payment_hash, Some(amt_msat), 3600, min_final_cltv_expiry_delta
+	).is_err());
+	assert_eq!(nodes[1].node.hold_invoice_state(&payment_hash), Some(HoldInvoiceState::Open));
+
+	let chan_manager_serialized = nodes[1].node.encode();
+	let chan_0_monitor_serialized = get_monitor!(nodes[1], chan_id).encode();
+	reload_node!(nodes[1], &chan_manager_serialized, &[&chan_0_monitor_serialized], persister, new_chain_monitor, nodes_1_deserialized);
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	reconnect_nodes(ReconnectArgs::new(&nodes[0], &nodes[1]));
+	assert_eq!(nodes[1].node.hold_invoice_state(&payment_hash), Some(HoldInvoiceState::Open));
+
+	// Nothing can be settled until the payment arrives.
+	assert!(nodes[1].node.settle_hold_invoice(payment_preimage).is_err());
+
+	let (route, ..) = get_route_and_payment_hash!(nodes[0], nodes[1], amt_msat);
+	let cltv_expiry = nodes[0].best_block_info().1 + TEST_FINAL_CLTV + 1;
+	send_along_route_with_secret(&nodes[0], route, &[&[&nodes[1]]], amt_msat, payment_hash, payment_secret);
+	let cancel_height = cltv_expiry - HTLC_FAIL_BACK_BUFFER - cancel_buffer_blocks as u32;
+	assert_eq!(
+		nodes[1].node.hold_invoice_state(&payment_hash),
+		Some(HoldInvoiceState::Accepted { amount_msat: amt_msat, cancel_height })
+	);
+
+	// Only the preimage matching the invoice's payment hash settles it.
+	assert!(nodes[1].node.settle_hold_invoice(PaymentPreimage([43; 32])).is_err());
+	nodes[1].node.settle_hold_invoice(payment_preimage).unwrap();
+	assert_eq!(nodes[1].node.hold_invoice_state(&payment_hash), Some(HoldInvoiceState::Settled));
+	assert!(nodes[1].node.cancel_hold_invoice(&payment_hash).is_err());
+
+	pass_claimed_payment_along_route(&nodes[0], &[&[&nodes[1]]], &[0], false, payment_preimage);
+	expect_payment_sent!(nodes[0], payment_preimage);
+}
+
+#[test]
+fn cancels_hold_invoice() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let amt_msat = 100_000;
+	let payment_preimage = PaymentPreimage([42; 32]);
+	let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).to_byte_array());
+	assert!(nodes[1].node.cancel_hold_invoice(&payment_hash).is_err());
+
+	let min_final_cltv_expiry_delta = MIN_FINAL_CLTV_EXPIRY_DELTA
+		+ UserConfig::default().hold_invoice_cancel_buffer_blocks;
+	let payment_secret = nodes[1].node.create_inbound_hold_payment(
+		payment_hash, Some(amt_msat), 3600, min_final_cltv_expiry_delta
+	).unwrap();
+	let (route, ..) = get_route_and_payment_hash!(nodes[0], nodes[1], amt_msat);
+	send_along_route_with_secret(&nodes[0], route, &[&[&nodes[1]]], amt_msat, payment_hash, payment_secret);
+
+	nodes[1].node.cancel_hold_invoice(&payment_hash).unwrap();
+	assert_eq!(nodes[1].node.hold_invoice_state(&payment_hash), Some(HoldInvoiceState::Cancelled));
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(
+		nodes[1], vec![HTLCDestination::FailedPayment { payment_hash }]
+	);
+	pass_failed_payment_back(&nodes[0], &[&[&nodes[1]]], false, payment_hash, PaymentFailureReason::RecipientRejected);
+
+	// Cancelling is idempotent, but a cancelled invoice can't be settled.
+	nodes[1].node.cancel_hold_invoice(&payment_hash).unwrap();
+	assert!(nodes[1].node.settle_hold_invoice(payment_preimage).is_err());
+	assert_eq!(nodes[1].node.hold_invoice_state(&payment_hash), Some(HoldInvoiceState::Cancelled));
+}
+
+#[test]
+fn cancel_hold_invoice_races_htlc_receipt() {
+	// Cancel a hold invoice while its HTLC is being received on another thread. Whichever happens
+	// first, the HTLC must be failed back rather than held until it times out.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let amt_msat = 100_000;
+	let min_final_cltv_expiry_delta = MIN_FINAL_CLTV_EXPIRY_DELTA
+		+ UserConfig::default().hold_invoice_cancel_buffer_blocks;
+	for i in 0..10 {
+		let payment_preimage = PaymentPreimage([i; 32]);
+		let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).to_byte_array());
+		let payment_secret = nodes[1].node.create_inbound_hold_payment(
+			payment_hash, Some(amt_msat), 3600, min_final_cltv_expiry_delta
+		).unwrap();
+		let (route, ..) = get_route_and_payment_hash!(nodes[0], nodes[1], amt_msat);
+		nodes[0].node.send_payment_with_route(&route, payment_hash,
+			RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+		check_added_monitors!(nodes[0], 1);
+		let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+		assert_eq!(events.len(), 1);
+		let send_event = SendEvent::from_event(events.remove(0));
+		nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &send_event.msgs[0]);
+		commitment_signed_dance!(nodes[1], nodes[0], send_event.commitment_msg, false);
+		expect_pending_htlcs_forwardable_ignore!(nodes[1]);
+
+		// We really want std::thread::scope, but its not stable until 1.63. Until then, we get unsafe.
+		let node_ref = NodePtr::from_node(&nodes[1]);
+		let cancel_thread = std::thread::spawn(move || {
+			let _ = &node_ref;
+			let node_b = unsafe { &*node_ref.0 };
+			node_b.node.cancel_hold_invoice(&payment_hash).unwrap();
+		});
+		nodes[1].node.process_pending_htlc_forwards();
+		cancel_thread.join().unwrap();
+
+		assert_eq!(nodes[1].node.hold_invoice_state(&payment_hash), Some(HoldInvoiceState::Cancelled));
+		let events = nodes[1].node.get_and_clear_pending_events();
+		assert!(events.iter().any(|event| matches!(event, Event::HTLCHandlingFailed {
+			failed_next_destination: HTLCDestination::FailedPayment { .. }, ..
+		})), "Unexpected events {:?}", events);
+		nodes[1].node.process_pending_htlc_forwards();
+		pass_failed_payment_back(&nodes[0], &[&[&nodes[1]]], false, payment_hash, PaymentFailureReason::RecipientRejected);
+	}
+}
+
+#[test]
+fn auto_cancels_hold_invoice_before_claim_deadline() {
+	// A held payment is failed back `hold_invoice_cancel_buffer_blocks` before its claim deadline,
+	// rather than at the claim deadline itself as for other claimable payments.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let amt_msat = 100_000;
+	let payment_preimage = PaymentPreimage([42; 32]);
+	let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).to_byte_array());
+	let cancel_buffer_blocks = UserConfig::default().hold_invoice_cancel_buffer_blocks;
+	let payment_secret = nodes[1].node.create_inbound_hold_payment(
+		payment_hash, Some(amt_msat), 3600, MIN_FINAL_CLTV_EXPIRY_DELTA + cancel_buffer_blocks
+	).unwrap();
+	let (route, ..) = get_route_and_payment_hash!(nodes[0], nodes[1], amt_msat);
+	send_along_route_with_secret(&nodes[0], route, &[&[&nodes[1]]], amt_msat, payment_hash, payment_secret);
+
+	let cancel_height = match nodes[1].node.hold_invoice_state(&payment_hash) {
+		Some(HoldInvoiceState::Accepted { cancel_height, .. }) => cancel_height,
+		_ => panic!("Unexpected hold invoice state"),
+	};
+	connect_blocks(&nodes[1], cancel_height - nodes[1].best_block_info().1 - 1);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	assert!(matches!(nodes[1].node.hold_invoice_state(&payment_hash), Some(HoldInvoiceState::Accepted { .. })));
+
+	connect_blocks(&nodes[1], 1);
+	assert_eq!(nodes[1].node.hold_invoice_state(&payment_hash), Some(HoldInvoiceState::Cancelled));
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(
+		nodes[1], vec![HTLCDestination::FailedPayment { payment_hash }]
+	);
+	pass_failed_payment_back(&nodes[0], &[&[&nodes[1]]], false, payment_hash, PaymentFailureReason::RecipientRejected);
+	assert!(nodes[1].node.settle_hold_invoice(payment_preimage).is_err());
+}
+
+#[test]
+fn htlc_endorsement_limits_unendorsed_forwards() {
+	// Test that a forwarding node with HTLC endorsement enabled only lets HTLCs which weren't
+	// endorsed by a peer with good reputation use the general share of the outgoing channel's
+	// liquidity, and forwards them unendorsed.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut forwarding_config = test_default_channel_config();
+	forwarding_config.htlc_endorsement_config.enabled = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(forwarding_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
+	let chan_id_2 = create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 100_000, 0).2;
+
+	// nodes[2] allows 10_000_000 msat in flight on the outgoing channel, half of which is reserved
+	// for endorsed HTLCs.
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 6_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	assert_eq!(updates.update_add_htlcs[0].endorsed, None);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &updates.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &updates.commitment_signed, false);
+
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(&nodes[1], vec![HTLCDestination::NextHopChannel {
+		node_id: Some(nodes[2].node.get_our_node_id()),
+		channel_id: chan_id_2
+	}]);
+	check_added_monitors(&nodes[1], 1);
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], &updates.commitment_signed, false);
+	expect_payment_failed!(nodes[0], payment_hash, false);
+
+	// A smaller HTLC fits in the general share and is forwarded unendorsed.
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 4_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &updates.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &updates.commitment_signed, false);
+
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	check_added_monitors(&nodes[1], 1);
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[2].node.get_our_node_id());
+	assert_eq!(updates.update_add_htlcs[0].endorsed, Some(false));
+	nodes[2].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &updates.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[2], nodes[1], &updates.commitment_signed, false);
+
+	expect_pending_htlcs_forwardable!(nodes[2]);
+	expect_payment_claimable!(nodes[2], payment_hash, payment_secret, 4_000_000);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+}
+
+#[test]
+fn peer_forwarding_limits() {
+	// Test that HTLCs from a peer which already has as many HTLCs pending forward as allowed by
+	// `UserConfig::peer_forwarding_limits` are failed back, generating an event, and that the
+	// limit is released once the pending HTLCs resolve.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut forwarding_config = test_default_channel_config();
+	forwarding_config.peer_forwarding_limits.max_inflight_htlcs = Some(1);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(forwarding_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let chan_id_2 = create_announced_chan_between_nodes(&nodes, 1, 2).2;
+
+	let (payment_preimage, ..) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 1_000_000);
+
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 1_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &updates.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &updates.commitment_signed, false);
+	expect_pending_htlcs_forwardable!(nodes[1]);
+
+	let mut events = nodes[1].node.get_and_clear_pending_events();
+	match events.remove(0) {
+		Event::PeerForwardingLimitExceeded { counterparty_node_id, prev_channel_id, inbound_amount_msat, limit } => {
+			assert_eq!(counterparty_node_id, nodes[0].node.get_our_node_id());
+			assert_eq!(prev_channel_id, chan_id_1);
+			assert_eq!(inbound_amount_msat, 1_000_000 + 1_000);
+			assert_eq!(limit, PeerForwardingLimit::InFlightHTLCs);
+		},
+		e => panic!("Unexpected event {:?}", e),
+	}
+	expect_pending_htlcs_forwardable_conditions(events, &[HTLCDestination::NextHopChannel {
+		node_id: Some(nodes[2].node.get_our_node_id()),
+		channel_id: chan_id_2
+	}]);
+	nodes[1].node.process_pending_htlc_forwards();
+	check_added_monitors(&nodes[1], 1);
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], &updates.commitment_signed, false);
+	expect_payment_failed!(nodes[0], payment_hash, false);
+
+	// Once the first HTLC is claimed, nodes[0] may have another one forwarded.
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+	send_payment(&nodes[0], &[&nodes[1], &nodes[2]], 1_000_000);
+}
+
+#[test]
+fn peer_forwarding_value_limit() {
+	// Test that an HTLC which would take the value we're forwarding on behalf of a peer over
+	// `PeerForwardingLimits::max_inflight_value_msat` is failed back, while a smaller one fits.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut forwarding_config = test_default_channel_config();
+	forwarding_config.peer_forwarding_limits.max_inflight_value_msat = Some(1_500_000);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(forwarding_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let chan_id_2 = create_announced_chan_between_nodes(&nodes, 1, 2).2;
+
+	let (payment_preimage_1, ..) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 1_000_000);
+
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 1_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &updates.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &updates.commitment_signed, false);
+	expect_pending_htlcs_forwardable!(nodes[1]);
+
+	let mut events = nodes[1].node.get_and_clear_pending_events();
+	match events.remove(0) {
+		Event::PeerForwardingLimitExceeded { counterparty_node_id, prev_channel_id, inbound_amount_msat, limit } => {
+			assert_eq!(counterparty_node_id, nodes[0].node.get_our_node_id());
+			assert_eq!(prev_channel_id, chan_id_1);
+			assert_eq!(inbound_amount_msat, 1_000_000 + 1_000);
+			assert_eq!(limit, PeerForwardingLimit::InFlightValue);
+		},
+		e => panic!("Unexpected event {:?}", e),
+	}
+	expect_pending_htlcs_forwardable_conditions(events, &[HTLCDestination::NextHopChannel {
+		node_id: Some(nodes[2].node.get_our_node_id()),
+		channel_id: chan_id_2
+	}]);
+	nodes[1].node.process_pending_htlc_forwards();
+	check_added_monitors(&nodes[1], 1);
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], &updates.commitment_signed, false);
+	expect_payment_failed!(nodes[0], payment_hash, false);
+
+	// An HTLC which keeps the value in flight within the limit is forwarded.
+	let (payment_preimage_2, ..) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 500_000);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage_1);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage_2);
+}
+
+#[test]
+fn peer_forwarding_rate_limit() {
+	// Test that HTLCs from a peer beyond `PeerForwardingLimits::max_htlcs_per_second` are failed
+	// back, even though they would be within its other limits.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut forwarding_config = test_default_channel_config();
+	forwarding_config.peer_forwarding_limits.max_htlcs_per_second = Some(1);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(forwarding_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let chan_id_2 = create_announced_chan_between_nodes(&nodes, 1, 2).2;
+
+	// Both HTLCs are forwarded in the same call to `process_pending_htlc_forwards`, and thus in
+	// the same second.
+	let (route_1, payment_hash_1, payment_preimage_1, payment_secret_1) = get_route_and_payment_hash!(nodes[0], nodes[2], 1_000_000);
+	let (route_2, payment_hash_2, _, payment_secret_2) = get_route_and_payment_hash!(nodes[0], nodes[2], 1_000_000);
+	for (route, payment_hash, payment_secret) in [(route_1, payment_hash_1, payment_secret_1), (route_2, payment_hash_2, payment_secret_2)] {
+		nodes[0].node.send_payment_with_route(&route, payment_hash,
+			RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+		check_added_monitors!(nodes[0], 1);
+		let updates = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+		nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &updates.update_add_htlcs[0]);
+		commitment_signed_dance!(nodes[1], nodes[0], &updates.commitment_signed, false);
+	}
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert!(!events.is_empty());
+	assert!(events.iter().all(|event| matches!(event, Event::PendingHTLCsForwardable { .. })));
+	nodes[1].node.process_pending_htlc_forwards();
+
+	let mut events = nodes[1].node.get_and_clear_pending_events();
+	match events.remove(0) {
+		Event::PeerForwardingLimitExceeded { counterparty_node_id, prev_channel_id, inbound_amount_msat, limit } => {
+			assert_eq!(counterparty_node_id, nodes[0].node.get_our_node_id());
+			assert_eq!(prev_channel_id, chan_id_1);
+			assert_eq!(inbound_amount_msat, 1_000_000 + 1_000);
+			assert_eq!(limit, PeerForwardingLimit::HTLCRate);
+		},
+		e => panic!("Unexpected event {:?}", e),
+	}
+	expect_pending_htlcs_forwardable_conditions(events, &[HTLCDestination::NextHopChannel {
+		node_id: Some(nodes[2].node.get_our_node_id()),
+		channel_id: chan_id_2
+	}]);
+
+	// The first HTLC is forwarded while the second is failed back.
+	check_added_monitors(&nodes[1], 1);
+	let mut events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	pass_along_path(&nodes[1], &[&nodes[2]], 1_000_000, payment_hash_1, Some(payment_secret_1), events.remove(0), true, None);
+
+	nodes[1].node.process_pending_htlc_forwards();
+	check_added_monitors(&nodes[1], 1);
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], &updates.commitment_signed, false);
+	expect_payment_failed!(nodes[0], payment_hash_2, false);
+
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage_1);
+}
+
+#[test]
+fn rebalance_via_circular_route() {
+	// Test that `ChannelManager::rebalance` moves liquidity between two of our channels by paying
+	// ourselves in a circle, claiming the payment automatically and generating a
+	// `RebalanceSucceeded` event instead of the usual payment events.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+	let chan_id_3 = create_announced_chan_between_nodes(&nodes, 2, 0).2;
+
+	let balance_msat = |channel_id| nodes[0].node.list_channels().into_iter()
+		.find(|details| details.channel_id == channel_id).unwrap().balance_msat;
+	let outbound_balance_msat = balance_msat(chan_id_1);
+	let inbound_balance_msat = balance_msat(chan_id_3);
+
+	// The fees charged by nodes[1] and nodes[2] exceed our maximum fee.
+	assert_eq!(nodes[0].node.rebalance(chan_id_1, chan_id_3, 1_000_000, 1_999, Retry::Attempts(0)),
+		Err(RetryableSendFailure::RouteNotFound));
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	let payment_id = nodes[0].node.rebalance(chan_id_1, chan_id_3, 1_000_000, 2_000, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let ev = events.pop().unwrap();
+	let payment_hash = SendEvent::from_event(ev.clone()).msgs[0].payment_hash;
+	do_pass_along_path(&nodes[0], &[&nodes[1], &nodes[2], &nodes[0]], 1_000_000, payment_hash, None,
+		ev, false, true, None, false);
+
+	// nodes[0] claimed the payment as it arrived, without generating a `PaymentClaimable`.
+	check_added_monitors!(nodes[0], 1);
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[2].node.get_our_node_id());
+	nodes[2].node.handle_update_fulfill_htlc(&nodes[0].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+	expect_payment_forwarded!(nodes[2], nodes[1], nodes[0], Some(1000), false, false);
+	check_added_monitors!(nodes[2], 1);
+	let updates_2 = get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+	commitment_signed_dance!(nodes[2], nodes[0], &updates.commitment_signed, false);
+
+	nodes[1].node.handle_update_fulfill_htlc(&nodes[2].node.get_our_node_id(), &updates_2.update_fulfill_htlcs[0]);
+	expect_payment_forwarded!(nodes[1], nodes[0], nodes[2], Some(1000), false, false);
+	check_added_monitors!(nodes[1], 1);
+	let updates_1 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	commitment_signed_dance!(nodes[1], nodes[2], &updates_2.commitment_signed, false);
+
+	nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &updates_1.update_fulfill_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], &updates_1.commitment_signed, false);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::RebalanceSucceeded {
+			payment_id: id, outbound_channel_id, inbound_channel_id, amount_msat, fee_paid_msat
+		} => {
+			assert_eq!(id, payment_id);
+			assert_eq!(outbound_channel_id, chan_id_1);
+			assert_eq!(inbound_channel_id, chan_id_3);
+			assert_eq!(amount_msat, 1_000_000);
+			assert_eq!(fee_paid_msat, Some(2_000));
+		},
+		ref e => panic!("Unexpected event {:?}", e),
+	}
+	match events[1] {
+		Event::PaymentPathSuccessful { payment_id: id, .. } => assert_eq!(id, payment_id),
+		ref e => panic!("Unexpected event {:?}", e),
+	}
+
+	assert_eq!(balance_msat(chan_id_1), outbound_balance_msat - 1_002_000);
+	assert_eq!(balance_msat(chan_id_3), inbound_balance_msat + 1_000_000);
+}
+
+#[test]
+fn rebalance_retries_over_alternative_route() {
+	// Test that a rebalance whose first circular route fails is retried over an alternative route
+	// to the counterparty of the inbound channel, avoiding the failed channel.
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	// nodes[1] lacks the liquidity to forward over the cheapest route via this channel.
+	let chan_id_2 = create_announced_chan_between_nodes(&nodes, 2, 1).2;
+	create_announced_chan_between_nodes(&nodes, 1, 3);
+	create_announced_chan_between_nodes(&nodes, 3, 2);
+	let chan_id_3 = create_announced_chan_between_nodes(&nodes, 2, 0).2;
+
+	let balance_msat = |channel_id| nodes[0].node.list_channels().into_iter()
+		.find(|details| details.channel_id == channel_id).unwrap().balance_msat;
+	let outbound_balance_msat = balance_msat(chan_id_1);
+	let inbound_balance_msat = balance_msat(chan_id_3);
+
+	let payment_id = nodes[0].node.rebalance(chan_id_1, chan_id_3, 1_000_000, 3_000, Retry::Attempts(1)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let update_0 = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	let payment_hash = update_0.update_add_htlcs[0].payment_hash;
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &update_0.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &update_0.commitment_signed, false, true);
+	expect_pending_htlcs_forwardable_ignore!(nodes[1]);
+	nodes[1].node.process_pending_htlc_forwards();
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed_ignore!(nodes[1],
+		vec![HTLCDestination::NextHopChannel {
+			node_id: Some(nodes[2].node.get_our_node_id()), channel_id: chan_id_2,
+		}]);
+	nodes[1].node.process_pending_htlc_forwards();
+	check_added_monitors!(nodes[1], 1);
+	let update_1 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &update_1.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], update_1.commitment_signed, false);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::PaymentPathFailed { payment_id: Some(id), payment_failed_permanently, .. } => {
+			assert_eq!(id, payment_id);
+			assert!(!payment_failed_permanently);
+		},
+		ref e => panic!("Unexpected event {:?}", e),
+	}
+	match events[1] {
+		Event::PendingHTLCsForwardable { .. } => {},
+		ref e => panic!("Unexpected event {:?}", e),
+	}
+
+	// The retry goes around nodes[1]'s channel with nodes[2] via nodes[3].
+	nodes[0].node.process_pending_htlc_forwards();
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let ev = events.pop().unwrap();
+	assert_eq!(SendEvent::from_event(ev.clone()).msgs[0].payment_hash, payment_hash);
+	do_pass_along_path(&nodes[0], &[&nodes[1], &nodes[3], &nodes[2], &nodes[0]], 1_000_000,
+		payment_hash, None, ev, false, true, None, false);
+
+	check_added_monitors!(nodes[0], 1);
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[2].node.get_our_node_id());
+	nodes[2].node.handle_update_fulfill_htlc(&nodes[0].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+	expect_payment_forwarded!(nodes[2], nodes[3], nodes[0], Some(1000), false, false);
+	check_added_monitors!(nodes[2], 1);
+	let updates_2 = get_htlc_update_msgs!(nodes[2], nodes[3].node.get_our_node_id());
+	commitment_signed_dance!(nodes[2], nodes[0], &updates.commitment_signed, false);
+
+	nodes[3].node.handle_update_fulfill_htlc(&nodes[2].node.get_our_node_id(), &updates_2.update_fulfill_htlcs[0]);
+	expect_payment_forwarded!(nodes[3], nodes[1], nodes[2], Some(1000), false, false);
+	check_added_monitors!(nodes[3], 1);
+	let updates_3 = get_htlc_update_msgs!(nodes[3], nodes[1].node.get_our_node_id());
+	commitment_signed_dance!(nodes[3], nodes[2], &updates_2.commitment_signed, false);
+
+	nodes[1].node.handle_update_fulfill_htlc(&nodes[3].node.get_our_node_id(), &updates_3.update_fulfill_htlcs[0]);
+	expect_payment_forwarded!(nodes[1], nodes[0], nodes[3], Some(1000), false, false);
+	check_added_monitors!(nodes[1], 1);
+	let updates_1 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	commitment_signed_dance!(nodes[1], nodes[3], &updates_3.commitment_signed, false);
+
+	nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &updates_1.update_fulfill_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], &updates_1.commitment_signed, false);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::RebalanceSucceeded { payment_id: id, amount_msat, fee_paid_msat, .. } => {
+			assert_eq!(id, payment_id);
+			assert_eq!(amount_msat, 1_000_000);
+			assert_eq!(fee_paid_msat, Some(3_000));
+		},
+		ref e => panic!("Unexpected event {:?}", e),
+	}
+	match events[1] {
+		Event::PaymentPathSuccessful { payment_id: id, .. } => assert_eq!(id, payment_id),
+		ref e => panic!("Unexpected event {:?}", e),
+	}
+
+	assert_eq!(balance_msat(chan_id_1), outbound_balance_msat - 1_003_000);
+	assert_eq!(balance_msat(chan_id_3), inbound_balance_msat + 1_000_000);
+}
+
+#[test]
+fn rebalance_fails_once_retries_exhausted() {
+	// Test that a rebalance whose only attempt fails generates a `RebalanceFailed` event in place of
+	// `PaymentFailed`, leaving the channel balances unchanged.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	// nodes[1] lacks the liquidity to forward the rebalance to nodes[2].
+	let chan_id_2 = create_announced_chan_between_nodes(&nodes, 2, 1).2;
+	let chan_id_3 = create_announced_chan_between_nodes(&nodes, 2, 0).2;
+
+	let balance_msat = |channel_id| nodes[0].node.list_channels().into_iter()
+		.find(|details| details.channel_id == channel_id).unwrap().balance_msat;
+	let outbound_balance_msat = balance_msat(chan_id_1);
+	let inbound_balance_msat = balance_msat(chan_id_3);
+
+	let payment_id = nodes[0].node.rebalance(chan_id_1, chan_id_3, 1_000_000, 2_000, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let update_0 = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &update_0.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &update_0.commitment_signed, false, true);
+	expect_pending_htlcs_forwardable_ignore!(nodes[1]);
+	nodes[1].node.process_pending_htlc_forwards();
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed_ignore!(nodes[1],
+		vec![HTLCDestination::NextHopChannel {
+			node_id: Some(nodes[2].node.get_our_node_id()), channel_id: chan_id_2,
+		}]);
+	nodes[1].node.process_pending_htlc_forwards();
+	check_added_monitors!(nodes[1], 1);
+	let update_1 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &update_1.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], update_1.commitment_signed, false);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::PaymentPathFailed { payment_id: Some(id), payment_failed_permanently, .. } => {
+			assert_eq!(id, payment_id);
+			assert!(!payment_failed_permanently);
+		},
+		ref e => panic!("Unexpected event {:?}", e),
+	}
+	match events[1] {
+		Event::RebalanceFailed {
+			payment_id: id, outbound_channel_id, inbound_channel_id, amount_msat, reason
+		} => {
+			assert_eq!(id, payment_id);
+			assert_eq!(outbound_channel_id, chan_id_1);
+			assert_eq!(inbound_channel_id, chan_id_3);
+			assert_eq!(amount_msat, 1_000_000);
+			assert_eq!(reason, Some(PaymentFailureReason::RetriesExhausted));
+		},
+		ref e => panic!("Unexpected event {:?}", e),
+	}
+
+	assert_eq!(balance_msat(chan_id_1), outbound_balance_msat);
+	assert_eq!(balance_msat(chan_id_3), inbound_balance_msat);
+}
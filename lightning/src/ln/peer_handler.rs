@@ -32,7 +32,8 @@ use crate::ln::wire;
 use crate::ln::wire::{Encode, Type};
 #[cfg(not(c_bindings))]
 use crate::onion_message::{SimpleArcOnionMessenger, SimpleRefOnionMessenger};
-use crate::onion_message::{CustomOnionMessageHandler, OffersMessage, OffersMessageHandler, OnionMessageContents, PendingOnionMessage};
+use crate::blinded_path::BlindedPath;
+use crate::onion_message::{AsyncPaymentsMessageHandler, CustomOnionMessageHandler, DNSResolverMessage, DNSResolverMessageHandler, DNSSECProof, DNSSECQuery, HeldHtlcAvailable, OffersMessage, OffersMessageHandler, OnionMessageContents, PendingOnionMessage, ReleaseHeldHtlc};
 use crate::routing::gossip::{NodeId, NodeAlias};
 use crate::util::atomic_counter::AtomicCounter;
 use crate::util::logger::{Logger, WithContext};
@@ -134,6 +135,16 @@ impl OnionMessageHandler for IgnoringMessageHandler {
 impl OffersMessageHandler for IgnoringMessageHandler {
 	fn handle_message(&self, _msg: OffersMessage) -> Option<OffersMessage> { None }
 }
+impl AsyncPaymentsMessageHandler for IgnoringMessageHandler {
+	fn held_htlc_available(
+		&self, _message: HeldHtlcAvailable, _path_id: Option<[u8; 32]>, _reply_path: Option<BlindedPath>
+	) {}
+	fn release_held_htlc(&self, _message: ReleaseHeldHtlc) {}
+}
+impl DNSResolverMessageHandler for IgnoringMessageHandler {
+	fn handle_dnssec_query(&self, _message: DNSSECQuery) -> Option<DNSResolverMessage> { None }
+	fn handle_dnssec_proof(&self, _message: DNSSECProof, _path_id: Option<[u8; 32]>) {}
+}
 impl CustomOnionMessageHandler for IgnoringMessageHandler {
 	type CustomMessage = Infallible;
 	fn handle_custom_message(&self, _msg: Infallible) -> Option<Infallible> {
@@ -245,6 +256,15 @@ impl ChannelMessageHandler for ErroringMessageHandler {
 	fn handle_stfu(&self, their_node_id: &PublicKey, msg: &msgs::Stfu) {
 		ErroringMessageHandler::push_error(&self, their_node_id, msg.channel_id);
 	}
+	fn handle_dyn_propose(&self, their_node_id: &PublicKey, msg: &msgs::DynPropose) {
+		ErroringMessageHandler::push_error(&self, their_node_id, msg.channel_id);
+	}
+	fn handle_dyn_ack(&self, their_node_id: &PublicKey, msg: &msgs::DynAck) {
+		ErroringMessageHandler::push_error(&self, their_node_id, msg.channel_id);
+	}
+	fn handle_dyn_reject(&self, their_node_id: &PublicKey, msg: &msgs::DynReject) {
+		ErroringMessageHandler::push_error(&self, their_node_id, msg.channel_id);
+	}
 	fn handle_splice(&self, their_node_id: &PublicKey, msg: &msgs::Splice) {
 		ErroringMessageHandler::push_error(&self, their_node_id, msg.channel_id);
 	}
@@ -283,6 +303,10 @@ impl ChannelMessageHandler for ErroringMessageHandler {
 	}
 	// msgs::ChannelUpdate does not contain the channel_id field, so we just drop them.
 	fn handle_channel_update(&self, _their_node_id: &PublicKey, _msg: &msgs::ChannelUpdate) {}
+	// Peer storage messages are not related to a specific channel, and as we don't advertise
+	// `option_provide_storage` we just drop them.
+	fn handle_peer_storage(&self, _their_node_id: &PublicKey, _msg: &msgs::PeerStorage) {}
+	fn handle_your_peer_storage(&self, _their_node_id: &PublicKey, _msg: &msgs::YourPeerStorage) {}
 	fn peer_disconnected(&self, _their_node_id: &PublicKey) {}
 	fn peer_connected(&self, _their_node_id: &PublicKey, _init: &msgs::Init, _inbound: bool) -> Result<(), ()> { Ok(()) }
 	fn handle_error(&self, _their_node_id: &PublicKey, _msg: &msgs::ErrorMessage) {}
@@ -303,6 +327,7 @@ impl ChannelMessageHandler for ErroringMessageHandler {
 		features.set_channel_type_optional();
 		features.set_scid_privacy_optional();
 		features.set_zero_conf_optional();
+		features.set_quiescence_optional();
 		features
 	}
 
@@ -1662,6 +1687,14 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				peer_lock.msgs_sent_since_pong = 0;
 			},
 
+			// Peer storage messages:
+			wire::Message::PeerStorage(msg) => {
+				self.message_handler.chan_handler.handle_peer_storage(&their_node_id, &msg);
+			},
+			wire::Message::YourPeerStorage(msg) => {
+				self.message_handler.chan_handler.handle_your_peer_storage(&their_node_id, &msg);
+			},
+
 			// Channel messages:
 			wire::Message::OpenChannel(msg) => {
 				self.message_handler.chan_handler.handle_open_channel(&their_node_id, &msg);
@@ -1691,6 +1724,17 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				self.message_handler.chan_handler.handle_stfu(&their_node_id, &msg);
 			}
 
+			// Dynamic commitment messages:
+			wire::Message::DynPropose(msg) => {
+				self.message_handler.chan_handler.handle_dyn_propose(&their_node_id, &msg);
+			}
+			wire::Message::DynAck(msg) => {
+				self.message_handler.chan_handler.handle_dyn_ack(&their_node_id, &msg);
+			}
+			wire::Message::DynReject(msg) => {
+				self.message_handler.chan_handler.handle_dyn_reject(&their_node_id, &msg);
+			}
+
 			// Splicing messages:
 			wire::Message::Splice(msg) => {
 				self.message_handler.chan_handler.handle_splice(&their_node_id, &msg);
@@ -2029,6 +2073,27 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 									&msg.channel_id);
 							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
 						}
+						MessageSendEvent::SendDynPropose { ref node_id, ref msg} => {
+							let logger = WithContext::from(&self.logger, Some(*node_id), Some(msg.channel_id));
+							log_debug!(logger, "Handling SendDynPropose event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									&msg.channel_id);
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						}
+						MessageSendEvent::SendDynAck { ref node_id, ref msg} => {
+							let logger = WithContext::from(&self.logger, Some(*node_id), Some(msg.channel_id));
+							log_debug!(logger, "Handling SendDynAck event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									&msg.channel_id);
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						}
+						MessageSendEvent::SendDynReject { ref node_id, ref msg} => {
+							let logger = WithContext::from(&self.logger, Some(*node_id), Some(msg.channel_id));
+							log_debug!(logger, "Handling SendDynReject event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									&msg.channel_id);
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						}
 						MessageSendEvent::SendSplice { ref node_id, ref msg} => {
 							let logger = WithContext::from(&self.logger, Some(*node_id), Some(msg.channel_id));
 							log_debug!(logger, "Handling SendSplice event in peer_handler for node {} for channel {}",
@@ -2159,6 +2224,18 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 									&msg.channel_id);
 							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
 						},
+						MessageSendEvent::SendPeerStorage { ref node_id, ref msg } => {
+							log_debug!(WithContext::from(&self.logger, Some(*node_id), None), "Handling SendPeerStorage event in peer_handler for node {} with {} bytes",
+									log_pubkey!(node_id),
+									msg.data.len());
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
+						MessageSendEvent::SendYourPeerStorage { ref node_id, ref msg } => {
+							log_debug!(WithContext::from(&self.logger, Some(*node_id), None), "Handling SendYourPeerStorage event in peer_handler for node {} with {} bytes",
+									log_pubkey!(node_id),
+									msg.data.len());
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
 						MessageSendEvent::SendChannelAnnouncement { ref node_id, ref msg, ref update_msg } => {
 							log_debug!(WithContext::from(&self.logger, Some(*node_id), None), "Handling SendChannelAnnouncement event in peer_handler for node {} for short channel id {}",
 									log_pubkey!(node_id),
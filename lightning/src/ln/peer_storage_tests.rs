@@ -0,0 +1,245 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests of storing encrypted backups of our channels with our peers via `peer_storage`.
+
+use crate::events::{Event, MessageSendEvent, MessageSendEventsProvider};
+use crate::ln::channelmanager::MAX_PEER_STORAGE_SIZE;
+use crate::ln::msgs::{self, ChannelMessageHandler};
+use crate::ln::our_peer_storage::DecryptedOurPeerStorage;
+use crate::sign::NodeSigner;
+use crate::util::config::UserConfig;
+use crate::util::ser::Writeable;
+use crate::util::test_utils;
+
+use crate::ln::functional_test_utils::*;
+
+fn peer_storage_config() -> UserConfig {
+	let mut config = test_default_channel_config();
+	config.enable_peer_storage = true;
+	config
+}
+
+/// Disconnects and reconnects `nodes[0]` and `nodes[1]`, returning the messages each generated
+/// upon reconnection.
+fn reconnect_peers<'a, 'b, 'c>(nodes: &Vec<Node<'a, 'b, 'c>>) -> (Vec<MessageSendEvent>, Vec<MessageSendEvent>) {
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	connect_peers(nodes)
+}
+
+/// Connects `nodes[0]` and `nodes[1]`, returning the messages each generated upon connection.
+fn connect_peers<'a, 'b, 'c>(nodes: &Vec<Node<'a, 'b, 'c>>) -> (Vec<MessageSendEvent>, Vec<MessageSendEvent>) {
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+	let init_0 = msgs::Init { features: nodes[0].node.init_features(), networks: None, remote_network_address: None };
+	let init_1 = msgs::Init { features: nodes[1].node.init_features(), networks: None, remote_network_address: None };
+	nodes[0].node.peer_connected(&node_id_1, &init_1, true).unwrap();
+	nodes[1].node.peer_connected(&node_id_0, &init_0, false).unwrap();
+	(nodes[0].node.get_and_clear_pending_msg_events(), nodes[1].node.get_and_clear_pending_msg_events())
+}
+
+fn find_your_peer_storage(msg_events: &[MessageSendEvent]) -> Option<msgs::YourPeerStorage> {
+	msg_events.iter().find_map(|event| match event {
+		MessageSendEvent::SendYourPeerStorage { msg, .. } => Some(msg.clone()),
+		_ => None,
+	})
+}
+
+#[test]
+fn test_peer_storage_exchange() {
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let configs = [Some(peer_storage_config()), Some(peer_storage_config()), Some(peer_storage_config())];
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &configs);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+	let node_id_2 = nodes[2].node.get_our_node_id();
+	assert!(nodes[0].node.init_features().supports_provide_storage());
+
+	// Without any channels, there's nothing to back up.
+	nodes[0].node.timer_tick_occurred();
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	nodes[0].node.timer_tick_occurred();
+	let peer_storage = get_event_msg!(nodes[0], MessageSendEvent::SendPeerStorage, node_id_1);
+	let key = nodes[0].keys_manager.get_peer_storage_key();
+	let backup = DecryptedOurPeerStorage::decrypt(&key, &peer_storage.data).unwrap();
+	assert_eq!(backup.channels.len(), 1);
+	assert_eq!(backup.channels[0].channel_id, chan_id_1);
+	assert_eq!(backup.channels[0].counterparty_node_id, node_id_1);
+	assert_eq!(backup.channels[0].channel_value_satoshis, 100_000);
+	nodes[1].node.handle_peer_storage(&node_id_0, &peer_storage);
+
+	// We don't resend the backup unless it changed.
+	nodes[0].node.timer_tick_occurred();
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	// Once we open another channel, each peer gets a backup leading with its own channels.
+	let chan_id_2 = create_announced_chan_between_nodes(&nodes, 0, 2).2;
+	nodes[0].node.timer_tick_occurred();
+	let msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 2);
+	for event in msg_events {
+		match event {
+			MessageSendEvent::SendPeerStorage { node_id, msg } => {
+				let backup = DecryptedOurPeerStorage::decrypt(&key, &msg.data).unwrap();
+				assert_eq!(backup.channels.len(), 2);
+				assert_eq!(backup.channels[0].counterparty_node_id, node_id);
+				let expected_chan_id = if node_id == node_id_1 { chan_id_1 } else { chan_id_2 };
+				assert_eq!(backup.channels[0].channel_id, expected_chan_id);
+				if node_id == node_id_1 {
+					nodes[1].node.handle_peer_storage(&node_id_0, &msg);
+				} else {
+					assert_eq!(node_id, node_id_2);
+				}
+			},
+			_ => panic!("Unexpected event"),
+		}
+	}
+
+	// The peer returns the latest backup upon reconnection, and we resend ours.
+	let (as_msgs, bs_msgs) = reconnect_peers(&nodes);
+	let your_peer_storage = find_your_peer_storage(&bs_msgs).unwrap();
+	let backup = DecryptedOurPeerStorage::decrypt(&key, &your_peer_storage.data).unwrap();
+	assert_eq!(backup.channels.len(), 2);
+	assert!(as_msgs.iter().any(|event| matches!(event, MessageSendEvent::SendPeerStorage { .. })));
+	assert!(find_your_peer_storage(&as_msgs).is_none());
+
+	// As we know about all channels in the backup, there's nothing to recover.
+	nodes[0].node.handle_your_peer_storage(&node_id_1, &your_peer_storage);
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	nodes[2].node.get_and_clear_pending_msg_events();
+}
+
+#[test]
+fn test_peer_storage_limits() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(peer_storage_config()), Some(peer_storage_config())]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_0 = nodes[0].node.get_our_node_id();
+
+	// We don't store data for peers we don't have a funded channel with.
+	nodes[1].node.handle_peer_storage(&node_id_0, &msgs::PeerStorage { data: vec![42; 32] });
+	let (_, bs_msgs) = reconnect_peers(&nodes);
+	assert!(find_your_peer_storage(&bs_msgs).is_none());
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	// Nor do we store data exceeding our size limit.
+	nodes[1].node.handle_peer_storage(&node_id_0, &msgs::PeerStorage { data: vec![42; MAX_PEER_STORAGE_SIZE + 1] });
+	let (_, bs_msgs) = reconnect_peers(&nodes);
+	assert!(find_your_peer_storage(&bs_msgs).is_none());
+
+	nodes[1].node.handle_peer_storage(&node_id_0, &msgs::PeerStorage { data: vec![42; MAX_PEER_STORAGE_SIZE] });
+	let (_, bs_msgs) = reconnect_peers(&nodes);
+	assert_eq!(find_your_peer_storage(&bs_msgs).unwrap().data, vec![42; MAX_PEER_STORAGE_SIZE]);
+}
+
+#[test]
+fn test_peer_storage_ignored_without_config() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(peer_storage_config()), None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	assert!(!nodes[1].node.init_features().supports_provide_storage());
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	// We don't send backups to peers which don't provide storage...
+	nodes[0].node.timer_tick_occurred();
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	// ...and don't store data if we don't provide storage ourselves.
+	nodes[1].node.handle_peer_storage(&node_id_0, &msgs::PeerStorage { data: vec![42; 32] });
+	let (_, bs_msgs) = reconnect_peers(&nodes);
+	assert!(find_your_peer_storage(&bs_msgs).is_none());
+}
+
+#[test]
+fn test_peer_storage_persisted() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(peer_storage_config()), Some(peer_storage_config())]);
+	let nodes_1_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	let chan_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	nodes[0].node.timer_tick_occurred();
+	let peer_storage = get_event_msg!(nodes[0], MessageSendEvent::SendPeerStorage, node_id_1);
+	nodes[1].node.handle_peer_storage(&node_id_0, &peer_storage);
+
+	nodes[0].node.peer_disconnected(&node_id_1);
+	let chan_monitor_serialized = get_monitor!(nodes[1], chan_id).encode();
+	reload_node!(nodes[1], peer_storage_config(), nodes[1].node.encode(), &[&chan_monitor_serialized], persister, new_chain_monitor, nodes_1_deserialized);
+
+	let (_, bs_msgs) = connect_peers(&nodes);
+	assert_eq!(find_your_peer_storage(&bs_msgs).unwrap(), msgs::YourPeerStorage { data: peer_storage.data });
+}
+
+#[test]
+fn test_peer_storage_recovery() {
+	// Test that a node which lost its channels gets them back from its peer's storage.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(peer_storage_config()), Some(peer_storage_config())]);
+	let nodes_0_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	let wiped_node_0_serialized = nodes[0].node.encode();
+
+	let (_, _, chan_id, funding_tx) = create_announced_chan_between_nodes(&nodes, 0, 1);
+	nodes[0].node.timer_tick_occurred();
+	let peer_storage = get_event_msg!(nodes[0], MessageSendEvent::SendPeerStorage, node_id_1);
+	nodes[1].node.handle_peer_storage(&node_id_0, &peer_storage);
+
+	// Restart nodes[0] without any channels, as if it lost everything but its seed.
+	nodes[1].node.peer_disconnected(&node_id_0);
+	reload_node!(nodes[0], peer_storage_config(), wiped_node_0_serialized, &[], persister, new_chain_monitor, nodes_0_deserialized);
+	assert!(nodes[0].node.list_channels().is_empty());
+	// The wiped node no longer watches the chain for its channel either.
+	nodes[0].chain_source.watched_txn.lock().unwrap().clear();
+	nodes[0].chain_source.watched_outputs.lock().unwrap().clear();
+
+	let (as_msgs, bs_msgs) = connect_peers(&nodes);
+	assert!(as_msgs.is_empty());
+	let your_peer_storage = find_your_peer_storage(&bs_msgs).unwrap();
+
+	nodes[0].node.handle_your_peer_storage(&node_id_1, &your_peer_storage);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match &events[0] {
+		Event::PeerStorageRetrieved { counterparty_node_id, peer_storage } => {
+			assert_eq!(*counterparty_node_id, node_id_1);
+			assert_eq!(peer_storage.channels.len(), 1);
+			let chan = &peer_storage.channels[0];
+			assert_eq!(chan.channel_id, chan_id);
+			assert_eq!(chan.counterparty_node_id, node_id_1);
+			assert_eq!(chan.funding_txo.txid, funding_tx.txid());
+			assert_eq!(chan.channel_value_satoshis, 100_000);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// A blob we can't decrypt is ignored.
+	nodes[0].node.handle_your_peer_storage(&node_id_1, &msgs::YourPeerStorage { data: vec![42; 64] });
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+}
@@ -0,0 +1,419 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests of our channel quiescence (`stfu`) logic.
+
+use crate::events::{ClosureReason, MessageSendEvent, MessageSendEventsProvider};
+use crate::ln::ChannelId;
+use crate::ln::channel::{ChannelPhase, DISCONNECT_PEER_AWAITING_RESPONSE_TICKS};
+use crate::ln::channelmanager::{PaymentId, RAACommitmentOrder, RecipientOnionFields};
+use crate::ln::msgs::{self, ChannelMessageHandler, ErrorAction};
+use crate::util::errors::APIError;
+
+use crate::ln::functional_test_utils::*;
+
+fn exchange_stfu<'a, 'b, 'c>(initiator: &Node<'a, 'b, 'c>, responder: &Node<'a, 'b, 'c>, stfu: &msgs::Stfu) {
+	assert_eq!(stfu.initiator, 1);
+	responder.node.handle_stfu(&initiator.node.get_our_node_id(), stfu);
+	let stfu_resp = get_event_msg!(responder, MessageSendEvent::SendStfu, initiator.node.get_our_node_id());
+	assert_eq!(stfu_resp.initiator, 0);
+	initiator.node.handle_stfu(&responder.node.get_our_node_id(), &stfu_resp);
+	assert!(initiator.node.get_and_clear_pending_msg_events().is_empty());
+}
+
+fn is_quiescence_initiator<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>, counterparty: &Node<'a, 'b, 'c>, channel_id: &ChannelId) -> bool {
+	let mut per_peer_state_lock;
+	let mut peer_state_lock;
+	match get_channel_ref!(node, counterparty, per_peer_state_lock, peer_state_lock, *channel_id) {
+		ChannelPhase::Funded(chan) => chan.context.is_holder_quiescence_initiator == Some(true),
+		_ => panic!("Unexpected ChannelPhase variant"),
+	}
+}
+
+/// Terminates quiescence from `node`'s side only, which disconnects `counterparty`, terminating
+/// quiescence on both sides.
+fn exit_quiescence<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>, counterparty: &Node<'a, 'b, 'c>, channel_id: &ChannelId) {
+	let node_id = node.node.get_our_node_id();
+	let counterparty_node_id = counterparty.node.get_our_node_id();
+	node.node.exit_quiescence(channel_id, &counterparty_node_id).unwrap();
+
+	// The channel stays quiescent until we actually disconnect.
+	assert!(node.node.is_channel_quiescent(channel_id, &counterparty_node_id));
+	let msg_events = node.node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	match &msg_events[0] {
+		MessageSendEvent::HandleError { node_id, action: ErrorAction::DisconnectPeerWithWarning { .. } } => {
+			assert_eq!(*node_id, counterparty_node_id);
+		},
+		_ => panic!("Unexpected event {:?}", msg_events[0]),
+	}
+
+	node.node.peer_disconnected(&counterparty_node_id);
+	counterparty.node.peer_disconnected(&node_id);
+	assert!(!node.node.is_channel_quiescent(channel_id, &counterparty_node_id));
+	assert!(!counterparty.node.is_channel_quiescent(channel_id, &node_id));
+}
+
+/// Reconnects `sender` and `receiver` after quiescence was terminated, returning the update
+/// `sender` frees from its holding cell once the channel is reestablished.
+fn reconnect_releasing_held_update<'a, 'b, 'c>(sender: &Node<'a, 'b, 'c>, receiver: &Node<'a, 'b, 'c>) -> msgs::CommitmentUpdate {
+	let sender_id = sender.node.get_our_node_id();
+	let receiver_id = receiver.node.get_our_node_id();
+	sender.node.peer_connected(&receiver_id, &msgs::Init {
+		features: receiver.node.init_features(), networks: None, remote_network_address: None
+	}, true).unwrap();
+	let sender_reestablish = get_chan_reestablish_msgs!(sender, receiver);
+	receiver.node.peer_connected(&sender_id, &msgs::Init {
+		features: sender.node.init_features(), networks: None, remote_network_address: None
+	}, false).unwrap();
+	let receiver_reestablish = get_chan_reestablish_msgs!(receiver, sender);
+
+	receiver.node.handle_channel_reestablish(&sender_id, &sender_reestablish[0]);
+	let receiver_resp = handle_chan_reestablish_msgs!(receiver, sender);
+	assert!(receiver_resp.1.is_none() && receiver_resp.2.is_none());
+
+	sender.node.handle_channel_reestablish(&receiver_id, &receiver_reestablish[0]);
+	let sender_resp = handle_chan_reestablish_msgs!(sender, receiver);
+	assert!(sender_resp.1.is_none());
+	check_added_monitors(sender, 1);
+	sender_resp.2.unwrap()
+}
+
+#[test]
+fn test_quiescence_holds_htlcs_until_exit() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	let stfu = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, node_id_1);
+	assert_eq!(stfu.channel_id, channel_id);
+	assert!(!nodes[0].node.is_channel_quiescent(&channel_id, &node_id_1));
+
+	// Proposing again while the handshake is in progress is a no-op.
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	exchange_stfu(&nodes[0], &nodes[1], &stfu);
+	assert!(nodes[0].node.is_channel_quiescent(&channel_id, &node_id_1));
+	assert!(nodes[1].node.is_channel_quiescent(&channel_id, &node_id_0));
+	assert!(matches!(nodes[0].node.propose_quiescence(&channel_id, &node_id_1), Err(APIError::APIMisuseError { .. })));
+
+	// Any payments sent while quiescent are held in the holding cell.
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 1_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors(&nodes[0], 0);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	// Only the initiator terminates quiescence, which disconnects the peer so that neither side
+	// considers the channel quiescent any longer.
+	exit_quiescence(&nodes[0], &nodes[1], &channel_id);
+	assert!(matches!(nodes[0].node.exit_quiescence(&channel_id, &node_id_1), Err(APIError::APIMisuseError { .. })));
+
+	// Once the channel is reestablished, the held HTLC is sent along.
+	let update_add = reconnect_releasing_held_update(&nodes[0], &nodes[1]);
+	assert_eq!(update_add.update_add_htlcs.len(), 1);
+	let ev = MessageSendEvent::UpdateHTLCs { node_id: node_id_1, updates: update_add };
+	pass_along_path(&nodes[0], &[&nodes[1]], 1_000_000, payment_hash, Some(payment_secret), ev, true, None);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+}
+
+#[test]
+fn test_quiescence_tie_break() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	// Both sides attempt to initiate quiescence at the same time, so the channel funder (nodes[0])
+	// wins the tie-break.
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	let stfu_0 = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, node_id_1);
+	nodes[1].node.propose_quiescence(&channel_id, &node_id_0).unwrap();
+	let stfu_1 = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, node_id_0);
+	assert_eq!(stfu_0.initiator, 1);
+	assert_eq!(stfu_1.initiator, 1);
+
+	nodes[0].node.handle_stfu(&node_id_1, &stfu_1);
+	nodes[1].node.handle_stfu(&node_id_0, &stfu_0);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[0].node.is_channel_quiescent(&channel_id, &node_id_1));
+	assert!(nodes[1].node.is_channel_quiescent(&channel_id, &node_id_0));
+	assert!(is_quiescence_initiator(&nodes[0], &nodes[1], &channel_id));
+	assert!(!is_quiescence_initiator(&nodes[1], &nodes[0], &channel_id));
+
+	exit_quiescence(&nodes[0], &nodes[1], &channel_id);
+	let mut reconnect_args = ReconnectArgs::new(&nodes[0], &nodes[1]);
+	// No commitment updates have happened on the channel yet, so `channel_ready` is re-sent.
+	reconnect_args.send_channel_ready = (true, true);
+	reconnect_nodes(reconnect_args);
+
+	// If the fundee only decides to initiate once the funder's `stfu` is already in flight, it
+	// still loses the tie-break.
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	let stfu_0 = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, node_id_1);
+	nodes[1].node.propose_quiescence(&channel_id, &node_id_0).unwrap();
+	let stfu_1 = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, node_id_0);
+
+	nodes[1].node.handle_stfu(&node_id_0, &stfu_0);
+	nodes[0].node.handle_stfu(&node_id_1, &stfu_1);
+	assert!(is_quiescence_initiator(&nodes[0], &nodes[1], &channel_id));
+	assert!(!is_quiescence_initiator(&nodes[1], &nodes[0], &channel_id));
+
+	// The side which lost the tie-break may terminate quiescence as well.
+	exit_quiescence(&nodes[1], &nodes[0], &channel_id);
+	let mut reconnect_args = ReconnectArgs::new(&nodes[0], &nodes[1]);
+	reconnect_args.send_channel_ready = (true, true);
+	reconnect_nodes(reconnect_args);
+
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 1_000_000);
+}
+
+#[test]
+fn test_quiescence_waits_for_pending_updates() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 1_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors(&nodes[0], 1);
+	let update_add = get_htlc_update_msgs!(nodes[0], node_id_1);
+
+	// We can't send our `stfu` until the HTLC has been irrevocably committed by both sides.
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	nodes[1].node.handle_update_add_htlc(&node_id_0, &update_add.update_add_htlcs[0]);
+	nodes[1].node.handle_commitment_signed(&node_id_0, &update_add.commitment_signed);
+	check_added_monitors(&nodes[1], 1);
+	let (bs_raa, bs_commitment_signed) = get_revoke_commit_msgs!(nodes[1], node_id_0);
+
+	nodes[0].node.handle_revoke_and_ack(&node_id_1, &bs_raa);
+	check_added_monitors(&nodes[0], 1);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	nodes[0].node.handle_commitment_signed(&node_id_1, &bs_commitment_signed);
+	check_added_monitors(&nodes[0], 1);
+	let msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 2);
+	let as_raa = match &msg_events[0] {
+		MessageSendEvent::SendRevokeAndACK { node_id, msg } => {
+			assert_eq!(*node_id, node_id_1);
+			msg.clone()
+		},
+		_ => panic!("Unexpected event {:?}", msg_events[0]),
+	};
+	let stfu = match &msg_events[1] {
+		MessageSendEvent::SendStfu { node_id, msg } => {
+			assert_eq!(*node_id, node_id_1);
+			msg.clone()
+		},
+		_ => panic!("Unexpected event {:?}", msg_events[1]),
+	};
+
+	nodes[1].node.handle_revoke_and_ack(&node_id_0, &as_raa);
+	check_added_monitors(&nodes[1], 1);
+	exchange_stfu(&nodes[0], &nodes[1], &stfu);
+
+	// The recipient can't claim the HTLC back while quiescent, so it's held until we exit.
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	expect_payment_claimable!(nodes[1], payment_hash, payment_secret, 1_000_000);
+	nodes[1].node.claim_funds(payment_preimage);
+	check_added_monitors(&nodes[1], 1);
+	expect_payment_claimed!(nodes[1], payment_hash, 1_000_000);
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	// Only the non-initiator terminates quiescence, and the held claim is sent along once the
+	// channel is reestablished.
+	exit_quiescence(&nodes[1], &nodes[0], &channel_id);
+	let mut reconnect_args = ReconnectArgs::new(&nodes[0], &nodes[1]);
+	reconnect_args.pending_cell_htlc_claims = (1, 0);
+	reconnect_nodes(reconnect_args);
+	expect_payment_sent!(nodes[0], payment_preimage);
+}
+
+#[test]
+fn test_quiescence_terminated_on_reconnect() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	let stfu = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, node_id_1);
+	exchange_stfu(&nodes[0], &nodes[1], &stfu);
+
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 1_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors(&nodes[0], 0);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	// Disconnecting implicitly terminates quiescence.
+	nodes[0].node.peer_disconnected(&node_id_1);
+	nodes[1].node.peer_disconnected(&node_id_0);
+	assert!(!nodes[0].node.is_channel_quiescent(&channel_id, &node_id_1));
+	assert!(!nodes[1].node.is_channel_quiescent(&channel_id, &node_id_0));
+	assert!(matches!(nodes[0].node.exit_quiescence(&channel_id, &node_id_1), Err(APIError::APIMisuseError { .. })));
+
+	// The held HTLC is released once the channel is reestablished.
+	let update_add = reconnect_releasing_held_update(&nodes[0], &nodes[1]);
+	assert_eq!(update_add.update_add_htlcs.len(), 1);
+
+	let ev = MessageSendEvent::UpdateHTLCs { node_id: node_id_1, updates: update_add };
+	pass_along_path(&nodes[0], &[&nodes[1]], 1_000_000, payment_hash, Some(payment_secret), ev, true, None);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+
+	// The channel can be made quiescent again after reconnecting.
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	let stfu = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, node_id_1);
+	exchange_stfu(&nodes[0], &nodes[1], &stfu);
+	assert!(nodes[0].node.is_channel_quiescent(&channel_id, &node_id_1));
+}
+
+#[test]
+fn test_quiescence_disconnect_before_stfu_response() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	// nodes[1] receives our `stfu` but the connection drops before it can respond.
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	let stfu = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, node_id_1);
+	nodes[1].node.handle_stfu(&node_id_0, &stfu);
+	let _ = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, node_id_0);
+	assert!(nodes[1].node.is_channel_quiescent(&channel_id, &node_id_0));
+
+	nodes[0].node.peer_disconnected(&node_id_1);
+	nodes[1].node.peer_disconnected(&node_id_0);
+	assert!(!nodes[1].node.is_channel_quiescent(&channel_id, &node_id_0));
+	let mut reconnect_args = ReconnectArgs::new(&nodes[0], &nodes[1]);
+	// No commitment updates have happened on the channel yet, so `channel_ready` is re-sent.
+	reconnect_args.send_channel_ready = (true, true);
+	reconnect_nodes(reconnect_args);
+
+	// Neither side should consider the channel quiescent, and both may freely send updates.
+	assert!(!nodes[0].node.is_channel_quiescent(&channel_id, &node_id_1));
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 1_000_000);
+
+	// Either side may start over, including the one that wasn't the initiator previously.
+	nodes[1].node.propose_quiescence(&channel_id, &node_id_0).unwrap();
+	let stfu = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, node_id_0);
+	exchange_stfu(&nodes[1], &nodes[0], &stfu);
+	assert!(is_quiescence_initiator(&nodes[1], &nodes[0], &channel_id));
+	assert!(!is_quiescence_initiator(&nodes[0], &nodes[1], &channel_id));
+}
+
+#[test]
+fn test_quiescence_exit_by_one_side() {
+	// There is no message to terminate quiescence, so if only one side decides to exit it, it has
+	// to disconnect. Otherwise, its counterparty would still consider the channel quiescent and
+	// close it upon receiving the next update.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	// Give nodes[1] some balance so that it can send a payment afterwards.
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	let stfu = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, node_id_1);
+	exchange_stfu(&nodes[0], &nodes[1], &stfu);
+
+	// nodes[1] exits without nodes[0] ever doing so.
+	exit_quiescence(&nodes[1], &nodes[0], &channel_id);
+	assert!(matches!(nodes[1].node.exit_quiescence(&channel_id, &node_id_0), Err(APIError::APIMisuseError { .. })));
+	reconnect_nodes(ReconnectArgs::new(&nodes[0], &nodes[1]));
+
+	// Both sides may send updates again without the channel being closed.
+	send_payment(&nodes[1], &[&nodes[0]], 1_000_000);
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+	assert_eq!(nodes[0].node.list_usable_channels().len(), 1);
+	assert_eq!(nodes[1].node.list_usable_channels().len(), 1);
+}
+
+#[test]
+fn test_quiescence_timeout_disconnects_peer() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	let _ = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, node_id_1);
+
+	// If our counterparty never responds with their `stfu`, we'll disconnect them.
+	for _ in 0..DISCONNECT_PEER_AWAITING_RESPONSE_TICKS - 1 {
+		nodes[0].node.timer_tick_occurred();
+		assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	}
+	nodes[0].node.timer_tick_occurred();
+	let msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	match &msg_events[0] {
+		MessageSendEvent::HandleError { node_id, action: ErrorAction::DisconnectPeerWithWarning { .. } } => {
+			assert_eq!(*node_id, node_id_1);
+		},
+		_ => panic!("Unexpected event {:?}", msg_events[0]),
+	}
+}
+
+#[test]
+fn test_quiescence_update_after_stfu_closes_channel() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let node_id_0 = nodes[0].node.get_our_node_id();
+	let node_id_1 = nodes[1].node.get_our_node_id();
+
+	nodes[0].node.propose_quiescence(&channel_id, &node_id_1).unwrap();
+	let stfu = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, node_id_1);
+	nodes[1].node.handle_stfu(&node_id_0, &stfu);
+	let _ = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, node_id_0);
+
+	// nodes[0] may not send any updates after its `stfu`.
+	nodes[1].node.handle_update_fee(&node_id_0, &msgs::UpdateFee { channel_id, feerate_per_kw: 1000 });
+	check_closed_broadcast!(nodes[1], true);
+	check_added_monitors(&nodes[1], 1);
+	check_closed_event!(nodes[1], 1, ClosureReason::ProcessingError {
+		err: "Peer sent update_fee after sending stfu".to_owned()
+	}, [node_id_0], 100000);
+}
@@ -0,0 +1,325 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tracks the local reputation of our peers and the resources used by HTLCs we forward, allowing
+//! us to set the experimental endorsement signal on outgoing HTLCs and to protect a share of each
+//! outgoing channel's HTLC slots and liquidity from channel jamming.
+//!
+//! See [`HTLCEndorsementConfig`] for details.
+
+use bitcoin::secp256k1::PublicKey;
+
+use crate::util::config::HTLCEndorsementConfig;
+
+use crate::prelude::*;
+use core::convert::TryInto;
+
+/// A value which decays by half every configured half-life.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct DecayingValue {
+	value_msat: i64,
+	/// Seconds since the Unix epoch when `value_msat` was last brought up to date.
+	last_updated_secs: u64,
+}
+
+impl_writeable_tlv_based!(DecayingValue, {
+	(0, value_msat, required),
+	(2, last_updated_secs, required),
+});
+
+impl DecayingValue {
+	/// Returns the value decayed up to `now_secs`, halving it for each elapsed half-life and
+	/// interpolating linearly within the current one.
+	fn value_at(&self, now_secs: u64, half_life_secs: u64) -> i64 {
+		if half_life_secs == 0 { return 0; }
+		let elapsed_secs = now_secs.saturating_sub(self.last_updated_secs);
+		let half_lives = elapsed_secs / half_life_secs;
+		if half_lives >= 64 { return 0; }
+		let value = (self.value_msat >> half_lives) as i128;
+		let remainder_secs = (elapsed_secs % half_life_secs) as i128;
+		(value - value * remainder_secs / (2 * half_life_secs as i128)) as i64
+	}
+
+	fn add(&mut self, amount_msat: i64, now_secs: u64, half_life_secs: u64) {
+		self.value_msat = self.value_at(now_secs, half_life_secs).saturating_add(amount_msat);
+		self.last_updated_secs = core::cmp::max(self.last_updated_secs, now_secs);
+	}
+}
+
+/// An HTLC we've forwarded and which hasn't yet been resolved.
+struct InFlightHTLC {
+	incoming_node_id: PublicKey,
+	outgoing_short_channel_id: u64,
+	outgoing_amt_msat: u64,
+	fee_msat: u64,
+	/// Seconds since the Unix epoch when we forwarded the HTLC.
+	added_at_secs: u64,
+	/// Whether the HTLC was endorsed by a peer with good reputation, and thus allowed to use the
+	/// protected share of the outgoing channel's resources.
+	protected: bool,
+	/// The amount by which the incoming peer's reputation would drop if the HTLC were held until
+	/// it expires.
+	risk_msat: u64,
+}
+
+impl_writeable_tlv_based!(InFlightHTLC, {
+	(0, incoming_node_id, required),
+	(2, outgoing_short_channel_id, required),
+	(4, outgoing_amt_msat, required),
+	(6, fee_msat, required),
+	(8, added_at_secs, required),
+	(10, protected, required),
+	(12, risk_msat, required),
+});
+
+/// Tracks the reputation of our peers, the revenue of our outgoing channels and the HTLCs we've
+/// forwarded which are still pending.
+///
+/// HTLCs are keyed by the short channel id and HTLC id of the incoming HTLC.
+pub(crate) struct HTLCReputationTracker {
+	/// The fees each peer's HTLCs have earned us, net of penalties for slowly-resolving endorsed
+	/// HTLCs.
+	peer_reputations: HashMap<PublicKey, DecayingValue>,
+	/// The fees each outgoing channel has earned us, keyed by its short channel id.
+	channel_revenues: HashMap<u64, DecayingValue>,
+	in_flight_htlcs: HashMap<(u64, u64), InFlightHTLC>,
+}
+
+impl_writeable_tlv_based!(HTLCReputationTracker, {
+	(0, peer_reputations, required),
+	(2, channel_revenues, required),
+	(4, in_flight_htlcs, required),
+});
+
+impl HTLCReputationTracker {
+	pub(crate) fn new() -> Self {
+		Self {
+			peer_reputations: HashMap::new(),
+			channel_revenues: HashMap::new(),
+			in_flight_htlcs: HashMap::new(),
+		}
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.peer_reputations.is_empty() && self.channel_revenues.is_empty() &&
+			self.in_flight_htlcs.is_empty()
+	}
+
+	/// Decides whether an HTLC received from `incoming_node_id` may be forwarded over the channel
+	/// with the given `outgoing_short_channel_id` and, if so, starts tracking it.
+	///
+	/// Returns `Ok(true)` if the HTLC was endorsed by a peer with good reputation, in which case it
+	/// may use all of the outgoing channel's resources and should be forwarded endorsed. Returns
+	/// `Ok(false)` if it fits in the outgoing channel's general (unprotected) resources, in which
+	/// case it should be forwarded unendorsed, and `Err(())` if it should be failed back.
+	///
+	/// `max_hold_secs` is the longest the HTLC could be held for before it expires.
+	pub(crate) fn add_htlc(
+		&mut self, config: &HTLCEndorsementConfig, incoming_htlc: (u64, u64),
+		incoming_node_id: PublicKey, incoming_endorsed: bool, outgoing_short_channel_id: u64,
+		outgoing_amt_msat: u64, fee_msat: u64, max_hold_secs: u64,
+		outgoing_max_accepted_htlcs: u16, outgoing_max_htlc_value_in_flight_msat: u64, now_secs: u64,
+	) -> Result<bool, ()> {
+		let half_life_secs = config.reputation_half_life_secs;
+		let resolution_period_secs = core::cmp::max(config.resolution_period_secs, 1);
+		let risk_msat = fee_msat.saturating_mul(max_hold_secs / resolution_period_secs);
+
+		let protected = incoming_endorsed && {
+			let reputation_msat = self.peer_reputations.get(&incoming_node_id)
+				.map_or(0, |reputation| reputation.value_at(now_secs, half_life_secs));
+			let revenue_msat = self.channel_revenues.get(&outgoing_short_channel_id)
+				.map_or(0, |revenue| revenue.value_at(now_secs, half_life_secs));
+			let pending_risk_msat = self.in_flight_htlcs.values()
+				.filter(|htlc| htlc.protected && htlc.incoming_node_id == incoming_node_id)
+				.fold(risk_msat, |total, htlc| total.saturating_add(htlc.risk_msat));
+			reputation_msat.saturating_sub(pending_risk_msat.try_into().unwrap_or(i64::MAX)) > revenue_msat
+		};
+
+		if !protected {
+			let (general_htlcs, general_value_msat) = self.in_flight_htlcs.values()
+				.filter(|htlc| !htlc.protected && htlc.outgoing_short_channel_id == outgoing_short_channel_id)
+				.fold((0u64, 0u64), |(count, value), htlc| (count + 1, value + htlc.outgoing_amt_msat));
+			let general_slots = outgoing_max_accepted_htlcs as u64 *
+				(100 - core::cmp::min(config.protected_slots_percent, 100) as u64) / 100;
+			let general_liquidity_msat = outgoing_max_htlc_value_in_flight_msat /
+				100 * (100 - core::cmp::min(config.protected_liquidity_percent, 100) as u64);
+			if general_htlcs + 1 > general_slots ||
+				general_value_msat.saturating_add(outgoing_amt_msat) > general_liquidity_msat
+			{
+				return Err(());
+			}
+		}
+
+		self.in_flight_htlcs.insert(incoming_htlc, InFlightHTLC {
+			incoming_node_id, outgoing_short_channel_id, outgoing_amt_msat, fee_msat,
+			added_at_secs: now_secs, protected, risk_msat,
+		});
+		Ok(protected)
+	}
+
+	/// Stops tracking an HTLC added via [`Self::add_htlc`] which we ended up not forwarding.
+	pub(crate) fn remove_htlc(&mut self, incoming_htlc: (u64, u64)) {
+		self.in_flight_htlcs.remove(&incoming_htlc);
+	}
+
+	/// Updates the incoming peer's reputation and the outgoing channel's revenue once a forwarded
+	/// HTLC has been settled or failed.
+	///
+	/// Endorsed HTLCs which took longer than [`HTLCEndorsementConfig::resolution_period_secs`] to
+	/// resolve reduce the incoming peer's reputation by the fees we could have earned from the
+	/// resources they used in the meantime.
+	pub(crate) fn resolve_htlc(
+		&mut self, config: &HTLCEndorsementConfig, incoming_htlc: (u64, u64), settled: bool,
+		now_secs: u64,
+	) {
+		let htlc = match self.in_flight_htlcs.remove(&incoming_htlc) {
+			Some(htlc) => htlc,
+			None => return,
+		};
+		let half_life_secs = config.reputation_half_life_secs;
+		let resolution_period_secs = core::cmp::max(config.resolution_period_secs, 1);
+		let fee_msat: i64 = htlc.fee_msat.try_into().unwrap_or(i64::MAX);
+
+		let hold_secs = now_secs.saturating_sub(htlc.added_at_secs);
+		let penalty_msat = if htlc.protected && hold_secs > resolution_period_secs {
+			fee_msat.saturating_mul((hold_secs / resolution_period_secs) as i64)
+		} else { 0 };
+		let earned_msat = if settled { fee_msat } else { 0 };
+
+		self.peer_reputations.entry(htlc.incoming_node_id).or_default()
+			.add(earned_msat.saturating_sub(penalty_msat), now_secs, half_life_secs);
+		if settled {
+			self.channel_revenues.entry(htlc.outgoing_short_channel_id).or_default()
+				.add(earned_msat, now_secs, half_life_secs);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+	use crate::util::config::HTLCEndorsementConfig;
+	use crate::util::ser::{Readable, Writeable};
+	use super::{DecayingValue, HTLCReputationTracker};
+
+	const HALF_LIFE: u64 = 1000;
+	const OUTGOING_SCID: u64 = 42;
+
+	fn config() -> HTLCEndorsementConfig {
+		HTLCEndorsementConfig {
+			enabled: true,
+			resolution_period_secs: 90,
+			reputation_half_life_secs: HALF_LIFE,
+			..Default::default()
+		}
+	}
+
+	fn node_id(byte: u8) -> PublicKey {
+		PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[byte; 32]).unwrap())
+	}
+
+	#[test]
+	fn decays_by_half_every_half_life() {
+		let value = DecayingValue { value_msat: 1000, last_updated_secs: 10 };
+		assert_eq!(value.value_at(10, HALF_LIFE), 1000);
+		assert_eq!(value.value_at(10 + HALF_LIFE / 2, HALF_LIFE), 750);
+		assert_eq!(value.value_at(10 + HALF_LIFE, HALF_LIFE), 500);
+		assert_eq!(value.value_at(10 + 2 * HALF_LIFE, HALF_LIFE), 250);
+		assert_eq!(value.value_at(10 + 64 * HALF_LIFE, HALF_LIFE), 0);
+		assert_eq!(value.value_at(10, 0), 0);
+
+		let negative = DecayingValue { value_msat: -1000, last_updated_secs: 10 };
+		assert_eq!(negative.value_at(10 + HALF_LIFE, HALF_LIFE), -500);
+	}
+
+	#[test]
+	fn limits_unprotected_htlcs_to_general_resources() {
+		let config = config();
+		let mut tracker = HTLCReputationTracker::new();
+		let peer = node_id(1);
+
+		// With 50% of 4 slots and 10_000 msat protected, only two HTLCs totalling 5_000 msat fit in
+		// the general bucket.
+		assert_eq!(tracker.add_htlc(&config, (1, 0), peer, true, OUTGOING_SCID, 3_000, 10, 900, 4, 10_000, 0), Ok(false));
+		assert_eq!(tracker.add_htlc(&config, (1, 1), peer, false, OUTGOING_SCID, 3_000, 10, 900, 4, 10_000, 0), Err(()));
+		assert_eq!(tracker.add_htlc(&config, (1, 1), peer, false, OUTGOING_SCID, 2_000, 10, 900, 4, 10_000, 0), Ok(false));
+		assert_eq!(tracker.add_htlc(&config, (1, 2), peer, false, OUTGOING_SCID, 1, 10, 900, 4, 10_000, 0), Err(()));
+
+		// Other outgoing channels have their own buckets, and removed HTLCs free up resources.
+		assert_eq!(tracker.add_htlc(&config, (1, 2), peer, false, OUTGOING_SCID + 1, 1, 10, 900, 4, 10_000, 0), Ok(false));
+		tracker.remove_htlc((1, 1));
+		assert_eq!(tracker.add_htlc(&config, (1, 3), peer, false, OUTGOING_SCID, 2_000, 10, 900, 4, 10_000, 0), Ok(false));
+	}
+
+	#[test]
+	fn protects_endorsed_htlcs_from_peers_with_good_reputation() {
+		let config = config();
+		let mut tracker = HTLCReputationTracker::new();
+		let good_peer = node_id(1);
+		let other_peer = node_id(2);
+
+		// `good_peer` earns us 10_000 msat over another channel, while `other_peer` earns us 1_000 msat
+		// over the outgoing channel.
+		assert_eq!(tracker.add_htlc(&config, (1, 0), good_peer, false, OUTGOING_SCID + 1, 1_000, 10_000, 900, 10, 100_000, 0), Ok(false));
+		tracker.resolve_htlc(&config, (1, 0), true, 1);
+		assert_eq!(tracker.add_htlc(&config, (2, 0), other_peer, true, OUTGOING_SCID, 1_000, 1_000, 900, 10, 100_000, 1), Ok(false));
+		tracker.resolve_htlc(&config, (2, 0), true, 2);
+
+		// Only endorsed HTLCs from `good_peer` whose risk it can cover are protected.
+		assert_eq!(tracker.add_htlc(&config, (1, 1), good_peer, false, OUTGOING_SCID, 1_000, 100, 900, 10, 100_000, 2), Ok(false));
+		assert_eq!(tracker.add_htlc(&config, (2, 1), other_peer, true, OUTGOING_SCID, 1_000, 100, 900, 10, 100_000, 2), Ok(false));
+		assert_eq!(tracker.add_htlc(&config, (1, 2), good_peer, true, OUTGOING_SCID, 1_000, 100, 900, 10, 100_000, 2), Ok(true));
+		assert_eq!(tracker.add_htlc(&config, (1, 3), good_peer, true, OUTGOING_SCID, 1_000, 1_000, 900, 10, 100_000, 2), Ok(false));
+
+		// Protected HTLCs may use resources beyond the general bucket.
+		assert_eq!(tracker.add_htlc(&config, (2, 2), other_peer, false, OUTGOING_SCID, 100_000, 1, 900, 10, 100_000, 2), Err(()));
+		assert_eq!(tracker.add_htlc(&config, (1, 4), good_peer, true, OUTGOING_SCID, 100_000, 1, 900, 10, 100_000, 2), Ok(true));
+	}
+
+	#[test]
+	fn penalizes_slow_endorsed_htlcs() {
+		let config = config();
+		let mut tracker = HTLCReputationTracker::new();
+		let peer = node_id(1);
+
+		assert_eq!(tracker.add_htlc(&config, (1, 0), peer, false, OUTGOING_SCID + 1, 1_000, 20_000, 900, 10, 100_000, 0), Ok(false));
+		tracker.resolve_htlc(&config, (1, 0), true, 0);
+		assert_eq!(tracker.peer_reputations.get(&peer).unwrap().value_msat, 20_000);
+
+		// A protected HTLC which takes three resolution periods to fail costs three times its fee.
+		assert_eq!(tracker.add_htlc(&config, (1, 1), peer, true, OUTGOING_SCID, 1_000, 1_000, 900, 10, 100_000, 0), Ok(true));
+		tracker.resolve_htlc(&config, (1, 1), false, 270);
+		let reputation = *tracker.peer_reputations.get(&peer).unwrap();
+		assert_eq!(reputation.value_msat, DecayingValue { value_msat: 20_000, last_updated_secs: 0 }.value_at(270, HALF_LIFE) - 3_000);
+		assert!(tracker.channel_revenues.get(&OUTGOING_SCID).is_none());
+
+		// Unprotected HTLCs are never penalized.
+		assert_eq!(tracker.add_htlc(&config, (1, 2), peer, false, OUTGOING_SCID, 1_000, 1_000, 900, 10, 100_000, 270), Ok(false));
+		tracker.resolve_htlc(&config, (1, 2), false, 270 + 900);
+		assert_eq!(tracker.peer_reputations.get(&peer).unwrap().value_at(270 + 900, HALF_LIFE),
+			reputation.value_at(270 + 900, HALF_LIFE));
+	}
+
+	#[test]
+	fn tracker_serialization_roundtrip() {
+		let config = config();
+		let mut tracker = HTLCReputationTracker::new();
+		let peer = node_id(1);
+		assert_eq!(tracker.add_htlc(&config, (1, 0), peer, false, OUTGOING_SCID, 1_000, 10, 900, 10, 100_000, 0), Ok(false));
+		tracker.resolve_htlc(&config, (1, 0), true, 5);
+		assert_eq!(tracker.add_htlc(&config, (1, 1), peer, true, OUTGOING_SCID, 1_000, 10, 900, 10, 100_000, 5), Ok(false));
+
+		let read_tracker: HTLCReputationTracker = Readable::read(&mut &tracker.encode()[..]).unwrap();
+		assert_eq!(read_tracker.peer_reputations, tracker.peer_reputations);
+		assert_eq!(read_tracker.channel_revenues, tracker.channel_revenues);
+		assert_eq!(read_tracker.in_flight_htlcs.len(), 1);
+		assert!(read_tracker.in_flight_htlcs.contains_key(&(1, 1)));
+	}
+}
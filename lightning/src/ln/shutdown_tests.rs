@@ -19,6 +19,7 @@ use crate::ln::msgs;
 use crate::ln::msgs::{ChannelMessageHandler, ErrorAction};
 use crate::ln::onion_utils::INVALID_ONION_BLINDING;
 use crate::ln::script::ShutdownScript;
+use crate::util::ser::Writeable;
 use crate::util::test_utils;
 use crate::util::test_utils::OnGetShutdownScriptpubkey;
 use crate::util::errors::APIError;
@@ -324,6 +325,57 @@ fn expect_channel_shutdown_state_with_force_closure() {
 	check_closed_event!(nodes[1], 1, ClosureReason::HolderForceClosed, [nodes[0].node.get_our_node_id()], 100000);
 }
 
+#[test]
+fn closed_channels_are_archived() {
+	// Test that closed channels are kept in the archive returned by `list_closed_channels`, learn
+	// their closing transaction once it confirms and survive a reload.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let persister;
+	let new_chain_monitor;
+	let nodes_1_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let chan_1 = create_announced_chan_between_nodes(&nodes, 0, 1);
+	let balance_msat = nodes[1].node.list_channels()[0].balance_msat;
+	assert!(nodes[1].node.list_closed_channels().is_empty());
+
+	nodes[1].node.force_close_broadcasting_latest_txn(&chan_1.2, &nodes[0].node.get_our_node_id()).unwrap();
+	check_closed_broadcast!(nodes[1], true);
+	check_added_monitors!(nodes[1], 1);
+	check_closed_event!(nodes[1], 1, ClosureReason::HolderForceClosed, [nodes[0].node.get_our_node_id()], 100000);
+
+	let closed_channels = nodes[1].node.list_closed_channels();
+	assert_eq!(closed_channels.len(), 1);
+	assert_eq!(closed_channels[0].channel_id, chan_1.2);
+	assert_eq!(closed_channels[0].counterparty_node_id, nodes[0].node.get_our_node_id());
+	assert_eq!(closed_channels[0].funding_txo.txid, chan_1.3.txid());
+	assert_eq!(closed_channels[0].channel_value_satoshis, 100000);
+	assert_eq!(closed_channels[0].closure_reason, Some(ClosureReason::HolderForceClosed));
+	assert_eq!(closed_channels[0].balance_msat, balance_msat);
+	assert_eq!(closed_channels[0].closed_at_height, nodes[1].best_block_info().1);
+	assert_eq!(closed_channels[0].closing_txid, None);
+
+	let node_txn = nodes[1].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0);
+	assert_eq!(node_txn.len(), 1);
+	mine_transaction(&nodes[1], &node_txn[0]);
+	let closed_channels = nodes[1].node.list_closed_channels();
+	assert_eq!(closed_channels[0].closing_txid, Some(node_txn[0].txid()));
+	assert_eq!(closed_channels[0].closing_tx_confirmation_height, Some(nodes[1].best_block_info().1));
+
+	// Our counterparty learns of the closure, and the closing transaction, in the same block.
+	mine_transaction(&nodes[0], &node_txn[0]);
+	check_added_monitors!(nodes[0], 1);
+	check_closed_broadcast!(nodes[0], true);
+	check_closed_event!(nodes[0], 1, ClosureReason::CommitmentTxConfirmed, [nodes[1].node.get_our_node_id()], 100000);
+	let closed_channel = nodes[0].node.list_closed_channels().pop().unwrap();
+	assert_eq!(closed_channel.closure_reason, Some(ClosureReason::CommitmentTxConfirmed));
+	assert_eq!(closed_channel.closing_txid, Some(node_txn[0].txid()));
+
+	reload_node!(nodes[1], &nodes[1].node.encode(), &[], persister, new_chain_monitor, nodes_1_deserialized);
+	assert_eq!(nodes[1].node.list_closed_channels(), closed_channels);
+}
+
 #[test]
 fn updates_shutdown_wait() {
 	// Test sending a shutdown with outstanding updates pending
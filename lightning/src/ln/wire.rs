@@ -53,6 +53,8 @@ pub(crate) enum Message<T> where T: core::fmt::Debug + Type + TestEq {
 	Warning(msgs::WarningMessage),
 	Ping(msgs::Ping),
 	Pong(msgs::Pong),
+	PeerStorage(msgs::PeerStorage),
+	YourPeerStorage(msgs::YourPeerStorage),
 	OpenChannel(msgs::OpenChannel),
 	OpenChannelV2(msgs::OpenChannelV2),
 	AcceptChannel(msgs::AcceptChannel),
@@ -60,6 +62,9 @@ pub(crate) enum Message<T> where T: core::fmt::Debug + Type + TestEq {
 	FundingCreated(msgs::FundingCreated),
 	FundingSigned(msgs::FundingSigned),
 	Stfu(msgs::Stfu),
+	DynPropose(msgs::DynPropose),
+	DynAck(msgs::DynAck),
+	DynReject(msgs::DynReject),
 	Splice(msgs::Splice),
 	SpliceAck(msgs::SpliceAck),
 	SpliceLocked(msgs::SpliceLocked),
@@ -108,6 +113,8 @@ impl<T> Writeable for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::Warning(ref msg) => msg.write(writer),
 			&Message::Ping(ref msg) => msg.write(writer),
 			&Message::Pong(ref msg) => msg.write(writer),
+			&Message::PeerStorage(ref msg) => msg.write(writer),
+			&Message::YourPeerStorage(ref msg) => msg.write(writer),
 			&Message::OpenChannel(ref msg) => msg.write(writer),
 			&Message::OpenChannelV2(ref msg) => msg.write(writer),
 			&Message::AcceptChannel(ref msg) => msg.write(writer),
@@ -115,6 +122,9 @@ impl<T> Writeable for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::FundingCreated(ref msg) => msg.write(writer),
 			&Message::FundingSigned(ref msg) => msg.write(writer),
 			&Message::Stfu(ref msg) => msg.write(writer),
+			&Message::DynPropose(ref msg) => msg.write(writer),
+			&Message::DynAck(ref msg) => msg.write(writer),
+			&Message::DynReject(ref msg) => msg.write(writer),
 			&Message::Splice(ref msg) => msg.write(writer),
 			&Message::SpliceAck(ref msg) => msg.write(writer),
 			&Message::SpliceLocked(ref msg) => msg.write(writer),
@@ -163,6 +173,8 @@ impl<T> Type for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::Warning(ref msg) => msg.type_id(),
 			&Message::Ping(ref msg) => msg.type_id(),
 			&Message::Pong(ref msg) => msg.type_id(),
+			&Message::PeerStorage(ref msg) => msg.type_id(),
+			&Message::YourPeerStorage(ref msg) => msg.type_id(),
 			&Message::OpenChannel(ref msg) => msg.type_id(),
 			&Message::OpenChannelV2(ref msg) => msg.type_id(),
 			&Message::AcceptChannel(ref msg) => msg.type_id(),
@@ -170,6 +182,9 @@ impl<T> Type for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::FundingCreated(ref msg) => msg.type_id(),
 			&Message::FundingSigned(ref msg) => msg.type_id(),
 			&Message::Stfu(ref msg) => msg.type_id(),
+			&Message::DynPropose(ref msg) => msg.type_id(),
+			&Message::DynAck(ref msg) => msg.type_id(),
+			&Message::DynReject(ref msg) => msg.type_id(),
 			&Message::Splice(ref msg) => msg.type_id(),
 			&Message::SpliceAck(ref msg) => msg.type_id(),
 			&Message::SpliceLocked(ref msg) => msg.type_id(),
@@ -252,6 +267,12 @@ fn do_read<R: io::Read, T, H: core::ops::Deref>(buffer: &mut R, message_type: u1
 		msgs::Pong::TYPE => {
 			Ok(Message::Pong(Readable::read(buffer)?))
 		},
+		msgs::PeerStorage::TYPE => {
+			Ok(Message::PeerStorage(Readable::read(buffer)?))
+		},
+		msgs::YourPeerStorage::TYPE => {
+			Ok(Message::YourPeerStorage(Readable::read(buffer)?))
+		},
 		msgs::OpenChannel::TYPE => {
 			Ok(Message::OpenChannel(Readable::read(buffer)?))
 		},
@@ -276,6 +297,15 @@ fn do_read<R: io::Read, T, H: core::ops::Deref>(buffer: &mut R, message_type: u1
 		msgs::Stfu::TYPE => {
 			Ok(Message::Stfu(Readable::read(buffer)?))
 		},
+		msgs::DynPropose::TYPE => {
+			Ok(Message::DynPropose(Readable::read(buffer)?))
+		},
+		msgs::DynAck::TYPE => {
+			Ok(Message::DynAck(Readable::read(buffer)?))
+		},
+		msgs::DynReject::TYPE => {
+			Ok(Message::DynReject(Readable::read(buffer)?))
+		},
 		msgs::SpliceAck::TYPE => {
 			Ok(Message::SpliceAck(Readable::read(buffer)?))
 		},
@@ -456,6 +486,15 @@ impl Encode for msgs::Pong {
 	const TYPE: u16 = 19;
 }
 
+// TODO(peer_storage): Double check with finalized spec
+impl Encode for msgs::PeerStorage {
+	const TYPE: u16 = 7;
+}
+
+impl Encode for msgs::YourPeerStorage {
+	const TYPE: u16 = 9;
+}
+
 impl Encode for msgs::OpenChannel {
 	const TYPE: u16 = 32;
 }
@@ -492,6 +531,19 @@ impl Encode for msgs::AcceptChannelV2 {
 	const TYPE: u16 = 65;
 }
 
+// TODO(dyn_commitments) Double check with finalized spec; these are the draft's odd message types
+impl Encode for msgs::DynPropose {
+	const TYPE: u16 = 111;
+}
+
+impl Encode for msgs::DynAck {
+	const TYPE: u16 = 113;
+}
+
+impl Encode for msgs::DynReject {
+	const TYPE: u16 = 115;
+}
+
 impl Encode for msgs::Splice {
 	// TODO(splicing) Double check with finalized spec; draft spec contains 74, which is probably wrong as it is used by tx_Abort; CLN uses 75
 	const TYPE: u16 = 75;
@@ -109,19 +109,20 @@ use core::convert::{AsRef, Infallible, TryFrom};
 use core::time::Duration;
 use crate::io;
 use crate::blinded_path::BlindedPath;
-use crate::ln::PaymentHash;
+use crate::ln::{PaymentHash, PaymentPreimage};
 use crate::ln::channelmanager::PaymentId;
 use crate::ln::features::{BlindedHopFeatures, Bolt12InvoiceFeatures, InvoiceRequestFeatures, OfferFeatures};
 use crate::ln::inbound_payment::ExpandedKey;
-use crate::ln::msgs::DecodeError;
+use crate::ln::msgs::{DecodeError, MAX_VALUE_MSAT};
 use crate::offers::invoice_request::{INVOICE_REQUEST_PAYER_ID_TYPE, INVOICE_REQUEST_TYPES, IV_BYTES as INVOICE_REQUEST_IV_BYTES, InvoiceRequest, InvoiceRequestContents, InvoiceRequestTlvStream, InvoiceRequestTlvStreamRef};
 use crate::offers::merkle::{SignError, SignatureTlvStream, SignatureTlvStreamRef, TaggedHash, TlvStream, WithoutSignatures, self};
 use crate::offers::offer::{Amount, OFFER_TYPES, OfferTlvStream, OfferTlvStreamRef, Quantity};
 use crate::offers::parse::{Bolt12ParseError, Bolt12SemanticError, ParsedMessage};
 use crate::offers::payer::{PAYER_METADATA_TYPE, PayerTlvStream, PayerTlvStreamRef};
+use crate::offers::payer_proof::PayerProofBuilder;
 use crate::offers::refund::{IV_BYTES as REFUND_IV_BYTES, Refund, RefundContents};
 use crate::offers::signer;
-use crate::util::ser::{HighZeroBytesDroppedBigSize, Iterable, SeekReadable, WithoutLength, Writeable, Writer};
+use crate::util::ser::{HighZeroBytesDroppedBigSize, Iterable, Readable, SeekReadable, WithoutLength, Writeable, Writer};
 use crate::util::string::PrintableString;
 
 use crate::prelude::*;
@@ -174,13 +175,14 @@ impl<'a> InvoiceBuilder<'a, ExplicitSigningPubkey> {
 		invoice_request: &'a InvoiceRequest, payment_paths: Vec<(BlindedPayInfo, BlindedPath)>,
 		created_at: Duration, payment_hash: PaymentHash
 	) -> Result<Self, Bolt12SemanticError> {
-		let amount_msats = Self::amount_msats(invoice_request)?;
+		let amount_msats = Self::amount_msats_or_unconverted(invoice_request)?;
 		let signing_pubkey = invoice_request.contents.inner.offer.signing_pubkey();
+		let mut fields = Self::fields(
+			payment_paths, created_at, payment_hash, amount_msats, signing_pubkey
+		);
+		fields.recurrence_basetime = Self::implied_recurrence_basetime(invoice_request, created_at);
 		let contents = InvoiceContents::ForOffer {
-			invoice_request: invoice_request.contents.clone(),
-			fields: Self::fields(
-				payment_paths, created_at, payment_hash, amount_msats, signing_pubkey
-			),
+			invoice_request: invoice_request.contents.clone(), fields,
 		};
 
 		Self::new(&invoice_request.bytes, contents, ExplicitSigningPubkey {})
@@ -207,13 +209,14 @@ impl<'a> InvoiceBuilder<'a, DerivedSigningPubkey> {
 		invoice_request: &'a InvoiceRequest, payment_paths: Vec<(BlindedPayInfo, BlindedPath)>,
 		created_at: Duration, payment_hash: PaymentHash, keys: KeyPair
 	) -> Result<Self, Bolt12SemanticError> {
-		let amount_msats = Self::amount_msats(invoice_request)?;
+		let amount_msats = Self::amount_msats_or_unconverted(invoice_request)?;
 		let signing_pubkey = invoice_request.contents.inner.offer.signing_pubkey();
+		let mut fields = Self::fields(
+			payment_paths, created_at, payment_hash, amount_msats, signing_pubkey
+		);
+		fields.recurrence_basetime = Self::implied_recurrence_basetime(invoice_request, created_at);
 		let contents = InvoiceContents::ForOffer {
-			invoice_request: invoice_request.contents.clone(),
-			fields: Self::fields(
-				payment_paths, created_at, payment_hash, amount_msats, signing_pubkey
-			),
+			invoice_request: invoice_request.contents.clone(), fields,
 		};
 
 		Self::new(&invoice_request.bytes, contents, DerivedSigningPubkey(keys))
@@ -253,6 +256,33 @@ impl<'a, S: SigningPubkeyStrategy> InvoiceBuilder<'a, S> {
 		}
 	}
 
+	// Like `amount_msats` but allows for an offer denominated in a currency other than bitcoin, in
+	// which case the amount must later be set using `InvoiceBuilder::converted_amount_msats`.
+	fn amount_msats_or_unconverted(
+		invoice_request: &InvoiceRequest
+	) -> Result<u64, Bolt12SemanticError> {
+		match Self::amount_msats(invoice_request) {
+			Err(Bolt12SemanticError::UnsupportedCurrency) => Ok(0),
+			result => result,
+		}
+	}
+
+	// The basetime for a recurring offer if it can be determined without the caller's help: either
+	// fixed by the offer or, when paying for the first period, the invoice creation time.
+	fn implied_recurrence_basetime(
+		invoice_request: &InvoiceRequest, created_at: Duration
+	) -> Option<Duration> {
+		let offer = &invoice_request.contents.inner.offer;
+		offer.recurrence()?;
+		match offer.recurrence_base() {
+			Some(base) => Some(Duration::from_secs(base.basetime)),
+			None if invoice_request.recurrence_counter() == Some(0) => {
+				Some(Duration::from_secs(created_at.as_secs()))
+			},
+			None => None,
+		}
+	}
+
 	fn fields(
 		payment_paths: Vec<(BlindedPayInfo, BlindedPath)>, created_at: Duration,
 		payment_hash: PaymentHash, amount_msats: u64, signing_pubkey: PublicKey
@@ -260,6 +290,7 @@ impl<'a, S: SigningPubkeyStrategy> InvoiceBuilder<'a, S> {
 		InvoiceFields {
 			payment_paths, created_at, relative_expiry: None, payment_hash, amount_msats,
 			fallbacks: None, features: Bolt12InvoiceFeatures::empty(), signing_pubkey,
+			recurrence_basetime: None,
 		}
 	}
 
@@ -329,6 +360,131 @@ impl<'a, S: SigningPubkeyStrategy> InvoiceBuilder<'a, S> {
 		self.invoice.fields_mut().features.set_basic_mpp_optional();
 		self
 	}
+
+	/// Sets [`Bolt12Invoice::amount_msats`] when responding to an [`InvoiceRequest`] for an
+	/// [`Offer`] denominated in a currency other than bitcoin. The amount should be the offer's
+	/// amount for the requested quantity converted at the current exchange rate, such as by using
+	/// [`Amount::to_msats`].
+	///
+	/// Must be called for such offers unless the [`InvoiceRequest`] specifies an amount, in which
+	/// case the invoice uses that amount.
+	///
+	/// Errors if the invoice is not for such an offer, if the [`InvoiceRequest`] specifies an
+	/// amount, or if the amount exceeds the total bitcoin supply.
+	///
+	/// [`Offer`]: crate::offers::offer::Offer
+	pub fn converted_amount_msats(mut self, amount_msats: u64) -> Result<Self, Bolt12SemanticError> {
+		match &self.invoice {
+			InvoiceContents::ForOffer { invoice_request, .. } => {
+				match invoice_request.inner.offer.amount() {
+					Some(Amount::Currency { .. }) => {},
+					_ => return Err(Bolt12SemanticError::UnexpectedAmount),
+				}
+				if invoice_request.amount_msats().is_some() {
+					return Err(Bolt12SemanticError::UnexpectedAmount);
+				}
+			},
+			InvoiceContents::ForRefund { .. } => return Err(Bolt12SemanticError::UnexpectedAmount),
+		}
+
+		if amount_msats > MAX_VALUE_MSAT {
+			return Err(Bolt12SemanticError::InvalidAmount);
+		}
+
+		self.invoice.fields_mut().amount_msats = amount_msats;
+		Ok(self)
+	}
+
+	/// Sets a reduced [`Bolt12Invoice::amount_msats`] when responding to an [`InvoiceRequest`] for
+	/// a period of a recurring [`Offer`] which has already started, as computed using
+	/// [`Recurrence::proportional_amount_msats`].
+	///
+	/// Errors if the offer's [`RecurrencePaywindow::proportional_amount`] isn't set, if the
+	/// [`InvoiceRequest`] specifies an amount, or if the amount exceeds the one that would otherwise
+	/// be invoiced, including any set using [`InvoiceBuilder::converted_amount_msats`].
+	///
+	/// [`Offer`]: crate::offers::offer::Offer
+	/// [`Recurrence::proportional_amount_msats`]: crate::offers::recurrence::Recurrence::proportional_amount_msats
+	/// [`RecurrencePaywindow::proportional_amount`]: crate::offers::recurrence::RecurrencePaywindow::proportional_amount
+	pub fn proportional_amount_msats(
+		mut self, amount_msats: u64
+	) -> Result<Self, Bolt12SemanticError> {
+		match &self.invoice {
+			InvoiceContents::ForOffer { invoice_request, .. } => {
+				let offer = &invoice_request.inner.offer;
+				if !offer.recurrence_paywindow().map_or(false, |paywindow| paywindow.proportional_amount) {
+					return Err(Bolt12SemanticError::UnexpectedAmount);
+				}
+				if invoice_request.amount_msats().is_some() {
+					return Err(Bolt12SemanticError::UnexpectedAmount);
+				}
+			},
+			InvoiceContents::ForRefund { .. } => return Err(Bolt12SemanticError::UnexpectedAmount),
+		}
+
+		if amount_msats > self.invoice.fields().amount_msats {
+			return Err(Bolt12SemanticError::InvalidAmount);
+		}
+
+		self.invoice.fields_mut().amount_msats = amount_msats;
+		Ok(self)
+	}
+
+	/// Sets [`Bolt12Invoice::recurrence_basetime`] when responding to an [`InvoiceRequest`] for a
+	/// period other than the first of a recurring [`Offer`] without a [`RecurrenceBase`]. The
+	/// basetime should be the [`Bolt12Invoice::created_at`] of the invoice for the first period.
+	///
+	/// Errors if the invoice is not for a recurring offer or if the basetime differs from the one
+	/// given by the offer's [`RecurrenceBase`].
+	///
+	/// [`Offer`]: crate::offers::offer::Offer
+	/// [`RecurrenceBase`]: crate::offers::recurrence::RecurrenceBase
+	pub fn recurrence_basetime(mut self, basetime: Duration) -> Result<Self, Bolt12SemanticError> {
+		let basetime = Duration::from_secs(basetime.as_secs());
+		match &self.invoice {
+			InvoiceContents::ForOffer { invoice_request, .. } => {
+				let offer = &invoice_request.inner.offer;
+				if offer.recurrence().is_none() {
+					return Err(Bolt12SemanticError::UnexpectedRecurrence);
+				}
+				if let Some(base) = offer.recurrence_base() {
+					if base.basetime != basetime.as_secs() {
+						return Err(Bolt12SemanticError::InvalidRecurrenceBasetime);
+					}
+				}
+			},
+			InvoiceContents::ForRefund { .. } => {
+				return Err(Bolt12SemanticError::UnexpectedRecurrence);
+			},
+		}
+
+		self.invoice.fields_mut().recurrence_basetime = Some(basetime);
+		Ok(self)
+	}
+
+	fn check_recurrence(&self) -> Result<(), Bolt12SemanticError> {
+		if let InvoiceContents::ForOffer { invoice_request, fields } = &self.invoice {
+			if invoice_request.inner.offer.recurrence().is_some() &&
+				fields.recurrence_basetime.is_none()
+			{
+				return Err(Bolt12SemanticError::MissingRecurrenceBasetime);
+			}
+		}
+
+		Ok(())
+	}
+
+	fn check_amount(&self) -> Result<(), Bolt12SemanticError> {
+		if let InvoiceContents::ForOffer { invoice_request, fields } = &self.invoice {
+			if let Some(Amount::Currency { .. }) = invoice_request.inner.offer.amount() {
+				if invoice_request.amount_msats().is_none() && fields.amount_msats == 0 {
+					return Err(Bolt12SemanticError::MissingAmount);
+				}
+			}
+		}
+
+		Ok(())
+	}
 }
 
 impl<'a> InvoiceBuilder<'a, ExplicitSigningPubkey> {
@@ -347,6 +503,9 @@ impl<'a> InvoiceBuilder<'a, ExplicitSigningPubkey> {
 			}
 		}
 
+		self.check_amount()?;
+		self.check_recurrence()?;
+
 		let InvoiceBuilder { invreq_bytes, invoice, .. } = self;
 		Ok(UnsignedBolt12Invoice::new(invreq_bytes, invoice))
 	}
@@ -369,6 +528,9 @@ impl<'a> InvoiceBuilder<'a, DerivedSigningPubkey> {
 			}
 		}
 
+		self.check_amount()?;
+		self.check_recurrence()?;
+
 		let InvoiceBuilder {
 			invreq_bytes, invoice, signing_pubkey_strategy: DerivedSigningPubkey(keys)
 		} = self;
@@ -459,9 +621,8 @@ impl AsRef<TaggedHash> for UnsignedBolt12Invoice {
 /// [`Refund`]: crate::offers::refund::Refund
 /// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
 #[derive(Clone, Debug)]
-#[cfg_attr(test, derive(PartialEq))]
 pub struct Bolt12Invoice {
-	bytes: Vec<u8>,
+	pub(super) bytes: Vec<u8>,
 	contents: InvoiceContents,
 	signature: Signature,
 	tagged_hash: TaggedHash,
@@ -501,6 +662,7 @@ struct InvoiceFields {
 	fallbacks: Option<Vec<FallbackAddress>>,
 	features: Bolt12InvoiceFeatures,
 	signing_pubkey: PublicKey,
+	recurrence_basetime: Option<Duration>,
 }
 
 macro_rules! invoice_accessors { ($self: ident, $contents: expr) => {
@@ -693,6 +855,29 @@ macro_rules! invoice_accessors { ($self: ident, $contents: expr) => {
 	pub fn signing_pubkey(&$self) -> PublicKey {
 		$contents.signing_pubkey()
 	}
+
+	/// The number of periods paid for a recurring offer since the first requested one.
+	///
+	/// From [`InvoiceRequest::recurrence_counter`]; `None` if the invoice was created in response
+	/// to a [`Refund`] or to an [`InvoiceRequest`] for an offer without recurrence.
+	pub fn recurrence_counter(&$self) -> Option<u32> {
+		$contents.recurrence_counter()
+	}
+
+	/// The period of a recurring offer that the payer started paying at.
+	///
+	/// From [`InvoiceRequest::recurrence_start`].
+	pub fn recurrence_start(&$self) -> Option<u32> {
+		$contents.recurrence_start()
+	}
+
+	/// Duration since the Unix epoch when the first period of a recurring offer began, from which
+	/// the start of the period being paid can be computed using [`Recurrence::period_start`].
+	///
+	/// [`Recurrence::period_start`]: crate::offers::recurrence::Recurrence::period_start
+	pub fn recurrence_basetime(&$self) -> Option<Duration> {
+		$contents.recurrence_basetime()
+	}
 } }
 
 impl UnsignedBolt12Invoice {
@@ -712,11 +897,30 @@ impl Bolt12Invoice {
 		self.tagged_hash.as_digest().as_ref().clone()
 	}
 
+	/// Creates a [`PayerProofBuilder`] for proving that the invoice was paid, which must be signed
+	/// by the payer using the keys for [`Bolt12Invoice::payer_id`].
+	///
+	/// Errors if `preimage` does not correspond to [`Bolt12Invoice::payment_hash`].
+	pub fn payer_proof_builder(
+		&self, preimage: PaymentPreimage
+	) -> Result<PayerProofBuilder, Bolt12SemanticError> {
+		PayerProofBuilder::new(self, preimage)
+	}
+
 	/// Verifies that the invoice was for a request or refund created using the given key. Returns
 	/// the associated [`PaymentId`] to use when sending the payment.
 	pub fn verify<T: secp256k1::Signing>(
 		&self, key: &ExpandedKey, secp_ctx: &Secp256k1<T>
 	) -> Result<PaymentId, ()> {
+		self.contents.verify(TlvStream::new(&self.bytes), key, secp_ctx)
+			.map(|(payment_id, _)| payment_id)
+	}
+
+	/// Verifies the invoice as in [`Bolt12Invoice::verify`], additionally returning the payer keys
+	/// for [`Bolt12Invoice::payer_id`] if they were derived from the payer metadata.
+	pub(crate) fn verify_and_derive_payer_keys<T: secp256k1::Signing>(
+		&self, key: &ExpandedKey, secp_ctx: &Secp256k1<T>
+	) -> Result<(PaymentId, Option<KeyPair>), ()> {
 		self.contents.verify(TlvStream::new(&self.bytes), key, secp_ctx)
 	}
 
@@ -905,37 +1109,7 @@ impl InvoiceContents {
 	}
 
 	fn fallbacks(&self) -> Vec<Address> {
-		let chain = self.chain();
-		let network = if chain == ChainHash::using_genesis_block(Network::Bitcoin) {
-			Network::Bitcoin
-		} else if chain == ChainHash::using_genesis_block(Network::Testnet) {
-			Network::Testnet
-		} else if chain == ChainHash::using_genesis_block(Network::Signet) {
-			Network::Signet
-		} else if chain == ChainHash::using_genesis_block(Network::Regtest) {
-			Network::Regtest
-		} else {
-			return Vec::new()
-		};
-
-		let to_valid_address = |address: &FallbackAddress| {
-			let version = match WitnessVersion::try_from(address.version) {
-				Ok(version) => version,
-				Err(_) => return None,
-			};
-
-			let program = &address.program;
-			let witness_program = match WitnessProgram::new(version, program.clone()) {
-				Ok(witness_program) => witness_program,
-				Err(_) => return None,
-			};
-			Some(Address::new(network, Payload::WitnessProgram(witness_program)))
-		};
-
-		self.fields().fallbacks
-			.as_ref()
-			.map(|fallbacks| fallbacks.iter().filter_map(to_valid_address).collect())
-			.unwrap_or_else(Vec::new)
+		filter_fallbacks(self.chain(), self.fields().fallbacks.as_ref())
 	}
 
 	fn features(&self) -> &Bolt12InvoiceFeatures {
@@ -946,6 +1120,24 @@ impl InvoiceContents {
 		self.fields().signing_pubkey
 	}
 
+	fn recurrence_counter(&self) -> Option<u32> {
+		match self {
+			InvoiceContents::ForOffer { invoice_request, .. } => invoice_request.recurrence_counter(),
+			InvoiceContents::ForRefund { .. } => None,
+		}
+	}
+
+	fn recurrence_start(&self) -> Option<u32> {
+		match self {
+			InvoiceContents::ForOffer { invoice_request, .. } => invoice_request.recurrence_start(),
+			InvoiceContents::ForRefund { .. } => None,
+		}
+	}
+
+	fn recurrence_basetime(&self) -> Option<Duration> {
+		self.fields().recurrence_basetime
+	}
+
 	fn fields(&self) -> &InvoiceFields {
 		match self {
 			InvoiceContents::ForOffer { fields, .. } => fields,
@@ -962,7 +1154,7 @@ impl InvoiceContents {
 
 	fn verify<T: secp256k1::Signing>(
 		&self, tlv_stream: TlvStream<'_>, key: &ExpandedKey, secp_ctx: &Secp256k1<T>
-	) -> Result<PaymentId, ()> {
+	) -> Result<(PaymentId, Option<KeyPair>), ()> {
 		let offer_records = tlv_stream.clone().range(OFFER_TYPES);
 		let invreq_records = tlv_stream.range(INVOICE_REQUEST_TYPES).filter(|record| {
 			match record.r#type {
@@ -1020,10 +1212,48 @@ impl InvoiceFields {
 			fallbacks: self.fallbacks.as_ref(),
 			features,
 			node_id: Some(&self.signing_pubkey),
+			recurrence_basetime: self.recurrence_basetime.map(|duration| duration.as_secs()),
+			message_paths: None,
 		}
 	}
 }
 
+/// Converts the given [`FallbackAddress`]es into [`Address`]es for `chain`, skipping any that are
+/// invalid or for an unknown chain.
+pub(super) fn filter_fallbacks(
+	chain: ChainHash, fallbacks: Option<&Vec<FallbackAddress>>
+) -> Vec<Address> {
+	let network = if chain == ChainHash::using_genesis_block(Network::Bitcoin) {
+		Network::Bitcoin
+	} else if chain == ChainHash::using_genesis_block(Network::Testnet) {
+		Network::Testnet
+	} else if chain == ChainHash::using_genesis_block(Network::Signet) {
+		Network::Signet
+	} else if chain == ChainHash::using_genesis_block(Network::Regtest) {
+		Network::Regtest
+	} else {
+		return Vec::new()
+	};
+
+	let to_valid_address = |address: &FallbackAddress| {
+		let version = match WitnessVersion::try_from(address.version) {
+			Ok(version) => version,
+			Err(_) => return None,
+		};
+
+		let program = &address.program;
+		let witness_program = match WitnessProgram::new(version, program.clone()) {
+			Ok(witness_program) => witness_program,
+			Err(_) => return None,
+		};
+		Some(Address::new(network, Payload::WitnessProgram(witness_program)))
+	};
+
+	fallbacks
+		.map(|fallbacks| fallbacks.iter().filter_map(to_valid_address).collect())
+		.unwrap_or_else(Vec::new)
+}
+
 impl Writeable for UnsignedBolt12Invoice {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
 		WithoutLength(&self.bytes).write(writer)
@@ -1036,6 +1266,21 @@ impl Writeable for Bolt12Invoice {
 	}
 }
 
+impl PartialEq for Bolt12Invoice {
+	fn eq(&self, other: &Self) -> bool {
+		self.bytes.eq(&other.bytes)
+	}
+}
+
+impl Eq for Bolt12Invoice {}
+
+impl Readable for Bolt12Invoice {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		let bytes: WithoutLength<Vec<u8>> = Readable::read(reader)?;
+		Self::try_from(bytes.0).map_err(|_| DecodeError::InvalidValue)
+	}
+}
+
 impl Writeable for InvoiceContents {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
 		self.as_tlv_stream().write(writer)
@@ -1080,6 +1325,9 @@ tlv_stream!(InvoiceTlvStream, InvoiceTlvStreamRef, 160..240, {
 	(172, fallbacks: (Vec<FallbackAddress>, WithoutLength)),
 	(174, features: (Bolt12InvoiceFeatures, WithoutLength)),
 	(176, node_id: PublicKey),
+	(178, recurrence_basetime: (u64, HighZeroBytesDroppedBigSize)),
+	// Only present in `StaticInvoice`s.
+	(238, message_paths: (Vec<BlindedPath>, WithoutLength)),
 });
 
 type BlindedPathIter<'a> = core::iter::Map<
@@ -1133,8 +1381,8 @@ impl_writeable!(BlindedPayInfo, {
 /// Wire representation for an on-chain fallback address.
 #[derive(Clone, Debug, PartialEq)]
 pub(super) struct FallbackAddress {
-	version: u8,
-	program: Vec<u8>,
+	pub(super) version: u8,
+	pub(super) program: Vec<u8>,
 }
 
 impl_writeable!(FallbackAddress, { version, program });
@@ -1218,10 +1466,12 @@ impl TryFrom<PartialInvoiceTlvStream> for InvoiceContents {
 			invoice_request_tlv_stream,
 			InvoiceTlvStream {
 				paths, blindedpay, created_at, relative_expiry, payment_hash, amount, fallbacks,
-				features, node_id,
+				features, node_id, recurrence_basetime, message_paths,
 			},
 		) = tlv_stream;
 
+		if message_paths.is_some() { return Err(Bolt12SemanticError::UnexpectedPaths) }
+
 		let payment_paths = match (blindedpay, paths) {
 			(_, None) => return Err(Bolt12SemanticError::MissingPaths),
 			(None, _) => return Err(Bolt12SemanticError::InvalidPayInfo),
@@ -1260,9 +1510,11 @@ impl TryFrom<PartialInvoiceTlvStream> for InvoiceContents {
 			Some(node_id) => node_id,
 		};
 
+		let recurrence_basetime = recurrence_basetime.map(Duration::from_secs);
+
 		let fields = InvoiceFields {
 			payment_paths, created_at, relative_expiry, payment_hash, amount_msats, fallbacks,
-			features, signing_pubkey,
+			features, signing_pubkey, recurrence_basetime,
 		};
 
 		match offer_tlv_stream.node_id {
@@ -1274,9 +1526,28 @@ impl TryFrom<PartialInvoiceTlvStream> for InvoiceContents {
 				let invoice_request = InvoiceRequestContents::try_from(
 					(payer_tlv_stream, offer_tlv_stream, invoice_request_tlv_stream)
 				)?;
+
+				let offer = &invoice_request.inner.offer;
+				match (offer.recurrence(), fields.recurrence_basetime) {
+					(None, None) => {},
+					(None, Some(_)) => return Err(Bolt12SemanticError::UnexpectedRecurrence),
+					(Some(_), None) => return Err(Bolt12SemanticError::MissingRecurrenceBasetime),
+					(Some(_), Some(basetime)) => {
+						if let Some(base) = offer.recurrence_base() {
+							if base.basetime != basetime.as_secs() {
+								return Err(Bolt12SemanticError::InvalidRecurrenceBasetime);
+							}
+						}
+					},
+				}
+
 				Ok(InvoiceContents::ForOffer { invoice_request, fields })
 			},
 			None => {
+				if fields.recurrence_basetime.is_some() {
+					return Err(Bolt12SemanticError::UnexpectedRecurrence);
+				}
+
 				let refund = RefundContents::try_from(
 					(payer_tlv_stream, offer_tlv_stream, invoice_request_tlv_stream)
 				)?;
@@ -1309,6 +1580,7 @@ mod tests {
 	use crate::offers::offer::{Amount, OfferBuilder, OfferTlvStreamRef, Quantity};
 	use crate::offers::parse::{Bolt12ParseError, Bolt12SemanticError};
 	use crate::offers::payer::PayerTlvStreamRef;
+	use crate::offers::recurrence::{Recurrence, RecurrenceBase, RecurrencePaywindow, RecurrenceTimeUnit};
 	use crate::offers::refund::RefundBuilder;
 	use crate::offers::test_utils::*;
 	use crate::util::ser::{BigSize, Iterable, Writeable};
@@ -1442,6 +1714,10 @@ mod tests {
 					issuer: None,
 					quantity_max: None,
 					node_id: Some(&recipient_pubkey()),
+					recurrence: None,
+					recurrence_base: None,
+					recurrence_paywindow: None,
+					recurrence_limit: None,
 				},
 				InvoiceRequestTlvStreamRef {
 					chain: None,
@@ -1450,6 +1726,8 @@ mod tests {
 					quantity: None,
 					payer_id: Some(&payer_pubkey()),
 					payer_note: None,
+					recurrence_counter: None,
+					recurrence_start: None,
 				},
 				InvoiceTlvStreamRef {
 					paths: Some(Iterable(payment_paths.iter().map(|(_, path)| path))),
@@ -1461,6 +1739,8 @@ mod tests {
 					fallbacks: None,
 					features: None,
 					node_id: Some(&recipient_pubkey()),
+					recurrence_basetime: None,
+					message_paths: None,
 				},
 				SignatureTlvStreamRef { signature: Some(&invoice.signature()) },
 			),
@@ -1534,6 +1814,10 @@ mod tests {
 					issuer: None,
 					quantity_max: None,
 					node_id: None,
+					recurrence: None,
+					recurrence_base: None,
+					recurrence_paywindow: None,
+					recurrence_limit: None,
 				},
 				InvoiceRequestTlvStreamRef {
 					chain: None,
@@ -1542,6 +1826,8 @@ mod tests {
 					quantity: None,
 					payer_id: Some(&payer_pubkey()),
 					payer_note: None,
+					recurrence_counter: None,
+					recurrence_start: None,
 				},
 				InvoiceTlvStreamRef {
 					paths: Some(Iterable(payment_paths.iter().map(|(_, path)| path))),
@@ -1553,6 +1839,8 @@ mod tests {
 					fallbacks: None,
 					features: None,
 					node_id: Some(&recipient_pubkey()),
+					recurrence_basetime: None,
+					message_paths: None,
 				},
 				SignatureTlvStreamRef { signature: Some(&invoice.signature()) },
 			),
@@ -1790,6 +2078,196 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn builds_invoice_with_converted_amount() {
+		let currency_amount = Amount::Currency { iso4217_code: *b"USD", amount: 10 };
+		let invoice_request = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount(currency_amount.clone())
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+
+		match invoice_request.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.build()
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::MissingAmount),
+		}
+
+		let invoice = invoice_request
+			.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.converted_amount_msats(250_000).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+		let (_, _, _, tlv_stream, _) = invoice.as_tlv_stream();
+		assert_eq!(invoice.amount(), Some(&currency_amount));
+		assert_eq!(invoice.amount_msats(), 250_000);
+		assert_eq!(tlv_stream.amount, Some(250_000));
+
+		// An amount given in the request is used as is.
+		let invoice_request = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount(currency_amount.clone())
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.amount_msats(300_000).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+
+		match invoice_request.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.converted_amount_msats(250_000)
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::UnexpectedAmount),
+		}
+
+		let invoice = invoice_request
+			.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+		assert_eq!(invoice.amount_msats(), 300_000);
+
+		match OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.converted_amount_msats(1000)
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::UnexpectedAmount),
+		}
+	}
+
+	#[test]
+	fn builds_invoice_with_recurrence_basetime() {
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 30 };
+		let offer = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.recurrence(recurrence)
+			.build().unwrap();
+		let now = now();
+		let basetime = Duration::from_secs(now.as_secs());
+
+		// The first invoice establishes the basetime.
+		let invoice = offer
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(0)
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash(), now).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+		let (_, _, _, tlv_stream, _) = invoice.as_tlv_stream();
+		assert_eq!(invoice.recurrence_counter(), Some(0));
+		assert_eq!(invoice.recurrence_basetime(), Some(basetime));
+		assert_eq!(tlv_stream.recurrence_basetime, Some(now.as_secs()));
+
+		// Later invoices must be given the basetime of the first one.
+		let invoice_request = offer
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(1)
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+		let created_at = now + Duration::from_secs(30 * 24 * 60 * 60);
+
+		match invoice_request.respond_with_no_std(payment_paths(), payment_hash(), created_at)
+			.unwrap()
+			.build()
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::MissingRecurrenceBasetime),
+		}
+
+		let invoice = invoice_request
+			.respond_with_no_std(payment_paths(), payment_hash(), created_at).unwrap()
+			.recurrence_basetime(basetime).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+		assert_eq!(invoice.recurrence_counter(), Some(1));
+		assert_eq!(invoice.recurrence_basetime(), Some(basetime));
+
+		// A basetime fixed by the offer is always used.
+		let offer_basetime = now.as_secs() - 3600;
+		let invoice = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.recurrence(recurrence)
+			.recurrence_base(RecurrenceBase { start_any_period: false, basetime: offer_basetime })
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(2)
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash(), now).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+		assert_eq!(invoice.recurrence_basetime(), Some(Duration::from_secs(offer_basetime)));
+
+		match OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash(), now).unwrap()
+			.recurrence_basetime(now)
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::UnexpectedRecurrence),
+		}
+	}
+
+	#[test]
+	fn builds_invoice_with_proportional_amount() {
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 30 };
+		let paywindow = RecurrencePaywindow {
+			seconds_before: 0, proportional_amount: true, seconds_after: 30 * 24 * 60 * 60,
+		};
+		let invoice_request = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.recurrence(recurrence)
+			.recurrence_paywindow(paywindow)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(0)
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+
+		let invoice = invoice_request
+			.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.proportional_amount_msats(250).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+		let (_, _, _, tlv_stream, _) = invoice.as_tlv_stream();
+		assert_eq!(invoice.amount_msats(), 250);
+		assert_eq!(tlv_stream.amount, Some(250));
+
+		match invoice_request.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.proportional_amount_msats(1001)
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::InvalidAmount),
+		}
+
+		// The amount can't be reduced without the offer allowing it.
+		match OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.recurrence(recurrence)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(0)
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.proportional_amount_msats(250)
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::UnexpectedAmount),
+		}
+	}
+
 	#[test]
 	fn builds_invoice_with_fallback_address() {
 		let script = ScriptBuf::new();
@@ -1995,6 +2473,63 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn parses_invoice_with_recurrence_basetime() {
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Months, period: 1 };
+		let now = now;
+		let invoice = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.recurrence(recurrence)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(0)
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash(), now).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+
+		let mut buffer = Vec::new();
+		invoice.write(&mut buffer).unwrap();
+
+		match Bolt12Invoice::try_from(buffer) {
+			Ok(invoice) => {
+				assert_eq!(invoice.recurrence_basetime(), Some(Duration::from_secs(now.as_secs())));
+			},
+			Err(e) => panic!("error parsing invoice: {:?}", e),
+		}
+
+		let mut tlv_stream = invoice.as_tlv_stream();
+		tlv_stream.3.recurrence_basetime = None;
+
+		match Bolt12Invoice::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => {
+				assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::MissingRecurrenceBasetime));
+			},
+		}
+
+		let invoice = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash(), now).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+
+		let mut tlv_stream = invoice.as_tlv_stream();
+		tlv_stream.3.recurrence_basetime = Some(now.as_secs());
+
+		match Bolt12Invoice::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => {
+				assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::UnexpectedRecurrence));
+			},
+		}
+	}
+
 	#[test]
 	fn parses_invoice_with_payment_hash() {
 		let invoice = OfferBuilder::new("foo".into(), recipient_pubkey())
@@ -2173,6 +2708,28 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn fails_parsing_invoice_with_message_paths() {
+		let invoice = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+
+		let message_paths = vec![payment_paths()[0].1.clone()];
+		let mut tlv_stream = invoice.as_tlv_stream();
+		tlv_stream.3.message_paths = Some(&message_paths);
+
+		match Bolt12Invoice::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::UnexpectedPaths)),
+		}
+	}
+
 	#[test]
 	fn fails_parsing_invoice_without_signature() {
 		let mut buffer = Vec::new();
@@ -171,6 +171,7 @@ impl<'a, 'b, P: PayerIdStrategy, T: secp256k1::Signing> InvoiceRequestBuilder<'a
 		InvoiceRequestContentsWithoutPayerId {
 			payer: PayerContents(metadata), offer, chain: None, amount_msats: None,
 			features: InvoiceRequestFeatures::empty(), quantity: None, payer_note: None,
+			recurrence_counter: None, recurrence_start: None,
 		}
 	}
 
@@ -229,6 +230,26 @@ impl<'a, 'b, P: PayerIdStrategy, T: secp256k1::Signing> InvoiceRequestBuilder<'a
 		self
 	}
 
+	/// Sets the [`InvoiceRequest::recurrence_counter`]. Must be called if [`Offer::recurrence`] is
+	/// set, starting at `0` for the first period paid and incrementing for each successive period.
+	///
+	/// Successive calls to this method will override the previous setting.
+	pub fn recurrence_counter(mut self, recurrence_counter: u32) -> Self {
+		self.invoice_request.recurrence_counter = Some(recurrence_counter);
+		self
+	}
+
+	/// Sets the [`InvoiceRequest::recurrence_start`]. Must be called if the offer's
+	/// [`RecurrenceBase::start_any_period`] is set.
+	///
+	/// Successive calls to this method will override the previous setting.
+	///
+	/// [`RecurrenceBase::start_any_period`]: crate::offers::recurrence::RecurrenceBase::start_any_period
+	pub fn recurrence_start(mut self, recurrence_start: u32) -> Self {
+		self.invoice_request.recurrence_start = Some(recurrence_start);
+		self
+	}
+
 	fn build_with_checks(mut self) -> Result<
 		(UnsignedInvoiceRequest, Option<KeyPair>, Option<&'b Secp256k1<T>>),
 		Bolt12SemanticError
@@ -256,6 +277,9 @@ impl<'a, 'b, P: PayerIdStrategy, T: secp256k1::Signing> InvoiceRequestBuilder<'a
 		self.invoice_request.offer.check_amount_msats_for_quantity(
 			self.invoice_request.amount_msats, self.invoice_request.quantity
 		)?;
+		self.invoice_request.offer.check_recurrence_counter(
+			self.invoice_request.recurrence_counter, self.invoice_request.recurrence_start
+		)?;
 
 		Ok(self.build_without_checks())
 	}
@@ -475,6 +499,8 @@ pub(super) struct InvoiceRequestContentsWithoutPayerId {
 	features: InvoiceRequestFeatures,
 	quantity: Option<u64>,
 	payer_note: Option<String>,
+	recurrence_counter: Option<u32>,
+	recurrence_start: Option<u32>,
 }
 
 macro_rules! invoice_request_accessors { ($self: ident, $contents: expr) => {
@@ -519,6 +545,24 @@ macro_rules! invoice_request_accessors { ($self: ident, $contents: expr) => {
 	pub fn payer_note(&$self) -> Option<PrintableString> {
 		$contents.payer_note()
 	}
+
+	/// The number of the period being requested relative to the first period paid using
+	/// [`payer_id`], set only for offers with a [`Offer::recurrence`].
+	///
+	/// [`payer_id`]: Self::payer_id
+	pub fn recurrence_counter(&$self) -> Option<u32> {
+		$contents.recurrence_counter()
+	}
+
+	/// The index of the first period paid, set only for offers whose
+	/// [`RecurrenceBase::start_any_period`] is set. The period requested is thus
+	/// [`recurrence_counter`] periods after it.
+	///
+	/// [`RecurrenceBase::start_any_period`]: crate::offers::recurrence::RecurrenceBase::start_any_period
+	/// [`recurrence_counter`]: Self::recurrence_counter
+	pub fn recurrence_start(&$self) -> Option<u32> {
+		$contents.recurrence_start()
+	}
 } }
 
 impl UnsignedInvoiceRequest {
@@ -731,6 +775,20 @@ impl InvoiceRequestContents {
 			.map(|payer_note| PrintableString(payer_note.as_str()))
 	}
 
+	pub(super) fn recurrence_counter(&self) -> Option<u32> {
+		self.inner.recurrence_counter
+	}
+
+	pub(super) fn recurrence_start(&self) -> Option<u32> {
+		self.inner.recurrence_start
+	}
+
+	/// The index of the requested period of a recurring offer.
+	pub(super) fn recurrence_period_index(&self) -> Option<u32> {
+		self.inner.recurrence_counter
+			.map(|counter| self.inner.recurrence_start.unwrap_or(0).saturating_add(counter))
+	}
+
 	pub(super) fn as_tlv_stream(&self) -> PartialInvoiceRequestTlvStreamRef {
 		let (payer, offer, mut invoice_request) = self.inner.as_tlv_stream();
 		invoice_request.payer_id = Some(&self.payer_id);
@@ -766,6 +824,8 @@ impl InvoiceRequestContentsWithoutPayerId {
 			quantity: self.quantity,
 			payer_id: None,
 			payer_note: self.payer_note.as_ref(),
+			recurrence_counter: self.recurrence_counter,
+			recurrence_start: self.recurrence_start,
 		};
 
 		(payer, offer, invoice_request)
@@ -805,6 +865,8 @@ tlv_stream!(InvoiceRequestTlvStream, InvoiceRequestTlvStreamRef, INVOICE_REQUEST
 	(86, quantity: (u64, HighZeroBytesDroppedBigSize)),
 	(INVOICE_REQUEST_PAYER_ID_TYPE, payer_id: PublicKey),
 	(89, payer_note: (String, WithoutLength)),
+	(92, recurrence_counter: (u32, HighZeroBytesDroppedBigSize)),
+	(94, recurrence_start: (u32, HighZeroBytesDroppedBigSize)),
 });
 
 type FullInvoiceRequestTlvStream =
@@ -887,7 +949,10 @@ impl TryFrom<PartialInvoiceRequestTlvStream> for InvoiceRequestContents {
 		let (
 			PayerTlvStream { metadata },
 			offer_tlv_stream,
-			InvoiceRequestTlvStream { chain, amount, features, quantity, payer_id, payer_note },
+			InvoiceRequestTlvStream {
+				chain, amount, features, quantity, payer_id, payer_note, recurrence_counter,
+				recurrence_start,
+			},
 		) = tlv_stream;
 
 		let payer = match metadata {
@@ -906,6 +971,7 @@ impl TryFrom<PartialInvoiceRequestTlvStream> for InvoiceRequestContents {
 
 		offer.check_quantity(quantity)?;
 		offer.check_amount_msats_for_quantity(amount, quantity)?;
+		offer.check_recurrence_counter(recurrence_counter, recurrence_start)?;
 
 		let features = features.unwrap_or_else(InvoiceRequestFeatures::empty);
 
@@ -917,6 +983,7 @@ impl TryFrom<PartialInvoiceRequestTlvStream> for InvoiceRequestContents {
 		Ok(InvoiceRequestContents {
 			inner: InvoiceRequestContentsWithoutPayerId {
 				payer, offer, chain, amount_msats: amount, features, quantity, payer_note,
+				recurrence_counter, recurrence_start,
 			},
 			payer_id,
 		})
@@ -944,6 +1011,7 @@ mod tests {
 	use crate::offers::offer::{Amount, OfferBuilder, OfferTlvStreamRef, Quantity};
 	use crate::offers::parse::{Bolt12ParseError, Bolt12SemanticError};
 	use crate::offers::payer::PayerTlvStreamRef;
+	use crate::offers::recurrence::{Recurrence, RecurrenceBase, RecurrenceTimeUnit};
 	use crate::offers::test_utils::*;
 	use crate::util::ser::{BigSize, Writeable};
 	use crate::util::string::PrintableString;
@@ -1029,6 +1097,10 @@ mod tests {
 					issuer: None,
 					quantity_max: None,
 					node_id: Some(&recipient_pubkey()),
+					recurrence: None,
+					recurrence_base: None,
+					recurrence_paywindow: None,
+					recurrence_limit: None,
 				},
 				InvoiceRequestTlvStreamRef {
 					chain: None,
@@ -1037,6 +1109,8 @@ mod tests {
 					quantity: None,
 					payer_id: Some(&payer_pubkey()),
 					payer_note: None,
+					recurrence_counter: None,
+					recurrence_start: None,
 				},
 				SignatureTlvStreamRef { signature: Some(&invoice_request.signature()) },
 			),
@@ -1543,6 +1617,99 @@ mod tests {
 		assert_eq!(tlv_stream.payer_note, Some(&String::from("baz")));
 	}
 
+	#[test]
+	fn builds_invoice_request_with_recurrence() {
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 7 };
+		let offer = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.recurrence(recurrence)
+			.recurrence_limit(4)
+			.build().unwrap();
+
+		let invoice_request = offer
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(4)
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+		let (_, _, tlv_stream, _) = invoice_request.as_tlv_stream();
+		assert_eq!(invoice_request.recurrence_counter(), Some(4));
+		assert_eq!(invoice_request.recurrence_start(), None);
+		assert_eq!(tlv_stream.recurrence_counter, Some(4));
+		assert_eq!(tlv_stream.recurrence_start, None);
+
+		match offer.request_invoice(vec![1; 32], payer_pubkey()).unwrap().build() {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::MissingRecurrenceCounter),
+		}
+
+		match offer
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(5)
+			.build()
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::InvalidRecurrenceCounter),
+		}
+
+		match offer
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(0)
+			.recurrence_start(1)
+			.build()
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::UnexpectedRecurrence),
+		}
+
+		let offer = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.recurrence(recurrence)
+			.recurrence_base(RecurrenceBase { start_any_period: true, basetime: 1_675_166_400 })
+			.recurrence_limit(4)
+			.build().unwrap();
+
+		let invoice_request = offer
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(1)
+			.recurrence_start(3)
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+		let (_, _, tlv_stream, _) = invoice_request.as_tlv_stream();
+		assert_eq!(invoice_request.recurrence_counter(), Some(1));
+		assert_eq!(invoice_request.recurrence_start(), Some(3));
+		assert_eq!(tlv_stream.recurrence_start, Some(3));
+
+		match offer
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(0)
+			.build()
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::MissingRecurrenceStart),
+		}
+
+		match offer
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(2)
+			.recurrence_start(3)
+			.build()
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::InvalidRecurrenceCounter),
+		}
+
+		match OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(0)
+			.build()
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::UnexpectedRecurrence),
+		}
+	}
+
 	#[test]
 	fn fails_signing_invoice_request() {
 		match OfferBuilder::new("foo".into(), recipient_pubkey())
@@ -1705,11 +1872,24 @@ mod tests {
 		let mut buffer = Vec::new();
 		invoice_request.write(&mut buffer).unwrap();
 
+		if let Err(e) = InvoiceRequest::try_from(buffer) {
+			panic!("error parsing invoice_request: {:?}", e);
+		}
+
+		let invoice_request = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount(Amount::Currency { iso4217_code: *b"USD", amount: 1000 })
+			.build_unchecked()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.amount_msats_unchecked(MAX_VALUE_MSAT + 1)
+			.build_unchecked()
+			.sign(payer_sign).unwrap();
+
+		let mut buffer = Vec::new();
+		invoice_request.write(&mut buffer).unwrap();
+
 		match InvoiceRequest::try_from(buffer) {
 			Ok(_) => panic!("expected error"),
-			Err(e) => {
-				assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::UnsupportedCurrency));
-			},
+			Err(e) => assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::InvalidAmount)),
 		}
 
 		let invoice_request = OfferBuilder::new("foo".into(), recipient_pubkey())
@@ -1855,6 +2035,61 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn parses_invoice_request_with_recurrence() {
+		let offer = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.recurrence(Recurrence { time_unit: RecurrenceTimeUnit::Months, period: 1 })
+			.build().unwrap();
+
+		let invoice_request = offer
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(0)
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+
+		let mut buffer = Vec::new();
+		invoice_request.write(&mut buffer).unwrap();
+
+		match InvoiceRequest::try_from(buffer) {
+			Ok(invoice_request) => assert_eq!(invoice_request.recurrence_counter(), Some(0)),
+			Err(e) => panic!("error parsing invoice_request: {:?}", e),
+		}
+
+		let invoice_request = offer
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.build_unchecked()
+			.sign(payer_sign).unwrap();
+
+		let mut buffer = Vec::new();
+		invoice_request.write(&mut buffer).unwrap();
+
+		match InvoiceRequest::try_from(buffer) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => {
+				assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::MissingRecurrenceCounter));
+			},
+		}
+
+		let invoice_request = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.recurrence_counter(0)
+			.build_unchecked()
+			.sign(payer_sign).unwrap();
+
+		let mut buffer = Vec::new();
+		invoice_request.write(&mut buffer).unwrap();
+
+		match InvoiceRequest::try_from(buffer) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => {
+				assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::UnexpectedRecurrence));
+			},
+		}
+	}
+
 	#[test]
 	fn fails_parsing_invoice_request_without_metadata() {
 		let offer = OfferBuilder::new("foo".into(), recipient_pubkey())
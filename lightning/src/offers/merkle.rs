@@ -42,9 +42,19 @@ impl TaggedHash {
 	///
 	/// Panics if `tlv_stream` is not a well-formed TLV stream containing at least one TLV record.
 	pub(super) fn new(tag: &'static str, tlv_stream: &[u8]) -> Self {
+		Self::from_merkle_root(tag, &[], root_hash(tlv_stream))
+	}
+
+	/// Creates a tagged hash over `prefix` followed by an already computed merkle root, such as one
+	/// reconstructed from a [`SelectiveDisclosure`].
+	pub(super) fn from_merkle_root(
+		tag: &'static str, prefix: &[u8], merkle_root: sha256::Hash
+	) -> Self {
 		let tag_hash = sha256::Hash::hash(tag.as_bytes());
-		let merkle_root = root_hash(tlv_stream);
-		let digest = Message::from_slice(tagged_hash(tag_hash, merkle_root).as_byte_array()).unwrap();
+		let mut engine = tagged_hash_engine(tag_hash);
+		engine.input(prefix);
+		engine.input(merkle_root.as_ref());
+		let digest = Message::from_slice(sha256::Hash::from_engine(engine).as_byte_array()).unwrap();
 		Self {
 			tag,
 			merkle_root,
@@ -123,13 +133,7 @@ pub(super) fn verify_signature(
 /// Computes a merkle root hash for the given data, which must be a well-formed TLV stream
 /// containing at least one TLV record.
 fn root_hash(data: &[u8]) -> sha256::Hash {
-	let nonce_tag = tagged_hash_engine(sha256::Hash::from_engine({
-		let first_tlv_record = TlvStream::new(&data[..]).next().unwrap();
-		let mut engine = sha256::Hash::engine();
-		engine.input("LnNonce".as_bytes());
-		engine.input(first_tlv_record.record_bytes);
-		engine
-	}));
+	let nonce_tag = nonce_tag_engine(data);
 	let leaf_tag = tagged_hash_engine(sha256::Hash::hash("LnLeaf".as_bytes()));
 	let branch_tag = tagged_hash_engine(sha256::Hash::hash("LnBranch".as_bytes()));
 
@@ -159,11 +163,196 @@ fn root_hash(data: &[u8]) -> sha256::Hash {
 	*leaves.first().unwrap()
 }
 
+/// Information needed to compute the merkle root of a TLV stream when only some of its records are
+/// disclosed, as used in a [`PayerProof`].
+///
+/// The first record, which contains the nonce used for hashing the record types, is never
+/// disclosed.
+///
+/// [`PayerProof`]: crate::offers::payer_proof::PayerProof
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct SelectiveDisclosure {
+	/// Hashes of the types of the disclosed records, in order, since they can't be computed without
+	/// the first record.
+	pub(super) leaf_hashes: Vec<sha256::Hash>,
+	/// Markers in place of the types of omitted records other than the first, in order. Each is one
+	/// more than the type or marker preceding it, or one for the first marker when no record before
+	/// it is disclosed. This preserves the position of omitted records relative to disclosed ones
+	/// without revealing their types.
+	pub(super) omitted_markers: Vec<u64>,
+	/// Hashes of the subtrees containing only omitted records that are needed to compute the
+	/// merkle root, in the order they are combined with the disclosed ones.
+	pub(super) missing_hashes: Vec<sha256::Hash>,
+	/// The merkle root of the entire TLV stream.
+	pub(super) merkle_root: sha256::Hash,
+}
+
+/// Computes a [`SelectiveDisclosure`] for the records of the TLV stream `data` with types in
+/// `disclosed_types`, excluding any signature records.
+///
+/// Panics if `data` is not a well-formed TLV stream containing at least one TLV record.
+pub(super) fn compute_selective_disclosure(
+	data: &[u8], disclosed_types: &[u64]
+) -> SelectiveDisclosure {
+	let nonce_tag = nonce_tag_engine(data);
+	let leaf_tag = tagged_hash_engine(sha256::Hash::hash("LnLeaf".as_bytes()));
+	let branch_tag = tagged_hash_engine(sha256::Hash::hash("LnBranch".as_bytes()));
+
+	let mut leaf_hashes = Vec::new();
+	let mut omitted_markers = Vec::new();
+	let mut nodes = Vec::new();
+	let mut prev_type = 0;
+	for (i, record) in TlvStream::new(&data[..]).skip_signatures().enumerate() {
+		let leaf_hash = tagged_hash_from_engine(leaf_tag.clone(), &record.record_bytes);
+		let nonce_hash = tagged_hash_from_engine(nonce_tag.clone(), &record.type_bytes);
+		let disclosed = i > 0 && disclosed_types.contains(&record.r#type);
+		if disclosed {
+			leaf_hashes.push(nonce_hash);
+			prev_type = record.r#type;
+		} else if i > 0 {
+			prev_type += 1;
+			omitted_markers.push(prev_type);
+		}
+
+		let hash = tagged_branch_hash_from_engine(branch_tag.clone(), leaf_hash, nonce_hash);
+		nodes.push((hash, disclosed));
+	}
+
+	// Calculate the merkle root hash in place as in `root_hash`, noting any hashes of omitted
+	// subtrees combined with disclosed ones.
+	let mut missing_hashes = Vec::new();
+	let num_nodes = nodes.len();
+	for level in 0.. {
+		let step = 2 << level;
+		let offset = step / 2;
+		if offset >= num_nodes {
+			break;
+		}
+
+		let left_branches = (0..num_nodes).step_by(step);
+		let right_branches = (offset..num_nodes).step_by(step);
+		for (i, j) in left_branches.zip(right_branches) {
+			let (left, left_disclosed) = nodes[i];
+			let (right, right_disclosed) = nodes[j];
+			if left_disclosed && !right_disclosed {
+				missing_hashes.push(right);
+			} else if !left_disclosed && right_disclosed {
+				missing_hashes.push(left);
+			}
+			let hash = tagged_branch_hash_from_engine(branch_tag.clone(), left, right);
+			nodes[i] = (hash, left_disclosed || right_disclosed);
+		}
+	}
+
+	SelectiveDisclosure {
+		leaf_hashes,
+		omitted_markers,
+		missing_hashes,
+		merkle_root: nodes.first().unwrap().0,
+	}
+}
+
+/// Computes the merkle root of a TLV stream from its disclosed records given in `data` along with
+/// the [`SelectiveDisclosure`] fields other than the merkle root.
+///
+/// Errors if `data` has no records or if the disclosure is inconsistent with its records.
+pub(super) fn root_hash_from_disclosure(
+	data: &[u8], leaf_hashes: &[sha256::Hash], omitted_markers: &[u64],
+	missing_hashes: &[sha256::Hash],
+) -> Result<sha256::Hash, ()> {
+	let leaf_tag = tagged_hash_engine(sha256::Hash::hash("LnLeaf".as_bytes()));
+	let branch_tag = tagged_hash_engine(sha256::Hash::hash("LnBranch".as_bytes()));
+
+	let mut disclosed_records = TlvStream::new(&data[..]).skip_signatures().peekable();
+	if disclosed_records.peek().is_none() || disclosed_records.peek().unwrap().r#type == 0 {
+		return Err(());
+	}
+	if leaf_hashes.len() != TlvStream::new(&data[..]).skip_signatures().count() {
+		return Err(());
+	}
+
+	// The first record is always omitted. Otherwise, order disclosed and omitted records by their
+	// types and markers, respectively.
+	let mut nodes = vec![None];
+	let mut leaf_hashes = leaf_hashes.iter();
+	let mut omitted_markers = omitted_markers.iter().peekable();
+	let mut prev_type = 0;
+	loop {
+		let next_marker = omitted_markers.peek().map(|marker| **marker);
+		let next_type = disclosed_records.peek().map(|record| record.r#type);
+		match (next_marker, next_type) {
+			(None, None) => break,
+			(Some(marker), next_type) if next_type.map_or(true, |r#type| marker < r#type) => {
+				if marker != prev_type + 1 {
+					return Err(());
+				}
+				omitted_markers.next();
+				nodes.push(None);
+				prev_type = marker;
+			},
+			(_, Some(r#type)) => {
+				if r#type <= prev_type {
+					return Err(());
+				}
+				let record = disclosed_records.next().unwrap();
+				let leaf_hash = tagged_hash_from_engine(leaf_tag.clone(), &record.record_bytes);
+				let nonce_hash = *leaf_hashes.next().unwrap();
+				let hash = tagged_branch_hash_from_engine(branch_tag.clone(), leaf_hash, nonce_hash);
+				nodes.push(Some(hash));
+				prev_type = r#type;
+			},
+			(Some(_), None) => unreachable!(),
+		}
+	}
+
+	let mut missing_hashes = missing_hashes.iter();
+	let num_nodes = nodes.len();
+	for level in 0.. {
+		let step = 2 << level;
+		let offset = step / 2;
+		if offset >= num_nodes {
+			break;
+		}
+
+		let left_branches = (0..num_nodes).step_by(step);
+		let right_branches = (offset..num_nodes).step_by(step);
+		for (i, j) in left_branches.zip(right_branches) {
+			nodes[i] = match (nodes[i], nodes[j]) {
+				(Some(left), Some(right)) => {
+					Some(tagged_branch_hash_from_engine(branch_tag.clone(), left, right))
+				},
+				(Some(disclosed), None) | (None, Some(disclosed)) => {
+					let missing = *missing_hashes.next().ok_or(())?;
+					Some(tagged_branch_hash_from_engine(branch_tag.clone(), disclosed, missing))
+				},
+				(None, None) => None,
+			};
+		}
+	}
+
+	if missing_hashes.next().is_some() {
+		return Err(());
+	}
+
+	nodes[0].ok_or(())
+}
+
+#[cfg(test)]
 fn tagged_hash<T: AsRef<[u8]>>(tag: sha256::Hash, msg: T) -> sha256::Hash {
 	let engine = tagged_hash_engine(tag);
 	tagged_hash_from_engine(engine, msg)
 }
 
+fn nonce_tag_engine(data: &[u8]) -> sha256::HashEngine {
+	tagged_hash_engine(sha256::Hash::from_engine({
+		let first_tlv_record = TlvStream::new(&data[..]).next().unwrap();
+		let mut engine = sha256::Hash::engine();
+		engine.input("LnNonce".as_bytes());
+		engine.input(first_tlv_record.record_bytes);
+		engine
+	}))
+}
+
 fn tagged_hash_engine(tag: sha256::Hash) -> sha256::HashEngine {
 	let mut engine = sha256::Hash::engine();
 	engine.input(tag.as_ref());
@@ -424,6 +613,81 @@ mod tests {
 		assert_eq!(tlv_stream, invoice_request.bytes);
 	}
 
+	#[test]
+	fn computes_merkle_root_from_selective_disclosure() {
+		macro_rules! tlv1 { () => { "010203e8" } }
+		macro_rules! tlv2 { () => { "02080000010000020003" } }
+		macro_rules! tlv3 { () => { "03310266e4598d1d3c415f572a8488830b60f7e744ed9235eb0b1ba93283b315c0351800000000000000010000000000000002" } }
+		macro_rules! tlv4 { () => { "0a0103" } }
+		macro_rules! tlv5 { () => { "0c020102" } }
+		let data = <Vec<u8>>::from_hex(concat!(tlv1!(), tlv2!(), tlv3!(), tlv4!(), tlv5!())).unwrap();
+		let merkle_root = super::root_hash(&data);
+
+		for disclosed_types in [vec![], vec![2], vec![3], vec![2, 10], vec![3, 12], vec![2, 3, 10, 12]] {
+			let disclosure = super::compute_selective_disclosure(&data, &disclosed_types);
+			assert_eq!(disclosure.merkle_root, merkle_root);
+			assert_eq!(disclosure.leaf_hashes.len(), disclosed_types.len());
+			assert_eq!(disclosure.omitted_markers.len(), 4 - disclosed_types.len());
+			if disclosed_types.is_empty() {
+				continue;
+			}
+
+			let disclosed_data = TlvStream::new(&data)
+				.filter(|record| disclosed_types.contains(&record.r#type))
+				.map(|record| record.record_bytes.to_vec())
+				.flatten()
+				.collect::<Vec<u8>>();
+			assert_eq!(
+				super::root_hash_from_disclosure(
+					&disclosed_data, &disclosure.leaf_hashes, &disclosure.omitted_markers,
+					&disclosure.missing_hashes,
+				),
+				Ok(merkle_root),
+			);
+		}
+
+		let disclosure = super::compute_selective_disclosure(&data, &[3, 12]);
+		assert_eq!(disclosure.omitted_markers, vec![1, 4]);
+
+		let disclosed_data = <Vec<u8>>::from_hex(concat!(tlv3!(), tlv5!())).unwrap();
+		let mut missing_hashes = disclosure.missing_hashes.clone();
+		missing_hashes.pop();
+		assert_eq!(
+			super::root_hash_from_disclosure(
+				&disclosed_data, &disclosure.leaf_hashes, &disclosure.omitted_markers,
+				&missing_hashes,
+			),
+			Err(()),
+		);
+
+		let mut missing_hashes = disclosure.missing_hashes.clone();
+		missing_hashes.push(merkle_root);
+		assert_eq!(
+			super::root_hash_from_disclosure(
+				&disclosed_data, &disclosure.leaf_hashes, &disclosure.omitted_markers,
+				&missing_hashes,
+			),
+			Err(()),
+		);
+
+		assert_eq!(
+			super::root_hash_from_disclosure(
+				&disclosed_data, &disclosure.leaf_hashes, &[1, 3],
+				&disclosure.missing_hashes,
+			),
+			Err(()),
+		);
+
+		let disclosed_data = <Vec<u8>>::from_hex(concat!(tlv3!(), tlv4!())).unwrap();
+		assert_ne!(
+			super::root_hash_from_disclosure(
+				&disclosed_data, &disclosure.leaf_hashes, &disclosure.omitted_markers,
+				&disclosure.missing_hashes,
+			),
+			Ok(merkle_root),
+		);
+	}
+
 	impl AsRef<[u8]> for InvoiceRequest {
 		fn as_ref(&self) -> &[u8] {
 			&self.bytes
@@ -21,7 +21,10 @@ pub mod invoice_request;
 pub mod merkle;
 pub mod parse;
 mod payer;
+pub mod payer_proof;
+pub mod recurrence;
 pub mod refund;
 pub(crate) mod signer;
+pub mod static_invoice;
 #[cfg(test)]
 pub(crate) mod test_utils;
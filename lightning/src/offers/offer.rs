@@ -90,12 +90,13 @@ use crate::blinded_path::BlindedPath;
 use crate::ln::channelmanager::PaymentId;
 use crate::ln::features::OfferFeatures;
 use crate::ln::inbound_payment::{ExpandedKey, IV_LEN, Nonce};
-use crate::ln::msgs::MAX_VALUE_MSAT;
+use crate::ln::msgs::{DecodeError, MAX_VALUE_MSAT};
 use crate::offers::invoice_request::{DerivedPayerId, ExplicitPayerId, InvoiceRequestBuilder};
 use crate::offers::merkle::TlvStream;
 use crate::offers::parse::{Bech32Encode, Bolt12ParseError, Bolt12SemanticError, ParsedMessage};
+use crate::offers::recurrence::{Recurrence, RecurrenceBase, RecurrencePaywindow};
 use crate::offers::signer::{Metadata, MetadataMaterial, self};
-use crate::util::ser::{HighZeroBytesDroppedBigSize, WithoutLength, Writeable, Writer};
+use crate::util::ser::{HighZeroBytesDroppedBigSize, Readable, WithoutLength, Writeable, Writer};
 use crate::util::string::PrintableString;
 
 use crate::prelude::*;
@@ -155,7 +156,8 @@ impl<'a> OfferBuilder<'a, ExplicitMetadata, secp256k1::SignOnly> {
 			offer: OfferContents {
 				chains: None, metadata: None, amount: None, description,
 				features: OfferFeatures::empty(), absolute_expiry: None, issuer: None, paths: None,
-				supported_quantity: Quantity::One, signing_pubkey,
+				supported_quantity: Quantity::One, signing_pubkey, recurrence: None,
+				recurrence_base: None, recurrence_paywindow: None, recurrence_limit: None,
 			},
 			metadata_strategy: core::marker::PhantomData,
 			secp_ctx: None,
@@ -194,7 +196,8 @@ impl<'a, T: secp256k1::Signing> OfferBuilder<'a, DerivedMetadata, T> {
 			offer: OfferContents {
 				chains: None, metadata: Some(metadata), amount: None, description,
 				features: OfferFeatures::empty(), absolute_expiry: None, issuer: None, paths: None,
-				supported_quantity: Quantity::One, signing_pubkey: node_id,
+				supported_quantity: Quantity::One, signing_pubkey: node_id, recurrence: None,
+				recurrence_base: None, recurrence_paywindow: None, recurrence_limit: None,
 			},
 			metadata_strategy: core::marker::PhantomData,
 			secp_ctx: Some(secp_ctx),
@@ -235,10 +238,10 @@ impl<'a, M: MetadataStrategy, T: secp256k1::Signing> OfferBuilder<'a, M, T> {
 		self.amount(Amount::Bitcoin { amount_msats })
 	}
 
-	/// Sets the [`Offer::amount`].
+	/// Sets the [`Offer::amount`], which may be denominated in a currency other than bitcoin.
 	///
 	/// Successive calls to this method will override the previous setting.
-	pub(super) fn amount(mut self, amount: Amount) -> Self {
+	pub fn amount(mut self, amount: Amount) -> Self {
 		self.offer.amount = Some(amount);
 		self
 	}
@@ -279,6 +282,39 @@ impl<'a, M: MetadataStrategy, T: secp256k1::Signing> OfferBuilder<'a, M, T> {
 		self
 	}
 
+	/// Sets the [`Offer::recurrence`], making the offer payable once per period.
+	///
+	/// Successive calls to this method will override the previous setting.
+	pub fn recurrence(mut self, recurrence: Recurrence) -> Self {
+		self.offer.recurrence = Some(recurrence);
+		self
+	}
+
+	/// Sets the [`Offer::recurrence_base`]. If not called, the first period begins when the invoice
+	/// for it is created. Requires calling [`OfferBuilder::recurrence`].
+	///
+	/// Successive calls to this method will override the previous setting.
+	pub fn recurrence_base(mut self, recurrence_base: RecurrenceBase) -> Self {
+		self.offer.recurrence_base = Some(recurrence_base);
+		self
+	}
+
+	/// Sets the [`Offer::recurrence_paywindow`]. Requires calling [`OfferBuilder::recurrence`].
+	///
+	/// Successive calls to this method will override the previous setting.
+	pub fn recurrence_paywindow(mut self, recurrence_paywindow: RecurrencePaywindow) -> Self {
+		self.offer.recurrence_paywindow = Some(recurrence_paywindow);
+		self
+	}
+
+	/// Sets the [`Offer::recurrence_limit`]. Requires calling [`OfferBuilder::recurrence`].
+	///
+	/// Successive calls to this method will override the previous setting.
+	pub fn recurrence_limit(mut self, max_period: u32) -> Self {
+		self.offer.recurrence_limit = Some(max_period);
+		self
+	}
+
 	/// Builds an [`Offer`] from the builder's settings.
 	pub fn build(mut self) -> Result<Offer, Bolt12SemanticError> {
 		match self.offer.amount {
@@ -287,10 +323,11 @@ impl<'a, M: MetadataStrategy, T: secp256k1::Signing> OfferBuilder<'a, M, T> {
 					return Err(Bolt12SemanticError::InvalidAmount);
 				}
 			},
-			Some(Amount::Currency { .. }) => return Err(Bolt12SemanticError::UnsupportedCurrency),
-			None => {},
+			Some(Amount::Currency { .. }) | None => {},
 		}
 
+		self.offer.check_recurrence()?;
+
 		if let Some(chains) = &self.offer.chains {
 			if chains.len() == 1 && chains[0] == self.offer.implied_chain() {
 				self.offer.chains = None;
@@ -384,6 +421,10 @@ pub(super) struct OfferContents {
 	paths: Option<Vec<BlindedPath>>,
 	supported_quantity: Quantity,
 	signing_pubkey: PublicKey,
+	recurrence: Option<Recurrence>,
+	recurrence_base: Option<RecurrenceBase>,
+	recurrence_paywindow: Option<RecurrencePaywindow>,
+	recurrence_limit: Option<u32>,
 }
 
 macro_rules! offer_accessors { ($self: ident, $contents: expr) => {
@@ -448,6 +489,27 @@ macro_rules! offer_accessors { ($self: ident, $contents: expr) => {
 	pub fn signing_pubkey(&$self) -> bitcoin::secp256k1::PublicKey {
 		$contents.signing_pubkey()
 	}
+
+	/// How often the offer is expected to be paid, if it is recurring.
+	pub fn recurrence(&$self) -> Option<&$crate::offers::recurrence::Recurrence> {
+		$contents.recurrence()
+	}
+
+	/// When the first period of a recurring offer begins. If `None`, it begins when the invoice for
+	/// the first period is created.
+	pub fn recurrence_base(&$self) -> Option<&$crate::offers::recurrence::RecurrenceBase> {
+		$contents.recurrence_base()
+	}
+
+	/// When each period of a recurring offer may be paid relative to its start.
+	pub fn recurrence_paywindow(&$self) -> Option<&$crate::offers::recurrence::RecurrencePaywindow> {
+		$contents.recurrence_paywindow()
+	}
+
+	/// The index of the last period of a recurring offer that may be paid, if limited.
+	pub fn recurrence_limit(&$self) -> Option<u32> {
+		$contents.recurrence_limit()
+	}
 } }
 
 impl Offer {
@@ -640,7 +702,14 @@ impl OfferContents {
 		let offer_amount_msats = match self.amount {
 			None => 0,
 			Some(Amount::Bitcoin { amount_msats }) => amount_msats,
-			Some(Amount::Currency { .. }) => return Err(Bolt12SemanticError::UnsupportedCurrency),
+			// The exchange rate is unknown here, so only the recipient can check that an amount
+			// is sufficient for an offer denominated in another currency.
+			Some(Amount::Currency { .. }) => {
+				if amount_msats.map_or(false, |amount_msats| amount_msats > MAX_VALUE_MSAT) {
+					return Err(Bolt12SemanticError::InvalidAmount);
+				}
+				return Ok(());
+			},
 		};
 
 		if !self.expects_quantity() || quantity.is_some() {
@@ -696,6 +765,66 @@ impl OfferContents {
 		self.signing_pubkey
 	}
 
+	pub fn recurrence(&self) -> Option<&Recurrence> {
+		self.recurrence.as_ref()
+	}
+
+	pub fn recurrence_base(&self) -> Option<&RecurrenceBase> {
+		self.recurrence_base.as_ref()
+	}
+
+	pub fn recurrence_paywindow(&self) -> Option<&RecurrencePaywindow> {
+		self.recurrence_paywindow.as_ref()
+	}
+
+	pub fn recurrence_limit(&self) -> Option<u32> {
+		self.recurrence_limit
+	}
+
+	fn check_recurrence(&self) -> Result<(), Bolt12SemanticError> {
+		match self.recurrence {
+			Some(Recurrence { period: 0, .. }) => Err(Bolt12SemanticError::InvalidRecurrence),
+			Some(_) => Ok(()),
+			None if self.recurrence_base.is_some() || self.recurrence_paywindow.is_some() ||
+				self.recurrence_limit.is_some() => Err(Bolt12SemanticError::InvalidRecurrence),
+			None => Ok(()),
+		}
+	}
+
+	/// Checks the recurrence fields of an invoice request against those of the offer.
+	pub(super) fn check_recurrence_counter(
+		&self, recurrence_counter: Option<u32>, recurrence_start: Option<u32>
+	) -> Result<(), Bolt12SemanticError> {
+		if self.recurrence.is_none() {
+			if recurrence_counter.is_some() || recurrence_start.is_some() {
+				return Err(Bolt12SemanticError::UnexpectedRecurrence);
+			}
+			return Ok(());
+		}
+
+		let recurrence_counter = match recurrence_counter {
+			None => return Err(Bolt12SemanticError::MissingRecurrenceCounter),
+			Some(recurrence_counter) => recurrence_counter,
+		};
+
+		let start_any_period = self.recurrence_base
+			.map(|base| base.start_any_period)
+			.unwrap_or(false);
+		match recurrence_start {
+			None if start_any_period => return Err(Bolt12SemanticError::MissingRecurrenceStart),
+			Some(_) if !start_any_period => return Err(Bolt12SemanticError::UnexpectedRecurrence),
+			_ => {},
+		}
+
+		let period_index = recurrence_start.unwrap_or(0).checked_add(recurrence_counter)
+			.ok_or(Bolt12SemanticError::InvalidRecurrenceCounter)?;
+		if self.recurrence_limit.map_or(false, |max_period| period_index > max_period) {
+			return Err(Bolt12SemanticError::InvalidRecurrenceCounter);
+		}
+
+		Ok(())
+	}
+
 	/// Verifies that the offer metadata was produced from the offer in the TLV stream.
 	pub(super) fn verify<T: secp256k1::Signing>(
 		&self, bytes: &[u8], key: &ExpandedKey, secp_ctx: &Secp256k1<T>
@@ -744,6 +873,10 @@ impl OfferContents {
 			issuer: self.issuer.as_ref(),
 			quantity_max: self.supported_quantity.to_tlv_record(),
 			node_id: Some(&self.signing_pubkey),
+			recurrence: self.recurrence.as_ref(),
+			recurrence_base: self.recurrence_base.as_ref(),
+			recurrence_paywindow: self.recurrence_paywindow.as_ref(),
+			recurrence_limit: self.recurrence_limit,
 		}
 	}
 }
@@ -754,6 +887,13 @@ impl Writeable for Offer {
 	}
 }
 
+impl Readable for Offer {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		let bytes: WithoutLength<Vec<u8>> = Readable::read(reader)?;
+		Self::try_from(bytes.0).map_err(|_| DecodeError::InvalidValue)
+	}
+}
+
 impl Writeable for OfferContents {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
 		self.as_tlv_stream().write(writer)
@@ -778,9 +918,51 @@ pub enum Amount {
 	},
 }
 
+impl Amount {
+	/// Returns the amount in millisatoshi, converting it using `currency_conversion` if it is
+	/// denominated in a currency other than bitcoin.
+	///
+	/// Errors if the currency isn't supported by `currency_conversion` or if the converted amount
+	/// exceeds the total bitcoin supply.
+	pub fn to_msats<CC: Deref>(
+		&self, currency_conversion: &CC
+	) -> Result<u64, Bolt12SemanticError> where CC::Target: CurrencyConversion {
+		let amount_msats = match self {
+			Amount::Bitcoin { amount_msats } => *amount_msats,
+			Amount::Currency { iso4217_code, amount } => currency_conversion
+				.to_msats(*iso4217_code, *amount)
+				.map_err(|()| Bolt12SemanticError::UnsupportedCurrency)?,
+		};
+
+		if amount_msats > MAX_VALUE_MSAT {
+			return Err(Bolt12SemanticError::InvalidAmount);
+		}
+
+		Ok(amount_msats)
+	}
+}
+
 /// An ISO 4712 three-letter currency code (e.g., USD).
 pub type CurrencyCode = [u8; 3];
 
+/// A source of exchange rates used to convert an [`Amount::Currency`] into millisatoshi, allowing
+/// [`Offer`]s to be denominated in currencies other than bitcoin.
+///
+/// Used by [`ChannelManager`] both when responding to [`InvoiceRequest`]s for such offers and when
+/// paying for them. See [`ChannelManager::set_currency_conversion`] for details.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+/// [`ChannelManager::set_currency_conversion`]: crate::ln::channelmanager::ChannelManager::set_currency_conversion
+/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+pub trait CurrencyConversion {
+	/// Converts `amount` of the currency given by `iso4217_code`, denominated in the currency unit
+	/// adjusted by the ISO 4712 exponent (e.g., USD cents), into millisatoshi at the current
+	/// exchange rate.
+	///
+	/// Returns `Err` if the currency is not supported or no exchange rate is currently available.
+	fn to_msats(&self, iso4217_code: CurrencyCode, amount: u64) -> Result<u64, ()>;
+}
+
 /// Quantity of items supported by an [`Offer`].
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Quantity {
@@ -827,6 +1009,10 @@ tlv_stream!(OfferTlvStream, OfferTlvStreamRef, OFFER_TYPES, {
 	(18, issuer: (String, WithoutLength)),
 	(20, quantity_max: (u64, HighZeroBytesDroppedBigSize)),
 	(OFFER_NODE_ID_TYPE, node_id: PublicKey),
+	(26, recurrence: Recurrence),
+	(28, recurrence_base: RecurrenceBase),
+	(64, recurrence_paywindow: RecurrencePaywindow),
+	(66, recurrence_limit: (u32, HighZeroBytesDroppedBigSize)),
 });
 
 impl Bech32Encode for Offer {
@@ -858,7 +1044,8 @@ impl TryFrom<OfferTlvStream> for OfferContents {
 	fn try_from(tlv_stream: OfferTlvStream) -> Result<Self, Self::Error> {
 		let OfferTlvStream {
 			chains, metadata, currency, amount, description, features, absolute_expiry, paths,
-			issuer, quantity_max, node_id,
+			issuer, quantity_max, node_id, recurrence, recurrence_base, recurrence_paywindow,
+			recurrence_limit,
 		} = tlv_stream;
 
 		let metadata = metadata.map(|metadata| Metadata::Bytes(metadata));
@@ -894,10 +1081,14 @@ impl TryFrom<OfferTlvStream> for OfferContents {
 			Some(node_id) => node_id,
 		};
 
-		Ok(OfferContents {
+		let contents = OfferContents {
 			chains, metadata, amount, description, features, absolute_expiry, issuer, paths,
-			supported_quantity, signing_pubkey,
-		})
+			supported_quantity, signing_pubkey, recurrence, recurrence_base, recurrence_paywindow,
+			recurrence_limit,
+		};
+		contents.check_recurrence()?;
+
+		Ok(contents)
 	}
 }
 
@@ -923,9 +1114,11 @@ mod tests {
 	use crate::ln::inbound_payment::ExpandedKey;
 	use crate::ln::msgs::{DecodeError, MAX_VALUE_MSAT};
 	use crate::offers::parse::{Bolt12ParseError, Bolt12SemanticError};
+	use crate::offers::recurrence::{Recurrence, RecurrenceBase, RecurrencePaywindow, RecurrenceTimeUnit};
 	use crate::offers::test_utils::*;
 	use crate::util::ser::{BigSize, Writeable};
 	use crate::util::string::PrintableString;
+	use crate::util::test_utils::TestCurrencyConversion;
 
 	#[test]
 	fn builds_offer_with_defaults() {
@@ -963,6 +1156,10 @@ mod tests {
 				issuer: None,
 				quantity_max: None,
 				node_id: Some(&pubkey(42)),
+				recurrence: None,
+				recurrence_base: None,
+				recurrence_paywindow: None,
+				recurrence_limit: None,
 			},
 		);
 
@@ -1154,10 +1351,12 @@ mod tests {
 		assert_eq!(builder.offer.amount, Some(currency_amount.clone()));
 		assert_eq!(tlv_stream.amount, Some(10));
 		assert_eq!(tlv_stream.currency, Some(b"USD"));
-		match builder.build() {
-			Ok(_) => panic!("expected error"),
-			Err(e) => assert_eq!(e, Bolt12SemanticError::UnsupportedCurrency),
-		}
+
+		let offer = builder.build().unwrap();
+		let tlv_stream = offer.as_tlv_stream();
+		assert_eq!(offer.amount(), Some(&currency_amount));
+		assert_eq!(tlv_stream.amount, Some(10));
+		assert_eq!(tlv_stream.currency, Some(b"USD"));
 
 		let offer = OfferBuilder::new("foo".into(), pubkey(42))
 			.amount(currency_amount.clone())
@@ -1320,6 +1519,55 @@ mod tests {
 		assert_eq!(tlv_stream.quantity_max, None);
 	}
 
+	#[test]
+	fn builds_offer_with_recurrence() {
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Months, period: 1 };
+		let base = RecurrenceBase { start_any_period: true, basetime: 1_675_166_400 };
+		let paywindow = RecurrencePaywindow {
+			seconds_before: 3600, proportional_amount: false, seconds_after: 86400,
+		};
+
+		let offer = OfferBuilder::new("foo".into(), pubkey(42))
+			.recurrence(recurrence)
+			.build()
+			.unwrap();
+		let tlv_stream = offer.as_tlv_stream();
+		assert_eq!(offer.recurrence(), Some(&recurrence));
+		assert_eq!(offer.recurrence_base(), None);
+		assert_eq!(offer.recurrence_paywindow(), None);
+		assert_eq!(offer.recurrence_limit(), None);
+		assert_eq!(tlv_stream.recurrence, Some(&recurrence));
+		assert_eq!(tlv_stream.recurrence_base, None);
+
+		let offer = OfferBuilder::new("foo".into(), pubkey(42))
+			.recurrence(recurrence)
+			.recurrence_base(base)
+			.recurrence_paywindow(paywindow)
+			.recurrence_limit(11)
+			.build()
+			.unwrap();
+		let tlv_stream = offer.as_tlv_stream();
+		assert_eq!(offer.recurrence_base(), Some(&base));
+		assert_eq!(offer.recurrence_paywindow(), Some(&paywindow));
+		assert_eq!(offer.recurrence_limit(), Some(11));
+		assert_eq!(tlv_stream.recurrence_base, Some(&base));
+		assert_eq!(tlv_stream.recurrence_paywindow, Some(&paywindow));
+		assert_eq!(tlv_stream.recurrence_limit, Some(11));
+
+		match OfferBuilder::new("foo".into(), pubkey(42))
+			.recurrence(Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 0 })
+			.build()
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::InvalidRecurrence),
+		}
+
+		match OfferBuilder::new("foo".into(), pubkey(42)).recurrence_limit(11).build() {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::InvalidRecurrence),
+		}
+	}
+
 	#[test]
 	fn fails_requesting_invoice_with_unknown_required_features() {
 		match OfferBuilder::new("foo".into(), pubkey(42))
@@ -1344,6 +1592,39 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn converts_amount_to_msats() {
+		let currency_conversion = TestCurrencyConversion::new();
+		currency_conversion.set_rate(*b"USD", 25_000);
+
+		let bitcoin_amount = Amount::Bitcoin { amount_msats: 1000 };
+		assert_eq!(bitcoin_amount.to_msats(&&currency_conversion), Ok(1000));
+
+		let currency_amount = Amount::Currency { iso4217_code: *b"USD", amount: 10 };
+		assert_eq!(currency_amount.to_msats(&&currency_conversion), Ok(250_000));
+
+		let unsupported_amount = Amount::Currency { iso4217_code: *b"EUR", amount: 10 };
+		assert_eq!(
+			unsupported_amount.to_msats(&&currency_conversion),
+			Err(Bolt12SemanticError::UnsupportedCurrency),
+		);
+
+		let excessive_amount = Amount::Currency { iso4217_code: *b"USD", amount: MAX_VALUE_MSAT };
+		assert_eq!(
+			excessive_amount.to_msats(&&currency_conversion),
+			Err(Bolt12SemanticError::UnsupportedCurrency),
+		);
+
+		currency_conversion.set_rate(*b"USD", 1000);
+		let excessive_amount = Amount::Currency {
+			iso4217_code: *b"USD", amount: MAX_VALUE_MSAT / 1000 + 1
+		};
+		assert_eq!(
+			excessive_amount.to_msats(&&currency_conversion),
+			Err(Bolt12SemanticError::InvalidAmount),
+		);
+	}
+
 	#[test]
 	fn parses_offer_with_amount() {
 		let offer = OfferBuilder::new("foo".into(), pubkey(42))
@@ -1480,6 +1761,51 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn parses_offer_with_recurrence() {
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Seconds, period: 3600 };
+		let offer = OfferBuilder::new("foo".into(), pubkey(42))
+			.recurrence(recurrence)
+			.recurrence_base(RecurrenceBase { start_any_period: false, basetime: 1_675_166_400 })
+			.recurrence_paywindow(RecurrencePaywindow {
+				seconds_before: 60, proportional_amount: true, seconds_after: 60,
+			})
+			.recurrence_limit(24)
+			.build()
+			.unwrap();
+		match offer.to_string().parse::<Offer>() {
+			Ok(parsed_offer) => assert_eq!(parsed_offer, offer),
+			Err(e) => panic!("error parsing offer: {:?}", e),
+		}
+
+		let mut tlv_stream = offer.as_tlv_stream();
+		tlv_stream.recurrence = None;
+
+		let mut encoded_offer = Vec::new();
+		tlv_stream.write(&mut encoded_offer).unwrap();
+
+		match Offer::try_from(encoded_offer) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => {
+				assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::InvalidRecurrence));
+			},
+		}
+
+		let mut tlv_stream = offer.as_tlv_stream();
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Seconds, period: 0 };
+		tlv_stream.recurrence = Some(&recurrence);
+
+		let mut encoded_offer = Vec::new();
+		tlv_stream.write(&mut encoded_offer).unwrap();
+
+		match Offer::try_from(encoded_offer) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => {
+				assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::InvalidRecurrence));
+			},
+		}
+	}
+
 	#[test]
 	fn parses_offer_with_node_id() {
 		let offer = OfferBuilder::new("foo".into(), pubkey(42)).build().unwrap();
@@ -183,14 +183,42 @@ pub enum Bolt12SemanticError {
 	DuplicatePaymentId,
 	/// Blinded paths were expected but were missing.
 	MissingPaths,
+	/// Blinded paths were provided but were not expected.
+	UnexpectedPaths,
 	/// The blinded payinfo given does not match the number of blinded path hops.
 	InvalidPayInfo,
 	/// An invoice creation time was expected but was missing.
 	MissingCreationTime,
 	/// An invoice payment hash was expected but was missing.
 	MissingPaymentHash,
+	/// An invoice payment hash was provided but was not expected.
+	UnexpectedPaymentHash,
 	/// A signature was expected but was missing.
 	MissingSignature,
+	/// An offer's recurrence has a zero period or has a base, paywindow, or limit without a period.
+	InvalidRecurrence,
+	/// Recurrence fields were provided but were not expected.
+	UnexpectedRecurrence,
+	/// A recurrence was expected but was missing.
+	MissingRecurrence,
+	/// A recurrence counter was expected but was missing.
+	MissingRecurrenceCounter,
+	/// A recurrence counter was provided for a period that can't be requested.
+	InvalidRecurrenceCounter,
+	/// A recurrence start was expected but was missing.
+	MissingRecurrenceStart,
+	/// An invoice recurrence basetime was expected but was missing.
+	MissingRecurrenceBasetime,
+	/// An invoice recurrence basetime was provided but a different one was expected.
+	InvalidRecurrenceBasetime,
+	/// A recurrence period was requested outside of its paywindow.
+	OutsideRecurrencePaywindow,
+	/// A payment preimage was expected but was missing.
+	MissingPaymentPreimage,
+	/// A payment preimage was provided but did not match the payment hash.
+	InvalidPaymentPreimage,
+	/// The disclosed TLV records could not be used to compute a merkle root.
+	InvalidSelectiveDisclosure,
 }
 
 impl From<bech32::Error> for Bolt12ParseError {
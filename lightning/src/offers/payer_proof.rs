@@ -0,0 +1,689 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Data structures and encoding for payer proofs.
+//!
+//! A [`PayerProof`] demonstrates that the payer of a [`Bolt12Invoice`] paid it. It consists of a
+//! subset of the invoice's TLV records, the recipient's signature over the entire invoice, the
+//! payment preimage, and a signature by the payer over the invoice and an optional note.
+//!
+//! Using the invoice's merkle tree, records not needed for the proof are omitted while still
+//! allowing the recipient's signature to be checked. The payer id, payment hash, amount, creation
+//! time, and signing pubkey of the invoice are always disclosed.
+//!
+//! ```
+//! extern crate bitcoin;
+//! extern crate lightning;
+//!
+//! use bitcoin::secp256k1::{KeyPair, Secp256k1, SecretKey};
+//! use core::convert::Infallible;
+//! use lightning::ln::PaymentPreimage;
+//! use lightning::offers::invoice::Bolt12Invoice;
+//! use lightning::offers::payer_proof::PayerProof;
+//!
+//! # fn parse(invoice: Bolt12Invoice, preimage: PaymentPreimage) -> Result<(), lightning::offers::parse::Bolt12ParseError> {
+//! let secp_ctx = Secp256k1::new();
+//! let keys = KeyPair::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32])?);
+//!
+//! // Prove payment of an invoice to a third party.
+//! let encoded_proof = invoice
+//!     .payer_proof_builder(preimage)?
+//!     .include_description()
+//!     .note("order #12345".to_string())
+//!     .build()?
+//!     .sign::<_, Infallible>(
+//!         |message| Ok(secp_ctx.sign_schnorr_no_aux_rand(message.as_ref().as_digest(), &keys))
+//!     )
+//!     .expect("failed verifying signature")
+//!     .to_string();
+//!
+//! // Parsing verifies the invoice signature, preimage, and payer signature.
+//! let proof = encoded_proof.parse::<PayerProof>()?;
+//! assert_eq!(proof.payment_hash(), invoice.payment_hash());
+//! # Ok(())
+//! # }
+//! ```
+
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::secp256k1::schnorr::Signature;
+use core::convert::TryFrom;
+use core::str::FromStr;
+use core::time::Duration;
+use crate::io;
+use crate::ln::{PaymentHash, PaymentPreimage};
+use crate::ln::msgs::DecodeError;
+use crate::offers::invoice::{Bolt12Invoice, InvoiceTlvStream, SIGNATURE_TAG};
+use crate::offers::invoice_request::{INVOICE_REQUEST_PAYER_ID_TYPE, InvoiceRequestTlvStream};
+use crate::offers::merkle::{SignError, SignatureTlvStream, SignatureTlvStreamRef, TaggedHash, TlvStream, self};
+use crate::offers::offer::OfferTlvStream;
+use crate::offers::parse::{Bech32Encode, Bolt12ParseError, Bolt12SemanticError, ParsedMessage};
+use crate::util::ser::{BigSize, SeekReadable, WithoutLength, Writeable, Writer};
+use crate::util::string::PrintableString;
+
+use crate::prelude::*;
+
+/// Tag for the hash function used when signing a [`PayerProof`]'s merkle root.
+pub const PAYER_SIGNATURE_TAG: &'static str = concat!("lightning", "payer_proof", "payer_signature");
+
+const OFFER_DESCRIPTION_TYPE: u64 = 10;
+const OFFER_ISSUER_TYPE: u64 = 18;
+const INVOICE_REQUEST_QUANTITY_TYPE: u64 = 86;
+const INVOICE_REQUEST_PAYER_NOTE_TYPE: u64 = 89;
+const INVOICE_CREATED_AT_TYPE: u64 = 164;
+const INVOICE_PAYMENT_HASH_TYPE: u64 = 168;
+const INVOICE_AMOUNT_TYPE: u64 = 170;
+const INVOICE_NODE_ID_TYPE: u64 = 176;
+
+/// Builds a [`PayerProof`] for a paid [`Bolt12Invoice`].
+///
+/// See [module-level documentation] for usage.
+///
+/// This is not exported to bindings users as builder patterns don't map outside of move semantics.
+///
+/// [module-level documentation]: self
+pub struct PayerProofBuilder<'a> {
+	invoice: &'a Bolt12Invoice,
+	preimage: PaymentPreimage,
+	disclosed_types: Vec<u64>,
+	note: Option<String>,
+}
+
+impl<'a> PayerProofBuilder<'a> {
+	pub(super) fn new(
+		invoice: &'a Bolt12Invoice, preimage: PaymentPreimage
+	) -> Result<Self, Bolt12SemanticError> {
+		if !preimage_matches(&preimage, &invoice.payment_hash()) {
+			return Err(Bolt12SemanticError::InvalidPaymentPreimage);
+		}
+
+		let disclosed_types = vec![
+			INVOICE_REQUEST_PAYER_ID_TYPE, INVOICE_CREATED_AT_TYPE, INVOICE_PAYMENT_HASH_TYPE,
+			INVOICE_AMOUNT_TYPE, INVOICE_NODE_ID_TYPE,
+		];
+
+		Ok(Self { invoice, preimage, disclosed_types, note: None })
+	}
+
+	/// Discloses [`Bolt12Invoice::description`] in the proof.
+	pub fn include_description(mut self) -> Self {
+		self.disclosed_types.push(OFFER_DESCRIPTION_TYPE);
+		self
+	}
+
+	/// Discloses [`Bolt12Invoice::issuer`] in the proof, if set.
+	pub fn include_issuer(mut self) -> Self {
+		self.disclosed_types.push(OFFER_ISSUER_TYPE);
+		self
+	}
+
+	/// Discloses [`Bolt12Invoice::quantity`] in the proof, if set.
+	pub fn include_quantity(mut self) -> Self {
+		self.disclosed_types.push(INVOICE_REQUEST_QUANTITY_TYPE);
+		self
+	}
+
+	/// Discloses [`Bolt12Invoice::payer_note`] in the proof, if set.
+	pub fn include_payer_note(mut self) -> Self {
+		self.disclosed_types.push(INVOICE_REQUEST_PAYER_NOTE_TYPE);
+		self
+	}
+
+	/// Sets a note from the payer to include in the proof, which is covered by the payer's
+	/// signature.
+	///
+	/// Successive calls to this method will override the previous setting.
+	pub fn note(mut self, note: String) -> Self {
+		self.note = Some(note);
+		self
+	}
+
+	/// Builds an unsigned [`PayerProof`] after checking for valid semantics. It can be signed by
+	/// [`UnsignedPayerProof::sign`].
+	pub fn build(self) -> Result<UnsignedPayerProof, Bolt12SemanticError> {
+		let PayerProofBuilder { invoice, preimage, disclosed_types, note } = self;
+
+		let disclosure = merkle::compute_selective_disclosure(&invoice.bytes, &disclosed_types);
+
+		let mut bytes = Vec::new();
+		for record in TlvStream::new(&invoice.bytes) {
+			if disclosed_types.contains(&record.r#type) {
+				bytes.extend_from_slice(record.record_bytes);
+			}
+		}
+
+		let disclosed = |r#type| disclosed_types.contains(&r#type);
+		let contents = PayerProofContents {
+			payer_id: invoice.payer_id(),
+			payment_hash: invoice.payment_hash(),
+			amount_msats: invoice.amount_msats(),
+			created_at: invoice.created_at(),
+			signing_pubkey: invoice.signing_pubkey(),
+			description: Some(invoice.description().0.to_string())
+				.filter(|_| disclosed(OFFER_DESCRIPTION_TYPE)),
+			issuer: invoice.issuer().map(|issuer| issuer.0.to_string())
+				.filter(|_| disclosed(OFFER_ISSUER_TYPE)),
+			quantity: invoice.quantity().filter(|_| disclosed(INVOICE_REQUEST_QUANTITY_TYPE)),
+			payer_note: invoice.payer_note().map(|payer_note| payer_note.0.to_string())
+				.filter(|_| disclosed(INVOICE_REQUEST_PAYER_NOTE_TYPE)),
+			invoice_signature: invoice.signature(),
+			preimage,
+			note,
+		};
+
+		let tagged_hash = TaggedHash::from_merkle_root(
+			PAYER_SIGNATURE_TAG, contents.note_bytes(), disclosure.merkle_root
+		);
+
+		Ok(UnsignedPayerProof {
+			bytes,
+			contents,
+			omitted_tlvs: disclosure.omitted_markers.into_iter().map(BigSize).collect(),
+			missing_hashes: disclosure.missing_hashes,
+			leaf_hashes: disclosure.leaf_hashes,
+			tagged_hash,
+		})
+	}
+}
+
+/// A [`PayerProof`] that hasn't been signed by the payer.
+pub struct UnsignedPayerProof {
+	bytes: Vec<u8>,
+	contents: PayerProofContents,
+	omitted_tlvs: Vec<BigSize>,
+	missing_hashes: Vec<sha256::Hash>,
+	leaf_hashes: Vec<sha256::Hash>,
+	tagged_hash: TaggedHash,
+}
+
+impl UnsignedPayerProof {
+	/// Returns the [`TaggedHash`] of the proof to sign.
+	pub fn tagged_hash(&self) -> &TaggedHash {
+		&self.tagged_hash
+	}
+
+	/// Signs the [`TaggedHash`] of the proof using the given function, which must produce a
+	/// signature for the invoice's [`Bolt12Invoice::payer_id`].
+	///
+	/// This is not exported to bindings users as functions aren't currently mapped.
+	pub fn sign<F, E>(mut self, sign: F) -> Result<PayerProof, SignError<E>>
+	where
+		F: FnOnce(&Self) -> Result<Signature, E>
+	{
+		let pubkey = self.contents.payer_id;
+		let payer_signature = merkle::sign_message(sign, &self, pubkey)?;
+
+		// Append the invoice signature and proof TLV records to the disclosed invoice records.
+		let signature_tlv_stream = SignatureTlvStreamRef {
+			signature: Some(&self.contents.invoice_signature),
+		};
+		let payer_proof_tlv_stream = PayerProofTlvStreamRef {
+			preimage: Some(&self.contents.preimage),
+			omitted_tlvs: Some(&self.omitted_tlvs),
+			missing_hashes: Some(&self.missing_hashes),
+			leaf_hashes: Some(&self.leaf_hashes),
+			payer_signature: Some(&payer_signature),
+			note: self.contents.note.as_ref(),
+		};
+		(signature_tlv_stream, payer_proof_tlv_stream).write(&mut self.bytes).unwrap();
+
+		Ok(PayerProof {
+			bytes: self.bytes,
+			contents: self.contents,
+			payer_signature,
+			merkle_root: self.tagged_hash.merkle_root(),
+		})
+	}
+}
+
+impl AsRef<TaggedHash> for UnsignedPayerProof {
+	fn as_ref(&self) -> &TaggedHash {
+		&self.tagged_hash
+	}
+}
+
+/// A `PayerProof` is evidence that a [`Bolt12Invoice`] was paid by its payer.
+///
+/// It includes the recipient's signature over the invoice, the preimage of the invoice's payment
+/// hash, and a signature over the invoice by the payer's [`payer_id`]. Only some of the invoice's
+/// fields are disclosed.
+///
+/// [`payer_id`]: Self::payer_id
+#[derive(Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct PayerProof {
+	bytes: Vec<u8>,
+	contents: PayerProofContents,
+	payer_signature: Signature,
+	merkle_root: sha256::Hash,
+}
+
+/// The disclosed contents of a paid [`Bolt12Invoice`] along with the proof of payment.
+#[derive(Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+struct PayerProofContents {
+	payer_id: PublicKey,
+	payment_hash: PaymentHash,
+	amount_msats: u64,
+	created_at: Duration,
+	signing_pubkey: PublicKey,
+	description: Option<String>,
+	issuer: Option<String>,
+	quantity: Option<u64>,
+	payer_note: Option<String>,
+	invoice_signature: Signature,
+	preimage: PaymentPreimage,
+	note: Option<String>,
+}
+
+impl PayerProof {
+	/// The payer's public key used to sign the [`Bolt12Invoice`]'s invoice request or refund and
+	/// the proof.
+	pub fn payer_id(&self) -> PublicKey {
+		self.contents.payer_id
+	}
+
+	/// SHA256 hash of [`PayerProof::payment_preimage`] from the paid [`Bolt12Invoice`].
+	pub fn payment_hash(&self) -> PaymentHash {
+		self.contents.payment_hash
+	}
+
+	/// The preimage revealed to the payer upon payment of the [`Bolt12Invoice`].
+	pub fn payment_preimage(&self) -> PaymentPreimage {
+		self.contents.preimage
+	}
+
+	/// The minimum amount required for a successful payment of the [`Bolt12Invoice`].
+	pub fn amount_msats(&self) -> u64 {
+		self.contents.amount_msats
+	}
+
+	/// Duration since the Unix epoch when the [`Bolt12Invoice`] was created.
+	pub fn created_at(&self) -> Duration {
+		self.contents.created_at
+	}
+
+	/// The public key used by the recipient to sign the [`Bolt12Invoice`].
+	pub fn signing_pubkey(&self) -> PublicKey {
+		self.contents.signing_pubkey
+	}
+
+	/// The [`Bolt12Invoice::description`], if disclosed.
+	pub fn description(&self) -> Option<PrintableString> {
+		self.contents.description.as_ref().map(|description| PrintableString(description.as_str()))
+	}
+
+	/// The [`Bolt12Invoice::issuer`], if set and disclosed.
+	pub fn issuer(&self) -> Option<PrintableString> {
+		self.contents.issuer.as_ref().map(|issuer| PrintableString(issuer.as_str()))
+	}
+
+	/// The [`Bolt12Invoice::quantity`], if set and disclosed.
+	pub fn quantity(&self) -> Option<u64> {
+		self.contents.quantity
+	}
+
+	/// The [`Bolt12Invoice::payer_note`], if set and disclosed.
+	pub fn payer_note(&self) -> Option<PrintableString> {
+		self.contents.payer_note.as_ref().map(|payer_note| PrintableString(payer_note.as_str()))
+	}
+
+	/// A note from the payer covered by [`PayerProof::payer_signature`].
+	pub fn note(&self) -> Option<PrintableString> {
+		self.contents.note.as_ref().map(|note| PrintableString(note.as_str()))
+	}
+
+	/// Signature of the [`Bolt12Invoice`] verified using [`PayerProof::signing_pubkey`].
+	pub fn invoice_signature(&self) -> Signature {
+		self.contents.invoice_signature
+	}
+
+	/// Signature of the proof verified using [`PayerProof::payer_id`].
+	pub fn payer_signature(&self) -> Signature {
+		self.payer_signature
+	}
+
+	/// The merkle root of the [`Bolt12Invoice`]'s TLV stream, which was computed from the disclosed
+	/// fields.
+	pub fn invoice_merkle_root(&self) -> sha256::Hash {
+		self.merkle_root
+	}
+}
+
+impl PayerProofContents {
+	fn note_bytes(&self) -> &[u8] {
+		self.note.as_ref().map(|note| note.as_bytes()).unwrap_or(&[])
+	}
+}
+
+fn preimage_matches(preimage: &PaymentPreimage, payment_hash: &PaymentHash) -> bool {
+	sha256::Hash::hash(&preimage.0).to_byte_array() == payment_hash.0
+}
+
+impl AsRef<[u8]> for PayerProof {
+	fn as_ref(&self) -> &[u8] {
+		&self.bytes
+	}
+}
+
+impl Writeable for PayerProof {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		WithoutLength(&self.bytes).write(writer)
+	}
+}
+
+impl Bech32Encode for PayerProof {
+	const BECH32_HRP: &'static str = "lnp";
+}
+
+impl FromStr for PayerProof {
+	type Err = Bolt12ParseError;
+
+	fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+		PayerProof::from_bech32_str(s)
+	}
+}
+
+impl core::fmt::Display for PayerProof {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+		self.fmt_bech32_str(f)
+	}
+}
+
+impl TryFrom<Vec<u8>> for PayerProof {
+	type Error = Bolt12ParseError;
+
+	fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+		let parsed_proof = ParsedMessage::<FullPayerProofTlvStream>::try_from(bytes)?;
+		PayerProof::try_from(parsed_proof)
+	}
+}
+
+/// Valid type range for payer proof TLV records following the invoice signature.
+const PAYER_PROOF_TYPES: core::ops::RangeInclusive<u64> = 242..=1000;
+
+tlv_stream!(PayerProofTlvStream, PayerProofTlvStreamRef, PAYER_PROOF_TYPES, {
+	(242, preimage: PaymentPreimage),
+	(244, omitted_tlvs: (Vec<BigSize>, WithoutLength)),
+	(246, missing_hashes: (Vec<sha256::Hash>, WithoutLength)),
+	(248, leaf_hashes: (Vec<sha256::Hash>, WithoutLength)),
+	(250, payer_signature: Signature),
+	(252, note: (String, WithoutLength)),
+});
+
+type FullPayerProofTlvStream = (
+	OfferTlvStream, InvoiceRequestTlvStream, InvoiceTlvStream, SignatureTlvStream,
+	PayerProofTlvStream,
+);
+
+impl SeekReadable for FullPayerProofTlvStream {
+	fn read<R: io::Read + io::Seek>(r: &mut R) -> Result<Self, DecodeError> {
+		let offer = SeekReadable::read(r)?;
+		let invoice_request = SeekReadable::read(r)?;
+		let invoice = SeekReadable::read(r)?;
+		let signature = SeekReadable::read(r)?;
+		let payer_proof = SeekReadable::read(r)?;
+
+		Ok((offer, invoice_request, invoice, signature, payer_proof))
+	}
+}
+
+impl TryFrom<ParsedMessage<FullPayerProofTlvStream>> for PayerProof {
+	type Error = Bolt12ParseError;
+
+	fn try_from(proof: ParsedMessage<FullPayerProofTlvStream>) -> Result<Self, Self::Error> {
+		let ParsedMessage { bytes, tlv_stream } = proof;
+		let (
+			OfferTlvStream { description, issuer, .. },
+			InvoiceRequestTlvStream { quantity, payer_id, payer_note, .. },
+			InvoiceTlvStream { created_at, payment_hash, amount, node_id, .. },
+			SignatureTlvStream { signature },
+			PayerProofTlvStream {
+				preimage, omitted_tlvs, missing_hashes, leaf_hashes, payer_signature, note,
+			},
+		) = tlv_stream;
+
+		let payer_id = payer_id.ok_or(Bolt12SemanticError::MissingPayerId)?;
+		let payment_hash = payment_hash.ok_or(Bolt12SemanticError::MissingPaymentHash)?;
+		let amount_msats = amount.ok_or(Bolt12SemanticError::MissingAmount)?;
+		let created_at = created_at
+			.map(Duration::from_secs)
+			.ok_or(Bolt12SemanticError::MissingCreationTime)?;
+		let signing_pubkey = node_id.ok_or(Bolt12SemanticError::MissingSigningPubkey)?;
+		let invoice_signature = signature.ok_or(Bolt12SemanticError::MissingSignature)?;
+		let payer_signature = payer_signature.ok_or(Bolt12SemanticError::MissingSignature)?;
+		let preimage = preimage.ok_or(Bolt12SemanticError::MissingPaymentPreimage)?;
+
+		if !preimage_matches(&preimage, &payment_hash) {
+			return Err(Bolt12ParseError::InvalidSemantics(
+				Bolt12SemanticError::InvalidPaymentPreimage
+			));
+		}
+
+		let omitted_markers = omitted_tlvs
+			.unwrap_or_else(Vec::new)
+			.into_iter()
+			.map(|marker| marker.0)
+			.collect::<Vec<_>>();
+		let merkle_root = merkle::root_hash_from_disclosure(
+			&bytes, &leaf_hashes.unwrap_or_else(Vec::new), &omitted_markers,
+			&missing_hashes.unwrap_or_else(Vec::new),
+		).map_err(|_| Bolt12SemanticError::InvalidSelectiveDisclosure)?;
+
+		let tagged_hash = TaggedHash::from_merkle_root(SIGNATURE_TAG, &[], merkle_root);
+		merkle::verify_signature(&invoice_signature, &tagged_hash, signing_pubkey)?;
+
+		let contents = PayerProofContents {
+			payer_id, payment_hash, amount_msats, created_at, signing_pubkey, description, issuer,
+			quantity, payer_note, invoice_signature, preimage, note,
+		};
+
+		let tagged_hash = TaggedHash::from_merkle_root(
+			PAYER_SIGNATURE_TAG, contents.note_bytes(), merkle_root
+		);
+		merkle::verify_signature(&payer_signature, &tagged_hash, payer_id)?;
+
+		Ok(PayerProof { bytes, contents, payer_signature, merkle_root })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{PAYER_SIGNATURE_TAG, PayerProof};
+
+	use bitcoin::hashes::{Hash, sha256};
+	use bitcoin::secp256k1::{KeyPair, Secp256k1, SecretKey};
+	use core::convert::{Infallible, TryFrom};
+	use crate::ln::{PaymentHash, PaymentPreimage};
+	use crate::offers::invoice::{Bolt12Invoice, SIGNATURE_TAG};
+	use crate::offers::merkle::{SignError, TaggedHash};
+	use crate::offers::offer::OfferBuilder;
+	use crate::offers::parse::{Bolt12ParseError, Bolt12SemanticError};
+	use crate::offers::test_utils::*;
+	use crate::util::ser::Writeable;
+	use crate::util::string::PrintableString;
+
+	fn preimage() -> PaymentPreimage {
+		PaymentPreimage([1; 32])
+	}
+
+	fn invoice() -> Bolt12Invoice {
+		let payment_hash = PaymentHash(sha256::Hash::hash(&preimage().0).to_byte_array());
+		OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.issuer("bar".into())
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.payer_note("baz".into())
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash, now()).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap()
+	}
+
+	#[test]
+	fn builds_payer_proof_with_defaults() {
+		let invoice = invoice();
+		let proof = invoice
+			.payer_proof_builder(preimage()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+
+		let mut buffer = Vec::new();
+		proof.write(&mut buffer).unwrap();
+
+		assert_eq!(proof.bytes, buffer.as_slice());
+		assert_eq!(proof.payer_id(), payer_pubkey());
+		assert_eq!(proof.payment_hash(), invoice.payment_hash());
+		assert_eq!(proof.payment_preimage(), preimage());
+		assert_eq!(proof.amount_msats(), 1000);
+		assert_eq!(proof.created_at(), invoice.created_at());
+		assert_eq!(proof.signing_pubkey(), recipient_pubkey());
+		assert_eq!(proof.description(), None);
+		assert_eq!(proof.issuer(), None);
+		assert_eq!(proof.quantity(), None);
+		assert_eq!(proof.payer_note(), None);
+		assert_eq!(proof.note(), None);
+		assert_eq!(proof.invoice_signature(), invoice.signature());
+
+		let merkle_root = TaggedHash::new(SIGNATURE_TAG, &invoice.bytes).merkle_root();
+		assert_eq!(proof.invoice_merkle_root(), merkle_root);
+
+		let tagged_hash = TaggedHash::from_merkle_root(PAYER_SIGNATURE_TAG, &[], merkle_root);
+		let secp_ctx = Secp256k1::verification_only();
+		assert!(
+			secp_ctx.verify_schnorr(
+				&proof.payer_signature(), tagged_hash.as_digest(), &payer_pubkey().into()
+			).is_ok()
+		);
+
+		match PayerProof::try_from(buffer) {
+			Err(e) => panic!("error parsing payer proof: {:?}", e),
+			Ok(parsed) => assert_eq!(parsed, proof),
+		}
+	}
+
+	#[test]
+	fn builds_payer_proof_with_disclosed_fields() {
+		let invoice = invoice();
+		let proof = invoice
+			.payer_proof_builder(preimage()).unwrap()
+			.include_description()
+			.include_issuer()
+			.include_quantity()
+			.include_payer_note()
+			.note("refund for order #12345".into())
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+
+		assert_eq!(proof.description(), Some(PrintableString("foo")));
+		assert_eq!(proof.issuer(), Some(PrintableString("bar")));
+		assert_eq!(proof.quantity(), None);
+		assert_eq!(proof.payer_note(), Some(PrintableString("baz")));
+		assert_eq!(proof.note(), Some(PrintableString("refund for order #12345")));
+
+		let encoded_proof = proof.to_string();
+		assert!(encoded_proof.starts_with("lnp1"));
+		match encoded_proof.parse::<PayerProof>() {
+			Err(e) => panic!("error parsing payer proof: {:?}", e),
+			Ok(parsed) => assert_eq!(parsed, proof),
+		}
+	}
+
+	#[test]
+	fn fails_building_payer_proof_with_invalid_preimage() {
+		match invoice().payer_proof_builder(PaymentPreimage([2; 32])) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::InvalidPaymentPreimage),
+		}
+	}
+
+	#[test]
+	fn fails_signing_payer_proof_with_wrong_keys() {
+		let secp_ctx = Secp256k1::new();
+		let keys = KeyPair::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[43; 32]).unwrap());
+
+		match invoice()
+			.payer_proof_builder(preimage()).unwrap()
+			.build().unwrap()
+			.sign::<_, Infallible>(
+				|message| Ok(secp_ctx.sign_schnorr_no_aux_rand(message.as_ref().as_digest(), &keys))
+			)
+		{
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert!(matches!(e, SignError::Verification(_))),
+		}
+	}
+
+	#[test]
+	fn fails_parsing_payer_proof_with_modified_fields() {
+		let proof = invoice()
+			.payer_proof_builder(preimage()).unwrap()
+			.note("foo".into())
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+
+		// Changing the disclosed amount changes the merkle root, invalidating the invoice signature.
+		let mut modified = proof.bytes.clone();
+		let amount_record = [170, 2, 3, 232];
+		let position = modified.windows(4).position(|bytes| bytes == amount_record).unwrap();
+		modified[position + 3] = 233;
+		match PayerProof::try_from(modified) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert!(matches!(e, Bolt12ParseError::InvalidSignature(_))),
+		}
+
+		// Changing the note invalidates the payer signature.
+		let mut modified = proof.bytes.clone();
+		let last = modified.len() - 1;
+		modified[last] = b'g';
+		match PayerProof::try_from(modified) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert!(matches!(e, Bolt12ParseError::InvalidSignature(_))),
+		}
+	}
+
+	#[test]
+	fn fails_parsing_payer_proof_with_invalid_disclosure() {
+		let mut unsigned_proof = invoice()
+			.payer_proof_builder(preimage()).unwrap()
+			.include_issuer()
+			.build().unwrap();
+		assert!(unsigned_proof.missing_hashes.pop().is_some());
+		let proof = unsigned_proof.sign(payer_sign).unwrap();
+		match PayerProof::try_from(proof.bytes) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(
+				e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::InvalidSelectiveDisclosure)
+			),
+		}
+
+		let mut unsigned_proof = invoice()
+			.payer_proof_builder(preimage()).unwrap()
+			.include_issuer()
+			.build().unwrap();
+		unsigned_proof.leaf_hashes.pop();
+		let proof = unsigned_proof.sign(payer_sign).unwrap();
+		match PayerProof::try_from(proof.bytes) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(
+				e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::InvalidSelectiveDisclosure)
+			),
+		}
+
+		let mut unsigned_proof = invoice()
+			.payer_proof_builder(preimage()).unwrap()
+			.include_issuer()
+			.build().unwrap();
+		assert!(unsigned_proof.omitted_tlvs.pop().is_some());
+		let proof = unsigned_proof.sign(payer_sign).unwrap();
+		assert!(PayerProof::try_from(proof.bytes).is_err());
+	}
+}
@@ -0,0 +1,356 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Data structures and encoding for recurring payments of an [`Offer`].
+//!
+//! A recurring offer is paid once per period, where each payment is made by requesting a new
+//! invoice with an incremented [`InvoiceRequest::recurrence_counter`] using the same
+//! [`InvoiceRequest::payer_id`]. Periods begin at a basetime, which is either given by the offer in
+//! a [`RecurrenceBase`] or is the creation time of the invoice for the first period, and may only
+//! be paid during their paywindow.
+//!
+//! [`Offer`]: crate::offers::offer::Offer
+//! [`InvoiceRequest::recurrence_counter`]: crate::offers::invoice_request::InvoiceRequest::recurrence_counter
+//! [`InvoiceRequest::payer_id`]: crate::offers::invoice_request::InvoiceRequest::payer_id
+
+use core::time::Duration;
+use crate::io;
+use crate::ln::msgs::DecodeError;
+use crate::util::ser::{HighZeroBytesDroppedBigSize, Readable, Writeable, Writer};
+
+use crate::prelude::*;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How often an [`Offer`] is expected to be paid.
+///
+/// [`Offer`]: crate::offers::offer::Offer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Recurrence {
+	/// The unit of time in which [`Recurrence::period`] is given.
+	pub time_unit: RecurrenceTimeUnit,
+	/// The number of time units in each period. Must be non-zero.
+	pub period: u32,
+}
+
+/// The unit of time for a [`Recurrence::period`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecurrenceTimeUnit {
+	/// Periods are a number of seconds long.
+	Seconds,
+	/// Periods are a number of days long, where each day is 86,400 seconds.
+	Days,
+	/// Periods are a number of calendar months long. Periods begin on the same day of the month as
+	/// the basetime (or the last day of shorter months) at the same time of day in UTC.
+	Months,
+}
+
+/// When the first period of a recurring [`Offer`] begins.
+///
+/// [`Offer`]: crate::offers::offer::Offer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecurrenceBase {
+	/// Whether the payer may start paying at any period rather than only the first one, in which
+	/// case [`InvoiceRequest::recurrence_start`] must be set.
+	///
+	/// [`InvoiceRequest::recurrence_start`]: crate::offers::invoice_request::InvoiceRequest::recurrence_start
+	pub start_any_period: bool,
+	/// Seconds since the Unix epoch when the first period begins.
+	pub basetime: u64,
+}
+
+/// When a period of a recurring [`Offer`] may be paid relative to its start.
+///
+/// If not given by the offer, a period may be paid from the start of the preceding period until the
+/// period ends.
+///
+/// [`Offer`]: crate::offers::offer::Offer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecurrencePaywindow {
+	/// Number of seconds before the period starts when it may be paid.
+	pub seconds_before: u32,
+	/// Whether the amount for the period is reduced in proportion to how much of the period has
+	/// elapsed when requesting an invoice after the period started.
+	pub proportional_amount: bool,
+	/// Number of seconds after the period starts when it may still be paid.
+	pub seconds_after: u32,
+}
+
+impl Recurrence {
+	/// Returns the start of the period with the given index, where period `0` begins at `basetime`,
+	/// or `None` if it is not representable.
+	pub fn period_start(&self, basetime: Duration, period_index: u32) -> Option<Duration> {
+		let basetime = basetime.as_secs();
+		let periods = (self.period as u64).checked_mul(period_index as u64)?;
+		let start = match self.time_unit {
+			RecurrenceTimeUnit::Seconds => basetime.checked_add(periods)?,
+			RecurrenceTimeUnit::Days => {
+				basetime.checked_add(periods.checked_mul(SECONDS_PER_DAY)?)?
+			},
+			RecurrenceTimeUnit::Months => add_months(basetime, periods)?,
+		};
+		Some(Duration::from_secs(start))
+	}
+
+	/// Returns the range of time during which the period with the given index may be paid, given
+	/// the offer's optional `paywindow`. Both ends of the range are inclusive.
+	pub fn paywindow(
+		&self, basetime: Duration, period_index: u32, paywindow: Option<&RecurrencePaywindow>
+	) -> Option<(Duration, Duration)> {
+		let start = self.period_start(basetime, period_index)?;
+		match paywindow {
+			Some(paywindow) => Some((
+				start.saturating_sub(Duration::from_secs(paywindow.seconds_before as u64)),
+				start.checked_add(Duration::from_secs(paywindow.seconds_after as u64))?,
+			)),
+			None => {
+				let earliest = match period_index.checked_sub(1) {
+					Some(previous_index) => self.period_start(basetime, previous_index)?,
+					None => Duration::ZERO,
+				};
+				let end = self.period_start(basetime, period_index.checked_add(1)?)?;
+				Some((earliest, end.saturating_sub(Duration::from_secs(1))))
+			},
+		}
+	}
+
+	/// Returns `amount_msats` reduced in proportion to how much of the period with the given index
+	/// has elapsed at `now`, as used when [`RecurrencePaywindow::proportional_amount`] is set, or
+	/// `None` if the period is not representable. The amount is not reduced before the period
+	/// starts.
+	pub fn proportional_amount_msats(
+		&self, basetime: Duration, period_index: u32, amount_msats: u64, now: Duration
+	) -> Option<u64> {
+		let start = self.period_start(basetime, period_index)?;
+		let end = self.period_start(basetime, period_index.checked_add(1)?)?;
+		if now <= start || end <= start {
+			return Some(amount_msats);
+		}
+
+		let remaining_secs = end.saturating_sub(now).as_secs() as u128;
+		let period_secs = (end - start).as_secs() as u128;
+		Some((amount_msats as u128 * remaining_secs / period_secs) as u64)
+	}
+}
+
+/// Adds `months` calendar months to `timestamp`, given as seconds since the Unix epoch, clamping the
+/// day of the month to the last day of the resulting month.
+fn add_months(timestamp: u64, months: u64) -> Option<u64> {
+	let days = timestamp / SECONDS_PER_DAY;
+	let seconds_of_day = timestamp % SECONDS_PER_DAY;
+
+	let (year, month, day) = civil_from_days(days);
+	let total_months = year.checked_mul(12)?.checked_add(month - 1)?.checked_add(months)?;
+	let year = total_months / 12;
+	let month = total_months % 12 + 1;
+	let day = core::cmp::min(day, days_in_month(year, month));
+
+	days_from_civil(year, month, day)
+		.checked_mul(SECONDS_PER_DAY)?
+		.checked_add(seconds_of_day)
+}
+
+fn is_leap_year(year: u64) -> bool {
+	(year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+	match month {
+		2 if is_leap_year(year) => 29,
+		2 => 28,
+		4 | 6 | 9 | 11 => 30,
+		_ => 31,
+	}
+}
+
+/// Converts days since the Unix epoch into a `(year, month, day)` date in the proleptic Gregorian
+/// calendar, where months and days are 1-indexed.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+	// Shift the epoch to 0000-03-01 so that leap days fall at the end of each 400-year era.
+	let days = days + 719_468;
+	let era = days / 146_097;
+	let day_of_era = days % 146_097;
+	let year_of_era =
+		(day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let shifted_month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+	let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+	(year, month, day)
+}
+
+/// Converts a `(year, month, day)` date in the proleptic Gregorian calendar into days since the
+/// Unix epoch. The inverse of [`civil_from_days`].
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year / 400;
+	let year_of_era = year % 400;
+	let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+	let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	era * 146_097 + day_of_era - 719_468
+}
+
+impl Writeable for Recurrence {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		let time_unit: u8 = match self.time_unit {
+			RecurrenceTimeUnit::Seconds => 0,
+			RecurrenceTimeUnit::Days => 1,
+			RecurrenceTimeUnit::Months => 2,
+		};
+		time_unit.write(writer)?;
+		HighZeroBytesDroppedBigSize(self.period).write(writer)
+	}
+}
+
+impl Readable for Recurrence {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		let time_unit = match <u8 as Readable>::read(reader)? {
+			0 => RecurrenceTimeUnit::Seconds,
+			1 => RecurrenceTimeUnit::Days,
+			2 => RecurrenceTimeUnit::Months,
+			_ => return Err(DecodeError::UnknownRequiredFeature),
+		};
+		let period: HighZeroBytesDroppedBigSize<u32> = Readable::read(reader)?;
+		Ok(Recurrence { time_unit, period: period.0 })
+	}
+}
+
+impl Writeable for RecurrenceBase {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		(self.start_any_period as u8).write(writer)?;
+		HighZeroBytesDroppedBigSize(self.basetime).write(writer)
+	}
+}
+
+impl Readable for RecurrenceBase {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		let start_any_period = match <u8 as Readable>::read(reader)? {
+			0 => false,
+			1 => true,
+			_ => return Err(DecodeError::InvalidValue),
+		};
+		let basetime: HighZeroBytesDroppedBigSize<u64> = Readable::read(reader)?;
+		Ok(RecurrenceBase { start_any_period, basetime: basetime.0 })
+	}
+}
+
+impl Writeable for RecurrencePaywindow {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.seconds_before.write(writer)?;
+		(self.proportional_amount as u8).write(writer)?;
+		HighZeroBytesDroppedBigSize(self.seconds_after).write(writer)
+	}
+}
+
+impl Readable for RecurrencePaywindow {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		let seconds_before = Readable::read(reader)?;
+		let proportional_amount = match <u8 as Readable>::read(reader)? {
+			0 => false,
+			1 => true,
+			_ => return Err(DecodeError::InvalidValue),
+		};
+		let seconds_after: HighZeroBytesDroppedBigSize<u32> = Readable::read(reader)?;
+		Ok(RecurrencePaywindow { seconds_before, proportional_amount, seconds_after: seconds_after.0 })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Recurrence, RecurrencePaywindow, RecurrenceTimeUnit};
+
+	use core::time::Duration;
+
+	// 2023-01-31T12:00:00Z
+	const BASETIME: u64 = 1_675_166_400;
+
+	#[test]
+	fn computes_period_start_in_seconds_and_days() {
+		let basetime = Duration::from_secs(BASETIME);
+
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Seconds, period: 3600 };
+		assert_eq!(recurrence.period_start(basetime, 0), Some(basetime));
+		assert_eq!(recurrence.period_start(basetime, 3), Some(Duration::from_secs(BASETIME + 10_800)));
+
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 7 };
+		assert_eq!(
+			recurrence.period_start(basetime, 2),
+			Some(Duration::from_secs(BASETIME + 14 * 86_400)),
+		);
+
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Seconds, period: u32::MAX };
+		let basetime = Duration::from_secs(u64::MAX - 1);
+		assert_eq!(recurrence.period_start(basetime, 1), None);
+	}
+
+	#[test]
+	fn computes_period_start_in_months() {
+		let basetime = Duration::from_secs(BASETIME);
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Months, period: 1 };
+
+		// 2023-02-28T12:00:00Z since February is shorter.
+		assert_eq!(recurrence.period_start(basetime, 1), Some(Duration::from_secs(1_677_585_600)));
+		// 2023-03-31T12:00:00Z
+		assert_eq!(recurrence.period_start(basetime, 2), Some(Duration::from_secs(1_680_264_000)));
+		// 2024-02-29T12:00:00Z in a leap year.
+		assert_eq!(recurrence.period_start(basetime, 13), Some(Duration::from_secs(1_709_208_000)));
+
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Months, period: 12 };
+		// 2025-01-31T12:00:00Z
+		assert_eq!(recurrence.period_start(basetime, 2), Some(Duration::from_secs(1_738_324_800)));
+	}
+
+	#[test]
+	fn computes_paywindow() {
+		let basetime = Duration::from_secs(BASETIME);
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 1 };
+
+		assert_eq!(
+			recurrence.paywindow(basetime, 0, None),
+			Some((Duration::ZERO, Duration::from_secs(BASETIME + 86_399))),
+		);
+		assert_eq!(
+			recurrence.paywindow(basetime, 2, None),
+			Some((
+				Duration::from_secs(BASETIME + 86_400), Duration::from_secs(BASETIME + 3 * 86_400 - 1)
+			)),
+		);
+
+		let paywindow = RecurrencePaywindow {
+			seconds_before: 60, proportional_amount: false, seconds_after: 3600,
+		};
+		assert_eq!(
+			recurrence.paywindow(basetime, 2, Some(&paywindow)),
+			Some((
+				Duration::from_secs(BASETIME + 2 * 86_400 - 60),
+				Duration::from_secs(BASETIME + 2 * 86_400 + 3600),
+			)),
+		);
+	}
+
+	#[test]
+	fn computes_proportional_amount() {
+		let basetime = Duration::from_secs(BASETIME);
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 1 };
+		let period_start = BASETIME + 86_400;
+
+		// Nothing has elapsed before or at the start of the period.
+		let now = Duration::from_secs(period_start - 60);
+		assert_eq!(recurrence.proportional_amount_msats(basetime, 1, 1000, now), Some(1000));
+		let now = Duration::from_secs(period_start);
+		assert_eq!(recurrence.proportional_amount_msats(basetime, 1, 1000, now), Some(1000));
+
+		let now = Duration::from_secs(period_start + 21_600);
+		assert_eq!(recurrence.proportional_amount_msats(basetime, 1, 1000, now), Some(750));
+		let now = Duration::from_secs(period_start + 86_400);
+		assert_eq!(recurrence.proportional_amount_msats(basetime, 1, 1000, now), Some(0));
+	}
+}
@@ -625,6 +625,10 @@ impl RefundContents {
 			issuer: self.issuer.as_ref(),
 			quantity_max: None,
 			node_id: None,
+			recurrence: None,
+			recurrence_base: None,
+			recurrence_paywindow: None,
+			recurrence_limit: None,
 		};
 
 		let features = {
@@ -639,6 +643,8 @@ impl RefundContents {
 			quantity: self.quantity,
 			payer_id: Some(&self.payer_id),
 			payer_note: self.payer_note.as_ref(),
+			recurrence_counter: None,
+			recurrence_start: None,
 		};
 
 		(payer, offer, invoice_request)
@@ -708,8 +714,12 @@ impl TryFrom<RefundTlvStream> for RefundContents {
 			OfferTlvStream {
 				chains, metadata, currency, amount: offer_amount, description,
 				features: offer_features, absolute_expiry, paths, issuer, quantity_max, node_id,
+				recurrence, recurrence_base, recurrence_paywindow, recurrence_limit,
+			},
+			InvoiceRequestTlvStream {
+				chain, amount, features, quantity, payer_id, payer_note, recurrence_counter,
+				recurrence_start,
 			},
-			InvoiceRequestTlvStream { chain, amount, features, quantity, payer_id, payer_note },
 		) = tlv_stream;
 
 		let payer = match payer_metadata {
@@ -748,6 +758,12 @@ impl TryFrom<RefundTlvStream> for RefundContents {
 			return Err(Bolt12SemanticError::UnexpectedSigningPubkey);
 		}
 
+		if recurrence.is_some() || recurrence_base.is_some() || recurrence_paywindow.is_some() ||
+			recurrence_limit.is_some() || recurrence_counter.is_some() || recurrence_start.is_some()
+		{
+			return Err(Bolt12SemanticError::UnexpectedRecurrence);
+		}
+
 		let amount_msats = match amount {
 			None => return Err(Bolt12SemanticError::MissingAmount),
 			Some(amount_msats) if amount_msats > MAX_VALUE_MSAT => {
@@ -795,6 +811,7 @@ mod tests {
 	use crate::offers::offer::OfferTlvStreamRef;
 	use crate::offers::parse::{Bolt12ParseError, Bolt12SemanticError};
 	use crate::offers::payer::PayerTlvStreamRef;
+	use crate::offers::recurrence::{Recurrence, RecurrenceTimeUnit};
 	use crate::offers::test_utils::*;
 	use crate::util::ser::{BigSize, Writeable};
 	use crate::util::string::PrintableString;
@@ -849,6 +866,10 @@ mod tests {
 					issuer: None,
 					quantity_max: None,
 					node_id: None,
+					recurrence: None,
+					recurrence_base: None,
+					recurrence_paywindow: None,
+					recurrence_limit: None,
 				},
 				InvoiceRequestTlvStreamRef {
 					chain: None,
@@ -857,6 +878,8 @@ mod tests {
 					quantity: None,
 					payer_id: Some(&payer_pubkey()),
 					payer_note: None,
+					recurrence_counter: None,
+					recurrence_start: None,
 				},
 			),
 		);
@@ -1361,6 +1384,27 @@ mod tests {
 				assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::UnexpectedSigningPubkey));
 			},
 		}
+
+		let recurrence = Recurrence { time_unit: RecurrenceTimeUnit::Days, period: 30 };
+		let mut tlv_stream = refund.as_tlv_stream();
+		tlv_stream.1.recurrence = Some(&recurrence);
+
+		match Refund::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => {
+				assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::UnexpectedRecurrence));
+			},
+		}
+
+		let mut tlv_stream = refund.as_tlv_stream();
+		tlv_stream.2.recurrence_counter = Some(0);
+
+		match Refund::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => {
+				assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::UnexpectedRecurrence));
+			},
+		}
 	}
 
 	#[test]
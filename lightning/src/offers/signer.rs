@@ -230,12 +230,13 @@ pub(super) fn derive_keys(nonce: Nonce, expanded_key: &ExpandedKey) -> KeyPair {
 /// If the latter is not included in the metadata, the TLV stream is used to check if the given
 /// `signing_pubkey` can be derived from it.
 ///
-/// Returns the [`PaymentId`] that should be used for sending the payment.
+/// Returns the [`PaymentId`] that should be used for sending the payment along with the
+/// [`KeyPair`] for `signing_pubkey`, if it can be derived from the metadata.
 pub(super) fn verify_payer_metadata<'a, T: secp256k1::Signing>(
 	metadata: &[u8], expanded_key: &ExpandedKey, iv_bytes: &[u8; IV_LEN],
 	signing_pubkey: PublicKey, tlv_stream: impl core::iter::Iterator<Item = TlvRecord<'a>>,
 	secp_ctx: &Secp256k1<T>
-) -> Result<PaymentId, ()> {
+) -> Result<(PaymentId, Option<KeyPair>), ()> {
 	if metadata.len() < PaymentId::LENGTH {
 		return Err(());
 	}
@@ -249,14 +250,14 @@ pub(super) fn verify_payer_metadata<'a, T: secp256k1::Signing>(
 	hmac.input(WITH_ENCRYPTED_PAYMENT_ID_HMAC_INPUT);
 	hmac.input(&encrypted_payment_id);
 
-	verify_metadata(
+	let keys = verify_metadata(
 		&metadata[PaymentId::LENGTH..], Hmac::from_engine(hmac), signing_pubkey, secp_ctx
 	)?;
 
 	let nonce = Nonce::try_from(&metadata[PaymentId::LENGTH..][..Nonce::LENGTH]).unwrap();
 	let payment_id = expanded_key.crypt_for_offer(encrypted_payment_id, nonce);
 
-	Ok(PaymentId(payment_id))
+	Ok((PaymentId(payment_id), keys))
 }
 
 /// Verifies data given in a TLV stream was used to produce the given metadata, consisting of:
@@ -0,0 +1,844 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Data structures and encoding for static BOLT 12 invoices.
+//!
+//! A [`StaticInvoice`] is a long-lived invoice for an [`Offer`], which an often-offline recipient
+//! hands to an always-online node (e.g., its LSP). That node may then respond to
+//! [`InvoiceRequest`]s for the [`Offer`] on the recipient's behalf while it is offline.
+//!
+//! Unlike a [`Bolt12Invoice`], a static invoice doesn't include a payment hash and may be paid
+//! multiple times. Instead, it contains [`StaticInvoice::message_paths`] over which the payer
+//! notifies the recipient's LSP of an async payment (see [`AsyncPaymentsMessage`]).
+//!
+//! [`Offer`]: crate::offers::offer::Offer
+//! [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+//! [`Bolt12Invoice`]: crate::offers::invoice::Bolt12Invoice
+//! [`AsyncPaymentsMessage`]: crate::onion_message::AsyncPaymentsMessage
+
+use bitcoin::blockdata::constants::ChainHash;
+use bitcoin::hash_types::{WPubkeyHash, WScriptHash};
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{KeyPair, PublicKey, Secp256k1, self};
+use bitcoin::secp256k1::schnorr::Signature;
+use bitcoin::address::{Address, WitnessVersion};
+use bitcoin::key::TweakedPublicKey;
+use core::convert::{AsRef, Infallible, TryFrom};
+use core::time::Duration;
+use crate::io;
+use crate::blinded_path::BlindedPath;
+use crate::ln::features::{Bolt12InvoiceFeatures, OfferFeatures};
+use crate::ln::inbound_payment::ExpandedKey;
+use crate::ln::msgs::DecodeError;
+use crate::offers::invoice::{BlindedPayInfo, DEFAULT_RELATIVE_EXPIRY, FallbackAddress, InvoiceTlvStream, InvoiceTlvStreamRef, SIGNATURE_TAG, filter_fallbacks};
+use crate::offers::merkle::{SignError, SignatureTlvStream, SignatureTlvStreamRef, TaggedHash, TlvStream, self};
+use crate::offers::offer::{Amount, OFFER_TYPES, Offer, OfferContents, OfferTlvStream, OfferTlvStreamRef, Quantity};
+use crate::offers::parse::{Bolt12ParseError, Bolt12SemanticError, ParsedMessage};
+use crate::util::ser::{Iterable, SeekReadable, WithoutLength, Writeable, Writer};
+use crate::util::string::PrintableString;
+
+use crate::prelude::*;
+
+#[cfg(feature = "std")]
+use std::time::SystemTime;
+
+/// Builds a [`StaticInvoice`] for an [`Offer`] created using derived keys.
+///
+/// See [module-level documentation] for usage.
+///
+/// This is not exported to bindings users as builder patterns don't map outside of move semantics.
+///
+/// [module-level documentation]: self
+pub struct StaticInvoiceBuilder<'a> {
+	offer_bytes: &'a Vec<u8>,
+	invoice: InvoiceContents,
+	keys: KeyPair,
+}
+
+impl<'a> StaticInvoiceBuilder<'a> {
+	/// Initializes a [`StaticInvoice`] builder for the given `offer`, which must have been built
+	/// using [`OfferBuilder::deriving_signing_pubkey`] with the same `expanded_key`.
+	///
+	/// The invoice will be paid over `payment_paths` once the payer's HTLCs are released by the
+	/// recipient, who is notified of them over `message_paths`. Neither may be empty.
+	///
+	/// [`OfferBuilder::deriving_signing_pubkey`]: crate::offers::offer::OfferBuilder::deriving_signing_pubkey
+	pub fn for_offer_using_derived_keys<T: secp256k1::Signing>(
+		offer: &'a Offer, payment_paths: Vec<(BlindedPayInfo, BlindedPath)>,
+		message_paths: Vec<BlindedPath>, created_at: Duration, expanded_key: &ExpandedKey,
+		secp_ctx: &Secp256k1<T>
+	) -> Result<Self, Bolt12SemanticError> {
+		if payment_paths.is_empty() || message_paths.is_empty() {
+			return Err(Bolt12SemanticError::MissingPaths);
+		}
+		if offer.contents.is_expired_no_std(created_at) {
+			return Err(Bolt12SemanticError::AlreadyExpired);
+		}
+
+		let keys = match offer.contents.verify(&offer.bytes, expanded_key, secp_ctx) {
+			Ok(Some(keys)) => keys,
+			Ok(None) | Err(()) => return Err(Bolt12SemanticError::InvalidMetadata),
+		};
+
+		let invoice = InvoiceContents {
+			offer: offer.contents.clone(),
+			payment_paths,
+			message_paths,
+			created_at,
+			relative_expiry: None,
+			fallbacks: None,
+			features: Bolt12InvoiceFeatures::empty(),
+			signing_pubkey: keys.public_key(),
+		};
+
+		Ok(Self { offer_bytes: &offer.bytes, invoice, keys })
+	}
+
+	/// Sets the [`StaticInvoice::relative_expiry`] as seconds since [`StaticInvoice::created_at`].
+	/// Any expiry that has already passed is valid and can be checked for using
+	/// [`StaticInvoice::is_expired`].
+	///
+	/// Successive calls to this method will override the previous setting.
+	pub fn relative_expiry(mut self, relative_expiry_secs: u32) -> Self {
+		let relative_expiry = Duration::from_secs(relative_expiry_secs as u64);
+		self.invoice.relative_expiry = Some(relative_expiry);
+		self
+	}
+
+	/// Adds a P2WSH address to [`StaticInvoice::fallbacks`].
+	///
+	/// Successive calls to this method will add another address. Caller is responsible for not
+	/// adding duplicate addresses and only calling if capable of receiving to P2WSH addresses.
+	pub fn fallback_v0_p2wsh(mut self, script_hash: &WScriptHash) -> Self {
+		let address = FallbackAddress {
+			version: WitnessVersion::V0.to_num(),
+			program: Vec::from(script_hash.to_byte_array()),
+		};
+		self.invoice.fallbacks.get_or_insert_with(Vec::new).push(address);
+		self
+	}
+
+	/// Adds a P2WPKH address to [`StaticInvoice::fallbacks`].
+	///
+	/// Successive calls to this method will add another address. Caller is responsible for not
+	/// adding duplicate addresses and only calling if capable of receiving to P2WPKH addresses.
+	pub fn fallback_v0_p2wpkh(mut self, pubkey_hash: &WPubkeyHash) -> Self {
+		let address = FallbackAddress {
+			version: WitnessVersion::V0.to_num(),
+			program: Vec::from(pubkey_hash.to_byte_array()),
+		};
+		self.invoice.fallbacks.get_or_insert_with(Vec::new).push(address);
+		self
+	}
+
+	/// Adds a P2TR address to [`StaticInvoice::fallbacks`].
+	///
+	/// Successive calls to this method will add another address. Caller is responsible for not
+	/// adding duplicate addresses and only calling if capable of receiving to P2TR addresses.
+	pub fn fallback_v1_p2tr_tweaked(mut self, output_key: &TweakedPublicKey) -> Self {
+		let address = FallbackAddress {
+			version: WitnessVersion::V1.to_num(),
+			program: Vec::from(&output_key.serialize()[..]),
+		};
+		self.invoice.fallbacks.get_or_insert_with(Vec::new).push(address);
+		self
+	}
+
+	/// Sets [`StaticInvoice::invoice_features`] to indicate MPP may be used. Otherwise, MPP is
+	/// disallowed.
+	pub fn allow_mpp(mut self) -> Self {
+		self.invoice.features.set_basic_mpp_optional();
+		self
+	}
+
+	/// Builds a signed [`StaticInvoice`] after checking for valid semantics.
+	pub fn build_and_sign<T: secp256k1::Signing>(
+		self, secp_ctx: &Secp256k1<T>
+	) -> Result<StaticInvoice, Bolt12SemanticError> {
+		#[cfg(feature = "std")] {
+			if self.invoice.is_offer_expired() {
+				return Err(Bolt12SemanticError::AlreadyExpired);
+			}
+		}
+
+		let StaticInvoiceBuilder { offer_bytes, invoice, keys } = self;
+		let unsigned_invoice = UnsignedStaticInvoice::new(offer_bytes, invoice);
+
+		let invoice = unsigned_invoice
+			.sign::<_, Infallible>(
+				|message| Ok(secp_ctx.sign_schnorr_no_aux_rand(message.as_ref().as_digest(), &keys))
+			)
+			.unwrap();
+		Ok(invoice)
+	}
+}
+
+/// A semantically valid [`StaticInvoice`] that hasn't been signed.
+struct UnsignedStaticInvoice {
+	bytes: Vec<u8>,
+	contents: InvoiceContents,
+	tagged_hash: TaggedHash,
+}
+
+impl UnsignedStaticInvoice {
+	fn new(offer_bytes: &Vec<u8>, contents: InvoiceContents) -> Self {
+		// Use the offer bytes instead of the offer TLV stream as the latter may have contained
+		// unknown TLV records, which are not stored in `OfferContents`.
+		let (_, invoice_tlv_stream) = contents.as_tlv_stream();
+		let unsigned_tlv_stream = (WithoutLength(offer_bytes), invoice_tlv_stream);
+
+		let mut bytes = Vec::new();
+		unsigned_tlv_stream.write(&mut bytes).unwrap();
+
+		let tagged_hash = TaggedHash::new(SIGNATURE_TAG, &bytes);
+
+		Self { bytes, contents, tagged_hash }
+	}
+
+	fn sign<F, E>(mut self, sign: F) -> Result<StaticInvoice, SignError<E>>
+	where
+		F: FnOnce(&Self) -> Result<Signature, E>
+	{
+		let pubkey = self.contents.signing_pubkey;
+		let signature = merkle::sign_message(sign, &self, pubkey)?;
+
+		// Append the signature TLV record to the bytes.
+		let signature_tlv_stream = SignatureTlvStreamRef {
+			signature: Some(&signature),
+		};
+		signature_tlv_stream.write(&mut self.bytes).unwrap();
+
+		Ok(StaticInvoice {
+			bytes: self.bytes,
+			contents: self.contents,
+			signature,
+		})
+	}
+}
+
+impl AsRef<TaggedHash> for UnsignedStaticInvoice {
+	fn as_ref(&self) -> &TaggedHash {
+		&self.tagged_hash
+	}
+}
+
+/// A `StaticInvoice` is a reusable payment request corresponding to an [`Offer`].
+///
+/// It is built by an often-offline recipient and served by another node on its behalf in response
+/// to [`InvoiceRequest`]s. Rather than a payment hash, it includes paths for notifying the
+/// recipient of a payment being held for it.
+///
+/// [`Offer`]: crate::offers::offer::Offer
+/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+#[derive(Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct StaticInvoice {
+	bytes: Vec<u8>,
+	contents: InvoiceContents,
+	signature: Signature,
+}
+
+/// The contents of a [`StaticInvoice`] for responding to an [`Offer`].
+///
+/// [`Offer`]: crate::offers::offer::Offer
+#[derive(Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+struct InvoiceContents {
+	offer: OfferContents,
+	payment_paths: Vec<(BlindedPayInfo, BlindedPath)>,
+	message_paths: Vec<BlindedPath>,
+	created_at: Duration,
+	relative_expiry: Option<Duration>,
+	fallbacks: Option<Vec<FallbackAddress>>,
+	features: Bolt12InvoiceFeatures,
+	signing_pubkey: PublicKey,
+}
+
+impl StaticInvoice {
+	/// The chain that must be used when paying the invoice, as implied by [`Offer::chains`].
+	///
+	/// [`Offer::chains`]: crate::offers::offer::Offer::chains
+	pub fn chain(&self) -> ChainHash {
+		self.contents.offer.implied_chain()
+	}
+
+	/// Opaque bytes set by the originating [`Offer`].
+	///
+	/// From [`Offer::metadata`].
+	///
+	/// [`Offer::metadata`]: crate::offers::offer::Offer::metadata
+	pub fn metadata(&self) -> Option<&Vec<u8>> {
+		self.contents.offer.metadata()
+	}
+
+	/// The minimum amount required for a successful payment of a single item.
+	///
+	/// From [`Offer::amount`].
+	///
+	/// [`Offer::amount`]: crate::offers::offer::Offer::amount
+	pub fn amount(&self) -> Option<&Amount> {
+		self.contents.offer.amount()
+	}
+
+	/// Features pertaining to the originating [`Offer`].
+	///
+	/// From [`Offer::offer_features`].
+	///
+	/// [`Offer::offer_features`]: crate::offers::offer::Offer::offer_features
+	pub fn offer_features(&self) -> &OfferFeatures {
+		self.contents.offer.features()
+	}
+
+	/// A complete description of the purpose of the originating offer.
+	///
+	/// From [`Offer::description`].
+	///
+	/// [`Offer::description`]: crate::offers::offer::Offer::description
+	pub fn description(&self) -> PrintableString {
+		self.contents.offer.description()
+	}
+
+	/// Duration since the Unix epoch when an invoice should no longer be requested.
+	///
+	/// From [`Offer::absolute_expiry`].
+	///
+	/// [`Offer::absolute_expiry`]: crate::offers::offer::Offer::absolute_expiry
+	pub fn absolute_expiry(&self) -> Option<Duration> {
+		self.contents.offer.absolute_expiry()
+	}
+
+	/// The issuer of the offer.
+	///
+	/// From [`Offer::issuer`].
+	///
+	/// [`Offer::issuer`]: crate::offers::offer::Offer::issuer
+	pub fn issuer(&self) -> Option<PrintableString> {
+		self.contents.offer.issuer()
+	}
+
+	/// Paths to the node serving the invoice on the recipient's behalf, from [`Offer::paths`].
+	///
+	/// [`Offer::paths`]: crate::offers::offer::Offer::paths
+	pub fn offer_message_paths(&self) -> &[BlindedPath] {
+		self.contents.offer.paths()
+	}
+
+	/// The quantity of items supported.
+	///
+	/// From [`Offer::supported_quantity`].
+	///
+	/// [`Offer::supported_quantity`]: crate::offers::offer::Offer::supported_quantity
+	pub fn supported_quantity(&self) -> Quantity {
+		self.contents.offer.supported_quantity()
+	}
+
+	/// Paths to the recipient originating from publicly reachable nodes, including information
+	/// needed for routing payments across them.
+	///
+	/// This is not exported to bindings users as slices with non-reference types cannot be ABI
+	/// matched in another language.
+	pub fn payment_paths(&self) -> &[(BlindedPayInfo, BlindedPath)] {
+		&self.contents.payment_paths[..]
+	}
+
+	/// Paths over which the payer should send a [`HeldHtlcAvailable`] onion message once its HTLC
+	/// is being held for the recipient.
+	///
+	/// [`HeldHtlcAvailable`]: crate::onion_message::HeldHtlcAvailable
+	pub fn message_paths(&self) -> &[BlindedPath] {
+		&self.contents.message_paths[..]
+	}
+
+	/// Duration since the Unix epoch when the invoice was created.
+	pub fn created_at(&self) -> Duration {
+		self.contents.created_at
+	}
+
+	/// Duration since [`StaticInvoice::created_at`] when the invoice has expired and therefore
+	/// should no longer be paid.
+	pub fn relative_expiry(&self) -> Duration {
+		self.contents.relative_expiry()
+	}
+
+	/// Whether the invoice has expired.
+	#[cfg(feature = "std")]
+	pub fn is_expired(&self) -> bool {
+		match SystemTime::UNIX_EPOCH.elapsed() {
+			Ok(elapsed) => self.is_expired_no_std(elapsed),
+			Err(_) => false,
+		}
+	}
+
+	/// Whether the invoice has expired given the current time as duration since the Unix epoch.
+	pub fn is_expired_no_std(&self, duration_since_epoch: Duration) -> bool {
+		match self.created_at().checked_add(self.relative_expiry()) {
+			Some(absolute_expiry) => duration_since_epoch > absolute_expiry,
+			None => false,
+		}
+	}
+
+	/// Fallback addresses for paying the invoice on-chain, in order of most-preferred to
+	/// least-preferred.
+	pub fn fallbacks(&self) -> Vec<Address> {
+		filter_fallbacks(self.chain(), self.contents.fallbacks.as_ref())
+	}
+
+	/// Features pertaining to paying an invoice.
+	pub fn invoice_features(&self) -> &Bolt12InvoiceFeatures {
+		&self.contents.features
+	}
+
+	/// The public key corresponding to the key used to sign the invoice, which is the same as
+	/// [`Offer::signing_pubkey`].
+	///
+	/// [`Offer::signing_pubkey`]: crate::offers::offer::Offer::signing_pubkey
+	pub fn signing_pubkey(&self) -> PublicKey {
+		self.contents.signing_pubkey
+	}
+
+	/// Signature of the invoice verified using [`StaticInvoice::signing_pubkey`].
+	pub fn signature(&self) -> Signature {
+		self.signature
+	}
+
+	/// Whether the invoice was created for the given `offer`.
+	pub fn is_for_offer(&self, offer: &Offer) -> bool {
+		let invoice_offer_records = TlvStream::new(&self.bytes).range(OFFER_TYPES)
+			.map(|record| record.record_bytes);
+		let offer_records = TlvStream::new(&offer.bytes).range(OFFER_TYPES)
+			.map(|record| record.record_bytes);
+		invoice_offer_records.eq(offer_records)
+	}
+
+	pub(crate) fn as_tlv_stream(&self) -> FullInvoiceTlvStreamRef {
+		let (offer_tlv_stream, invoice_tlv_stream) = self.contents.as_tlv_stream();
+		let signature_tlv_stream = SignatureTlvStreamRef {
+			signature: Some(&self.signature),
+		};
+		(offer_tlv_stream, invoice_tlv_stream, signature_tlv_stream)
+	}
+}
+
+impl InvoiceContents {
+	#[cfg(feature = "std")]
+	fn is_offer_expired(&self) -> bool {
+		self.offer.is_expired()
+	}
+
+	fn relative_expiry(&self) -> Duration {
+		self.relative_expiry.unwrap_or(DEFAULT_RELATIVE_EXPIRY)
+	}
+
+	fn as_tlv_stream(&self) -> PartialInvoiceTlvStreamRef {
+		let features = {
+			if self.features == Bolt12InvoiceFeatures::empty() { None }
+			else { Some(&self.features) }
+		};
+
+		let invoice = InvoiceTlvStreamRef {
+			paths: Some(Iterable(self.payment_paths.iter().map(|(_, path)| path))),
+			blindedpay: Some(Iterable(self.payment_paths.iter().map(|(payinfo, _)| payinfo))),
+			created_at: Some(self.created_at.as_secs()),
+			relative_expiry: self.relative_expiry.map(|duration| duration.as_secs() as u32),
+			payment_hash: None,
+			amount: None,
+			fallbacks: self.fallbacks.as_ref(),
+			features,
+			node_id: Some(&self.signing_pubkey),
+			recurrence_basetime: None,
+			message_paths: Some(&self.message_paths),
+		};
+
+		(self.offer.as_tlv_stream(), invoice)
+	}
+}
+
+impl Writeable for StaticInvoice {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		WithoutLength(&self.bytes).write(writer)
+	}
+}
+
+impl TryFrom<Vec<u8>> for StaticInvoice {
+	type Error = Bolt12ParseError;
+
+	fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+		let parsed_invoice = ParsedMessage::<FullInvoiceTlvStream>::try_from(bytes)?;
+		StaticInvoice::try_from(parsed_invoice)
+	}
+}
+
+type FullInvoiceTlvStream = (OfferTlvStream, InvoiceTlvStream, SignatureTlvStream);
+
+type FullInvoiceTlvStreamRef<'a> = (
+	OfferTlvStreamRef<'a>,
+	InvoiceTlvStreamRef<'a>,
+	SignatureTlvStreamRef<'a>,
+);
+
+impl SeekReadable for FullInvoiceTlvStream {
+	fn read<R: io::Read + io::Seek>(r: &mut R) -> Result<Self, DecodeError> {
+		let offer = SeekReadable::read(r)?;
+		let invoice = SeekReadable::read(r)?;
+		let signature = SeekReadable::read(r)?;
+
+		Ok((offer, invoice, signature))
+	}
+}
+
+type PartialInvoiceTlvStream = (OfferTlvStream, InvoiceTlvStream);
+
+type PartialInvoiceTlvStreamRef<'a> = (OfferTlvStreamRef<'a>, InvoiceTlvStreamRef<'a>);
+
+impl TryFrom<ParsedMessage<FullInvoiceTlvStream>> for StaticInvoice {
+	type Error = Bolt12ParseError;
+
+	fn try_from(invoice: ParsedMessage<FullInvoiceTlvStream>) -> Result<Self, Self::Error> {
+		let ParsedMessage { bytes, tlv_stream } = invoice;
+		let (offer_tlv_stream, invoice_tlv_stream, SignatureTlvStream { signature }) = tlv_stream;
+		let contents = InvoiceContents::try_from((offer_tlv_stream, invoice_tlv_stream))?;
+
+		let signature = match signature {
+			None => return Err(Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::MissingSignature)),
+			Some(signature) => signature,
+		};
+		let tagged_hash = TaggedHash::new(SIGNATURE_TAG, &bytes);
+		let pubkey = contents.signing_pubkey;
+		merkle::verify_signature(&signature, &tagged_hash, pubkey)?;
+
+		Ok(StaticInvoice { bytes, contents, signature })
+	}
+}
+
+impl TryFrom<PartialInvoiceTlvStream> for InvoiceContents {
+	type Error = Bolt12SemanticError;
+
+	fn try_from(tlv_stream: PartialInvoiceTlvStream) -> Result<Self, Self::Error> {
+		let (
+			offer_tlv_stream,
+			InvoiceTlvStream {
+				paths, blindedpay, created_at, relative_expiry, payment_hash, amount, fallbacks,
+				features, node_id, recurrence_basetime, message_paths,
+			},
+		) = tlv_stream;
+
+		if payment_hash.is_some() { return Err(Bolt12SemanticError::UnexpectedPaymentHash) }
+		if amount.is_some() { return Err(Bolt12SemanticError::UnexpectedAmount) }
+		if recurrence_basetime.is_some() { return Err(Bolt12SemanticError::UnexpectedRecurrence) }
+
+		let payment_paths = match (blindedpay, paths) {
+			(_, None) => return Err(Bolt12SemanticError::MissingPaths),
+			(None, _) => return Err(Bolt12SemanticError::InvalidPayInfo),
+			(_, Some(paths)) if paths.is_empty() => return Err(Bolt12SemanticError::MissingPaths),
+			(Some(blindedpay), Some(paths)) if paths.len() != blindedpay.len() => {
+				return Err(Bolt12SemanticError::InvalidPayInfo);
+			},
+			(Some(blindedpay), Some(paths)) => {
+				blindedpay.into_iter().zip(paths.into_iter()).collect::<Vec<_>>()
+			},
+		};
+
+		let message_paths = match message_paths {
+			None => return Err(Bolt12SemanticError::MissingPaths),
+			Some(paths) if paths.is_empty() => return Err(Bolt12SemanticError::MissingPaths),
+			Some(paths) => paths,
+		};
+
+		let created_at = match created_at {
+			None => return Err(Bolt12SemanticError::MissingCreationTime),
+			Some(timestamp) => Duration::from_secs(timestamp),
+		};
+
+		let relative_expiry = relative_expiry
+			.map(Into::<u64>::into)
+			.map(Duration::from_secs);
+
+		let features = features.unwrap_or_else(Bolt12InvoiceFeatures::empty);
+
+		let signing_pubkey = match node_id {
+			None => return Err(Bolt12SemanticError::MissingSigningPubkey),
+			Some(node_id) => node_id,
+		};
+
+		let offer = OfferContents::try_from(offer_tlv_stream)?;
+		if offer.signing_pubkey() != signing_pubkey {
+			return Err(Bolt12SemanticError::InvalidSigningPubkey);
+		}
+
+		Ok(InvoiceContents {
+			offer, payment_paths, message_paths, created_at, relative_expiry, fallbacks, features,
+			signing_pubkey,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{FullInvoiceTlvStreamRef, StaticInvoice, StaticInvoiceBuilder};
+
+	use bitcoin::blockdata::constants::ChainHash;
+	use bitcoin::network::constants::Network;
+	use bitcoin::secp256k1::Secp256k1;
+	use core::convert::TryFrom;
+	use core::time::Duration;
+	use crate::blinded_path::{BlindedHop, BlindedPath};
+	use crate::ln::features::Bolt12InvoiceFeatures;
+	use crate::ln::inbound_payment::ExpandedKey;
+	use crate::ln::msgs::DecodeError;
+	use crate::offers::invoice::{DEFAULT_RELATIVE_EXPIRY, SIGNATURE_TAG};
+	use crate::offers::merkle::{self, TaggedHash};
+	use crate::offers::offer::{Offer, OfferBuilder};
+	use crate::offers::parse::{Bolt12ParseError, Bolt12SemanticError};
+	use crate::offers::test_utils::*;
+	use crate::sign::KeyMaterial;
+	use crate::util::ser::{Iterable, Writeable};
+
+	trait ToBytes {
+		fn to_bytes(&self) -> Vec<u8>;
+	}
+
+	impl<'a> ToBytes for FullInvoiceTlvStreamRef<'a> {
+		fn to_bytes(&self) -> Vec<u8> {
+			let mut buffer = Vec::new();
+			self.0.write(&mut buffer).unwrap();
+			self.1.write(&mut buffer).unwrap();
+			self.2.write(&mut buffer).unwrap();
+			buffer
+		}
+	}
+
+	fn expanded_key() -> ExpandedKey {
+		ExpandedKey::new(&KeyMaterial([42; 32]))
+	}
+
+	fn message_paths() -> Vec<BlindedPath> {
+		vec![BlindedPath {
+			introduction_node_id: pubkey(40),
+			blinding_point: pubkey(41),
+			blinded_hops: vec![
+				BlindedHop { blinded_node_id: pubkey(42), encrypted_payload: vec![0; 43] },
+				BlindedHop { blinded_node_id: pubkey(43), encrypted_payload: vec![0; 44] },
+			],
+		}]
+	}
+
+	fn offer() -> Offer {
+		let entropy = FixedEntropy {};
+		let secp_ctx = Secp256k1::new();
+		OfferBuilder::deriving_signing_pubkey(
+			"foo".into(), recipient_pubkey(), &expanded_key(), &entropy, &secp_ctx
+		)
+			.amount_msats(1000)
+			.path(message_paths()[0].clone())
+			.build().unwrap()
+	}
+
+	fn invoice(created_at: Duration) -> StaticInvoice {
+		let secp_ctx = Secp256k1::new();
+		StaticInvoiceBuilder::for_offer_using_derived_keys(
+			&offer(), payment_paths(), message_paths(), created_at, &expanded_key(), &secp_ctx
+		).unwrap()
+			.build_and_sign(&secp_ctx).unwrap()
+	}
+
+	#[test]
+	fn builds_invoice_for_offer_with_defaults() {
+		let now = now();
+		let offer = offer();
+		let invoice = invoice(now);
+
+		let mut buffer = Vec::new();
+		invoice.write(&mut buffer).unwrap();
+
+		assert_eq!(invoice.bytes, buffer.as_slice());
+		assert_eq!(invoice.chain(), ChainHash::using_genesis_block(Network::Bitcoin));
+		assert_eq!(invoice.metadata(), offer.metadata());
+		assert_eq!(invoice.amount(), offer.amount());
+		assert_eq!(invoice.description(), offer.description());
+		assert_eq!(invoice.offer_message_paths(), offer.paths());
+		assert_eq!(invoice.payment_paths(), payment_paths().as_slice());
+		assert_eq!(invoice.message_paths(), message_paths().as_slice());
+		assert_eq!(invoice.created_at(), now);
+		assert_eq!(invoice.relative_expiry(), DEFAULT_RELATIVE_EXPIRY);
+		#[cfg(feature = "std")]
+		assert!(!invoice.is_expired());
+		assert!(invoice.fallbacks().is_empty());
+		assert_eq!(invoice.invoice_features(), &Bolt12InvoiceFeatures::empty());
+		assert_eq!(invoice.signing_pubkey(), offer.signing_pubkey());
+		assert_ne!(invoice.signing_pubkey(), recipient_pubkey());
+		assert!(invoice.is_for_offer(&offer));
+
+		let message = TaggedHash::new(SIGNATURE_TAG, &invoice.bytes);
+		assert!(merkle::verify_signature(&invoice.signature, &message, invoice.signing_pubkey()).is_ok());
+
+		let tlv_stream = invoice.as_tlv_stream();
+		assert_eq!(tlv_stream.0, offer.as_tlv_stream());
+		assert_eq!(tlv_stream.1.payment_hash, None);
+		assert_eq!(tlv_stream.1.amount, None);
+		assert_eq!(tlv_stream.1.message_paths, Some(&message_paths()));
+
+		if let Err(e) = StaticInvoice::try_from(buffer) {
+			panic!("error parsing invoice: {:?}", e);
+		}
+	}
+
+	#[test]
+	fn builds_invoice_with_relative_expiry() {
+		let now = now();
+		let secp_ctx = Secp256k1::new();
+		let invoice = StaticInvoiceBuilder::for_offer_using_derived_keys(
+			&offer(), payment_paths(), message_paths(), now, &expanded_key(), &secp_ctx
+		).unwrap()
+			.relative_expiry(3600)
+			.allow_mpp()
+			.build_and_sign(&secp_ctx).unwrap();
+		assert_eq!(invoice.relative_expiry(), Duration::from_secs(3600));
+		assert!(!invoice.is_expired_no_std(now + Duration::from_secs(3600)));
+		assert!(invoice.is_expired_no_std(now + Duration::from_secs(3601)));
+
+		let mut features = Bolt12InvoiceFeatures::empty();
+		features.set_basic_mpp_optional();
+		assert_eq!(invoice.invoice_features(), &features);
+	}
+
+	#[test]
+	fn fails_building_invoice_with_invalid_parameters() {
+		let secp_ctx = Secp256k1::new();
+
+		match StaticInvoiceBuilder::for_offer_using_derived_keys(
+			&offer(), Vec::new(), message_paths(), now(), &expanded_key(), &secp_ctx
+		) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::MissingPaths),
+		}
+
+		match StaticInvoiceBuilder::for_offer_using_derived_keys(
+			&offer(), payment_paths(), Vec::new(), now(), &expanded_key(), &secp_ctx
+		) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::MissingPaths),
+		}
+
+		let other_key = ExpandedKey::new(&KeyMaterial([43; 32]));
+		match StaticInvoiceBuilder::for_offer_using_derived_keys(
+			&offer(), payment_paths(), message_paths(), now(), &other_key, &secp_ctx
+		) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::InvalidMetadata),
+		}
+
+		let offer = OfferBuilder::new("foo".into(), recipient_pubkey()).build().unwrap();
+		match StaticInvoiceBuilder::for_offer_using_derived_keys(
+			&offer, payment_paths(), message_paths(), now(), &expanded_key(), &secp_ctx
+		) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::InvalidMetadata),
+		}
+
+		let entropy = FixedEntropy {};
+		let offer = OfferBuilder::deriving_signing_pubkey(
+			"foo".into(), recipient_pubkey(), &expanded_key(), &entropy, &secp_ctx
+		)
+			.absolute_expiry(now())
+			.build().unwrap();
+		match StaticInvoiceBuilder::for_offer_using_derived_keys(
+			&offer, payment_paths(), message_paths(), now() + Duration::from_secs(1),
+			&expanded_key(), &secp_ctx
+		) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12SemanticError::AlreadyExpired),
+		}
+	}
+
+	#[test]
+	fn fails_parsing_invoice_with_invalid_semantics() {
+		let invoice = invoice(now());
+
+		let mut tlv_stream = invoice.as_tlv_stream();
+		tlv_stream.1.message_paths = None;
+		match StaticInvoice::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::MissingPaths)),
+		}
+
+		let empty_payment_paths = vec![];
+		let mut tlv_stream = invoice.as_tlv_stream();
+		tlv_stream.1.paths = Some(Iterable(empty_payment_paths.iter().map(|(_, path)| path)));
+		match StaticInvoice::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::MissingPaths)),
+		}
+
+		let payment_hash = payment_hash();
+		let mut tlv_stream = invoice.as_tlv_stream();
+		tlv_stream.1.payment_hash = Some(&payment_hash);
+		match StaticInvoice::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::UnexpectedPaymentHash)),
+		}
+
+		let mut tlv_stream = invoice.as_tlv_stream();
+		tlv_stream.1.amount = Some(1000);
+		match StaticInvoice::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::UnexpectedAmount)),
+		}
+
+		let invalid_pubkey = payer_pubkey();
+		let mut tlv_stream = invoice.as_tlv_stream();
+		tlv_stream.1.node_id = Some(&invalid_pubkey);
+		match StaticInvoice::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::InvalidSigningPubkey)),
+		}
+
+		let mut tlv_stream = invoice.as_tlv_stream();
+		tlv_stream.2.signature = None;
+		match StaticInvoice::try_from(tlv_stream.to_bytes()) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12ParseError::InvalidSemantics(Bolt12SemanticError::MissingSignature)),
+		}
+	}
+
+	#[test]
+	fn fails_parsing_invoice_with_invalid_signature() {
+		let mut invoice = invoice(now());
+		let last_signature_byte = invoice.bytes.last_mut().unwrap();
+		*last_signature_byte = last_signature_byte.wrapping_add(1);
+
+		let mut buffer = Vec::new();
+		invoice.write(&mut buffer).unwrap();
+
+		match StaticInvoice::try_from(buffer) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert!(matches!(e, Bolt12ParseError::InvalidSignature(_))),
+		}
+	}
+
+	#[test]
+	fn fails_parsing_bolt12_invoice_as_static_invoice() {
+		let invoice = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap()
+			.respond_with_no_std(payment_paths(), payment_hash(), now()).unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+
+		let mut buffer = Vec::new();
+		invoice.write(&mut buffer).unwrap();
+
+		match StaticInvoice::try_from(buffer) {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, Bolt12ParseError::Decode(DecodeError::InvalidValue)),
+		}
+	}
+}
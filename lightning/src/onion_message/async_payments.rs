@@ -0,0 +1,143 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Message handling for async payments.
+//!
+//! Async payments allow an often-offline sender to pay an often-offline recipient. The sender's
+//! LSP holds the sender's HTLC until the recipient comes online, at which point the recipient's
+//! LSP asks the sender's LSP to release it.
+
+use crate::blinded_path::BlindedPath;
+use crate::io;
+use crate::ln::msgs::DecodeError;
+use crate::onion_message::OnionMessageContents;
+use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer};
+#[cfg(not(c_bindings))]
+use crate::onion_message::messenger::PendingOnionMessage;
+
+use crate::prelude::*;
+
+// TLV record types for the `onionmsg_tlv` TLV stream as defined in BOLT 4.
+const HELD_HTLC_AVAILABLE_TLV_TYPE: u64 = 72;
+const RELEASE_HELD_HTLC_TLV_TYPE: u64 = 74;
+
+/// A handler for an [`OnionMessage`] containing an async payments message as its payload.
+///
+/// [`OnionMessage`]: crate::ln::msgs::OnionMessage
+pub trait AsyncPaymentsMessageHandler {
+	/// Handles a [`HeldHtlcAvailable`] message, sent by an async sender to notify the recipient (or
+	/// its LSP) that an HTLC is being held for it.
+	///
+	/// `path_id` is the one set in the blinded path the message was sent over, if any, while
+	/// `reply_path` is where the [`ReleaseHeldHtlc`] message should be sent once the recipient is
+	/// online, which may be done via [`Self::release_pending_messages`].
+	fn held_htlc_available(
+		&self, message: HeldHtlcAvailable, path_id: Option<[u8; 32]>, reply_path: Option<BlindedPath>
+	);
+
+	/// Handles a [`ReleaseHeldHtlc`] message, releasing the HTLC(s) held for the corresponding
+	/// [`HeldHtlcAvailable::payment_release_secret`].
+	fn release_held_htlc(&self, message: ReleaseHeldHtlc);
+
+	/// Releases any [`AsyncPaymentsMessage`]s that need to be sent.
+	///
+	/// Typically, this is used for messages sent once a recipient comes online or for notifying a
+	/// recipient of a held HTLC.
+	#[cfg(not(c_bindings))]
+	fn release_pending_messages(&self) -> Vec<PendingOnionMessage<AsyncPaymentsMessage>> { vec![] }
+
+	/// Releases any [`AsyncPaymentsMessage`]s that need to be sent.
+	///
+	/// Typically, this is used for messages sent once a recipient comes online or for notifying a
+	/// recipient of a held HTLC.
+	#[cfg(c_bindings)]
+	fn release_pending_messages(&self) -> Vec<(AsyncPaymentsMessage, crate::onion_message::Destination, Option<BlindedPath>)> { vec![] }
+}
+
+/// Possible async payment messages sent and received via an [`OnionMessage`].
+///
+/// [`OnionMessage`]: crate::ln::msgs::OnionMessage
+#[derive(Clone, Debug)]
+pub enum AsyncPaymentsMessage {
+	/// An HTLC is being held upstream for the often-offline recipient, to be released via
+	/// [`ReleaseHeldHtlc`].
+	HeldHtlcAvailable(HeldHtlcAvailable),
+
+	/// Releases the HTLC corresponding to an inbound [`HeldHtlcAvailable`] message.
+	ReleaseHeldHtlc(ReleaseHeldHtlc),
+}
+
+/// An HTLC destined for the recipient of this message is being held upstream. The reply path
+/// accompanying this onion message should be used to send a [`ReleaseHeldHtlc`] response, which
+/// will cause the upstream HTLC to be released.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeldHtlcAvailable {
+	/// The secret that will be used by the recipient of this message to release the held HTLC.
+	pub payment_release_secret: [u8; 32],
+}
+
+/// Releases the HTLC corresponding to an inbound [`HeldHtlcAvailable`] message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReleaseHeldHtlc {
+	/// Used to release the HTLC held upstream if it matches the corresponding
+	/// [`HeldHtlcAvailable::payment_release_secret`].
+	pub payment_release_secret: [u8; 32],
+}
+
+impl OnionMessageContents for ReleaseHeldHtlc {
+	fn tlv_type(&self) -> u64 {
+		RELEASE_HELD_HTLC_TLV_TYPE
+	}
+}
+
+impl_writeable_tlv_based!(HeldHtlcAvailable, {
+	(0, payment_release_secret, required),
+});
+
+impl_writeable_tlv_based!(ReleaseHeldHtlc, {
+	(0, payment_release_secret, required),
+});
+
+impl AsyncPaymentsMessage {
+	/// Returns whether `tlv_type` corresponds to a TLV record for async payment messages.
+	pub fn is_known_type(tlv_type: u64) -> bool {
+		match tlv_type {
+			HELD_HTLC_AVAILABLE_TLV_TYPE | RELEASE_HELD_HTLC_TLV_TYPE => true,
+			_ => false,
+		}
+	}
+}
+
+impl OnionMessageContents for AsyncPaymentsMessage {
+	fn tlv_type(&self) -> u64 {
+		match self {
+			Self::HeldHtlcAvailable(_) => HELD_HTLC_AVAILABLE_TLV_TYPE,
+			Self::ReleaseHeldHtlc(msg) => msg.tlv_type(),
+		}
+	}
+}
+
+impl Writeable for AsyncPaymentsMessage {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		match self {
+			Self::HeldHtlcAvailable(message) => message.write(w),
+			Self::ReleaseHeldHtlc(message) => message.write(w),
+		}
+	}
+}
+
+impl ReadableArgs<u64> for AsyncPaymentsMessage {
+	fn read<R: io::Read>(r: &mut R, tlv_type: u64) -> Result<Self, DecodeError> {
+		match tlv_type {
+			HELD_HTLC_AVAILABLE_TLV_TYPE => Ok(Self::HeldHtlcAvailable(Readable::read(r)?)),
+			RELEASE_HELD_HTLC_TLV_TYPE => Ok(Self::ReleaseHeldHtlc(Readable::read(r)?)),
+			_ => Err(DecodeError::InvalidValue),
+		}
+	}
+}
@@ -0,0 +1,517 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Message handling for resolving [BIP 353] human-readable names, such as `₿alice@example.com`,
+//! to [`Offer`]s via DNSSEC proofs sent over onion messages, as defined in [bLIP 32].
+//!
+//! A node wishing to pay a human-readable name sends a [`DNSSECQuery`] to a node offering DNS
+//! resolution, which responds with a [`DNSSECProof`] of the name's `TXT` records. The proof is
+//! validated and the [`Offer`] is extracted from the BIP 21 `bitcoin:` URI in those records by an
+//! [`OMNameResolver`].
+//!
+//! [BIP 353]: https://github.com/bitcoin/bips/blob/master/bip-0353.mediawiki
+//! [bLIP 32]: https://github.com/lightning/blips/blob/master/blip-0032.md
+
+use dnssec_prover::rr::{Name, RR};
+use dnssec_prover::ser::parse_rr_stream;
+use dnssec_prover::validation::verify_rr_stream;
+
+use core::convert::TryFrom;
+use core::fmt;
+use core::ops::Deref;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(test)]
+use core::sync::atomic::AtomicBool;
+
+use crate::io;
+use crate::ln::channelmanager::PaymentId;
+use crate::ln::msgs::DecodeError;
+use crate::offers::offer::Offer;
+use crate::onion_message::OnionMessageContents;
+use crate::sign::EntropySource;
+use crate::sync::Mutex;
+use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer};
+#[cfg(not(c_bindings))]
+use crate::onion_message::messenger::PendingOnionMessage;
+
+use crate::prelude::*;
+
+// TLV record types for the `onionmsg_tlv` TLV stream as defined in bLIP 32.
+const DNSSEC_QUERY_TLV_TYPE: u64 = 65536;
+const DNSSEC_PROOF_TLV_TYPE: u64 = 65538;
+
+/// The most a block timestamp may be ahead of the validity period of a [`DNSSECProof`], since
+/// block timestamps may be up to two hours in the future.
+const BLOCK_TIME_SLACK_SECS: u64 = 60 * 60 * 2;
+
+/// A handler for an [`OnionMessage`] containing a DNS resolution message as its payload.
+///
+/// [`OnionMessage`]: crate::ln::msgs::OnionMessage
+pub trait DNSResolverMessageHandler {
+	/// Handles a [`DNSSECQuery`], returning a [`DNSResolverMessage::DNSSECProof`] to send back to
+	/// the querying node, if any.
+	///
+	/// Only nodes offering DNS resolution to others need to respond.
+	fn handle_dnssec_query(&self, message: DNSSECQuery) -> Option<DNSResolverMessage>;
+
+	/// Handles a [`DNSSECProof`] sent in response to a [`DNSSECQuery`].
+	///
+	/// `path_id` is the one set in the reply path sent with the corresponding query, if any.
+	fn handle_dnssec_proof(&self, message: DNSSECProof, path_id: Option<[u8; 32]>);
+
+	/// Releases any [`DNSResolverMessage`]s that need to be sent.
+	///
+	/// Typically, this is used for queries resolving human-readable names.
+	#[cfg(not(c_bindings))]
+	fn release_pending_messages(&self) -> Vec<PendingOnionMessage<DNSResolverMessage>> { vec![] }
+
+	/// Releases any [`DNSResolverMessage`]s that need to be sent.
+	///
+	/// Typically, this is used for queries resolving human-readable names.
+	#[cfg(c_bindings)]
+	fn release_pending_messages(&self) -> Vec<(DNSResolverMessage, crate::onion_message::Destination, Option<crate::blinded_path::BlindedPath>)> { vec![] }
+}
+
+/// Possible DNS resolution messages sent and received via an [`OnionMessage`].
+///
+/// [`OnionMessage`]: crate::ln::msgs::OnionMessage
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DNSResolverMessage {
+	/// A query requesting a DNSSEC proof of a name's `TXT` records.
+	DNSSECQuery(DNSSECQuery),
+
+	/// A DNSSEC proof sent in response to a [`DNSSECQuery`].
+	DNSSECProof(DNSSECProof),
+}
+
+/// A query requesting a DNSSEC proof of the `TXT` records of the given fully-qualified name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DNSSECQuery(pub Name);
+
+/// A DNSSEC proof of the `TXT` records of a name, sent in response to a [`DNSSECQuery`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DNSSECProof {
+	/// The fully-qualified name that was queried.
+	pub name: Name,
+	/// The proof, as a stream of RFC 9102 DNS resource records including the signatures and keys
+	/// needed to validate them back to the root trust anchor.
+	pub proof: Vec<u8>,
+}
+
+fn write_name<W: Writer>(name: &Name, w: &mut W) -> Result<(), io::Error> {
+	let bytes = name.as_bytes();
+	(bytes.len() as u8).write(w)?;
+	w.write_all(bytes)
+}
+
+fn read_name<R: io::Read>(r: &mut R) -> Result<Name, DecodeError> {
+	let len: u8 = Readable::read(r)?;
+	let mut bytes = vec![0; len as usize];
+	r.read_exact(&mut bytes)?;
+	let name = String::from_utf8(bytes).map_err(|_| DecodeError::InvalidValue)?;
+	Name::try_from(name).map_err(|_| DecodeError::InvalidValue)
+}
+
+impl Writeable for DNSSECQuery {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		write_name(&self.0, w)
+	}
+}
+
+impl Readable for DNSSECQuery {
+	fn read<R: io::Read>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(DNSSECQuery(read_name(r)?))
+	}
+}
+
+impl Writeable for DNSSECProof {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		write_name(&self.name, w)?;
+		self.proof.write(w)
+	}
+}
+
+impl Readable for DNSSECProof {
+	fn read<R: io::Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let name = read_name(r)?;
+		let proof = Readable::read(r)?;
+		Ok(DNSSECProof { name, proof })
+	}
+}
+
+impl DNSResolverMessage {
+	/// Returns whether `tlv_type` corresponds to a TLV record for DNS resolution messages.
+	pub fn is_known_type(tlv_type: u64) -> bool {
+		match tlv_type {
+			DNSSEC_QUERY_TLV_TYPE | DNSSEC_PROOF_TLV_TYPE => true,
+			_ => false,
+		}
+	}
+}
+
+impl OnionMessageContents for DNSResolverMessage {
+	fn tlv_type(&self) -> u64 {
+		match self {
+			Self::DNSSECQuery(_) => DNSSEC_QUERY_TLV_TYPE,
+			Self::DNSSECProof(_) => DNSSEC_PROOF_TLV_TYPE,
+		}
+	}
+}
+
+impl Writeable for DNSResolverMessage {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		match self {
+			Self::DNSSECQuery(message) => message.write(w),
+			Self::DNSSECProof(message) => message.write(w),
+		}
+	}
+}
+
+impl ReadableArgs<u64> for DNSResolverMessage {
+	fn read<R: io::Read>(r: &mut R, tlv_type: u64) -> Result<Self, DecodeError> {
+		match tlv_type {
+			DNSSEC_QUERY_TLV_TYPE => Ok(Self::DNSSECQuery(Readable::read(r)?)),
+			DNSSEC_PROOF_TLV_TYPE => Ok(Self::DNSSECProof(Readable::read(r)?)),
+			_ => Err(DecodeError::InvalidValue),
+		}
+	}
+}
+
+/// A [BIP 353] human-readable name, i.e., a user and a domain, typically written as
+/// `₿user@domain`.
+///
+/// Both parts are normalized to lowercase.
+///
+/// [BIP 353]: https://github.com/bitcoin/bips/blob/master/bip-0353.mediawiki
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct HumanReadableName {
+	user: String,
+	domain: String,
+}
+
+/// The labels between the user and the domain of the DNS name resolved for a
+/// [`HumanReadableName`].
+const HRN_DNS_LABELS: &str = ".user._bitcoin-payment.";
+
+impl HumanReadableName {
+	/// Constructs a new [`HumanReadableName`] from the `user` and `domain` parts.
+	///
+	/// Errors if either part is empty or contains characters other than ASCII alphanumerics, `-`,
+	/// `_`, and `.`, or if the resulting DNS name would be too long.
+	pub fn new(mut user: String, mut domain: String) -> Result<Self, ()> {
+		user.make_ascii_lowercase();
+		domain.make_ascii_lowercase();
+
+		// The fully-qualified DNS name, including its trailing `.`, must fit in 255 bytes.
+		if user.len() + HRN_DNS_LABELS.len() + domain.len() + 1 > 255 {
+			return Err(());
+		}
+		if !Self::is_valid_part(&user) || !Self::is_valid_part(&domain) {
+			return Err(());
+		}
+
+		Ok(Self { user, domain })
+	}
+
+	/// Parses a [`HumanReadableName`] of the form `user@domain`, optionally prefixed with `₿`.
+	pub fn from_encoded(encoded: &str) -> Result<Self, ()> {
+		let encoded = encoded.strip_prefix("₿").unwrap_or(encoded);
+		let (user, domain) = encoded.split_once('@').ok_or(())?;
+		Self::new(user.to_string(), domain.to_string())
+	}
+
+	/// The user part of the name.
+	pub fn user(&self) -> &str {
+		&self.user
+	}
+
+	/// The domain part of the name.
+	pub fn domain(&self) -> &str {
+		&self.domain
+	}
+
+	fn is_valid_part(part: &str) -> bool {
+		!part.is_empty() && !part.starts_with('.') && !part.ends_with('.') &&
+			part.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+	}
+
+	/// The fully-qualified DNS name holding the `TXT` records for the name.
+	fn dns_name(&self) -> Result<Name, ()> {
+		let name = format!("{}{}{}.", self.user, HRN_DNS_LABELS, self.domain);
+		Name::try_from(name).map_err(|_| ())
+	}
+}
+
+impl fmt::Display for HumanReadableName {
+	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		write!(f, "₿{}@{}", self.user, self.domain)
+	}
+}
+
+impl FromStr for HumanReadableName {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, ()> {
+		Self::from_encoded(s)
+	}
+}
+
+/// A client for resolving [`HumanReadableName`]s to [`Offer`]s using [`DNSSECQuery`] onion
+/// messages sent to a DNS resolver.
+///
+/// Tracks pending resolutions, matching each [`DNSSECProof`] to the query it answers by the
+/// `path_id` of the reply path the query was sent with. Proofs are validated back to the DNS root
+/// trust anchor and must be valid at the latest block time given via [`Self::new_best_block`].
+pub struct OMNameResolver {
+	/// Pending resolutions keyed by the queried DNS name, each with the `path_id` expected in the
+	/// reply path of the response.
+	pending_resolves: Mutex<HashMap<Name, Vec<(PaymentId, [u8; 32], HumanReadableName)>>>,
+	latest_block_time: AtomicUsize,
+	/// Accepts the records in proofs without validating them, as tests can't sign records back to
+	/// the DNS root trust anchor.
+	#[cfg(test)]
+	pub(crate) skip_proof_validation: AtomicBool,
+}
+
+impl OMNameResolver {
+	/// Constructs a new [`OMNameResolver`] given the timestamp of the latest block.
+	pub fn new(latest_block_time: u32) -> Self {
+		Self {
+			pending_resolves: Mutex::new(HashMap::new()),
+			latest_block_time: AtomicUsize::new(latest_block_time as usize),
+			#[cfg(test)]
+			skip_proof_validation: AtomicBool::new(false),
+		}
+	}
+
+	/// Informs the resolver of the timestamp of a newly connected block, used to check that proofs
+	/// are currently valid.
+	pub fn new_best_block(&self, block_time: u32) {
+		self.latest_block_time.fetch_max(block_time as usize, Ordering::AcqRel);
+	}
+
+	/// Begins resolving `name` for the payment with `payment_id`, returning the [`DNSSECQuery`] to
+	/// send to a DNS resolver along with the `path_id` to use in the query's reply path.
+	///
+	/// Errors if `name` can't be expressed as a DNS name.
+	pub fn resolve_name<ES: Deref>(
+		&self, payment_id: PaymentId, name: HumanReadableName, entropy_source: &ES
+	) -> Result<(DNSSECQuery, [u8; 32]), ()>
+	where
+		ES::Target: EntropySource,
+	{
+		let dns_name = name.dns_name()?;
+		let path_id = entropy_source.get_secure_random_bytes();
+		self.pending_resolves.lock().unwrap()
+			.entry(dns_name.clone())
+			.or_insert_with(Vec::new)
+			.push((payment_id, path_id, name));
+		Ok((DNSSECQuery(dns_name), path_id))
+	}
+
+	/// Handles a [`DNSSECProof`] received over a reply path with the given `path_id`, returning the
+	/// [`Offer`] the name resolved to along with each pending payment for the name.
+	///
+	/// Returns `None` if the proof doesn't correspond to a pending resolution, is invalid or not
+	/// currently valid, or doesn't contain exactly one BIP 21 `bitcoin:` URI with an offer. Any
+	/// pending resolutions remain pending in that case, as another resolver may still respond.
+	pub fn handle_dnssec_proof_for_offer(
+		&self, message: DNSSECProof, path_id: [u8; 32]
+	) -> Option<(Vec<(HumanReadableName, PaymentId)>, Offer)> {
+		let mut pending_resolves = self.pending_resolves.lock().unwrap();
+		let pending = pending_resolves.get(&message.name)?;
+		if !pending.iter().any(|(_, expected_path_id, _)| *expected_path_id == path_id) {
+			return None;
+		}
+
+		let rrs = parse_rr_stream(&message.proof).ok()?;
+		let txt_records = self.verified_txt_records(&message.name, &rrs)?;
+		let offer = offer_from_txt_records(&txt_records).ok()?;
+
+		let payments = pending_resolves.remove(&message.name)?
+			.into_iter()
+			.map(|(payment_id, _, name)| (name, payment_id))
+			.collect();
+		Some((payments, offer))
+	}
+
+	/// Validates `rrs` back to the DNS root trust anchor, returning the data of the `TXT` records
+	/// `name` resolves to if they are currently valid.
+	fn verified_txt_records(&self, name: &Name, rrs: &[RR]) -> Option<Vec<Vec<u8>>> {
+		#[cfg(test)] {
+			if self.skip_proof_validation.load(Ordering::Acquire) {
+				return Some(rrs.iter()
+					.filter_map(|rr| match rr {
+						RR::Txt(txt) if txt.name == *name => Some(txt.data.as_vec()),
+						_ => None,
+					})
+					.collect());
+			}
+		}
+
+		let verified_rrs = verify_rr_stream(rrs).ok()?;
+
+		let block_time = self.latest_block_time.load(Ordering::Acquire) as u64;
+		if verified_rrs.valid_from > block_time + BLOCK_TIME_SLACK_SECS ||
+			verified_rrs.expires < block_time
+		{
+			return None;
+		}
+
+		Some(verified_rrs.resolve_name(name).into_iter()
+			.filter_map(|rr| match rr {
+				RR::Txt(txt) => Some(txt.data.as_vec()),
+				_ => None,
+			})
+			.collect())
+	}
+
+	/// Drops any pending resolutions for payments for which `is_pending` returns `false`, e.g.,
+	/// because they timed out or were abandoned.
+	pub fn retain_pending_resolutions<F: Fn(PaymentId) -> bool>(&self, is_pending: F) {
+		let mut pending_resolves = self.pending_resolves.lock().unwrap();
+		pending_resolves.retain(|_, pending| {
+			pending.retain(|(payment_id, _, _)| is_pending(*payment_id));
+			!pending.is_empty()
+		});
+	}
+}
+
+/// Extracts the [`Offer`] from the `lno` parameter of the only BIP 21 `bitcoin:` URI in the given
+/// `TXT` records, as required by BIP 353.
+fn offer_from_txt_records(txt_records: &[Vec<u8>]) -> Result<Offer, ()> {
+	const URI_SCHEME: &str = "bitcoin:";
+
+	let mut uris = txt_records.iter().filter(|record| {
+		record.len() >= URI_SCHEME.len() &&
+			record[..URI_SCHEME.len()].eq_ignore_ascii_case(URI_SCHEME.as_bytes())
+	});
+	let uri = uris.next().ok_or(())?;
+	if uris.next().is_some() {
+		return Err(());
+	}
+
+	let uri = core::str::from_utf8(uri).map_err(|_| ())?;
+	let (_, query) = uri.split_once('?').ok_or(())?;
+	for param in query.split('&') {
+		if let Some((key, value)) = param.split_once('=') {
+			if key.eq_ignore_ascii_case("lno") {
+				return Offer::from_str(value).map_err(|_| ());
+			}
+		}
+	}
+
+	Err(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{DNSResolverMessage, DNSSECProof, DNSSECQuery, HumanReadableName, OMNameResolver, offer_from_txt_records};
+
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+	use dnssec_prover::rr::Name;
+	use core::convert::TryFrom;
+
+	use crate::io::Cursor;
+	use crate::ln::channelmanager::PaymentId;
+	use crate::offers::offer::{Offer, OfferBuilder};
+	use crate::onion_message::OnionMessageContents;
+	use crate::util::ser::{ReadableArgs, Writeable};
+	use crate::util::test_utils::TestKeysInterface;
+
+	use crate::prelude::*;
+
+	fn offer() -> Offer {
+		let secp_ctx = Secp256k1::new();
+		let pubkey = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		OfferBuilder::new("foo".into(), pubkey).build().unwrap()
+	}
+
+	#[test]
+	fn parses_human_readable_names() {
+		let name = HumanReadableName::from_encoded("₿Alice@Example.com").unwrap();
+		assert_eq!(name.user(), "alice");
+		assert_eq!(name.domain(), "example.com");
+		assert_eq!(name.to_string(), "₿alice@example.com");
+		assert_eq!(HumanReadableName::from_encoded("alice@example.com"), Ok(name.clone()));
+		assert_eq!(
+			name.dns_name().unwrap(),
+			Name::try_from("alice.user._bitcoin-payment.example.com.").unwrap()
+		);
+
+		assert!(HumanReadableName::from_encoded("alice").is_err());
+		assert!(HumanReadableName::from_encoded("@example.com").is_err());
+		assert!(HumanReadableName::from_encoded("alice@").is_err());
+		assert!(HumanReadableName::from_encoded("alice@.example.com").is_err());
+		assert!(HumanReadableName::from_encoded("al ice@example.com").is_err());
+		assert!(HumanReadableName::from_encoded("alice@bob@example.com").is_err());
+
+		let long_domain = "a".repeat(255 - "alice.user._bitcoin-payment..".len());
+		assert!(HumanReadableName::new("alice".into(), long_domain.clone()).is_ok());
+		assert!(HumanReadableName::new("alice".into(), long_domain + "a").is_err());
+	}
+
+	#[test]
+	fn extracts_offer_from_txt_records() {
+		let offer = offer();
+		let uri = format!("bitcoin:?lno={}", offer).into_bytes();
+		let uri_with_params = format!("BITCOIN:bc1qexample?amount=1&LNO={}&sp=sp1", offer).into_bytes();
+		let other = b"v=spf1 -all".to_vec();
+
+		assert_eq!(offer_from_txt_records(&[other.clone(), uri.clone()]), Ok(offer.clone()));
+		assert_eq!(offer_from_txt_records(&[uri_with_params]), Ok(offer.clone()));
+
+		// BIP 353 requires exactly one `bitcoin:` URI.
+		assert!(offer_from_txt_records(&[other.clone()]).is_err());
+		assert!(offer_from_txt_records(&[uri.clone(), uri]).is_err());
+
+		assert!(offer_from_txt_records(&[b"bitcoin:bc1qexample".to_vec()]).is_err());
+		assert!(offer_from_txt_records(&[b"bitcoin:?lno=lno1invalid".to_vec()]).is_err());
+	}
+
+	#[test]
+	fn encodes_and_decodes_messages() {
+		let name = Name::try_from("alice.user._bitcoin-payment.example.com.").unwrap();
+		let query = DNSResolverMessage::DNSSECQuery(DNSSECQuery(name.clone()));
+		let proof = DNSResolverMessage::DNSSECProof(DNSSECProof { name, proof: vec![42; 300] });
+
+		for message in [query, proof] {
+			let encoded = message.encode();
+			let decoded = DNSResolverMessage::read(&mut Cursor::new(&encoded), message.tlv_type());
+			assert_eq!(decoded.unwrap(), message);
+		}
+	}
+
+	#[test]
+	fn ignores_proofs_without_pending_resolution() {
+		let entropy_source = TestKeysInterface::new(&[42; 32], bitcoin::Network::Testnet);
+		let resolver = OMNameResolver::new(0);
+		let name = HumanReadableName::from_encoded("alice@example.com").unwrap();
+		let (query, path_id) = resolver
+			.resolve_name(PaymentId([1; 32]), name, &&entropy_source)
+			.unwrap();
+		assert_eq!(query.0, Name::try_from("alice.user._bitcoin-payment.example.com.").unwrap());
+
+		// Unknown names and unexpected reply paths are ignored, as are invalid proofs.
+		let other_name = Name::try_from("bob.user._bitcoin-payment.example.com.").unwrap();
+		let proof = DNSSECProof { name: other_name, proof: vec![] };
+		assert!(resolver.handle_dnssec_proof_for_offer(proof, path_id).is_none());
+
+		let proof = DNSSECProof { name: query.0.clone(), proof: vec![] };
+		assert!(resolver.handle_dnssec_proof_for_offer(proof, [0; 32]).is_none());
+
+		let proof = DNSSECProof { name: query.0.clone(), proof: vec![42; 32] };
+		assert!(resolver.handle_dnssec_proof_for_offer(proof, path_id).is_none());
+		assert_eq!(resolver.pending_resolves.lock().unwrap().len(), 1);
+
+		resolver.retain_pending_resolutions(|payment_id| payment_id != PaymentId([1; 32]));
+		assert!(resolver.pending_resolves.lock().unwrap().is_empty());
+	}
+}
@@ -16,11 +16,12 @@ use crate::ln::msgs::{self, DecodeError, OnionMessageHandler, SocketAddress};
 use crate::sign::{EntropySource, NodeSigner, Recipient};
 use crate::util::ser::{FixedLengthReader, LengthReadable, Writeable, Writer};
 use crate::util::test_utils;
-use super::{CustomOnionMessageHandler, Destination, MessageRouter, OffersMessage, OffersMessageHandler, OnionMessageContents, OnionMessagePath, OnionMessenger, PendingOnionMessage, SendError};
+use super::{AsyncPaymentsMessage, AsyncPaymentsMessageHandler, CustomOnionMessageHandler, Destination, DNSResolverMessage, DNSResolverMessageHandler, DNSSECProof, DNSSECQuery, HeldHtlcAvailable, MessageRouter, OffersMessage, OffersMessageHandler, OnionMessageContents, OnionMessagePath, OnionMessenger, PendingOnionMessage, ReleaseHeldHtlc, SendError};
 
 use bitcoin::network::constants::Network;
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey, self};
+use dnssec_prover::rr::Name;
 
 use crate::io;
 use crate::io_extras::read_to_end;
@@ -37,8 +38,12 @@ struct MessengerNode {
 		Arc<test_utils::TestLogger>,
 		Arc<TestMessageRouter>,
 		Arc<TestOffersMessageHandler>,
+		Arc<TestAsyncPaymentsMessageHandler>,
+		Arc<TestDNSResolverMessageHandler>,
 		Arc<TestCustomMessageHandler>
 	>,
+	async_payments_message_handler: Arc<TestAsyncPaymentsMessageHandler>,
+	dns_resolver_message_handler: Arc<TestDNSResolverMessageHandler>,
 	custom_message_handler: Arc<TestCustomMessageHandler>,
 }
 
@@ -74,6 +79,72 @@ impl OffersMessageHandler for TestOffersMessageHandler {
 	}
 }
 
+/// Records received async payments messages, releasing any [`HeldHtlcAvailable`] message it
+/// receives with a reply path.
+struct TestAsyncPaymentsMessageHandler {
+	received_messages: Mutex<Vec<(AsyncPaymentsMessage, Option<[u8; 32]>)>>,
+	pending_messages: Mutex<Vec<PendingOnionMessage<AsyncPaymentsMessage>>>,
+}
+
+impl TestAsyncPaymentsMessageHandler {
+	fn new() -> Self {
+		Self { received_messages: Mutex::new(Vec::new()), pending_messages: Mutex::new(Vec::new()) }
+	}
+}
+
+impl AsyncPaymentsMessageHandler for TestAsyncPaymentsMessageHandler {
+	fn held_htlc_available(
+		&self, message: HeldHtlcAvailable, path_id: Option<[u8; 32]>, reply_path: Option<BlindedPath>
+	) {
+		if let Some(reply_path) = reply_path {
+			self.pending_messages.lock().unwrap().push(PendingOnionMessage {
+				contents: AsyncPaymentsMessage::ReleaseHeldHtlc(ReleaseHeldHtlc {
+					payment_release_secret: message.payment_release_secret,
+				}),
+				destination: Destination::BlindedPath(reply_path),
+				reply_path: None,
+			});
+		}
+		self.received_messages.lock().unwrap()
+			.push((AsyncPaymentsMessage::HeldHtlcAvailable(message), path_id));
+	}
+
+	fn release_held_htlc(&self, message: ReleaseHeldHtlc) {
+		self.received_messages.lock().unwrap()
+			.push((AsyncPaymentsMessage::ReleaseHeldHtlc(message), None));
+	}
+
+	fn release_pending_messages(&self) -> Vec<PendingOnionMessage<AsyncPaymentsMessage>> {
+		core::mem::take(&mut self.pending_messages.lock().unwrap())
+	}
+}
+
+/// A stand-in DNS resolver, responding to any [`DNSSECQuery`] with a fixed proof and recording
+/// any [`DNSSECProof`] it receives.
+struct TestDNSResolverMessageHandler {
+	received_proofs: Mutex<Vec<(DNSSECProof, Option<[u8; 32]>)>>,
+}
+
+const TEST_DNSSEC_PROOF: [u8; 64] = [44; 64];
+
+impl TestDNSResolverMessageHandler {
+	fn new() -> Self {
+		Self { received_proofs: Mutex::new(Vec::new()) }
+	}
+}
+
+impl DNSResolverMessageHandler for TestDNSResolverMessageHandler {
+	fn handle_dnssec_query(&self, message: DNSSECQuery) -> Option<DNSResolverMessage> {
+		Some(DNSResolverMessage::DNSSECProof(DNSSECProof {
+			name: message.0, proof: TEST_DNSSEC_PROOF.to_vec(),
+		}))
+	}
+
+	fn handle_dnssec_proof(&self, message: DNSSECProof, path_id: Option<[u8; 32]>) {
+		self.received_proofs.lock().unwrap().push((message, path_id));
+	}
+}
+
 #[derive(Clone, Debug, PartialEq)]
 enum TestCustomMessage {
 	Request,
@@ -179,14 +250,19 @@ fn create_nodes_using_secrets(secrets: Vec<SecretKey>) -> Vec<MessengerNode> {
 
 		let message_router = Arc::new(TestMessageRouter {});
 		let offers_message_handler = Arc::new(TestOffersMessageHandler {});
+		let async_payments_message_handler = Arc::new(TestAsyncPaymentsMessageHandler::new());
+		let dns_resolver_message_handler = Arc::new(TestDNSResolverMessageHandler::new());
 		let custom_message_handler = Arc::new(TestCustomMessageHandler::new());
 		nodes.push(MessengerNode {
 			node_id: node_signer.get_node_id(Recipient::Node).unwrap(),
 			entropy_source: entropy_source.clone(),
 			messenger: OnionMessenger::new(
 				entropy_source, node_signer, logger.clone(), message_router,
-				offers_message_handler, custom_message_handler.clone()
+				offers_message_handler, async_payments_message_handler.clone(),
+				dns_resolver_message_handler.clone(), custom_message_handler.clone()
 			),
+			async_payments_message_handler,
+			dns_resolver_message_handler,
 			custom_message_handler,
 		});
 	}
@@ -312,6 +388,75 @@ fn three_blinded_hops() {
 	pass_along_path(&nodes);
 }
 
+#[test]
+fn async_payments_held_htlc_release() {
+	let nodes = create_nodes(2);
+	let payment_release_secret = [42; 32];
+
+	let secp_ctx = Secp256k1::new();
+	let blinded_path = BlindedPath::new_for_message_with_path_id(
+		&[nodes[1].node_id], [43; 32], &*nodes[1].entropy_source, &secp_ctx
+	).unwrap();
+	let reply_path = BlindedPath::new_for_message(&[nodes[0].node_id], &*nodes[0].entropy_source, &secp_ctx).unwrap();
+	let path = OnionMessagePath {
+		intermediate_nodes: vec![],
+		destination: Destination::BlindedPath(blinded_path),
+		first_node_addresses: None,
+	};
+	let message = AsyncPaymentsMessage::HeldHtlcAvailable(HeldHtlcAvailable { payment_release_secret });
+	nodes[0].messenger.send_onion_message_using_path(path, message, Some(reply_path)).unwrap();
+	pass_along_path(&nodes);
+	match &nodes[1].async_payments_message_handler.received_messages.lock().unwrap()[..] {
+		[(AsyncPaymentsMessage::HeldHtlcAvailable(msg), Some(path_id))] => {
+			assert_eq!(msg.payment_release_secret, payment_release_secret);
+			assert_eq!(*path_id, [43; 32]);
+		},
+		_ => panic!("Unexpected messages"),
+	}
+
+	// The recipient releases the HTLC via the reply path.
+	let onion_message = nodes[1].messenger.next_onion_message_for_peer(nodes[0].node_id).unwrap();
+	nodes[0].messenger.handle_onion_message(&nodes[1].node_id, &onion_message);
+	match &nodes[0].async_payments_message_handler.received_messages.lock().unwrap()[..] {
+		[(AsyncPaymentsMessage::ReleaseHeldHtlc(msg), None)] => {
+			assert_eq!(msg.payment_release_secret, payment_release_secret);
+		},
+		_ => panic!("Unexpected messages"),
+	};
+}
+
+#[test]
+fn dns_resolver_query_and_proof() {
+	// nodes[1] acts as a DNS resolver, responding to nodes[0]'s query over the reply path.
+	let nodes = create_nodes(2);
+	let name = Name::try_from("alice.user._bitcoin-payment.example.com.").unwrap();
+
+	let secp_ctx = Secp256k1::new();
+	let reply_path = BlindedPath::new_for_message_with_path_id(
+		&[nodes[0].node_id], [45; 32], &*nodes[0].entropy_source, &secp_ctx
+	).unwrap();
+	let path = OnionMessagePath {
+		intermediate_nodes: vec![],
+		destination: Destination::Node(nodes[1].node_id),
+		first_node_addresses: None,
+	};
+	let message = DNSResolverMessage::DNSSECQuery(DNSSECQuery(name.clone()));
+	nodes[0].messenger.send_onion_message_using_path(path, message, Some(reply_path)).unwrap();
+	pass_along_path(&nodes);
+	assert!(nodes[1].dns_resolver_message_handler.received_proofs.lock().unwrap().is_empty());
+
+	let onion_message = nodes[1].messenger.next_onion_message_for_peer(nodes[0].node_id).unwrap();
+	nodes[0].messenger.handle_onion_message(&nodes[1].node_id, &onion_message);
+	match &nodes[0].dns_resolver_message_handler.received_proofs.lock().unwrap()[..] {
+		[(proof, Some(path_id))] => {
+			assert_eq!(proof.name, name);
+			assert_eq!(proof.proof, TEST_DNSSEC_PROOF.to_vec());
+			assert_eq!(*path_id, [45; 32]);
+		},
+		_ => panic!("Unexpected messages"),
+	};
+}
+
 #[test]
 fn too_big_packet_error() {
 	// Make sure we error as expected if a packet is too big to send.
@@ -28,6 +28,8 @@ use crate::ln::onion_utils;
 use crate::routing::gossip::{NetworkGraph, NodeId};
 pub use super::packet::OnionMessageContents;
 use super::packet::ParsedOnionMessageContents;
+use super::async_payments::{AsyncPaymentsMessage, AsyncPaymentsMessageHandler};
+use super::dns_resolution::{DNSResolverMessage, DNSResolverMessageHandler};
 use super::offers::OffersMessageHandler;
 use super::packet::{BIG_PACKET_HOP_DATA_LEN, ForwardControlTlvs, Packet, Payload, ReceiveControlTlvs, SMALL_PACKET_HOP_DATA_LEN};
 use crate::util::logger::Logger;
@@ -56,6 +58,8 @@ pub(super) const MAX_TIMER_TICKS: usize = 2;
 /// messages to peers or delegating to the appropriate handler for the message type. Currently, the
 /// available handlers are:
 /// * [`OffersMessageHandler`], for responding to [`InvoiceRequest`]s and paying [`Bolt12Invoice`]s
+/// * [`AsyncPaymentsMessageHandler`], for holding and releasing HTLCs of async payments
+/// * [`DNSResolverMessageHandler`], for resolving human-readable names to offers via DNSSEC proofs
 /// * [`CustomOnionMessageHandler`], for handling user-defined message types
 ///
 /// # Sending Messages
@@ -114,11 +118,13 @@ pub(super) const MAX_TIMER_TICKS: usize = 2;
 /// # let message_router = Arc::new(FakeMessageRouter {});
 /// # let custom_message_handler = IgnoringMessageHandler {};
 /// # let offers_message_handler = IgnoringMessageHandler {};
+/// # let async_payments_message_handler = IgnoringMessageHandler {};
+/// # let dns_resolver_message_handler = IgnoringMessageHandler {};
 /// // Create the onion messenger. This must use the same `keys_manager` as is passed to your
 /// // ChannelManager.
 /// let onion_messenger = OnionMessenger::new(
 ///     &keys_manager, &keys_manager, logger, message_router, &offers_message_handler,
-///     &custom_message_handler
+///     &async_payments_message_handler, &dns_resolver_message_handler, &custom_message_handler
 /// );
 
 /// # #[derive(Debug)]
@@ -155,13 +161,15 @@ pub(super) const MAX_TIMER_TICKS: usize = 2;
 ///
 /// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
 /// [`Bolt12Invoice`]: crate::offers::invoice::Bolt12Invoice
-pub struct OnionMessenger<ES: Deref, NS: Deref, L: Deref, MR: Deref, OMH: Deref, CMH: Deref>
+pub struct OnionMessenger<ES: Deref, NS: Deref, L: Deref, MR: Deref, OMH: Deref, APH: Deref, DRH: Deref, CMH: Deref>
 where
 	ES::Target: EntropySource,
 	NS::Target: NodeSigner,
 	L::Target: Logger,
 	MR::Target: MessageRouter,
 	OMH::Target: OffersMessageHandler,
+	APH::Target: AsyncPaymentsMessageHandler,
+	DRH::Target: DNSResolverMessageHandler,
 	CMH::Target: CustomOnionMessageHandler,
 {
 	entropy_source: ES,
@@ -171,6 +179,8 @@ where
 	secp_ctx: Secp256k1<secp256k1::All>,
 	message_router: MR,
 	offers_handler: OMH,
+	async_payments_handler: APH,
+	dns_resolver_handler: DRH,
 	custom_handler: CMH,
 }
 
@@ -683,21 +693,23 @@ where
 	}
 }
 
-impl<ES: Deref, NS: Deref, L: Deref, MR: Deref, OMH: Deref, CMH: Deref>
-OnionMessenger<ES, NS, L, MR, OMH, CMH>
+impl<ES: Deref, NS: Deref, L: Deref, MR: Deref, OMH: Deref, APH: Deref, DRH: Deref, CMH: Deref>
+OnionMessenger<ES, NS, L, MR, OMH, APH, DRH, CMH>
 where
 	ES::Target: EntropySource,
 	NS::Target: NodeSigner,
 	L::Target: Logger,
 	MR::Target: MessageRouter,
 	OMH::Target: OffersMessageHandler,
+	APH::Target: AsyncPaymentsMessageHandler,
+	DRH::Target: DNSResolverMessageHandler,
 	CMH::Target: CustomOnionMessageHandler,
 {
 	/// Constructs a new `OnionMessenger` to send, forward, and delegate received onion messages to
 	/// their respective handlers.
 	pub fn new(
 		entropy_source: ES, node_signer: NS, logger: L, message_router: MR, offers_handler: OMH,
-		custom_handler: CMH
+		async_payments_handler: APH, dns_resolver_handler: DRH, custom_handler: CMH
 	) -> Self {
 		let mut secp_ctx = Secp256k1::new();
 		secp_ctx.seeded_randomize(&entropy_source.get_secure_random_bytes());
@@ -709,6 +721,8 @@ where
 			logger,
 			message_router,
 			offers_handler,
+			async_payments_handler,
+			dns_resolver_handler,
 			custom_handler,
 		}
 	}
@@ -866,14 +880,16 @@ fn outbound_buffer_full(peer_node_id: &PublicKey, buffer: &HashMap<PublicKey, On
 	false
 }
 
-impl<ES: Deref, NS: Deref, L: Deref, MR: Deref, OMH: Deref, CMH: Deref> EventsProvider
-for OnionMessenger<ES, NS, L, MR, OMH, CMH>
+impl<ES: Deref, NS: Deref, L: Deref, MR: Deref, OMH: Deref, APH: Deref, DRH: Deref, CMH: Deref> EventsProvider
+for OnionMessenger<ES, NS, L, MR, OMH, APH, DRH, CMH>
 where
 	ES::Target: EntropySource,
 	NS::Target: NodeSigner,
 	L::Target: Logger,
 	MR::Target: MessageRouter,
 	OMH::Target: OffersMessageHandler,
+	APH::Target: AsyncPaymentsMessageHandler,
+	DRH::Target: DNSResolverMessageHandler,
 	CMH::Target: CustomOnionMessageHandler,
 {
 	fn process_pending_events<H: Deref>(&self, handler: H) where H::Target: EventHandler {
@@ -887,14 +903,16 @@ where
 	}
 }
 
-impl<ES: Deref, NS: Deref, L: Deref, MR: Deref, OMH: Deref, CMH: Deref> OnionMessageHandler
-for OnionMessenger<ES, NS, L, MR, OMH, CMH>
+impl<ES: Deref, NS: Deref, L: Deref, MR: Deref, OMH: Deref, APH: Deref, DRH: Deref, CMH: Deref> OnionMessageHandler
+for OnionMessenger<ES, NS, L, MR, OMH, APH, DRH, CMH>
 where
 	ES::Target: EntropySource,
 	NS::Target: NodeSigner,
 	L::Target: Logger,
 	MR::Target: MessageRouter,
 	OMH::Target: OffersMessageHandler,
+	APH::Target: AsyncPaymentsMessageHandler,
+	DRH::Target: DNSResolverMessageHandler,
 	CMH::Target: CustomOnionMessageHandler,
 {
 	fn handle_onion_message(&self, _peer_node_id: &PublicKey, msg: &OnionMessage) {
@@ -917,6 +935,24 @@ where
 							)
 						);
 					},
+					ParsedOnionMessageContents::AsyncPayments(AsyncPaymentsMessage::HeldHtlcAvailable(msg)) => {
+						self.async_payments_handler.held_htlc_available(msg, path_id, reply_path);
+					},
+					ParsedOnionMessageContents::AsyncPayments(AsyncPaymentsMessage::ReleaseHeldHtlc(msg)) => {
+						self.async_payments_handler.release_held_htlc(msg);
+					},
+					ParsedOnionMessageContents::DNSResolver(DNSResolverMessage::DNSSECQuery(msg)) => {
+						let response = self.dns_resolver_handler.handle_dnssec_query(msg);
+						self.handle_onion_message_response(
+							response, reply_path, format_args!(
+								"when responding to DNSSECQuery onion message with path_id {:02x?}",
+								path_id
+							)
+						);
+					},
+					ParsedOnionMessageContents::DNSResolver(DNSResolverMessage::DNSSECProof(msg)) => {
+						self.dns_resolver_handler.handle_dnssec_proof(msg, path_id);
+					},
 					ParsedOnionMessageContents::Custom(msg) => {
 						let response = self.custom_handler.handle_custom_message(msg);
 						self.handle_onion_message_response(
@@ -1027,6 +1063,28 @@ where
 			);
 		}
 
+		// Enqueue any initiating `AsyncPaymentsMessage`s to send.
+		for message in self.async_payments_handler.release_pending_messages() {
+			#[cfg(not(c_bindings))]
+			let PendingOnionMessage { contents, destination, reply_path } = message;
+			#[cfg(c_bindings)]
+			let (contents, destination, reply_path) = message;
+			let _ = self.find_path_and_enqueue_onion_message(
+				contents, destination, reply_path, format_args!("when sending AsyncPaymentsMessage")
+			);
+		}
+
+		// Enqueue any initiating `DNSResolverMessage`s to send.
+		for message in self.dns_resolver_handler.release_pending_messages() {
+			#[cfg(not(c_bindings))]
+			let PendingOnionMessage { contents, destination, reply_path } = message;
+			#[cfg(c_bindings)]
+			let (contents, destination, reply_path) = message;
+			let _ = self.find_path_and_enqueue_onion_message(
+				contents, destination, reply_path, format_args!("when sending DNSResolverMessage")
+			);
+		}
+
 		// Enqueue any initiating `CustomMessage`s to send.
 		for message in self.custom_handler.release_pending_custom_messages() {
 			#[cfg(not(c_bindings))]
@@ -1060,6 +1118,8 @@ pub type SimpleArcOnionMessenger<M, T, F, L> = OnionMessenger<
 	Arc<L>,
 	Arc<DefaultMessageRouter<Arc<NetworkGraph<Arc<L>>>, Arc<L>>>,
 	Arc<SimpleArcChannelManager<M, T, F, L>>,
+	Arc<SimpleArcChannelManager<M, T, F, L>>,
+	Arc<SimpleArcChannelManager<M, T, F, L>>,
 	IgnoringMessageHandler
 >;
 
@@ -1079,6 +1139,8 @@ pub type SimpleRefOnionMessenger<
 	&'b L,
 	&'i DefaultMessageRouter<&'g NetworkGraph<&'b L>, &'b L>,
 	&'j SimpleRefChannelManager<'a, 'b, 'c, 'd, 'e, 'f, 'g, 'h, M, T, F, L>,
+	&'j SimpleRefChannelManager<'a, 'b, 'c, 'd, 'e, 'f, 'g, 'h, M, T, F, L>,
+	&'j SimpleRefChannelManager<'a, 'b, 'c, 'd, 'e, 'f, 'g, 'h, M, T, F, L>,
 	IgnoringMessageHandler
 >;
 
@@ -17,6 +17,7 @@ use crate::offers::invoice_error::InvoiceError;
 use crate::offers::invoice_request::InvoiceRequest;
 use crate::offers::invoice::Bolt12Invoice;
 use crate::offers::parse::Bolt12ParseError;
+use crate::offers::static_invoice::StaticInvoice;
 use crate::onion_message::OnionMessageContents;
 use crate::util::logger::Logger;
 use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer};
@@ -29,6 +30,7 @@ use crate::prelude::*;
 const INVOICE_REQUEST_TLV_TYPE: u64 = 64;
 const INVOICE_TLV_TYPE: u64 = 66;
 const INVOICE_ERROR_TLV_TYPE: u64 = 68;
+const STATIC_INVOICE_TLV_TYPE: u64 = 70;
 
 /// A handler for an [`OnionMessage`] containing a BOLT 12 Offers message as its payload.
 ///
@@ -74,13 +76,18 @@ pub enum OffersMessage {
 
 	/// An error from handling an [`OffersMessage`].
 	InvoiceError(InvoiceError),
+
+	/// A [`StaticInvoice`] sent in response to an [`InvoiceRequest`] on behalf of an often-offline
+	/// recipient.
+	StaticInvoice(StaticInvoice),
 }
 
 impl OffersMessage {
 	/// Returns whether `tlv_type` corresponds to a TLV record for Offers.
 	pub fn is_known_type(tlv_type: u64) -> bool {
 		match tlv_type {
-			INVOICE_REQUEST_TLV_TYPE | INVOICE_TLV_TYPE | INVOICE_ERROR_TLV_TYPE => true,
+			INVOICE_REQUEST_TLV_TYPE | INVOICE_TLV_TYPE | INVOICE_ERROR_TLV_TYPE
+				| STATIC_INVOICE_TLV_TYPE => true,
 			_ => false,
 		}
 	}
@@ -89,6 +96,7 @@ impl OffersMessage {
 		match tlv_type {
 			INVOICE_REQUEST_TLV_TYPE => Ok(Self::InvoiceRequest(InvoiceRequest::try_from(bytes)?)),
 			INVOICE_TLV_TYPE => Ok(Self::Invoice(Bolt12Invoice::try_from(bytes)?)),
+			STATIC_INVOICE_TLV_TYPE => Ok(Self::StaticInvoice(StaticInvoice::try_from(bytes)?)),
 			_ => Err(Bolt12ParseError::Decode(DecodeError::InvalidValue)),
 		}
 	}
@@ -106,6 +114,9 @@ impl fmt::Debug for OffersMessage {
 			OffersMessage::InvoiceError(message) => {
 				write!(f, "{:?}", message)
 			}
+			OffersMessage::StaticInvoice(message) => {
+				write!(f, "{:?}", message.as_tlv_stream())
+			}
 		}
 	}
 }
@@ -116,6 +127,7 @@ impl OnionMessageContents for OffersMessage {
 			OffersMessage::InvoiceRequest(_) => INVOICE_REQUEST_TLV_TYPE,
 			OffersMessage::Invoice(_) => INVOICE_TLV_TYPE,
 			OffersMessage::InvoiceError(_) => INVOICE_ERROR_TLV_TYPE,
+			OffersMessage::StaticInvoice(_) => STATIC_INVOICE_TLV_TYPE,
 		}
 	}
 }
@@ -126,6 +138,7 @@ impl Writeable for OffersMessage {
 			OffersMessage::InvoiceRequest(message) => message.write(w),
 			OffersMessage::Invoice(message) => message.write(w),
 			OffersMessage::InvoiceError(message) => message.write(w),
+			OffersMessage::StaticInvoice(message) => message.write(w),
 		}
 	}
 }
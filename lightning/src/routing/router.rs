@@ -418,6 +418,27 @@ impl_writeable_tlv_based!(BlindedTail, {
 	(6, final_value_msat, required),
 });
 
+/// A trampoline node which we hand a payment off to, letting it find a route to the recipient on
+/// our behalf.
+///
+/// Used in [`ChannelManager::send_payment_via_trampoline`] by nodes which lack enough of the
+/// network graph to route to the recipient themselves.
+///
+/// [`ChannelManager::send_payment_via_trampoline`]: crate::ln::channelmanager::ChannelManager::send_payment_via_trampoline
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct TrampolineHop {
+	/// The node_id of the trampoline node, which must be one of our channel counterparties and
+	/// support [`NodeFeatures::supports_trampoline_routing`].
+	pub pubkey: PublicKey,
+	/// The fee the trampoline node charges to find and pay for a route to the recipient.
+	pub fee_msat: u64,
+	/// The CLTV delta the trampoline node requires, which must cover both its own forwarding delta
+	/// and the route it finds to the recipient. Must be at least [`MIN_CLTV_EXPIRY_DELTA`].
+	///
+	/// [`MIN_CLTV_EXPIRY_DELTA`]: crate::ln::channelmanager::MIN_CLTV_EXPIRY_DELTA
+	pub cltv_expiry_delta: u32,
+}
+
 /// A path in a [`Route`] to the payment recipient. Must always be at least length one.
 /// If no [`Path::blinded_tail`] is present, then [`Path::hops`] length may be up to 19.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
@@ -458,6 +479,11 @@ impl Path {
 	}
 }
 
+impl_writeable_tlv_based!(Path, {
+	(0, hops, required_vec),
+	(1, blinded_tail, option),
+});
+
 /// A route directs a payment from the sender (us) to the recipient. If the recipient supports MPP,
 /// it can take multiple paths. Each path is composed of one or more hops through the network.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
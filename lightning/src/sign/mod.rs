@@ -620,13 +620,24 @@ pub trait ChannelSigner {
 	/// Set the counterparty static channel data, including basepoints,
 	/// `counterparty_selected`/`holder_selected_contest_delay` and funding outpoint.
 	///
-	/// This data is static, and will never change for a channel once set. For a given [`ChannelSigner`]
-	/// instance, LDK will call this method exactly once - either immediately after construction
-	/// (not including if done via [`SignerProvider::read_chan_signer`]) or when the funding
-	/// information has been generated.
+	/// This data is static, and will never change for a channel once set, with the exception of
+	/// the channel type, which is only ever updated via [`Self::provide_channel_type_features`].
+	/// For a given [`ChannelSigner`] instance, LDK will call this method exactly once - either
+	/// immediately after construction (not including if done via [`SignerProvider::read_chan_signer`])
+	/// or when the funding information has been generated.
 	///
 	/// channel_parameters.is_populated() MUST be true.
 	fn provide_channel_parameters(&mut self, channel_parameters: &ChannelTransactionParameters);
+
+	/// Updates the channel type of the parameters previously set via
+	/// [`Self::provide_channel_parameters`].
+	///
+	/// This is called when the channel's type is upgraded while the channel is open (e.g. to
+	/// anchor outputs via `dyn_propose`/`dyn_ack`), before any commitment transaction of the new
+	/// type is signed. It may be called again with the previous channel type if our counterparty
+	/// never committed to the upgrade before a reconnection. All other channel parameters remain
+	/// unchanged.
+	fn provide_channel_type_features(&mut self, channel_type_features: &ChannelTypeFeatures);
 }
 
 /// Specifies the recipient of an invoice.
@@ -666,6 +677,14 @@ pub trait NodeSigner {
 	/// [phantom node payments]: PhantomKeysManager
 	fn get_inbound_payment_key_material(&self) -> KeyMaterial;
 
+	/// Get secret key material as bytes for use in encrypting and decrypting the backup of our
+	/// channels which we ask our peers to store for us via `peer_storage` messages.
+	///
+	/// This method must return the same value each time it is called, including when
+	/// re-initialized from the same seed after losing all other state, as otherwise we won't be
+	/// able to read the backups our peers return to us.
+	fn get_peer_storage_key(&self) -> KeyMaterial;
+
 	/// Get node id based on the provided [`Recipient`].
 	///
 	/// This method must return the same value each time it is called with a given [`Recipient`]
@@ -1107,6 +1126,10 @@ impl ChannelSigner for InMemorySigner {
 		assert!(channel_parameters.is_populated(), "Channel parameters must be fully populated");
 		self.channel_parameters = Some(channel_parameters.clone());
 	}
+
+	fn provide_channel_type_features(&mut self, channel_type_features: &ChannelTypeFeatures) {
+		self.channel_parameters.as_mut().expect(MISSING_PARAMS_ERR).channel_type_features = channel_type_features.clone();
+	}
 }
 
 const MISSING_PARAMS_ERR: &'static str = "ChannelSigner::provide_channel_parameters must be called before signing operations";
@@ -1374,6 +1397,7 @@ pub struct KeysManager {
 	node_secret: SecretKey,
 	node_id: PublicKey,
 	inbound_payment_key: KeyMaterial,
+	peer_storage_key: KeyMaterial,
 	destination_script: ScriptBuf,
 	shutdown_pubkey: PublicKey,
 	channel_master_key: ExtendedPrivKey,
@@ -1429,6 +1453,9 @@ impl KeysManager {
 				let inbound_payment_key: SecretKey = master_key.ckd_priv(&secp_ctx, ChildNumber::from_hardened_idx(5).unwrap()).expect("Your RNG is busted").private_key;
 				let mut inbound_pmt_key_bytes = [0; 32];
 				inbound_pmt_key_bytes.copy_from_slice(&inbound_payment_key[..]);
+				let peer_storage_key: SecretKey = master_key.ckd_priv(&secp_ctx, ChildNumber::from_hardened_idx(6).unwrap()).expect("Your RNG is busted").private_key;
+				let mut peer_storage_key_bytes = [0; 32];
+				peer_storage_key_bytes.copy_from_slice(&peer_storage_key[..]);
 
 				let mut rand_bytes_engine = Sha256::engine();
 				rand_bytes_engine.input(&starting_time_secs.to_be_bytes());
@@ -1442,6 +1469,7 @@ impl KeysManager {
 					node_secret,
 					node_id,
 					inbound_payment_key: KeyMaterial(inbound_pmt_key_bytes),
+					peer_storage_key: KeyMaterial(peer_storage_key_bytes),
 
 					destination_script,
 					shutdown_pubkey,
@@ -1661,6 +1689,10 @@ impl NodeSigner for KeysManager {
 		self.inbound_payment_key.clone()
 	}
 
+	fn get_peer_storage_key(&self) -> KeyMaterial {
+		self.peer_storage_key.clone()
+	}
+
 	fn sign_invoice(&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient) -> Result<RecoverableSignature, ()> {
 		let preimage = construct_invoice_preimage(&hrp_bytes, &invoice_data);
 		let secret = match recipient {
@@ -1789,6 +1821,10 @@ impl NodeSigner for PhantomKeysManager {
 		self.inbound_payment_key.clone()
 	}
 
+	fn get_peer_storage_key(&self) -> KeyMaterial {
+		self.inner.get_peer_storage_key()
+	}
+
 	fn sign_invoice(&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient) -> Result<RecoverableSignature, ()> {
 		let preimage = construct_invoice_preimage(&hrp_bytes, &invoice_data);
 		let secret = match recipient {
@@ -178,6 +178,25 @@ pub struct ChannelHandshakeConfig {
 	/// [`DecodeError::InvalidValue`]: crate::ln::msgs::DecodeError::InvalidValue
 	/// [`SIGHASH_SINGLE + update_fee Considered Harmful`]: https://lists.linuxfoundation.org/pipermail/lightning-dev/2020-September/002796.html
 	pub negotiate_anchors_zero_fee_htlc_tx: bool,
+	/// If set along with [`Self::negotiate_anchors_zero_fee_htlc_tx`], we attempt to negotiate
+	/// the `zero_fee_commitments` option for all future channels.
+	///
+	/// Commitment transactions of such channels are version 3 (TRUC) transactions which pay no fee
+	/// and carry a single ephemeral anchor output in place of the two 330 sat anchors. As neither
+	/// side has to agree on a commitment feerate, `update_fee` is never sent and disagreements
+	/// over the feerate can no longer force-close the channel. Instead, the full fee of a
+	/// commitment transaction is paid on broadcast by a child transaction spending the ephemeral
+	/// anchor, so the same reserve of onchain funds as for anchor channels is required.
+	///
+	/// Note that this option requires transactions to be relayed as packages, which not all
+	/// [`BroadcasterInterface`] implementations support. Setting this to true does *not* prevent
+	/// us from opening channels with counterparties that do not support the option; we will
+	/// simply fall back to an `anchors_zero_fee_htlc_tx` channel.
+	///
+	/// Default value: false.
+	///
+	/// [`BroadcasterInterface`]: crate::chain::chaininterface::BroadcasterInterface
+	pub negotiate_zero_fee_commitments: bool,
 
 	/// The maximum number of HTLCs in-flight from our counterparty towards us at the same time.
 	///
@@ -189,7 +208,9 @@ pub struct ChannelHandshakeConfig {
 	///
 	/// Default value: 50
 	/// Maximum value: 483, any values larger will be treated as 483.
-	///                     This is the BOLT #2 spec limit on `max_accepted_htlcs`.
+	///                     This is the BOLT #2 spec limit on `max_accepted_htlcs`. On channels
+	///                     using zero-fee commitments, values larger than 114 will be treated as
+	///                     114 to keep the commitment transaction within the TRUC size limit.
 	pub our_max_accepted_htlcs: u16,
 }
 
@@ -205,6 +226,7 @@ impl Default for ChannelHandshakeConfig {
 			commit_upfront_shutdown_pubkey: true,
 			their_channel_reserve_proportional_millionths: 10_000,
 			negotiate_anchors_zero_fee_htlc_tx: false,
+			negotiate_zero_fee_commitments: false,
 			our_max_accepted_htlcs: 50,
 		}
 	}
@@ -697,6 +719,175 @@ impl crate::util::ser::Readable for LegacyChannelConfig {
 	}
 }
 
+/// Config for the experimental HTLC endorsement and local reputation scheme used to mitigate
+/// channel jamming when forwarding HTLCs.
+///
+/// When enabled, we track a reputation for each peer forwarding HTLCs to us, based on the fees
+/// its HTLCs earned us and how long they took to resolve. A share of each outgoing channel's HTLC
+/// slots and liquidity is then reserved for HTLCs which our counterparty endorsed (see
+/// [`msgs::UpdateAddHTLC::endorsed`]) and which come from a peer with good reputation, and only
+/// such HTLCs are forwarded as endorsed.
+///
+/// Default::default() provides sane defaults for most configurations.
+///
+/// [`msgs::UpdateAddHTLC::endorsed`]: crate::ln::msgs::UpdateAddHTLC::endorsed
+#[derive(Copy, Clone, Debug)]
+pub struct HTLCEndorsementConfig {
+	/// If this is set to true, we'll read and set the endorsement signal on HTLCs we forward, track
+	/// our peers' reputation, and reserve resources for endorsed HTLCs as described above.
+	///
+	/// If this is set to false, HTLCs are forwarded without an endorsement signal and no
+	/// resources are reserved.
+	///
+	/// Default value: false.
+	pub enabled: bool,
+	/// The time, in seconds, within which we expect a forwarded HTLC to be resolved. Endorsed HTLCs
+	/// which take longer to resolve are penalized in their incoming peer's reputation in
+	/// proportion to the fees they would have earned.
+	///
+	/// Default value: 90.
+	pub resolution_period_secs: u64,
+	/// The half-life, in seconds, of a peer's reputation and of the revenue each outgoing channel
+	/// earned us. A peer has good reputation if the fees its HTLCs earned us, net of penalties,
+	/// exceed the revenue of the outgoing channel over the same period, even if all of its pending
+	/// HTLCs were to be held until they expire.
+	///
+	/// Default value: 1,209,600 (two weeks).
+	pub reputation_half_life_secs: u64,
+	/// The percentage of an outgoing channel's HTLC slots which may only be used by endorsed HTLCs
+	/// from peers with good reputation.
+	///
+	/// Default value: 50.
+	pub protected_slots_percent: u8,
+	/// The percentage of an outgoing channel's maximum value in flight which may only be used by
+	/// endorsed HTLCs from peers with good reputation.
+	///
+	/// Default value: 50.
+	pub protected_liquidity_percent: u8,
+}
+
+impl Default for HTLCEndorsementConfig {
+	fn default() -> Self {
+		HTLCEndorsementConfig {
+			enabled: false,
+			resolution_period_secs: 90,
+			reputation_half_life_secs: 60 * 60 * 24 * 14,
+			protected_slots_percent: 50,
+			protected_liquidity_percent: 50,
+		}
+	}
+}
+
+/// Limits on the HTLCs a single peer may have us forward, across all of its channels.
+///
+/// Unlike the per-channel limits in [`ChannelHandshakeConfig`], these cap the total resources a
+/// peer can consume on our outgoing channels and how quickly it can add HTLCs. HTLCs exceeding a
+/// limit are failed back with a `temporary_channel_failure` and an
+/// [`Event::PeerForwardingLimitExceeded`] is generated.
+///
+/// Default::default() sets no limits.
+///
+/// [`Event::PeerForwardingLimitExceeded`]: crate::events::Event::PeerForwardingLimitExceeded
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PeerForwardingLimits {
+	/// The maximum number of HTLCs received from a peer which we may have pending forward at once.
+	///
+	/// Default value: None (no limit).
+	pub max_inflight_htlcs: Option<u32>,
+	/// The maximum total value, in millisatoshi, of the HTLCs we forward on behalf of a peer which
+	/// may be pending at once. This is counted in the amount forwarded, excluding our fees.
+	///
+	/// Default value: None (no limit).
+	pub max_inflight_value_msat: Option<u64>,
+	/// The maximum number of HTLCs received from a peer which we'll forward in any given second.
+	///
+	/// Default value: None (no limit).
+	pub max_htlcs_per_second: Option<u32>,
+}
+
+/// Config for automatically managing the forwarding fees of our channels.
+///
+/// When enabled, every [`update_interval_ticks`] calls to
+/// [`ChannelManager::timer_tick_occurred`] we set each usable channel's
+/// [`ChannelConfig::forwarding_fee_base_msat`] and
+/// [`ChannelConfig::forwarding_fee_proportional_millionths`] based on our share of its liquidity,
+/// between the configured minimum (when all of the channel's funds are on our side) and maximum
+/// (when none are). Channels which recently forwarded a lot of value relative to their capacity
+/// have their fees increased further by up to [`volume_fee_increase_percent`].
+///
+/// In order to limit the number of `channel_update`s we broadcast, fees are only changed if they
+/// moved by at least [`min_change_percent`].
+///
+/// Default::default() provides sane defaults for most configurations.
+///
+/// [`update_interval_ticks`]: Self::update_interval_ticks
+/// [`volume_fee_increase_percent`]: Self::volume_fee_increase_percent
+/// [`min_change_percent`]: Self::min_change_percent
+/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+#[derive(Copy, Clone, Debug)]
+pub struct DynamicFeeConfig {
+	/// If this is set to true, we'll manage the forwarding fees of our channels as described above,
+	/// overriding any fees set via [`ChannelManager::update_channel_config`].
+	///
+	/// Default value: false.
+	///
+	/// [`ChannelManager::update_channel_config`]: crate::ln::channelmanager::ChannelManager::update_channel_config
+	pub enabled: bool,
+	/// The proportional fee we charge when all of a channel's funds are on our side.
+	///
+	/// Default value: 1.
+	pub min_fee_proportional_millionths: u32,
+	/// The proportional fee we charge when none of a channel's funds are on our side.
+	///
+	/// Default value: 2,000.
+	pub max_fee_proportional_millionths: u32,
+	/// The base fee we charge when all of a channel's funds are on our side.
+	///
+	/// Default value: 0.
+	pub min_fee_base_msat: u32,
+	/// The base fee we charge when none of a channel's funds are on our side.
+	///
+	/// Default value: 1,000.
+	pub max_fee_base_msat: u32,
+	/// The percentage by which we increase the fees of a channel which recently forwarded at least
+	/// its capacity, scaled down linearly for channels which forwarded less. Fees are never
+	/// increased beyond the configured maximum.
+	///
+	/// Recent volume is measured from the HTLCs forwarded over the channel and halves every
+	/// [`update_interval_ticks`].
+	///
+	/// Default value: 50.
+	///
+	/// [`update_interval_ticks`]: Self::update_interval_ticks
+	pub volume_fee_increase_percent: u16,
+	/// The number of [`ChannelManager::timer_tick_occurred`] calls between fee updates.
+	///
+	/// Default value: 60 (one hour if `timer_tick_occurred` is called once a minute).
+	///
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	pub update_interval_ticks: u16,
+	/// The minimum change, as a percentage of the current fee, for which we'll update a channel's
+	/// fees and broadcast a new `channel_update`.
+	///
+	/// Default value: 10.
+	pub min_change_percent: u8,
+}
+
+impl Default for DynamicFeeConfig {
+	fn default() -> Self {
+		DynamicFeeConfig {
+			enabled: false,
+			min_fee_proportional_millionths: 1,
+			max_fee_proportional_millionths: 2_000,
+			min_fee_base_msat: 0,
+			max_fee_base_msat: 1_000,
+			volume_fee_increase_percent: 50,
+			update_interval_ticks: 60,
+			min_change_percent: 10,
+		}
+	}
+}
+
 /// Top-level config which holds ChannelHandshakeLimits and ChannelConfig.
 ///
 /// Default::default() provides sane defaults for most configurations
@@ -765,6 +956,79 @@ pub struct UserConfig {
 	///
 	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
 	pub accept_mpp_keysend: bool,
+	/// If this is set to true, we'll advertise `option_provide_storage` and store a small backup
+	/// blob on behalf of each peer we have a funded channel with, returning it to them whenever
+	/// they reconnect. We'll also send an encrypted backup of our own channels to every peer which
+	/// advertises `option_provide_storage`, allowing us to recover after losing all state but our
+	/// seed (see [`Event::PeerStorageRetrieved`]).
+	///
+	/// Default value: false.
+	///
+	/// [`Event::PeerStorageRetrieved`]: crate::events::Event::PeerStorageRetrieved
+	pub enable_peer_storage: bool,
+	/// If this is set to true, we'll hold HTLCs forwarded to us by often-offline senders which
+	/// request it (via [`msgs::UpdateAddHTLC::hold_htlc`]) until we receive a [`ReleaseHeldHtlc`]
+	/// onion message from the recipient's LSP, indicating the recipient is online. Held HTLCs are
+	/// failed back if they come close to expiring before being released.
+	///
+	/// This should only be set by LSPs serving often-offline senders. If this is set to false, such
+	/// HTLCs are forwarded immediately.
+	///
+	/// Default value: false.
+	///
+	/// [`msgs::UpdateAddHTLC::hold_htlc`]: crate::ln::msgs::UpdateAddHTLC::hold_htlc
+	/// [`ReleaseHeldHtlc`]: crate::onion_message::ReleaseHeldHtlc
+	pub hold_htlcs_for_async_payments: bool,
+	/// If this is set to true, we'll advertise `option_trampoline_routing` and accept HTLCs which
+	/// carry a trampoline onion, finding a route to the next trampoline node on the sender's behalf
+	/// using our [`Router`]. The sender pays us a trampoline fee out of which we pay the fees of the
+	/// route we find, with the remainder being reported in [`Event::PaymentForwarded`].
+	///
+	/// If this is set to false, HTLCs carrying a trampoline onion are failed back.
+	///
+	/// Default value: false.
+	///
+	/// [`Router`]: crate::routing::router::Router
+	/// [`Event::PaymentForwarded`]: crate::events::Event::PaymentForwarded
+	pub accept_trampoline_forwards: bool,
+	/// The percentage by which an amount converted from an [`Offer`] denominated in a currency
+	/// other than bitcoin may deviate from our own conversion using the [`CurrencyConversion`]
+	/// given to [`ChannelManager::set_currency_conversion`].
+	///
+	/// When paying for such an offer, we won't pay a [`Bolt12Invoice`] whose amount exceeds our
+	/// conversion by more than this percentage. When responding to an [`InvoiceRequest`] for such an
+	/// offer which specifies an amount, we'll reject it if the amount is less than our conversion by
+	/// more than this percentage.
+	///
+	/// Default value: 1.
+	///
+	/// [`Offer`]: crate::offers::offer::Offer
+	/// [`CurrencyConversion`]: crate::offers::offer::CurrencyConversion
+	/// [`ChannelManager::set_currency_conversion`]: crate::ln::channelmanager::ChannelManager::set_currency_conversion
+	/// [`Bolt12Invoice`]: crate::offers::invoice::Bolt12Invoice
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	pub currency_conversion_tolerance_percent: u8,
+	/// The number of blocks before a held payment's [`PaymentClaimable::claim_deadline`] at which
+	/// we'll automatically cancel a hold invoice created via
+	/// [`ChannelManager::create_inbound_hold_payment`], failing its HTLCs back to the sender.
+	///
+	/// This leaves time to fail the HTLCs back off-chain before the sender's upstream hops have to
+	/// close their channels to avoid losing funds. Hold invoices must be created with a
+	/// `min_final_cltv_expiry_delta` of at least [`MIN_FINAL_CLTV_EXPIRY_DELTA`] plus this value.
+	///
+	/// Default value: 12.
+	///
+	/// [`PaymentClaimable::claim_deadline`]: crate::events::Event::PaymentClaimable::claim_deadline
+	/// [`ChannelManager::create_inbound_hold_payment`]: crate::ln::channelmanager::ChannelManager::create_inbound_hold_payment
+	/// [`MIN_FINAL_CLTV_EXPIRY_DELTA`]: crate::ln::channelmanager::MIN_FINAL_CLTV_EXPIRY_DELTA
+	pub hold_invoice_cancel_buffer_blocks: u16,
+	/// Config for the experimental HTLC endorsement scheme used to protect our channels from being
+	/// jammed when forwarding.
+	pub htlc_endorsement_config: HTLCEndorsementConfig,
+	/// Limits on the HTLCs each peer may have us forward, see [`PeerForwardingLimits`].
+	pub peer_forwarding_limits: PeerForwardingLimits,
+	/// Config for automatically managing our channels' forwarding fees, see [`DynamicFeeConfig`].
+	pub dynamic_fee_config: DynamicFeeConfig,
 }
 
 impl Default for UserConfig {
@@ -778,6 +1042,14 @@ impl Default for UserConfig {
 			manually_accept_inbound_channels: false,
 			accept_intercept_htlcs: false,
 			accept_mpp_keysend: false,
+			enable_peer_storage: false,
+			hold_htlcs_for_async_payments: false,
+			accept_trampoline_forwards: false,
+			currency_conversion_tolerance_percent: 1,
+			hold_invoice_cancel_buffer_blocks: 12,
+			htlc_endorsement_config: HTLCEndorsementConfig::default(),
+			peer_forwarding_limits: PeerForwardingLimits::default(),
+			dynamic_fee_config: DynamicFeeConfig::default(),
 		}
 	}
 }
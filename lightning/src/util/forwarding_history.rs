@@ -0,0 +1,457 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A persistent record of the HTLCs we forwarded, both successfully and not, which can be queried
+//! by time range, channel and peer.
+//!
+//! See [`ForwardingHistory`] for details.
+
+use bitcoin::secp256k1::PublicKey;
+
+use core::ops::Deref;
+use core::time::Duration;
+
+use crate::events::{Event, HTLCDestination};
+use crate::io;
+use crate::ln::ChannelId;
+use crate::ln::channelmanager::ChannelDetails;
+use crate::prelude::*;
+use crate::sync::Mutex;
+use crate::util::persist::KVStore;
+use crate::util::ser::{Readable, Writeable};
+
+/// The primary namespace under which the time ranges covered by the [`ForwardingHistory`] will be
+/// persisted.
+pub const FORWARDING_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE: &str = "forwarding_history";
+/// The secondary namespace under which the time ranges covered by the [`ForwardingHistory`] will
+/// be persisted.
+pub const FORWARDING_HISTORY_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
+/// The primary namespace under which the [`ForwardRecord`]s of the [`ForwardingHistory`] will be
+/// persisted. The secondary namespace is the start of the time range the records fall in.
+pub const FORWARDING_HISTORY_RECORDS_PERSISTENCE_PRIMARY_NAMESPACE: &str = "forwarding_history_records";
+
+/// The length of the time ranges the records of the [`ForwardingHistory`] are grouped into.
+pub const FORWARDING_HISTORY_BUCKET_SECS: u64 = 60 * 60;
+
+/// The outcome of an HTLC forward recorded in a [`ForwardRecord`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ForwardOutcome {
+	/// The HTLC was forwarded and claimed, see [`Event::PaymentForwarded`].
+	Succeeded {
+		/// The fee we earned for forwarding the HTLC, if known.
+		fee_earned_msat: Option<u64>,
+		/// The amount we forwarded over the outbound channel, if known.
+		outbound_amount_forwarded_msat: Option<u64>,
+		/// Whether the HTLC was claimed on-chain.
+		claim_from_onchain_tx: bool,
+	},
+	/// We failed to forward the HTLC, see [`Event::HTLCHandlingFailed`].
+	Failed {
+		/// Where we tried to forward the HTLC to.
+		///
+		/// This will be `None` if the destination was written by a newer version of LDK which
+		/// added a new [`HTLCDestination`] variant.
+		failed_next_destination: Option<HTLCDestination>,
+	},
+}
+
+impl_writeable_tlv_based_enum!(ForwardOutcome,
+	(0, Succeeded) => {
+		(0, fee_earned_msat, option),
+		(2, outbound_amount_forwarded_msat, option),
+		(4, claim_from_onchain_tx, required),
+	},
+	(2, Failed) => {
+		(0, failed_next_destination, upgradable_option),
+	};
+);
+
+/// An HTLC forward recorded by the [`ForwardingHistory`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardRecord {
+	/// The time at which the forward was resolved, in seconds since the Unix epoch.
+	pub timestamp: u64,
+	/// The channel over which we received the HTLC, if known.
+	pub prev_channel_id: Option<ChannelId>,
+	/// The counterparty of the channel over which we received the HTLC, if the channel was still
+	/// open when the forward was recorded.
+	pub prev_node_id: Option<PublicKey>,
+	/// The channel over which we forwarded, or tried to forward, the HTLC, if known.
+	pub next_channel_id: Option<ChannelId>,
+	/// The counterparty of the channel over which we forwarded, or tried to forward, the HTLC, if
+	/// known.
+	pub next_node_id: Option<PublicKey>,
+	/// Whether the forward succeeded.
+	pub outcome: ForwardOutcome,
+}
+
+impl_writeable_tlv_based!(ForwardRecord, {
+	(0, timestamp, required),
+	(2, prev_channel_id, option),
+	(4, prev_node_id, option),
+	(6, next_channel_id, option),
+	(8, next_node_id, option),
+	(10, outcome, required),
+});
+
+impl ForwardRecord {
+	fn involves_channel(&self, channel_id: &ChannelId) -> bool {
+		self.prev_channel_id.as_ref() == Some(channel_id) || self.next_channel_id.as_ref() == Some(channel_id)
+	}
+
+	fn involves_peer(&self, node_id: &PublicKey) -> bool {
+		self.prev_node_id.as_ref() == Some(node_id) || self.next_node_id.as_ref() == Some(node_id)
+	}
+}
+
+/// Aggregate statistics over a set of [`ForwardRecord`]s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ForwardingSummary {
+	/// The number of successful forwards.
+	pub succeeded_forwards: u64,
+	/// The number of failed forwards.
+	pub failed_forwards: u64,
+	/// The total fee earned by the successful forwards, not including forwards whose fee is
+	/// unknown.
+	pub fee_earned_msat: u64,
+	/// The total amount forwarded over the outbound channels of the successful forwards, not
+	/// including forwards whose amount is unknown.
+	pub amount_forwarded_msat: u64,
+}
+
+impl ForwardingSummary {
+	/// Aggregates the given records.
+	pub fn from_records<'a, I: IntoIterator<Item = &'a ForwardRecord>>(records: I) -> Self {
+		let mut summary = Self::default();
+		for record in records {
+			summary.add(record);
+		}
+		summary
+	}
+
+	fn add(&mut self, record: &ForwardRecord) {
+		match record.outcome {
+			ForwardOutcome::Succeeded { fee_earned_msat, outbound_amount_forwarded_msat, .. } => {
+				self.succeeded_forwards += 1;
+				self.fee_earned_msat += fee_earned_msat.unwrap_or(0);
+				self.amount_forwarded_msat += outbound_amount_forwarded_msat.unwrap_or(0);
+			},
+			ForwardOutcome::Failed { .. } => self.failed_forwards += 1,
+		}
+	}
+}
+
+/// Records the HTLCs we forwarded, both successfully and not, in a [`KVStore`] and allows querying
+/// them.
+///
+/// Records are created from [`Event::PaymentForwarded`] and [`Event::HTLCHandlingFailed`] events
+/// passed to [`Self::handle_event`], which the `lightning-background-processor` crate does
+/// automatically if a `ForwardingHistory` is provided to its `BackgroundProcessor::start_with_history`
+/// or `process_events_async_with_history`. Failures to handle payments we
+/// received, i.e. [`HTLCDestination::FailedPayment`], are not recorded.
+///
+/// Records are grouped into [`FORWARDING_HISTORY_BUCKET_SECS`]-long time ranges. The start of each
+/// range in seconds since the Unix epoch is stored as a key under the
+/// [`FORWARDING_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE`], and each record is written once, under
+/// its own key in the [`FORWARDING_HISTORY_RECORDS_PERSISTENCE_PRIMARY_NAMESPACE`] with the start
+/// of its range as secondary namespace. Old records may be removed via [`Self::prune`].
+pub struct ForwardingHistory<K: Deref> where K::Target: KVStore {
+	kv_store: K,
+	/// The start of the most recently written range and the key the next record in it will be
+	/// written under, which we keep around to avoid listing the range for each new record.
+	current_bucket: Mutex<Option<(u64, u64)>>,
+}
+
+impl<K: Deref> ForwardingHistory<K> where K::Target: KVStore {
+	/// Constructs a new `ForwardingHistory` which stores records in `kv_store`, appending to any
+	/// records stored previously.
+	pub fn new(kv_store: K) -> Self {
+		Self { kv_store, current_bucket: Mutex::new(None) }
+	}
+
+	/// Records the forward described by the given event, if any, as having been resolved at
+	/// `duration_since_epoch`.
+	///
+	/// `list_channels` is used to look up the counterparties of the channels involved in the
+	/// forward and will generally be [`ChannelManager::list_channels`]. It is only called for
+	/// events which are recorded.
+	///
+	/// [`ChannelManager::list_channels`]: crate::ln::channelmanager::ChannelManager::list_channels
+	pub fn handle_event<F: FnOnce() -> Vec<ChannelDetails>>(
+		&self, event: &Event, duration_since_epoch: Duration, list_channels: F
+	) -> Result<(), io::Error> {
+		let (prev_channel_id, next_channel_id, next_node_id, outcome) = match event {
+			Event::PaymentForwarded {
+				prev_channel_id, next_channel_id, fee_earned_msat, claim_from_onchain_tx,
+				outbound_amount_forwarded_msat, ..
+			} => {
+				let outcome = ForwardOutcome::Succeeded {
+					fee_earned_msat: *fee_earned_msat,
+					outbound_amount_forwarded_msat: *outbound_amount_forwarded_msat,
+					claim_from_onchain_tx: *claim_from_onchain_tx,
+				};
+				(*prev_channel_id, *next_channel_id, None, outcome)
+			},
+			Event::HTLCHandlingFailed { failed_next_destination: HTLCDestination::FailedPayment { .. }, .. } => {
+				return Ok(());
+			},
+			Event::HTLCHandlingFailed { prev_channel_id, failed_next_destination } => {
+				let (next_channel_id, next_node_id) = match failed_next_destination {
+					HTLCDestination::NextHopChannel { node_id, channel_id } => (Some(*channel_id), *node_id),
+					HTLCDestination::TrampolineForward { node_id } => (None, Some(*node_id)),
+					_ => (None, None),
+				};
+				let outcome = ForwardOutcome::Failed {
+					failed_next_destination: Some(failed_next_destination.clone()),
+				};
+				(Some(*prev_channel_id), next_channel_id, next_node_id, outcome)
+			},
+			_ => return Ok(()),
+		};
+
+		let channels = list_channels();
+		let counterparty_node_id = |channel_id: Option<ChannelId>| channel_id.and_then(|channel_id| {
+			channels.iter()
+				.find(|details| details.channel_id == channel_id)
+				.map(|details| details.counterparty.node_id)
+		});
+		let record = ForwardRecord {
+			timestamp: duration_since_epoch.as_secs(),
+			prev_channel_id,
+			prev_node_id: counterparty_node_id(prev_channel_id),
+			next_channel_id,
+			next_node_id: next_node_id.or_else(|| counterparty_node_id(next_channel_id)),
+			outcome,
+		};
+		self.record(record)
+	}
+
+	/// Persists the given record.
+	pub fn record(&self, record: ForwardRecord) -> Result<(), io::Error> {
+		let bucket_start = record.timestamp - record.timestamp % FORWARDING_HISTORY_BUCKET_SECS;
+		let mut current_bucket = self.current_bucket.lock().unwrap();
+		let record_idx = match *current_bucket {
+			Some((start, next_idx)) if start == bucket_start => next_idx,
+			_ => {
+				// Make sure the range is known before writing any records to it, so that we never
+				// write records which aren't returned by queries.
+				self.kv_store.write(
+					FORWARDING_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE,
+					FORWARDING_HISTORY_PERSISTENCE_SECONDARY_NAMESPACE, &bucket_start.to_string(),
+					&[]
+				)?;
+				self.list_record_keys(bucket_start)?.into_iter().max().map_or(0, |idx| idx + 1)
+			},
+		};
+		*current_bucket = None;
+		self.kv_store.write(
+			FORWARDING_HISTORY_RECORDS_PERSISTENCE_PRIMARY_NAMESPACE, &bucket_start.to_string(),
+			&record_idx.to_string(), &record.encode()
+		)?;
+		*current_bucket = Some((bucket_start, record_idx + 1));
+		Ok(())
+	}
+
+	/// Returns all records with a timestamp in the range `[start_secs, end_secs)`, ordered by
+	/// timestamp.
+	pub fn list_forwards(&self, start_secs: u64, end_secs: u64) -> Result<Vec<ForwardRecord>, io::Error> {
+		self.list_forwards_matching(start_secs, end_secs, |_| true)
+	}
+
+	/// Returns the records with a timestamp in the range `[start_secs, end_secs)` for which the
+	/// given channel was either the inbound or the outbound channel, ordered by timestamp.
+	pub fn list_channel_forwards(
+		&self, channel_id: &ChannelId, start_secs: u64, end_secs: u64
+	) -> Result<Vec<ForwardRecord>, io::Error> {
+		self.list_forwards_matching(start_secs, end_secs, |record| record.involves_channel(channel_id))
+	}
+
+	/// Returns the records with a timestamp in the range `[start_secs, end_secs)` for which the
+	/// given peer was either the previous or the next hop, ordered by timestamp.
+	pub fn list_peer_forwards(
+		&self, counterparty_node_id: &PublicKey, start_secs: u64, end_secs: u64
+	) -> Result<Vec<ForwardRecord>, io::Error> {
+		self.list_forwards_matching(start_secs, end_secs, |record| record.involves_peer(counterparty_node_id))
+	}
+
+	/// Aggregates all records with a timestamp in the range `[start_secs, end_secs)`.
+	pub fn summary(&self, start_secs: u64, end_secs: u64) -> Result<ForwardingSummary, io::Error> {
+		Ok(ForwardingSummary::from_records(&self.list_forwards(start_secs, end_secs)?))
+	}
+
+	/// Aggregates the records with a timestamp in the range `[start_secs, end_secs)` by the
+	/// channel over which we forwarded, or tried to forward, the HTLC.
+	///
+	/// Records without a known outbound channel are not included.
+	pub fn summary_by_channel(
+		&self, start_secs: u64, end_secs: u64
+	) -> Result<HashMap<ChannelId, ForwardingSummary>, io::Error> {
+		let mut summaries: HashMap<ChannelId, ForwardingSummary> = HashMap::new();
+		for record in self.list_forwards(start_secs, end_secs)? {
+			if let Some(channel_id) = record.next_channel_id {
+				summaries.entry(channel_id).or_default().add(&record);
+			}
+		}
+		Ok(summaries)
+	}
+
+	/// Removes the records with a timestamp before `before_secs`.
+	///
+	/// Records are removed at the granularity of [`FORWARDING_HISTORY_BUCKET_SECS`], so some
+	/// records older than `before_secs` may remain.
+	pub fn prune(&self, before_secs: u64) -> Result<(), io::Error> {
+		let mut current_bucket = self.current_bucket.lock().unwrap();
+		for bucket_start in self.list_buckets()? {
+			if bucket_start.saturating_add(FORWARDING_HISTORY_BUCKET_SECS) <= before_secs {
+				if current_bucket.as_ref().map_or(false, |(start, _)| *start == bucket_start) {
+					*current_bucket = None;
+				}
+				for record_idx in self.list_record_keys(bucket_start)? {
+					self.kv_store.remove(
+						FORWARDING_HISTORY_RECORDS_PERSISTENCE_PRIMARY_NAMESPACE,
+						&bucket_start.to_string(), &record_idx.to_string(), false
+					)?;
+				}
+				self.kv_store.remove(
+					FORWARDING_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE,
+					FORWARDING_HISTORY_PERSISTENCE_SECONDARY_NAMESPACE, &bucket_start.to_string(),
+					false
+				)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn list_forwards_matching<F: Fn(&ForwardRecord) -> bool>(
+		&self, start_secs: u64, end_secs: u64, filter: F
+	) -> Result<Vec<ForwardRecord>, io::Error> {
+		let mut bucket_starts = self.list_buckets()?;
+		bucket_starts.retain(|bucket_start| {
+			*bucket_start < end_secs &&
+				bucket_start.saturating_add(FORWARDING_HISTORY_BUCKET_SECS) > start_secs
+		});
+		bucket_starts.sort_unstable();
+
+		let mut forwards = Vec::new();
+		for bucket_start in bucket_starts {
+			forwards.extend(self.read_bucket(bucket_start)?.into_iter().filter(|record| {
+				record.timestamp >= start_secs && record.timestamp < end_secs && filter(record)
+			}));
+		}
+		forwards.sort_by_key(|record| record.timestamp);
+		Ok(forwards)
+	}
+
+	fn list_buckets(&self) -> Result<Vec<u64>, io::Error> {
+		Ok(self.kv_store.list(
+			FORWARDING_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE,
+			FORWARDING_HISTORY_PERSISTENCE_SECONDARY_NAMESPACE
+		)?.iter().filter_map(|key| key.parse().ok()).collect())
+	}
+
+	fn list_record_keys(&self, bucket_start: u64) -> Result<Vec<u64>, io::Error> {
+		Ok(self.kv_store.list(
+			FORWARDING_HISTORY_RECORDS_PERSISTENCE_PRIMARY_NAMESPACE, &bucket_start.to_string()
+		)?.iter().filter_map(|key| key.parse().ok()).collect())
+	}
+
+	fn read_bucket(&self, bucket_start: u64) -> Result<Vec<ForwardRecord>, io::Error> {
+		let mut records = Vec::new();
+		for record_idx in self.list_record_keys(bucket_start)? {
+			let bytes = self.kv_store.read(
+				FORWARDING_HISTORY_RECORDS_PERSISTENCE_PRIMARY_NAMESPACE, &bucket_start.to_string(),
+				&record_idx.to_string()
+			)?;
+			records.push(Readable::read(&mut io::Cursor::new(bytes)).map_err(|_| io::Error::new(
+				io::ErrorKind::InvalidData, "Failed to read forwarding history"
+			))?);
+		}
+		Ok(records)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+	use core::time::Duration;
+
+	use crate::events::{Event, HTLCDestination};
+	use crate::ln::ChannelId;
+	use crate::ln::PaymentHash;
+	use crate::util::persist::KVStore;
+	use crate::util::test_utils::TestStore;
+	use super::{ForwardingHistory, ForwardingSummary, ForwardOutcome, FORWARDING_HISTORY_BUCKET_SECS,
+		FORWARDING_HISTORY_RECORDS_PERSISTENCE_PRIMARY_NAMESPACE};
+
+	fn forwarded(prev_channel_id: ChannelId, next_channel_id: ChannelId, fee_earned_msat: u64) -> Event {
+		Event::PaymentForwarded {
+			prev_channel_id: Some(prev_channel_id),
+			next_channel_id: Some(next_channel_id),
+			fee_earned_msat: Some(fee_earned_msat),
+			claim_from_onchain_tx: false,
+			outbound_amount_forwarded_msat: Some(100_000),
+			trampoline_route_fee_msat: None,
+		}
+	}
+
+	#[test]
+	fn records_and_queries_forwards() {
+		let store = TestStore::new(false);
+		let history = ForwardingHistory::new(&store);
+		let chan_a = ChannelId::from_bytes([1; 32]);
+		let chan_b = ChannelId::from_bytes([2; 32]);
+		let chan_c = ChannelId::from_bytes([3; 32]);
+		let node_c = PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[42; 32]).unwrap());
+		let start = 1_000 * FORWARDING_HISTORY_BUCKET_SECS;
+		let at = |secs| Duration::from_secs(start + secs);
+
+		history.handle_event(&forwarded(chan_a, chan_b, 10), at(0), Vec::new).unwrap();
+		history.handle_event(&forwarded(chan_b, chan_a, 20), at(FORWARDING_HISTORY_BUCKET_SECS), Vec::new).unwrap();
+		history.handle_event(&Event::HTLCHandlingFailed {
+			prev_channel_id: chan_a,
+			failed_next_destination: HTLCDestination::NextHopChannel { node_id: Some(node_c), channel_id: chan_c },
+		}, at(FORWARDING_HISTORY_BUCKET_SECS + 1), Vec::new).unwrap();
+		// Failures to receive payments aren't forwards.
+		history.handle_event(&Event::HTLCHandlingFailed {
+			prev_channel_id: chan_a,
+			failed_next_destination: HTLCDestination::FailedPayment { payment_hash: PaymentHash([0; 32]) },
+		}, at(FORWARDING_HISTORY_BUCKET_SECS + 2), Vec::new).unwrap();
+
+		// Each record is written under its own key rather than rewriting its whole time range.
+		let second_bucket = (start + FORWARDING_HISTORY_BUCKET_SECS).to_string();
+		let mut record_keys = store.list(FORWARDING_HISTORY_RECORDS_PERSISTENCE_PRIMARY_NAMESPACE, &second_bucket).unwrap();
+		record_keys.sort_unstable();
+		assert_eq!(record_keys, vec!["0".to_string(), "1".to_string()]);
+
+		let forwards = history.list_forwards(start, start + 2 * FORWARDING_HISTORY_BUCKET_SECS).unwrap();
+		assert_eq!(forwards.len(), 3);
+		assert_eq!(forwards[2].next_node_id, Some(node_c));
+		assert!(matches!(forwards[2].outcome, ForwardOutcome::Failed { .. }));
+		assert_eq!(history.list_forwards(start + 1, start + FORWARDING_HISTORY_BUCKET_SECS + 1).unwrap(), vec![forwards[1].clone()]);
+		assert_eq!(history.list_channel_forwards(&chan_c, 0, u64::max_value()).unwrap(), vec![forwards[2].clone()]);
+		assert_eq!(history.list_peer_forwards(&node_c, 0, u64::max_value()).unwrap(), vec![forwards[2].clone()]);
+
+		assert_eq!(history.summary(0, u64::max_value()).unwrap(), ForwardingSummary {
+			succeeded_forwards: 2, failed_forwards: 1, fee_earned_msat: 30, amount_forwarded_msat: 200_000,
+		});
+		let by_channel = history.summary_by_channel(0, u64::max_value()).unwrap();
+		assert_eq!(by_channel.get(&chan_a).unwrap().fee_earned_msat, 20);
+		assert_eq!(by_channel.get(&chan_b).unwrap().fee_earned_msat, 10);
+		assert_eq!(by_channel.get(&chan_c).unwrap().failed_forwards, 1);
+
+		// Records are appended to those written by a previous instance.
+		let history = ForwardingHistory::new(&store);
+		history.handle_event(&forwarded(chan_a, chan_b, 5), at(2), Vec::new).unwrap();
+		assert_eq!(history.list_forwards(0, u64::max_value()).unwrap().len(), 4);
+
+		history.prune(start + FORWARDING_HISTORY_BUCKET_SECS).unwrap();
+		assert_eq!(history.list_forwards(0, u64::max_value()).unwrap(), forwards[1..].to_vec());
+	}
+}
@@ -20,6 +20,8 @@ pub mod ser;
 pub mod message_signing;
 pub mod invoice;
 pub mod persist;
+pub mod forwarding_history;
+pub mod payment_history;
 pub mod string;
 pub mod wakers;
 #[cfg(fuzzing)]
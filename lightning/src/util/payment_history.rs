@@ -0,0 +1,490 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A persistent ledger of the payments we sent and received, which, unlike
+//! [`ChannelManager::list_recent_payments`], retains payments after they have been resolved.
+//!
+//! See [`PaymentHistory`] for details.
+//!
+//! [`ChannelManager::list_recent_payments`]: crate::ln::channelmanager::ChannelManager::list_recent_payments
+
+use core::ops::Deref;
+use core::time::Duration;
+
+use crate::events::{Event, HTLCDestination, PaymentFailureReason};
+use crate::io;
+use crate::ln::{PaymentHash, PaymentPreimage};
+use crate::ln::channelmanager::PaymentId;
+use crate::prelude::*;
+use crate::routing::router::Path;
+use crate::sync::Mutex;
+use crate::util::persist::KVStore;
+use crate::util::ser::{Readable, Writeable};
+
+/// The primary namespace under which the [`PaymentHistory`] will be persisted.
+pub const PAYMENT_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE: &str = "payment_history";
+/// The secondary namespace under which inbound payments of the [`PaymentHistory`] will be
+/// persisted, keyed by their [`PaymentHash`].
+pub const PAYMENT_HISTORY_INBOUND_PERSISTENCE_SECONDARY_NAMESPACE: &str = "inbound";
+/// The secondary namespace under which outbound payments of the [`PaymentHistory`] will be
+/// persisted, keyed by their [`PaymentId`].
+pub const PAYMENT_HISTORY_OUTBOUND_PERSISTENCE_SECONDARY_NAMESPACE: &str = "outbound";
+
+/// Whether a [`PaymentRecord`] describes a payment we received or one we sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaymentDirection {
+	/// A payment we received.
+	Inbound,
+	/// A payment we sent.
+	Outbound,
+}
+
+impl_writeable_tlv_based_enum!(PaymentDirection,
+	(0, Inbound) => {},
+	(2, Outbound) => {};
+);
+
+/// The status of a payment recorded in a [`PaymentRecord`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaymentStatus {
+	/// The payment has neither succeeded nor failed yet.
+	Pending,
+	/// For outbound payments, the recipient revealed the preimage, see [`Event::PaymentSent`]. For
+	/// inbound payments, we claimed the payment, see [`Event::PaymentClaimed`].
+	Succeeded,
+	/// For outbound payments, we gave up on the payment, see [`Event::PaymentFailed`]. For inbound
+	/// payments, we failed the HTLCs back rather than claiming them.
+	Failed {
+		/// The reason an outbound payment failed, if known.
+		///
+		/// This is always `None` for inbound payments and may be `None` for outbound payments if
+		/// the reason was written by a newer version of LDK which added a new
+		/// [`PaymentFailureReason`] variant.
+		reason: Option<PaymentFailureReason>,
+	},
+}
+
+impl_writeable_tlv_based_enum!(PaymentStatus,
+	(0, Pending) => {},
+	(2, Succeeded) => {},
+	(4, Failed) => {
+		(1, reason, upgradable_option),
+	};
+);
+
+/// What a [`PaymentRecord`] paid for, as provided via [`PaymentHistory::record_outbound_payment`]
+/// or [`PaymentHistory::record_inbound_payment`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PaymentReference {
+	/// A BOLT 11 invoice, in its string encoding.
+	Bolt11Invoice {
+		/// The bech32-encoded invoice.
+		invoice: String,
+	},
+	/// A BOLT 12 offer, in its string encoding.
+	Bolt12Offer {
+		/// The bech32-encoded offer.
+		offer: String,
+	},
+}
+
+impl_writeable_tlv_based_enum_upgradable!(PaymentReference,
+	(0, Bolt11Invoice) => {
+		(0, invoice, required),
+	},
+	(2, Bolt12Offer) => {
+		(0, offer, required),
+	},
+);
+
+/// A payment recorded by the [`PaymentHistory`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaymentRecord {
+	/// Whether we received or sent the payment.
+	pub direction: PaymentDirection,
+	/// The id of the payment. This is always `Some` for outbound payments and `None` for inbound
+	/// payments, which are identified by their [`Self::payment_hash`].
+	pub payment_id: Option<PaymentId>,
+	/// The hash of the payment.
+	pub payment_hash: PaymentHash,
+	/// The preimage of the payment, once known.
+	pub payment_preimage: Option<PaymentPreimage>,
+	/// The current status of the payment.
+	pub status: PaymentStatus,
+	/// The amount of the payment, excluding fees, if known.
+	///
+	/// For inbound payments this is the amount we claimed or can claim. For outbound payments
+	/// this is the amount provided to [`PaymentHistory::record_outbound_payment`], if any.
+	pub amount_msat: Option<u64>,
+	/// The total fee paid for an outbound payment, once it has succeeded. Always `None` for
+	/// inbound payments.
+	pub fee_paid_msat: Option<u64>,
+	/// The paths over which an outbound payment successfully reached the recipient, see
+	/// [`Event::PaymentPathSuccessful`]. Always empty for inbound payments.
+	pub paths: Vec<Path>,
+	/// What the payment paid for, if provided.
+	pub reference: Option<PaymentReference>,
+	/// The time at which the payment was first recorded, in seconds since the Unix epoch.
+	pub created_at: u64,
+	/// The time at which the payment was last updated, in seconds since the Unix epoch.
+	pub updated_at: u64,
+}
+
+impl_writeable_tlv_based!(PaymentRecord, {
+	(0, direction, required),
+	(1, payment_id, option),
+	(2, payment_hash, required),
+	(3, payment_preimage, option),
+	(4, status, required),
+	(5, amount_msat, option),
+	(7, fee_paid_msat, option),
+	(8, paths, required_vec),
+	(9, reference, upgradable_option),
+	(10, created_at, required),
+	(12, updated_at, required),
+});
+
+impl PaymentRecord {
+	fn new(
+		direction: PaymentDirection, payment_id: Option<PaymentId>, payment_hash: PaymentHash,
+		timestamp: u64
+	) -> Self {
+		Self {
+			direction, payment_id, payment_hash, payment_preimage: None,
+			status: PaymentStatus::Pending, amount_msat: None, fee_paid_msat: None,
+			paths: Vec::new(), reference: None, created_at: timestamp, updated_at: timestamp,
+		}
+	}
+
+	/// The total amount which reached the recipient of an outbound payment over
+	/// [`Self::paths`], excluding fees.
+	pub fn amount_sent_msat(&self) -> u64 {
+		self.paths.iter().map(|path| path.final_value_msat()).sum()
+	}
+}
+
+/// Identifies a [`PaymentRecord`] within the [`PaymentHistory`].
+enum PaymentKey {
+	Inbound(PaymentHash),
+	Outbound(PaymentId, PaymentHash),
+}
+
+impl PaymentKey {
+	fn secondary_namespace(&self) -> &'static str {
+		match self {
+			PaymentKey::Inbound(_) => PAYMENT_HISTORY_INBOUND_PERSISTENCE_SECONDARY_NAMESPACE,
+			PaymentKey::Outbound(..) => PAYMENT_HISTORY_OUTBOUND_PERSISTENCE_SECONDARY_NAMESPACE,
+		}
+	}
+
+	fn key(&self) -> String {
+		match self {
+			PaymentKey::Inbound(payment_hash) => payment_hash.to_string(),
+			PaymentKey::Outbound(payment_id, _) => payment_id.to_string(),
+		}
+	}
+
+	fn new_record(&self, timestamp: u64) -> PaymentRecord {
+		match self {
+			PaymentKey::Inbound(payment_hash) =>
+				PaymentRecord::new(PaymentDirection::Inbound, None, *payment_hash, timestamp),
+			PaymentKey::Outbound(payment_id, payment_hash) =>
+				PaymentRecord::new(PaymentDirection::Outbound, Some(*payment_id), *payment_hash, timestamp),
+		}
+	}
+}
+
+/// Records the payments we sent and received in a [`KVStore`] and allows querying them.
+///
+/// Records are created and updated from [`Event::PaymentSent`], [`Event::PaymentPathSuccessful`],
+/// [`Event::PaymentFailed`], [`Event::PaymentClaimable`], [`Event::PaymentClaimed`] and
+/// [`Event::HTLCHandlingFailed`] events passed to [`Self::handle_event`], which the
+/// `lightning-background-processor` crate does automatically if a `PaymentHistory` is provided to
+/// its `BackgroundProcessor::start_with_history` or `process_events_async_with_history`.
+/// Payments may additionally be registered before any event is generated for them via
+/// [`Self::record_outbound_payment`] and [`Self::record_inbound_payment`] to attach the amount and
+/// what the payment is for.
+///
+/// Each payment is stored under the [`PAYMENT_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE`], with
+/// inbound payments keyed by their [`PaymentHash`] and outbound payments keyed by their
+/// [`PaymentId`]. Queries other than [`Self::inbound_payment`] and [`Self::outbound_payment`]
+/// read all stored payments.
+pub struct PaymentHistory<K: Deref> where K::Target: KVStore {
+	kv_store: K,
+	/// Held while updating a record to avoid losing concurrent updates to the same payment.
+	update_lock: Mutex<()>,
+}
+
+impl<K: Deref> PaymentHistory<K> where K::Target: KVStore {
+	/// Constructs a new `PaymentHistory` which stores records in `kv_store`, alongside any records
+	/// stored previously.
+	pub fn new(kv_store: K) -> Self {
+		Self { kv_store, update_lock: Mutex::new(()) }
+	}
+
+	/// Registers an outbound payment we're about to send, or update the amount and reference of
+	/// one already recorded.
+	///
+	/// This should be called with the same [`PaymentId`] passed to the [`ChannelManager`] method
+	/// used to send the payment.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	pub fn record_outbound_payment(
+		&self, payment_id: PaymentId, payment_hash: PaymentHash, amount_msat: Option<u64>,
+		reference: Option<PaymentReference>, duration_since_epoch: Duration
+	) -> Result<(), io::Error> {
+		self.update(PaymentKey::Outbound(payment_id, payment_hash), duration_since_epoch, true, |record| {
+			record.amount_msat = amount_msat.or(record.amount_msat);
+			record.reference = reference.or(record.reference.take());
+		})
+	}
+
+	/// Registers an inbound payment we expect to receive, e.g. because we issued an invoice for
+	/// it, or update the amount and reference of one already recorded.
+	pub fn record_inbound_payment(
+		&self, payment_hash: PaymentHash, amount_msat: Option<u64>,
+		reference: Option<PaymentReference>, duration_since_epoch: Duration
+	) -> Result<(), io::Error> {
+		self.update(PaymentKey::Inbound(payment_hash), duration_since_epoch, true, |record| {
+			record.amount_msat = amount_msat.or(record.amount_msat);
+			record.reference = reference.or(record.reference.take());
+		})
+	}
+
+	/// Updates the record of the payment described by the given event, if any, as of
+	/// `duration_since_epoch`.
+	///
+	/// Payments which weren't registered previously are recorded when first seen. Failures of
+	/// inbound payments are only recorded for payments which are already known.
+	pub fn handle_event(&self, event: &Event, duration_since_epoch: Duration) -> Result<(), io::Error> {
+		match event {
+			Event::PaymentSent { payment_id: Some(payment_id), payment_preimage, payment_hash, fee_paid_msat, .. } => {
+				self.update(PaymentKey::Outbound(*payment_id, *payment_hash), duration_since_epoch, true, |record| {
+					record.status = PaymentStatus::Succeeded;
+					record.payment_preimage = Some(*payment_preimage);
+					record.fee_paid_msat = *fee_paid_msat;
+				})
+			},
+			Event::PaymentPathSuccessful { payment_id, payment_hash: Some(payment_hash), path } => {
+				self.update(PaymentKey::Outbound(*payment_id, *payment_hash), duration_since_epoch, true, |record| {
+					record.paths.push(path.clone());
+				})
+			},
+			Event::PaymentPathSuccessful { payment_id, payment_hash: None, path } => {
+				// Only events written by LDK versions prior to 0.0.104 lack the payment hash, in
+				// which case we can only update a payment we already know about.
+				let record = match self.outbound_payment(payment_id)? {
+					Some(record) => record,
+					None => return Ok(()),
+				};
+				self.update(PaymentKey::Outbound(*payment_id, record.payment_hash), duration_since_epoch, false, |record| {
+					record.paths.push(path.clone());
+				})
+			},
+			Event::PaymentFailed { payment_id, payment_hash, reason } => {
+				self.update(PaymentKey::Outbound(*payment_id, *payment_hash), duration_since_epoch, true, |record| {
+					record.status = PaymentStatus::Failed { reason: *reason };
+				})
+			},
+			Event::PaymentClaimable { payment_hash, amount_msat, purpose, .. } => {
+				self.update(PaymentKey::Inbound(*payment_hash), duration_since_epoch, true, |record| {
+					record.amount_msat = Some(*amount_msat);
+					record.payment_preimage = purpose.preimage().or(record.payment_preimage);
+				})
+			},
+			Event::PaymentClaimed { payment_hash, amount_msat, purpose, .. } => {
+				self.update(PaymentKey::Inbound(*payment_hash), duration_since_epoch, true, |record| {
+					record.status = PaymentStatus::Succeeded;
+					record.amount_msat = Some(*amount_msat);
+					record.payment_preimage = purpose.preimage().or(record.payment_preimage);
+				})
+			},
+			Event::HTLCHandlingFailed { failed_next_destination: HTLCDestination::FailedPayment { payment_hash }, .. } => {
+				self.update(PaymentKey::Inbound(*payment_hash), duration_since_epoch, false, |record| {
+					if record.status == PaymentStatus::Pending {
+						record.status = PaymentStatus::Failed { reason: None };
+					}
+				})
+			},
+			_ => Ok(()),
+		}
+	}
+
+	/// Returns the outbound payment with the given [`PaymentId`], if recorded.
+	pub fn outbound_payment(&self, payment_id: &PaymentId) -> Result<Option<PaymentRecord>, io::Error> {
+		self.read_record(PAYMENT_HISTORY_OUTBOUND_PERSISTENCE_SECONDARY_NAMESPACE, &payment_id.to_string())
+	}
+
+	/// Returns the inbound payment with the given [`PaymentHash`], if recorded.
+	pub fn inbound_payment(&self, payment_hash: &PaymentHash) -> Result<Option<PaymentRecord>, io::Error> {
+		self.read_record(PAYMENT_HISTORY_INBOUND_PERSISTENCE_SECONDARY_NAMESPACE, &payment_hash.to_string())
+	}
+
+	/// Returns all payments, inbound and outbound, with the given [`PaymentHash`], ordered by
+	/// creation time.
+	pub fn list_payments_with_hash(&self, payment_hash: &PaymentHash) -> Result<Vec<PaymentRecord>, io::Error> {
+		self.list_payments_matching(|record| record.payment_hash == *payment_hash)
+	}
+
+	/// Returns all payments, inbound and outbound, created in the range `[start_secs, end_secs)`,
+	/// ordered by creation time.
+	pub fn list_payments(&self, start_secs: u64, end_secs: u64) -> Result<Vec<PaymentRecord>, io::Error> {
+		self.list_payments_matching(|record| record.created_at >= start_secs && record.created_at < end_secs)
+	}
+
+	/// Removes the payments last updated before `before_secs` which are no longer pending.
+	pub fn prune(&self, before_secs: u64) -> Result<(), io::Error> {
+		let _lock = self.update_lock.lock().unwrap();
+		for record in self.list_payments_matching(|record| {
+			record.updated_at < before_secs && record.status != PaymentStatus::Pending
+		})? {
+			let key = match record.payment_id {
+				Some(payment_id) => PaymentKey::Outbound(payment_id, record.payment_hash),
+				None => PaymentKey::Inbound(record.payment_hash),
+			};
+			self.kv_store.remove(
+				PAYMENT_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE, key.secondary_namespace(), &key.key(), false
+			)?;
+		}
+		Ok(())
+	}
+
+	fn update<F: FnOnce(&mut PaymentRecord)>(
+		&self, key: PaymentKey, duration_since_epoch: Duration, create_if_missing: bool, update: F
+	) -> Result<(), io::Error> {
+		let _lock = self.update_lock.lock().unwrap();
+		let timestamp = duration_since_epoch.as_secs();
+		let mut record = match self.read_record(key.secondary_namespace(), &key.key())? {
+			Some(record) => record,
+			None if create_if_missing => key.new_record(timestamp),
+			None => return Ok(()),
+		};
+		update(&mut record);
+		record.updated_at = timestamp;
+		self.kv_store.write(
+			PAYMENT_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE, key.secondary_namespace(), &key.key(),
+			&record.encode()
+		)
+	}
+
+	fn list_payments_matching<F: Fn(&PaymentRecord) -> bool>(
+		&self, filter: F
+	) -> Result<Vec<PaymentRecord>, io::Error> {
+		let mut payments = Vec::new();
+		for secondary_namespace in [
+			PAYMENT_HISTORY_INBOUND_PERSISTENCE_SECONDARY_NAMESPACE,
+			PAYMENT_HISTORY_OUTBOUND_PERSISTENCE_SECONDARY_NAMESPACE,
+		].iter() {
+			for key in self.kv_store.list(PAYMENT_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE, secondary_namespace)? {
+				if let Some(record) = self.read_record(secondary_namespace, &key)? {
+					if filter(&record) {
+						payments.push(record);
+					}
+				}
+			}
+		}
+		payments.sort_by_key(|record| record.created_at);
+		Ok(payments)
+	}
+
+	fn read_record(&self, secondary_namespace: &str, key: &str) -> Result<Option<PaymentRecord>, io::Error> {
+		match self.kv_store.read(PAYMENT_HISTORY_PERSISTENCE_PRIMARY_NAMESPACE, secondary_namespace, key) {
+			Ok(bytes) => Readable::read(&mut io::Cursor::new(bytes)).map(Some).map_err(|_| io::Error::new(
+				io::ErrorKind::InvalidData, "Failed to read payment history"
+			)),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use core::time::Duration;
+
+	use crate::events::{Event, HTLCDestination, PaymentFailureReason, PaymentPurpose};
+	use crate::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
+	use crate::ln::channelmanager::PaymentId;
+	use crate::routing::router::Path;
+	use crate::util::test_utils::TestStore;
+	use super::{PaymentDirection, PaymentHistory, PaymentReference, PaymentStatus};
+
+	#[test]
+	fn records_and_queries_payments() {
+		let store = TestStore::new(false);
+		let history = PaymentHistory::new(&store);
+		let at = |secs| Duration::from_secs(1_000_000 + secs);
+		let preimage = PaymentPreimage([1; 32]);
+		let payment_hash = PaymentHash([2; 32]);
+		let payment_id = PaymentId([3; 32]);
+		let reference = PaymentReference::Bolt11Invoice { invoice: "lnbc1".to_string() };
+
+		// An outbound payment registered ahead of time, which then succeeds.
+		history.record_outbound_payment(payment_id, payment_hash, Some(1000), Some(reference.clone()), at(0)).unwrap();
+		history.handle_event(&Event::PaymentPathSuccessful {
+			payment_id, payment_hash: Some(payment_hash), path: Path { hops: Vec::new(), blinded_tail: None },
+		}, at(1)).unwrap();
+		history.handle_event(&Event::PaymentSent {
+			payment_id: Some(payment_id), payment_preimage: preimage, payment_hash, fee_paid_msat: Some(10),
+			bolt12_invoice: None,
+		}, at(2)).unwrap();
+
+		let sent = history.outbound_payment(&payment_id).unwrap().unwrap();
+		assert_eq!(sent.direction, PaymentDirection::Outbound);
+		assert_eq!(sent.status, PaymentStatus::Succeeded);
+		assert_eq!(sent.payment_preimage, Some(preimage));
+		assert_eq!(sent.amount_msat, Some(1000));
+		assert_eq!(sent.fee_paid_msat, Some(10));
+		assert_eq!(sent.paths.len(), 1);
+		assert_eq!(sent.reference, Some(reference));
+		assert_eq!((sent.created_at, sent.updated_at), (at(0).as_secs(), at(2).as_secs()));
+
+		// An outbound payment we only learn about from its failure.
+		let failed_id = PaymentId([4; 32]);
+		history.handle_event(&Event::PaymentFailed {
+			payment_id: failed_id, payment_hash: PaymentHash([5; 32]),
+			reason: Some(PaymentFailureReason::RouteNotFound),
+		}, at(3)).unwrap();
+		assert_eq!(history.outbound_payment(&failed_id).unwrap().unwrap().status,
+			PaymentStatus::Failed { reason: Some(PaymentFailureReason::RouteNotFound) });
+
+		// An inbound payment with the same hash, which we claim.
+		let purpose = PaymentPurpose::InvoicePayment { payment_preimage: Some(preimage), payment_secret: PaymentSecret([6; 32]) };
+		history.handle_event(&Event::PaymentClaimable {
+			receiver_node_id: None, payment_hash, onion_fields: None, amount_msat: 2000,
+			counterparty_skimmed_fee_msat: 0, purpose: purpose.clone(), via_channel_id: None,
+			via_user_channel_id: None, claim_deadline: None,
+		}, at(4)).unwrap();
+		assert_eq!(history.inbound_payment(&payment_hash).unwrap().unwrap().status, PaymentStatus::Pending);
+		history.handle_event(&Event::PaymentClaimed {
+			receiver_node_id: None, payment_hash, amount_msat: 2000, purpose, htlcs: Vec::new(),
+			sender_intended_total_msat: None,
+		}, at(5)).unwrap();
+		let received = history.inbound_payment(&payment_hash).unwrap().unwrap();
+		assert_eq!(received.status, PaymentStatus::Succeeded);
+		assert_eq!(received.amount_msat, Some(2000));
+
+		// Failures to receive unknown payments aren't recorded.
+		let unknown_hash = PaymentHash([7; 32]);
+		history.handle_event(&Event::HTLCHandlingFailed {
+			prev_channel_id: crate::ln::ChannelId::new_zero(),
+			failed_next_destination: HTLCDestination::FailedPayment { payment_hash: unknown_hash },
+		}, at(6)).unwrap();
+		assert!(history.inbound_payment(&unknown_hash).unwrap().is_none());
+
+		assert_eq!(history.list_payments_with_hash(&payment_hash).unwrap(), vec![sent.clone(), received.clone()]);
+		assert_eq!(history.list_payments(at(1).as_secs(), u64::max_value()).unwrap().len(), 2);
+
+		// Records are kept across instances and can be pruned once resolved.
+		let history = PaymentHistory::new(&store);
+		assert_eq!(history.list_payments(0, u64::max_value()).unwrap().len(), 3);
+		history.prune(at(4).as_secs()).unwrap();
+		assert_eq!(history.list_payments(0, u64::max_value()).unwrap(), vec![received]);
+	}
+}
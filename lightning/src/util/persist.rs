@@ -51,6 +51,12 @@ pub const CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
 /// The primary namespace under which [`ChannelMonitorUpdate`]s will be persisted.
 pub const CHANNEL_MONITOR_UPDATE_PERSISTENCE_PRIMARY_NAMESPACE: &str = "monitor_updates";
 
+/// The primary namespace under which fully resolved [`ChannelMonitor`]s will be archived, see
+/// [`Persist::archive_persisted_channel`].
+pub const ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE: &str = "archived_monitors";
+/// The secondary namespace under which fully resolved [`ChannelMonitor`]s will be archived.
+pub const ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
+
 /// The primary namespace under which the [`NetworkGraph`] will be persisted.
 pub const NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE: &str = "";
 /// The secondary namespace under which the [`NetworkGraph`] will be persisted.
@@ -216,6 +222,23 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner, K: KVStore> Persist<ChannelSign
 			Err(_) => chain::ChannelMonitorUpdateStatus::UnrecoverableError
 		}
 	}
+
+	fn archive_persisted_channel(&self, funding_txo: OutPoint) -> Result<(), io::Error> {
+		let key = format!("{}_{}", funding_txo.txid.to_string(), funding_txo.index);
+		let monitor = self.read(
+			CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+			CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+			&key)?;
+		// Only remove the monitor once we're sure it's safely archived.
+		self.write(
+			ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+			ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+			&key, &monitor)?;
+		self.remove(
+			CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+			CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+			&key, true)
+	}
 }
 
 /// Read previously persisted [`ChannelMonitor`]s from the store.
@@ -722,6 +745,77 @@ where
 			self.persist_new_channel(funding_txo, monitor, monitor_update_call_id)
 		}
 	}
+
+	/// Moves the full monitor, with any pending updates applied, to the
+	/// [`ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE`] and removes it and its updates
+	/// from the namespaces read on startup.
+	fn archive_persisted_channel(&self, funding_txo: OutPoint) -> Result<(), io::Error> {
+		let monitor_name = MonitorName::from(funding_txo);
+		let monitor = match self.read_monitor(&monitor_name) {
+			Ok((_, monitor)) => monitor,
+			Err(e) => {
+				log_error!(self.logger, "Failed to read ChannelMonitor {} to archive it, reason: {}",
+					monitor_name.as_str(), e);
+				return Err(e);
+			},
+		};
+		if let Err(e) = self.kv_store.write(
+			ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+			ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+			monitor_name.as_str(),
+			&monitor.encode(),
+		) {
+			log_error!(
+				self.logger,
+				"Failed to write ChannelMonitor {}/{}/{} reason: {}",
+				ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+				ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+				monitor_name.as_str(),
+				e
+			);
+			return Err(e);
+		}
+		if let Err(e) = self.kv_store.remove(
+			CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+			CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+			monitor_name.as_str(),
+			true,
+		) {
+			log_error!(self.logger, "Failed to remove archived ChannelMonitor {}, reason: {}",
+				monitor_name.as_str(), e);
+			return Err(e);
+		}
+		// Any updates left behind are stale now that the monitor is gone, so failing to remove
+		// them is logged but otherwise harmless.
+		let update_names = match self.kv_store.list(
+			CHANNEL_MONITOR_UPDATE_PERSISTENCE_PRIMARY_NAMESPACE, monitor_name.as_str()
+		) {
+			Ok(update_names) => update_names,
+			Err(e) => {
+				log_error!(self.logger, "Failed to list updates of archived ChannelMonitor {}, reason: {}",
+					monitor_name.as_str(), e);
+				return Ok(());
+			},
+		};
+		for update_name in update_names {
+			if let Err(e) = self.kv_store.remove(
+				CHANNEL_MONITOR_UPDATE_PERSISTENCE_PRIMARY_NAMESPACE,
+				monitor_name.as_str(),
+				&update_name,
+				true,
+			) {
+				log_error!(
+					self.logger,
+					"Failed to remove ChannelMonitorUpdate {}/{}/{} reason: {}",
+					CHANNEL_MONITOR_UPDATE_PERSISTENCE_PRIMARY_NAMESPACE,
+					monitor_name.as_str(),
+					update_name,
+					e
+				);
+			}
+		}
+		Ok(())
+	}
 }
 
 impl<K: Deref, L: Deref, ES: Deref, SP: Deref> MonitorUpdatingPersister<K, L, ES, SP>
@@ -843,6 +937,7 @@ mod tests {
 	use crate::chain::ChannelMonitorUpdateStatus;
 	use crate::events::{ClosureReason, MessageSendEventsProvider};
 	use crate::ln::functional_test_utils::*;
+	use crate::util::test_channel_signer::TestChannelSigner;
 	use crate::util::test_utils::{self, TestLogger, TestStore};
 	use crate::{check_added_monitors, check_closed_broadcast};
 
@@ -1185,4 +1280,59 @@ mod tests {
 			.read(CHANNEL_MONITOR_UPDATE_PERSISTENCE_PRIMARY_NAMESPACE, monitor_name.as_str(), UpdateName::from(u64::MAX - 1).as_str())
 			.is_err());
 	}
+
+	#[test]
+	fn archive_persisted_channel_works() {
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let persister_0 = MonitorUpdatingPersister {
+			kv_store: &TestStore::new(false),
+			logger: &TestLogger::new(),
+			maximum_pending_updates: 11,
+			entropy_source: &chanmon_cfgs[0].keys_manager,
+			signer_provider: &chanmon_cfgs[0].keys_manager,
+		};
+		let mut node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let chain_mon_0 = test_utils::TestChainMonitor::new(
+			Some(&chanmon_cfgs[0].chain_source),
+			&chanmon_cfgs[0].tx_broadcaster,
+			&chanmon_cfgs[0].logger,
+			&chanmon_cfgs[0].fee_estimator,
+			&persister_0,
+			&chanmon_cfgs[0].keys_manager,
+		);
+		node_cfgs[0].chain_monitor = chain_mon_0;
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+		let broadcaster_0 = &chanmon_cfgs[0].tx_broadcaster;
+		let fee_estimator_0 = &chanmon_cfgs[0].fee_estimator;
+
+		// Open a channel and send a payment so that some updates are persisted alongside the
+		// full monitor.
+		let _ = create_announced_chan_between_nodes(&nodes, 0, 1);
+		send_payment(&nodes[0], &vec![&nodes[1]][..], 8_000_000);
+
+		let persisted_chan_data = persister_0.read_all_channel_monitors_with_updates(&broadcaster_0, &fee_estimator_0).unwrap();
+		assert_eq!(persisted_chan_data.len(), 1);
+		let (_, monitor) = &persisted_chan_data[0];
+		let funding_txo = monitor.get_funding_txo().0;
+		let monitor_name = MonitorName::from(funding_txo);
+		assert!(!persister_0.kv_store.list(CHANNEL_MONITOR_UPDATE_PERSISTENCE_PRIMARY_NAMESPACE, monitor_name.as_str()).unwrap().is_empty());
+
+		Persist::<TestChannelSigner>::archive_persisted_channel(&persister_0, funding_txo).unwrap();
+
+		// The monitor and its updates are gone from the namespaces read on startup...
+		assert!(persister_0.read_all_channel_monitors_with_updates(&broadcaster_0, &fee_estimator_0).unwrap().is_empty());
+		assert!(persister_0.kv_store.list(CHANNEL_MONITOR_UPDATE_PERSISTENCE_PRIMARY_NAMESPACE, monitor_name.as_str()).unwrap().is_empty());
+
+		// ...but the archived copy has all updates applied.
+		let archived_bytes = persister_0.kv_store.read(
+			ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+			ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+			monitor_name.as_str(),
+		).unwrap();
+		let (_, archived_monitor) = <(BlockHash, ChannelMonitor<TestChannelSigner>)>::read(
+			&mut io::Cursor::new(&archived_bytes), (&chanmon_cfgs[0].keys_manager, &chanmon_cfgs[0].keys_manager)).unwrap();
+		assert_eq!(archived_monitor.get_latest_update_id(), monitor.get_latest_update_id());
+	}
 }
@@ -75,6 +75,10 @@ macro_rules! _encode_tlv {
 		// Just a read-mapped type
 		$crate::_encode_tlv!($stream, $type, $field, option);
 	};
+	($stream: expr, $type: expr, $field: expr, (required: $trait: ident $(, $read_arg: expr)?)) => {
+		// Just a read-mapped type
+		$crate::_encode_tlv!($stream, $type, $field, required);
+	};
 }
 
 /// Panics if the last seen TLV type is not numerically less than the TLV type currently being checked.
@@ -214,6 +218,9 @@ macro_rules! _get_varint_length_prefixed_tlv_length {
 	($len: expr, $type: expr, $field: expr, (option: $trait: ident $(, $read_arg: expr)?)) => {
 		$crate::_get_varint_length_prefixed_tlv_length!($len, $type, $field, option);
 	};
+	($len: expr, $type: expr, $field: expr, (required: $trait: ident $(, $read_arg: expr)?)) => {
+		$crate::_get_varint_length_prefixed_tlv_length!($len, $type, $field, required);
+	};
 	($len: expr, $type: expr, $field: expr, (option, encoding: ($fieldty: ty, $encoding: ident))) => {
 		$crate::_get_varint_length_prefixed_tlv_length!($len, $type, $field.map(|f| $encoding(f)), option);
 	};
@@ -740,6 +747,9 @@ macro_rules! _init_tlv_based_struct_field {
 	($field: ident, required) => {
 		$field.0.unwrap()
 	};
+	($field: ident, (required: $trait: ident $(, $read_arg: expr)?)) => {
+		$crate::_init_tlv_based_struct_field!($field, required)
+	};
 	($field: ident, required_vec) => {
 		$field
 	};
@@ -163,6 +163,10 @@ impl ChannelSigner for TestChannelSigner {
 	fn provide_channel_parameters(&mut self, channel_parameters: &ChannelTransactionParameters) {
 		self.inner.provide_channel_parameters(channel_parameters)
 	}
+
+	fn provide_channel_type_features(&mut self, channel_type_features: &ChannelTypeFeatures) {
+		self.inner.provide_channel_type_features(channel_type_features)
+	}
 }
 
 impl EcdsaChannelSigner for TestChannelSigner {
@@ -32,6 +32,7 @@ use crate::ln::msgs::LightningError;
 use crate::ln::script::ShutdownScript;
 use crate::offers::invoice::{BlindedPayInfo, UnsignedBolt12Invoice};
 use crate::offers::invoice_request::UnsignedInvoiceRequest;
+use crate::offers::offer::{CurrencyCode, CurrencyConversion};
 use crate::onion_message::{Destination, MessageRouter, OnionMessagePath};
 use crate::routing::gossip::{EffectiveCapacity, NetworkGraph, NodeId, RoutingFees};
 use crate::routing::utxo::{UtxoLookup, UtxoLookupError, UtxoResult};
@@ -103,6 +104,26 @@ impl chaininterface::FeeEstimator for TestFeeEstimator {
 	}
 }
 
+pub struct TestCurrencyConversion {
+	/// Millisatoshi per currency unit (e.g., USD cent) for each supported currency.
+	pub msats_per_unit: Mutex<HashMap<CurrencyCode, u64>>,
+}
+impl TestCurrencyConversion {
+	pub fn new() -> Self {
+		Self { msats_per_unit: Mutex::new(HashMap::new()) }
+	}
+
+	pub fn set_rate(&self, iso4217_code: CurrencyCode, msats_per_unit: u64) {
+		self.msats_per_unit.lock().unwrap().insert(iso4217_code, msats_per_unit);
+	}
+}
+impl CurrencyConversion for TestCurrencyConversion {
+	fn to_msats(&self, iso4217_code: CurrencyCode, amount: u64) -> Result<u64, ()> {
+		let msats_per_unit = *self.msats_per_unit.lock().unwrap().get(&iso4217_code).ok_or(())?;
+		amount.checked_mul(msats_per_unit).ok_or(())
+	}
+}
+
 pub struct TestRouter<'a> {
 	pub network_graph: Arc<NetworkGraph<&'a TestLogger>>,
 	pub next_routes: Mutex<VecDeque<(RouteParameters, Result<Route, LightningError>)>>,
@@ -196,10 +217,11 @@ impl<'a> Router for TestRouter<'a> {
 	fn create_blinded_payment_paths<
 		ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification
 	>(
-		&self, _recipient: PublicKey, _first_hops: Vec<ChannelDetails>, _tlvs: ReceiveTlvs,
-		_amount_msats: u64, _entropy_source: &ES, _secp_ctx: &Secp256k1<T>
+		&self, recipient: PublicKey, _first_hops: Vec<ChannelDetails>, tlvs: ReceiveTlvs,
+		_amount_msats: u64, entropy_source: &ES, secp_ctx: &Secp256k1<T>
 	) -> Result<Vec<(BlindedPayInfo, BlindedPath)>, ()> {
-		unreachable!()
+		BlindedPath::one_hop_for_payment(recipient, tlvs, entropy_source, secp_ctx)
+			.map(|path| vec![path])
 	}
 }
 
@@ -213,10 +235,11 @@ impl<'a> MessageRouter for TestRouter<'a> {
 	fn create_blinded_paths<
 		ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification
 	>(
-		&self, _recipient: PublicKey, _peers: Vec<PublicKey>, _entropy_source: &ES,
-		_secp_ctx: &Secp256k1<T>
+		&self, recipient: PublicKey, _peers: Vec<PublicKey>, entropy_source: &ES,
+		secp_ctx: &Secp256k1<T>
 	) -> Result<Vec<BlindedPath>, ()> {
-		unreachable!()
+		BlindedPath::one_hop_for_message(recipient, entropy_source, secp_ctx)
+			.map(|path| vec![path])
 	}
 }
 
@@ -451,6 +474,10 @@ impl<Signer: sign::ecdsa::WriteableEcdsaChannelSigner> chainmonitor::Persist<Sig
 		}
 		res
 	}
+
+	fn archive_persisted_channel(&self, funding_txo: OutPoint) -> Result<(), io::Error> {
+		<TestPersister as chainmonitor::Persist<Signer>>::archive_persisted_channel(&self.persister, funding_txo)
+	}
 }
 
 pub struct TestPersister {
@@ -463,6 +490,8 @@ pub struct TestPersister {
 	/// When we get an update_persisted_channel call *with* a ChannelMonitorUpdate, we insert the
 	/// MonitorUpdateId here.
 	pub offchain_monitor_updates: Mutex<HashMap<OutPoint, HashSet<MonitorUpdateId>>>,
+	/// When we get an archive_persisted_channel call, we insert the OutPoint here.
+	pub archived_channels: Mutex<HashSet<OutPoint>>,
 }
 impl TestPersister {
 	pub fn new() -> Self {
@@ -470,6 +499,7 @@ impl TestPersister {
 			update_rets: Mutex::new(VecDeque::new()),
 			chain_sync_monitor_persistences: Mutex::new(HashMap::new()),
 			offchain_monitor_updates: Mutex::new(HashMap::new()),
+			archived_channels: Mutex::new(HashSet::new()),
 		}
 	}
 
@@ -499,6 +529,11 @@ impl<Signer: sign::ecdsa::WriteableEcdsaChannelSigner> chainmonitor::Persist<Sig
 		}
 		ret
 	}
+
+	fn archive_persisted_channel(&self, funding_txo: OutPoint) -> Result<(), io::Error> {
+		self.archived_channels.lock().unwrap().insert(funding_txo);
+		Ok(())
+	}
 }
 
 pub struct TestStore {
@@ -594,6 +629,7 @@ impl KVStore for TestStore {
 
 pub struct TestBroadcaster {
 	pub txn_broadcasted: Mutex<Vec<Transaction>>,
+	pub packages_broadcasted: Mutex<Vec<Vec<Transaction>>>,
 	pub blocks: Arc<Mutex<Vec<(Block, u32)>>>,
 }
 
@@ -601,12 +637,13 @@ impl TestBroadcaster {
 	pub fn new(network: Network) -> Self {
 		Self {
 			txn_broadcasted: Mutex::new(Vec::new()),
+			packages_broadcasted: Mutex::new(Vec::new()),
 			blocks: Arc::new(Mutex::new(vec![(genesis_block(network), 0)])),
 		}
 	}
 
 	pub fn with_blocks(blocks: Arc<Mutex<Vec<(Block, u32)>>>) -> Self {
-		Self { txn_broadcasted: Mutex::new(Vec::new()), blocks }
+		Self { txn_broadcasted: Mutex::new(Vec::new()), packages_broadcasted: Mutex::new(Vec::new()), blocks }
 	}
 
 	pub fn txn_broadcast(&self) -> Vec<Transaction> {
@@ -619,6 +656,10 @@ impl TestBroadcaster {
 		txn.retain(|tx| seen.insert(tx.txid()));
 		txn
 	}
+
+	pub fn packages_broadcast(&self) -> Vec<Vec<Transaction>> {
+		self.packages_broadcasted.lock().unwrap().split_off(0)
+	}
 }
 
 impl chaininterface::BroadcasterInterface for TestBroadcaster {
@@ -637,6 +678,17 @@ impl chaininterface::BroadcasterInterface for TestBroadcaster {
 		let owned_txs: Vec<Transaction> = txs.iter().map(|tx| (*tx).clone()).collect();
 		self.txn_broadcasted.lock().unwrap().extend(owned_txs);
 	}
+
+	fn broadcast_package(&self, package: &[&Transaction]) {
+		// Every parent must be spent by the child, which always comes last.
+		let (child, parents) = package.split_last().unwrap();
+		for parent in parents {
+			let parent_txid = parent.txid();
+			assert!(child.input.iter().any(|input| input.previous_output.txid == parent_txid));
+		}
+		self.broadcast_transactions(package);
+		self.packages_broadcasted.lock().unwrap().push(package.iter().map(|tx| (*tx).clone()).collect());
+	}
 }
 
 pub struct TestChannelMessageHandler {
@@ -712,6 +764,15 @@ impl msgs::ChannelMessageHandler for TestChannelMessageHandler {
 	fn handle_stfu(&self, _their_node_id: &PublicKey, msg: &msgs::Stfu) {
 		self.received_msg(wire::Message::Stfu(msg.clone()));
 	}
+	fn handle_dyn_propose(&self, _their_node_id: &PublicKey, msg: &msgs::DynPropose) {
+		self.received_msg(wire::Message::DynPropose(msg.clone()));
+	}
+	fn handle_dyn_ack(&self, _their_node_id: &PublicKey, msg: &msgs::DynAck) {
+		self.received_msg(wire::Message::DynAck(msg.clone()));
+	}
+	fn handle_dyn_reject(&self, _their_node_id: &PublicKey, msg: &msgs::DynReject) {
+		self.received_msg(wire::Message::DynReject(msg.clone()));
+	}
 	fn handle_splice(&self, _their_node_id: &PublicKey, msg: &msgs::Splice) {
 		self.received_msg(wire::Message::Splice(msg.clone()));
 	}
@@ -751,6 +812,12 @@ impl msgs::ChannelMessageHandler for TestChannelMessageHandler {
 	fn handle_channel_reestablish(&self, _their_node_id: &PublicKey, msg: &msgs::ChannelReestablish) {
 		self.received_msg(wire::Message::ChannelReestablish(msg.clone()));
 	}
+	fn handle_peer_storage(&self, _their_node_id: &PublicKey, msg: &msgs::PeerStorage) {
+		self.received_msg(wire::Message::PeerStorage(msg.clone()));
+	}
+	fn handle_your_peer_storage(&self, _their_node_id: &PublicKey, msg: &msgs::YourPeerStorage) {
+		self.received_msg(wire::Message::YourPeerStorage(msg.clone()));
+	}
 	fn peer_disconnected(&self, their_node_id: &PublicKey) {
 		assert!(self.connected_peers.lock().unwrap().remove(their_node_id));
 	}
@@ -1078,6 +1145,10 @@ impl NodeSigner for TestNodeSigner {
 		unreachable!()
 	}
 
+	fn get_peer_storage_key(&self) -> crate::sign::KeyMaterial {
+		unreachable!()
+	}
+
 	fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()> {
 		let node_secret = match recipient {
 			Recipient::Node => Ok(&self.node_secret),
@@ -1149,6 +1220,10 @@ impl NodeSigner for TestKeysInterface {
 		self.backing.get_inbound_payment_key_material()
 	}
 
+	fn get_peer_storage_key(&self) -> sign::KeyMaterial {
+		self.backing.get_peer_storage_key()
+	}
+
 	fn sign_invoice(&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient) -> Result<RecoverableSignature, ()> {
 		self.backing.sign_invoice(hrp_bytes, invoice_data, recipient)
 	}